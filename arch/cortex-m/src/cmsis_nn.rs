@@ -0,0 +1,148 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Neural network kernels backed by the CMSIS-NN library.
+//!
+//! CMSIS-NN provides int8 neural network kernels optimized for the DSP and
+//! MVE extensions of Cortex-M cores. This module is only available with the
+//! `cmsis_nn` feature, and the board must link the CMSIS-NN library into the
+//! kernel.
+
+use core::ptr;
+
+use kernel::hil::neural_network::{FullyConnectedParams, NeuralNetworkKernels};
+use kernel::ErrorCode;
+
+/// `ARM_CMSIS_NN_SUCCESS`
+const CMSIS_NN_SUCCESS: i32 = 0;
+
+#[repr(C)]
+struct Context {
+    buf: *mut u8,
+    size: i32,
+}
+
+#[repr(C)]
+struct Activation {
+    min: i32,
+    max: i32,
+}
+
+#[repr(C)]
+struct FcParams {
+    input_offset: i32,
+    filter_offset: i32,
+    output_offset: i32,
+    activation: Activation,
+}
+
+#[repr(C)]
+struct PerTensorQuantParams {
+    multiplier: i32,
+    shift: i32,
+}
+
+#[repr(C)]
+struct Dims {
+    n: i32,
+    h: i32,
+    w: i32,
+    c: i32,
+}
+
+impl Dims {
+    const fn new(n: usize, c: usize) -> Dims {
+        Dims {
+            n: n as i32,
+            h: 1,
+            w: 1,
+            c: c as i32,
+        }
+    }
+}
+
+extern "C" {
+    fn arm_fully_connected_s8(
+        ctx: *const Context,
+        fc_params: *const FcParams,
+        quant_params: *const PerTensorQuantParams,
+        input_dims: *const Dims,
+        input_data: *const i8,
+        filter_dims: *const Dims,
+        filter_data: *const i8,
+        bias_dims: *const Dims,
+        bias_data: *const i32,
+        output_dims: *const Dims,
+        output_data: *mut i8,
+    ) -> i32;
+}
+
+/// CMSIS-NN kernels.
+pub struct CmsisNn;
+
+impl NeuralNetworkKernels for CmsisNn {
+    fn fully_connected_s8(
+        &self,
+        params: &FullyConnectedParams,
+        input: &[u8],
+        weights: &[u8],
+        bias: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), ErrorCode> {
+        let (input_len, output_len) = (input.len(), output.len());
+        if weights.len() != input_len * output_len || bias.len() != output_len * 4 {
+            return Err(ErrorCode::SIZE);
+        }
+        // CMSIS-NN reads the bias as `int32_t`.
+        if bias.as_ptr() as usize % core::mem::align_of::<i32>() != 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // The int8 fully connected kernel does not use a scratch buffer.
+        let ctx = Context {
+            buf: ptr::null_mut(),
+            size: 0,
+        };
+        let fc_params = FcParams {
+            input_offset: params.input_offset,
+            filter_offset: 0,
+            output_offset: params.output_offset,
+            activation: Activation {
+                min: params.activation_min,
+                max: params.activation_max,
+            },
+        };
+        let quant_params = PerTensorQuantParams {
+            multiplier: params.multiplier,
+            shift: params.shift,
+        };
+        let input_dims = Dims::new(1, input_len);
+        let filter_dims = Dims::new(input_len, output_len);
+        let bias_dims = Dims::new(1, output_len);
+        let output_dims = Dims::new(1, output_len);
+
+        // Safety: the tensors are valid for the dimensions passed, which
+        // were checked against their lengths, and the bias is aligned.
+        let status = unsafe {
+            arm_fully_connected_s8(
+                &ctx,
+                &fc_params,
+                &quant_params,
+                &input_dims,
+                input.as_ptr() as *const i8,
+                &filter_dims,
+                weights.as_ptr() as *const i8,
+                &bias_dims,
+                bias.as_ptr() as *const i32,
+                &output_dims,
+                output.as_mut_ptr() as *mut i8,
+            )
+        };
+        if status == CMSIS_NN_SUCCESS {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        }
+    }
+}
@@ -13,6 +13,8 @@ use core::fmt::Write;
 #[cfg(all(target_arch = "arm", target_os = "none"))]
 use core::arch::global_asm;
 
+#[cfg(feature = "cmsis_nn")]
+pub mod cmsis_nn;
 pub mod dcb;
 pub mod dwt;
 pub mod mpu;
@@ -21,6 +21,7 @@ pub use cortexm::support;
 pub use cortexm::systick;
 pub use cortexm::unhandled_interrupt;
 pub use cortexm::CortexMVariant;
+pub use cortexv7m::interrupt_latency;
 
 // Enum with no variants to ensure that this type is not instantiable. It is
 // only used to pass architecture-specific constants and functions via the
@@ -28,7 +29,11 @@ pub use cortexm::CortexMVariant;
 pub enum CortexM7 {}
 
 impl cortexm::CortexMVariant for CortexM7 {
-    const GENERIC_ISR: unsafe extern "C" fn() = cortexv7m::generic_isr_arm_v7m;
+    const GENERIC_ISR: unsafe extern "C" fn() = if cfg!(feature = "interrupt_latency") {
+        cortexv7m::generic_isr_latency_arm_v7m
+    } else {
+        cortexv7m::generic_isr_arm_v7m
+    };
     const SYSTICK_HANDLER: unsafe extern "C" fn() = cortexv7m::systick_handler_arm_v7m;
     const SVC_HANDLER: unsafe extern "C" fn() = cortexv7m::svc_handler_arm_v7m;
     const HARD_FAULT_HANDLER: unsafe extern "C" fn() = cortexv7m::hard_fault_handler_arm_v7m;
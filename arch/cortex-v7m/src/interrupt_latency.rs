@@ -0,0 +1,112 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interrupt latency measurements for ARMv7-M.
+//!
+//! With the `interrupt_latency` feature, the generic interrupt handler
+//! records the DWT cycle counter when an interrupt is entered. The chip
+//! calls [`complete()`] after the driver handled the interrupt in
+//! `service_pending_interrupts()`, and the time between the two is added to
+//! the statistics of the interrupt. This includes the time the interrupt
+//! waited for the kernel loop, for example while a process or another
+//! interrupt ran.
+//!
+//! The DWT cycle counter must be started by the board, and the statistics
+//! are read through [`tracker()`]:
+//!
+//! ```rust,ignore
+//! kernel::hil::hw_debug::CycleCounter::start(&cortexm4::dwt::Dwt::new());
+//! pconsole.set_interrupt_latency(cortexm4::interrupt_latency::tracker());
+//! ```
+//!
+//! Without the feature, nothing is recorded and the tracker stays empty.
+
+use core::cell::Cell;
+use core::ptr::addr_of;
+
+use kernel::hil::interrupt_latency::{InterruptLatency, LatencyStats};
+
+/// Interrupts numbered from this value are not measured.
+pub const MAX_INTERRUPTS: usize = 128;
+
+/// Number of different interrupts with statistics.
+pub const MAX_TRACKED_INTERRUPTS: usize = 16;
+
+/// Address of the DWT cycle counter register.
+const DWT_CYCCNT: *const u32 = 0xE000_1004 as *const u32;
+
+/// Cycle counter at the entry of each interrupt, written by
+/// `generic_isr_latency_arm_v7m`.
+#[no_mangle]
+static mut INTERRUPT_ENTRY_CYCLES: [u32; MAX_INTERRUPTS] = [0; MAX_INTERRUPTS];
+
+const EMPTY: Cell<Option<(u32, LatencyStats)>> = Cell::new(None);
+
+/// Latency statistics of the interrupts that fired.
+pub struct InterruptLatencyTracker {
+    interrupts: [Cell<Option<(u32, LatencyStats)>>; MAX_TRACKED_INTERRUPTS],
+}
+
+impl InterruptLatencyTracker {
+    const fn new() -> InterruptLatencyTracker {
+        InterruptLatencyTracker {
+            interrupts: [EMPTY; MAX_TRACKED_INTERRUPTS],
+        }
+    }
+
+    fn record(&self, interrupt: u32, cycles: u32) {
+        // Use the slot of the interrupt, or the first free one.
+        let slot = self
+            .interrupts
+            .iter()
+            .find(|slot| slot.get().map_or(true, |(number, _)| number == interrupt));
+        if let Some(slot) = slot {
+            let (_, mut stats) = slot.get().unwrap_or_default();
+            stats.record(cycles);
+            slot.set(Some((interrupt, stats)));
+        }
+    }
+}
+
+impl InterruptLatency for InterruptLatencyTracker {
+    fn count(&self) -> usize {
+        self.interrupts
+            .iter()
+            .take_while(|slot| slot.get().is_some())
+            .count()
+    }
+
+    fn get(&self, index: usize) -> Option<(u32, LatencyStats)> {
+        self.interrupts.get(index).and_then(|slot| slot.get())
+    }
+
+    fn reset(&self) {
+        self.interrupts.iter().for_each(|slot| slot.set(None));
+    }
+}
+
+static mut TRACKER: InterruptLatencyTracker = InterruptLatencyTracker::new();
+
+/// Return the latency statistics.
+pub fn tracker() -> &'static InterruptLatencyTracker {
+    // Safety: the tracker is only accessed from the kernel thread, and only
+    // through shared references.
+    unsafe { &*addr_of!(TRACKER) }
+}
+
+/// Record the latency of `interrupt`, whose processing just completed. Does
+/// nothing without the `interrupt_latency` feature.
+///
+/// # Safety
+///
+/// Must be called from the kernel thread, and the DWT cycle counter must be
+/// accessible.
+pub unsafe fn complete(interrupt: u32) {
+    if !cfg!(feature = "interrupt_latency") || interrupt as usize >= MAX_INTERRUPTS {
+        return;
+    }
+    let now = core::ptr::read_volatile(DWT_CYCCNT);
+    let entry = core::ptr::read_volatile(addr_of!(INTERRUPT_ENTRY_CYCLES[interrupt as usize]));
+    tracker().record(interrupt, now.wrapping_sub(entry));
+}
@@ -11,6 +11,8 @@
 #[cfg(all(target_arch = "arm", target_os = "none"))]
 use core::arch::global_asm;
 
+pub mod interrupt_latency;
+
 // These constants are defined in the linker script.
 extern "C" {
     static _estack: u8;
@@ -215,6 +217,43 @@ global_asm!(
     bx lr
     ");
 
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+extern "C" {
+    /// ARMv7-M generic interrupt handler that records the entry time of the
+    /// interrupt for the latency measurements, then runs
+    /// `generic_isr_arm_v7m`.
+    pub fn generic_isr_latency_arm_v7m();
+}
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+global_asm!(
+        "
+    .section .generic_isr_latency_arm_v7m, \"ax\"
+    .global generic_isr_latency_arm_v7m
+    .thumb_func
+  generic_isr_latency_arm_v7m:
+    // Read the DWT cycle counter first, so the measurement includes as little
+    // of the handler as possible.
+    ldr r1, =0xe0001004               // r1 = &DWT.CYCCNT
+    ldr r1, [r1]                      // r1 = DWT.CYCCNT
+
+    // Find the ISR number (`index`) as in `generic_isr_arm_v7m`.
+    mrs r0, IPSR                      // r0 = Interrupt Program Status Register (IPSR)
+    and r0, #0xff                     // r0 = r0 & 0xFF; Get lowest 8 bits
+    sub r0, #16                       // r0 = r0 - 16;   ISRs start at 16, so subtract 16 to get zero-indexed.
+
+    // Interrupts beyond the table are not measured.
+    cmp r0, #128                      // if r0 >= MAX_INTERRUPTS
+    bhs 100f                          //   skip the store
+
+    // INTERRUPT_ENTRY_CYCLES[index] = cycle counter
+    ldr r2, =INTERRUPT_ENTRY_CYCLES   // r2 = &INTERRUPT_ENTRY_CYCLES
+    str r1, [r2, r0, lsl #2]          // *(r2 + r0 * 4) = r1
+
+  100:
+    // Disable the interrupt and return to the kernel.
+    b generic_isr_arm_v7m
+    ");
+
 /// Assembly function to switch into userspace and store/restore application
 /// state.
 ///
@@ -571,6 +610,11 @@ pub unsafe extern "C" fn generic_isr_arm_v7m() {
     unimplemented!()
 }
 
+#[cfg(not(all(target_arch = "arm", target_os = "none")))]
+pub unsafe extern "C" fn generic_isr_latency_arm_v7m() {
+    unimplemented!()
+}
+
 #[cfg(not(all(target_arch = "arm", target_os = "none")))]
 pub unsafe extern "C" fn switch_to_user_arm_v7m(
     _user_stack: *const u8,
@@ -146,7 +146,11 @@ type SHT3xSensor = components::sht3x::SHT3xComponentType<
     capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, nrf52840::i2c::TWI<'static>>,
 >;
 type TemperatureDriver = components::temperature::TemperatureComponentType<SHT3xSensor>;
-type HumidityDriver = components::humidity::HumidityComponentType<SHT3xSensor>;
+type HumiditySensor = components::condensation_recovery::CondensationRecoveryComponentType<
+    nrf52::rtc::Rtc<'static>,
+    SHT3xSensor,
+>;
+type HumidityDriver = components::humidity::HumidityComponentType<HumiditySensor>;
 type RngDriver = components::rng::RngComponentType<nrf52840::trng::Trng<'static>>;
 
 type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
@@ -535,7 +539,7 @@ unsafe fn start() -> (
     //--------------------------------------------------------------------------
     base_peripherals.adc.calibrate();
 
-    let adc_mux = components::adc::AdcMuxComponent::new(&base_peripherals.adc)
+    let adc_mux = components::adc::AdcHighSpeedMuxComponent::new(&base_peripherals.adc)
         .finalize(components::adc_mux_component_static!(nrf52840::adc::Adc));
 
     let adc_syscall =
@@ -630,12 +634,21 @@ unsafe fn start() -> (
     )
     .finalize(components::temperature_component_static!(SHT3xSensor));
 
+    // Heat the sensor on request to recover from condensation, and discard
+    // the three readings taken next.
+    let humidity_sensor =
+        components::condensation_recovery::CondensationRecoveryComponent::new(sht3x, mux_alarm, 3)
+            .finalize(components::condensation_recovery_component_static!(
+                nrf52::rtc::Rtc<'static>,
+                SHT3xSensor
+            ));
+
     let humidity = components::humidity::HumidityComponent::new(
         board_kernel,
         capsules_extra::humidity::DRIVER_NUM,
-        sht3x,
+        humidity_sensor,
     )
-    .finalize(components::humidity_component_static!(SHT3xSensor));
+    .finalize(components::humidity_component_static!(HumiditySensor));
 
     //--------------------------------------------------------------------------
     // TFT
@@ -7,11 +7,13 @@
 use capsules_core::adc::AdcDedicated;
 use capsules_core::adc::AdcVirtualized;
 use capsules_core::virtualizers::virtual_adc::{AdcDevice, MuxAdc};
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil::adc;
+use kernel::hil::time::Alarm;
 
 #[macro_export]
 macro_rules! adc_mux_component_static {
@@ -27,6 +29,15 @@ macro_rules! adc_component_static {
     };};
 }
 
+#[macro_export]
+macro_rules! adc_periodic_alarm_component_static {
+    ($T:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $T>
+        )
+    };};
+}
+
 #[macro_export]
 macro_rules! adc_syscall_component_helper {
     ($($P:expr),+ $(,)?) => {{
@@ -41,7 +52,8 @@ macro_rules! adc_syscall_component_helper {
             ]
         );
         let adc_virtualized = kernel::static_buf!(capsules_core::adc::AdcVirtualized<'static>);
-        (adc_virtualized, drivers)
+        let buffer = kernel::static_buf!([u16; capsules_core::adc::BUF_LEN]);
+        (adc_virtualized, drivers, buffer)
     };};
 }
 
@@ -80,6 +92,30 @@ impl<A: 'static + adc::Adc<'static>> Component for AdcMuxComponent<A> {
     }
 }
 
+/// ADC mux also sampling buffers with the high-speed interface of the ADC,
+/// with the same static input as `AdcMuxComponent`.
+pub struct AdcHighSpeedMuxComponent<A: 'static + adc::AdcHighSpeed<'static>> {
+    adc: &'static A,
+}
+
+impl<A: 'static + adc::AdcHighSpeed<'static>> AdcHighSpeedMuxComponent<A> {
+    pub fn new(adc: &'static A) -> Self {
+        AdcHighSpeedMuxComponent { adc }
+    }
+}
+
+impl<A: 'static + adc::AdcHighSpeed<'static>> Component for AdcHighSpeedMuxComponent<A> {
+    type StaticInput = &'static mut MaybeUninit<MuxAdc<'static, A>>;
+    type Output = &'static MuxAdc<'static, A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let adc_mux = AdcMuxComponent::new(self.adc).finalize(static_buffer);
+        adc_mux.enable_highspeed();
+
+        adc_mux
+    }
+}
+
 pub struct AdcComponent<A: 'static + adc::Adc<'static>> {
     adc_mux: &'static MuxAdc<'static, A>,
     channel: A::Channel,
@@ -107,6 +143,80 @@ impl<A: 'static + adc::Adc<'static>> Component for AdcComponent<A> {
     }
 }
 
+/// Virtual ADC channel sampling the difference between two channels, with
+/// the same static input as `AdcComponent`.
+pub struct AdcDifferentialComponent<A: 'static + adc::Adc<'static>> {
+    adc_mux: &'static MuxAdc<'static, A>,
+    positive: A::Channel,
+    negative: A::Channel,
+}
+
+impl<A: 'static + adc::Adc<'static>> AdcDifferentialComponent<A> {
+    pub fn new(
+        mux: &'static MuxAdc<'static, A>,
+        positive: A::Channel,
+        negative: A::Channel,
+    ) -> Self {
+        AdcDifferentialComponent {
+            adc_mux: mux,
+            positive,
+            negative,
+        }
+    }
+}
+
+impl<A: 'static + adc::Adc<'static>> Component for AdcDifferentialComponent<A> {
+    type StaticInput = &'static mut MaybeUninit<AdcDevice<'static, A>>;
+    type Output = &'static AdcDevice<'static, A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let adc_device = static_buffer.write(AdcDevice::new_differential(
+            self.adc_mux,
+            self.positive,
+            self.negative,
+        ));
+
+        adc_device.add_to_mux();
+
+        adc_device
+    }
+}
+
+/// Periodic alarm of a virtual ADC channel, letting the channel be sampled
+/// at a fixed interval.
+pub struct AdcPeriodicAlarmComponent<A: 'static + adc::Adc<'static>, T: 'static + Alarm<'static>> {
+    adc_device: &'static AdcDevice<'static, A>,
+    alarm_mux: &'static MuxAlarm<'static, T>,
+}
+
+impl<A: 'static + adc::Adc<'static>, T: 'static + Alarm<'static>> AdcPeriodicAlarmComponent<A, T> {
+    pub fn new(
+        adc_device: &'static AdcDevice<'static, A>,
+        alarm_mux: &'static MuxAlarm<'static, T>,
+    ) -> Self {
+        AdcPeriodicAlarmComponent {
+            adc_device,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + adc::Adc<'static>, T: 'static + Alarm<'static>> Component
+    for AdcPeriodicAlarmComponent<A, T>
+{
+    type StaticInput = &'static mut MaybeUninit<VirtualMuxAlarm<'static, T>>;
+    type Output = &'static VirtualMuxAlarm<'static, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        self.adc_device.set_periodic_alarm(alarm);
+
+        alarm
+    }
+}
+
 pub struct AdcVirtualComponent {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
@@ -125,6 +235,7 @@ impl Component for AdcVirtualComponent {
     type StaticInput = (
         &'static mut MaybeUninit<AdcVirtualized<'static>>,
         &'static [&'static dyn kernel::hil::adc::AdcChannel<'static>],
+        &'static mut MaybeUninit<[u16; capsules_core::adc::BUF_LEN]>,
     );
     type Output = &'static capsules_core::adc::AdcVirtualized<'static>;
 
@@ -137,10 +248,13 @@ impl Component for AdcVirtualComponent {
             .write(capsules_core::adc::AdcVirtualized::new(
                 static_buffer.1,
                 grant_adc,
+                static_buffer.2.write([0; capsules_core::adc::BUF_LEN]),
             ));
 
         for driver in static_buffer.1 {
             kernel::hil::adc::AdcChannel::set_client(*driver, adc);
+            kernel::hil::adc::AdcChannel::set_highspeed_client(*driver, adc);
+            kernel::hil::adc::AdcChannel::set_periodic_client(*driver, adc);
         }
 
         adc
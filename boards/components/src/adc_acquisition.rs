@@ -0,0 +1,114 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for low-power scheduled ADC acquisition.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let adc_channel = components::adc::AdcComponent::new(adc_mux, Channel::Channel3)
+//!     .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+//! let adc_acquisition = components::adc_acquisition::AdcAcquisitionComponent::new(
+//!     board_kernel,
+//!     capsules_extra::adc_acquisition::DRIVER_NUM,
+//!     mux_alarm,
+//!     adc_channel,
+//! )
+//! .finalize(components::adc_acquisition_component_static!(
+//!     stm32f429zi::tim2::Tim2,
+//!     capsules_core::virtualizers::virtual_adc::AdcDevice<'static, stm32f429zi::adc::Adc>,
+//!     64,
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::adc_acquisition::AdcAcquisition;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::adc::AdcChannel;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! adc_acquisition_component_static {
+    ($A:ty, $C:ty, $L:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let adc_acquisition = kernel::static_buf!(
+            capsules_extra::adc_acquisition::AdcAcquisition<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $C,
+            >
+        );
+        let buffer = kernel::static_buf!([u16; $L]);
+
+        (alarm, adc_acquisition, buffer)
+    };};
+}
+
+pub type AdcAcquisitionComponentType<A, C> =
+    AdcAcquisition<'static, VirtualMuxAlarm<'static, A>, C>;
+
+pub struct AdcAcquisitionComponent<
+    A: 'static + Alarm<'static>,
+    C: 'static + AdcChannel<'static>,
+    const L: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    channel: &'static C,
+}
+
+impl<A: 'static + Alarm<'static>, C: 'static + AdcChannel<'static>, const L: usize>
+    AdcAcquisitionComponent<A, C, L>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        channel: &'static C,
+    ) -> AdcAcquisitionComponent<A, C, L> {
+        AdcAcquisitionComponent {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            channel,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, C: 'static + AdcChannel<'static>, const L: usize> Component
+    for AdcAcquisitionComponent<A, C, L>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<AdcAcquisition<'static, VirtualMuxAlarm<'static, A>, C>>,
+        &'static mut MaybeUninit<[u16; L]>,
+    );
+    type Output = &'static AdcAcquisition<'static, VirtualMuxAlarm<'static, A>, C>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let buffer = static_buffer.2.write([0; L]);
+
+        let adc_acquisition = static_buffer.1.write(AdcAcquisition::new(
+            alarm,
+            self.channel,
+            buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        time::Alarm::set_alarm_client(alarm, adc_acquisition);
+        self.channel.set_client(adc_acquisition);
+
+        adc_acquisition
+    }
+}
@@ -0,0 +1,69 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for voltage and temperature readings from an ADC channel.
+
+use capsules_core::virtualizers::virtual_adc::AdcDevice;
+use capsules_extra::adc_sensor::{AdcSensor, Scale};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::adc;
+use kernel::hil::adc::AdcChannel;
+
+#[macro_export]
+macro_rules! adc_sensor_component_static {
+    ($A:ty $(,)?) => {{
+        let adc_device = components::adc_component_static!($A);
+        let adc_sensor = kernel::static_buf!(
+            capsules_extra::adc_sensor::AdcSensor<
+                'static,
+                capsules_core::virtualizers::virtual_adc::AdcDevice<'static, $A>,
+            >
+        );
+
+        (adc_device, adc_sensor)
+    };};
+}
+
+pub type AdcSensorComponentType<A> =
+    capsules_extra::adc_sensor::AdcSensor<'static, AdcDevice<'static, A>>;
+
+pub struct AdcSensorComponent<A: 'static + adc::Adc<'static>> {
+    adc_mux: &'static capsules_core::virtualizers::virtual_adc::MuxAdc<'static, A>,
+    adc_channel: A::Channel,
+    scale: Scale,
+}
+
+impl<A: 'static + adc::Adc<'static>> AdcSensorComponent<A> {
+    pub fn new(
+        adc_mux: &'static capsules_core::virtualizers::virtual_adc::MuxAdc<'static, A>,
+        adc_channel: A::Channel,
+        scale: Scale,
+    ) -> AdcSensorComponent<A> {
+        AdcSensorComponent {
+            adc_mux,
+            adc_channel,
+            scale,
+        }
+    }
+}
+
+impl<A: 'static + adc::Adc<'static>> Component for AdcSensorComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<AdcDevice<'static, A>>,
+        &'static mut MaybeUninit<AdcSensor<'static, AdcDevice<'static, A>>>,
+    );
+    type Output = &'static AdcSensor<'static, AdcDevice<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let adc_device =
+            crate::adc::AdcComponent::new(self.adc_mux, self.adc_channel).finalize(s.0);
+
+        let adc_sensor = s.1.write(AdcSensor::new(adc_device, self.scale));
+
+        adc_device.set_client(adc_sensor);
+
+        adc_sensor
+    }
+}
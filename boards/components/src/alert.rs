@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for playing alert patterns on LEDs, buzzers or screens.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let alert_outputs = static_init!(
+//!     [&'static dyn kernel::hil::alert::AlertOutput; 1],
+//!     [static_init!(
+//!         kernel::hil::alert::AlertLed<'static, LedLow<'static, nrf52840::gpio::GPIOPin>>,
+//!         kernel::hil::alert::AlertLed::new(&led)
+//!     )]
+//! );
+//! let alert = components::alert::AlertComponent::new(mux_alarm, alert_outputs, ALERT_PATTERNS)
+//!     .finalize(components::alert_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::alert::AlertPlayer;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::alert::{AlertOutput, AlertPatterns};
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! alert_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let alert = kernel::static_buf!(
+            capsules_extra::alert::AlertPlayer<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, alert)
+    };};
+}
+
+pub type AlertComponentType<A> = AlertPlayer<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct AlertComponent<A: 'static + Alarm<'static>> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    outputs: &'static [&'static dyn AlertOutput],
+    patterns: AlertPatterns,
+}
+
+impl<A: 'static + Alarm<'static>> AlertComponent<A> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        outputs: &'static [&'static dyn AlertOutput],
+        patterns: AlertPatterns,
+    ) -> AlertComponent<A> {
+        AlertComponent {
+            alarm_mux,
+            outputs,
+            patterns,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for AlertComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<AlertComponentType<A>>,
+    );
+    type Output = &'static AlertComponentType<A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let alert = static_buffer
+            .1
+            .write(AlertPlayer::new(alarm, self.outputs, self.patterns));
+        time::Alarm::set_alarm_client(alarm, alert);
+        alert.init();
+
+        alert
+    }
+}
@@ -0,0 +1,83 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for sharing read-only assets in flash with processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let asset_store = components::asset_store::AssetStoreComponent::new(
+//!     board_kernel,
+//!     capsules_extra::asset_store::DRIVER_NUM,
+//!     core::slice::from_raw_parts(
+//!         core::ptr::addr_of!(_sassets),
+//!         core::ptr::addr_of!(_eassets) as usize - core::ptr::addr_of!(_sassets) as usize,
+//!     ),
+//! )
+//! .finalize(components::asset_store_component_static!());
+//! ```
+
+use capsules_extra::asset_store::{AssetStore, AssetTable};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! asset_store_component_static {
+    () => {{
+        let table = kernel::static_buf!(capsules_extra::asset_store::AssetTable);
+        let asset_store = kernel::static_buf!(
+            capsules_extra::asset_store::AssetStore<'static, components::asset_store::Capability>
+        );
+
+        (table, asset_store)
+    };};
+}
+
+pub type AssetStoreComponentType = AssetStore<'static, Capability>;
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct AssetStoreComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    region: &'static [u8],
+}
+
+impl AssetStoreComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        region: &'static [u8],
+    ) -> AssetStoreComponent {
+        AssetStoreComponent {
+            board_kernel,
+            driver_num,
+            region,
+        }
+    }
+}
+
+impl Component for AssetStoreComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<AssetTable>,
+        &'static mut MaybeUninit<AssetStore<'static, Capability>>,
+    );
+    type Output = &'static AssetStore<'static, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let table = static_buffer.0.write(AssetTable::new(self.region));
+
+        static_buffer.1.write(AssetStore::new(
+            self.board_kernel,
+            Capability,
+            table,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
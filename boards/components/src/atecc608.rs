@@ -0,0 +1,122 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the ATECC608 secure element.
+//!
+//! The I2C bus must run at 100 kHz or less for the wake pulse to be long
+//! enough.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let atecc608 = components::atecc608::Atecc608Component::new(
+//!     i2c_mux,
+//!     capsules_extra::atecc608::BASE_ADDR,
+//!     mux_alarm,
+//!     0,
+//! )
+//! .finalize(components::atecc608_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     nrf52840::i2c::TWI
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::atecc608::{Atecc608, BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::i2c;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! atecc608_component_static {
+    ($A:ty, $I:ty $(,)?) => {{
+        let buffer = kernel::static_buf!([u8; capsules_extra::atecc608::BUFFER_LEN]);
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let wake_i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let atecc608 = kernel::static_buf!(
+            capsules_extra::atecc608::Atecc608<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (alarm, i2c_device, wake_i2c_device, atecc608, buffer)
+    };};
+}
+
+pub type Atecc608ComponentType<A, I> = Atecc608<'static, A, I>;
+
+pub struct Atecc608Component<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    key_slot: u16,
+}
+
+impl<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Atecc608Component<A, I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        key_slot: u16,
+    ) -> Atecc608Component<A, I> {
+        Atecc608Component {
+            i2c_mux,
+            i2c_address,
+            alarm_mux,
+            key_slot,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Component
+    for Atecc608Component<A, I>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<
+            Atecc608<'static, VirtualMuxAlarm<'static, A>, I2CDevice<'static, I>>,
+        >,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+    );
+    type Output = &'static Atecc608<'static, VirtualMuxAlarm<'static, A>, I2CDevice<'static, I>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let atecc608_i2c = static_buffer
+            .1
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        // The wake pulse is sent by writing to the general call address.
+        let wake_i2c = static_buffer.2.write(I2CDevice::new(self.i2c_mux, 0x00));
+
+        let buffer = static_buffer.4.write([0; BUFFER_LEN]);
+
+        let atecc608_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        atecc608_alarm.setup();
+
+        let atecc608 = static_buffer.3.write(Atecc608::new(
+            atecc608_i2c,
+            wake_i2c,
+            atecc608_alarm,
+            self.key_slot,
+            buffer,
+        ));
+        atecc608_i2c.set_client(atecc608);
+        wake_i2c.set_client(atecc608);
+        atecc608_alarm.set_alarm_client(atecc608);
+
+        atecc608
+    }
+}
@@ -0,0 +1,204 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for remote attestation over UDP.
+//!
+//! The digest engine and the signer must not have another client.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sha = components::sha::ShaSoftware256Component::new()
+//!     .finalize(components::sha_software_256_component_static!());
+//!
+//! let attestation = components::attestation::AttestationComponent::new(
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     capsules_extra::attestation::DEFAULT_PORT,
+//!     measured_boot,
+//!     sha,
+//!     atecc608,
+//! )
+//! .finalize(components::attestation_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     components::atecc608::Atecc608ComponentType<
+//!         capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!         capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, nrf52840::i2c::TWI>,
+//!     >
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::attestation::{Attestation, MIN_BUFFER_LEN, SIGNATURE_LEN};
+use capsules_extra::measured_boot::{MeasurementLog, DIGEST_LEN};
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use core::mem::MaybeUninit;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::digest::{DigestDataHash, Sha256};
+use kernel::hil::public_key_crypto::signature::SignatureSign;
+use kernel::hil::time::Alarm;
+
+const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
+
+// A response must fit in a datagram.
+const _: () = assert!(MIN_BUFFER_LEN <= MAX_PAYLOAD_LEN);
+
+#[macro_export]
+macro_rules! attestation_component_static {
+    ($A:ty, $D:ty, $S:ty $(,)?) => {{
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let hash = kernel::static_buf!([u8; capsules_extra::measured_boot::DIGEST_LEN]);
+        let signature = kernel::static_buf!([u8; capsules_extra::attestation::SIGNATURE_LEN]);
+        let attestation =
+            kernel::static_buf!(capsules_extra::attestation::Attestation<'static, $D, $S>);
+
+        (
+            udp_send,
+            udp_vis_cap,
+            net_cap,
+            udp_recv,
+            buffer,
+            hash,
+            signature,
+            attestation,
+        )
+    };};
+}
+
+pub type AttestationComponentType<D, S> = Attestation<'static, D, S>;
+
+pub struct AttestationComponent<
+    A: 'static + Alarm<'static>,
+    D: 'static + DigestDataHash<'static, DIGEST_LEN> + Sha256,
+    S: 'static + SignatureSign<'static, DIGEST_LEN, SIGNATURE_LEN>,
+> {
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    port: u16,
+    log: &'static dyn MeasurementLog,
+    digest: &'static D,
+    signer: &'static S,
+}
+
+impl<
+        A: 'static + Alarm<'static>,
+        D: 'static + DigestDataHash<'static, DIGEST_LEN> + Sha256,
+        S: 'static + SignatureSign<'static, DIGEST_LEN, SIGNATURE_LEN>,
+    > AttestationComponent<A, D, S>
+{
+    pub fn new(
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        port: u16,
+        log: &'static dyn MeasurementLog,
+        digest: &'static D,
+        signer: &'static S,
+    ) -> Self {
+        Self {
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            port,
+            log,
+            digest,
+            signer,
+        }
+    }
+}
+
+impl<
+        A: 'static + Alarm<'static>,
+        D: 'static + DigestDataHash<'static, DIGEST_LEN> + Sha256,
+        S: 'static + SignatureSign<'static, DIGEST_LEN, SIGNATURE_LEN>,
+    > Component for AttestationComponent<A, D, S>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; DIGEST_LEN]>,
+        &'static mut MaybeUninit<[u8; SIGNATURE_LEN]>,
+        &'static mut MaybeUninit<Attestation<'static, D, S>>,
+    );
+    type Output = &'static Attestation<'static, D, S>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.1.write(UdpVisibilityCapability::new(&create_cap));
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        // Verifiers may be anywhere, so replies can go to any address.
+        let net_cap = s.2.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+        let udp_recv = s.3.write(UDPReceiver::new());
+        self.udp_recv_mux.add_client(udp_recv);
+
+        let buffer = s.4.write([0; MAX_PAYLOAD_LEN]);
+        let hash = s.5.write([0; DIGEST_LEN]);
+        let signature = s.6.write([0; SIGNATURE_LEN]);
+
+        let attestation = s.7.write(Attestation::new(
+            udp_send,
+            udp_recv,
+            self.port_table,
+            net_cap,
+            self.log,
+            self.digest,
+            self.signer,
+            buffer,
+            hash,
+            signature,
+        ));
+        udp_send.set_client(attestation);
+        udp_recv.set_client(attestation);
+        self.digest.set_client(attestation);
+        self.signer.set_sign_client(attestation);
+
+        if attestation.bind(self.port).is_err() {
+            kernel::debug!("Attestation: could not bind port {}", self.port);
+        }
+
+        attestation
+    }
+}
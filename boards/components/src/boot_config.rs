@@ -0,0 +1,139 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the persistent boot configuration.
+//!
+//! `BUF_LEN` must be the size of the boot configuration region.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! kernel::storage_volume!(BOOT_CONFIG, 4);
+//!
+//! let boot_env = capsules_extra::boot_config::BootEnv::new(&BOOT_CONFIG);
+//! let baud_rate = boot_env.get_u32("console.baud").unwrap_or(115200);
+//!
+//! let boot_config = components::boot_config::BootConfigComponent::new(
+//!     board_kernel,
+//!     capsules_extra::boot_config::DRIVER_NUM,
+//!     &base_peripherals.nvmc,
+//!     &BOOT_CONFIG,
+//!     core::ptr::addr_of!(BOOT_CONFIG) as usize,
+//!     kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1).unwrap()),
+//! )
+//! .finalize(components::boot_config_component_static!(
+//!     nrf52840::nvmc::Nvmc,
+//!     4096
+//! ));
+//! ```
+
+use capsules_extra::boot_config::BootConfigStore;
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::process::ShortId;
+
+#[macro_export]
+macro_rules! boot_config_component_static {
+    ($F:ty, $buffer_size: literal) => {{
+        let buffer = kernel::static_buf!([u8; $buffer_size]);
+        let page_buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let nv_to_page = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let boot_config =
+            kernel::static_buf!(capsules_extra::boot_config::BootConfigStore<'static>);
+        (buffer, page_buffer, nv_to_page, boot_config)
+    };};
+}
+
+pub type BootConfigComponentType = BootConfigStore<'static>;
+
+pub struct BootConfigComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    const BUF_LEN: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    storage: &'static F,
+    region: &'static [u8],
+    region_address: usize,
+    privileged: ShortId,
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        const BUF_LEN: usize,
+    > BootConfigComponent<F, BUF_LEN>
+{
+    /// `region` is the memory-mapped boot configuration region, and
+    /// `region_address` its address in `storage`.
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        storage: &'static F,
+        region: &'static [u8],
+        region_address: usize,
+        privileged: ShortId,
+    ) -> BootConfigComponent<F, BUF_LEN> {
+        BootConfigComponent {
+            board_kernel,
+            driver_num,
+            storage,
+            region,
+            region_address,
+            privileged,
+        }
+    }
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        const BUF_LEN: usize,
+    > Component for BootConfigComponent<F, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<BootConfigStore<'static>>,
+    );
+    type Output = &'static BootConfigStore<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let buffer = static_buffer.0.write([0; BUF_LEN]);
+
+        let flash_pagebuffer = static_buffer
+            .1
+            .write(<F as hil::flash::Flash>::Page::default());
+
+        let nv_to_page = static_buffer
+            .2
+            .write(NonvolatileToPages::new(self.storage, flash_pagebuffer));
+        self.storage.set_client(nv_to_page);
+
+        let boot_config = static_buffer.3.write(BootConfigStore::new(
+            nv_to_page,
+            self.region,
+            self.region_address,
+            self.privileged,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+        ));
+
+        nv_to_page.set_client(boot_config);
+
+        boot_config
+    }
+}
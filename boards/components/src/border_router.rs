@@ -0,0 +1,289 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for a border router between 6LoWPAN and a SLIP serial line.
+//!
+//! The border router uses its own MAC user and 6LoWPAN state, next to the
+//! ones of the UDP stack, so it sees every packet received from the mesh
+//! and forwards those whose destination is off-mesh.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let (border_router, slip) = components::border_router::BorderRouterComponent::new(
+//!     mux_mac,
+//!     DEFAULT_CTX_PREFIX_LEN,
+//!     DEFAULT_CTX_PREFIX,
+//!     DST_MAC_ADDR,
+//!     src_mac_from_serial_num,
+//!     MESH_PREFIX,
+//!     64,
+//!     mux_alarm,
+//!     uart_mux,
+//! )
+//! .finalize(components::border_router_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     nrf52840::ieee802154_radio::Radio
+//! ));
+//! slip.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::ieee802154::virtual_mac::{MacUser, MuxMac};
+use capsules_extra::net::border_router::{BorderRouter, IP6Interface};
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvStruct};
+use capsules_extra::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange,
+};
+use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+use capsules_extra::net::slip::{self, Slip};
+use capsules_extra::net::udp::UDPHeader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+use kernel::hil::uart;
+
+/// Largest IPv6 packet forwarded, the IPv6 minimum MTU.
+pub const MTU: usize = 1280;
+
+/// Size of the SLIP transmit buffer.
+pub const SLIP_TX_BUF_LEN: usize = slip::tx_buffer_len(MTU);
+
+#[macro_export]
+macro_rules! border_router_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+        use components::border_router::{MTU, SLIP_TX_BUF_LEN};
+
+        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                sixlowpan_compression::Context,
+            >
+        );
+        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let ip6_receive =
+            kernel::static_buf!(capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct<'static>);
+        let ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let uart_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice<'static>);
+        let slip = kernel::static_buf!(capsules_extra::net::slip::Slip<'static>);
+        let border_router = kernel::static_buf!(
+            capsules_extra::net::border_router::BorderRouter<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let sixlowpan_rx = kernel::static_buf!([u8; MTU]);
+        let ip6_payload = kernel::static_buf!([u8; MTU]);
+        let slip_tx = kernel::static_buf!([u8; SLIP_TX_BUF_LEN]);
+        let slip_rx_byte = kernel::static_buf!([u8; 1]);
+        let slip_rx_packet = kernel::static_buf!([u8; MTU]);
+        let forward_buf = kernel::static_buf!([u8; MTU]);
+
+        (
+            alarm,
+            mac_user,
+            sixlowpan,
+            rx_state,
+            ip6_receive,
+            ip6_send,
+            ip6_packet,
+            uart_device,
+            slip,
+            border_router,
+            ip_vis_cap,
+            net_cap,
+            radio_buf,
+            sixlowpan_rx,
+            ip6_payload,
+            slip_tx,
+            slip_rx_byte,
+            slip_rx_packet,
+            forward_buf,
+        )
+    };};
+}
+
+pub type BorderRouterComponentType<A> =
+    BorderRouter<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>;
+
+pub struct BorderRouterComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
+    mux_mac: &'static MuxMac<'static, M>,
+    ctx_pfix_len: u8,
+    ctx_pfix: [u8; 16],
+    dst_mac_addr: MacAddress,
+    src_mac_addr: MacAddress,
+    mesh_prefix: IPAddr,
+    mesh_prefix_len: u8,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    uart_mux: &'static MuxUart<'static>,
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> BorderRouterComponent<A, M> {
+    pub fn new(
+        mux_mac: &'static MuxMac<'static, M>,
+        ctx_pfix_len: u8,
+        ctx_pfix: [u8; 16],
+        dst_mac_addr: MacAddress,
+        src_mac_addr: MacAddress,
+        mesh_prefix: IPAddr,
+        mesh_prefix_len: u8,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        uart_mux: &'static MuxUart<'static>,
+    ) -> Self {
+        Self {
+            mux_mac,
+            ctx_pfix_len,
+            ctx_pfix,
+            dst_mac_addr,
+            src_mac_addr,
+            mesh_prefix,
+            mesh_prefix_len,
+            alarm_mux,
+            uart_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for BorderRouterComponent<A, M> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                sixlowpan_compression::Context,
+            >,
+        >,
+        &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
+        &'static mut MaybeUninit<IP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<Slip<'static>>,
+        &'static mut MaybeUninit<BorderRouterComponentType<A>>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; MTU]>,
+        &'static mut MaybeUninit<[u8; MTU]>,
+        &'static mut MaybeUninit<[u8; SLIP_TX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 1]>,
+        &'static mut MaybeUninit<[u8; MTU]>,
+        &'static mut MaybeUninit<[u8; MTU]>,
+    );
+    type Output = (
+        &'static BorderRouterComponentType<A>,
+        &'static Slip<'static>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        virtual_alarm.setup();
+
+        let mac_user = s.1.write(MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(mac_user);
+
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let ip_vis = s.10.write(IpVisibilityCapability::new(&create_cap));
+        let net_cap = s.11.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let sixlowpan = s.2.write(sixlowpan_state::Sixlowpan::new(
+            sixlowpan_compression::Context {
+                prefix: self.ctx_pfix,
+                prefix_len: self.ctx_pfix_len,
+                id: 0,
+                compress: false,
+            },
+            virtual_alarm,
+        ));
+        let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
+        let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
+        let rx_state =
+            s.3.write(sixlowpan_state::RxState::new(s.13.write([0; MTU])));
+        sixlowpan_state.add_rx_state(rx_state);
+        mac_user.set_receive_client(sixlowpan);
+
+        let ip6_packet = s.6.write(IP6Packet::new(IPPayload {
+            header: TransportHeader::UDP(UDPHeader::new()),
+            payload: s.14.write([0; MTU]),
+        }));
+        let ip_send = s.5.write(IP6SendStruct::new(
+            ip6_packet,
+            virtual_alarm,
+            s.12.write([0; radio::MAX_BUF_SIZE]),
+            sixlowpan_tx,
+            mac_user,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        virtual_alarm.set_alarm_client(ip_send);
+        mac_user.set_transmit_client(ip_send);
+
+        let uart_device = s.7.write(UartDevice::new(self.uart_mux, true));
+        uart_device.setup();
+        let slip = s.8.write(Slip::new(
+            uart_device,
+            s.15.write([0; SLIP_TX_BUF_LEN]),
+            s.16.write([0; 1]),
+            s.17.write([0; MTU]),
+        ));
+        uart::Transmit::set_transmit_client(uart_device, slip);
+        uart::Receive::set_receive_client(uart_device, slip);
+
+        let border_router = s.9.write(BorderRouter::new(
+            ip_send,
+            slip,
+            self.mesh_prefix,
+            self.mesh_prefix_len,
+            net_cap,
+            s.18.write([0; MTU]),
+        ));
+        ip_send.set_client(border_router);
+        slip.set_client(border_router);
+
+        let ip_receive = s.4.write(IP6RecvStruct::new());
+        sixlowpan_state.set_rx_client(ip_receive);
+        ip_receive.set_client(border_router);
+
+        (border_router, slip)
+    }
+}
@@ -22,8 +22,12 @@
 //! ));
 //! ```
 //!
+//! The length of the transmit queue of each process can be given as a second
+//! argument of `can_component_static!`, for example
+//! `can_component_static!(stm32f429zi::can::Can<'static>, 8)`.
+//!
 
-use capsules_extra::can::CanCapsule;
+use capsules_extra::can::{CanCapsule, DEFAULT_TX_QUEUE_LEN};
 use core::mem::MaybeUninit;
 use kernel::component::Component;
 use kernel::hil::can;
@@ -32,6 +36,9 @@ use kernel::{capabilities, create_capability};
 #[macro_export]
 macro_rules! can_component_static {
     ($C:ty $(,)?) => {{
+        $crate::can_component_static!($C, capsules_extra::can::DEFAULT_TX_QUEUE_LEN)
+    };};
+    ($C:ty, $N:expr $(,)?) => {{
         use capsules_extra::can::CanCapsule;
         use core::mem::MaybeUninit;
         use kernel::hil::can;
@@ -39,23 +46,23 @@ macro_rules! can_component_static {
 
         let CAN_TX_BUF = static_buf!([u8; can::STANDARD_CAN_PACKET_SIZE]);
         let CAN_RX_BUF = static_buf!([u8; can::STANDARD_CAN_PACKET_SIZE]);
-        let can = static_buf!(capsules_extra::can::CanCapsule<'static, $C>);
+        let can = static_buf!(capsules_extra::can::CanCapsule<'static, $C, { $N }>);
         (can, CAN_TX_BUF, CAN_RX_BUF)
     };};
 }
 
-pub struct CanComponent<A: 'static + can::Can> {
+pub struct CanComponent<A: 'static + can::Can, const N: usize = DEFAULT_TX_QUEUE_LEN> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
     can: &'static A,
 }
 
-impl<A: 'static + can::Can> CanComponent<A> {
+impl<A: 'static + can::Can, const N: usize> CanComponent<A, N> {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
         driver_num: usize,
         can: &'static A,
-    ) -> CanComponent<A> {
+    ) -> CanComponent<A, N> {
         CanComponent {
             board_kernel,
             driver_num,
@@ -64,13 +71,13 @@ impl<A: 'static + can::Can> CanComponent<A> {
     }
 }
 
-impl<A: 'static + can::Can> Component for CanComponent<A> {
+impl<A: 'static + can::Can, const N: usize> Component for CanComponent<A, N> {
     type StaticInput = (
-        &'static mut MaybeUninit<CanCapsule<'static, A>>,
+        &'static mut MaybeUninit<CanCapsule<'static, A, N>>,
         &'static mut MaybeUninit<[u8; can::STANDARD_CAN_PACKET_SIZE]>,
         &'static mut MaybeUninit<[u8; can::STANDARD_CAN_PACKET_SIZE]>,
     );
-    type Output = &'static CanCapsule<'static, A>;
+    type Output = &'static CanCapsule<'static, A, N>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
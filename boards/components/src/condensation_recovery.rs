@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the condensation recovery of humidity sensors.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let recovery = components::condensation_recovery::CondensationRecoveryComponent::new(
+//!     sht3x, mux_alarm, 3,
+//! )
+//! .finalize(components::condensation_recovery_component_static!(
+//!     nrf52::rtc::Rtc<'static>,
+//!     SHT3xSensor
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::condensation_recovery::CondensationRecovery;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::sensors::HumidityDriver;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! condensation_recovery_component_static {
+    ($A:ty, $H:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let recovery = kernel::static_buf!(
+            capsules_extra::condensation_recovery::CondensationRecovery<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $H,
+            >
+        );
+
+        (alarm, recovery)
+    };};
+}
+
+pub type CondensationRecoveryComponentType<A, H> =
+    CondensationRecovery<'static, VirtualMuxAlarm<'static, A>, H>;
+
+pub struct CondensationRecoveryComponent<
+    A: 'static + Alarm<'static>,
+    H: 'static + HumidityDriver<'static>,
+> {
+    sensor: &'static H,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    discard_count: usize,
+}
+
+impl<A: 'static + Alarm<'static>, H: 'static + HumidityDriver<'static>>
+    CondensationRecoveryComponent<A, H>
+{
+    pub fn new(
+        sensor: &'static H,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        discard_count: usize,
+    ) -> CondensationRecoveryComponent<A, H> {
+        CondensationRecoveryComponent {
+            sensor,
+            alarm_mux,
+            discard_count,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, H: 'static + HumidityDriver<'static>> Component
+    for CondensationRecoveryComponent<A, H>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<CondensationRecoveryComponentType<A, H>>,
+    );
+    type Output = &'static CondensationRecoveryComponentType<A, H>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let recovery = static_buffer.1.write(CondensationRecovery::new(
+            self.sensor,
+            alarm,
+            self.discard_count,
+        ));
+        HumidityDriver::set_client(self.sensor, recovery);
+        time::Alarm::set_alarm_client(alarm, recovery);
+
+        recovery
+    }
+}
@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for UART fingerprint modules.
+//!
+//! The module gets its own virtual UART device and alarm.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fingerprint = components::fingerprint::FingerprintComponent::new(
+//!     board_kernel,
+//!     capsules_extra::fingerprint::DRIVER_NUM,
+//!     uart_mux,
+//!     mux_alarm,
+//!     162,
+//! )
+//! .finalize(components::fingerprint_component_static!(nrf52840::rtc::Rtc));
+//!
+//! // Optionally, save the templates in the key-value store.
+//! fingerprint.set_template_store(kv_fingerprint, permissions);
+//! kernel::hil::kv::KVPermissions::set_client(kv_fingerprint, fingerprint);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::fingerprint::{
+    Fingerprint, BUFFER_LEN, DEFAULT_ADDRESS, KEY_LEN, TEMPLATE_BUFFER_LEN,
+};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! fingerprint_component_static {
+    ($A:ty $(,)?) => {{
+        let uart = kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let fingerprint = kernel::static_buf!(
+            capsules_extra::fingerprint::Fingerprint<
+                'static,
+                capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let tx_buffer = kernel::static_buf!([u8; capsules_extra::fingerprint::BUFFER_LEN]);
+        let rx_buffer = kernel::static_buf!([u8; capsules_extra::fingerprint::BUFFER_LEN]);
+        let template_buffer =
+            kernel::static_buf!([u8; capsules_extra::fingerprint::TEMPLATE_BUFFER_LEN]);
+        let key_buffer = kernel::static_buf!([u8; capsules_extra::fingerprint::KEY_LEN]);
+
+        (
+            uart,
+            alarm,
+            fingerprint,
+            tx_buffer,
+            rx_buffer,
+            template_buffer,
+            key_buffer,
+        )
+    };};
+}
+
+pub type FingerprintComponentType<A> =
+    Fingerprint<'static, UartDevice<'static>, VirtualMuxAlarm<'static, A>>;
+
+pub struct FingerprintComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    uart_mux: &'static MuxUart<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    capacity: u16,
+}
+
+impl<A: 'static + Alarm<'static>> FingerprintComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        uart_mux: &'static MuxUart<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        capacity: u16,
+    ) -> FingerprintComponent<A> {
+        FingerprintComponent {
+            board_kernel,
+            driver_num,
+            uart_mux,
+            alarm_mux,
+            capacity,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for FingerprintComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<FingerprintComponentType<A>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; TEMPLATE_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+    );
+    type Output = &'static FingerprintComponentType<A>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let uart = s.0.write(UartDevice::new(self.uart_mux, true));
+        uart.setup();
+        let alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let fingerprint = s.2.write(Fingerprint::new(
+            uart,
+            alarm,
+            DEFAULT_ADDRESS,
+            self.capacity,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            s.3.write([0; BUFFER_LEN]),
+            s.4.write([0; BUFFER_LEN]),
+            s.5.write([0; TEMPLATE_BUFFER_LEN]),
+            s.6.write([0; KEY_LEN]),
+        ));
+        hil::uart::Transmit::set_transmit_client(uart, fingerprint);
+        hil::uart::Receive::set_receive_client(uart, fingerprint);
+        alarm.set_alarm_client(fingerprint);
+
+        fingerprint
+    }
+}
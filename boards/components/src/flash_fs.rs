@@ -0,0 +1,122 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the flash file system and its syscall driver.
+//!
+//! The component mounts the file system on a region of a shared flash.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let mux_flash = components::flash::FlashMuxComponent::new(&base_peripherals.nvmc).finalize(
+//!     components::flash_mux_component_static!(nrf52840::nvmc::Nvmc),
+//! );
+//!
+//! let file_system = components::flash_fs::FlashFsComponent::new(
+//!     board_kernel,
+//!     capsules_extra::flash_fs_driver::DRIVER_NUM,
+//!     mux_flash,
+//!     0xC0000, // Start address of the region
+//!     0x20000, // Size of the region
+//! )
+//! .finalize(components::flash_fs_component_static!(nrf52840::nvmc::Nvmc));
+//! ```
+
+use capsules_core::virtualizers::virtual_flash::FlashUser;
+use capsules_core::virtualizers::virtual_flash::MuxFlash;
+use capsules_extra::flash_fs::FlashFs;
+use capsules_extra::flash_fs_driver::FileSystemDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::flash::{Flash, HasClient};
+
+#[macro_export]
+macro_rules! flash_fs_component_static {
+    ($F:ty $(,)?) => {{
+        let flash =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_flash::FlashUser<'static, $F>);
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let flash_fs = kernel::static_buf!(
+            capsules_extra::flash_fs::FlashFs<
+                'static,
+                capsules_core::virtualizers::virtual_flash::FlashUser<'static, $F>,
+            >
+        );
+        let driver = kernel::static_buf!(
+            capsules_extra::flash_fs_driver::FileSystemDriver<
+                'static,
+                capsules_core::virtualizers::virtual_flash::FlashUser<'static, $F>,
+            >
+        );
+
+        (flash, page, flash_fs, driver)
+    };};
+}
+
+pub type FlashFsComponentType<F> = FileSystemDriver<'static, FlashUser<'static, F>>;
+
+pub struct FlashFsComponent<F: 'static + Flash + HasClient<'static, MuxFlash<'static, F>>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    mux_flash: &'static MuxFlash<'static, F>,
+    address: usize,
+    length: usize,
+}
+
+impl<F: 'static + Flash + HasClient<'static, MuxFlash<'static, F>>> FlashFsComponent<F> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        mux_flash: &'static MuxFlash<'static, F>,
+        address: usize,
+        length: usize,
+    ) -> FlashFsComponent<F> {
+        FlashFsComponent {
+            board_kernel,
+            driver_num,
+            mux_flash,
+            address,
+            length,
+        }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, MuxFlash<'static, F>>> Component
+    for FlashFsComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<FlashUser<'static, F>>,
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<FlashFs<'static, FlashUser<'static, F>>>,
+        &'static mut MaybeUninit<FileSystemDriver<'static, FlashUser<'static, F>>>,
+    );
+    type Output = &'static FileSystemDriver<'static, FlashUser<'static, F>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let virtual_flash = static_buffer.0.write(FlashUser::new(self.mux_flash));
+        let page = static_buffer.1.write(F::Page::default());
+
+        let flash_fs =
+            static_buffer
+                .2
+                .write(FlashFs::new(virtual_flash, self.address, self.length, page));
+        virtual_flash.set_client(flash_fs);
+        flash_fs.register();
+
+        let driver = static_buffer.3.write(FileSystemDriver::new(
+            flash_fs,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        flash_fs.set_client(driver);
+
+        let _ = flash_fs.mount();
+
+        driver
+    }
+}
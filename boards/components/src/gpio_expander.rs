@@ -0,0 +1,125 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the MCP23017 and PCA9555 I2C GPIO expanders.
+//!
+//! The component returns the 16 pins of the expander, which can be passed to
+//! the GPIO or button components like any other pin.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let expander_pins = components::gpio_expander::GpioExpanderComponent::new(
+//!     i2c_mux,
+//!     0x20,
+//!     capsules_extra::gpio_expander::Model::Mcp23017,
+//!     &nrf52840_peripherals.gpio_port[Pin::P1_08],
+//! )
+//! .finalize(components::gpio_expander_component_static!(nrf52840::i2c::TWI));
+//!
+//! let gpio = components::gpio::GpioComponent::new(
+//!     board_kernel,
+//!     capsules_core::gpio::DRIVER_NUM,
+//!     components::gpio_component_helper!(
+//!         capsules_extra::gpio_expander::GpioExpanderPin<
+//!             'static,
+//!             capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, nrf52840::i2c::TWI>,
+//!         >,
+//!         0 => &expander_pins[0],
+//!         1 => &expander_pins[1],
+//!     ),
+//! )
+//! .finalize(...);
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::gpio_expander::{
+    GpioExpander, GpioExpanderPin, Model, BUFFER_LENGTH, NUM_PINS,
+};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+
+#[macro_export]
+macro_rules! gpio_expander_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::gpio_expander::BUFFER_LENGTH]);
+        let expander = kernel::static_buf!(
+            capsules_extra::gpio_expander::GpioExpander<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+        let pins = kernel::static_buf!(
+            [capsules_extra::gpio_expander::GpioExpanderPin<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >; capsules_extra::gpio_expander::NUM_PINS]
+        );
+
+        (i2c_device, buffer, expander, pins)
+    };};
+}
+
+pub type GpioExpanderPinType<I> = GpioExpanderPin<'static, I2CDevice<'static, I>>;
+
+pub struct GpioExpanderComponent<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    model: Model,
+    interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> GpioExpanderComponent<I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        model: Model,
+        interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+    ) -> GpioExpanderComponent<I> {
+        GpioExpanderComponent {
+            i2c_mux,
+            i2c_address,
+            model,
+            interrupt_pin,
+        }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for GpioExpanderComponent<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LENGTH]>,
+        &'static mut MaybeUninit<GpioExpander<'static, I2CDevice<'static, I>>>,
+        &'static mut MaybeUninit<[GpioExpanderPinType<I>; NUM_PINS]>,
+    );
+    type Output = &'static [GpioExpanderPinType<I>; NUM_PINS];
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let i2c_device = static_buffer
+            .0
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = static_buffer.1.write([0; BUFFER_LENGTH]);
+
+        let expander = static_buffer.2.write(GpioExpander::new(
+            i2c_device,
+            self.model,
+            self.interrupt_pin,
+            buffer,
+        ));
+        i2c_device.set_client(expander);
+        self.interrupt_pin.set_client(expander);
+
+        let pins = static_buffer.3.write(core::array::from_fn(|pin| {
+            GpioExpanderPin::new(expander, pin)
+        }));
+
+        expander.init();
+        pins
+    }
+}
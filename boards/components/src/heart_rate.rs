@@ -0,0 +1,71 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the heart beat detection on an optical pulse sensor.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let heart_rate = components::heart_rate::HeartRateComponent::new(
+//!     board_kernel,
+//!     capsules_extra::heart_rate::DRIVER_NUM,
+//!     max30102,
+//! )
+//! .finalize(components::heart_rate_component_static!(
+//!     components::max30102::Max30102ComponentType<nrf52840::i2c::TWI<'static>>
+//! ));
+//! ```
+
+use capsules_extra::heart_rate::HeartRate;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::sensors::PulseOximeterDriver;
+
+#[macro_export]
+macro_rules! heart_rate_component_static {
+    ($S:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::heart_rate::HeartRate<'static, $S>)
+    };};
+}
+
+pub type HeartRateComponentType<S> = HeartRate<'static, S>;
+
+pub struct HeartRateComponent<S: 'static + PulseOximeterDriver<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    sensor: &'static S,
+}
+
+impl<S: 'static + PulseOximeterDriver<'static>> HeartRateComponent<S> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        sensor: &'static S,
+    ) -> HeartRateComponent<S> {
+        HeartRateComponent {
+            board_kernel,
+            driver_num,
+            sensor,
+        }
+    }
+}
+
+impl<S: 'static + PulseOximeterDriver<'static>> Component for HeartRateComponent<S> {
+    type StaticInput = &'static mut MaybeUninit<HeartRate<'static, S>>;
+    type Output = &'static HeartRate<'static, S>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let heart_rate = static_buffer.write(HeartRate::new(
+            self.sensor,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.sensor.set_client(heart_rate);
+
+        heart_rate
+    }
+}
@@ -0,0 +1,93 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for neural network inference on models from the asset store.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let inference = components::inference::InferenceComponent::new(
+//!     board_kernel,
+//!     capsules_extra::inference::DRIVER_NUM,
+//!     &capsules_extra::inference::PortableKernels,
+//!     asset_store.assets(),
+//! )
+//! .finalize(components::inference_component_static!(
+//!     capsules_extra::inference::PortableKernels,
+//!     1024,
+//! ));
+//! ```
+
+use capsules_extra::asset_store::AssetTable;
+use capsules_extra::inference::Inference;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::neural_network::NeuralNetworkKernels;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! inference_component_static {
+    ($K:ty, $L:expr $(,)?) => {{
+        let inference = kernel::static_buf!(capsules_extra::inference::Inference<'static, $K>);
+        let input_buffer = kernel::static_buf!([u8; $L]);
+        let output_buffer = kernel::static_buf!([u8; $L]);
+
+        (inference, input_buffer, output_buffer)
+    };};
+}
+
+pub type InferenceComponentType<K> = Inference<'static, K>;
+
+pub struct InferenceComponent<K: 'static + NeuralNetworkKernels, const L: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    kernels: &'static K,
+    assets: &'static AssetTable,
+}
+
+impl<K: 'static + NeuralNetworkKernels, const L: usize> InferenceComponent<K, L> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        kernels: &'static K,
+        assets: &'static AssetTable,
+    ) -> InferenceComponent<K, L> {
+        InferenceComponent {
+            board_kernel,
+            driver_num,
+            kernels,
+            assets,
+        }
+    }
+}
+
+impl<K: 'static + NeuralNetworkKernels, const L: usize> Component for InferenceComponent<K, L> {
+    type StaticInput = (
+        &'static mut MaybeUninit<Inference<'static, K>>,
+        &'static mut MaybeUninit<[u8; L]>,
+        &'static mut MaybeUninit<[u8; L]>,
+    );
+    type Output = &'static Inference<'static, K>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let input_buffer = s.1.write([0; L]);
+        let output_buffer = s.2.write([0; L]);
+
+        let inference = s.0.write(Inference::new(
+            self.kernels,
+            self.assets,
+            input_buffer,
+            output_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        inference.register();
+
+        inference
+    }
+}
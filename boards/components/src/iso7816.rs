@@ -0,0 +1,125 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for smart cards on a UART in smart card mode.
+//!
+//! The UART is used directly, not through a UART mux, since the card needs
+//! the whole UART. The driver gets its own virtual alarm.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let iso7816 = components::iso7816::Iso7816Component::new(
+//!     board_kernel,
+//!     capsules_extra::iso7816::DRIVER_NUM,
+//!     &base_peripherals.usart2,
+//!     card_reset_pin,
+//!     mux_alarm,
+//!     3_571_200,
+//! )
+//! .finalize(components::iso7816_component_static!(
+//!     stm32f429zi::usart::Usart<'static, stm32f429zi::dma::Dma1<'static>>,
+//!     stm32f429zi::tim2::Tim2
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::iso7816::{Iso7816, MAX_APDU_LEN, RESPONSE_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::gpio;
+use kernel::hil::time::Alarm;
+use kernel::hil::uart;
+
+#[macro_export]
+macro_rules! iso7816_component_static {
+    ($U:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let iso7816 = kernel::static_buf!(
+            capsules_extra::iso7816::Iso7816<
+                'static,
+                $U,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let command = kernel::static_buf!([u8; capsules_extra::iso7816::MAX_APDU_LEN]);
+        let response = kernel::static_buf!([u8; capsules_extra::iso7816::RESPONSE_LEN]);
+
+        (alarm, iso7816, command, response)
+    };};
+}
+
+pub type Iso7816ComponentType<U, A> = Iso7816<'static, U, VirtualMuxAlarm<'static, A>>;
+
+pub struct Iso7816Component<
+    U: 'static + uart::UartData<'static> + uart::SmartCard,
+    A: 'static + Alarm<'static>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    uart: &'static U,
+    reset: &'static dyn gpio::Pin,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    clock_hz: u32,
+}
+
+impl<U: 'static + uart::UartData<'static> + uart::SmartCard, A: 'static + Alarm<'static>>
+    Iso7816Component<U, A>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        uart: &'static U,
+        reset: &'static dyn gpio::Pin,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        clock_hz: u32,
+    ) -> Iso7816Component<U, A> {
+        Iso7816Component {
+            board_kernel,
+            driver_num,
+            uart,
+            reset,
+            alarm_mux,
+            clock_hz,
+        }
+    }
+}
+
+impl<U: 'static + uart::UartData<'static> + uart::SmartCard, A: 'static + Alarm<'static>> Component
+    for Iso7816Component<U, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Iso7816ComponentType<U, A>>,
+        &'static mut MaybeUninit<[u8; MAX_APDU_LEN]>,
+        &'static mut MaybeUninit<[u8; RESPONSE_LEN]>,
+    );
+    type Output = &'static Iso7816ComponentType<U, A>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let iso7816 = s.1.write(Iso7816::new(
+            self.uart,
+            alarm,
+            self.reset,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            self.clock_hz,
+            s.2.write([0; MAX_APDU_LEN]),
+            s.3.write([0; RESPONSE_LEN]),
+        ));
+        self.uart.set_transmit_client(iso7816);
+        self.uart.set_receive_client(iso7816);
+        alarm.set_alarm_client(iso7816);
+
+        iso7816
+    }
+}
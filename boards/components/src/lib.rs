@@ -5,23 +5,32 @@
 #![no_std]
 
 pub mod adc;
+pub mod adc_acquisition;
 pub mod adc_microphone;
+pub mod adc_sensor;
 pub mod aes;
 pub mod air_quality;
 pub mod alarm;
+pub mod alert;
 pub mod analog_comparator;
 pub mod apds9960;
 pub mod app_flash_driver;
 pub mod appid;
+pub mod asset_store;
+pub mod atecc608;
+pub mod attestation;
 pub mod ble;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
+pub mod boot_config;
+pub mod border_router;
 pub mod bus;
 pub mod button;
 pub mod can;
 pub mod ccs811;
 pub mod cdc;
+pub mod condensation_recovery;
 pub mod console;
 pub mod crc;
 pub mod ctap;
@@ -30,19 +39,25 @@ pub mod date_time;
 pub mod debug_queue;
 pub mod debug_writer;
 pub mod eui64;
+pub mod fingerprint;
 pub mod flash;
+pub mod flash_fs;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700;
 pub mod gpio;
+pub mod gpio_expander;
 pub mod hd44780;
+pub mod heart_rate;
 pub mod hmac;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
 pub mod ieee802154;
+pub mod inference;
 pub mod isl29035;
+pub mod iso7816;
 pub mod keyboard_hid;
 pub mod kv;
 pub mod l3gd20;
@@ -56,12 +71,18 @@ pub mod lsm303agr;
 pub mod lsm303dlhc;
 pub mod lsm6dsox;
 pub mod ltc294x;
+pub mod max30102;
+pub mod measured_boot;
 pub mod mlx90614;
+pub mod motor_control;
 pub mod mx25r6435f;
+pub mod net_stats;
 pub mod ninedof;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
 pub mod panic_button;
+pub mod pn532;
+pub mod power_control;
 pub mod pressure;
 pub mod process_console;
 pub mod process_printer;
@@ -69,6 +90,8 @@ pub mod proximity;
 pub mod pwm;
 pub mod rf233;
 pub mod rng;
+pub mod rs485;
+pub mod scale;
 pub mod sched;
 pub mod screen;
 pub mod segger_rtt;
@@ -78,11 +101,15 @@ pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
 pub mod siphash;
+pub mod sntp;
 pub mod sound_pressure;
 pub mod spi;
 pub mod ssd1306;
 pub mod st77xx;
+pub mod system_config;
+pub mod tamper;
 pub mod temperature;
+pub mod temperature_compensation;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod test;
@@ -92,4 +119,6 @@ pub mod tickv;
 pub mod touch;
 pub mod udp_driver;
 pub mod udp_mux;
+pub mod uptime;
 pub mod usb;
+pub mod yield_timer;
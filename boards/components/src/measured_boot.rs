@@ -0,0 +1,105 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the measured boot of processes.
+//!
+//! The digest engine must not be used by anything else while processes are
+//! loaded.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sha = components::sha::ShaSoftware256Component::new()
+//!     .finalize(components::sha_software_256_component_static!());
+//!
+//! let measured_boot = components::measured_boot::MeasuredBootComponent::new(
+//!     board_kernel,
+//!     capsules_extra::measured_boot::DRIVER_NUM,
+//!     sha,
+//! )
+//! .finalize(components::measured_boot_component_static!(
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     NUM_PROCS
+//! ));
+//!
+//! loader.set_binary_client(measured_boot);
+//! ```
+
+use capsules_extra::measured_boot::{MeasuredBoot, Measurement, DIGEST_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::digest::{DigestDataHash, Sha256};
+
+#[macro_export]
+macro_rules! measured_boot_component_static {
+    ($D:ty, $num_procs:expr $(,)?) => {{
+        let measurements =
+            kernel::static_buf!([Option<capsules_extra::measured_boot::Measurement>; $num_procs]);
+        let data_buffer = kernel::static_buf!([u8; 2 * capsules_extra::measured_boot::DIGEST_LEN]);
+        let digest_buffer = kernel::static_buf!([u8; capsules_extra::measured_boot::DIGEST_LEN]);
+        let measured_boot =
+            kernel::static_buf!(capsules_extra::measured_boot::MeasuredBoot<'static, $D>);
+        (measurements, data_buffer, digest_buffer, measured_boot)
+    };};
+}
+
+pub type MeasuredBootComponentType<D> = MeasuredBoot<'static, D>;
+
+pub struct MeasuredBootComponent<
+    D: 'static + DigestDataHash<'static, DIGEST_LEN> + Sha256,
+    const NUM_PROCS: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    digest: &'static D,
+}
+
+impl<D: 'static + DigestDataHash<'static, DIGEST_LEN> + Sha256, const NUM_PROCS: usize>
+    MeasuredBootComponent<D, NUM_PROCS>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        digest: &'static D,
+    ) -> MeasuredBootComponent<D, NUM_PROCS> {
+        MeasuredBootComponent {
+            board_kernel,
+            driver_num,
+            digest,
+        }
+    }
+}
+
+impl<D: 'static + DigestDataHash<'static, DIGEST_LEN> + Sha256, const NUM_PROCS: usize> Component
+    for MeasuredBootComponent<D, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[Option<Measurement>; NUM_PROCS]>,
+        &'static mut MaybeUninit<[u8; 2 * DIGEST_LEN]>,
+        &'static mut MaybeUninit<[u8; DIGEST_LEN]>,
+        &'static mut MaybeUninit<MeasuredBoot<'static, D>>,
+    );
+    type Output = &'static MeasuredBoot<'static, D>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let measurements = static_buffer.0.write([None; NUM_PROCS]);
+        let data_buffer = static_buffer.1.write([0; 2 * DIGEST_LEN]);
+        let digest_buffer = static_buffer.2.write([0; DIGEST_LEN]);
+
+        let measured_boot = static_buffer.3.write(MeasuredBoot::new(
+            self.digest,
+            measurements,
+            data_buffer,
+            digest_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.digest.set_client(measured_boot);
+
+        measured_boot
+    }
+}
@@ -0,0 +1,118 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the motor control driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static CURRENT_CHANNELS: [stm32f429zi::adc::Channel; 2] = [
+//!     stm32f429zi::adc::Channel::Channel3,
+//!     stm32f429zi::adc::Channel::Channel10,
+//! ];
+//! static CURRENT_LOOP: capsules_extra::motor_control::foc::CurrentLoop =
+//!     capsules_extra::motor_control::foc::CurrentLoop::new(
+//!         7,
+//!         0,
+//!         0x8000,
+//!         capsules_extra::motor_control::foc::PiController::new(8192, 256, 16384),
+//!         capsules_extra::motor_control::foc::PiController::new(8192, 256, 16384),
+//!     );
+//!
+//! let motor_control = components::motor_control::MotorControlComponent::new(
+//!     board_kernel,
+//!     capsules_extra::motor_control::driver::DRIVER_NUM,
+//!     phase_pwm,
+//!     &base_peripherals.adc1,
+//!     encoder,
+//!     &CURRENT_CHANNELS,
+//!     stm32f429zi::adc::Trigger::Tim1Cc4,
+//! )
+//! .finalize(components::motor_control_component_static!(
+//!     PhasePwm,
+//!     stm32f429zi::adc::Adc<'static>,
+//!     Encoder
+//! ));
+//! motor_control.set_control_loop(&CURRENT_LOOP);
+//! ```
+
+use capsules_extra::motor_control::driver::MotorControl;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::adc::AdcTriggered;
+use kernel::hil::motor_control::{Encoder, PhasePwm};
+
+#[macro_export]
+macro_rules! motor_control_component_static {
+    ($P:ty, $A:ty, $E:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::motor_control::driver::MotorControl<'static, $P, $A, $E>
+        )
+    };};
+}
+
+pub type MotorControlComponentType<P, A, E> = MotorControl<'static, P, A, E>;
+
+pub struct MotorControlComponent<
+    P: 'static + PhasePwm,
+    A: 'static + AdcTriggered<'static>,
+    E: 'static + Encoder,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    pwm: &'static P,
+    adc: &'static A,
+    encoder: &'static E,
+    channels: &'static [A::Channel],
+    trigger: A::Trigger,
+}
+
+impl<P: 'static + PhasePwm, A: 'static + AdcTriggered<'static>, E: 'static + Encoder>
+    MotorControlComponent<P, A, E>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        pwm: &'static P,
+        adc: &'static A,
+        encoder: &'static E,
+        channels: &'static [A::Channel],
+        trigger: A::Trigger,
+    ) -> MotorControlComponent<P, A, E> {
+        MotorControlComponent {
+            board_kernel,
+            driver_num,
+            pwm,
+            adc,
+            encoder,
+            channels,
+            trigger,
+        }
+    }
+}
+
+impl<P: 'static + PhasePwm, A: 'static + AdcTriggered<'static>, E: 'static + Encoder> Component
+    for MotorControlComponent<P, A, E>
+{
+    type StaticInput = &'static mut MaybeUninit<MotorControl<'static, P, A, E>>;
+    type Output = &'static MotorControl<'static, P, A, E>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let motor_control = static_buffer.write(MotorControl::new(
+            self.pwm,
+            self.adc,
+            self.encoder,
+            self.channels,
+            self.trigger,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.adc.set_triggered_client(motor_control);
+
+        motor_control
+    }
+}
@@ -0,0 +1,49 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the network statistics userspace driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let interfaces = static_init!(
+//!     [&'static dyn kernel::hil::network_statistics::NetworkStatistics; 1],
+//!     [&peripherals.can1]
+//! );
+//! let net_stats = components::net_stats::NetStatsComponent::new(interfaces)
+//!     .finalize(components::net_stats_component_static!());
+//! ```
+
+use capsules_extra::net_stats::NetStats;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::network_statistics::NetworkStatistics;
+
+#[macro_export]
+macro_rules! net_stats_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::net_stats::NetStats<'static>)
+    };};
+}
+
+pub type NetStatsComponentType = capsules_extra::net_stats::NetStats<'static>;
+
+pub struct NetStatsComponent {
+    interfaces: &'static [&'static dyn NetworkStatistics],
+}
+
+impl NetStatsComponent {
+    pub fn new(interfaces: &'static [&'static dyn NetworkStatistics]) -> Self {
+        Self { interfaces }
+    }
+}
+
+impl Component for NetStatsComponent {
+    type StaticInput = &'static mut MaybeUninit<NetStats<'static>>;
+    type Output = &'static NetStats<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(NetStats::new(self.interfaces))
+    }
+}
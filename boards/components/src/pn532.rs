@@ -0,0 +1,230 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for the PN532 NFC controller.
+//!
+//! The PN532 is connected with `Pn532I2CBusComponent` or
+//! `Pn532SpiBusComponent`, and the driver gets its own virtual alarm.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pn532_bus = components::pn532::Pn532I2CBusComponent::new(
+//!     mux_i2c,
+//!     capsules_extra::pn532::DEFAULT_I2C_ADDRESS,
+//! )
+//! .finalize(components::pn532_i2c_bus_component_static!(nrf52840::i2c::TWI));
+//!
+//! // Or, on SPI:
+//! let pn532_bus = components::pn532::Pn532SpiBusComponent::new(mux_spi, chip_select)
+//!     .finalize(components::pn532_spi_bus_component_static!(nrf52840::spi::SPIM));
+//!
+//! let pn532 = components::pn532::Pn532Component::new(
+//!     board_kernel,
+//!     capsules_extra::pn532::DRIVER_NUM,
+//!     pn532_bus,
+//!     mux_alarm,
+//! )
+//! .finalize(components::pn532_component_static!(
+//!     capsules_extra::pn532::I2CBus<
+//!         'static,
+//!         capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, nrf52840::i2c::TWI>,
+//!     >,
+//!     nrf52840::rtc::Rtc
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_core::virtualizers::virtual_spi::{MuxSpiMaster, VirtualSpiMasterDevice};
+use capsules_extra::pn532::{I2CBus, Pn532, Pn532Bus, SpiBus, BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::i2c;
+use kernel::hil::spi::{self, ClockPhase, ClockPolarity, SpiMasterDevice};
+use kernel::hil::time::Alarm;
+
+/// SPI clock rate of the PN532, which supports up to 5 MHz.
+const SPI_RATE: u32 = 1_000_000;
+
+#[macro_export]
+macro_rules! pn532_i2c_bus_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let bus = kernel::static_buf!(
+            capsules_extra::pn532::I2CBus<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (i2c_device, bus)
+    };};
+}
+
+#[macro_export]
+macro_rules! pn532_spi_bus_component_static {
+    ($S:ty $(,)?) => {{
+        let spi_device = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_spi::VirtualSpiMasterDevice<'static, $S>
+        );
+        let bus = kernel::static_buf!(
+            capsules_extra::pn532::SpiBus<
+                'static,
+                capsules_core::virtualizers::virtual_spi::VirtualSpiMasterDevice<'static, $S>,
+            >
+        );
+        let read_command = kernel::static_buf!([u8; capsules_extra::pn532::BUFFER_LEN]);
+
+        (spi_device, bus, read_command)
+    };};
+}
+
+#[macro_export]
+macro_rules! pn532_component_static {
+    ($B:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let pn532 = kernel::static_buf!(
+            capsules_extra::pn532::Pn532<
+                'static,
+                $B,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let buffer = kernel::static_buf!([u8; capsules_extra::pn532::BUFFER_LEN]);
+
+        (alarm, pn532, buffer)
+    };};
+}
+
+pub type Pn532I2CBusComponentType<I> = I2CBus<'static, I2CDevice<'static, I>>;
+pub type Pn532SpiBusComponentType<S> = SpiBus<'static, VirtualSpiMasterDevice<'static, S>>;
+pub type Pn532ComponentType<B, A> = Pn532<'static, B, VirtualMuxAlarm<'static, A>>;
+
+pub struct Pn532I2CBusComponent<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    address: u8,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Pn532I2CBusComponent<I> {
+    pub fn new(i2c_mux: &'static MuxI2C<'static, I>, address: u8) -> Pn532I2CBusComponent<I> {
+        Pn532I2CBusComponent { i2c_mux, address }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for Pn532I2CBusComponent<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<Pn532I2CBusComponentType<I>>,
+    );
+    type Output = &'static Pn532I2CBusComponentType<I>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let i2c_device = s.0.write(I2CDevice::new(self.i2c_mux, self.address));
+        let bus = s.1.write(I2CBus::new(i2c_device));
+        i2c_device.set_client(bus);
+
+        bus
+    }
+}
+
+pub struct Pn532SpiBusComponent<S: 'static + spi::SpiMaster<'static>> {
+    spi_mux: &'static MuxSpiMaster<'static, S>,
+    chip_select: S::ChipSelect,
+}
+
+impl<S: 'static + spi::SpiMaster<'static>> Pn532SpiBusComponent<S> {
+    pub fn new(
+        spi_mux: &'static MuxSpiMaster<'static, S>,
+        chip_select: S::ChipSelect,
+    ) -> Pn532SpiBusComponent<S> {
+        Pn532SpiBusComponent {
+            spi_mux,
+            chip_select,
+        }
+    }
+}
+
+impl<S: 'static + spi::SpiMaster<'static>> Component for Pn532SpiBusComponent<S> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualSpiMasterDevice<'static, S>>,
+        &'static mut MaybeUninit<Pn532SpiBusComponentType<S>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+    );
+    type Output = &'static Pn532SpiBusComponentType<S>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let spi_device =
+            s.0.write(VirtualSpiMasterDevice::new(self.spi_mux, self.chip_select));
+        spi_device.setup();
+        if let Err(error) =
+            spi_device.configure(ClockPolarity::IdleLow, ClockPhase::SampleLeading, SPI_RATE)
+        {
+            panic!("Failed to setup the PN532 SPI device ({:?})", error);
+        }
+
+        let bus =
+            s.1.write(SpiBus::new(spi_device, s.2.write([0; BUFFER_LEN])));
+        spi_device.set_client(bus);
+
+        bus
+    }
+}
+
+pub struct Pn532Component<B: 'static + Pn532Bus<'static>, A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    bus: &'static B,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<B: 'static + Pn532Bus<'static>, A: 'static + Alarm<'static>> Pn532Component<B, A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        bus: &'static B,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Pn532Component<B, A> {
+        Pn532Component {
+            board_kernel,
+            driver_num,
+            bus,
+            alarm_mux,
+        }
+    }
+}
+
+impl<B: 'static + Pn532Bus<'static>, A: 'static + Alarm<'static>> Component
+    for Pn532Component<B, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Pn532ComponentType<B, A>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+    );
+    type Output = &'static Pn532ComponentType<B, A>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let pn532 = s.1.write(Pn532::new(
+            self.bus,
+            alarm,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            s.2.write([0; BUFFER_LEN]),
+        ));
+        self.bus.set_client(pn532);
+        alarm.set_alarm_client(pn532);
+
+        pn532
+    }
+}
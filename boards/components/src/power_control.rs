@@ -0,0 +1,75 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the power control driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let domains = static_init!(
+//!     [capsules_extra::power_control::Domain<'static>; 1],
+//!     [capsules_extra::power_control::Domain::new(adc_domain, adc_dependents)]
+//! );
+//! let power_control = components::power_control::PowerControlComponent::new(
+//!     board_kernel,
+//!     capsules_extra::power_control::DRIVER_NUM,
+//!     domains,
+//!     kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1).unwrap()),
+//! )
+//! .finalize(components::power_control_component_static!());
+//! ```
+
+use capsules_extra::power_control::{Domain, PowerControl};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::process::ShortId;
+
+#[macro_export]
+macro_rules! power_control_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::power_control::PowerControl<'static>)
+    };};
+}
+
+pub type PowerControlComponentType = PowerControl<'static>;
+
+pub struct PowerControlComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    domains: &'static [Domain<'static>],
+    privileged: ShortId,
+}
+
+impl PowerControlComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        domains: &'static [Domain<'static>],
+        privileged: ShortId,
+    ) -> PowerControlComponent {
+        PowerControlComponent {
+            board_kernel,
+            driver_num,
+            domains,
+            privileged,
+        }
+    }
+}
+
+impl Component for PowerControlComponent {
+    type StaticInput = &'static mut MaybeUninit<PowerControl<'static>>;
+    type Output = &'static PowerControl<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(PowerControl::new(
+            self.domains,
+            self.privileged,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
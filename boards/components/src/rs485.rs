@@ -0,0 +1,102 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for RS-485 direction control on a UART.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let rs485 = components::rs485::Rs485Component::new(
+//!     &base_peripherals.usart3,
+//!     mux_alarm,
+//!     driver_enable_pin,
+//!     20,
+//!     0,
+//! )
+//! .finalize(components::rs485_component_static!(
+//!     stm32f429zi::usart::Usart<'static, stm32f429zi::dma::Dma1<'static>>,
+//!     stm32f429zi::tim2::Tim2
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::rs485::Rs485;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::time::{self, Alarm};
+use kernel::hil::uart;
+
+#[macro_export]
+macro_rules! rs485_component_static {
+    ($U:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let rs485 = kernel::static_buf!(
+            capsules_extra::rs485::Rs485<
+                'static,
+                $U,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, rs485)
+    };};
+}
+
+pub type Rs485ComponentType<U, A> = Rs485<'static, U, VirtualMuxAlarm<'static, A>>;
+
+pub struct Rs485Component<U: 'static + uart::Uart<'static>, A: 'static + Alarm<'static>> {
+    uart: &'static U,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    driver_enable: &'static dyn gpio::Pin,
+    pre_delay_us: u32,
+    post_delay_us: u32,
+}
+
+impl<U: 'static + uart::Uart<'static>, A: 'static + Alarm<'static>> Rs485Component<U, A> {
+    pub fn new(
+        uart: &'static U,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        driver_enable: &'static dyn gpio::Pin,
+        pre_delay_us: u32,
+        post_delay_us: u32,
+    ) -> Rs485Component<U, A> {
+        Rs485Component {
+            uart,
+            alarm_mux,
+            driver_enable,
+            pre_delay_us,
+            post_delay_us,
+        }
+    }
+}
+
+impl<U: 'static + uart::Uart<'static>, A: 'static + Alarm<'static>> Component
+    for Rs485Component<U, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Rs485<'static, U, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static Rs485<'static, U, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let rs485 = static_buffer.1.write(Rs485::new(
+            self.uart,
+            alarm,
+            self.driver_enable,
+            self.pre_delay_us,
+            self.post_delay_us,
+        ));
+        uart::Transmit::set_transmit_client(self.uart, rs485);
+        time::Alarm::set_alarm_client(alarm, rs485);
+
+        rs485
+    }
+}
@@ -0,0 +1,115 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for a weight scale with its calibration in the key-value store.
+//!
+//! The component restores the saved calibration.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let hx711 = static_init!(
+//!     capsules_extra::hx711::Hx711<'static>,
+//!     capsules_extra::hx711::Hx711::new(clock_pin, data_pin, 4)
+//! );
+//! data_pin.set_client(hx711);
+//! kernel::deferred_call::DeferredCallClient::register(hx711);
+//!
+//! let kv_scale = components::kv::VirtualKVPermissionsComponent::new(kv_store_permissions_mux)
+//!     .finalize(components::virtual_kv_permissions_component_static!(
+//!         KVStorePermissionsType
+//!     ));
+//! let scale = components::scale::ScaleComponent::new(
+//!     board_kernel,
+//!     capsules_extra::scale::DRIVER_NUM,
+//!     hx711,
+//!     kv_scale,
+//! )
+//! .finalize(components::scale_component_static!(
+//!     capsules_extra::hx711::Hx711<'static>,
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<'static, KVStorePermissionsType>
+//! ));
+//! ```
+
+use capsules_extra::scale::{Scale, KEY, VALUE_BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv::KVPermissions;
+use kernel::hil::sensors::LoadCellDriver;
+use kernel::storage_permissions::StoragePermissions;
+
+#[macro_export]
+macro_rules! scale_component_static {
+    ($L:ty, $K:ty $(,)?) => {{
+        let scale = kernel::static_buf!(capsules_extra::scale::Scale<'static, $L, $K>);
+        let key_buffer = kernel::static_buf!([u8; capsules_extra::scale::KEY.len()]);
+        let value_buffer = kernel::static_buf!([u8; capsules_extra::scale::VALUE_BUFFER_LEN]);
+
+        (scale, key_buffer, value_buffer)
+    };};
+}
+
+pub type ScaleComponentType<L, K> = Scale<'static, L, K>;
+
+pub struct ScaleComponent<L: 'static + LoadCellDriver<'static>, K: 'static + KVPermissions<'static>>
+{
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    load_cell: &'static L,
+    kv: &'static K,
+}
+
+impl<L: 'static + LoadCellDriver<'static>, K: 'static + KVPermissions<'static>>
+    ScaleComponent<L, K>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        load_cell: &'static L,
+        kv: &'static K,
+    ) -> ScaleComponent<L, K> {
+        ScaleComponent {
+            board_kernel,
+            driver_num,
+            load_cell,
+            kv,
+        }
+    }
+}
+
+impl<L: 'static + LoadCellDriver<'static>, K: 'static + KVPermissions<'static>> Component
+    for ScaleComponent<L, K>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Scale<'static, L, K>>,
+        &'static mut MaybeUninit<[u8; KEY.len()]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_LEN]>,
+    );
+    type Output = &'static Scale<'static, L, K>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let key_buffer = static_buffer.1.write([0; KEY.len()]);
+        let value_buffer = static_buffer.2.write([0; VALUE_BUFFER_LEN]);
+
+        let scale = static_buffer.0.write(Scale::new(
+            self.load_cell,
+            self.kv,
+            StoragePermissions::new_kernel_permissions(&storage_cap),
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            key_buffer,
+            value_buffer,
+        ));
+        self.load_cell.set_client(scale);
+        self.kv.set_client(scale);
+
+        let _ = scale.load_calibration();
+
+        scale
+    }
+}
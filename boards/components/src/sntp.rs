@@ -0,0 +1,188 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the SNTP client.
+//!
+//! The `DateTime` device must not have another client.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sntp = components::sntp::SntpComponent::new(
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     capsules_extra::sntp::DEFAULT_PORT,
+//!     mux_alarm,
+//!     &peripherals.rtc,
+//!     SNTP_SERVER,
+//!     3600, // Poll interval in seconds
+//! )
+//! .finalize(components::sntp_component_static!(
+//!     stm32f429zi::rtc::Rtc<'static>,
+//!     stm32f429zi::tim2::Tim2<'static>,
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use capsules_extra::sntp::{Sntp, NTP_PORT, PACKET_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::date_time::DateTime;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! sntp_component_static {
+    ($D:ty, $A:ty $(,)?) => {{
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::sntp::PACKET_LEN]);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let sntp = kernel::static_buf!(
+            capsules_extra::sntp::Sntp<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $D,
+            >
+        );
+
+        (
+            udp_send,
+            udp_vis_cap,
+            net_cap,
+            udp_recv,
+            buffer,
+            alarm,
+            sntp,
+        )
+    };};
+}
+
+pub type SntpComponentType<A, D> = Sntp<'static, VirtualMuxAlarm<'static, A>, D>;
+
+pub struct SntpComponent<A: 'static + Alarm<'static>, D: 'static + DateTime<'static>> {
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    port: u16,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    date_time: &'static D,
+    server: IPAddr,
+    poll_interval_s: u32,
+}
+
+impl<A: 'static + Alarm<'static>, D: 'static + DateTime<'static>> SntpComponent<A, D> {
+    pub fn new(
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        port: u16,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        date_time: &'static D,
+        server: IPAddr,
+        poll_interval_s: u32,
+    ) -> Self {
+        Self {
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            port,
+            alarm_mux,
+            date_time,
+            server,
+            poll_interval_s,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, D: 'static + DateTime<'static>> Component
+    for SntpComponent<A, D>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<[u8; PACKET_LEN]>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Sntp<'static, VirtualMuxAlarm<'static, A>, D>>,
+    );
+    type Output = &'static Sntp<'static, VirtualMuxAlarm<'static, A>, D>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.1.write(UdpVisibilityCapability::new(&create_cap));
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        // The server may be changed later, but is always reached on the NTP
+        // port.
+        let net_cap = s.2.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Port(NTP_PORT),
+            PortRange::Port(self.port),
+            &create_cap,
+        ));
+        let udp_recv = s.3.write(UDPReceiver::new());
+        self.udp_recv_mux.add_client(udp_recv);
+
+        let buffer = s.4.write([0; PACKET_LEN]);
+
+        let alarm = s.5.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let sntp = s.6.write(Sntp::new(
+            alarm,
+            self.date_time,
+            udp_send,
+            udp_recv,
+            self.port_table,
+            net_cap,
+            self.server,
+            self.poll_interval_s,
+            buffer,
+        ));
+        udp_send.set_client(sntp);
+        udp_recv.set_client(sntp);
+        alarm.set_alarm_client(sntp);
+        self.date_time.set_client(sntp);
+
+        if sntp.bind(self.port).is_err() {
+            kernel::debug!("SNTP: could not bind port {}", self.port);
+        }
+        // Let the network come up before the first query.
+        sntp.start(5);
+
+        sntp
+    }
+}
@@ -0,0 +1,66 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the system configuration userspace driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let system_config = components::system_config::SystemConfigComponent::new(
+//!     board_kernel,
+//!     capsules_extra::system_config::DRIVER_NUM,
+//!     capsules_extra::system_config::SystemInfo {
+//!         board: "nucleo_f429zi",
+//!         chip: "stm32f429zi",
+//!         cpu_frequency: 16_000_000,
+//!         drivers: &[capsules_core::console::DRIVER_NUM],
+//!     },
+//! )
+//! .finalize(components::system_config_component_static!());
+//! ```
+
+use capsules_extra::system_config::{SystemConfig, SystemInfo};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! system_config_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::system_config::SystemConfig)
+    };};
+}
+
+pub type SystemConfigComponentType = capsules_extra::system_config::SystemConfig;
+
+pub struct SystemConfigComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    info: SystemInfo,
+}
+
+impl SystemConfigComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize, info: SystemInfo) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            info,
+        }
+    }
+}
+
+impl Component for SystemConfigComponent {
+    type StaticInput = &'static mut MaybeUninit<SystemConfig>;
+    type Output = &'static SystemConfig;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        s.write(SystemConfig::new(
+            self.info,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
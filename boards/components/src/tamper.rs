@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for tamper detection with a log in nonvolatile storage.
+//!
+//! The component connects the tamper sensors to the capsule. The board arms
+//! the sensors by calling `start()` on the capsule.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let case_switch = static_init!(
+//!     capsules_extra::tamper::TamperInput<'static>,
+//!     capsules_extra::tamper::TamperInput::new(
+//!         &nrf52840_peripherals.gpio_port[CASE_SWITCH_PIN],
+//!         kernel::hil::wakeup_source::WakeupEdge::Rising,
+//!     )
+//! );
+//! let tamper = components::tamper::TamperComponent::new(
+//!     board_kernel,
+//!     capsules_extra::tamper::DRIVER_NUM,
+//!     alarm,
+//!     tamper_storage,
+//!     kv_tamper,
+//!     static_init!([&'static TamperInput<'static>; 1], [case_switch]),
+//!     &[b"payment-key"],
+//!     0x60000,
+//!     64,
+//! )
+//! .finalize(components::tamper_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::mx25r6435f::MX25R6435F<'static, ...>,
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<'static, KVStorePermissionsType>
+//! ));
+//! let _ = tamper.start();
+//! ```
+
+use capsules_extra::tamper::{Tamper, TamperInput, BUFFER_LEN, KEY_BUFFER_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv::KVPermissions;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::hil::time::Time;
+use kernel::storage_permissions::StoragePermissions;
+
+#[macro_export]
+macro_rules! tamper_component_static {
+    ($T:ty, $S:ty, $K:ty $(,)?) => {{
+        let tamper = kernel::static_buf!(capsules_extra::tamper::Tamper<'static, $T, $S, $K>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::tamper::BUFFER_LEN]);
+        let key_buffer = kernel::static_buf!([u8; capsules_extra::tamper::KEY_BUFFER_LEN]);
+
+        (tamper, buffer, key_buffer)
+    };};
+}
+
+pub type TamperComponentType<T, S, K> = Tamper<'static, T, S, K>;
+
+pub struct TamperComponent<
+    T: 'static + Time,
+    S: 'static + NonvolatileStorage<'static>,
+    K: 'static + KVPermissions<'static>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    time: &'static T,
+    storage: &'static S,
+    kv: &'static K,
+    inputs: &'static [&'static TamperInput<'static>],
+    keys: &'static [&'static [u8]],
+    log_address: usize,
+    log_capacity: usize,
+}
+
+impl<
+        T: 'static + Time,
+        S: 'static + NonvolatileStorage<'static>,
+        K: 'static + KVPermissions<'static>,
+    > TamperComponent<T, S, K>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        time: &'static T,
+        storage: &'static S,
+        kv: &'static K,
+        inputs: &'static [&'static TamperInput<'static>],
+        keys: &'static [&'static [u8]],
+        log_address: usize,
+        log_capacity: usize,
+    ) -> TamperComponent<T, S, K> {
+        TamperComponent {
+            board_kernel,
+            driver_num,
+            time,
+            storage,
+            kv,
+            inputs,
+            keys,
+            log_address,
+            log_capacity,
+        }
+    }
+}
+
+impl<
+        T: 'static + Time,
+        S: 'static + NonvolatileStorage<'static>,
+        K: 'static + KVPermissions<'static>,
+    > Component for TamperComponent<T, S, K>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Tamper<'static, T, S, K>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; KEY_BUFFER_LEN]>,
+    );
+    type Output = &'static Tamper<'static, T, S, K>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let buffer = static_buffer.1.write([0; BUFFER_LEN]);
+        let key_buffer = static_buffer.2.write([0; KEY_BUFFER_LEN]);
+
+        let tamper = static_buffer.0.write(Tamper::new(
+            self.time,
+            self.storage,
+            self.kv,
+            StoragePermissions::new_kernel_permissions(&storage_cap),
+            self.inputs,
+            self.keys,
+            self.log_address,
+            self.log_capacity,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+            key_buffer,
+        ));
+        self.storage.set_client(tamper);
+        self.kv.set_client(tamper);
+        for input in self.inputs.iter() {
+            input.set_client(tamper);
+        }
+
+        tamper
+    }
+}
@@ -0,0 +1,101 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the temperature compensation service.
+//!
+//! The returned capsule implements `TemperatureDriver`, so the temperature
+//! syscall driver can be stacked on top of it.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let compensation = components::temperature_compensation::TemperatureCompensationComponent::new(
+//!     &base_peripherals.temp,
+//!     mux_alarm,
+//!     10_000,
+//! )
+//! .finalize(components::temperature_compensation_component_static!(
+//!     nrf52840::temperature::Temp<'static>,
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::temperature_compensation::TemperatureCompensator;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::sensors::TemperatureDriver;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! temperature_compensation_component_static {
+    ($T:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let compensator = kernel::static_buf!(
+            capsules_extra::temperature_compensation::TemperatureCompensator<
+                'static,
+                $T,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, compensator)
+    };};
+}
+
+pub type TemperatureCompensationComponentType<T, A> =
+    TemperatureCompensator<'static, T, VirtualMuxAlarm<'static, A>>;
+
+pub struct TemperatureCompensationComponent<
+    T: 'static + TemperatureDriver<'static>,
+    A: 'static + Alarm<'static>,
+> {
+    sensor: &'static T,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    period_ms: u32,
+}
+
+impl<T: 'static + TemperatureDriver<'static>, A: 'static + Alarm<'static>>
+    TemperatureCompensationComponent<T, A>
+{
+    pub fn new(
+        sensor: &'static T,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        period_ms: u32,
+    ) -> TemperatureCompensationComponent<T, A> {
+        TemperatureCompensationComponent {
+            sensor,
+            alarm_mux,
+            period_ms,
+        }
+    }
+}
+
+impl<T: 'static + TemperatureDriver<'static>, A: 'static + Alarm<'static>> Component
+    for TemperatureCompensationComponent<T, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<TemperatureCompensator<'static, T, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static TemperatureCompensator<'static, T, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let compensator = static_buffer.1.write(TemperatureCompensator::new(
+            self.sensor,
+            alarm,
+            self.period_ms,
+        ));
+        self.sensor.set_client(compensator);
+        time::Alarm::set_alarm_client(alarm, compensator);
+
+        let _ = compensator.start();
+        compensator
+    }
+}
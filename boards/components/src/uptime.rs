@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the 64-bit uptime counter.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let uptime = components::uptime::UptimeComponent::new(
+//!     board_kernel,
+//!     capsules_extra::uptime::DRIVER_NUM,
+//!     mux_alarm,
+//! )
+//! .finalize(components::uptime_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::uptime::Uptime;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! uptime_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let uptime = kernel::static_buf!(
+            capsules_extra::uptime::Uptime<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, uptime)
+    };};
+}
+
+pub type UptimeComponentType<A> = Uptime<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct UptimeComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + Alarm<'static>> UptimeComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> UptimeComponent<A> {
+        UptimeComponent {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for UptimeComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Uptime<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static Uptime<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let uptime = static_buffer.1.write(Uptime::new(
+            alarm,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        time::Alarm::set_alarm_client(alarm, uptime);
+        uptime.start();
+
+        uptime
+    }
+}
@@ -0,0 +1,81 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the timer of the `yield-wait-timeout` system call.
+//!
+//! This provides one Component, YieldTimerComponent, which gives the kernel
+//! a virtual alarm to wake the processes whose timeout expired.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! components::yield_timer::YieldTimerComponent::new(board_kernel, mux_alarm).finalize(
+//!     components::yield_timer_component_static!(nrf52840::rtc::Rtc, NUM_PROCS),
+//! );
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::platform::yield_timer::VirtualYieldTimer;
+
+#[macro_export]
+macro_rules! yield_timer_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let yield_timer = kernel::static_buf!(
+            kernel::platform::yield_timer::VirtualYieldTimer<
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $N,
+            >
+        );
+
+        (alarm, yield_timer)
+    };};
+}
+
+pub type YieldTimerComponentType<A, const NUM_PROCS: usize> =
+    VirtualYieldTimer<VirtualMuxAlarm<'static, A>, NUM_PROCS>;
+
+pub struct YieldTimerComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> YieldTimerComponent<A, NUM_PROCS> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> YieldTimerComponent<A, NUM_PROCS> {
+        YieldTimerComponent {
+            board_kernel,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for YieldTimerComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<VirtualYieldTimer<VirtualMuxAlarm<'static, A>, NUM_PROCS>>,
+    );
+    type Output = &'static VirtualYieldTimer<VirtualMuxAlarm<'static, A>, NUM_PROCS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let yield_timer = static_buffer.1.write(VirtualYieldTimer::new(alarm));
+        alarm.set_alarm_client(yield_timer);
+        self.board_kernel.set_yield_timer(yield_timer);
+
+        yield_timer
+    }
+}
@@ -96,6 +96,9 @@ impl TestLauncher {
             3 => unsafe { test::aes_test::run_aes128_ctr(&self.peripherals.ecb, self) },
             4 => unsafe { test::aes_test::run_aes128_cbc(&self.peripherals.ecb, self) },
             5 => unsafe { test::aes_test::run_aes128_ecb(&self.peripherals.ecb, self) },
+            6 => unsafe {
+                test::adc_self_test_test::run_adc_self_test(&self.peripherals.adc, self)
+            },
             _ => kernel::debug!("All tests finished."),
         }
     }
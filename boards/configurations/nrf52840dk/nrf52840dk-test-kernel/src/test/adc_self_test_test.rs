@@ -0,0 +1,47 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the SAADC against the supply voltage, which the nRF52840DK
+//! regulates to 3.0 V.
+
+use capsules_core::adc::SelfTestReference;
+use capsules_core::test::adc_self_test::TestAdcSelfTest;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use kernel::component::Component;
+use kernel::static_init;
+
+/// VDD of the nRF52840DK, and the tolerance of its regulator and of the
+/// SAADC gain.
+const VDD_MV: usize = 3000;
+const TOLERANCE_MV: usize = 150;
+
+pub unsafe fn run_adc_self_test(
+    adc: &'static nrf52840::adc::Adc<'static>,
+    client: &'static dyn CapsuleTestClient,
+) {
+    let t = static_init_test_adc_self_test(adc, client);
+    t.run();
+}
+
+unsafe fn static_init_test_adc_self_test(
+    adc: &'static nrf52840::adc::Adc<'static>,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestAdcSelfTest<'static> {
+    adc.calibrate();
+    let adc_mux = components::adc::AdcMuxComponent::new(adc)
+        .finalize(components::adc_mux_component_static!(nrf52840::adc::Adc));
+    let vdd = components::adc::AdcComponent::new(
+        adc_mux,
+        nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::VDD),
+    )
+    .finalize(components::adc_component_static!(nrf52840::adc::Adc));
+
+    let test = static_init!(
+        TestAdcSelfTest<'static>,
+        TestAdcSelfTest::new(vdd, SelfTestReference::new(0, VDD_MV, TOLERANCE_MV))
+    );
+    test.set_client(client);
+
+    test
+}
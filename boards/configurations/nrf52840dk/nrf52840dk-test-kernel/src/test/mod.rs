@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2023.
 
+pub(crate) mod adc_self_test_test;
 pub(crate) mod aes_test;
 pub(crate) mod hmac_sha256_test;
 pub(crate) mod sha256_test;
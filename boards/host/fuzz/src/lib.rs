@@ -0,0 +1,554 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fuzzing harness for the system call interface of capsules.
+//!
+//! The kernel runs on the `host` chip with a single process, whose system
+//! calls are scripted from the fuzzer input through the chip's
+//! [`host::syscall::SysCall`] boundary. The console, ADC and key-value store
+//! capsules are set up as on the host board, so arbitrary `command`, `allow`
+//! and `subscribe` calls reach them through the real kernel paths: driver
+//! lookup, grant allocation, buffer validation and upcall scheduling.
+//!
+//! The 6LoWPAN layer has no system call interface; its entry point is the
+//! reception of frames from the radio, so arbitrary frames are fed to it
+//! instead.
+//!
+//! The kernel and capsules are set up once per fuzzer process. Each input
+//! restarts the process, which releases its grants, but capsule state that
+//! is not kept in grants carries over between inputs.
+
+use std::fs::OpenOptions;
+use std::ptr::{addr_of, addr_of_mut};
+
+use capsules_extra::net::ieee802154::Header;
+use capsules_extra::net::sixlowpan::sixlowpan_compression::Context;
+use capsules_extra::net::sixlowpan::sixlowpan_state::{RxState, Sixlowpan, SixlowpanState};
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::radio;
+use kernel::platform::{KernelResources, SyscallDriverLookup};
+use kernel::process::{FunctionCall, FunctionCallSource, Process, Task};
+use kernel::scheduler::cooperative::CooperativeSched;
+use kernel::syscall::Syscall;
+use kernel::{create_capability, static_init};
+
+use host::chip::{Host, HostDefaultPeripherals};
+
+/// Number of pages of the flash file backing the key-value store.
+const FLASH_PAGES: usize = 8;
+
+/// Size of the process binary in flash.
+const APP_FLASH_SIZE: usize = 512;
+
+/// Memory reserved for the process and its grants.
+const APP_MEMORY_SIZE: usize = 64 * 1024;
+
+/// Minimum RAM requested in the process header.
+const APP_MIN_RAM_SIZE: u32 = 16 * 1024;
+
+/// Memory made accessible to the process, the rest is left for grants.
+const APP_BRK_SIZE: usize = 8 * 1024;
+
+/// Bound on the kernel loop iterations run for a single step of an input.
+const MAX_LOOP_ITERATIONS: usize = 64;
+
+/// Size of the buffer IPv6 packets are reassembled into.
+const SIXLOWPAN_RX_SIZE: usize = 1280;
+
+const NUM_PROCS: usize = 1;
+
+static mut PROCESSES: [Option<&'static dyn Process>; NUM_PROCS] = [None; NUM_PROCS];
+
+type HostAlarm = host::time::HostAlarm<'static>;
+type AdcDriver = capsules_core::adc::AdcDedicated<'static, host::adc::HostAdc<'static>>;
+
+// TicKV
+type FileFlash = host::flash::FileFlash<'static>;
+const TICKV_PAGE_SIZE: usize =
+    core::mem::size_of::<<FileFlash as kernel::hil::flash::Flash>::Page>();
+type Siphasher24 = components::siphash::Siphasher24ComponentType;
+type TicKVDedicatedFlash =
+    components::tickv::TicKVDedicatedFlashComponentType<FileFlash, Siphasher24, TICKV_PAGE_SIZE>;
+type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
+    TicKVDedicatedFlash,
+    capsules_extra::tickv::TicKVKeyType,
+>;
+type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
+type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+
+struct FuzzPlatform {
+    console: &'static capsules_core::console::Console<'static>,
+    adc: &'static AdcDriver,
+    kv_driver: &'static KVDriver,
+    scheduler: &'static CooperativeSched<'static>,
+}
+
+impl SyscallDriverLookup for FuzzPlatform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+    {
+        match driver_num {
+            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
+            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
+            capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            _ => f(None),
+        }
+    }
+}
+
+impl KernelResources<Host<'static>> for FuzzPlatform {
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = ();
+    type ProcessFault = ();
+    type Scheduler = CooperativeSched<'static>;
+    type SchedulerTimer = ();
+    type WatchDog = ();
+    type ContextSwitchCallback = ();
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        self
+    }
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        &()
+    }
+    fn process_fault(&self) -> &Self::ProcessFault {
+        &()
+    }
+    fn scheduler(&self) -> &Self::Scheduler {
+        self.scheduler
+    }
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        &()
+    }
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        &()
+    }
+}
+
+/// Kernel, capsules and process shared by all inputs.
+struct Harness {
+    kernel: &'static kernel::Kernel,
+    chip: &'static Host<'static>,
+    platform: FuzzPlatform,
+    process: &'static dyn Process,
+    sixlowpan: &'static Sixlowpan<'static, HostAlarm, Context>,
+}
+
+thread_local! {
+    static HARNESS: &'static Harness = unsafe { setup() };
+}
+
+/// Build a process binary with a TBF header and no code. Its code never runs:
+/// the host chip replays scripted system calls instead.
+fn process_binary() -> Vec<u8> {
+    const HEADER_SIZE: u16 = 40;
+
+    let mut binary = vec![0; APP_FLASH_SIZE];
+    // Base header: version, header size, total size, flags (enabled).
+    binary[0..2].copy_from_slice(&2u16.to_le_bytes());
+    binary[2..4].copy_from_slice(&HEADER_SIZE.to_le_bytes());
+    binary[4..8].copy_from_slice(&(APP_FLASH_SIZE as u32).to_le_bytes());
+    binary[8..12].copy_from_slice(&1u32.to_le_bytes());
+    // Main TLV: init function offset, protected trailer size, minimum RAM.
+    binary[16..18].copy_from_slice(&1u16.to_le_bytes());
+    binary[18..20].copy_from_slice(&12u16.to_le_bytes());
+    binary[20..24].copy_from_slice(&0u32.to_le_bytes());
+    binary[24..28].copy_from_slice(&0u32.to_le_bytes());
+    binary[28..32].copy_from_slice(&APP_MIN_RAM_SIZE.to_le_bytes());
+    // Kernel version TLV, required by the loader.
+    binary[32..34].copy_from_slice(&8u16.to_le_bytes());
+    binary[34..36].copy_from_slice(&4u16.to_le_bytes());
+    binary[36..38].copy_from_slice(&kernel::KERNEL_MAJOR_VERSION.to_le_bytes());
+    binary[38..40].copy_from_slice(&kernel::KERNEL_MINOR_VERSION.to_le_bytes());
+
+    let checksum = binary[..HEADER_SIZE as usize]
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .fold(0, |checksum, word| checksum ^ word);
+    binary[12..16].copy_from_slice(&checksum.to_le_bytes());
+    binary
+}
+
+unsafe fn setup() -> &'static Harness {
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&*addr_of!(PROCESSES)));
+
+    //--------------------------------------------------------------------------
+    // PERIPHERALS
+    //--------------------------------------------------------------------------
+
+    let flash_path = std::env::temp_dir().join(format!("tock-fuzz-{}.bin", std::process::id()));
+    let flash_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&flash_path)
+        .expect("cannot create flash file");
+
+    let peripherals = static_init!(
+        HostDefaultPeripherals,
+        HostDefaultPeripherals::new(flash_file, FLASH_PAGES).expect("cannot initialize flash")
+    );
+    peripherals.init();
+    // Keep the fuzzer output readable.
+    peripherals.uart.set_quiet(true);
+
+    //--------------------------------------------------------------------------
+    // CAPSULES
+    //--------------------------------------------------------------------------
+
+    let uart_mux = components::console::UartMuxComponent::new(&peripherals.uart, 115200)
+        .finalize(components::uart_mux_component_static!());
+
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules_core::console::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::console_component_static!());
+
+    components::debug_writer::DebugWriterComponent::new(uart_mux)
+        .finalize(components::debug_writer_component_static!());
+
+    let adc_channels = static_init!(
+        [host::adc::Channel; host::adc::NUM_CHANNELS],
+        [
+            host::adc::Channel::new(0),
+            host::adc::Channel::new(1),
+            host::adc::Channel::new(2),
+            host::adc::Channel::new(3),
+        ]
+    );
+    let adc = components::adc::AdcDedicatedComponent::new(
+        &peripherals.adc,
+        adc_channels,
+        board_kernel,
+        capsules_core::adc::DRIVER_NUM,
+    )
+    .finalize(components::adc_dedicated_component_static!(
+        host::adc::HostAdc
+    ));
+
+    let page_buffer = static_init!(
+        <FileFlash as kernel::hil::flash::Flash>::Page,
+        <FileFlash as kernel::hil::flash::Flash>::Page::default()
+    );
+    let sip_hash = components::siphash::Siphasher24Component::new()
+        .finalize(components::siphasher24_component_static!());
+    let tickv = components::tickv::TicKVDedicatedFlashComponent::new(
+        sip_hash,
+        &peripherals.flash,
+        0,
+        TICKV_PAGE_SIZE * FLASH_PAGES,
+        page_buffer,
+    )
+    .finalize(components::tickv_dedicated_flash_component_static!(
+        FileFlash,
+        Siphasher24,
+        TICKV_PAGE_SIZE,
+    ));
+    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
+        components::tickv_kv_store_component_static!(
+            TicKVDedicatedFlash,
+            capsules_extra::tickv::TicKVKeyType,
+        ),
+    );
+    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
+        .finalize(components::kv_store_permissions_component_static!(
+            TicKVKVStore
+        ));
+    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
+        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    );
+    let virtual_kv_driver = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+    let kv_driver = components::kv::KVDriverComponent::new(
+        virtual_kv_driver,
+        board_kernel,
+        capsules_extra::kv_driver::DRIVER_NUM,
+    )
+    .finalize(components::kv_driver_component_static!(
+        VirtualKVPermissions
+    ));
+
+    // 6LoWPAN reassembly, with the link-local prefix as context 0.
+    let mut prefix = [0; 16];
+    prefix[0] = 0xfe;
+    prefix[1] = 0x80;
+    let sixlowpan = static_init!(
+        Sixlowpan<'static, HostAlarm, Context>,
+        Sixlowpan::new(
+            Context {
+                prefix,
+                prefix_len: 64,
+                id: 0,
+                compress: false,
+            },
+            &peripherals.alarm,
+        )
+    );
+    let sixlowpan_rx_buffer = static_init!([u8; SIXLOWPAN_RX_SIZE], [0; SIXLOWPAN_RX_SIZE]);
+    let sixlowpan_rx_state = static_init!(RxState<'static>, RxState::new(sixlowpan_rx_buffer));
+    sixlowpan.add_rx_state(sixlowpan_rx_state);
+
+    //--------------------------------------------------------------------------
+    // PROCESS
+    //--------------------------------------------------------------------------
+
+    let scheduler =
+        components::sched::cooperative::CooperativeComponent::new(&*addr_of!(PROCESSES))
+            .finalize(components::cooperative_component_static!(NUM_PROCS));
+
+    let chip = static_init!(Host, Host::new(peripherals));
+
+    let app_flash: &'static [u8] = Box::leak(process_binary().into_boxed_slice());
+    // Allocate words so that the process memory is suitably aligned.
+    let app_memory_words: &'static mut [u64] =
+        Box::leak(vec![0u64; APP_MEMORY_SIZE / 8].into_boxed_slice());
+    let app_memory = core::slice::from_raw_parts_mut(
+        app_memory_words.as_mut_ptr().cast::<u8>(),
+        APP_MEMORY_SIZE,
+    );
+
+    let fault_policy = static_init!(
+        capsules_system::process_policies::PanicFaultPolicy,
+        capsules_system::process_policies::PanicFaultPolicy {}
+    );
+
+    kernel::process::load_processes(
+        board_kernel,
+        chip,
+        app_flash,
+        app_memory,
+        &mut *addr_of_mut!(PROCESSES),
+        fault_policy,
+        &process_management_capability,
+    )
+    .expect("cannot load the scripted process");
+    let process = (*addr_of!(PROCESSES))[0].expect("scripted process not loaded");
+
+    let harness = static_init!(
+        Harness,
+        Harness {
+            kernel: board_kernel,
+            chip,
+            platform: FuzzPlatform {
+                console,
+                adc,
+                kv_driver,
+                scheduler,
+            },
+            process,
+            sixlowpan,
+        }
+    );
+
+    // Let the process start and the key-value store initialize the flash.
+    harness.run(MAX_LOOP_ITERATIONS);
+    harness
+}
+
+/// Reader for the fuzzer input. Reads past the end return zeros.
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    fn word(&mut self) -> u32 {
+        u32::from_le_bytes([self.byte(), self.byte(), self.byte(), self.byte()])
+    }
+}
+
+/// Decode a subdriver number, mostly among those drivers implement.
+fn subdriver_number(input: &mut Input) -> usize {
+    let byte = input.byte();
+    if byte & 0x80 != 0 {
+        byte as usize
+    } else {
+        byte as usize % 16
+    }
+}
+
+impl Harness {
+    fn run(&self, iterations: usize) {
+        let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+        for _ in 0..iterations {
+            self.kernel.kernel_loop_operation(
+                &self.platform,
+                self.chip,
+                None::<&kernel::ipc::IPC<0>>,
+                true,
+                &main_loop_capability,
+            );
+        }
+    }
+
+    /// Let the process issue its queued system calls, waking it up if it is
+    /// waiting for an upcall.
+    fn flush(&self) {
+        let syscall = self.chip.syscall();
+        let mut iterations = 0;
+        while syscall.pending_syscalls() > 0 && iterations < MAX_LOOP_ITERATIONS {
+            if !self.process.ready() {
+                let _ = self.process.enqueue_task(Task::FunctionCall(FunctionCall {
+                    source: FunctionCallSource::Kernel,
+                    argument0: 0,
+                    argument1: 0,
+                    argument2: 0,
+                    argument3: 0,
+                    pc: 0,
+                }));
+            }
+            self.run(1);
+            iterations += 1;
+        }
+        syscall.clear_syscalls();
+    }
+
+    /// Decode an allow buffer: mostly within process memory, sometimes
+    /// arbitrary to exercise the kernel's validation.
+    fn buffer(&self, input: &mut Input) -> (*mut u8, usize) {
+        let flags = input.byte();
+        let address = input.word() as usize;
+        let size = input.word() as usize;
+        if flags & 0x80 != 0 {
+            return (address as *mut u8, size);
+        }
+        if flags & 0x40 != 0 {
+            return (core::ptr::null_mut(), 0);
+        }
+        match self.chip.syscall().process_memory() {
+            Some((start, brk)) => {
+                let len = brk as usize - start as usize;
+                let offset = address % len.max(1);
+                let size = size % (len - offset + 1);
+                (start.wrapping_add(offset).cast_mut(), size)
+            }
+            None => (address as *mut u8, size),
+        }
+    }
+
+    fn fuzz_driver(&self, driver_number: usize, data: &[u8]) {
+        let syscall = self.chip.syscall();
+
+        // Start from a fresh process that has grown its memory, as a process
+        // would at startup.
+        self.process.try_restart(None);
+        let sram_start = self.process.get_addresses().sram_start;
+        syscall.push_syscall(Syscall::Memop {
+            operand: 0,
+            arg0: sram_start + APP_BRK_SIZE,
+        });
+        self.flush();
+
+        let mut input = Input { data };
+        while !input.is_empty() {
+            match input.byte() % 5 {
+                0 => syscall.push_syscall(Syscall::Command {
+                    driver_number,
+                    subdriver_number: subdriver_number(&mut input),
+                    arg0: input.word() as usize,
+                    arg1: input.word() as usize,
+                }),
+                1 => {
+                    let subdriver_number = subdriver_number(&mut input);
+                    let (allow_address, allow_size) = self.buffer(&mut input);
+                    syscall.push_syscall(Syscall::ReadWriteAllow {
+                        driver_number,
+                        subdriver_number,
+                        allow_address,
+                        allow_size,
+                    })
+                }
+                2 => {
+                    let subdriver_number = subdriver_number(&mut input);
+                    let (allow_address, allow_size) = self.buffer(&mut input);
+                    syscall.push_syscall(Syscall::ReadOnlyAllow {
+                        driver_number,
+                        subdriver_number,
+                        allow_address,
+                        allow_size,
+                    })
+                }
+                3 => {
+                    let subdriver_number = subdriver_number(&mut input);
+                    // Upcalls never run, so any pointer into the process
+                    // binary will do.
+                    let upcall_ptr = match input.byte() % 4 {
+                        0 => core::ptr::null_mut(),
+                        1 => input.word() as usize as *mut (),
+                        _ => self.process.get_addresses().flash_non_protected_start as *mut (),
+                    };
+                    syscall.push_syscall(Syscall::Subscribe {
+                        driver_number,
+                        subdriver_number,
+                        upcall_ptr,
+                        appdata: input.word() as usize,
+                    })
+                }
+                _ => {
+                    // Let asynchronous operations progress before issuing
+                    // the next system calls.
+                    self.flush();
+                    self.run(input.byte() as usize % MAX_LOOP_ITERATIONS);
+                }
+            }
+        }
+        self.flush();
+        self.run(MAX_LOOP_ITERATIONS);
+    }
+
+    fn fuzz_sixlowpan(&self, frame: &[u8]) {
+        use capsules_extra::ieee802154::device::RxClient;
+
+        // Parse the frame as the 802.15.4 framer does before passing it up.
+        let frame = &frame[..frame.len().min(radio::MAX_FRAME_SIZE)];
+        let parsed: Option<(usize, (Header, usize))> = Header::decode(frame, true).done();
+        if let Some((data_offset, (header, _))) = parsed {
+            // Secured frames are dropped by the framer without a key.
+            if header.security.is_none() {
+                self.sixlowpan
+                    .receive(frame, header, 0, data_offset, frame.len() - data_offset);
+            }
+        }
+    }
+}
+
+/// Issue the system calls encoded in `data` to the driver `driver_number`.
+///
+/// The input is a sequence of operations, each starting with a byte that
+/// selects a `command`, read-write `allow`, read-only `allow`, `subscribe`,
+/// or running the kernel so asynchronous operations can complete.
+pub fn fuzz_driver(driver_number: usize, data: &[u8]) {
+    HARNESS.with(|harness| harness.fuzz_driver(driver_number, data));
+}
+
+/// Pass `data` to the 6LoWPAN layer as a received 802.15.4 frame.
+pub fn fuzz_sixlowpan(data: &[u8]) {
+    HARNESS.with(|harness| harness.fuzz_sixlowpan(data));
+}
@@ -0,0 +1,296 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Board file for running the Tock kernel as a process on the host.
+//!
+//! The kernel is wired to the emulated peripherals of the `host` chip: the
+//! console uses standard input and output, and a key-value store is kept in a
+//! flash file. The path of the flash file can be passed as the first argument,
+//! and defaults to `host-flash.bin`.
+//!
+//! No processes are loaded, so this board is meant for running capsules in
+//! integration tests without hardware or QEMU. The fuzz targets in `fuzz/`
+//! set up the same capsules with a scripted process instead.
+
+use std::fs::OpenOptions;
+use std::ptr::addr_of;
+
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::platform::{KernelResources, SyscallDriverLookup};
+use kernel::scheduler::cooperative::CooperativeSched;
+use kernel::{create_capability, debug, static_init};
+
+use host::chip::{Host, HostDefaultPeripherals};
+
+/// Number of pages of the flash file.
+const FLASH_PAGES: usize = 32;
+
+/// Default path of the flash file.
+const FLASH_FILE: &str = "host-flash.bin";
+
+pub const NUM_PROCS: usize = 4;
+
+// Actual memory for holding the active process structures. No processes are
+// loaded on the host, but the kernel needs the list.
+static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
+    [None; NUM_PROCS];
+
+type AlarmDriver = components::alarm::AlarmDriverComponentType<host::time::HostAlarm<'static>>;
+type AdcDriver = capsules_core::adc::AdcDedicated<'static, host::adc::HostAdc<'static>>;
+
+// TicKV
+type FileFlash = host::flash::FileFlash<'static>;
+const TICKV_PAGE_SIZE: usize =
+    core::mem::size_of::<<FileFlash as kernel::hil::flash::Flash>::Page>();
+type Siphasher24 = components::siphash::Siphasher24ComponentType;
+type TicKVDedicatedFlash =
+    components::tickv::TicKVDedicatedFlashComponentType<FileFlash, Siphasher24, TICKV_PAGE_SIZE>;
+type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
+    TicKVDedicatedFlash,
+    capsules_extra::tickv::TicKVKeyType,
+>;
+type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
+type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+
+/// A structure representing this platform that holds references to all
+/// capsules for this platform.
+struct HostPlatform {
+    console: &'static capsules_core::console::Console<'static>,
+    alarm: &'static AlarmDriver,
+    adc: &'static AdcDriver,
+    kv_driver: &'static KVDriver,
+    scheduler: &'static CooperativeSched<'static>,
+}
+
+/// Mapping of integer syscalls to objects that implement syscalls.
+impl SyscallDriverLookup for HostPlatform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+    {
+        match driver_num {
+            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
+            capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
+            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
+            capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            _ => f(None),
+        }
+    }
+}
+
+impl KernelResources<Host<'static>> for HostPlatform {
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = ();
+    type ProcessFault = ();
+    type Scheduler = CooperativeSched<'static>;
+    type SchedulerTimer = ();
+    type WatchDog = ();
+    type ContextSwitchCallback = ();
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        self
+    }
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        &()
+    }
+    fn process_fault(&self) -> &Self::ProcessFault {
+        &()
+    }
+    fn scheduler(&self) -> &Self::Scheduler {
+        self.scheduler
+    }
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        &()
+    }
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        &()
+    }
+}
+
+/// This is in a separate, inline(never) function so that its stack frame is
+/// removed when this function returns. Otherwise, the stack space used for
+/// these static_inits is wasted.
+#[inline(never)]
+unsafe fn start() -> (
+    &'static kernel::Kernel,
+    HostPlatform,
+    &'static Host<'static>,
+) {
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&*addr_of!(PROCESSES)));
+
+    //--------------------------------------------------------------------------
+    // PERIPHERALS
+    //--------------------------------------------------------------------------
+
+    let flash_path = std::env::args().nth(1).unwrap_or(FLASH_FILE.into());
+    let flash_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&flash_path)
+        .unwrap_or_else(|err| panic!("cannot open flash file {}: {}", flash_path, err));
+
+    let peripherals = static_init!(
+        HostDefaultPeripherals,
+        HostDefaultPeripherals::new(flash_file, FLASH_PAGES)
+            .unwrap_or_else(|err| panic!("cannot initialize flash file {}: {}", flash_path, err))
+    );
+    peripherals.init();
+    peripherals.uart.start_input();
+
+    //--------------------------------------------------------------------------
+    // ALARM
+    //--------------------------------------------------------------------------
+
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(&peripherals.alarm).finalize(
+        components::alarm_mux_component_static!(host::time::HostAlarm),
+    );
+    let alarm = components::alarm::AlarmDriverComponent::new(
+        board_kernel,
+        capsules_core::alarm::DRIVER_NUM,
+        mux_alarm,
+    )
+    .finalize(components::alarm_component_static!(host::time::HostAlarm));
+
+    //--------------------------------------------------------------------------
+    // UART & CONSOLE & DEBUG
+    //--------------------------------------------------------------------------
+
+    let uart_mux = components::console::UartMuxComponent::new(&peripherals.uart, 115200)
+        .finalize(components::uart_mux_component_static!());
+
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules_core::console::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::console_component_static!());
+
+    components::debug_writer::DebugWriterComponent::new(uart_mux)
+        .finalize(components::debug_writer_component_static!());
+
+    //--------------------------------------------------------------------------
+    // ADC
+    //--------------------------------------------------------------------------
+
+    let adc_channels = static_init!(
+        [host::adc::Channel; host::adc::NUM_CHANNELS],
+        [
+            host::adc::Channel::new(0),
+            host::adc::Channel::new(1),
+            host::adc::Channel::new(2),
+            host::adc::Channel::new(3),
+        ]
+    );
+    let adc = components::adc::AdcDedicatedComponent::new(
+        &peripherals.adc,
+        adc_channels,
+        board_kernel,
+        capsules_core::adc::DRIVER_NUM,
+    )
+    .finalize(components::adc_dedicated_component_static!(
+        host::adc::HostAdc
+    ));
+
+    //--------------------------------------------------------------------------
+    // TICKV
+    //--------------------------------------------------------------------------
+
+    // Static buffer to use when reading/writing flash for TicKV.
+    let page_buffer = static_init!(
+        <FileFlash as kernel::hil::flash::Flash>::Page,
+        <FileFlash as kernel::hil::flash::Flash>::Page::default()
+    );
+
+    // SipHash for creating TicKV hashed keys.
+    let sip_hash = components::siphash::Siphasher24Component::new()
+        .finalize(components::siphasher24_component_static!());
+
+    // TicKV with Tock wrapper/interface, over the whole flash file.
+    let tickv = components::tickv::TicKVDedicatedFlashComponent::new(
+        sip_hash,
+        &peripherals.flash,
+        0,
+        TICKV_PAGE_SIZE * FLASH_PAGES,
+        page_buffer,
+    )
+    .finalize(components::tickv_dedicated_flash_component_static!(
+        FileFlash,
+        Siphasher24,
+        TICKV_PAGE_SIZE,
+    ));
+
+    // KVSystem interface to KV (built on TicKV).
+    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
+        components::tickv_kv_store_component_static!(
+            TicKVDedicatedFlash,
+            capsules_extra::tickv::TicKVKeyType,
+        ),
+    );
+
+    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
+        .finalize(components::kv_store_permissions_component_static!(
+            TicKVKVStore
+        ));
+
+    // Share the KV stack with a mux.
+    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
+        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    );
+
+    // Create a virtual component for the userspace driver.
+    let virtual_kv_driver = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+
+    // Userspace driver for KV.
+    let kv_driver = components::kv::KVDriverComponent::new(
+        virtual_kv_driver,
+        board_kernel,
+        capsules_extra::kv_driver::DRIVER_NUM,
+    )
+    .finalize(components::kv_driver_component_static!(
+        VirtualKVPermissions
+    ));
+
+    //--------------------------------------------------------------------------
+    // FINAL SETUP AND BOARD BOOT
+    //--------------------------------------------------------------------------
+
+    let scheduler =
+        components::sched::cooperative::CooperativeComponent::new(&*addr_of!(PROCESSES))
+            .finalize(components::cooperative_component_static!(NUM_PROCS));
+
+    let platform = HostPlatform {
+        console,
+        alarm,
+        adc,
+        kv_driver,
+        scheduler,
+    };
+
+    let chip = static_init!(Host, Host::new(peripherals));
+
+    debug!("Initialization complete. Entering main loop.");
+
+    (board_kernel, platform, chip)
+}
+
+fn main() {
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+
+    let (board_kernel, platform, chip) = unsafe { start() };
+    board_kernel.kernel_loop(
+        &platform,
+        chip,
+        None::<&kernel::ipc::IPC<0>>,
+        &main_loop_capability,
+    );
+}
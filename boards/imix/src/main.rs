@@ -170,6 +170,10 @@ struct Imix {
     nrf51822: &'static capsules_extra::nrf51822_serialization::Nrf51822Serialization<'static>,
     nonvolatile_storage:
         &'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    measured_boot: &'static capsules_extra::measured_boot::MeasuredBoot<
+        'static,
+        capsules_extra::sha256::Sha256Software<'static>,
+    >,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -200,6 +204,7 @@ impl SyscallDriverLookup for Imix {
                 f(Some(self.nonvolatile_storage))
             }
             capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules_extra::measured_boot::DRIVER_NUM => f(Some(self.measured_boot)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -760,6 +765,21 @@ pub unsafe fn main() {
 
     checker.set_client(loader);
 
+    // Measure the binary of each loaded process, with a separate SHA engine
+    // since the credential checker uses the first one.
+    let measured_boot_sha = components::sha::ShaSoftware256Component::new()
+        .finalize(components::sha_software_256_component_static!());
+    let measured_boot = components::measured_boot::MeasuredBootComponent::new(
+        board_kernel,
+        capsules_extra::measured_boot::DRIVER_NUM,
+        measured_boot_sha,
+    )
+    .finalize(components::measured_boot_component_static!(
+        capsules_extra::sha256::Sha256Software<'static>,
+        NUM_PROCS
+    ));
+    loader.set_binary_client(measured_boot);
+
     let imix = Imix {
         pconsole,
         console,
@@ -781,6 +801,7 @@ pub unsafe fn main() {
         usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage,
+        measured_boot,
         scheduler,
         systick: cortexm4::systick::SysTick::new(),
     };
@@ -7,6 +7,7 @@ use core::panic::PanicInfo;
 
 use kernel::debug;
 use kernel::debug::IoWrite;
+use kernel::hil::alert;
 use kernel::hil::led;
 use kernel::hil::uart;
 use nrf52833::gpio::Pin;
@@ -28,6 +29,10 @@ impl Writer {
     }
 }
 
+/// Calls to `nop` taking about a millisecond at 64 MHz, to time the panic
+/// alert pattern.
+const PANIC_NOPS_PER_MS: usize = 8000;
+
 /// Global static for debug writer
 pub static mut WRITER: Writer = Writer { initialized: false };
 
@@ -79,15 +84,28 @@ pub unsafe fn panic_fmt(pi: &PanicInfo) -> ! {
 
     use core::ptr::{addr_of, addr_of_mut};
     let led_kernel_pin = &nrf52833::gpio::GPIOPin::new(Pin::P0_20);
-    let led = &mut led::LedLow::new(led_kernel_pin);
+    let led = &led::LedLow::new(led_kernel_pin);
+
+    // The speaker beeps the panic pattern along with the LED
+    let pwm = &nrf52833::pwm::Pwm::new();
+    let speaker_pin = &nrf52833::pinmux::Pinmux::new(crate::SPEAKER_PIN as u32);
+
     let writer = &mut *addr_of_mut!(WRITER);
-    debug::panic(
-        &mut [led],
+    debug::panic_print(
         writer,
         pi,
         &cortexm4::support::nop,
         &*addr_of!(PROCESSES),
         &*addr_of!(CHIP),
         &*addr_of!(PROCESS_PRINTER),
+    );
+    debug::panic_alert_forever(
+        &[
+            &alert::AlertLed::new(led),
+            &alert::AlertPwm::new(pwm, speaker_pin),
+        ],
+        &crate::ALERT_PATTERNS.get(alert::AlertClass::Panic),
+        &cortexm4::support::nop,
+        PANIC_NOPS_PER_MS,
     )
 }
@@ -56,6 +56,14 @@ const LED_MATRIX_ROWS: [Pin; 5] = [Pin::P0_21, Pin::P0_22, Pin::P0_15, Pin::P0_2
 
 const SPEAKER_PIN: Pin = Pin::P0_00;
 
+/// Blink and beep patterns signaling failures. Panics blink and beep
+/// continuously at a high pitch, so that they are noticed without the
+/// display.
+const ALERT_PATTERNS: kernel::hil::alert::AlertPatterns = kernel::hil::alert::AlertPatterns {
+    panic: kernel::hil::alert::Pattern::new(&[500, 100, 500, 100], 3000),
+    ..kernel::hil::alert::DEFAULT_PATTERNS
+};
+
 /// I2C pins for all of the sensors.
 const I2C_SDA_PIN: Pin = Pin::P0_16;
 const I2C_SCL_PIN: Pin = Pin::P0_08;
@@ -498,7 +506,7 @@ unsafe fn start() -> (
     //--------------------------------------------------------------------------
     base_peripherals.adc.calibrate();
 
-    let adc_mux = components::adc::AdcMuxComponent::new(&base_peripherals.adc)
+    let adc_mux = components::adc::AdcHighSpeedMuxComponent::new(&base_peripherals.adc)
         .finalize(components::adc_mux_component_static!(nrf52833::adc::Adc));
 
     // Comment out the following to use P0, P1 and P2 as GPIO
@@ -431,7 +431,7 @@ pub unsafe fn start() -> (
     //--------------------------------------------------------------------------
     base_peripherals.adc.calibrate();
 
-    let adc_mux = components::adc::AdcMuxComponent::new(&base_peripherals.adc)
+    let adc_mux = components::adc::AdcHighSpeedMuxComponent::new(&base_peripherals.adc)
         .finalize(components::adc_mux_component_static!(nrf52840::adc::Adc));
 
     let adc_syscall =
@@ -98,6 +98,7 @@ pub struct Platform {
         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
     >,
     ieee802154_radio: &'static Ieee802154Driver,
+    boot_config: &'static components::boot_config::BootConfigComponentType,
     button: &'static capsules_core::button::Button<'static, nrf52840::gpio::GPIOPin<'static>>,
     pconsole: &'static capsules_core::process_console::ProcessConsole<
         'static,
@@ -144,6 +145,7 @@ impl SyscallDriverLookup for Platform {
             capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
             capsules_extra::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
             capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_radio)),
+            capsules_extra::boot_config::DRIVER_NUM => f(Some(self.boot_config)),
             capsules_extra::temperature::DRIVER_NUM => f(Some(self.temp)),
             capsules_extra::analog_comparator::DRIVER_NUM => f(Some(self.analog_comparator)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
@@ -294,6 +296,24 @@ pub unsafe fn start() -> (
         create_capability!(capabilities::ProcessManagementCapability);
     let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
 
+    // Settings kept in flash and applied below, before the capsules that use
+    // them are configured. They can be changed with the `env` command of the
+    // process console.
+    kernel::storage_volume!(BOOT_CONFIG, 4);
+    let boot_env = capsules_extra::boot_config::BootEnv::new(&BOOT_CONFIG);
+
+    // Log levels, e.g. `log.radio=trace`.
+    for (key, value) in boot_env.entries() {
+        let subsystem = key
+            .strip_prefix("log.")
+            .and_then(kernel::debug::LogSubsystem::from_name);
+        if let (Some(subsystem), Some(level)) =
+            (subsystem, kernel::debug::LogLevel::from_name(value))
+        {
+            kernel::debug::set_log_level(subsystem, level);
+        }
+    }
+
     let gpio_port = &nrf52840_peripherals.gpio_port;
 
     // Configure kernel debug gpios as early as possible
@@ -328,7 +348,8 @@ pub unsafe fn start() -> (
     PROCESS_PRINTER = Some(process_printer);
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(channel, 115200)
+    let baud_rate = boot_env.get_u32("console.baud").unwrap_or(115200);
+    let uart_mux = components::console::UartMuxComponent::new(channel, baud_rate)
         .finalize(components::uart_mux_component_static!());
 
     let pconsole = components::process_console::ProcessConsoleComponent::new(
@@ -342,6 +363,21 @@ pub unsafe fn start() -> (
         nrf52840::rtc::Rtc<'static>
     ));
 
+    let boot_config = components::boot_config::BootConfigComponent::new(
+        board_kernel,
+        capsules_extra::boot_config::DRIVER_NUM,
+        &base_peripherals.nvmc,
+        &BOOT_CONFIG,
+        addr_of!(BOOT_CONFIG) as usize,
+        kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1).unwrap()),
+    )
+    .finalize(components::boot_config_component_static!(
+        nrf52840::nvmc::Nvmc,
+        4096
+    ));
+    pconsole.set_boot_config(boot_config);
+    pconsole.run_boot_script();
+
     // Setup the console.
     let console = components::console::ConsoleComponent::new(
         board_kernel,
@@ -384,6 +420,16 @@ pub unsafe fn start() -> (
         nrf52840::ieee802154_radio::Radio,
         nrf52840::aes::AesECB<'static>
     ));
+    if let Some(radio_channel) = boot_env
+        .get_u32("radio.channel")
+        .and_then(|channel| u8::try_from(channel).ok())
+        .and_then(|channel| kernel::hil::radio::RadioChannel::try_from(channel).ok())
+    {
+        kernel::hil::radio::RadioConfig::set_channel(
+            &nrf52840_peripherals.ieee802154_radio,
+            radio_channel,
+        );
+    }
 
     let temp = components::temperature::TemperatureComponent::new(
         board_kernel,
@@ -425,6 +471,7 @@ pub unsafe fn start() -> (
         button,
         ble_radio,
         ieee802154_radio,
+        boot_config,
         pconsole,
         console,
         led,
@@ -16,6 +16,10 @@ enum Writer {
 
 static mut WRITER: Writer = Writer::WriterUart(false);
 
+/// Calls to `nop` taking about a millisecond at 64 MHz, to time the panic
+/// alert pattern.
+const PANIC_NOPS_PER_MS: usize = 8000;
+
 // Wait a fixed number of cycles to avoid missing characters over the RTT console
 fn wait() {
     for _ in 0..1000 {
@@ -93,24 +97,44 @@ impl IoWrite for Writer {
 pub unsafe fn panic_fmt(pi: &core::panic::PanicInfo) -> ! {
     use core::ptr::{addr_of, addr_of_mut};
     use kernel::debug;
-    use kernel::hil::led;
-    use nrf52840::gpio::Pin;
+    use kernel::hil::{alert, led};
 
     use crate::CHIP;
     use crate::PROCESSES;
     use crate::PROCESS_PRINTER;
 
-    // The nRF52840DK LEDs (see back of board)
-    let led_kernel_pin = &nrf52840::gpio::GPIOPin::new(Pin::P0_13);
-    let led = &mut led::LedLow::new(led_kernel_pin);
+    // The nRF52840DK LEDs (see back of board): LED1 and LED4 blink the
+    // panic pattern.
+    let led1_pin = &nrf52840::gpio::GPIOPin::new(crate::LED1_PIN);
+    let led4_pin = &nrf52840::gpio::GPIOPin::new(crate::LED4_PIN);
+    let led1 = &led::LedLow::new(led1_pin);
+    let led4 = &led::LedLow::new(led4_pin);
     let writer = &mut *addr_of_mut!(WRITER);
-    debug::panic(
-        &mut [led],
+    // Reset instead of halting if a syscall driver panicked and the
+    // `contain_capsule_panics` kernel feature is enabled. The nRF52840 keeps
+    // its RAM content across the soft reset requested with SYSRESETREQ, so
+    // the record of the panicked driver survives it.
+    debug::panic_contain(
+        writer,
+        pi,
+        &cortexm4::support::nop,
+        &*addr_of!(PROCESSES),
+        &*addr_of!(CHIP),
+        &*addr_of!(PROCESS_PRINTER),
+        cortexm4::support::reset,
+    );
+    debug::panic_print(
         writer,
         pi,
         &cortexm4::support::nop,
         &*addr_of!(PROCESSES),
         &*addr_of!(CHIP),
         &*addr_of!(PROCESS_PRINTER),
+    );
+    debug::panic_alert_forever(
+        &[&alert::AlertLed::new(led1), &alert::AlertLed::new(led4)],
+        &crate::ALERT_PATTERNS.get(alert::AlertClass::Panic),
+        &cortexm4::support::nop,
+        PANIC_NOPS_PER_MS,
     )
 }
@@ -94,6 +94,9 @@ const LED2_PIN: Pin = Pin::P0_14;
 const LED3_PIN: Pin = Pin::P0_15;
 const LED4_PIN: Pin = Pin::P0_16;
 
+/// Blink patterns signaling failures on the LEDs.
+const ALERT_PATTERNS: kernel::hil::alert::AlertPatterns = kernel::hil::alert::DEFAULT_PATTERNS;
+
 // The nRF52840DK buttons (see back of board)
 const BUTTON1_PIN: Pin = Pin::P0_11;
 const BUTTON2_PIN: Pin = Pin::P0_12;
@@ -180,8 +183,12 @@ type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KV
 type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
 
 // Temperature
-type TemperatureDriver =
-    components::temperature::TemperatureComponentType<nrf52840::temperature::Temp<'static>>;
+type TemperatureCompensation =
+    components::temperature_compensation::TemperatureCompensationComponentType<
+        nrf52840::temperature::Temp<'static>,
+        nrf52840::rtc::Rtc<'static>,
+    >;
+type TemperatureDriver = components::temperature::TemperatureComponentType<TemperatureCompensation>;
 
 // IEEE 802.15.4
 type Ieee802154MacDevice = components::ieee802154::Ieee802154ComponentMacDeviceType<
@@ -241,6 +248,7 @@ pub struct Platform {
         >,
     >,
     kv_driver: &'static KVDriver,
+    net_stats: &'static components::net_stats::NetStatsComponentType,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -250,23 +258,24 @@ impl SyscallDriverLookup for Platform {
     where
         F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
     {
-        match driver_num {
-            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
-            capsules_core::gpio::DRIVER_NUM => f(Some(self.gpio)),
-            capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
-            capsules_core::led::DRIVER_NUM => f(Some(self.led)),
-            capsules_core::button::DRIVER_NUM => f(Some(self.button)),
-            capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
-            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
-            capsules_extra::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
-            capsules_extra::temperature::DRIVER_NUM => f(Some(self.temp)),
-            capsules_extra::analog_comparator::DRIVER_NUM => f(Some(self.analog_comparator)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
-            capsules_core::i2c_master_slave_driver::DRIVER_NUM => f(Some(self.i2c_master_slave)),
-            capsules_core::spi_controller::DRIVER_NUM => f(Some(self.spi_controller)),
-            capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+        kernel::dispatch_drivers!(driver_num, f, {
+            capsules_core::console::DRIVER_NUM => self.console,
+            capsules_core::gpio::DRIVER_NUM => self.gpio,
+            capsules_core::alarm::DRIVER_NUM => self.alarm,
+            capsules_core::led::DRIVER_NUM => self.led,
+            capsules_core::button::DRIVER_NUM => self.button,
+            capsules_core::rng::DRIVER_NUM => self.rng,
+            capsules_core::adc::DRIVER_NUM => self.adc,
+            capsules_extra::ble_advertising_driver::DRIVER_NUM => self.ble_radio,
+            capsules_extra::temperature::DRIVER_NUM => self.temp,
+            capsules_extra::analog_comparator::DRIVER_NUM => self.analog_comparator,
+            kernel::ipc::DRIVER_NUM => &self.ipc,
+            capsules_core::i2c_master_slave_driver::DRIVER_NUM => self.i2c_master_slave,
+            capsules_core::spi_controller::DRIVER_NUM => self.spi_controller,
+            capsules_extra::kv_driver::DRIVER_NUM => self.kv_driver,
+            capsules_extra::net_stats::DRIVER_NUM => self.net_stats,
             _ => f(None),
-        }
+        })
     }
 }
 
@@ -575,6 +584,13 @@ pub unsafe fn start() -> (
     )
     .finalize(components::alarm_component_static!(nrf52840::rtc::Rtc));
 
+    // Wake processes at the end of their `yield-wait-timeout`.
+    components::yield_timer::YieldTimerComponent::<_, NUM_PROCS>::new(board_kernel, mux_alarm)
+        .finalize(components::yield_timer_component_static!(
+            nrf52840::rtc::Rtc,
+            NUM_PROCS
+        ));
+
     //--------------------------------------------------------------------------
     // UART & CONSOLE & DEBUG
     //--------------------------------------------------------------------------
@@ -610,6 +626,21 @@ pub unsafe fn start() -> (
         nrf52840::rtc::Rtc<'static>
     ));
 
+    // Report the statistics of the 802.15.4 radio through the process console
+    // and to userspace.
+    let network_interfaces = static_init!(
+        [&'static dyn kernel::hil::network_statistics::NetworkStatistics; 1],
+        [&nrf52840_peripherals.ieee802154_radio]
+    );
+    pconsole.set_network_interfaces(network_interfaces);
+
+    // Report the interrupt latencies measured with the `interrupt_latency`
+    // feature of the `cortexm4` crate through the process console.
+    kernel::hil::hw_debug::CycleCounter::start(&cortexm4::dwt::Dwt::new());
+    pconsole.set_interrupt_latency(cortexm4::interrupt_latency::tracker());
+    let net_stats = components::net_stats::NetStatsComponent::new(network_interfaces)
+        .finalize(components::net_stats_component_static!());
+
     // Setup the serial console for userspace.
     let console = components::console::ConsoleComponent::new(
         board_kernel,
@@ -626,6 +657,16 @@ pub unsafe fn start() -> (
     // BLE
     //--------------------------------------------------------------------------
 
+    // Share the radio between BLE and 802.15.4.
+    let radio_arbiter = static_init!(
+        nrf52840::radio_arbiter::RadioArbiter<'static>,
+        nrf52840::radio_arbiter::RadioArbiter::new(
+            &nrf52840_peripherals.ieee802154_radio,
+            &base_peripherals.ble_radio,
+        )
+    );
+    radio_arbiter.init();
+
     let ble_radio = components::ble::BLEComponent::new(
         board_kernel,
         capsules_extra::ble_advertising_driver::DRIVER_NUM,
@@ -641,13 +682,26 @@ pub unsafe fn start() -> (
     // TEMPERATURE (internal)
     //--------------------------------------------------------------------------
 
+    // Keep the die temperature cached for drift compensation, refreshed
+    // every 10 seconds.
+    let temperature_compensation =
+        components::temperature_compensation::TemperatureCompensationComponent::new(
+            &base_peripherals.temp,
+            mux_alarm,
+            10_000,
+        )
+        .finalize(components::temperature_compensation_component_static!(
+            nrf52840::temperature::Temp<'static>,
+            nrf52840::rtc::Rtc
+        ));
+
     let temp = components::temperature::TemperatureComponent::new(
         board_kernel,
         capsules_extra::temperature::DRIVER_NUM,
-        &base_peripherals.temp,
+        temperature_compensation,
     )
     .finalize(components::temperature_component_static!(
-        nrf52840::temperature::Temp
+        TemperatureCompensation
     ));
 
     //--------------------------------------------------------------------------
@@ -666,7 +720,7 @@ pub unsafe fn start() -> (
     //--------------------------------------------------------------------------
 
     let adc_channels = static_init!(
-        [nrf52840::adc::AdcChannelSetup; 6],
+        [nrf52840::adc::AdcChannelSetup; 8],
         [
             nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput1),
             nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput2),
@@ -674,6 +728,10 @@ pub unsafe fn start() -> (
             nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput5),
             nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput6),
             nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput7),
+            // Internal channels, to monitor the supplies without external
+            // wiring.
+            nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::VDD),
+            nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::VDDHDIV5),
         ]
     );
     let adc = components::adc::AdcDedicatedComponent::new(
@@ -906,6 +964,7 @@ pub unsafe fn start() -> (
         i2c_master_slave,
         spi_controller,
         kv_driver,
+        net_stats,
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
@@ -46,6 +46,15 @@ static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::Pr
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
 
+/// Register blocks dumped by the `regs` command of the process console.
+static REGISTER_BLOCKS: [kernel::debug::RegisterBlock; 2] = unsafe {
+    [
+        // MCR to BTR: mode, status, error and bit timing of CAN1.
+        kernel::debug::RegisterBlock::new("CAN1", 0x4000_6400, 8),
+        kernel::debug::RegisterBlock::new("RCC", 0x4002_3800, 36),
+    ]
+};
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -58,7 +67,12 @@ pub static mut STACK_MEMORY: [u8; 0x2000] = [0; 0x2000];
 type TemperatureSTMSensor = components::temperature_stm::TemperatureSTMComponentType<
     capsules_core::virtualizers::virtual_adc::AdcDevice<'static, stm32f429zi::adc::Adc<'static>>,
 >;
-type TemperatureDriver = components::temperature::TemperatureComponentType<TemperatureSTMSensor>;
+type TemperatureCompensation =
+    components::temperature_compensation::TemperatureCompensationComponentType<
+        TemperatureSTMSensor,
+        stm32f429zi::tim2::Tim2<'static>,
+    >;
+type TemperatureDriver = components::temperature::TemperatureComponentType<TemperatureCompensation>;
 type RngDriver = components::rng::RngComponentType<stm32f429zi::trng::Trng<'static>>;
 
 /// Nucleo F429ZI HSE frequency in MHz
@@ -76,6 +90,13 @@ struct NucleoF429ZI {
     >,
     button: &'static capsules_core::button::Button<'static, stm32f429zi::gpio::Pin<'static>>,
     adc: &'static capsules_core::adc::AdcVirtualized<'static>,
+    adc_acquisition: &'static components::adc_acquisition::AdcAcquisitionComponentType<
+        stm32f429zi::tim2::Tim2<'static>,
+        capsules_core::virtualizers::virtual_adc::AdcDevice<
+            'static,
+            stm32f429zi::adc::Adc<'static>,
+        >,
+    >,
     dac: &'static capsules_extra::dac::Dac<'static>,
     alarm: &'static capsules_core::alarm::AlarmDriver<
         'static,
@@ -92,6 +113,9 @@ struct NucleoF429ZI {
         'static,
         stm32f429zi::rtc::Rtc<'static>,
     >,
+    net_stats: &'static components::net_stats::NetStatsComponentType,
+    system_config: &'static components::system_config::SystemConfigComponentType,
+    uptime: &'static components::uptime::UptimeComponentType<stm32f429zi::tim2::Tim2<'static>>,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -100,21 +124,25 @@ impl SyscallDriverLookup for NucleoF429ZI {
     where
         F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
     {
-        match driver_num {
-            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
-            capsules_core::led::DRIVER_NUM => f(Some(self.led)),
-            capsules_core::button::DRIVER_NUM => f(Some(self.button)),
-            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
-            capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
-            capsules_extra::temperature::DRIVER_NUM => f(Some(self.temperature)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
-            capsules_core::gpio::DRIVER_NUM => f(Some(self.gpio)),
-            capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
-            capsules_extra::can::DRIVER_NUM => f(Some(self.can)),
-            capsules_extra::dac::DRIVER_NUM => f(Some(self.dac)),
-            capsules_extra::date_time::DRIVER_NUM => f(Some(self.date_time)),
+        kernel::dispatch_drivers!(driver_num, f, {
+            capsules_core::console::DRIVER_NUM => self.console,
+            capsules_core::led::DRIVER_NUM => self.led,
+            capsules_core::button::DRIVER_NUM => self.button,
+            capsules_core::adc::DRIVER_NUM => self.adc,
+            capsules_extra::adc_acquisition::DRIVER_NUM => self.adc_acquisition,
+            capsules_core::alarm::DRIVER_NUM => self.alarm,
+            capsules_extra::temperature::DRIVER_NUM => self.temperature,
+            kernel::ipc::DRIVER_NUM => &self.ipc,
+            capsules_core::gpio::DRIVER_NUM => self.gpio,
+            capsules_core::rng::DRIVER_NUM => self.rng,
+            capsules_extra::can::DRIVER_NUM => self.can,
+            capsules_extra::dac::DRIVER_NUM => self.dac,
+            capsules_extra::date_time::DRIVER_NUM => self.date_time,
+            capsules_extra::net_stats::DRIVER_NUM => self.net_stats,
+            capsules_extra::system_config::DRIVER_NUM => self.system_config,
+            capsules_extra::uptime::DRIVER_NUM => self.uptime,
             _ => f(None),
-        }
+        })
     }
 }
 
@@ -542,7 +570,7 @@ unsafe fn start() -> (
 
     let temp_sensor = components::temperature_stm::TemperatureSTMComponent::new(
         adc_mux,
-        stm32f429zi::adc::Channel::Channel18,
+        stm32f429zi::adc::Channel::Temperature,
         2.5,
         0.76,
     )
@@ -550,13 +578,26 @@ unsafe fn start() -> (
         stm32f429zi::adc::Adc
     ));
 
+    // Keep the die temperature cached for drift compensation, refreshed
+    // every 10 seconds.
+    let temperature_compensation =
+        components::temperature_compensation::TemperatureCompensationComponent::new(
+            temp_sensor,
+            mux_alarm,
+            10_000,
+        )
+        .finalize(components::temperature_compensation_component_static!(
+            TemperatureSTMSensor,
+            stm32f429zi::tim2::Tim2
+        ));
+
     let temp = components::temperature::TemperatureComponent::new(
         board_kernel,
         capsules_extra::temperature::DRIVER_NUM,
-        temp_sensor,
+        temperature_compensation,
     )
     .finalize(components::temperature_component_static!(
-        TemperatureSTMSensor
+        TemperatureCompensation
     ));
 
     let adc_channel_0 =
@@ -579,6 +620,19 @@ unsafe fn start() -> (
         components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Channel12)
             .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
 
+    // Internal channels, to monitor the supplies without external wiring.
+    let adc_channel_vrefint =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Vrefint)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+
+    let adc_channel_vbat =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Vbat)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+
+    let adc_channel_temperature =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Temperature)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+
     let adc_syscall =
         components::adc::AdcVirtualComponent::new(board_kernel, capsules_core::adc::DRIVER_NUM)
             .finalize(components::adc_syscall_component_helper!(
@@ -587,8 +641,27 @@ unsafe fn start() -> (
                 adc_channel_2,
                 adc_channel_3,
                 adc_channel_4,
+                adc_channel_vrefint,
+                adc_channel_vbat,
+                adc_channel_temperature,
             ));
 
+    // Scheduled acquisition on A0, sharing the pin with the ADC driver.
+    let adc_acquisition_channel =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Channel3)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+    let adc_acquisition = components::adc_acquisition::AdcAcquisitionComponent::new(
+        board_kernel,
+        capsules_extra::adc_acquisition::DRIVER_NUM,
+        mux_alarm,
+        adc_acquisition_channel,
+    )
+    .finalize(components::adc_acquisition_component_static!(
+        stm32f429zi::tim2::Tim2,
+        capsules_core::virtualizers::virtual_adc::AdcDevice<'static, stm32f429zi::adc::Adc>,
+        64,
+    ));
+
     let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
         .finalize(components::process_printer_text_component_static!());
     PROCESS_PRINTER = Some(process_printer);
@@ -614,6 +687,8 @@ unsafe fn start() -> (
     .finalize(components::can_component_static!(
         stm32f429zi::can::Can<'static>
     ));
+    can.set_filter(&peripherals.can1);
+    can.set_diagnostics(&peripherals.can1);
 
     // RTC DATE TIME
     match peripherals.rtc.rtc_init() {
@@ -630,6 +705,53 @@ unsafe fn start() -> (
         stm32f429zi::rtc::Rtc<'static>
     ));
 
+    // NETWORK STATISTICS
+    let network_interfaces = static_init!(
+        [&'static dyn kernel::hil::network_statistics::NetworkStatistics; 1],
+        [&peripherals.can1]
+    );
+    let net_stats = components::net_stats::NetStatsComponent::new(network_interfaces)
+        .finalize(components::net_stats_component_static!());
+
+    // SYSTEM CONFIGURATION
+    let uptime = components::uptime::UptimeComponent::new(
+        board_kernel,
+        capsules_extra::uptime::DRIVER_NUM,
+        mux_alarm,
+    )
+    .finalize(components::uptime_component_static!(
+        stm32f429zi::tim2::Tim2
+    ));
+
+    let system_config = components::system_config::SystemConfigComponent::new(
+        board_kernel,
+        capsules_extra::system_config::DRIVER_NUM,
+        capsules_extra::system_config::SystemInfo {
+            board: "nucleo_f429zi",
+            chip: "stm32f429zi",
+            cpu_frequency: (clocks.get_sys_clock_frequency_mhz() * 1_000_000) as u32,
+            drivers: &[
+                capsules_core::console::DRIVER_NUM,
+                capsules_core::led::DRIVER_NUM,
+                capsules_core::button::DRIVER_NUM,
+                capsules_core::adc::DRIVER_NUM,
+                capsules_extra::adc_acquisition::DRIVER_NUM,
+                capsules_core::alarm::DRIVER_NUM,
+                capsules_extra::temperature::DRIVER_NUM,
+                kernel::ipc::DRIVER_NUM,
+                capsules_core::gpio::DRIVER_NUM,
+                capsules_core::rng::DRIVER_NUM,
+                capsules_extra::can::DRIVER_NUM,
+                capsules_extra::dac::DRIVER_NUM,
+                capsules_extra::date_time::DRIVER_NUM,
+                capsules_extra::net_stats::DRIVER_NUM,
+                capsules_extra::system_config::DRIVER_NUM,
+                capsules_extra::uptime::DRIVER_NUM,
+            ],
+        },
+    )
+    .finalize(components::system_config_component_static!());
+
     // PROCESS CONSOLE
     let process_console = components::process_console::ProcessConsoleComponent::new(
         board_kernel,
@@ -641,6 +763,8 @@ unsafe fn start() -> (
     .finalize(components::process_console_component_static!(
         stm32f429zi::tim2::Tim2
     ));
+    process_console.set_network_interfaces(network_interfaces);
+    process_console.set_register_blocks(&REGISTER_BLOCKS);
     let _ = process_console.start();
 
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
@@ -654,6 +778,7 @@ unsafe fn start() -> (
             &memory_allocation_capability,
         ),
         adc: adc_syscall,
+        adc_acquisition,
         dac: dac,
         led: led,
         temperature: temp,
@@ -666,6 +791,9 @@ unsafe fn start() -> (
         systick: cortexm4::systick::SysTick::new(),
         can: can,
         date_time,
+        net_stats,
+        system_config,
+        uptime,
     };
 
     // // Optional kernel tests
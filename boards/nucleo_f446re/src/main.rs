@@ -174,6 +174,26 @@ unsafe fn setup_dma(
     cortexm4::nvic::Nvic::new(Dma1Peripheral::USART2_RX.get_stream_irqn()).enable();
 }
 
+/// Helper function called during bring-up that lets the ADC sample buffers
+/// through DMA2.
+unsafe fn setup_adc_dma(
+    dma: &stm32f446re::dma::Dma2,
+    dma_streams: &'static [stm32f446re::dma::Stream<stm32f446re::dma::Dma2>; 8],
+    adc: &'static stm32f446re::adc::Adc,
+) {
+    use stm32f446re::dma::Dma2Peripheral;
+
+    dma.enable_clock();
+
+    let adc_stream = &dma_streams[Dma2Peripheral::ADC1.get_stream_idx()];
+
+    adc.set_dma(adc_stream);
+    adc_stream.set_client(adc);
+    adc_stream.setup(Dma2Peripheral::ADC1);
+
+    cortexm4::nvic::Nvic::new(Dma2Peripheral::ADC1.get_stream_irqn()).enable();
+}
+
 /// Helper function called during bring-up that configures multiplexed I/O.
 unsafe fn set_pin_primary_functions(
     syscfg: &stm32f446re::syscfg::Syscfg,
@@ -303,6 +323,7 @@ unsafe fn start() -> (
         &base_peripherals.dma1_streams,
         &base_peripherals.usart2,
     );
+    setup_adc_dma(dma2, &base_peripherals.dma2_streams, &base_peripherals.adc1);
 
     let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&*addr_of!(PROCESSES)));
 
@@ -378,12 +399,12 @@ unsafe fn start() -> (
     .finalize(components::alarm_component_static!(stm32f446re::tim2::Tim2));
 
     // ADC
-    let adc_mux = components::adc::AdcMuxComponent::new(&base_peripherals.adc1)
+    let adc_mux = components::adc::AdcHighSpeedMuxComponent::new(&base_peripherals.adc1)
         .finalize(components::adc_mux_component_static!(stm32f446re::adc::Adc));
 
     let temp_sensor = components::temperature_stm::TemperatureSTMComponent::new(
         adc_mux,
-        stm32f446re::adc::Channel::Channel18,
+        stm32f446re::adc::Channel::Temperature,
         2.5,
         0.76,
     )
@@ -424,6 +445,19 @@ unsafe fn start() -> (
         components::adc::AdcComponent::new(adc_mux, stm32f446re::adc::Channel::Channel10)
             .finalize(components::adc_component_static!(stm32f446re::adc::Adc));
 
+    // Internal channels, to monitor the supplies without external wiring.
+    let adc_channel_vrefint =
+        components::adc::AdcComponent::new(adc_mux, stm32f446re::adc::Channel::Vrefint)
+            .finalize(components::adc_component_static!(stm32f446re::adc::Adc));
+
+    let adc_channel_vbat =
+        components::adc::AdcComponent::new(adc_mux, stm32f446re::adc::Channel::Vbat)
+            .finalize(components::adc_component_static!(stm32f446re::adc::Adc));
+
+    let adc_channel_temperature =
+        components::adc::AdcComponent::new(adc_mux, stm32f446re::adc::Channel::Temperature)
+            .finalize(components::adc_component_static!(stm32f446re::adc::Adc));
+
     let adc_syscall =
         components::adc::AdcVirtualComponent::new(board_kernel, capsules_core::adc::DRIVER_NUM)
             .finalize(components::adc_syscall_component_helper!(
@@ -432,7 +466,10 @@ unsafe fn start() -> (
                 adc_channel_2,
                 adc_channel_3,
                 adc_channel_4,
-                adc_channel_5
+                adc_channel_5,
+                adc_channel_vrefint,
+                adc_channel_vbat,
+                adc_channel_temperature
             ));
 
     let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
@@ -685,7 +685,7 @@ pub unsafe fn main() {
 
     let temp_sensor = components::temperature_stm::TemperatureSTMComponent::new(
         adc_mux,
-        stm32f412g::adc::Channel::Channel18,
+        stm32f412g::adc::Channel::Temperature,
         2.5,
         0.76,
     )
@@ -726,6 +726,19 @@ pub unsafe fn main() {
         components::adc::AdcComponent::new(adc_mux, stm32f412g::adc::Channel::Channel8)
             .finalize(components::adc_component_static!(stm32f412g::adc::Adc));
 
+    // Internal channels, to monitor the supplies without external wiring.
+    let adc_channel_vrefint =
+        components::adc::AdcComponent::new(adc_mux, stm32f412g::adc::Channel::Vrefint)
+            .finalize(components::adc_component_static!(stm32f412g::adc::Adc));
+
+    let adc_channel_vbat =
+        components::adc::AdcComponent::new(adc_mux, stm32f412g::adc::Channel::Vbat)
+            .finalize(components::adc_component_static!(stm32f412g::adc::Adc));
+
+    let adc_channel_temperature =
+        components::adc::AdcComponent::new(adc_mux, stm32f412g::adc::Channel::Temperature)
+            .finalize(components::adc_component_static!(stm32f412g::adc::Adc));
+
     let adc_syscall =
         components::adc::AdcVirtualComponent::new(board_kernel, capsules_core::adc::DRIVER_NUM)
             .finalize(components::adc_syscall_component_helper!(
@@ -734,7 +747,10 @@ pub unsafe fn main() {
                 adc_channel_2,
                 adc_channel_3,
                 adc_channel_4,
-                adc_channel_5
+                adc_channel_5,
+                adc_channel_vrefint,
+                adc_channel_vbat,
+                adc_channel_temperature
             ));
 
     let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
@@ -51,6 +51,41 @@ const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
 #[link_section = ".stack_buffer"]
 pub static mut STACK_MEMORY: [u8; 0x2000] = [0; 0x2000];
 
+/// IS42S16400J SDRAM, timings for an SDRAM clock of up to 90 MHz.
+const SDRAM_CONFIG: stm32f429zi::fmc_sdram::SdramConfig = stm32f429zi::fmc_sdram::SdramConfig {
+    bank: stm32f429zi::fmc_sdram::SdramBank::Bank2,
+    column_bits: 8,
+    row_bits: 12,
+    bus_width: 16,
+    four_internal_banks: true,
+    cas_latency: 3,
+    load_to_active_delay: 2,
+    exit_self_refresh_delay: 7,
+    self_refresh_time: 4,
+    row_cycle_delay: 7,
+    write_recovery_time: 2,
+    rp_delay: 2,
+    rcd_delay: 2,
+    refresh_period_ms: 64,
+};
+
+/// ILI9341 240x320 panel, driven through its RGB interface.
+const LCD_TIMING: stm32f429zi::ltdc::LtdcTiming = stm32f429zi::ltdc::LtdcTiming {
+    width: 240,
+    height: 320,
+    hsync: 10,
+    hback_porch: 20,
+    hfront_porch: 10,
+    vsync: 2,
+    vback_porch: 2,
+    vfront_porch: 4,
+    pixel_clock: 6_000_000,
+    hsync_active_high: false,
+    vsync_active_high: false,
+    data_enable_active_high: false,
+    pixel_clock_inverted: false,
+};
+
 type TemperatureSTMSensor = components::temperature_stm::TemperatureSTMComponentType<
     capsules_core::virtualizers::virtual_adc::AdcDevice<'static, stm32f429zi::adc::Adc<'static>>,
 >;
@@ -74,6 +109,7 @@ struct STM32F429IDiscovery {
     >,
     temperature: &'static TemperatureDriver,
     gpio: &'static capsules_core::gpio::GPIO<'static, stm32f429zi::gpio::Pin<'static>>,
+    screen: &'static capsules_extra::screen::Screen<'static>,
 
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
@@ -94,6 +130,7 @@ impl SyscallDriverLookup for STM32F429IDiscovery {
             capsules_extra::temperature::DRIVER_NUM => f(Some(self.temperature)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             capsules_core::gpio::DRIVER_NUM => f(Some(self.gpio)),
+            capsules_extra::screen::DRIVER_NUM => f(Some(self.screen)),
             _ => f(None),
         }
     }
@@ -225,35 +262,171 @@ unsafe fn set_pin_primary_functions(
     //           G: already enabled
     gpio_ports.get_port_from_port_id(PortId::H).enable_clock();
 
-    // Arduino A0
-    gpio_ports.get_pin(PinId::PA03).map(|pin| {
-        pin.set_mode(stm32f429zi::gpio::Mode::AnalogMode);
-    });
-
-    // Arduino A1
-    gpio_ports.get_pin(PinId::PC00).map(|pin| {
-        pin.set_mode(stm32f429zi::gpio::Mode::AnalogMode);
-    });
-
     // Arduino A2
     gpio_ports.get_pin(PinId::PC03).map(|pin| {
         pin.set_mode(stm32f429zi::gpio::Mode::AnalogMode);
     });
 
-    // Arduino A3
-    gpio_ports.get_pin(PinId::PF03).map(|pin| {
-        pin.set_mode(stm32f429zi::gpio::Mode::AnalogMode);
-    });
+    // The other Arduino analog pins are wired to the LCD and the SDRAM, see
+    // `set_display_pin_functions`.
+}
 
-    // Arduino A4
-    gpio_ports.get_pin(PinId::PF05).map(|pin| {
-        pin.set_mode(stm32f429zi::gpio::Mode::AnalogMode);
-    });
+/// Configure the pins of the SDRAM and of the LCD RGB interface.
+unsafe fn set_display_pin_functions(gpio_ports: &'static stm32f429zi::gpio::GpioPorts<'static>) {
+    // FMC SDRAM bank 2, connected to the IS42S16400J
+    let fmc_pins = [
+        PinId::PB05, // SDCKE1
+        PinId::PB06, // SDNE1
+        PinId::PC00, // SDNWE
+        PinId::PD00,
+        PinId::PD01,
+        PinId::PD08,
+        PinId::PD09,
+        PinId::PD10,
+        PinId::PD14,
+        PinId::PD15,
+        PinId::PE00,
+        PinId::PE01,
+        PinId::PE07,
+        PinId::PE08,
+        PinId::PE09,
+        PinId::PE10,
+        PinId::PE11,
+        PinId::PE12,
+        PinId::PE13,
+        PinId::PE14,
+        PinId::PE15,
+        PinId::PF00,
+        PinId::PF01,
+        PinId::PF02,
+        PinId::PF03,
+        PinId::PF04,
+        PinId::PF05,
+        PinId::PF11, // SDNRAS
+        PinId::PF12,
+        PinId::PF13,
+        PinId::PF14,
+        PinId::PF15,
+        PinId::PG00,
+        PinId::PG01,
+        PinId::PG04, // BA0
+        PinId::PG05, // BA1
+        PinId::PG08, // SDCLK
+        PinId::PG15, // SDNCAS
+    ];
+
+    // LTDC, connected to the RGB interface of the ILI9341
+    let ltdc_pins = [
+        (PinId::PA03, AlternateFunction::AF14), // B5
+        (PinId::PA04, AlternateFunction::AF14), // VSYNC
+        (PinId::PA06, AlternateFunction::AF14), // G2
+        (PinId::PA11, AlternateFunction::AF14), // R4
+        (PinId::PA12, AlternateFunction::AF14), // R5
+        (PinId::PB00, AlternateFunction::AF9),  // R3
+        (PinId::PB01, AlternateFunction::AF9),  // R6
+        (PinId::PB08, AlternateFunction::AF14), // B6
+        (PinId::PB09, AlternateFunction::AF14), // B7
+        (PinId::PB10, AlternateFunction::AF14), // G4
+        (PinId::PB11, AlternateFunction::AF14), // G5
+        (PinId::PC06, AlternateFunction::AF14), // HSYNC
+        (PinId::PC07, AlternateFunction::AF14), // G6
+        (PinId::PC10, AlternateFunction::AF14), // R2
+        (PinId::PD03, AlternateFunction::AF14), // G7
+        (PinId::PD06, AlternateFunction::AF14), // B2
+        (PinId::PF10, AlternateFunction::AF14), // DE
+        (PinId::PG06, AlternateFunction::AF14), // R7
+        (PinId::PG07, AlternateFunction::AF14), // CLK
+        (PinId::PG10, AlternateFunction::AF9),  // G3
+        (PinId::PG11, AlternateFunction::AF14), // B3
+        (PinId::PG12, AlternateFunction::AF9),  // B4
+    ];
+
+    for pin in fmc_pins.iter() {
+        gpio_ports.get_pin(*pin).map(|pin| {
+            pin.set_mode(Mode::AlternateFunctionMode);
+            pin.set_speed();
+            pin.set_alternate_function(AlternateFunction::AF12);
+        });
+    }
 
-    // Arduino A5
-    gpio_ports.get_pin(PinId::PF10).map(|pin| {
-        pin.set_mode(stm32f429zi::gpio::Mode::AnalogMode);
-    });
+    for (pin, function) in ltdc_pins {
+        gpio_ports.get_pin(pin).map(|pin| {
+            pin.set_mode(Mode::AlternateFunctionMode);
+            pin.set_speed();
+            pin.set_alternate_function(function);
+        });
+    }
+}
+
+/// Switch the ILI9341 panel to its RGB interface.
+///
+/// The panel is only configurable through a serial interface (SPI5 pins,
+/// bit-banged here as this is only done once at boot).
+unsafe fn init_lcd_panel(gpio_ports: &'static stm32f429zi::gpio::GpioPorts<'static>) {
+    use kernel::hil::gpio::{Configure, Output};
+
+    // Commands and parameters, followed by a delay in milliseconds.
+    const COMMANDS: [(u8, &[u8], usize); 20] = [
+        (0xCA, &[0xC3, 0x08, 0x50], 0),
+        (0xCF, &[0x00, 0xC1, 0x30], 0),             // power control B
+        (0xED, &[0x64, 0x03, 0x12, 0x81], 0),       // power on sequence
+        (0xE8, &[0x85, 0x00, 0x78], 0),             // driver timing control A
+        (0xCB, &[0x39, 0x2C, 0x00, 0x34, 0x02], 0), // power control A
+        (0xF7, &[0x20], 0),                         // pump ratio control
+        (0xEA, &[0x00, 0x00], 0),                   // driver timing control B
+        (0xB1, &[0x00, 0x1B], 0),                   // frame rate control
+        (0xC0, &[0x10], 0),                         // power control 1
+        (0xC1, &[0x10], 0),                         // power control 2
+        (0xC5, &[0x45, 0x15], 0),                   // VCOM control 1
+        (0xC7, &[0x90], 0),                         // VCOM control 2
+        (0x36, &[0xC8], 0),                         // memory access control
+        (0xB0, &[0xC2], 0),                         // RGB interface
+        (0xB6, &[0x0A, 0xA7, 0x27, 0x04], 0),       // display function control
+        (0x2A, &[0x00, 0x00, 0x00, 0xEF], 0),       // column address
+        (0x2B, &[0x00, 0x00, 0x01, 0x3F], 0),       // page address
+        (0xF6, &[0x01, 0x00, 0x06], 0),             // interface control
+        (0x11, &[], 200),                           // sleep out
+        (0x29, &[], 0),                             // display on
+    ];
+
+    let pin = |id| gpio_ports.get_pin(id).unwrap();
+    let chip_select = pin(PinId::PC02);
+    let data_command = pin(PinId::PD13);
+    let clock = pin(PinId::PF07);
+    let data = pin(PinId::PF09);
+    for output in [chip_select, data_command, clock, data] {
+        output.make_output();
+    }
+    chip_select.set();
+    clock.clear();
+
+    // Busy wait, assuming a core clock of at most 180 MHz.
+    let delay_ms = |ms: usize| {
+        for _ in 0..ms * 180_000 {
+            cortexm4::support::nop();
+        }
+    };
+    let send = |byte: u8| {
+        for bit in (0..8).rev() {
+            if byte & (1 << bit) != 0 {
+                data.set();
+            } else {
+                data.clear();
+            }
+            clock.set();
+            clock.clear();
+        }
+    };
+
+    for (command, parameters, delay) in COMMANDS {
+        chip_select.clear();
+        data_command.clear();
+        send(command);
+        data_command.set();
+        parameters.iter().for_each(|parameter| send(*parameter));
+        chip_select.set();
+        delay_ms(delay);
+    }
 }
 
 /// Helper function for miscellaneous peripheral functions
@@ -513,7 +686,7 @@ pub unsafe fn main() {
 
     let temp_sensor = components::temperature_stm::TemperatureSTMComponent::new(
         adc_mux,
-        stm32f429zi::adc::Channel::Channel18,
+        stm32f429zi::adc::Channel::Temperature,
         2.5,
         0.76,
     )
@@ -554,6 +727,19 @@ pub unsafe fn main() {
         components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Channel8)
             .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
 
+    // Internal channels, to monitor the supplies without external wiring.
+    let adc_channel_vrefint =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Vrefint)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+
+    let adc_channel_vbat =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Vbat)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+
+    let adc_channel_temperature =
+        components::adc::AdcComponent::new(adc_mux, stm32f429zi::adc::Channel::Temperature)
+            .finalize(components::adc_component_static!(stm32f429zi::adc::Adc));
+
     let adc_syscall =
         components::adc::AdcVirtualComponent::new(board_kernel, capsules_core::adc::DRIVER_NUM)
             .finalize(components::adc_syscall_component_helper!(
@@ -562,7 +748,10 @@ pub unsafe fn main() {
                 adc_channel_2,
                 adc_channel_3,
                 adc_channel_4,
-                adc_channel_5
+                adc_channel_5,
+                adc_channel_vrefint,
+                adc_channel_vbat,
+                adc_channel_temperature
             ));
 
     let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
@@ -582,6 +771,46 @@ pub unsafe fn main() {
     ));
     let _ = process_console.start();
 
+    // LCD
+
+    set_display_pin_functions(&base_peripherals.gpio_ports);
+    init_lcd_panel(&base_peripherals.gpio_ports);
+
+    let sdram = peripherals
+        .fmc_sdram
+        .init(&SDRAM_CONFIG)
+        .expect("SDRAM initialization failed");
+    let framebuffer_size = LCD_TIMING.width * LCD_TIMING.height * 2;
+    let framebuffers = [
+        core::slice::from_raw_parts_mut(sdram as *mut u8, framebuffer_size),
+        core::slice::from_raw_parts_mut((sdram + framebuffer_size) as *mut u8, framebuffer_size),
+    ];
+    peripherals
+        .ltdc
+        .init(
+            &LCD_TIMING,
+            kernel::hil::screen::ScreenPixelFormat::RGB_565,
+            framebuffers,
+        )
+        .expect("LTDC initialization failed");
+    peripherals.dma2d.enable_clock();
+    peripherals.ltdc.set_dma2d(&peripherals.dma2d);
+    kernel::hil::screen::ScreenAccelerated::set_client(&peripherals.dma2d, &peripherals.ltdc);
+    cortexm4::nvic::Nvic::new(stm32f429zi::stm32f429zi_nvic::LTDC).enable();
+    cortexm4::nvic::Nvic::new(stm32f429zi::stm32f429zi_nvic::LTDCE).enable();
+    cortexm4::nvic::Nvic::new(stm32f429zi::stm32f429zi_nvic::DMA2D).enable();
+
+    let screen = components::screen::ScreenComponent::new(
+        board_kernel,
+        capsules_extra::screen::DRIVER_NUM,
+        &peripherals.ltdc,
+        None,
+    )
+    .finalize(components::screen_component_static!(4096));
+    screen.set_accelerated(&peripherals.ltdc);
+    kernel::hil::screen::ScreenAccelerated::set_client(&peripherals.ltdc, screen);
+    let _ = kernel::hil::screen::Screen::set_power(&peripherals.ltdc, true);
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -598,6 +827,7 @@ pub unsafe fn main() {
         button: button,
         alarm: alarm,
         gpio: gpio,
+        screen,
 
         scheduler,
         systick: cortexm4::systick::SysTick::new(),
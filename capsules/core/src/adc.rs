@@ -8,18 +8,34 @@
 //!
 //! The first, called AdcDedicated, assumes that it has complete (dedicated)
 //! control of the kernel ADC. This capsule provides userspace with
-//! the ability to perform single, continuous, and high speed samples.
+//! the ability to perform single, continuous, and high speed samples, and to
+//! sleep until a channel leaves a voltage window. Single samples can also be
+//! reported in millivolts, converted with `hil::adc::Adc::sample_to_mv`.
+//! High speed samples can also be streamed indefinitely into an application
+//! ring buffer, whose head index is maintained by the kernel.
 //! However, using this capsule means that no other
-//! capsule or kernel service can use the ADC. It also allows only
-//! a single process to use the ADC: other processes will receive
-//! NOMEM errors.
+//! capsule or kernel service can use the ADC. Single samples can be
+//! requested by any number of processes, on any number of channels: requests
+//! made while another single sample is in progress are queued, one per
+//! channel and process, and served round-robin between the processes.
+//! Continuous, buffered and window sampling are exclusive to a single
+//! process while they run: other processes will receive NOMEM errors until
+//! the operation is stopped.
 //!
 //! The second, called AdcVirtualized, sits top of an ADC virtualizer.
 //! This capsule shares the ADC with the rest of the kernel through this
 //! virtualizer, so allows other kernel services and capsules to use the
 //! ADC. It also supports multiple processes requesting ADC samples
-//! concurrently. However, it only supports processes requesting single
-//! ADC samples: they cannot sample continuously or at high speed.
+//! concurrently. Processes can request single samples, and buffered samples
+//! if the virtualizer supports high-speed sampling. Buffered requests are
+//! served in chunks of up to `BUF_LEN` samples, round-robin between the
+//! processes, so the samples of a buffer are not contiguous in time when
+//! several processes sample concurrently. Channels whose virtual device
+//! has a periodic alarm can also be sampled at a fixed interval, by one
+//! process at a time per channel. The gain and voltage reference of each
+//! channel can be selected, if the chip supports it. If the board provides a
+//! [`SelfTestReference`], processes can also check the ADC against a known
+//! internal reference, such as VREFINT on STM32, for manufacturing tests.
 //!
 //!
 //! Usage
@@ -57,9 +73,10 @@ use core::cmp;
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
-use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer, WriteableProcessSlice};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::upcall_packing::{PackedField, PackedLayout};
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -67,19 +84,88 @@ use crate::driver;
 use crate::virtualizers::virtual_adc::Operation;
 pub const DRIVER_NUM: usize = driver::NUM::Adc as usize;
 
+/// Version of the userspace interface, returned for
+/// `kernel::syscall::ABI_VERSION_COMMAND`.
+pub const ABI_VERSION: u32 = 1;
+
+/// Encoding of the second argument of buffered sampling upcalls: the channel
+/// in the low 8 bits and the number of samples in the bits above.
+pub const UPCALL_CHANNEL_LEN: PackedLayout<2> =
+    PackedLayout::new([PackedField::new(0, 8), PackedField::new(8, 24)]);
+
+/// Encoding of the second argument of the window sampling command: the low
+/// threshold in the low 16 bits and the high threshold in the bits above.
+/// With 32-bit samples, the thresholds are the 16 most significant bits of
+/// the samples, see `window_thresholds`.
+pub const WINDOW_THRESHOLDS: PackedLayout<2> =
+    PackedLayout::new([PackedField::new(0, 16), PackedField::new(16, 16)]);
+
+/// Encoding of the second argument of the gain and reference command: the
+/// numerator of the gain in the low 8 bits, its denominator in the next 8
+/// bits, and the `hil::adc::Reference` in the 8 bits above.
+pub const GAIN_REFERENCE: PackedLayout<3> = PackedLayout::new([
+    PackedField::new(0, 8),
+    PackedField::new(8, 8),
+    PackedField::new(16, 8),
+]);
+
+/// Size in bytes of the header of the ring buffer, holding the index of the
+/// next sample the kernel writes as a little-endian `u32`.
+pub const RING_HEADER_LEN: usize = 4;
+
+/// Sampling frequency used to watch a window in software, when the ADC has
+/// no analog watchdog.
+pub const SOFTWARE_WINDOW_FREQUENCY_HZ: u32 = 10;
+
 /// Multiplexed ADC syscall driver, used by applications and capsules.
 /// Virtualized, and can be use by multiple applications at the same time;
-/// requests are queued. Does not support continuous or high-speed sampling.
+/// requests are queued. Does not support continuous single samples.
 pub struct AdcVirtualized<'a> {
     drivers: &'a [&'a dyn hil::adc::AdcChannel<'a>],
-    apps: Grant<AppSys, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    apps: Grant<AppSys, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
     current_process: OptionalCell<ProcessId>,
+    /// Process served last, to serve the queued requests round-robin.
+    last_process: OptionalCell<ProcessId>,
+    /// Buffer of the chunk of buffered samples being collected.
+    buffer: TakeCell<'static, [u16]>,
+    /// Reference sampled by the self-test.
+    self_test: OptionalCell<SelfTestReference>,
+}
+
+/// A known internal voltage, such as VREFINT or a bandgap reference, sampled
+/// to check that the ADC and its reference work.
+#[derive(Copy, Clone, Debug)]
+pub struct SelfTestReference {
+    /// Index of the channel measuring the reference.
+    pub channel: usize,
+    /// Nominal voltage of the reference.
+    pub expected_mv: usize,
+    /// Largest accepted difference between the measured and nominal
+    /// voltages.
+    pub tolerance_mv: usize,
+}
+
+impl SelfTestReference {
+    pub const fn new(channel: usize, expected_mv: usize, tolerance_mv: usize) -> Self {
+        SelfTestReference {
+            channel,
+            expected_mv,
+            tolerance_mv,
+        }
+    }
+
+    /// Whether `measured_mv` is within the tolerance, and its offset from the
+    /// nominal voltage.
+    pub fn check(&self, measured_mv: usize) -> (bool, i32) {
+        let offset = measured_mv as i32 - self.expected_mv as i32;
+        (offset.unsigned_abs() as usize <= self.tolerance_mv, offset)
+    }
 }
 
 /// ADC syscall driver, used by applications to interact with ADC.
-/// Not currently virtualized: does not share the ADC with other capsules
-/// and only one application can use it at a time. Supports continuous and
-/// high speed sampling.
+/// Not currently virtualized: does not share the ADC with other capsules.
+/// Single samples are shared between applications, while continuous and
+/// high speed sampling can only be used by one application at a time.
 pub struct AdcDedicated<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> {
     // ADC driver
     adc: &'a A,
@@ -88,11 +174,19 @@ pub struct AdcDedicated<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> {
     // ADC state
     active: Cell<bool>,
     mode: Cell<AdcMode>,
+    /// The power domain of the ADC is off.
+    powered_off: Cell<bool>,
 
     // App state
-    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<3>>,
     processid: OptionalCell<ProcessId>,
+    sampling_process: OptionalCell<ProcessId>,
     channel: Cell<usize>,
+    /// The single sample in progress is reported in millivolts.
+    millivolts: Cell<bool>,
+    /// Thresholds of the window watched in software, if the ADC has no
+    /// analog watchdog.
+    software_window: OptionalCell<(u32, u32)>,
 
     // ADC buffers
     adc_buf1: TakeCell<'static, [u16]>,
@@ -109,6 +203,13 @@ pub(crate) enum AdcMode {
     ContinuousSample = 1,
     SingleBuffer = 2,
     ContinuousBuffer = 3,
+    DifferentialSample = 4,
+    WindowSample = 5,
+    MillivoltSample = 6,
+    SelfTest = 7,
+    RingBuffer = 8,
+    Calibration = 9,
+    SampleError = 10,
 }
 
 // Datas passed by the application to us
@@ -116,6 +217,17 @@ pub struct AppSys {
     pending_command: bool,
     command: OptionalCell<Operation>,
     channel: usize,
+    // Buffered sampling state
+    frequency: u32,
+    continuous: bool,
+    samples_done: usize,
+    using_app_buf1: bool,
+    // The single sample is reported in millivolts
+    millivolts: bool,
+    // The single sample is the self-test
+    self_test: bool,
+    // Channels sampled periodically for this process, one bit per channel
+    periodic_channels: u32,
 }
 
 /// Holds buffers that the application has passed us
@@ -125,6 +237,31 @@ pub struct App {
     samples_outstanding: Cell<usize>,
     next_samples_outstanding: Cell<usize>,
     using_app_buf0: Cell<bool>,
+    /// Index of the next sample written to the ring buffer.
+    ring_head: Cell<usize>,
+    /// Channels with a queued single sample, one bit per channel.
+    pending_samples: Cell<u32>,
+    /// Channels whose queued single sample is reported in millivolts.
+    pending_millivolts: Cell<u32>,
+}
+
+impl App {
+    /// Take the queued single sample on the lowest channel, and whether it
+    /// is reported in millivolts.
+    fn take_pending_sample(&self) -> Option<(usize, bool)> {
+        let pending = self.pending_samples.get();
+        if pending == 0 {
+            return None;
+        }
+        let channel = pending.trailing_zeros() as usize;
+        self.pending_samples.set(pending & !(1 << channel));
+        Some((channel, self.pending_millivolts.get() & (1 << channel) != 0))
+    }
+
+    fn clear_pending_samples(&self) {
+        self.pending_samples.set(0);
+        self.pending_millivolts.set(0);
+    }
 }
 
 impl Default for App {
@@ -135,6 +272,9 @@ impl Default for App {
             samples_outstanding: Cell::new(0),
             next_samples_outstanding: Cell::new(0),
             using_app_buf0: Cell::new(true),
+            ring_head: Cell::new(0),
+            pending_samples: Cell::new(0),
+            pending_millivolts: Cell::new(0),
         }
     }
 }
@@ -145,6 +285,13 @@ impl Default for AppSys {
             pending_command: false,
             command: OptionalCell::empty(),
             channel: 0,
+            frequency: 0,
+            continuous: false,
+            samples_done: 0,
+            using_app_buf1: false,
+            millivolts: false,
+            self_test: false,
+            periodic_channels: 0,
         }
     }
 }
@@ -163,7 +310,7 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
     /// - `adc_buf2` - second buffer used when continuously sampling ADC
     pub fn new(
         adc: &'a A,
-        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<3>>,
         channels: &'a [<A as hil::adc::Adc<'a>>::Channel],
         adc_buf1: &'static mut [u16; 128],
         adc_buf2: &'static mut [u16; 128],
@@ -177,11 +324,15 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
             // ADC state
             active: Cell::new(false),
             mode: Cell::new(AdcMode::NoMode),
+            powered_off: Cell::new(false),
 
             // App state
             apps: grant,
             processid: OptionalCell::empty(),
+            sampling_process: OptionalCell::empty(),
             channel: Cell::new(0),
+            millivolts: Cell::new(false),
+            software_window: OptionalCell::empty(),
 
             // ADC buffers
             adc_buf1: TakeCell::new(adc_buf1),
@@ -245,25 +396,86 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         }
     }
 
-    /// Collect a single analog sample on a channel.
+    /// Collect a single analog sample on a channel for a process.
+    ///
+    /// If another single sample is in progress, the request is queued in the
+    /// process grant and started once the ADC is free.
     ///
     /// - `channel` - index into `channels` array, which channel to sample
-    fn sample(&self, channel: usize) -> Result<(), ErrorCode> {
-        // only one sample at a time
-        if self.active.get() {
-            return Err(ErrorCode::BUSY);
-        }
-
+    /// - `millivolts` - report the sample converted to millivolts
+    /// - `processid` - process requesting the sample
+    fn sample(
+        &self,
+        channel: usize,
+        millivolts: bool,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
         // convert channel index
         if channel >= self.channels.len() {
             return Err(ErrorCode::INVAL);
         }
+
+        // conversion needs the voltage reference
+        if millivolts && self.get_voltage_reference_mv().is_none() {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
+        if self.active.get() {
+            // only single samples can be queued
+            if self.mode.get() != AdcMode::SingleSample {
+                return Err(ErrorCode::BUSY);
+            }
+
+            // one outstanding sample per channel and process
+            if channel >= u32::BITS as usize {
+                return Err(ErrorCode::BUSY);
+            }
+            let bit = 1 << channel;
+            return self
+                .apps
+                .enter(processid, |app, _| {
+                    if app.pending_samples.get() & bit != 0
+                        || (self.sampling_process.contains(&processid)
+                            && self.channel.get() == channel)
+                    {
+                        Err(ErrorCode::BUSY)
+                    } else {
+                        app.pending_samples.set(app.pending_samples.get() | bit);
+                        if millivolts {
+                            app.pending_millivolts
+                                .set(app.pending_millivolts.get() | bit);
+                        } else {
+                            app.pending_millivolts
+                                .set(app.pending_millivolts.get() & !bit);
+                        }
+                        Ok(())
+                    }
+                })
+                .unwrap_or_else(|err| Err(err.into()));
+        }
+
+        self.start_sample(channel, millivolts, processid)
+    }
+
+    /// Start a single sample on a channel for a process.
+    ///
+    /// - `channel` - index into `channels` array, which channel to sample
+    /// - `millivolts` - report the sample converted to millivolts
+    /// - `processid` - process to deliver the sample to
+    fn start_sample(
+        &self,
+        channel: usize,
+        millivolts: bool,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
         let chan = &self.channels[channel];
 
         // save state for callback
         self.active.set(true);
         self.mode.set(AdcMode::SingleSample);
         self.channel.set(channel);
+        self.millivolts.set(millivolts);
+        self.sampling_process.set(processid);
 
         // start a single sample
         let res = self.adc.sample(chan);
@@ -271,6 +483,7 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
             // failure, clear state
             self.active.set(false);
             self.mode.set(AdcMode::NoMode);
+            self.sampling_process.clear();
 
             return res;
         }
@@ -278,6 +491,62 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         Ok(())
     }
 
+    /// Start the next queued single sample, if the ADC is free.
+    ///
+    /// Processes are served round-robin, starting after the process whose
+    /// sample just completed, and the channels of a process in increasing
+    /// order.
+    fn run_next_sample(&self) {
+        let last = self.sampling_process.take();
+        while !self.active.get() {
+            let mut first = None;
+            let mut next = None;
+            let mut after_last = last.is_none();
+            for cntr in self.apps.iter() {
+                let processid = cntr.processid();
+                if cntr.enter(|app, _| app.pending_samples.get() != 0) {
+                    if after_last {
+                        next = Some(processid);
+                        break;
+                    }
+                    first = first.or(Some(processid));
+                }
+                after_last = after_last || last == Some(processid);
+            }
+
+            match next.or(first) {
+                Some(processid) => {
+                    let request = self
+                        .apps
+                        .enter(processid, |app, _| app.take_pending_sample())
+                        .ok()
+                        .flatten();
+                    // If the sample cannot be started, the request is
+                    // dropped, the error is reported to the process so it
+                    // does not wait for the sample, and the next process is
+                    // tried.
+                    if let Some((channel, millivolts)) = request {
+                        if let Err(err) = self.start_sample(channel, millivolts, processid) {
+                            let _ = self.apps.enter(processid, |_app, upcalls| {
+                                upcalls
+                                    .schedule_upcall(
+                                        0,
+                                        (
+                                            AdcMode::SampleError as usize,
+                                            channel,
+                                            kernel::errorcode::into_statuscode(Err(err)),
+                                        ),
+                                    )
+                                    .ok();
+                            });
+                        }
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
     /// Collect repeated single analog samples on a channel.
     ///
     /// - `channel` - index into `channels` array, which channel to sample
@@ -312,6 +581,58 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         Ok(())
     }
 
+    /// Watch a channel until a sample falls outside of a window.
+    ///
+    /// Uses the analog watchdog of the ADC if it has one, and otherwise
+    /// samples continuously at `SOFTWARE_WINDOW_FREQUENCY_HZ` and compares
+    /// the samples in the capsule. Only the first sample outside of the
+    /// window is reported.
+    ///
+    /// - `channel` - index into `channels` array, which channel to sample
+    /// - `low` - lowest sample inside the window
+    /// - `high` - highest sample inside the window
+    fn sample_window(&self, channel: usize, low: u32, high: u32) -> Result<(), ErrorCode> {
+        // only one sample at a time
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // convert channel index
+        if channel >= self.channels.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        if low > high {
+            return Err(ErrorCode::INVAL);
+        }
+        let chan = &self.channels[channel];
+
+        // save state for callback
+        self.active.set(true);
+        self.mode.set(AdcMode::WindowSample);
+        self.channel.set(channel);
+        self.software_window.clear();
+
+        let res = match self.adc.sample_window(chan, low, high) {
+            Err(ErrorCode::NOSUPPORT) => {
+                // no analog watchdog, compare the samples ourselves
+                self.software_window.set((low, high));
+                self.adc
+                    .sample_continuous(chan, SOFTWARE_WINDOW_FREQUENCY_HZ)
+            }
+            res => res,
+        };
+        if res != Ok(()) {
+            // failure, clear state
+            self.active.set(false);
+            self.mode.set(AdcMode::NoMode);
+            self.software_window.clear();
+
+            return res;
+        }
+
+        Ok(())
+    }
+
     /// Collect a buffer-full of analog samples.
     ///
     /// Samples are collected into the first app buffer provided. The number of
@@ -364,42 +685,47 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
                     app.app_buf_offset.set(0);
                     self.channel.set(channel);
                     // start a continuous sample
-                    let res = self.adc_buf1.take().map_or(Err(ErrorCode::BUSY), |buf1| {
-                        self.adc_buf2
-                            .take()
-                            .map_or(Err(ErrorCode::BUSY), move |buf2| {
-                                // determine request length
-                                let request_len = app_buf_length / 2;
-                                let len1;
-                                let len2;
-                                if request_len <= buf1.len() {
-                                    len1 = app_buf_length / 2;
-                                    len2 = 0;
-                                } else if request_len <= (buf1.len() + buf2.len()) {
-                                    len1 = buf1.len();
-                                    len2 = request_len - buf1.len();
-                                } else {
-                                    len1 = buf1.len();
-                                    len2 = buf2.len();
-                                }
+                    let res = match (self.adc_buf1.take(), self.adc_buf2.take()) {
+                        (Some(buf1), Some(buf2)) => {
+                            // determine request length
+                            let request_len = app_buf_length / 2;
+                            let len1;
+                            let len2;
+                            if request_len <= buf1.len() {
+                                len1 = app_buf_length / 2;
+                                len2 = 0;
+                            } else if request_len <= (buf1.len() + buf2.len()) {
+                                len1 = buf1.len();
+                                len2 = request_len - buf1.len();
+                            } else {
+                                len1 = buf1.len();
+                                len2 = buf2.len();
+                            }
 
-                                // begin sampling
-                                app.using_app_buf0.set(true);
-                                app.samples_remaining.set(request_len - len1 - len2);
-                                app.samples_outstanding.set(len1 + len2);
-                                self.adc
-                                    .sample_highspeed(chan, frequency, buf1, len1, buf2, len2)
-                                    .map_or_else(
-                                        |(ecode, buf1, buf2)| {
-                                            // store buffers again
-                                            self.replace_buffer(buf1);
-                                            self.replace_buffer(buf2);
-                                            Err(ecode)
-                                        },
-                                        |()| Ok(()),
-                                    )
-                            })
-                    });
+                            // begin sampling
+                            app.using_app_buf0.set(true);
+                            app.samples_remaining.set(request_len - len1 - len2);
+                            app.samples_outstanding.set(len1 + len2);
+                            self.adc
+                                .sample_highspeed(chan, frequency, buf1, len1, buf2, len2)
+                                .map_or_else(
+                                    |(ecode, buf1, buf2)| {
+                                        // store buffers again
+                                        self.replace_buffer(buf1);
+                                        self.replace_buffer(buf2);
+                                        Err(ecode)
+                                    },
+                                    |()| Ok(()),
+                                )
+                        }
+                        // Both buffers are back when the ADC is idle, so a
+                        // missing one is an internal error.
+                        (buf1, buf2) => {
+                            buf1.map(|buf| self.replace_buffer(buf));
+                            buf2.map(|buf| self.replace_buffer(buf));
+                            Err(ErrorCode::FAIL)
+                        }
+                    };
                     res
                 })
                 .map_err(|err| {
@@ -492,56 +818,61 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
                     app.app_buf_offset.set(0);
                     self.channel.set(channel);
                     // start a continuous sample
-                    self.adc_buf1.take().map_or(Err(ErrorCode::BUSY), |buf1| {
-                        self.adc_buf2
-                            .take()
-                            .map_or(Err(ErrorCode::BUSY), move |buf2| {
-                                // determine request lengths
-                                let samples_needed = app_buf_length / 2;
-                                let next_samples_needed = next_app_buf_length / 2;
-
-                                // determine request lengths
-                                let len1;
-                                let len2;
-                                if samples_needed <= buf1.len() {
-                                    // we can fit the entire app_buffer request in the first
-                                    // buffer. The second buffer will be used for the next
-                                    // app_buffer
-                                    len1 = samples_needed;
-                                    len2 = cmp::min(next_samples_needed, buf2.len());
-                                    app.samples_remaining.set(0);
-                                    app.samples_outstanding.set(len1);
-                                } else if samples_needed <= (buf1.len() + buf2.len()) {
-                                    // we can fit the entire app_buffer request between the two
-                                    // buffers
-                                    len1 = buf1.len();
-                                    len2 = samples_needed - buf1.len();
-                                    app.samples_remaining.set(0);
-                                    app.samples_outstanding.set(len1 + len2);
-                                } else {
-                                    // the app_buffer is larger than both buffers, so just
-                                    // request max lengths
-                                    len1 = buf1.len();
-                                    len2 = buf2.len();
-                                    app.samples_remaining.set(samples_needed - len1 - len2);
-                                    app.samples_outstanding.set(len1 + len2);
-                                }
+                    match (self.adc_buf1.take(), self.adc_buf2.take()) {
+                        (Some(buf1), Some(buf2)) => {
+                            // determine request lengths
+                            let samples_needed = app_buf_length / 2;
+                            let next_samples_needed = next_app_buf_length / 2;
+
+                            // determine request lengths
+                            let len1;
+                            let len2;
+                            if samples_needed <= buf1.len() {
+                                // we can fit the entire app_buffer request in the first
+                                // buffer. The second buffer will be used for the next
+                                // app_buffer
+                                len1 = samples_needed;
+                                len2 = cmp::min(next_samples_needed, buf2.len());
+                                app.samples_remaining.set(0);
+                                app.samples_outstanding.set(len1);
+                            } else if samples_needed <= (buf1.len() + buf2.len()) {
+                                // we can fit the entire app_buffer request between the two
+                                // buffers
+                                len1 = buf1.len();
+                                len2 = samples_needed - buf1.len();
+                                app.samples_remaining.set(0);
+                                app.samples_outstanding.set(len1 + len2);
+                            } else {
+                                // the app_buffer is larger than both buffers, so just
+                                // request max lengths
+                                len1 = buf1.len();
+                                len2 = buf2.len();
+                                app.samples_remaining.set(samples_needed - len1 - len2);
+                                app.samples_outstanding.set(len1 + len2);
+                            }
 
-                                // begin sampling
-                                app.using_app_buf0.set(true);
-                                self.adc
-                                    .sample_highspeed(chan, frequency, buf1, len1, buf2, len2)
-                                    .map_or_else(
-                                        |(ecode, buf1, buf2)| {
-                                            // store buffers again
-                                            self.replace_buffer(buf1);
-                                            self.replace_buffer(buf2);
-                                            Err(ecode)
-                                        },
-                                        |()| Ok(()),
-                                    )
-                            })
-                    })
+                            // begin sampling
+                            app.using_app_buf0.set(true);
+                            self.adc
+                                .sample_highspeed(chan, frequency, buf1, len1, buf2, len2)
+                                .map_or_else(
+                                    |(ecode, buf1, buf2)| {
+                                        // store buffers again
+                                        self.replace_buffer(buf1);
+                                        self.replace_buffer(buf2);
+                                        Err(ecode)
+                                    },
+                                    |()| Ok(()),
+                                )
+                        }
+                        // Both buffers are back when the ADC is idle, so a
+                        // missing one is an internal error.
+                        (buf1, buf2) => {
+                            buf1.map(|buf| self.replace_buffer(buf));
+                            buf2.map(|buf| self.replace_buffer(buf));
+                            Err(ErrorCode::FAIL)
+                        }
+                    }
                 })
                 .map_err(|err| {
                     if err == kernel::process::Error::NoSuchApp
@@ -574,6 +905,151 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         ret
     }
 
+    /// Collect analog samples continuously into a ring buffer.
+    ///
+    /// The third "allowed" application buffer is used as a circular buffer:
+    /// after a `RING_HEADER_LEN` bytes header holding the index of the next
+    /// sample to write, samples are written in a loop, overwriting the
+    /// oldest ones. The kernel updates the index and performs an upcall after
+    /// each chunk of samples, and sampling continues until stopped, without
+    /// the application having to swap buffers.
+    ///
+    /// - `channel` - index into `channels` array, which channel to sample
+    /// - `frequency` - number of samples per second to collect
+    fn sample_buffer_ring(&self, channel: usize, frequency: u32) -> Result<(), ErrorCode> {
+        // only one sample at a time
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // convert channel index
+        if channel >= self.channels.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        let chan = &self.channels[channel];
+
+        // cannot sample without room for the header and one sample
+        let capacity = self.processid.map_or(0, |id| {
+            self.apps
+                .enter(id, |app, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(2)
+                        .and_then(|ring| {
+                            ring.mut_enter(|ring| {
+                                // restart at the beginning of the ring
+                                app.ring_head.set(0);
+                                write_ring_head(ring, 0);
+                                ring.len().saturating_sub(RING_HEADER_LEN) / 2
+                            })
+                        })
+                        .unwrap_or(0)
+                })
+                .map_err(|err| {
+                    if err == kernel::process::Error::NoSuchApp
+                        || err == kernel::process::Error::InactiveApp
+                    {
+                        self.processid.clear();
+                    }
+                })
+                .unwrap_or(0)
+        });
+        if capacity == 0 {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        // save state for callback
+        self.active.set(true);
+        self.mode.set(AdcMode::RingBuffer);
+        self.channel.set(channel);
+
+        // chunks do not overlap in the ring, so that each upcall reports
+        // samples the application has not seen yet
+        let ret = match (self.adc_buf1.take(), self.adc_buf2.take()) {
+            (Some(buf1), Some(buf2)) => {
+                let len1 = cmp::min(capacity, buf1.len());
+                let len2 = cmp::min(capacity, buf2.len());
+                self.adc
+                    .sample_highspeed(chan, frequency, buf1, len1, buf2, len2)
+                    .map_err(|(ecode, buf1, buf2)| {
+                        // store buffers again
+                        self.replace_buffer(buf1);
+                        self.replace_buffer(buf2);
+                        ecode
+                    })
+            }
+            // Both buffers are back when the ADC is idle, so a missing one
+            // is an internal error.
+            (buf1, buf2) => {
+                buf1.map(|buf| self.replace_buffer(buf));
+                buf2.map(|buf| self.replace_buffer(buf));
+                Err(ErrorCode::FAIL)
+            }
+        };
+        if ret != Ok(()) {
+            // failure, clear state
+            self.active.set(false);
+            self.mode.set(AdcMode::NoMode);
+        }
+        ret
+    }
+
+    /// Copy a chunk of samples into the ring buffer of the sampling process,
+    /// update its head index and notify it, then give the ADC a buffer for
+    /// the next chunk.
+    ///
+    /// Returns `false` if the process is gone and sampling must stop.
+    ///
+    /// - `samples` - samples collected by the ADC
+    fn ring_samples_ready(&self, samples: &[u16]) -> bool {
+        self.processid.map_or(false, |id| {
+            self.apps
+                .enter(id, |app, kernel_data| {
+                    let ring = match kernel_data.get_readwrite_processbuffer(2) {
+                        Ok(ring) => ring,
+                        Err(_) => return,
+                    };
+                    let (head, capacity) = ring
+                        .mut_enter(|ring| {
+                            let capacity = ring.len().saturating_sub(RING_HEADER_LEN) / 2;
+                            let head = ring_copy_samples(
+                                &ring[RING_HEADER_LEN.min(ring.len())..],
+                                capacity,
+                                app.ring_head.get(),
+                                samples,
+                            );
+                            write_ring_head(ring, head);
+                            (head, capacity)
+                        })
+                        .unwrap_or((0, 0));
+                    app.ring_head.set(head);
+
+                    let len_chan = UPCALL_CHANNEL_LEN.pack([self.channel.get(), samples.len()]);
+                    kernel_data
+                        .schedule_upcall(0, (AdcMode::RingBuffer as usize, len_chan, head))
+                        .ok();
+
+                    // a new ring may be smaller, keep the chunks within it
+                    self.take_and_map_buffer(|adc_buf| {
+                        let request_len = cmp::min(cmp::max(capacity, 1), adc_buf.len());
+                        let _ =
+                            self.adc
+                                .provide_buffer(adc_buf, request_len)
+                                .map_err(|(_, buf)| {
+                                    self.replace_buffer(buf);
+                                });
+                    });
+                })
+                .map_err(|err| {
+                    if err == kernel::process::Error::NoSuchApp
+                        || err == kernel::process::Error::InactiveApp
+                    {
+                        self.processid.clear();
+                    }
+                })
+                .is_ok()
+        })
+    }
+
     /// Stops sampling the ADC.
     ///
     /// Any active operation by the ADC is canceled. No additional callbacks
@@ -584,6 +1060,19 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
             return Ok(());
         }
 
+        if self.mode.get() == AdcMode::Calibration {
+            // the calibration cannot be interrupted
+            return Err(ErrorCode::BUSY);
+        }
+
+        if self.mode.get() == AdcMode::WindowSample {
+            // no buffers to retrieve
+            self.active.set(false);
+            self.mode.set(AdcMode::NoMode);
+            self.software_window.clear();
+            return self.adc.stop_sampling();
+        }
+
         // clean up state
         self.processid.map_or(Err(ErrorCode::FAIL), |id| {
             self.apps
@@ -623,6 +1112,65 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         })
     }
 
+    /// Stops sampling the ADC for a process.
+    ///
+    /// Drops the single sample the process has queued, or cancels the one in
+    /// progress for it. Otherwise, stops the continuous or buffered sampling
+    /// of the process owning the ADC.
+    ///
+    /// - `processid` - process requesting to stop sampling
+    fn stop_process_sampling(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let _ = self
+            .apps
+            .enter(processid, |app, _| app.clear_pending_samples());
+
+        if self.active.get() && self.mode.get() == AdcMode::SingleSample {
+            if !self.sampling_process.contains(&processid) {
+                return Ok(());
+            }
+
+            self.active.set(false);
+            self.mode.set(AdcMode::NoMode);
+            let rc = self.adc.stop_sampling();
+            self.run_next_sample();
+            return rc;
+        }
+
+        self.claim(processid)?;
+        self.stop_sampling()
+    }
+
+    /// Take ownership of the ADC for continuous, buffered and window
+    /// sampling, and for changing its configuration.
+    ///
+    /// A process owns the ADC only while one of its exclusive operations is
+    /// running: claiming fails with `NOMEM` if another process is sampling
+    /// continuously, into buffers or in a window, and succeeds otherwise.
+    /// Single samples in progress do not hold the ADC, the operation started
+    /// by the new owner then fails with `BUSY`.
+    ///
+    /// - `processid` - process requesting ownership
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let exclusive = self.active.get() && self.mode.get() != AdcMode::SingleSample;
+        if !exclusive {
+            self.processid.set(processid);
+            return Ok(());
+        }
+
+        // The operation in progress belongs to its owner even if the owner
+        // no longer exists (it may have crashed): the operation is then
+        // stopped when its next upcall cannot be delivered.
+        if self
+            .processid
+            .map_or(true, |owning_app| owning_app == processid)
+        {
+            self.processid.set(processid);
+            Ok(())
+        } else {
+            Err(ErrorCode::NOMEM)
+        }
+    }
+
     fn get_resolution_bits(&self) -> usize {
         self.adc.get_resolution_bits()
     }
@@ -630,6 +1178,50 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
     fn get_voltage_reference_mv(&self) -> Option<usize> {
         self.adc.get_voltage_reference_mv()
     }
+
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        self.adc.get_sampling_frequency_hz()
+    }
+
+    /// Set the number of conversions averaged in each sample. The setting
+    /// applies to the samples of all processes, so it cannot change during a
+    /// sampling operation.
+    ///
+    /// - `ratio` - oversampling ratio, 1 to disable oversampling
+    fn set_oversampling(&self, ratio: usize) -> Result<(), ErrorCode> {
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.adc.set_oversampling(ratio)
+    }
+
+    /// Set the resolution of the conversions of all the channels. Like the
+    /// oversampling ratio, it cannot change during a sampling operation.
+    ///
+    /// - `channel` - index of a channel of the ADC
+    /// - `bits` - number of bits of the conversions
+    fn set_resolution_bits(&self, channel: usize, bits: usize) -> Result<(), ErrorCode> {
+        if channel >= self.channels.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.adc.set_resolution_bits(bits)
+    }
+
+    /// Run the hardware calibration of the ADC. The calibration applies to
+    /// the samples of all processes, so it cannot run during a sampling
+    /// operation. Completion is reported with an upcall.
+    fn calibrate(&self) -> Result<(), ErrorCode> {
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.adc.calibrate()?;
+        self.active.set(true);
+        self.mode.set(AdcMode::Calibration);
+        Ok(())
+    }
 }
 
 /// Functions to create, initialize, and interact with the virtualized ADC
@@ -637,17 +1229,27 @@ impl<'a> AdcVirtualized<'a> {
     /// Create a new `Adc` application interface.
     ///
     /// - `drivers` - Virtual ADC drivers to provide application access to
+    /// - `buffer` - buffer used to collect the chunks of buffered samples
     pub fn new(
         drivers: &'a [&'a dyn hil::adc::AdcChannel<'a>],
-        grant: Grant<AppSys, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+        grant: Grant<AppSys, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
+        buffer: &'static mut [u16],
     ) -> AdcVirtualized<'a> {
         AdcVirtualized {
             drivers: drivers,
             apps: grant,
             current_process: OptionalCell::empty(),
+            last_process: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            self_test: OptionalCell::empty(),
         }
     }
 
+    /// Set the reference sampled by the self-test command.
+    pub fn set_self_test_reference(&self, reference: SelfTestReference) {
+        self.self_test.set(reference);
+    }
+
     /// Enqueue the command to be executed when the ADC is available.
     fn enqueue_command(
         &self,
@@ -658,12 +1260,13 @@ impl<'a> AdcVirtualized<'a> {
         if channel < self.drivers.len() {
             if self.current_process.is_none() {
                 self.current_process.set(processid);
-                let r = self.call_driver(command, channel);
+                self.last_process.set(processid);
+                let r = self.call_driver(command, channel, processid);
                 if r != Ok(()) {
                     self.current_process.clear();
                 }
                 self.run_next_command();
-                Ok(())
+                r
             } else {
                 match self
                     .apps
@@ -688,42 +1291,260 @@ impl<'a> AdcVirtualized<'a> {
         }
     }
 
-    /// Run next command in queue, when available
-    fn run_next_command(&self) {
-        let mut command = Operation::OneSample;
-        let mut channel = 0;
-        for app in self.apps.iter() {
-            let processid = app.processid();
-            let start_command = app.enter(|app, _| {
-                if app.pending_command {
-                    app.pending_command = false;
-                    app.command.take().map(|c| {
-                        command = c;
-                    });
-                    channel = app.channel;
-                    self.current_process.set(processid);
-                    true
-                } else {
-                    false
+    /// Enqueue a single sample, on a differential channel if `differential`
+    /// and on a single-ended channel otherwise. Single-ended samples are
+    /// converted to millivolts if `millivolts`.
+    fn sample(
+        &self,
+        channel: usize,
+        differential: bool,
+        millivolts: bool,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
+        match self.drivers.get(channel) {
+            Some(driver) if driver.is_differential() != differential => Err(ErrorCode::INVAL),
+            Some(driver) if millivolts && driver.get_voltage_reference_mv().is_none() => {
+                Err(ErrorCode::NOSUPPORT)
+            }
+            Some(_) => {
+                self.enqueue_command(Operation::OneSample, channel, processid)?;
+                // The sample completes in a later callback, so the flag can
+                // be set once the request is accepted.
+                self.apps
+                    .enter(processid, |app, _| {
+                        app.millivolts = millivolts;
+                        app.self_test = false;
+                    })
+                    .map_err(ErrorCode::from)
+            }
+            None => Err(ErrorCode::NODEVICE),
+        }
+    }
+
+    /// Enqueue a sample of the self-test reference, reported with whether it
+    /// is within the tolerance and its offset.
+    fn self_test(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let reference = self.self_test.get().ok_or(ErrorCode::NOSUPPORT)?;
+        self.sample(reference.channel, false, true, processid)?;
+        self.apps
+            .enter(processid, |app, _| app.self_test = true)
+            .map_err(ErrorCode::from)
+    }
+
+    /// Enqueue buffered sampling into the buffers allowed by the process,
+    /// once if not `continuous`.
+    fn sample_buffer(
+        &self,
+        channel: usize,
+        frequency: usize,
+        continuous: bool,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
+        match self.drivers.get(channel) {
+            Some(driver) if driver.is_differential() => return Err(ErrorCode::INVAL),
+            Some(_) => {}
+            None => return Err(ErrorCode::NODEVICE),
+        }
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                let app_buf_len = |index| {
+                    kernel_data
+                        .get_readwrite_processbuffer(index)
+                        .map_or(0, |buf| buf.len() / 2)
+                };
+                if app_buf_len(0) == 0 || (continuous && app_buf_len(1) == 0) {
+                    return Err(ErrorCode::NOMEM);
                 }
-            });
-            if start_command {
-                match self.call_driver(command, channel) {
-                    Err(_) => {
-                        self.current_process.clear();
-                    }
-                    Ok(()) => {
+                if app.pending_command || self.current_process.contains(&processid) {
+                    return Err(ErrorCode::BUSY);
+                }
+                app.frequency = frequency as u32;
+                app.continuous = continuous;
+                app.samples_done = 0;
+                app.using_app_buf1 = false;
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.enqueue_command(Operation::Buffer, channel, processid)
+    }
+
+    /// Stop the buffered sampling of a process. The samples already
+    /// collected in the current app buffer are dropped.
+    fn stop_buffer(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let channel = self.apps.enter(processid, |app, _| {
+            app.continuous = false;
+            app.samples_done = 0;
+            if app.command.contains(&Operation::Buffer) {
+                app.pending_command = false;
+                app.command.clear();
+            }
+            app.channel
+        })?;
+        if self.current_process.contains(&processid) && self.buffer.is_none() {
+            // The chunk buffer is returned without samples, which ends the
+            // request.
+            let _ = self.drivers[channel].stop_sampling();
+        }
+        Ok(())
+    }
+
+    /// Sample `channel` every `interval_ms` milliseconds for the process,
+    /// with the alarm of the virtual ADC device. A channel is sampled
+    /// periodically for a single process at a time.
+    fn sample_periodic(
+        &self,
+        channel: usize,
+        interval_ms: usize,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
+        let driver = self.drivers.get(channel).ok_or(ErrorCode::NODEVICE)?;
+        if channel >= u32::BITS as usize {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let bit = 1 << channel;
+        if self
+            .periodic_owner(channel)
+            .map_or(false, |owner| owner != processid)
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        driver.sample_periodic(interval_ms as u32, channel)?;
+        self.apps
+            .enter(processid, |app, _| app.periodic_channels |= bit)
+            .map_err(ErrorCode::from)
+    }
+
+    /// Stop the periodic sampling of `channel` by the process.
+    fn stop_periodic(&self, channel: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        let driver = self.drivers.get(channel).ok_or(ErrorCode::NODEVICE)?;
+        if self.periodic_owner(channel) != Some(processid) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.apps.enter(processid, |app, _| {
+            app.periodic_channels &= !(1 << channel);
+        })?;
+        driver.stop_periodic()
+    }
+
+    /// Whether a process other than `processid` has samples in flight: a
+    /// request in progress or queued, continuous buffered sampling, or
+    /// periodic sampling. The resolution, gain and reference are shared, so
+    /// changing them would change the samples of that process.
+    fn other_process_sampling(&self, processid: ProcessId) -> bool {
+        self.current_process
+            .map_or(false, |current| current != processid)
+            || self.apps.iter().any(|cntr| {
+                cntr.processid() != processid
+                    && cntr.enter(|app, _| {
+                        app.pending_command || app.continuous || app.periodic_channels != 0
+                    })
+            })
+    }
+
+    /// The process sampling `channel` periodically.
+    fn periodic_owner(&self, channel: usize) -> Option<ProcessId> {
+        if channel >= u32::BITS as usize {
+            return None;
+        }
+        self.apps.iter().find_map(|cntr| {
+            let processid = cntr.processid();
+            cntr.enter(|app, _| app.periodic_channels & (1 << channel) != 0)
+                .then_some(processid)
+        })
+    }
+
+    /// Run next command in queue, when available.
+    ///
+    /// Processes are served round-robin, starting after the process served
+    /// last.
+    fn run_next_command(&self) {
+        while self.current_process.is_none() {
+            let last = self.last_process.get();
+            let mut first = None;
+            let mut next = None;
+            let mut after_last = last.is_none();
+            for cntr in self.apps.iter() {
+                let processid = cntr.processid();
+                if cntr.enter(|app, _| app.pending_command) {
+                    if after_last {
+                        next = Some(processid);
                         break;
                     }
+                    first = first.or(Some(processid));
+                }
+                after_last = after_last || last == Some(processid);
+            }
+
+            let processid = match next.or(first) {
+                Some(processid) => processid,
+                None => break,
+            };
+            let command = self
+                .apps
+                .enter(processid, |app, _| {
+                    app.pending_command = false;
+                    app.command.take().map(|command| (command, app.channel))
+                })
+                .ok()
+                .flatten();
+            if let Some((command, channel)) = command {
+                self.current_process.set(processid);
+                self.last_process.set(processid);
+                // If the command cannot be started, it is dropped, the error
+                // is reported to the process, and the next process is tried.
+                if let Err(err) = self.call_driver(command, channel, processid) {
+                    self.current_process.clear();
+                    self.report_sample_error(processid, channel, err);
                 }
             }
         }
     }
 
-    /// Request the sample from the specified channel
-    fn call_driver(&self, command: Operation, channel: usize) -> Result<(), ErrorCode> {
+    /// Report to the process that its queued request on `channel` failed.
+    fn report_sample_error(&self, processid: ProcessId, channel: usize, err: ErrorCode) {
+        let _ = self.apps.enter(processid, |app, upcalls| {
+            app.self_test = false;
+            upcalls
+                .schedule_upcall(
+                    0,
+                    (
+                        AdcMode::SampleError as usize,
+                        channel,
+                        kernel::errorcode::into_statuscode(Err(err)),
+                    ),
+                )
+                .ok();
+        });
+    }
+
+    /// Request the sample, or the next chunk of buffered samples, from the
+    /// specified channel
+    fn call_driver(
+        &self,
+        command: Operation,
+        channel: usize,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
         match command {
             Operation::OneSample => self.drivers[channel].sample(),
+            // Periodic samples are started by the alarms of the devices.
+            Operation::Periodic => Err(ErrorCode::INVAL),
+            Operation::Buffer => {
+                let (frequency, remaining) = self.apps.enter(processid, |app, kernel_data| {
+                    let app_buf_len = kernel_data
+                        .get_readwrite_processbuffer(app.using_app_buf1 as usize)
+                        .map_or(0, |buf| buf.len() / 2);
+                    (app.frequency, app_buf_len.saturating_sub(app.samples_done))
+                })?;
+                let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+                let length = cmp::min(remaining, buffer.len());
+                self.drivers[channel]
+                    .sample_buffer(frequency, buffer, length)
+                    .map_err(|(err, buffer)| {
+                        self.buffer.replace(buffer);
+                        err
+                    })
+            }
         }
     }
 }
@@ -737,37 +1558,31 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::Client
     /// Collects the sample and provides a callback to the application.
     ///
     /// - `sample` - analog sample value
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
         let mut calledback = false;
         if self.active.get() && self.mode.get() == AdcMode::SingleSample {
             // single sample complete, clean up state
             self.active.set(false);
             self.mode.set(AdcMode::NoMode);
 
-            // perform callback
-
-            self.processid.map(|id| {
-                self.apps
-                    .enter(id, |_app, upcalls| {
-                        calledback = true;
-                        upcalls
-                            .schedule_upcall(
-                                0,
-                                (
-                                    AdcMode::SingleSample as usize,
-                                    self.channel.get(),
-                                    sample as usize,
-                                ),
-                            )
-                            .ok();
-                    })
-                    .map_err(|err| {
-                        if err == kernel::process::Error::NoSuchApp
-                            || err == kernel::process::Error::InactiveApp
-                        {
-                            self.processid.clear();
-                        }
+            let (mode, value) = if self.millivolts.get() {
+                self.adc
+                    .sample_to_mv(sample)
+                    .map_or((AdcMode::SingleSample, sample as usize), |mv| {
+                        (AdcMode::MillivoltSample, mv)
                     })
+            } else {
+                (AdcMode::SingleSample, sample as usize)
+            };
+
+            // perform callback to the process that requested the sample
+            self.sampling_process.map(|id| {
+                let _ = self.apps.enter(id, |_app, upcalls| {
+                    calledback = true;
+                    upcalls
+                        .schedule_upcall(0, (mode as usize, self.channel.get(), value))
+                        .ok();
+                });
             });
         } else if self.active.get() && self.mode.get() == AdcMode::ContinuousSample {
             // sample ready in continuous sampling operation, keep state
@@ -796,6 +1611,38 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::Client
                         }
                     })
             });
+        } else if self.active.get() && self.mode.get() == AdcMode::WindowSample {
+            let inside = self
+                .software_window
+                .map_or(false, |(low, high)| low <= sample && sample <= high);
+            if inside {
+                // keep watching
+                return;
+            }
+
+            // the window was left, the operation is complete
+            self.active.set(false);
+            self.mode.set(AdcMode::NoMode);
+            if self.software_window.take().is_some() {
+                let _ = self.adc.stop_sampling();
+            }
+
+            // perform callback
+            self.processid.map(|id| {
+                let _ = self.apps.enter(id, |_app, upcalls| {
+                    calledback = true;
+                    upcalls
+                        .schedule_upcall(
+                            0,
+                            (
+                                AdcMode::WindowSample as usize,
+                                self.channel.get(),
+                                sample as usize,
+                            ),
+                        )
+                        .ok();
+                });
+            });
         }
         if !calledback {
             // operation probably canceled. Make sure state is consistent. No
@@ -807,6 +1654,57 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::Client
             // continuous mode.
             let _ = self.adc.stop_sampling();
         }
+
+        // Serve the next process waiting for a single sample.
+        self.run_next_sample();
+    }
+
+    /// Calibration complete: notify the process that requested it.
+    ///
+    /// - `result` - whether the calibration succeeded
+    fn calibration_done(&self, result: Result<(), ErrorCode>) {
+        if !self.active.get() || self.mode.get() != AdcMode::Calibration {
+            // calibration started by the kernel
+            return;
+        }
+        self.active.set(false);
+        self.mode.set(AdcMode::NoMode);
+
+        self.processid.map(|id| {
+            let _ = self.apps.enter(id, |_app, upcalls| {
+                upcalls
+                    .schedule_upcall(
+                        0,
+                        (
+                            AdcMode::Calibration as usize,
+                            kernel::errorcode::into_statuscode(result),
+                            0,
+                        ),
+                    )
+                    .ok();
+            });
+        });
+
+        // Serve the next process waiting for a single sample.
+        self.run_next_sample();
+    }
+}
+
+/// Power domain notifications
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::power::PowerDependent
+    for AdcDedicated<'a, A>
+{
+    /// The ADC can only be switched off when it is not sampling.
+    fn powering_off(&self) -> Result<(), ErrorCode> {
+        if self.active.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.powered_off.set(true);
+        Ok(())
+    }
+
+    fn powered_on(&self) {
+        self.powered_off.set(false);
     }
 }
 
@@ -833,7 +1731,12 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedC
         let buffer_with_samples = self.replace_buffer(buf);
 
         // do we expect a buffer?
-        if self.active.get()
+        if self.active.get() && self.mode.get() == AdcMode::RingBuffer {
+            unexpected_state = !buffer_with_samples.map_or(false, |adc_buf| {
+                let length = cmp::min(length, adc_buf.len());
+                self.ring_samples_ready(&adc_buf[..length])
+            });
+        } else if self.active.get()
             && (self.mode.get() == AdcMode::SingleBuffer
                 || self.mode.get() == AdcMode::ContinuousBuffer)
         {
@@ -1010,35 +1913,11 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedC
                         {
                             let app_buf = if use0 { &app_buf0 } else { &app_buf1 };
 
-                            // next we should copy bytes to the app buffer
+                            // next we should copy the samples to the app buffer
                             let _ = app_buf.mut_enter(|app_buf| {
-                                // Copy bytes to app buffer by iterating over the
-                                // data.
                                 buffer_with_samples.map(|adc_buf| {
-                                    // The `for` commands:
-                                    //  * `chunks_mut`: get sets of two bytes from the app
-                                    //                  buffer
-                                    //  * `skip`: skips the already written bytes from the
-                                    //            app buffer
-                                    //  * `zip`: ties that iterator to an iterator on the
-                                    //           adc buffer, limiting iteration length to
-                                    //           the minimum of each of their lengths
-                                    //  * `take`: limits us to the minimum of buffer lengths
-                                    //            or sample length
-                                    // We then split each sample into its two bytes and copy
-                                    // them to the app buffer
-                                    for (chunk, &sample) in app_buf
-                                        .chunks(2)
-                                        .skip(skip_amt)
-                                        .zip(adc_buf.iter())
-                                        .take(length)
-                                    {
-                                        let mut val = sample;
-                                        for byte in chunk.iter() {
-                                            byte.set((val & 0xFF) as u8);
-                                            val >>= 8;
-                                        }
-                                    }
+                                    let length = cmp::min(length, adc_buf.len());
+                                    copy_samples(app_buf, skip_amt, &adc_buf[..length]);
                                 });
                             });
                         }
@@ -1056,7 +1935,8 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedC
                         // if the app_buffer is filled, perform callback
                         if perform_callback {
                             // actually schedule the callback
-                            let len_chan = ((buf_len / 2) << 8) | (self.channel.get() & 0xFF);
+                            let len_chan =
+                                UPCALL_CHANNEL_LEN.pack([self.channel.get(), buf_len / 2]);
                             kernel_data
                                 .schedule_upcall(
                                     0,
@@ -1139,13 +2019,66 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedC
     }
 }
 
+/// Copy `samples` into the ring of `capacity` samples stored in `ring`,
+/// starting at sample `head` and wrapping around at the end of the ring.
+/// Only the last `capacity` samples are kept if there are more. Returns the
+/// index of the sample following the last one copied.
+fn ring_copy_samples(
+    ring: &WriteableProcessSlice,
+    capacity: usize,
+    head: usize,
+    samples: &[u16],
+) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+    let samples = &samples[samples.len().saturating_sub(capacity)..];
+    let head = head % capacity;
+    let first = cmp::min(samples.len(), capacity - head);
+    copy_samples(ring, head, &samples[..first]);
+    copy_samples(ring, 0, &samples[first..]);
+    (head + samples.len()) % capacity
+}
+
+/// Write the index of the next sample into the header of a ring buffer.
+fn write_ring_head(ring: &WriteableProcessSlice, head: usize) {
+    if let Some(header) = ring.get(0..RING_HEADER_LEN) {
+        header.copy_from_slice(&(head as u32).to_le_bytes());
+    }
+}
+
+/// Copy `samples` into `app_buf`, starting at sample `offset`, as
+/// little-endian values. Samples that do not fit in the app buffer are
+/// dropped.
+fn copy_samples(app_buf: &WriteableProcessSlice, offset: usize, samples: &[u16]) {
+    let start = cmp::min(offset * 2, app_buf.len());
+    let count = cmp::min(samples.len(), (app_buf.len() - start) / 2);
+    if let Some(dest) = app_buf.get(start..start + count * 2) {
+        let _ = dest.copy_from_u16_slice_le(&samples[..count]);
+    }
+}
+
+/// Unpack the thresholds of the window sampling command for samples of
+/// `width_bits` bits. The packed thresholds are 16 bits each, so with 32-bit
+/// samples they are the 16 most significant bits of the samples: the window
+/// covers every sample whose top 16 bits are between the two thresholds.
+fn window_thresholds(packed: usize, width_bits: usize) -> (u32, u32) {
+    let [low, high] = WINDOW_THRESHOLDS.unpack(packed);
+    let (low, high) = (low as u32, high as u32);
+    if width_bits > 16 {
+        (low << 16, high << 16 | 0xFFFF)
+    } else {
+        (low, high)
+    }
+}
+
 /// Implementations of application syscalls
 impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for AdcDedicated<'a, A> {
     /// Method for the application to command or query this driver.
     ///
     /// - `command_num` - which command call this is
     /// - `data` - value sent by the application, varying uses
-    /// - `_processid` - application identifier, unused
+    /// - `processid` - application identifier
     fn command(
         &self,
         command_num: usize,
@@ -1153,37 +2086,6 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
         frequency: usize,
         processid: ProcessId,
     ) -> CommandReturn {
-        // Return true if this app already owns the ADC capsule, if no app owns
-        // the ADC capsule, or if the app that is marked as owning the ADC
-        // capsule no longer exists.
-        let match_or_empty_or_nonexistant = self.processid.map_or(true, |owning_app| {
-            // We have recorded that an app has ownership of the ADC.
-
-            // If the ADC is still active, then we need to wait for the operation
-            // to finish and the app, whether it exists or not (it may have crashed),
-            // still owns this capsule. If the ADC is not active, then
-            // we need to verify that that application still exists, and remove
-            // it as owner if not.
-            if self.active.get() {
-                owning_app == processid
-            } else {
-                // Check the app still exists.
-                //
-                // If the `.enter()` succeeds, then the app is still valid, and
-                // we can check if the owning app matches the one that called
-                // the command. If the `.enter()` fails, then the owning app no
-                // longer exists and we return `true` to signify the
-                // "or_nonexistant" case.
-                self.apps
-                    .enter(owning_app, |_, _| owning_app == processid)
-                    .unwrap_or(true)
-            }
-        });
-        if match_or_empty_or_nonexistant {
-            self.processid.set(processid);
-        } else {
-            return CommandReturn::failure(ErrorCode::NOMEM);
-        }
         match command_num {
             // Driver existence check
             // TODO(Tock 3.0): TRD104 specifies that Command 0 should return Success, not SuccessU32,
@@ -1191,55 +2093,66 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
             // compliance as part of the next major release of Tock. See #3375.
             0 => CommandReturn::success_u32(self.channels.len() as u32),
 
+            // No sampling while the power domain of the ADC is off
+            1..=4 | 6 | 8 | 9 | 11 | 12 | 15 if self.powered_off.get() => {
+                CommandReturn::failure(ErrorCode::OFF)
+            }
+
             // Single sample on channel
-            1 => match self.sample(channel) {
-                Ok(()) => CommandReturn::success(),
-                e => CommandReturn::failure(if let Ok(err) = ErrorCode::try_from(e) {
-                    err
-                } else {
-                    panic!("ADC: invalid return code")
-                }),
-            },
+            1 => self.sample(channel, false, processid).into(),
 
             // Repeated single samples on a channel
-            2 => match self.sample_continuous(channel, frequency as u32) {
-                Ok(()) => CommandReturn::success(),
-                e => CommandReturn::failure(if let Ok(err) = ErrorCode::try_from(e) {
-                    err
-                } else {
-                    panic!("ADC: invalid return code")
-                }),
-            },
+            2 => self
+                .claim(processid)
+                .and_then(|()| self.sample_continuous(channel, frequency as u32))
+                .into(),
 
             // Multiple sample on a channel
-            3 => match self.sample_buffer(channel, frequency as u32) {
-                Ok(()) => CommandReturn::success(),
-                e => CommandReturn::failure(if let Ok(err) = ErrorCode::try_from(e) {
-                    err
-                } else {
-                    panic!("ADC: invalid return code")
-                }),
-            },
+            3 => self
+                .claim(processid)
+                .and_then(|()| self.sample_buffer(channel, frequency as u32))
+                .into(),
 
             // Continuous buffered sampling on a channel
-            4 => match self.sample_buffer_continuous(channel, frequency as u32) {
-                Ok(()) => CommandReturn::success(),
-                e => CommandReturn::failure(if let Ok(err) = ErrorCode::try_from(e) {
-                    err
-                } else {
-                    panic!("ADC: invalid return code")
-                }),
-            },
+            4 => self
+                .claim(processid)
+                .and_then(|()| self.sample_buffer_continuous(channel, frequency as u32))
+                .into(),
 
             // Stop sampling
-            5 => match self.stop_sampling() {
-                Ok(()) => CommandReturn::success(),
-                e => CommandReturn::failure(if let Ok(err) = ErrorCode::try_from(e) {
-                    err
-                } else {
-                    panic!("ADC: invalid return code")
-                }),
-            },
+            5 => self.stop_process_sampling(processid).into(),
+
+            // Set the oversampling ratio
+            6 => self
+                .claim(processid)
+                .and_then(|()| self.set_oversampling(channel))
+                .into(),
+
+            // Watch a channel until a sample leaves the window
+            8 => {
+                let (low, high) = window_thresholds(frequency, self.adc.get_sample_width_bits());
+                self.claim(processid)
+                    .and_then(|()| self.sample_window(channel, low, high))
+                    .into()
+            }
+
+            // Single sample on channel, in millivolts
+            9 => self.sample(channel, true, processid).into(),
+
+            // Set the resolution of the conversions
+            11 => self
+                .claim(processid)
+                .and_then(|()| self.set_resolution_bits(channel, frequency))
+                .into(),
+
+            // Continuous sampling on a channel into a ring buffer
+            12 => self
+                .claim(processid)
+                .and_then(|()| self.sample_buffer_ring(channel, frequency as u32))
+                .into(),
+
+            // Run the hardware calibration
+            15 => self.claim(processid).and_then(|()| self.calibrate()).into(),
 
             // Get resolution bits
             101 => CommandReturn::success_u32(self.get_resolution_bits() as u32),
@@ -1251,12 +2164,29 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
                     CommandReturn::failure(ErrorCode::NOSUPPORT)
                 }
             }
+            // Get the actual sampling frequency in Hz
+            103 => {
+                if let Some(frequency) = self.get_sampling_frequency_hz() {
+                    CommandReturn::success_u32(frequency)
+                } else {
+                    CommandReturn::failure(ErrorCode::NOSUPPORT)
+                }
+            }
+            // Get the oversampling ratio
+            104 => CommandReturn::success_u32(self.adc.get_oversampling() as u32),
+
+            // Get the sample width bits
+            106 => CommandReturn::success_u32(self.adc.get_sample_width_bits() as u32),
 
             // default
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
 
+    fn abi_version(&self) -> Option<u32> {
+        Some(ABI_VERSION)
+    }
+
     fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
         self.apps.enter(processid, |_, _| {})
     }
@@ -1274,7 +2204,7 @@ impl SyscallDriver for AdcVirtualized<'_> {
         &self,
         command_num: usize,
         channel: usize,
-        _: usize,
+        frequency: usize,
         processid: ProcessId,
     ) -> CommandReturn {
         match command_num {
@@ -1282,17 +2212,63 @@ impl SyscallDriver for AdcVirtualized<'_> {
             0 => CommandReturn::success_u32(self.drivers.len() as u32),
 
             // Single sample.
-            1 => {
-                let res = self.enqueue_command(Operation::OneSample, channel, processid);
-                if res == Ok(()) {
-                    CommandReturn::success()
-                } else {
-                    match ErrorCode::try_from(res) {
-                        Ok(error) => CommandReturn::failure(error),
-                        _ => panic!("ADC Syscall: invalid error from enqueue_command"),
-                    }
+            1 => self.sample(channel, false, false, processid).into(),
+
+            // Buffered sampling.
+            3 => self
+                .sample_buffer(channel, frequency, false, processid)
+                .into(),
+
+            // Continuous buffered sampling.
+            4 => self
+                .sample_buffer(channel, frequency, true, processid)
+                .into(),
+
+            // Stop buffered sampling.
+            5 => self.stop_buffer(processid).into(),
+
+            // Single differential sample.
+            7 => self.sample(channel, true, false, processid).into(),
+
+            // Single sample in millivolts.
+            9 => self.sample(channel, false, true, processid).into(),
+
+            // Self-test against the internal reference.
+            10 => self.self_test(processid).into(),
+
+            // Set the resolution of the conversions.
+            11 => match self.drivers.get(channel) {
+                Some(_) if self.other_process_sampling(processid) => {
+                    CommandReturn::failure(ErrorCode::BUSY)
                 }
-            }
+                Some(driver) => driver.set_resolution_bits(frequency).into(),
+                None => CommandReturn::failure(ErrorCode::NODEVICE),
+            },
+
+            // Periodic sampling, with the interval in milliseconds.
+            13 => self.sample_periodic(channel, frequency, processid).into(),
+
+            // Stop periodic sampling.
+            14 => self.stop_periodic(channel, processid).into(),
+
+            // Set the gain and voltage reference of the channel.
+            16 => match self.drivers.get(channel) {
+                Some(_) if self.other_process_sampling(processid) => {
+                    CommandReturn::failure(ErrorCode::BUSY)
+                }
+                Some(driver) => {
+                    let [numerator, denominator, reference] = GAIN_REFERENCE.unpack(frequency);
+                    let reference = match reference {
+                        0 => hil::adc::Reference::Internal,
+                        1 => hil::adc::Reference::Vdd,
+                        2 => hil::adc::Reference::External,
+                        _ => return CommandReturn::failure(ErrorCode::INVAL),
+                    };
+                    let gain = hil::adc::Gain::new(numerator as u8, denominator as u8);
+                    driver.set_gain_and_reference(gain, reference).into()
+                }
+                None => CommandReturn::failure(ErrorCode::NODEVICE),
+            },
 
             // Get resolution bits
             101 => {
@@ -1316,29 +2292,217 @@ impl SyscallDriver for AdcVirtualized<'_> {
                 }
             }
 
+            // Is the channel differential
+            105 => match self.drivers.get(channel) {
+                Some(driver) => CommandReturn::success_u32(driver.is_differential() as u32),
+                None => CommandReturn::failure(ErrorCode::NODEVICE),
+            },
+
+            // Get the sample width bits
+            106 => match self.drivers.get(channel) {
+                Some(driver) => CommandReturn::success_u32(driver.get_sample_width_bits() as u32),
+                None => CommandReturn::failure(ErrorCode::NODEVICE),
+            },
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
 
+    fn abi_version(&self) -> Option<u32> {
+        Some(ABI_VERSION)
+    }
+
     fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
         self.apps.enter(processid, |_, _| {})
     }
 }
 
 impl<'a> hil::adc::Client for AdcVirtualized<'a> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
         self.current_process.take().map(|processid| {
             let _ = self.apps.enter(processid, |app, upcalls| {
                 app.pending_command = false;
                 let channel = app.channel;
+                let driver = self.drivers[channel];
+                if app.self_test {
+                    app.self_test = false;
+                    let measured = self.self_test.get().zip(driver.sample_to_mv(sample));
+                    if let Some((reference, mv)) = measured {
+                        let (passed, offset) = reference.check(mv);
+                        upcalls
+                            .schedule_upcall(
+                                0,
+                                (AdcMode::SelfTest as usize, passed as usize, offset as usize),
+                            )
+                            .ok();
+                        return;
+                    }
+                }
+                let (mode, value) = if driver.is_differential() {
+                    (AdcMode::DifferentialSample, sample as usize)
+                } else if app.millivolts {
+                    driver
+                        .sample_to_mv(sample)
+                        .map_or((AdcMode::SingleSample, sample as usize), |mv| {
+                            (AdcMode::MillivoltSample, mv)
+                        })
+                } else {
+                    (AdcMode::SingleSample, sample as usize)
+                };
                 upcalls
-                    .schedule_upcall(
-                        0,
-                        (AdcMode::SingleSample as usize, channel, sample as usize),
-                    )
+                    .schedule_upcall(0, (mode as usize, channel, value))
                     .ok();
             });
         });
         self.run_next_command();
     }
+
+    fn sample_failed(&self, error: ErrorCode) {
+        self.current_process.take().map(|processid| {
+            let channel = self
+                .apps
+                .enter(processid, |app, _| app.channel)
+                .unwrap_or(0);
+            self.report_sample_error(processid, channel, error);
+        });
+        self.run_next_command();
+    }
+}
+
+impl<'a> hil::adc::PeriodicClient for AdcVirtualized<'a> {
+    /// The sample of a period, identified by its channel.
+    fn periodic_sample_ready(&self, channel: usize, sample: u32) {
+        let delivered = self.periodic_owner(channel).map_or(false, |processid| {
+            self.apps
+                .enter(processid, |_, upcalls| {
+                    upcalls
+                        .schedule_upcall(
+                            0,
+                            (AdcMode::ContinuousSample as usize, channel, sample as usize),
+                        )
+                        .ok();
+                })
+                .is_ok()
+        });
+        if !delivered {
+            // The process sampling the channel is gone.
+            let _ = self.drivers[channel].stop_periodic();
+        }
+    }
+}
+
+impl<'a> hil::adc::HighSpeedClient for AdcVirtualized<'a> {
+    /// A chunk of buffered samples is ready: copy it to the app buffer, and
+    /// queue the next chunk.
+    fn samples_ready(&self, buf: &'static mut [u16], length: usize) {
+        self.current_process.take().map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                if length == 0 {
+                    // The request was stopped, or could not be started.
+                    app.continuous = false;
+                    app.samples_done = 0;
+                    return;
+                }
+                let (buf_ptr, buf_len) =
+                    match kernel_data.get_readwrite_processbuffer(app.using_app_buf1 as usize) {
+                        Ok(app_buf) => {
+                            let _ = app_buf.mut_enter(|app_buf| {
+                                let length = cmp::min(length, buf.len());
+                                copy_samples(app_buf, app.samples_done, &buf[..length])
+                            });
+                            (app_buf.ptr(), app_buf.len() / 2)
+                        }
+                        Err(_) => (core::ptr::null(), 0),
+                    };
+                app.samples_done += length;
+
+                if app.samples_done >= buf_len {
+                    let mode = if app.continuous {
+                        AdcMode::ContinuousBuffer
+                    } else {
+                        AdcMode::SingleBuffer
+                    };
+                    let len_chan = UPCALL_CHANNEL_LEN.pack([app.channel, buf_len]);
+                    kernel_data
+                        .schedule_upcall(0, (mode as usize, len_chan, buf_ptr as usize))
+                        .ok();
+                    app.samples_done = 0;
+                    if !app.continuous {
+                        return;
+                    }
+                    app.using_app_buf1 = !app.using_app_buf1;
+                }
+                app.pending_command = true;
+                app.command.set(Operation::Buffer);
+            });
+        });
+        self.buffer.replace(buf);
+        self.run_next_command();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copy one second of samples at 200 kHz, in chunks of `BUF_LEN`, into
+    /// two alternating app buffers, as high speed sampling does.
+    #[test]
+    fn copy_samples_at_full_rate() {
+        const APP_BUF_SAMPLES: usize = 4 * BUF_LEN;
+        let mut app_bufs = [[0u8; APP_BUF_SAMPLES * 2]; 2];
+        let mut chunk = [0u16; BUF_LEN];
+        let mut next_sample: u16 = 0;
+        let mut offset = 0;
+        let mut current = 0;
+        for _ in 0..200_000 / BUF_LEN {
+            for sample in chunk.iter_mut() {
+                *sample = next_sample;
+                next_sample = next_sample.wrapping_add(1);
+            }
+            let app_buf: &WriteableProcessSlice = (&mut app_bufs[current][..]).into();
+            copy_samples(app_buf, offset, &chunk);
+            offset += BUF_LEN;
+            if offset == APP_BUF_SAMPLES {
+                // The buffer just filled holds the last samples in order.
+                let first = next_sample.wrapping_sub(APP_BUF_SAMPLES as u16);
+                for (i, bytes) in app_bufs[current].chunks_exact(2).enumerate() {
+                    let expected = first.wrapping_add(i as u16);
+                    assert_eq!(bytes, expected.to_le_bytes());
+                }
+                offset = 0;
+                current = 1 - current;
+            }
+        }
+    }
+
+    #[test]
+    fn copy_samples_drops_overflow() {
+        let mut bytes = [0u8; 7];
+        let app_buf: &WriteableProcessSlice = (&mut bytes[..]).into();
+        copy_samples(app_buf, 2, &[0x1234, 0x5678]);
+        assert_eq!(bytes, [0, 0, 0, 0, 0x34, 0x12, 0]);
+
+        let app_buf: &WriteableProcessSlice = (&mut bytes[..]).into();
+        copy_samples(app_buf, 4, &[0xFFFF]);
+        assert_eq!(bytes, [0, 0, 0, 0, 0x34, 0x12, 0]);
+    }
+
+    #[test]
+    fn ring_copy_wraps_around() {
+        let mut bytes = [0u8; RING_HEADER_LEN + 8];
+        let ring: &WriteableProcessSlice = (&mut bytes[..]).into();
+        let samples = ring.get(RING_HEADER_LEN..ring.len()).unwrap();
+        let head = ring_copy_samples(samples, 4, 3, &[1, 2, 3]);
+        write_ring_head(ring, head);
+        assert_eq!(head, 2);
+        assert_eq!(bytes, [2, 0, 0, 0, 2, 0, 3, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn window_thresholds_follow_sample_width() {
+        let packed = WINDOW_THRESHOLDS.pack([0x1000, 0x8000]);
+        assert_eq!(window_thresholds(packed, 16), (0x1000, 0x8000));
+        assert_eq!(window_thresholds(packed, 32), (0x1000_0000, 0x8000_FFFF));
+    }
 }
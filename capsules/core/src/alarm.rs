@@ -4,6 +4,12 @@
 
 //! Tock syscall driver capsule for Alarms, which issue callbacks when
 //! a point in time has been reached.
+//!
+//! Alarms can also be periodic: a periodic alarm is re-armed by the kernel
+//! each time it expires, from its previous expiration rather than from the
+//! time the process handles the upcall, so its period does not drift.
+
+use core::cell::Cell;
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::time::{self, Alarm, Ticks};
@@ -23,6 +29,8 @@ struct Expiration<T: Ticks> {
 #[derive(Copy, Clone)]
 pub struct AlarmData<T: Ticks> {
     expiration: Option<Expiration<T>>,
+    /// Whether the alarm is re-armed with the same `dt` when it expires.
+    periodic: bool,
 }
 
 const ALARM_CALLBACK_NUM: usize = 0;
@@ -30,7 +38,10 @@ const NUM_UPCALLS: u8 = 1;
 
 impl<T: Ticks> Default for AlarmData<T> {
     fn default() -> AlarmData<T> {
-        AlarmData { expiration: None }
+        AlarmData {
+            expiration: None,
+            periodic: false,
+        }
     }
 }
 
@@ -143,11 +154,33 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
         Ok(earliest)
     }
 
+    /// Compute the next expiration of a periodic alarm which expired at
+    /// `expired.reference + expired.dt`.
+    ///
+    /// The next expiration is one period after the one that expired, so
+    /// delays in handling expirations do not accumulate. If more than one
+    /// period has already elapsed, the missed expirations are skipped while
+    /// keeping the alarm's phase.
+    fn next_periodic_expiration(
+        now: A::Ticks,
+        expired: Expiration<A::Ticks>,
+    ) -> Expiration<A::Ticks> {
+        let mut next = Expiration {
+            reference: expired.reference.wrapping_add(expired.dt),
+            dt: expired.dt,
+        };
+        while !now.within_range(next.reference, next.reference.wrapping_add(next.dt)) {
+            next.reference = next.reference.wrapping_add(next.dt);
+        }
+        next
+    }
+
     /// Re-arm the timer. This must be called in response to the underlying
     /// timer firing, or the set of [`Expiration`]s changing. This will iterate
     /// over all [`Expiration`]s and
     ///
-    /// - invoke upcalls for all expired app alarms, resetting them afterwards,
+    /// - invoke upcalls for all expired app alarms, resetting them (or moving
+    ///   periodic alarms to their next expiration) afterwards,
     /// - re-arming the alarm for the next earliest [`Expiration`], or
     /// - disarming the alarm if no unexpired [`Expiration`] is found.
     fn process_rearm_or_callback(&self) {
@@ -155,6 +188,10 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
         // volatile read, and this may not be optimized if done in a loop:
         let now = self.alarm.now();
 
+        // Whether a periodic alarm was moved to its next expiration, which is
+        // not considered when computing the earliest alarm below.
+        let periodic_rearmed = Cell::new(false);
+
         let expired_handler = |expired: Expiration<A::Ticks>, process_id: &ProcessId| {
             // This closure is run on every expired alarm, _after_ the `enter()`
             // closure on the Grant iterator has returned. We are thus not
@@ -162,8 +199,14 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
 
             // Enter the app's grant again:
             let _ = self.app_alarms.enter(*process_id, |alarm_state, upcalls| {
-                // Reset this app's alarm:
-                alarm_state.expiration = None;
+                // Reset this app's alarm, or move it to its next expiration
+                // if it is periodic:
+                if alarm_state.periodic {
+                    alarm_state.expiration = Some(Self::next_periodic_expiration(now, expired));
+                    periodic_rearmed.set(true);
+                } else {
+                    alarm_state.expiration = None;
+                }
 
                 // Deliver the upcall:
                 upcalls
@@ -218,6 +261,12 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
                 unreachable!();
             }
         }
+
+        // Take the new expirations of the periodic alarms into account. Those
+        // are in the future, so this does not deliver their upcalls again:
+        if periodic_rearmed.get() {
+            self.process_rearm_or_callback();
+        }
     }
 
     fn rearm_u32_left_justified_expiration(
@@ -370,6 +419,9 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
     /// - `5`: Set an alarm to fire at a given clock value `time` relative to `now`
     /// - `6`: Set an alarm to fire at a given clock value `time` relative to a provided
     ///        reference point.
+    /// - `7`: Set a periodic alarm to fire every `period` clock ticks, starting
+    ///        `period` ticks from now. Stopped with `3`, or replaced by setting
+    ///        a one-shot alarm with `5` or `6`.
     fn command(
         &self,
         cmd_type: usize,
@@ -427,6 +479,7 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                             Some(_old_expiraton) => {
                                 // Clear the expiration:
                                 td.expiration = None;
+                                td.periodic = false;
 
                                 // Ask for the timer to be re-armed. We can't do
                                 // this here, as it would re-enter the grant
@@ -452,6 +505,7 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                         // timers.
                         //
                         // All of this is done in the following helper method:
+                        td.periodic = false;
                         let new_exp_left_justified = Self::rearm_u32_left_justified_expiration(
                             // Current time:
                             now,
@@ -475,6 +529,7 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                         // timers.
                         //
                         // All of this is done in the following helper method:
+                        td.periodic = false;
                         let new_exp_left_justified = Self::rearm_u32_left_justified_expiration(
                             // Current time:
                             now,
@@ -493,6 +548,33 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                         // the grant region:
                         (CommandReturn::success_u32(new_exp_left_justified), true)
                     }
+                    7 => {
+                        // Set periodic expiration, relative to now.
+                        //
+                        // A zero period would expire continuously:
+                        if data == 0 {
+                            (CommandReturn::failure(ErrorCode::INVAL), false)
+                        } else {
+                            // The first expiration is set up like a relative
+                            // alarm, its `dt` being the period:
+                            td.periodic = true;
+                            let new_exp_left_justified = Self::rearm_u32_left_justified_expiration(
+                                // Current time:
+                                now,
+                                // No userspace-provided reference:
+                                None,
+                                // Left-justified period:
+                                data as u32,
+                                // Reference to the `Option<Expiration>`:
+                                &mut td.expiration,
+                            );
+
+                            // Report success, with the left-justified time at
+                            // which the alarm will first fire, and ask for the
+                            // timer to be re-armed:
+                            (CommandReturn::success_u32(new_exp_left_justified), true)
+                        }
+                    }
 
                     // Unknown command:
                     //
@@ -821,6 +903,31 @@ mod test {
         assert!(bool_exp_list == [false, true, false, true,]);
     }
 
+    #[test]
+    fn test_next_periodic_expiration() {
+        // Handled late, but before the next expiration:
+        let next = AlarmDriver::<MockAlarm<Ticks32, Freq10MHz>>::next_periodic_expiration(
+            Ticks32::from(1150_u32),
+            Expiration {
+                reference: Ticks32::from(1000_u32),
+                dt: Ticks32::from(100_u32),
+            },
+        );
+        assert_eq!(next.reference.into_u32(), 1100);
+        assert_eq!(next.dt.into_u32(), 100);
+
+        // One expiration was missed, and the timer wrapped:
+        let next = AlarmDriver::<MockAlarm<Ticks24, Freq10MHz>>::next_periodic_expiration(
+            Ticks24::from(30_u32),
+            Expiration {
+                reference: Ticks24::from(0xffff00_u32),
+                dt: Ticks24::from(100_u32),
+            },
+        );
+        assert_eq!(next.reference.into_u32(), 0xffff00 + 200);
+        assert_eq!(next.dt.into_u32(), 100);
+    }
+
     #[test]
     fn test_rearm_24bit_left_justified_noref_basic() {
         let mut expiration = None;
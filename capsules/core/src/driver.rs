@@ -34,6 +34,7 @@ pub enum NUM {
     UsbUser               = 0x20005,
     I2cMasterSlave        = 0x20006,
     Can                   = 0x20007,
+    SmartCard             = 0x20008,
 
     // Radio
     BleAdvertising        = 0x30000,
@@ -43,6 +44,7 @@ pub enum NUM {
     LoRaPhyGPIO           = 0x30004,
     Thread                = 0x30005,
     Eui64                 = 0x30006,
+    NetStats              = 0x30007,
 
     // Cryptography
     Rng                   = 0x40001,
@@ -57,6 +59,11 @@ pub enum NUM {
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     Kv                    = 0x50003,
+    AppAcceptList         = 0x50004,
+    AssetStore            = 0x50005,
+    BootConfig            = 0x50006,
+    MeasuredBoot          = 0x50007,
+    FileSystem            = 0x50008,
 
     // Sensors
     Temperature           = 0x60000,
@@ -67,6 +74,11 @@ pub enum NUM {
     SoundPressure         = 0x60006,
     AirQuality            = 0x60007,
     Pressure              = 0x60008,
+    SoundLevel            = 0x60009,
+    TdmCapture            = 0x6000A,
+    AdcAcquisition        = 0x6000B,
+    Scale                 = 0x6000C,
+    HeartRate             = 0x6000D,
 
     // Sensor ICs
     Tsl2561               = 0x70000,
@@ -76,6 +88,7 @@ pub enum NUM {
     Lsm303dlch            = 0x70006,
     Mlx90614              = 0x70007,
     Lsm6dsoxtr            = 0x70008,
+    Max30102              = 0x70009,
 
     // Other ICs
     Ltc294x               = 0x80000,
@@ -83,6 +96,7 @@ pub enum NUM {
     Pca9544a              = 0x80002,
     GpioAsync             = 0x80003,
     Nrf51822Serialization = 0x80004,
+    Pn532                 = 0x80005,
 
     // Misc
     Buzzer                = 0x90000,
@@ -93,5 +107,17 @@ pub enum NUM {
     KeyboardHid           = 0x90005,
     DateTime              = 0x90007,
     CycleCount            = 0x90008,
+    IsolationAudit        = 0x90009,
+    AdcDsp                = 0x9000A,
+    SystemConfig          = 0x9000B,
+    Uptime                = 0x9000C,
+    Inference             = 0x9000D,
+    MotorControl          = 0x9000E,
+    PowerControl          = 0x9000F,
+    Tamper                = 0x90010,
+    Fingerprint           = 0x90011,
+    AdcDecimator          = 0x90012,
+    ProcessInfo           = 0x90013,
+    Supervisor            = 0x90014,
 }
 }
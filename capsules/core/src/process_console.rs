@@ -12,16 +12,21 @@ use core::fmt;
 use core::fmt::write;
 use core::str;
 use kernel::capabilities::ProcessManagementCapability;
+use kernel::debug::{LogLevel, LogSubsystem, RegisterBlock};
+use kernel::hil::boot_config::BootConfig;
+use kernel::hil::interrupt_latency::InterruptLatency;
+use kernel::hil::network_statistics::NetworkStatistics;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
 use kernel::debug;
 use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::hil::uart;
-use kernel::introspection::KernelInfo;
-use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::introspection::{self, KernelInfo};
+use kernel::process::{self, ProcessPrinter, ProcessPrinterContext, State};
 use kernel::utilities::binary_write::BinaryWrite;
 use kernel::ErrorCode;
 use kernel::Kernel;
@@ -43,7 +48,14 @@ pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
 /// List of valid commands for printing help. Consolidated as these are
 /// displayed in a few different cases.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
+    b"help status list stop start fault boot terminate process trace cputime drivers kernel netstat irqs env regs log reset panic console-start console-stop\r\n";
+
+/// Prefix of the boot configuration keys holding the boot script. The
+/// commands are stored in `console.boot.0`, `console.boot.1`, ...
+const BOOT_SCRIPT_KEY: &str = "console.boot.";
+
+/// Number of registers printed on each line by the `regs` command.
+const REGISTERS_PER_LINE: usize = 4;
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
@@ -90,6 +102,37 @@ enum WriterState {
         index: isize,
         total: isize,
     },
+    NetStat {
+        index: isize,
+        total: isize,
+    },
+    Drivers {
+        index: isize,
+        total: isize,
+    },
+    Interrupts {
+        index: isize,
+        total: isize,
+    },
+    BootEnv {
+        index: isize,
+        total: isize,
+    },
+    Registers {
+        block: usize,
+        index: isize,
+        total: isize,
+    },
+    SyscallTrace {
+        process_id: ProcessId,
+        index: isize,
+        total: isize,
+    },
+    UpcallTime {
+        process_id: ProcessId,
+        index: isize,
+        total: isize,
+    },
 }
 
 /// Key that can be part from an escape sequence.
@@ -269,6 +312,21 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Network interfaces reported by the `netstat` command.
+    network_interfaces: OptionalCell<&'a [&'a dyn NetworkStatistics]>,
+
+    /// Interrupt latency measurements reported by the `irqs` command.
+    interrupt_latency: OptionalCell<&'a dyn InterruptLatency>,
+
+    /// Boot configuration displayed and edited by the `env` command.
+    boot_config: OptionalCell<&'a dyn BootConfig>,
+
+    /// Peripheral registers dumped by the `regs` command.
+    register_blocks: OptionalCell<&'a [RegisterBlock]>,
+
+    /// Index of the next boot script command, while the script runs.
+    boot_script: Cell<Option<usize>>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -471,10 +529,56 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
             kernel: kernel,
             kernel_addresses: kernel_addresses,
             reset_function: reset_function,
+            network_interfaces: OptionalCell::empty(),
+            interrupt_latency: OptionalCell::empty(),
+            boot_config: OptionalCell::empty(),
+            register_blocks: OptionalCell::empty(),
+            boot_script: Cell::new(None),
             capability: capability,
         }
     }
 
+    /// Set the network interfaces whose counters are displayed by the
+    /// `netstat` command.
+    pub fn set_network_interfaces(&self, interfaces: &'a [&'a dyn NetworkStatistics]) {
+        self.network_interfaces.set(interfaces);
+    }
+
+    /// Set the interrupt latency measurements reported by the `irqs` command.
+    pub fn set_interrupt_latency(&self, interrupt_latency: &'a dyn InterruptLatency) {
+        self.interrupt_latency.set(interrupt_latency);
+    }
+
+    /// Set the boot configuration displayed and edited by the `env` command.
+    pub fn set_boot_config(&self, boot_config: &'a dyn BootConfig) {
+        self.boot_config.set(boot_config);
+    }
+
+    /// Set the peripheral register blocks dumped by the `regs` command.
+    pub fn set_register_blocks(&self, blocks: &'a [RegisterBlock]) {
+        self.register_blocks.set(blocks);
+    }
+
+    /// Run the boot script stored in the boot configuration once the console
+    /// is started.
+    ///
+    /// The script is made of the settings `console.boot.0`,
+    /// `console.boot.1`, ... up to the first missing one, each holding one
+    /// command. The commands are echoed and executed as if they were typed,
+    /// so devices without serial access can, for example, stop a diagnostic
+    /// application or set log levels at every boot. In hibernating mode,
+    /// commands are ignored until a `console-start` command.
+    ///
+    /// The boot configuration must be set with `set_boot_config()`. Since
+    /// the script can run any command, the board should only enable it if
+    /// it trusts the processes allowed to change the boot configuration.
+    pub fn run_boot_script(&self) {
+        if self.boot_config.is_some() {
+            self.boot_script.set(Some(0));
+            self.run_script_command();
+        }
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -557,6 +661,91 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                     }
                 }
             }
+            WriterState::Drivers { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Drivers {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::NetStat { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::NetStat {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::Interrupts { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Interrupts {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::BootEnv { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::BootEnv {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::Registers {
+                block,
+                index,
+                total,
+            } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Registers {
+                        block,
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::SyscallTrace {
+                process_id,
+                index,
+                total,
+            } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::SyscallTrace {
+                        process_id,
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::UpcallTime {
+                process_id,
+                index,
+                total,
+            } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::UpcallTime {
+                        process_id,
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
             WriterState::Empty => WriterState::Empty,
         }
     }
@@ -741,6 +930,162 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                         }
                     });
             }
+            WriterState::NetStat { index, total: _ } => {
+                self.network_interfaces.map(|interfaces| {
+                    interfaces.get(index as usize).map(|interface| {
+                        let counters = interface.counters();
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(
+                                " {:<12}{:>10}{:>11}{:>9}{:>9}{:>8}{:>8}\r\n",
+                                interface.interface_name(),
+                                counters.tx_frames,
+                                counters.rx_frames,
+                                counters.tx_dropped,
+                                counters.rx_dropped,
+                                counters.tx_errors,
+                                counters.rx_errors,
+                            ),
+                        );
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    });
+                });
+            }
+            WriterState::Drivers { index, total: _ } => {
+                let info = KernelInfo::new(self.kernel);
+                if let Some(stats) = info.driver_syscall_stats(index as usize, &self.capability) {
+                    let calls = stats.commands + stats.allows + stats.subscribes;
+                    let mut console_writer = ConsoleWriter::new();
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!(
+                            " {:#07x}{:>10}{:>9}{:>11}{:>10}{:>6}%{}\r\n",
+                            stats.driver_num,
+                            stats.commands,
+                            stats.allows,
+                            stats.subscribes,
+                            stats.failures,
+                            stats.failures * 100 / calls.max(1),
+                            if stats.registered { "" } else { "  no driver" },
+                        ),
+                    );
+                    let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                }
+            }
+            WriterState::Interrupts { index, total: _ } => {
+                self.interrupt_latency.map(|interrupt_latency| {
+                    interrupt_latency
+                        .get(index as usize)
+                        .map(|(interrupt, stats)| {
+                            let mut console_writer = ConsoleWriter::new();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    " {:>3}{:>10}{:>9}{:>9} ",
+                                    interrupt,
+                                    stats.count,
+                                    stats.mean(),
+                                    stats.max,
+                                ),
+                            );
+                            for bucket in stats.histogram {
+                                let _ = write(&mut console_writer, format_args!("{:>7}", bucket));
+                            }
+                            let _ = write(&mut console_writer, format_args!("\r\n"));
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        });
+                });
+            }
+            WriterState::BootEnv { index, total: _ } => {
+                self.boot_config.map(|boot_config| {
+                    boot_config.with_entry(index as usize, &mut |key, value| {
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(&mut console_writer, format_args!(" {}={}\r\n", key, value));
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    });
+                });
+            }
+            WriterState::Registers {
+                block,
+                index,
+                total: _,
+            } => {
+                self.register_blocks.map(|blocks| {
+                    blocks.get(block).map(|block| {
+                        // Each line holds four registers, prefixed by the
+                        // address of the first one.
+                        let first = index as usize * REGISTERS_PER_LINE;
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(" {:#010x}:", block.base() + first * 4),
+                        );
+                        for register in first..cmp::min(first + REGISTERS_PER_LINE, block.len()) {
+                            if let Some(value) = block.read(register) {
+                                let _ = write(&mut console_writer, format_args!(" {:08x}", value));
+                            }
+                        }
+                        let _ = write(&mut console_writer, format_args!("\r\n"));
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    });
+                });
+            }
+            WriterState::SyscallTrace {
+                process_id,
+                index,
+                total,
+            } => {
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if process_id != process.processid() {
+                            return;
+                        }
+                        // Print the oldest syscall first.
+                        let entry = process.debug_syscall_trace((total - 1 - index) as usize);
+                        entry.map(|entry| {
+                            let mut console_writer = ConsoleWriter::new();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(" {:>3}  {:?}\r\n", index, entry.syscall),
+                            );
+                            let _ = match entry.return_value {
+                                Some(return_value) => write(
+                                    &mut console_writer,
+                                    format_args!("      -> {:?}\r\n", return_value),
+                                ),
+                                None => write(&mut console_writer, format_args!("      -> -\r\n")),
+                            };
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        });
+                    });
+            }
+            WriterState::UpcallTime {
+                process_id,
+                index,
+                total: _,
+            } => {
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if process_id != process.processid() {
+                            return;
+                        }
+                        process.debug_upcall_time(index as usize).map(|entry| {
+                            let mut console_writer = ConsoleWriter::new();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    " {:#07x}  {:>7} {:>10}us {:>7}\r\n",
+                                    entry.driver_num,
+                                    entry.upcall_count,
+                                    entry.time_us,
+                                    entry.timeslice_expiration_count,
+                                ),
+                            );
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        });
+                    });
+            }
             WriterState::Empty => {
                 self.prompt();
             }
@@ -908,6 +1253,202 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                                     total: count,
                                 });
                             }
+                        } else if clean_str.starts_with("netstat") {
+                            let interfaces = self.network_interfaces.get().unwrap_or(&[]);
+                            let argument = clean_str.split_whitespace().nth(1);
+                            if interfaces.is_empty() {
+                                let _ = self.write_bytes(b"No network interfaces.\r\n");
+                            } else if argument == Some("reset") {
+                                for interface in interfaces.iter() {
+                                    interface.reset_counters();
+                                }
+                                let _ = self.write_bytes(b"Network counters reset.\r\n");
+                            } else {
+                                let _ = self.write_bytes(
+                                    b" Interface    TX frames  RX frames  TX drop  RX drop  ",
+                                );
+                                let _ = self.write_bytes(b"TX err  RX err\r\n");
+
+                                // Start the state machine to print each
+                                // interface separately.
+                                self.write_state(WriterState::NetStat {
+                                    index: -1,
+                                    total: interfaces.len() as isize,
+                                });
+                            }
+                        } else if clean_str.starts_with("drivers") {
+                            let info = KernelInfo::new(self.kernel);
+                            let argument = clean_str.split_whitespace().nth(1);
+                            if argument == Some("reset") {
+                                info.reset_driver_syscall_stats(&self.capability);
+                                let _ = self.write_bytes(b"Driver syscall counts reset.\r\n");
+                            } else {
+                                let count = (0..introspection::SYSCALL_STATS_DRIVERS)
+                                    .take_while(|index| {
+                                        info.driver_syscall_stats(*index, &self.capability)
+                                            .is_some()
+                                    })
+                                    .count();
+                                if count == 0 {
+                                    let _ = self.write_bytes(b"No driver syscalls recorded.\r\n");
+                                } else {
+                                    let _ = self.write_bytes(
+                                        b" Driver   Commands   Allows Subscribes  Failures   Rate\r\n",
+                                    );
+                                    // Start the state machine to print each
+                                    // driver separately.
+                                    self.write_state(WriterState::Drivers {
+                                        index: -1,
+                                        total: count as isize,
+                                    });
+                                }
+                            }
+                        } else if clean_str.starts_with("irqs") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            match self.interrupt_latency.get() {
+                                None => {
+                                    let _ = self
+                                        .write_bytes(b"Interrupt latency is not measured.\r\n");
+                                }
+                                Some(interrupt_latency) if argument == Some("reset") => {
+                                    interrupt_latency.reset();
+                                    let _ = self.write_bytes(b"Interrupt latency reset.\r\n");
+                                }
+                                Some(interrupt_latency) => {
+                                    let count = interrupt_latency.count();
+                                    if count == 0 {
+                                        let _ = self.write_bytes(b"No interrupt measured.\r\n");
+                                    } else {
+                                        // Latencies are in CPU cycles, each
+                                        // histogram bucket is four times
+                                        // larger than the previous one.
+                                        let _ = self.write_bytes(
+                                            b" IRQ     count     mean      max    <256    <1k    <4k",
+                                        );
+                                        let _ = self.write_bytes(
+                                            b"   <16k   <64k  <256k    <1M   more\r\n",
+                                        );
+                                        self.write_state(WriterState::Interrupts {
+                                            index: -1,
+                                            total: count as isize,
+                                        });
+                                    }
+                                }
+                            }
+                        } else if clean_str.starts_with("env") {
+                            let mut arguments = clean_str.split_whitespace().skip(1);
+                            let result = match (self.boot_config.get(), arguments.next()) {
+                                (None, _) => Some(Err(ErrorCode::NODEVICE)),
+                                (Some(boot_config), Some("set")) => {
+                                    match (arguments.next(), arguments.next()) {
+                                        (Some(key), Some(value)) => {
+                                            Some(boot_config.set(key, value))
+                                        }
+                                        _ => Some(Err(ErrorCode::INVAL)),
+                                    }
+                                }
+                                (Some(boot_config), Some("unset")) => match arguments.next() {
+                                    Some(key) => Some(boot_config.unset(key)),
+                                    None => Some(Err(ErrorCode::INVAL)),
+                                },
+                                (Some(boot_config), _) => {
+                                    let count = boot_config.count();
+                                    if count > 0 {
+                                        // Start the state machine to print
+                                        // each setting separately.
+                                        self.write_state(WriterState::BootEnv {
+                                            index: -1,
+                                            total: count as isize,
+                                        });
+                                    }
+                                    None
+                                }
+                            };
+                            match result {
+                                Some(Ok(())) => {
+                                    let _ = self.write_bytes(
+                                        b"Saved, the change takes effect at the next boot.\r\n",
+                                    );
+                                }
+                                Some(Err(ErrorCode::NODEVICE)) => {
+                                    let _ = self.write_bytes(b"No boot configuration.\r\n");
+                                }
+                                Some(Err(ErrorCode::FAIL)) => {
+                                    let _ = self.write_bytes(b"Key not set.\r\n");
+                                }
+                                Some(Err(ErrorCode::INVAL)) => {
+                                    let _ = self.write_bytes(
+                                        b"Usage: env [set <key> <value> | unset <key>]\r\n",
+                                    );
+                                }
+                                Some(Err(e)) => {
+                                    let mut console_writer = ConsoleWriter::new();
+                                    let _ = write(
+                                        &mut console_writer,
+                                        format_args!("Failed to save: {:?}\r\n", e),
+                                    );
+                                    let _ = self
+                                        .write_bytes(&(console_writer.buf)[..console_writer.size]);
+                                }
+                                None => {}
+                            }
+                        } else if clean_str.starts_with("log") {
+                            let mut arguments = clean_str.split_whitespace().skip(1);
+                            let mut console_writer = ConsoleWriter::new();
+                            match (arguments.next(), arguments.next().map(LogLevel::from_name)) {
+                                (None, _) => {
+                                    for subsystem in LogSubsystem::ALL {
+                                        let _ = write(
+                                            &mut console_writer,
+                                            format_args!(
+                                                " {}={}",
+                                                subsystem.name(),
+                                                kernel::debug::log_level(subsystem).name()
+                                            ),
+                                        );
+                                    }
+                                    let _ = write(&mut console_writer, format_args!("\r\n"));
+                                }
+                                (Some("all"), Some(Some(level))) => {
+                                    for subsystem in LogSubsystem::ALL {
+                                        kernel::debug::set_log_level(subsystem, level);
+                                    }
+                                    let _ = write(
+                                        &mut console_writer,
+                                        format_args!("Log level set to {}\r\n", level.name()),
+                                    );
+                                }
+                                (Some(name), Some(Some(level))) => {
+                                    match LogSubsystem::from_name(name) {
+                                        Some(subsystem) => {
+                                            kernel::debug::set_log_level(subsystem, level);
+                                            let _ = write(
+                                                &mut console_writer,
+                                                format_args!(
+                                                    "Log level of {} set to {}\r\n",
+                                                    name,
+                                                    level.name()
+                                                ),
+                                            );
+                                        }
+                                        None => {
+                                            let _ = write(
+                                                &mut console_writer,
+                                                format_args!("Unknown subsystem {}\r\n", name),
+                                            );
+                                        }
+                                    }
+                                }
+                                _ => {
+                                    let _ = write(
+                                        &mut console_writer,
+                                        format_args!(
+                                            "Usage: log [<subsystem>|all off|error|warn|info|trace]\r\n"
+                                        ),
+                                    );
+                                }
+                            }
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         } else if clean_str.starts_with("status") {
                             let info: KernelInfo = KernelInfo::new(self.kernel);
                             let mut console_writer = ConsoleWriter::new();
@@ -937,6 +1478,15 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                                 ),
                             );
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            console_writer.clear();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    "Debug bytes dropped: {}\r\n",
+                                    kernel::debug::debug_dropped_bytes()
+                                ),
+                            );
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         } else if clean_str.starts_with("process") {
                             let argument = clean_str.split_whitespace().nth(1);
                             argument.map(|name| {
@@ -975,6 +1525,121 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                                         }
                                     });
                             });
+                        } else if clean_str.starts_with("trace") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                let mut found = false;
+                                self.kernel
+                                    .process_each_capability(&self.capability, |proc| {
+                                        if found || proc.get_process_name() != name {
+                                            return;
+                                        }
+                                        found = true;
+
+                                        let count = (0..process::SYSCALL_TRACE_LENGTH)
+                                            .take_while(|index| {
+                                                proc.debug_syscall_trace(*index).is_some()
+                                            })
+                                            .count();
+                                        if count == 0 {
+                                            let _ = self.write_bytes(b"No syscalls recorded.\r\n");
+                                        } else {
+                                            // Start the state machine to print
+                                            // each syscall separately.
+                                            self.write_state(WriterState::SyscallTrace {
+                                                process_id: proc.processid(),
+                                                index: -1,
+                                                total: count as isize,
+                                            });
+                                        }
+                                    });
+                            });
+                        } else if clean_str.starts_with("regs") {
+                            let blocks = self.register_blocks.get().unwrap_or(&[]);
+                            let argument = clean_str.split_whitespace().nth(1);
+                            match argument {
+                                _ if blocks.is_empty() => {
+                                    let _ = self.write_bytes(b"No register blocks.\r\n");
+                                }
+                                None => {
+                                    let mut console_writer = ConsoleWriter::new();
+                                    let _ = write(&mut console_writer, format_args!("Blocks:"));
+                                    for block in blocks.iter() {
+                                        let _ = write(
+                                            &mut console_writer,
+                                            format_args!(" {}", block.name()),
+                                        );
+                                    }
+                                    let _ = write(&mut console_writer, format_args!("\r\n"));
+                                    let _ = self
+                                        .write_bytes(&(console_writer.buf)[..console_writer.size]);
+                                }
+                                Some(name) => {
+                                    match blocks.iter().position(|block| block.name() == name) {
+                                        Some(index) => {
+                                            let block = &blocks[index];
+                                            let mut console_writer = ConsoleWriter::new();
+                                            let _ = write(
+                                                &mut console_writer,
+                                                format_args!(
+                                                    "{} @ {:#010x}, {} registers\r\n",
+                                                    block.name(),
+                                                    block.base(),
+                                                    block.len()
+                                                ),
+                                            );
+                                            let _ = self.write_bytes(
+                                                &(console_writer.buf)[..console_writer.size],
+                                            );
+                                            if !block.is_empty() {
+                                                // Start the state machine to
+                                                // print each line separately.
+                                                self.write_state(WriterState::Registers {
+                                                    block: index,
+                                                    index: -1,
+                                                    total: block.len().div_ceil(REGISTERS_PER_LINE)
+                                                        as isize,
+                                                });
+                                            }
+                                        }
+                                        None => {
+                                            let _ = self.write_bytes(b"Unknown register block.\r\n");
+                                        }
+                                    }
+                                }
+                            }
+                        } else if clean_str.starts_with("cputime") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                let mut found = false;
+                                self.kernel
+                                    .process_each_capability(&self.capability, |proc| {
+                                        if found || proc.get_process_name() != name {
+                                            return;
+                                        }
+                                        found = true;
+
+                                        let count = (0..process::UPCALL_TIME_DRIVERS)
+                                            .take_while(|index| {
+                                                proc.debug_upcall_time(*index).is_some()
+                                            })
+                                            .count();
+                                        if count == 0 {
+                                            let _ = self.write_bytes(b"No upcalls recorded.\r\n");
+                                        } else {
+                                            let _ = self.write_bytes(
+                                                b" Driver   Upcalls         Time Expired\r\n",
+                                            );
+                                            // Start the state machine to print
+                                            // each driver separately.
+                                            self.write_state(WriterState::UpcallTime {
+                                                process_id: proc.processid(),
+                                                index: -1,
+                                                total: count as isize,
+                                            });
+                                        }
+                                    });
+                            });
                         } else if clean_str.starts_with("kernel") {
                             let mut console_writer = ConsoleWriter::new();
                             let _ = write(
@@ -1028,6 +1693,67 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
         }
     }
 
+    /// Run the next command of the boot script, unless the console is busy.
+    fn run_script_command(&self) {
+        while let Some(index) = self.boot_script.get() {
+            if self.mode.get() == ProcessConsoleState::Off
+                || self.tx_in_progress.get()
+                || self.writer_state.get() != WriterState::Empty
+                || self.execute.get()
+                || self.command_index.get() != 0
+            {
+                // Continue when the console is idle and nothing is typed.
+                return;
+            }
+
+            let mut key = ConsoleWriter::new();
+            let _ = write(&mut key, format_args!("{}{}", BOOT_SCRIPT_KEY, index));
+            let mut value = [0; COMMAND_BUF_LEN];
+            let result = match str::from_utf8(&key.buf[..key.size]) {
+                Ok(key) => self
+                    .boot_config
+                    .map_or(Err(ErrorCode::FAIL), |boot_config| {
+                        boot_config.get(key, &mut value)
+                    }),
+                Err(_) => Err(ErrorCode::FAIL),
+            };
+            let length = match result {
+                Ok(length) => length,
+                Err(ErrorCode::SIZE) => {
+                    let _ = self.write_bytes(b"Boot script command too long\r\n");
+                    self.boot_script.set(Some(index + 1));
+                    continue;
+                }
+                Err(_) => {
+                    // The script ends at the first missing command.
+                    self.boot_script.set(None);
+                    return;
+                }
+            };
+            self.boot_script.set(Some(index + 1));
+
+            let fits = self.command_buffer.map_or(false, |command| {
+                if length < command.len() {
+                    command[..length].copy_from_slice(&value[..length]);
+                    command[length] = EOL;
+                    true
+                } else {
+                    false
+                }
+            });
+            if !fits {
+                let _ = self.write_bytes(b"Boot script command too long\r\n");
+            } else if self.mode.get() == ProcessConsoleState::Active {
+                // Echo the command, it runs once the echo is transmitted.
+                self.execute.set(true);
+                let _ = self.write_bytes(&value[..length]);
+                let _ = self.write_bytes(b"\r\n");
+            } else {
+                self.read_command();
+            }
+        }
+    }
+
     fn prompt(&self) {
         // Only display the prompt in active mode.
         match self.mode.get() {
@@ -1137,6 +1863,7 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
         self.rx_buffer.take().map(|buffer| {
             let _ = self.uart.receive_buffer(buffer, 1);
         });
+        self.run_script_command();
     }
 }
 
@@ -1172,6 +1899,9 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                 self.execute.set(false);
                 self.read_command();
             }
+
+            // Continue the boot script if the command completed.
+            self.run_script_command();
         }
     }
 }
@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the ADC by sampling a known internal reference, such as VREFINT or a
+//! bandgap reference, and checking the measured voltage against its nominal
+//! value. Meant for manufacturing tests: the test fails if the ADC, its
+//! voltage reference or its calibration are off.
+//!
+//! The expected output is
+//! AdcSelfTest: measured 1212 mV, offset -9 mV: passed
+
+use crate::adc::SelfTestReference;
+use crate::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::adc::{AdcChannel, Client};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+pub struct TestAdcSelfTest<'a> {
+    /// Channel measuring the reference; the channel index of `reference` is
+    /// unused.
+    channel: &'a dyn AdcChannel<'a>,
+    reference: SelfTestReference,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a> TestAdcSelfTest<'a> {
+    pub fn new(channel: &'a dyn AdcChannel<'a>, reference: SelfTestReference) -> Self {
+        TestAdcSelfTest {
+            channel,
+            reference,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'a self) {
+        self.channel.set_client(self);
+        if let Err(e) = self.channel.sample() {
+            debug!("AdcSelfTest: failed to start sampling: {:?}", e);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        self.client.map(|client| client.done(result));
+    }
+}
+
+impl Client for TestAdcSelfTest<'_> {
+    fn sample_ready(&self, sample: u32) {
+        let measured_mv = match self.channel.sample_to_mv(sample) {
+            Some(mv) => mv,
+            None => {
+                debug!("AdcSelfTest: unknown voltage reference");
+                self.done(Err(CapsuleTestError::ErrorCode(ErrorCode::NOSUPPORT)));
+                return;
+            }
+        };
+
+        let (passed, offset) = self.reference.check(measured_mv);
+        debug!(
+            "AdcSelfTest: measured {} mV, offset {} mV: {}",
+            measured_mv,
+            offset,
+            if passed { "passed" } else { "FAILED" }
+        );
+        self.done(if passed {
+            Ok(())
+        } else {
+            Err(CapsuleTestError::IncorrectResult)
+        });
+    }
+}
+
+impl CapsuleTest for TestAdcSelfTest<'_> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
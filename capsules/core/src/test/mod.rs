@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+pub mod adc_self_test;
 pub mod alarm;
 pub mod alarm_edge_cases;
 pub mod capsule_test;
@@ -0,0 +1,152 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Request priorities for virtualizer muxes.
+//!
+//! By default, the muxes serve pending requests in list order. A kernel
+//! client with latency requirements (e.g. a radio driver sharing an SPI bus
+//! with a bulk userspace transfer) can tag its virtual device with a higher
+//! `Priority` so that its requests are served first.
+//!
+//! To keep low-priority clients from starving, every time a pending request is
+//! passed over its age is incremented. Once it has been passed over
+//! `STARVATION_LIMIT` times it is served before any request that has not.
+
+use core::cell::Cell;
+
+/// Priority of the requests of a virtual device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low = 0,
+    Normal = 1,
+    High = 2,
+}
+
+/// Number of times a pending request can be passed over in favor of
+/// higher-priority requests before it is served regardless of priority.
+pub const STARVATION_LIMIT: usize = 8;
+
+/// Per-device priority state used by a mux to pick the next request.
+pub struct PriorityTag {
+    priority: Cell<Priority>,
+    passed_over: Cell<usize>,
+}
+
+impl PriorityTag {
+    pub const fn new() -> PriorityTag {
+        PriorityTag {
+            priority: Cell::new(Priority::Normal),
+            passed_over: Cell::new(0),
+        }
+    }
+
+    pub fn set(&self, priority: Priority) {
+        self.priority.set(priority);
+    }
+
+    pub fn get(&self) -> Priority {
+        self.priority.get()
+    }
+
+    fn rank(&self) -> (bool, Priority) {
+        (
+            self.passed_over.get() >= STARVATION_LIMIT,
+            self.priority.get(),
+        )
+    }
+}
+
+/// Select the next node with a pending request.
+///
+/// Starved nodes come first, then nodes are ordered by priority. Nodes of
+/// equal rank are served in list order. Every pending node that is not
+/// selected ages by one.
+pub(crate) fn select_next<'a, T: 'a, I: Iterator<Item = &'a T>>(
+    nodes: impl Fn() -> I,
+    pending: impl Fn(&T) -> bool,
+    tag: impl Fn(&'a T) -> &'a PriorityTag,
+) -> Option<&'a T> {
+    let mut selected: Option<&'a T> = None;
+    for node in nodes().filter(|node| pending(node)) {
+        let better = selected.map_or(true, |current| tag(node).rank() > tag(current).rank());
+        if better {
+            selected = Some(node);
+        }
+    }
+
+    selected.map(|chosen| {
+        for node in nodes().filter(|node| pending(node)) {
+            let node_tag = tag(node);
+            if core::ptr::eq(node, chosen) {
+                node_tag.passed_over.set(0);
+            } else {
+                node_tag
+                    .passed_over
+                    .set(node_tag.passed_over.get().saturating_add(1));
+            }
+        }
+        chosen
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Node {
+        pending: Cell<bool>,
+        tag: PriorityTag,
+    }
+
+    impl Node {
+        fn new(priority: Priority) -> Node {
+            let node = Node {
+                pending: Cell::new(true),
+                tag: PriorityTag::new(),
+            };
+            node.tag.set(priority);
+            node
+        }
+    }
+
+    fn next(nodes: &[Node]) -> Option<usize> {
+        select_next(|| nodes.iter(), |n| n.pending.get(), |n| &n.tag)
+            .map(|n| nodes.iter().position(|m| core::ptr::eq(m, n)).unwrap())
+    }
+
+    #[test]
+    fn list_order_for_equal_priority() {
+        let nodes = [Node::new(Priority::Normal), Node::new(Priority::Normal)];
+        assert_eq!(next(&nodes), Some(0));
+        nodes[0].pending.set(false);
+        assert_eq!(next(&nodes), Some(1));
+    }
+
+    #[test]
+    fn high_priority_first() {
+        let nodes = [
+            Node::new(Priority::Low),
+            Node::new(Priority::Normal),
+            Node::new(Priority::High),
+        ];
+        assert_eq!(next(&nodes), Some(2));
+    }
+
+    #[test]
+    fn starved_node_is_served() {
+        let nodes = [Node::new(Priority::Low), Node::new(Priority::High)];
+        for _ in 0..STARVATION_LIMIT {
+            assert_eq!(next(&nodes), Some(1));
+        }
+        assert_eq!(next(&nodes), Some(0));
+        assert_eq!(next(&nodes), Some(1));
+    }
+
+    #[test]
+    fn nothing_pending() {
+        let nodes = [Node::new(Priority::High)];
+        nodes[0].pending.set(false);
+        assert_eq!(next(&nodes), None);
+    }
+}
@@ -4,30 +4,116 @@
 
 //! Virtual ADC Capsule
 //!
-//! Support Single Sample for now.
+//! Support Single Sample for now, on single-ended channels and on
+//! differential pairs of channels, and Buffered Sampling on single-ended
+//! channels when the ADC supports high-speed sampling.
+//!
+//! A buffered sampling operation fills one buffer at the requested
+//! frequency, then releases the ADC to the next device. Long or continuous
+//! acquisitions are made of several operations, so samples are not
+//! contiguous in time across buffers.
+//!
+//! A device given a periodic alarm with `AdcDevice::set_periodic_alarm` can
+//! also sample its channel at a fixed interval: each period queues a single
+//! sample, reported to the `PeriodicClient` of the device.
+
+use core::cell::Cell;
 
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil;
-use kernel::utilities::cells::OptionalCell;
+use kernel::hil::time::{ConvertTicks, PeriodicAlarm};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
+use crate::virtualizers::priority::{self, Priority, PriorityTag};
+
 /// ADC Mux
 pub struct MuxAdc<'a, A: hil::adc::Adc<'a>> {
     adc: &'a A,
     devices: List<'a, AdcDevice<'a, A>>,
     inflight: OptionalCell<&'a AdcDevice<'a, A>>,
+    /// High-speed interface of the ADC, set if buffered sampling is enabled.
+    highspeed: OptionalCell<&'a dyn BufferedAdc<A::Channel>>,
+}
+
+/// The high-speed operations of the ADC used by the mux. `AdcHighSpeed`
+/// cannot be used as a trait object, as its channel type is `PartialEq`.
+trait BufferedAdc<C> {
+    /// Fill a single buffer with samples of `channel`.
+    fn start(
+        &self,
+        channel: &C,
+        frequency: u32,
+        buffer: &'static mut [u16],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])>;
+
+    /// Stop sampling, and return the buffer if the ADC still holds it.
+    fn stop(&self) -> Option<&'static mut [u16]>;
+}
+
+impl<'a, A: hil::adc::AdcHighSpeed<'a>> BufferedAdc<A::Channel> for A {
+    fn start(
+        &self,
+        channel: &A::Channel,
+        frequency: u32,
+        buffer: &'static mut [u16],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])> {
+        // The second buffer is empty: the mux stops the ADC when the first
+        // one is filled.
+        self.sample_highspeed(channel, frequency, buffer, length, &mut [], 0)
+            .map_err(|(err, buffer, _)| (err, buffer))
+    }
+
+    fn stop(&self) -> Option<&'static mut [u16]> {
+        let _ = self.stop_sampling();
+        self.retrieve_buffers().ok().and_then(|(buffer, _)| buffer)
+    }
+}
+
+/// The periodic alarm of a device. `PeriodicAlarm` cannot be used as a trait
+/// object, as its ticks are an associated type.
+trait SampleTimer {
+    fn start(&self, interval_ms: u32);
+
+    fn stop(&self);
+}
+
+impl<'a, T: PeriodicAlarm<'a>> SampleTimer for T {
+    fn start(&self, interval_ms: u32) {
+        self.set_periodic_alarm(self.now(), self.ticks_from_ms(interval_ms));
+    }
+
+    fn stop(&self) {
+        let _ = self.disarm();
+    }
 }
 
 impl<'a, A: hil::adc::Adc<'a>> hil::adc::Client for MuxAdc<'a, A> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
         self.inflight.take().map(|inflight| {
+            // The sample is shared by the devices waiting for a sample of
+            // the same channel.
             for node in self.devices.iter() {
-                if node.channel == inflight.channel {
-                    node.operation.take().map(|operation| match operation {
-                        Operation::OneSample => {
-                            node.client.map(|client| client.sample_ready(sample))
+                if node.channel != inflight.channel || node.negative != inflight.negative {
+                    continue;
+                }
+                match node.operation.get() {
+                    Some(Operation::OneSample) => {
+                        node.operation.clear();
+                        node.client.map(|client| client.sample_ready(sample));
+                    }
+                    Some(Operation::Periodic) => {
+                        node.operation.clear();
+                        // The sample is dropped if the periodic sampling was
+                        // stopped while it was in progress.
+                        if let Some(id) = node.periodic_id.get() {
+                            node.periodic_client
+                                .map(|client| client.periodic_sample_ready(id, sample));
                         }
-                    });
+                    }
+                    _ => {}
                 }
             }
         });
@@ -35,55 +121,191 @@ impl<'a, A: hil::adc::Adc<'a>> hil::adc::Client for MuxAdc<'a, A> {
     }
 }
 
+impl<'a, A: hil::adc::Adc<'a>> hil::adc::HighSpeedClient for MuxAdc<'a, A> {
+    fn samples_ready(&self, buf: &'static mut [u16], length: usize) {
+        // Each operation fills a single buffer: stop the ADC before it
+        // samples into the empty second buffer.
+        self.highspeed.map(|adc| adc.stop());
+        match self.inflight.take() {
+            Some(node) => {
+                node.operation.clear();
+                // Start the queued operations before the client can request
+                // another buffer, so that it does not keep the ADC.
+                self.do_next_op();
+                node.highspeed_client
+                    .map(|client| client.samples_ready(buf, length));
+            }
+            None => self.do_next_op(),
+        }
+    }
+}
+
 impl<'a, A: hil::adc::Adc<'a>> MuxAdc<'a, A> {
     pub const fn new(adc: &'a A) -> MuxAdc<'a, A> {
         MuxAdc {
             adc: adc,
             devices: List::new(),
             inflight: OptionalCell::empty(),
+            highspeed: OptionalCell::empty(),
         }
     }
 
     fn do_next_op(&self) {
         if self.inflight.is_none() {
-            let mnode = self.devices.iter().find(|node| node.operation.is_some());
+            let mnode = priority::select_next(
+                || self.devices.iter(),
+                |node| node.operation.is_some(),
+                |node| &node.priority,
+            );
             mnode.map(|node| {
-                let started = node.operation.map_or(false, |operation| match operation {
-                    Operation::OneSample => {
-                        let _ = self.adc.sample(&node.channel);
-                        true
+                let operation = node.operation.get();
+                if let Err(err) = self.start(node) {
+                    match operation {
+                        // The buffer of a failed buffered operation is
+                        // returned to the client without samples.
+                        Some(Operation::Buffer) => {
+                            node.buffer.take().map(|buffer| {
+                                node.highspeed_client
+                                    .map(|client| client.samples_ready(buffer, 0))
+                            });
+                        }
+                        Some(Operation::OneSample) => {
+                            node.client.map(|client| client.sample_failed(err));
+                        }
+                        // The period is skipped, the next one tries again.
+                        Some(Operation::Periodic) | None => {}
                     }
-                });
-                if started {
-                    self.inflight.set(node);
-                } else {
                     self.do_next_op();
                 }
             });
         }
     }
 
+    /// Start the operation of `node`. If it fails, the operation is cleared,
+    /// and the buffer of a buffered operation is kept by `node`.
+    fn start(&self, node: &'a AdcDevice<'a, A>) -> Result<(), ErrorCode> {
+        let result = match node.operation.get() {
+            Some(Operation::OneSample | Operation::Periodic) => match &node.negative {
+                Some(negative) => self.adc.sample_differential(&node.channel, negative),
+                None => self.adc.sample(&node.channel),
+            },
+            Some(Operation::Buffer) => self.start_buffer(node),
+            None => Err(ErrorCode::FAIL),
+        };
+        match result {
+            Ok(()) => self.inflight.set(node),
+            Err(_) => node.operation.clear(),
+        }
+        result
+    }
+
+    fn start_buffer(&self, node: &'a AdcDevice<'a, A>) -> Result<(), ErrorCode> {
+        let adc = self.highspeed.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let buffer = node.buffer.take().ok_or(ErrorCode::NOMEM)?;
+        adc.start(
+            &node.channel,
+            node.frequency.get(),
+            buffer,
+            node.length.get(),
+        )
+        .map_err(|(err, buffer)| {
+            node.buffer.replace(buffer);
+            err
+        })
+    }
+
+    /// Stop the buffered operation of `node` if the ADC is running it, and
+    /// give the buffer back to `node`.
+    fn stop_buffer(&self, node: &AdcDevice<'a, A>) {
+        if self
+            .inflight
+            .map_or(false, |inflight| core::ptr::eq(inflight, node))
+        {
+            self.highspeed
+                .and_then(|adc| adc.stop())
+                .map(|buffer| node.buffer.replace(buffer));
+            self.inflight.clear();
+        }
+    }
+
     pub fn get_resolution_bits(&self) -> usize {
         self.adc.get_resolution_bits()
     }
 
+    pub fn get_sample_width_bits(&self) -> usize {
+        self.adc.get_sample_width_bits()
+    }
+
+    /// Set the resolution of the ADC, which applies to all the devices. It
+    /// cannot change while an operation is in progress or pending.
+    pub fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        if self.inflight.is_some() || self.devices.iter().any(|node| node.operation.is_some()) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.adc.set_resolution_bits(bits)
+    }
+
     pub fn get_voltage_reference_mv(&self) -> Option<usize> {
         self.adc.get_voltage_reference_mv()
     }
+
+    pub fn sample_to_mv(&self, sample: u32) -> Option<usize> {
+        self.adc.sample_to_mv(sample)
+    }
+
+    pub fn set_channel_gain_and_reference(
+        &self,
+        channel: &A::Channel,
+        gain: hil::adc::Gain,
+        reference: hil::adc::Reference,
+    ) -> Result<(), ErrorCode> {
+        self.adc
+            .set_channel_gain_and_reference(channel, gain, reference)
+    }
+
+    pub fn get_channel_voltage_reference_mv(&self, channel: &A::Channel) -> Option<usize> {
+        self.adc.get_channel_voltage_reference_mv(channel)
+    }
+}
+
+impl<'a, A: hil::adc::AdcHighSpeed<'a>> MuxAdc<'a, A> {
+    /// Let the devices sample buffers, with the high-speed interface of the
+    /// ADC. The mux becomes the high-speed client of the ADC.
+    pub fn enable_highspeed(&'a self) {
+        self.highspeed.set(self.adc);
+        self.adc.set_highspeed_client(self);
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
 pub(crate) enum Operation {
     OneSample,
+    Buffer,
+    /// The single sample of a period of periodic sampling.
+    Periodic,
 }
 
 /// Virtual ADC device
 pub struct AdcDevice<'a, A: hil::adc::Adc<'a>> {
     mux: &'a MuxAdc<'a, A>,
     channel: A::Channel,
+    /// Negative channel of a differential pair.
+    negative: Option<A::Channel>,
     operation: OptionalCell<Operation>,
+    priority: PriorityTag,
     next: ListLink<'a, AdcDevice<'a, A>>,
     client: OptionalCell<&'a dyn hil::adc::Client>,
+    /// Buffer of a buffered operation, until the mux starts it.
+    buffer: TakeCell<'static, [u16]>,
+    length: Cell<usize>,
+    frequency: Cell<u32>,
+    highspeed_client: OptionalCell<&'a dyn hil::adc::HighSpeedClient>,
+    /// Alarm of periodic sampling, if the device supports it.
+    timer: OptionalCell<&'a dyn SampleTimer>,
+    /// Identifier given to `sample_periodic`, set while periodic sampling
+    /// runs.
+    periodic_id: OptionalCell<usize>,
+    periodic_client: OptionalCell<&'a dyn hil::adc::PeriodicClient>,
 }
 
 impl<'a, A: hil::adc::Adc<'a>> AdcDevice<'a, A> {
@@ -91,16 +313,74 @@ impl<'a, A: hil::adc::Adc<'a>> AdcDevice<'a, A> {
         let adc_user = AdcDevice {
             mux: mux,
             channel: channel,
+            negative: None,
             operation: OptionalCell::empty(),
+            priority: PriorityTag::new(),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            length: Cell::new(0),
+            frequency: Cell::new(0),
+            highspeed_client: OptionalCell::empty(),
+            timer: OptionalCell::empty(),
+            periodic_id: OptionalCell::empty(),
+            periodic_client: OptionalCell::empty(),
         };
         adc_user
     }
 
+    /// Create a device sampling the voltage of `positive` minus the voltage
+    /// of `negative`.
+    pub const fn new_differential(
+        mux: &'a MuxAdc<'a, A>,
+        positive: A::Channel,
+        negative: A::Channel,
+    ) -> AdcDevice<'a, A> {
+        AdcDevice {
+            mux,
+            channel: positive,
+            negative: Some(negative),
+            operation: OptionalCell::empty(),
+            priority: PriorityTag::new(),
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            length: Cell::new(0),
+            frequency: Cell::new(0),
+            highspeed_client: OptionalCell::empty(),
+            timer: OptionalCell::empty(),
+            periodic_id: OptionalCell::empty(),
+            periodic_client: OptionalCell::empty(),
+        }
+    }
+
     pub fn add_to_mux(&'a self) {
         self.mux.devices.push_head(self);
     }
+
+    /// Set the priority of this device's requests relative to the other
+    /// devices sharing the mux.
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.set(priority);
+    }
+
+    /// Let the device sample its channel periodically, with `alarm`. The
+    /// device becomes the client of the alarm.
+    pub fn set_periodic_alarm<T: PeriodicAlarm<'a>>(&'a self, alarm: &'a T) {
+        self.timer.set(alarm);
+        alarm.set_alarm_client(self);
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a>> hil::time::AlarmClient for AdcDevice<'a, A> {
+    fn alarm(&self) {
+        // The period is skipped if the previous operation of the device is
+        // not done yet.
+        if self.periodic_id.is_some() && self.operation.is_none() {
+            self.operation.set(Operation::Periodic);
+            self.mux.do_next_op();
+        }
+    }
 }
 
 impl<'a, A: hil::adc::Adc<'a>> ListNode<'a, AdcDevice<'a, A>> for AdcDevice<'a, A> {
@@ -110,14 +390,44 @@ impl<'a, A: hil::adc::Adc<'a>> ListNode<'a, AdcDevice<'a, A>> for AdcDevice<'a,
 }
 
 impl<'a, A: hil::adc::Adc<'a>> hil::adc::AdcChannel<'a> for AdcDevice<'a, A> {
+    /// If the ADC is free, the sample starts now and its errors are returned.
+    /// Otherwise it is queued, and `Client::sample_failed` reports if it
+    /// cannot be started. Fails with `BUSY` while another operation of the
+    /// device, including the sample of a period, is pending.
     fn sample(&self) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
         self.operation.set(Operation::OneSample);
-        self.mux.do_next_op();
+        if self.mux.inflight.is_none() {
+            let node = self
+                .mux
+                .devices
+                .iter()
+                .find(|node| core::ptr::eq(*node, self));
+            if let Some(Err(err)) = node.map(|node| self.mux.start(node)) {
+                return Err(err);
+            }
+        }
         Ok(())
     }
 
+    /// Stop the single sample or buffered operation of the device. Periodic
+    /// sampling is only stopped by `stop_periodic`, so that stopping the
+    /// operation of one user of the channel does not end it for another.
     fn stop_sampling(&self) -> Result<(), ErrorCode> {
-        self.operation.clear();
+        match self.operation.get() {
+            Some(Operation::Buffer) => {
+                self.mux.stop_buffer(self);
+                self.operation.clear();
+            }
+            Some(Operation::OneSample) => self.operation.clear(),
+            Some(Operation::Periodic) | None => {}
+        }
+        self.buffer.take().map(|buffer| {
+            self.highspeed_client
+                .map(|client| client.samples_ready(buffer, 0))
+        });
         self.mux.do_next_op();
         Ok(())
     }
@@ -130,10 +440,107 @@ impl<'a, A: hil::adc::Adc<'a>> hil::adc::AdcChannel<'a> for AdcDevice<'a, A> {
         self.mux.get_resolution_bits()
     }
 
+    fn get_sample_width_bits(&self) -> usize {
+        self.mux.get_sample_width_bits()
+    }
+
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        self.mux.set_resolution_bits(bits)
+    }
+
+    /// The full-scale voltage of the channel, with its gain and reference.
     fn get_voltage_reference_mv(&self) -> Option<usize> {
-        self.mux.get_voltage_reference_mv()
+        self.mux.get_channel_voltage_reference_mv(&self.channel)
     }
+
+    /// The gain and reference cannot change while an operation of the
+    /// device is pending or in progress. They apply to all the devices of
+    /// the same channel.
+    fn set_gain_and_reference(
+        &self,
+        gain: hil::adc::Gain,
+        reference: hil::adc::Reference,
+    ) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.mux
+            .set_channel_gain_and_reference(&self.channel, gain, reference)
+    }
+
+    fn is_differential(&self) -> bool {
+        self.negative.is_some()
+    }
+
+    fn sample_buffer(
+        &self,
+        frequency: u32,
+        buffer: &'static mut [u16],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])> {
+        if self.mux.highspeed.is_none() || self.negative.is_some() {
+            return Err((ErrorCode::NOSUPPORT, buffer));
+        }
+        if self.operation.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if length == 0 || length > buffer.len() {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        self.buffer.replace(buffer);
+        self.length.set(length);
+        self.frequency.set(frequency);
+        self.operation.set(Operation::Buffer);
+        if self.mux.inflight.is_none() {
+            // The ADC is free: the operation starts now, and its errors are
+            // returned to the caller.
+            let node = self
+                .mux
+                .devices
+                .iter()
+                .find(|node| core::ptr::eq(*node, self));
+            if let Some(Err(err)) = node.map(|node| self.mux.start(node)) {
+                return Err((err, self.buffer.take().unwrap_or(&mut [])));
+            }
+        }
+        Ok(())
+    }
+
+    fn sample_periodic(&self, interval_ms: u32, id: usize) -> Result<(), ErrorCode> {
+        let timer = self.timer.get().ok_or(ErrorCode::NOSUPPORT)?;
+        if interval_ms == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.periodic_id.set(id);
+        timer.start(interval_ms);
+        Ok(())
+    }
+
+    fn stop_periodic(&self) -> Result<(), ErrorCode> {
+        let timer = self.timer.get().ok_or(ErrorCode::NOSUPPORT)?;
+        self.periodic_id.clear();
+        timer.stop();
+        // A sample already started completes without being reported.
+        if self.operation.contains(&Operation::Periodic)
+            && !self
+                .mux
+                .inflight
+                .map_or(false, |inflight| core::ptr::eq(inflight, self))
+        {
+            self.operation.clear();
+        }
+        Ok(())
+    }
+
     fn set_client(&self, client: &'a dyn hil::adc::Client) {
         self.client.set(client);
     }
+
+    fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
+        self.highspeed_client.set(client);
+    }
+
+    fn set_periodic_client(&self, client: &'a dyn hil::adc::PeriodicClient) {
+        self.periodic_client.set(client);
+    }
 }
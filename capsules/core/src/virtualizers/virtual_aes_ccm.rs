@@ -83,7 +83,6 @@
 use core::cell::Cell;
 
 use kernel::collections::list::{List, ListLink, ListNode};
-use kernel::debug;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::symmetric_encryption;
 use kernel::hil::symmetric_encryption::{
@@ -177,10 +176,16 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> MuxAES128CCM<'a, A>
                     // notice that we didn't put the parameters back...
                     // because it's already eaten
                     if node.ccm_client.is_none() {
-                        debug!("virtual_aes_ccm: no ccm_client is registered in VirtualAES128CCM");
+                        kernel::log_error!(
+                            Crypto,
+                            "virtual_aes_ccm: no ccm_client is registered in VirtualAES128CCM"
+                        );
                     }
                     if node.buf.is_none() {
-                        debug!("virtual_aes_ccm: no buffer is binded with VirtualAES128CCM");
+                        kernel::log_error!(
+                            Crypto,
+                            "virtual_aes_ccm: no buffer is bound to VirtualAES128CCM"
+                        );
                     }
                     // notify the client that there's a failure
                     node.buf.take().map(|buf| {
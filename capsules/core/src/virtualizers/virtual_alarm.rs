@@ -4,11 +4,15 @@
 
 //! Virtualize the Alarm interface to enable multiple users of an underlying
 //! alarm hardware peripheral.
+//!
+//! Virtual alarms also implement `PeriodicAlarm`: periodic alarms are re-armed
+//! by the mux when they fire, from their previous expiration, so their period
+//! does not drift with the latency of the client callbacks.
 
 use core::cell::Cell;
 
 use kernel::collections::list::{List, ListLink, ListNode};
-use kernel::hil::time::{self, Alarm, Ticks, Time};
+use kernel::hil::time::{self, Alarm, PeriodicAlarm, Ticks, Time};
 use kernel::utilities::cells::OptionalCell;
 use kernel::ErrorCode;
 
@@ -46,6 +50,8 @@ pub struct VirtualMuxAlarm<'a, A: Alarm<'a>> {
     next: ListLink<'a, VirtualMuxAlarm<'a, A>>,
     /// Alarm client for this node in the list.
     client: OptionalCell<&'a dyn time::AlarmClient>,
+    /// Period of the alarm, if it is a periodic alarm.
+    period: OptionalCell<A::Ticks>,
 }
 
 impl<'a, A: Alarm<'a>> ListNode<'a, VirtualMuxAlarm<'a, A>> for VirtualMuxAlarm<'a, A> {
@@ -68,6 +74,7 @@ impl<'a, A: Alarm<'a>> VirtualMuxAlarm<'a, A> {
             armed: Cell::new(false),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
+            period: OptionalCell::empty(),
         }
     }
 
@@ -76,52 +83,15 @@ impl<'a, A: Alarm<'a>> VirtualMuxAlarm<'a, A> {
     pub fn setup(&'a self) {
         self.mux.virtual_alarms.push_head(self);
     }
-}
-
-impl<'a, A: Alarm<'a>> Time for VirtualMuxAlarm<'a, A> {
-    type Frequency = A::Frequency;
-    type Ticks = A::Ticks;
-
-    fn now(&self) -> Self::Ticks {
-        self.mux.alarm.now()
-    }
-}
-
-impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
-    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
-        self.client.set(client);
-    }
-
-    fn disarm(&self) -> Result<(), ErrorCode> {
-        if !self.armed.get() {
-            return Ok(());
-        }
-
-        self.armed.set(false);
-
-        let enabled = self.mux.enabled.get() - 1;
-        self.mux.enabled.set(enabled);
-
-        // If there are not more enabled alarms, disable the underlying alarm
-        // completely.
-        if enabled == 0 {
-            let _ = self.mux.alarm.disarm();
-        }
-        Ok(())
-    }
-
-    fn is_armed(&self) -> bool {
-        self.armed.get()
-    }
 
-    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
-        let enabled = self.mux.enabled.get();
-        let half_max = Self::Ticks::half_max_value();
+    /// Compute the internal reference and dt of an alarm firing at `reference + dt`.
+    fn tick_dt_reference(&self, reference: A::Ticks, dt: A::Ticks) -> TickDtReference<A::Ticks> {
+        let half_max = A::Ticks::half_max_value();
         // If the dt is more than half of the available time resolution, then we need to break
         // up the alarm into two internal alarms. This ensures that our internal comparisons of
         // now outside of range [ref, ref + dt) will trigger correctly even with latency in the
         // system
-        let dt_reference = if dt > half_max.wrapping_add(self.minimum_dt()) {
+        if dt > half_max.wrapping_add(self.minimum_dt()) {
             TickDtReference {
                 reference,
                 dt: dt.wrapping_sub(half_max),
@@ -133,7 +103,12 @@ impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
                 dt,
                 extended: false,
             }
-        };
+        }
+    }
+
+    fn arm(&self, reference: A::Ticks, dt: A::Ticks) {
+        let enabled = self.mux.enabled.get();
+        let dt_reference = self.tick_dt_reference(reference, dt);
         self.dt_reference.set(dt_reference);
         // Ensure local variable has correct value when used below
         let dt = dt_reference.dt;
@@ -176,6 +151,49 @@ impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
             }
         }
     }
+}
+
+impl<'a, A: Alarm<'a>> Time for VirtualMuxAlarm<'a, A> {
+    type Frequency = A::Frequency;
+    type Ticks = A::Ticks;
+
+    fn now(&self) -> Self::Ticks {
+        self.mux.alarm.now()
+    }
+}
+
+impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.period.clear();
+        if !self.armed.get() {
+            return Ok(());
+        }
+
+        self.armed.set(false);
+
+        let enabled = self.mux.enabled.get() - 1;
+        self.mux.enabled.set(enabled);
+
+        // If there are not more enabled alarms, disable the underlying alarm
+        // completely.
+        if enabled == 0 {
+            let _ = self.mux.alarm.disarm();
+        }
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+        self.period.clear();
+        self.arm(reference, dt);
+    }
 
     fn get_alarm(&self) -> Self::Ticks {
         let dt_reference = self.dt_reference.get();
@@ -192,6 +210,18 @@ impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualMuxAlarm<'a, A> {
     }
 }
 
+impl<'a, A: Alarm<'a>> PeriodicAlarm<'a> for VirtualMuxAlarm<'a, A> {
+    fn set_periodic_alarm(&self, reference: Self::Ticks, period: Self::Ticks) {
+        let period = core::cmp::max(period, Self::Ticks::from(1));
+        self.period.set(period);
+        self.arm(reference, period);
+    }
+
+    fn period(&self) -> Option<Self::Ticks> {
+        self.period.get()
+    }
+}
+
 impl<'a, A: Alarm<'a>> time::AlarmClient for VirtualMuxAlarm<'a, A> {
     fn alarm(&self) {
         self.client.map(|client| client.alarm());
@@ -238,8 +268,9 @@ impl<'a, A: Alarm<'a>> time::AlarmClient for MuxAlarm<'a, A> {
     /// When the underlying alarm has fired, we have to multiplex this event back to the virtual
     /// alarms that should now fire.
     fn alarm(&self) {
-        // Check whether to fire each alarm. At this level, alarms are one-shot,
-        // so a repeating client will set it again in the alarm() callback.
+        // Check whether to fire each alarm. Periodic alarms are re-armed here,
+        // other alarms are one-shot, so a repeating client will set it again
+        // in the alarm() callback.
         self.firing.set(true);
         self.virtual_alarms
             .iter()
@@ -261,6 +292,12 @@ impl<'a, A: Alarm<'a>> time::AlarmClient for MuxAlarm<'a, A> {
                         dt: A::Ticks::half_max_value(),
                         extended: false,
                     });
+                } else if let Some(period) = cur.period.get() {
+                    // Periodic alarm expired, re-arm it from this expiration (not from now) so
+                    // the callback latency does not accumulate, and fire callback
+                    cur.dt_reference
+                        .set(cur.tick_dt_reference(dt_ref.reference_plus_dt(), period));
+                    cur.alarm();
                 } else {
                     // Alarm fully expired, disarm and fire callback
                     cur.armed.set(false);
@@ -536,6 +573,77 @@ mod tests {
         assert!(!still_armed);
     }
 
+    struct DisarmAfterClient<'a> {
+        alarm: &'a VirtualMuxAlarm<'a, FakeAlarm<'a>>,
+        remaining: Cell<usize>,
+    }
+
+    impl AlarmClient for DisarmAfterClient<'_> {
+        fn alarm(&self) {
+            let remaining = self.remaining.get() - 1;
+            self.remaining.set(remaining);
+            if remaining == 0 {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    #[test]
+    fn test_periodic_alarm_does_not_drift() {
+        let alarm = FakeAlarm::new();
+        let mux = MuxAlarm::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        let valarm = VirtualMuxAlarm::new(&mux);
+        valarm.setup();
+        let client = ClientCounter::new();
+        valarm.set_alarm_client(&client);
+
+        let reference = valarm.now();
+        valarm.set_periodic_alarm(reference, 100.into());
+        assert_eq!(valarm.period(), Some(100.into()));
+
+        // Every expiration is only handled after the hardware delay, but the
+        // next expiration is still computed from the previous one.
+        for fired in 1..=10 {
+            assert!(alarm.trigger_next_alarm());
+            assert_eq!(client.count(), fired);
+            assert_eq!(
+                valarm.get_alarm(),
+                reference.wrapping_add(Ticks32::from(100 * (fired as u32 + 1)))
+            );
+        }
+
+        // Switching back to a one-shot alarm stops the periodic behaviour.
+        valarm.set_alarm(valarm.now(), 100.into());
+        assert_eq!(valarm.period(), None);
+        run_until_disarmed(&alarm);
+        assert_eq!(client.count(), 11);
+        assert!(!valarm.is_armed());
+    }
+
+    #[test]
+    fn test_periodic_alarm_disarmed_from_callback() {
+        let alarm = FakeAlarm::new();
+        let mux = MuxAlarm::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        let valarm = VirtualMuxAlarm::new(&mux);
+        valarm.setup();
+        let client = DisarmAfterClient {
+            alarm: &valarm,
+            remaining: Cell::new(3),
+        };
+        valarm.set_alarm_client(&client);
+        valarm.set_periodic_alarm(valarm.now(), 10.into());
+
+        run_until_disarmed(&alarm);
+
+        assert_eq!(client.remaining.get(), 0);
+        assert!(!valarm.is_armed());
+        assert_eq!(valarm.period(), None);
+    }
+
     #[test]
     fn test_quick_alarms_not_skipped() {
         let alarm = FakeAlarm::new();
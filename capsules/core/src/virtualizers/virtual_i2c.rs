@@ -13,6 +13,9 @@ use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::i2c::{self, Error, I2CClient, I2CHwMasterClient, NoSMBus};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+use crate::virtualizers::priority::{self, Priority, PriorityTag};
+
 // `NoSMBus` provides a placeholder for `SMBusMaster` in case the board doesn't have a SMBus
 pub struct MuxI2C<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a> = NoSMBus> {
     i2c: &'a I,
@@ -75,10 +78,11 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
             // Nothing is currently in flight
 
             // Try to do the next I2C operation
-            let mnode = self
-                .i2c_devices
-                .iter()
-                .find(|node| node.operation.get() != Op::Idle);
+            let mnode = priority::select_next(
+                || self.i2c_devices.iter(),
+                |node| node.operation.get() != Op::Idle,
+                |node| &node.priority,
+            );
             mnode.map(|node| {
                 node.buffer.take().map(|buf| {
                     match node.operation.get() {
@@ -120,10 +124,11 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
 
             if self.i2c_inflight.is_none() && self.smbus.is_some() {
                 // No I2C operation in flight, try SMBus next
-                let mnode = self
-                    .smbus_devices
-                    .iter()
-                    .find(|node| node.operation.get() != Op::Idle);
+                let mnode = priority::select_next(
+                    || self.smbus_devices.iter(),
+                    |node| node.operation.get() != Op::Idle,
+                    |node| &node.priority,
+                );
                 mnode.map(|node| {
                     node.buffer.take().map(|buf| match node.operation.get() {
                         Op::Write(len) => {
@@ -210,6 +215,7 @@ pub struct I2CDevice<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a> = NoSMBu
     enabled: Cell<bool>,
     buffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
+    priority: PriorityTag,
     next: ListLink<'a, I2CDevice<'a, I, S>>,
     client: OptionalCell<&'a dyn I2CClient>,
 }
@@ -222,6 +228,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CDevice<'a, I, S> {
             enabled: Cell::new(false),
             buffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            priority: PriorityTag::new(),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -231,6 +238,12 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CDevice<'a, I, S> {
         self.mux.i2c_devices.push_head(self);
         self.client.set(client);
     }
+
+    /// Set the priority of this device's requests relative to the other
+    /// devices sharing the mux.
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.set(priority);
+    }
 }
 
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CClient for I2CDevice<'a, I, S> {
@@ -313,6 +326,7 @@ pub struct SMBusDevice<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> {
     enabled: Cell<bool>,
     buffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
+    priority: PriorityTag,
     next: ListLink<'a, SMBusDevice<'a, I, S>>,
     client: OptionalCell<&'a dyn I2CClient>,
 }
@@ -329,6 +343,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> SMBusDevice<'a, I, S> {
             enabled: Cell::new(false),
             buffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            priority: PriorityTag::new(),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -338,6 +353,12 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> SMBusDevice<'a, I, S> {
         self.mux.smbus_devices.push_head(self);
         self.client.set(client);
     }
+
+    /// Set the priority of this device's requests relative to the other
+    /// devices sharing the mux.
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.set(priority);
+    }
 }
 
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CClient for SMBusDevice<'a, I, S> {
@@ -12,6 +12,8 @@ use kernel::hil::spi::SpiMasterClient;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
+use crate::virtualizers::priority::{self, Priority, PriorityTag};
+
 /// The Mux struct manages multiple Spi clients. Each client may have
 /// at most one outstanding Spi request.
 pub struct MuxSpiMaster<'a, Spi: hil::spi::SpiMaster<'a>> {
@@ -53,10 +55,11 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
 
     fn do_next_op(&self) {
         if self.inflight.is_none() {
-            let mnode = self
-                .devices
-                .iter()
-                .find(|node| node.operation.get() != Op::Idle);
+            let mnode = priority::select_next(
+                || self.devices.iter(),
+                |node| node.operation.get() != Op::Idle,
+                |node| &node.priority,
+            );
             mnode.map(|node| {
                 let configuration = node.configuration.get();
                 let cs = configuration.chip_select;
@@ -174,6 +177,7 @@ pub struct VirtualSpiMasterDevice<'a, Spi: hil::spi::SpiMaster<'a>> {
     txbuffer: TakeCell<'static, [u8]>,
     rxbuffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
+    priority: PriorityTag,
     next: ListLink<'a, VirtualSpiMasterDevice<'a, Spi>>,
     client: OptionalCell<&'a dyn hil::spi::SpiMasterClient>,
 }
@@ -194,6 +198,7 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> VirtualSpiMasterDevice<'a, Spi> {
             txbuffer: TakeCell::empty(),
             rxbuffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            priority: PriorityTag::new(),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -203,6 +208,12 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> VirtualSpiMasterDevice<'a, Spi> {
     pub fn setup(&'a self) {
         self.mux.devices.push_head(self);
     }
+
+    /// Set the priority of this device's requests relative to the other
+    /// devices sharing the mux.
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.set(priority);
+    }
 }
 
 impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient
@@ -54,6 +54,8 @@ use kernel::hil::uart;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
+use crate::virtualizers::priority::{self, Priority, PriorityTag};
+
 pub const RX_BUF_LEN: usize = 64;
 
 pub struct MuxUart<'a> {
@@ -235,7 +237,11 @@ impl<'a> MuxUart<'a> {
 
     fn do_next_op(&self) {
         if self.inflight.is_none() {
-            let mnode = self.devices.iter().find(|node| node.operation.is_some());
+            let mnode = priority::select_next(
+                || self.devices.iter(),
+                |node| node.operation.is_some(),
+                |node| &node.priority,
+            );
             mnode.map(|node| {
                 node.tx_buffer.take().map(|buf| {
                     node.operation.take().map(move |op| match op {
@@ -347,6 +353,7 @@ pub struct UartDevice<'a> {
     rx_position: Cell<usize>,
     rx_len: Cell<usize>,
     operation: OptionalCell<Operation>,
+    priority: PriorityTag,
     next: ListLink<'a, UartDevice<'a>>,
     rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
     tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
@@ -364,6 +371,7 @@ impl<'a> UartDevice<'a> {
             rx_position: Cell::new(0),
             rx_len: Cell::new(0),
             operation: OptionalCell::empty(),
+            priority: PriorityTag::new(),
             next: ListLink::empty(),
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
@@ -374,6 +382,12 @@ impl<'a> UartDevice<'a> {
     pub fn setup(&'a self) {
         self.mux.devices.push_head(self);
     }
+
+    /// Set the priority of this device's requests relative to the other
+    /// devices sharing the mux.
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.set(priority);
+    }
 }
 
 impl<'a> uart::TransmitClient for UartDevice<'a> {
@@ -0,0 +1,285 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Low-power scheduled ADC acquisition.
+//!
+//! Data loggers running on batteries for years sample a sensor at a low rate
+//! (every few seconds or minutes), and spend most of their energy waking up
+//! the application for every sample. This capsule samples an ADC channel on a
+//! periodic (drift-free) alarm, typically backed by the RTC or a low-power
+//! timer, and stores the samples in a kernel buffer. The application is only
+//! woken up once a batch of samples has been collected: in between, the chip
+//! sleeps, except for the short ADC conversions.
+//!
+//! Only one process can run an acquisition at a time.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! Buffer `0` receives the batches of samples, as little-endian `u16` values.
+//!
+//! ### `subscribe` System Call
+//!
+//! Subscribe `0` is called with every batch of samples, with:
+//!
+//! - the number of samples written to the buffer,
+//! - the number of samples dropped because the buffer was too small.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Start an acquisition with one sample every `data` milliseconds,
+//!   delivered in batches of `data2` samples. Restarts the acquisition if it
+//!   is already running for the process. Returns `BUSY` if another process
+//!   runs an acquisition, and `SIZE` if the batch is larger than the kernel
+//!   buffer.
+//! - `2`: Stop the acquisition. The samples not delivered yet are dropped.
+//! - `3`: Deliver the samples collected so far without waiting for a full
+//!   batch.
+//! - `4`: Get the largest batch size supported.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let adc_acquisition = static_init!(
+//!     capsules_extra::adc_acquisition::AdcAcquisition<
+//!         'static,
+//!         VirtualMuxAlarm<'static, stm32f429zi::tim2::Tim2>,
+//!         AdcDevice<'static, stm32f429zi::adc::Adc>,
+//!     >,
+//!     capsules_extra::adc_acquisition::AdcAcquisition::new(
+//!         acquisition_alarm,
+//!         adc_channel,
+//!         static_init!([u16; 64], [0; 64]),
+//!         board_kernel.create_grant(
+//!             capsules_extra::adc_acquisition::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! acquisition_alarm.set_alarm_client(adc_acquisition);
+//! adc_channel.set_client(adc_acquisition);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::adc::{self, AdcChannel};
+use kernel::hil::time::{self, ConvertTicks, PeriodicAlarm};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AdcAcquisition as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Destination of the batches of samples.
+    pub const SAMPLES: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// A batch of samples is available.
+    pub const BATCH: usize = 0;
+    /// The number of subscribe upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct AdcAcquisition<'a, A: PeriodicAlarm<'a>, C: AdcChannel<'a>> {
+    alarm: &'a A,
+    channel: &'a C,
+    buffer: TakeCell<'static, [u16]>,
+    /// Number of samples in `buffer`.
+    count: Cell<usize>,
+    /// Number of samples delivered at once.
+    batch: Cell<usize>,
+    /// Process running the acquisition.
+    owner: OptionalCell<ProcessId>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, A: PeriodicAlarm<'a>, C: AdcChannel<'a>> AdcAcquisition<'a, A, C> {
+    pub fn new(
+        alarm: &'a A,
+        channel: &'a C,
+        buffer: &'static mut [u16],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> AdcAcquisition<'a, A, C> {
+        AdcAcquisition {
+            alarm,
+            channel,
+            buffer: TakeCell::new(buffer),
+            count: Cell::new(0),
+            batch: Cell::new(0),
+            owner: OptionalCell::empty(),
+            apps: grant,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.map_or(0, |buffer| buffer.len())
+    }
+
+    /// Whether a process other than `processid` runs an acquisition.
+    fn owned_by_other(&self, processid: ProcessId) -> bool {
+        self.owner.map_or(false, |owner| {
+            // The owner may have terminated without stopping the acquisition.
+            owner != processid && self.apps.enter(owner, |_, _| {}).is_ok()
+        })
+    }
+
+    fn start(&self, processid: ProcessId, period_ms: u32, batch: usize) -> Result<(), ErrorCode> {
+        if period_ms == 0 || batch == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if batch > self.capacity() {
+            return Err(ErrorCode::SIZE);
+        }
+        if self.owned_by_other(processid) {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.owner.set(processid);
+        self.count.set(0);
+        self.batch.set(batch);
+        self.alarm
+            .set_periodic_alarm(self.alarm.now(), self.alarm.ticks_from_ms(period_ms));
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.owner.clear();
+        self.count.set(0);
+        let _ = self.alarm.disarm();
+    }
+
+    /// Copy the collected samples to the owner and notify it.
+    fn deliver(&self) {
+        let count = self.count.replace(0);
+        let delivered = self.owner.map_or(false, |owner| {
+            self.apps
+                .enter(owner, |_, kernel_data| {
+                    let written = self.buffer.map_or(0, |samples| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::SAMPLES)
+                            .and_then(|dest| {
+                                dest.mut_enter(|dest| {
+                                    let mut written = 0;
+                                    for (chunk, sample) in
+                                        dest.chunks(2).zip(samples[..count].iter())
+                                    {
+                                        if chunk.len() < 2 {
+                                            break;
+                                        }
+                                        chunk.copy_from_slice(&sample.to_le_bytes());
+                                        written += 1;
+                                    }
+                                    written
+                                })
+                            })
+                            .unwrap_or(0)
+                    });
+                    let _ =
+                        kernel_data.schedule_upcall(upcall::BATCH, (written, count - written, 0));
+                })
+                .is_ok()
+        });
+        if !delivered {
+            // The owner is gone.
+            self.stop();
+        }
+    }
+}
+
+impl<'a, A: PeriodicAlarm<'a>, C: AdcChannel<'a>> time::AlarmClient for AdcAcquisition<'a, A, C> {
+    fn alarm(&self) {
+        // If the ADC is busy with another client, this sample is skipped.
+        let _ = self.channel.sample();
+    }
+}
+
+impl<'a, A: PeriodicAlarm<'a>, C: AdcChannel<'a>> adc::Client for AdcAcquisition<'a, A, C> {
+    fn sample_ready(&self, sample: u32) {
+        if self.owner.is_none() {
+            return;
+        }
+        let sample = adc::sample_to_u16(sample, self.channel.get_sample_width_bits());
+        let count = self.count.get();
+        self.buffer.map(|buffer| {
+            if let Some(slot) = buffer.get_mut(count) {
+                *slot = sample;
+                self.count.set(count + 1);
+            }
+        });
+        if self.count.get() >= self.batch.get() {
+            self.deliver();
+        }
+    }
+}
+
+impl<'a, A: PeriodicAlarm<'a>, C: AdcChannel<'a>> SyscallDriver for AdcAcquisition<'a, A, C> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.start(processid, data as u32, data2).into(),
+
+            2 => {
+                if self.owner.contains(&processid) {
+                    self.stop();
+                    CommandReturn::success()
+                } else {
+                    CommandReturn::failure(ErrorCode::RESERVE)
+                }
+            }
+
+            3 => {
+                if self.owner.contains(&processid) {
+                    self.deliver();
+                    CommandReturn::success()
+                } else {
+                    CommandReturn::failure(ErrorCode::RESERVE)
+                }
+            }
+
+            4 => CommandReturn::success_u32(self.capacity() as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,547 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! In-kernel decimation of high-speed ADC samples.
+//!
+//! `AdcDecimator` sits between a chip's `hil::adc::AdcHighSpeed`
+//! implementation and the ADC syscall driver. When the driver requests
+//! samples at a frequency, the ADC samples `factor` times faster into the
+//! decimator's own buffers, and the decimator hands the driver one sample
+//! for every `factor` raw samples. Applications sensing at a high rate then
+//! receive only the samples they need, instead of streaming the raw data to
+//! userspace to throw most of it away.
+//!
+//! Each raw sample goes through, in order:
+//!
+//! - DC-offset removal, if enabled: a slowly tracking estimate of the mean is
+//!   subtracted. The samples are then signed values centered on `0x8000`.
+//! - A single pole IIR low-pass filter, if enabled, which smooths the signal
+//!   by `1 / 2^shift` of the difference at each sample.
+//! - Decimation: the average of `factor` consecutive samples is output.
+//!
+//! Filter state is reset whenever a new high-speed sampling operation
+//! starts. Single and continuous samples are forwarded to the ADC
+//! unchanged.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Set the decimation factor `data`, from `1` (no decimation) to
+//!   `MAX_DECIMATION`.
+//! - `2`: Set the shift of the low-pass filter `data`, from `1` to
+//!   `MAX_LOW_PASS_SHIFT`, or `0` to disable it.
+//! - `3`: Enable DC-offset removal if `data` is not `0`, disable it
+//!   otherwise.
+//! - `4`: Get the decimation factor.
+//!
+//! Configuration commands return `BUSY` while the ADC is sampling, and
+//! `INVAL` if the value is out of range.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let raw1 = static_init!([u16; 256], [0; 256]);
+//! let raw2 = static_init!([u16; 256], [0; 256]);
+//! let adc_decimator = static_init!(
+//!     capsules_extra::adc_decimator::AdcDecimator<'static, Adc<'static>>,
+//!     capsules_extra::adc_decimator::AdcDecimator::new(
+//!         &peripherals.adc,
+//!         raw1,
+//!         raw2,
+//!         board_kernel.create_grant(
+//!             capsules_extra::adc_decimator::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! hil::adc::AdcHighSpeed::set_highspeed_client(&peripherals.adc, adc_decimator);
+//! // Use `adc_decimator` as the ADC of the `AdcDedicated` driver.
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AdcDecimator as usize;
+
+/// Largest decimation factor.
+pub const MAX_DECIMATION: usize = 64;
+
+/// Largest shift of the low-pass filter, which sets its time constant to
+/// about `2^shift` raw samples.
+pub const MAX_LOW_PASS_SHIFT: u32 = 12;
+
+/// Shift of the DC-offset tracking filter: the offset follows changes over
+/// about 1024 raw samples.
+const DC_SHIFT: u32 = 10;
+
+/// Fractional bits of the filter states.
+const STATE_FRACTION_BITS: u32 = 8;
+
+/// Processing applied to the raw samples.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Number of raw samples averaged into each output sample.
+    pub factor: usize,
+    /// Remove the DC offset of the samples.
+    pub dc_removal: bool,
+    /// Shift of the low-pass filter, `0` to disable it.
+    pub low_pass_shift: u32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            factor: 1,
+            dc_removal: false,
+            low_pass_shift: 0,
+        }
+    }
+}
+
+/// State of the filters across the raw samples of a sampling operation.
+#[derive(Copy, Clone, Debug, Default)]
+struct Filter {
+    /// Whether the filters have seen a sample since they were reset.
+    primed: bool,
+    /// Estimate of the DC offset.
+    dc: i32,
+    /// Output of the low-pass filter.
+    low_pass: i32,
+    /// Sum of the samples of the output sample being decimated.
+    sum: i32,
+    /// Number of samples in `sum`.
+    count: usize,
+}
+
+impl Filter {
+    /// Filter a raw sample, and return an output sample every
+    /// `config.factor` raw samples.
+    fn push(&mut self, config: &Config, sample: u16) -> Option<u16> {
+        let mut value = i32::from(sample) << STATE_FRACTION_BITS;
+        if !self.primed {
+            self.primed = true;
+            self.dc = value;
+            self.low_pass = if config.dc_removal {
+                0x8000 << STATE_FRACTION_BITS
+            } else {
+                value
+            };
+        }
+
+        if config.dc_removal {
+            self.dc += (value - self.dc) >> DC_SHIFT;
+            value = value - self.dc + (0x8000 << STATE_FRACTION_BITS);
+        }
+
+        if config.low_pass_shift > 0 {
+            self.low_pass += (value - self.low_pass) >> config.low_pass_shift;
+            value = self.low_pass;
+        }
+
+        self.sum += value >> STATE_FRACTION_BITS;
+        self.count += 1;
+        if self.count < config.factor {
+            return None;
+        }
+        let average = self.sum / self.count as i32;
+        self.sum = 0;
+        self.count = 0;
+        Some(average.clamp(0, u16::MAX.into()) as u16)
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct AdcDecimator<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> {
+    adc: &'a A,
+    client: OptionalCell<&'a dyn hil::adc::HighSpeedClient>,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    sampling: Cell<bool>,
+    config: Cell<Config>,
+    filter: Cell<Filter>,
+    /// Buffers of raw samples, while not held by the ADC.
+    raw1: TakeCell<'static, [u16]>,
+    raw2: TakeCell<'static, [u16]>,
+    /// Buffer of the client being filled with output samples.
+    current: TakeCell<'static, [u16]>,
+    current_len: Cell<usize>,
+    current_filled: Cell<usize>,
+    /// Buffer of the client filled after the current one.
+    next: TakeCell<'static, [u16]>,
+    next_len: Cell<usize>,
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDecimator<'a, A> {
+    /// `raw1` and `raw2` receive the raw samples from the ADC. Longer
+    /// buffers reduce the number of interrupts at high raw frequencies.
+    pub fn new(
+        adc: &'a A,
+        raw1: &'static mut [u16],
+        raw2: &'static mut [u16],
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> AdcDecimator<'a, A> {
+        AdcDecimator {
+            adc,
+            client: OptionalCell::empty(),
+            apps: grant,
+            sampling: Cell::new(false),
+            config: Cell::new(Config::default()),
+            filter: Cell::new(Filter::default()),
+            raw1: TakeCell::new(raw1),
+            raw2: TakeCell::new(raw2),
+            current: TakeCell::empty(),
+            current_len: Cell::new(0),
+            current_filled: Cell::new(0),
+            next: TakeCell::empty(),
+            next_len: Cell::new(0),
+        }
+    }
+
+    /// Set the processing of the following sampling operations.
+    pub fn set_config(&self, config: Config) -> Result<(), ErrorCode> {
+        if self.sampling.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if config.factor == 0
+            || config.factor > MAX_DECIMATION
+            || config.low_pass_shift > MAX_LOW_PASS_SHIFT
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        self.config.set(config);
+        Ok(())
+    }
+
+    pub fn get_config(&self) -> Config {
+        self.config.get()
+    }
+
+    /// Store a raw buffer the ADC gave back.
+    fn store_raw(&self, buf: &'static mut [u16]) {
+        if self.raw1.is_none() {
+            self.raw1.replace(buf);
+        } else {
+            self.raw2.replace(buf);
+        }
+    }
+
+    /// Store an output sample in the current buffer of the client, and hand
+    /// the buffer to the client once it is full.
+    fn output(&self, sample: u16) {
+        let filled = self.current_filled.get();
+        let len = self.current_len.get();
+        if filled >= len || self.current.map(|buf| buf[filled] = sample).is_none() {
+            // the client has not provided a buffer, drop the sample
+            return;
+        }
+        self.current_filled.set(filled + 1);
+        if filled + 1 < len {
+            return;
+        }
+
+        let full = self.current.take();
+        self.current_filled.set(0);
+        if self.next_len.get() > 0 {
+            self.next.take().map(|next| self.current.replace(next));
+            self.current_len.set(self.next_len.get());
+            self.next_len.set(0);
+        }
+        full.map(|buf| self.client.map(|client| client.samples_ready(buf, len)));
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::Adc<'a>
+    for AdcDecimator<'a, A>
+{
+    type Channel = A::Channel;
+
+    fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        self.adc.sample(channel)
+    }
+
+    fn sample_continuous(&self, channel: &Self::Channel, frequency: u32) -> Result<(), ErrorCode> {
+        self.adc.sample_continuous(channel, frequency)
+    }
+
+    fn stop_sampling(&self) -> Result<(), ErrorCode> {
+        let res = self.adc.stop_sampling();
+        if res.is_ok() {
+            self.sampling.set(false);
+        }
+        res
+    }
+
+    fn get_resolution_bits(&self) -> usize {
+        self.adc.get_resolution_bits()
+    }
+
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        self.adc.set_resolution_bits(bits)
+    }
+
+    fn get_voltage_reference_mv(&self) -> Option<usize> {
+        self.adc.get_voltage_reference_mv()
+    }
+
+    fn set_client(&self, client: &'a dyn hil::adc::Client) {
+        self.adc.set_client(client);
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::AdcHighSpeed<'a>
+    for AdcDecimator<'a, A>
+{
+    /// Sample `factor` times faster than `frequency` into the raw buffers,
+    /// and fill `buffer1` then `buffer2` with the decimated samples.
+    fn sample_highspeed(
+        &self,
+        channel: &Self::Channel,
+        frequency: u32,
+        buffer1: &'static mut [u16],
+        length1: usize,
+        buffer2: &'static mut [u16],
+        length2: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16], &'static mut [u16])> {
+        if self.sampling.get() {
+            return Err((ErrorCode::BUSY, buffer1, buffer2));
+        }
+        let length1 = cmp::min(length1, buffer1.len());
+        let length2 = cmp::min(length2, buffer2.len());
+        let config = self.config.get();
+        let raw_frequency = match frequency.checked_mul(config.factor as u32) {
+            Some(raw_frequency) => raw_frequency,
+            None => return Err((ErrorCode::INVAL, buffer1, buffer2)),
+        };
+        let (raw1, raw2) = match (self.raw1.take(), self.raw2.take()) {
+            (Some(raw1), Some(raw2)) => (raw1, raw2),
+            // Both raw buffers are back when the ADC is idle, so a missing
+            // one is an internal error.
+            (raw1, raw2) => {
+                if let Some(buf) = raw1 {
+                    self.store_raw(buf);
+                }
+                if let Some(buf) = raw2 {
+                    self.store_raw(buf);
+                }
+                return Err((ErrorCode::FAIL, buffer1, buffer2));
+            }
+        };
+
+        let (raw_len1, raw_len2) = (raw1.len(), raw2.len());
+        match self
+            .adc
+            .sample_highspeed(channel, raw_frequency, raw1, raw_len1, raw2, raw_len2)
+        {
+            Ok(()) => {
+                self.sampling.set(true);
+                self.filter.set(Filter::default());
+                self.current.replace(buffer1);
+                self.current_len.set(length1);
+                self.current_filled.set(0);
+                self.next.replace(buffer2);
+                self.next_len.set(length2);
+                Ok(())
+            }
+            Err((ecode, raw1, raw2)) => {
+                self.store_raw(raw1);
+                self.store_raw(raw2);
+                Err((ecode, buffer1, buffer2))
+            }
+        }
+    }
+
+    fn provide_buffer(
+        &self,
+        buf: &'static mut [u16],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])> {
+        if !self.sampling.get() {
+            return Err((ErrorCode::OFF, buf));
+        }
+        let length = cmp::min(length, buf.len());
+        if self.current.is_none() {
+            self.current.replace(buf);
+            self.current_len.set(length);
+            self.current_filled.set(0);
+        } else if self.next.is_none() {
+            self.next.replace(buf);
+            self.next_len.set(length);
+        } else {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        Ok(())
+    }
+
+    fn retrieve_buffers(
+        &self,
+    ) -> Result<(Option<&'static mut [u16]>, Option<&'static mut [u16]>), ErrorCode> {
+        if self.sampling.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let (raw1, raw2) = self.adc.retrieve_buffers()?;
+        if let Some(buf) = raw1 {
+            self.store_raw(buf);
+        }
+        if let Some(buf) = raw2 {
+            self.store_raw(buf);
+        }
+        self.current_filled.set(0);
+        self.next_len.set(0);
+        Ok((self.current.take(), self.next.take()))
+    }
+
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        self.adc
+            .get_sampling_frequency_hz()
+            .map(|frequency| frequency / self.config.get().factor as u32)
+    }
+
+    fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedClient
+    for AdcDecimator<'a, A>
+{
+    fn samples_ready(&self, buf: &'static mut [u16], length: usize) {
+        let config = self.config.get();
+        let length = cmp::min(length, buf.len());
+        for sample in buf[..length].iter() {
+            // the client may stop sampling when it receives a buffer
+            if !self.sampling.get() {
+                break;
+            }
+            let mut filter = self.filter.get();
+            let output = filter.push(&config, *sample);
+            self.filter.set(filter);
+            if let Some(output) = output {
+                self.output(output);
+            }
+        }
+
+        if self.sampling.get() {
+            let len = buf.len();
+            if let Err((_, buf)) = self.adc.provide_buffer(buf, len) {
+                self.store_raw(buf);
+            }
+        } else {
+            self.store_raw(buf);
+        }
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for AdcDecimator<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        let config = self.config.get();
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self
+                .set_config(Config {
+                    factor: data,
+                    ..config
+                })
+                .into(),
+
+            2 => match u32::try_from(data) {
+                Ok(low_pass_shift) => self
+                    .set_config(Config {
+                        low_pass_shift,
+                        ..config
+                    })
+                    .into(),
+                Err(_) => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            3 => self
+                .set_config(Config {
+                    dc_removal: data != 0,
+                    ..config
+                })
+                .into(),
+
+            4 => CommandReturn::success_u32(config.factor as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(config: &Config, samples: &[u16]) -> ([u16; 64], usize) {
+        let mut filter = Filter::default();
+        let mut outputs = [0; 64];
+        let mut count = 0;
+        for sample in samples {
+            if let Some(output) = filter.push(config, *sample) {
+                outputs[count] = output;
+                count += 1;
+            }
+        }
+        (outputs, count)
+    }
+
+    #[test]
+    fn decimates_by_averaging() {
+        let config = Config {
+            factor: 4,
+            ..Config::default()
+        };
+        let (outputs, count) = run(&config, &[100, 200, 300, 400, 1000, 1000, 2000, 2000, 7]);
+        assert_eq!(&outputs[..count], &[250, 1500]);
+    }
+
+    #[test]
+    fn removes_dc_offset() {
+        let config = Config {
+            factor: 64,
+            dc_removal: true,
+            low_pass_shift: 2,
+        };
+        // a square wave of amplitude 1000 around 0x4000, long enough for the
+        // offset estimate to settle
+        let mut samples = [0; 4096];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = if i % 2 == 0 {
+                0x4000 + 1000
+            } else {
+                0x4000 - 1000
+            };
+        }
+        let (outputs, count) = run(&config, &samples);
+        assert_eq!(count, 64);
+        for output in &outputs[count - 8..count] {
+            assert!((i32::from(*output) - 0x8000).abs() < 32, "{}", output);
+        }
+    }
+}
@@ -0,0 +1,582 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! In-kernel signal processing of high-speed ADC buffers.
+//!
+//! `AdcDsp` sits between a chip's `hil::adc::AdcHighSpeed` implementation and
+//! the ADC syscall driver. It forwards all requests to the ADC unchanged, and
+//! processes every buffer of samples in place before handing it up, so that
+//! applications monitoring vibrations can receive filtered samples or spectra
+//! without streaming raw data to userspace.
+//!
+//! The supported processing modes are:
+//!
+//! - Pass-through: the buffers are not modified.
+//! - FIR filter: up to `MAX_FIR_TAPS` coefficients in Q1.15 fixed point.
+//! - IIR filter: one biquad section with coefficients in Q2.14 fixed point.
+//! - Spectrum: a fixed-point radix-2 FFT over the largest power of two number
+//!   of samples in the buffer (up to `MAX_FFT_SIZE`). The first half of those
+//!   values is replaced with the magnitudes of the bins, from DC up to half the
+//!   sample rate, scaled by `1 / N`. The rest of the buffer is zeroed, so the
+//!   length of the buffer seen by the client does not change.
+//!
+//! Samples are the raw left-justified ADC values, and are processed as signed
+//! values centered on `0x8000`. Filter state is kept across buffers and reset
+//! whenever a new high-speed sampling operation starts.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `allow_readonly` System Call
+//!
+//! Buffer `0` holds the filter coefficients, as little-endian `i16` values.
+//! For the IIR filter the coefficients are `b0, b1, b2, a1, a2`.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Select the processing mode `data`: `0` pass-through, `1` FIR
+//!   filter, `2` IIR filter, `3` spectrum. Filter coefficients are copied
+//!   from the allowed buffer. Returns `BUSY` if the ADC is sampling.
+//! - `2`: Get the current processing mode.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let scratch = static_init!([i16; 1024], [0; 1024]);
+//! let adc_dsp = static_init!(
+//!     capsules_extra::adc_dsp::AdcDsp<'static, Adc<'static>>,
+//!     capsules_extra::adc_dsp::AdcDsp::new(
+//!         &peripherals.adc,
+//!         scratch,
+//!         board_kernel.create_grant(
+//!             capsules_extra::adc_dsp::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! hil::adc::AdcHighSpeed::set_highspeed_client(&peripherals.adc, adc_dsp);
+//! // Use `adc_dsp` as the ADC of the `AdcDedicated` driver.
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AdcDsp as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const COEFFICIENTS: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Maximum number of coefficients of the FIR filter.
+pub const MAX_FIR_TAPS: usize = 32;
+
+/// Maximum number of samples transformed by the FFT.
+pub const MAX_FFT_SIZE: usize = 1024;
+
+/// Number of coefficients of the IIR biquad section.
+const IIR_COEFFICIENTS: usize = 5;
+
+/// `sin(2 * pi * i / MAX_FFT_SIZE)` in Q1.15, for the first quarter period.
+const SINE: [i16; MAX_FFT_SIZE / 4 + 1] = [
+    0, 201, 402, 603, 804, 1005, 1206, 1407, 1608, 1809, 2009, 2210, 2410, 2611, 2811, 3012, 3212,
+    3412, 3612, 3811, 4011, 4210, 4410, 4609, 4808, 5007, 5205, 5404, 5602, 5800, 5998, 6195, 6393,
+    6590, 6786, 6983, 7179, 7375, 7571, 7767, 7962, 8157, 8351, 8545, 8739, 8933, 9126, 9319, 9512,
+    9704, 9896, 10087, 10278, 10469, 10659, 10849, 11039, 11228, 11417, 11605, 11793, 11980, 12167,
+    12353, 12539, 12725, 12910, 13094, 13279, 13462, 13645, 13828, 14010, 14191, 14372, 14553,
+    14732, 14912, 15090, 15269, 15446, 15623, 15800, 15976, 16151, 16325, 16499, 16673, 16846,
+    17018, 17189, 17360, 17530, 17700, 17869, 18037, 18204, 18371, 18537, 18703, 18868, 19032,
+    19195, 19357, 19519, 19680, 19841, 20000, 20159, 20317, 20475, 20631, 20787, 20942, 21096,
+    21250, 21403, 21554, 21705, 21856, 22005, 22154, 22301, 22448, 22594, 22739, 22884, 23027,
+    23170, 23311, 23452, 23592, 23731, 23870, 24007, 24143, 24279, 24413, 24547, 24680, 24811,
+    24942, 25072, 25201, 25329, 25456, 25582, 25708, 25832, 25955, 26077, 26198, 26319, 26438,
+    26556, 26674, 26790, 26905, 27019, 27133, 27245, 27356, 27466, 27575, 27683, 27790, 27896,
+    28001, 28105, 28208, 28310, 28411, 28510, 28609, 28706, 28803, 28898, 28992, 29085, 29177,
+    29268, 29358, 29447, 29534, 29621, 29706, 29791, 29874, 29956, 30037, 30117, 30195, 30273,
+    30349, 30424, 30498, 30571, 30643, 30714, 30783, 30852, 30919, 30985, 31050, 31113, 31176,
+    31237, 31297, 31356, 31414, 31470, 31526, 31580, 31633, 31685, 31736, 31785, 31833, 31880,
+    31926, 31971, 32014, 32057, 32098, 32137, 32176, 32213, 32250, 32285, 32318, 32351, 32382,
+    32412, 32441, 32469, 32495, 32521, 32545, 32567, 32589, 32609, 32628, 32646, 32663, 32678,
+    32692, 32705, 32717, 32728, 32737, 32745, 32752, 32757, 32761, 32765, 32766, 32767,
+];
+
+/// Processing applied to every buffer of samples.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Processing {
+    PassThrough = 0,
+    Fir = 1,
+    Iir = 2,
+    Spectrum = 3,
+}
+
+impl Processing {
+    fn from_usize(value: usize) -> Option<Processing> {
+        match value {
+            0 => Some(Processing::PassThrough),
+            1 => Some(Processing::Fir),
+            2 => Some(Processing::Iir),
+            3 => Some(Processing::Spectrum),
+            _ => None,
+        }
+    }
+}
+
+fn to_signed(sample: u16) -> i32 {
+    i32::from((sample ^ 0x8000) as i16)
+}
+
+fn from_signed(value: i32) -> u16 {
+    (value.clamp(i16::MIN.into(), i16::MAX.into()) as i16 as u16) ^ 0x8000
+}
+
+/// Filter `samples` in place with a FIR filter. `history` holds the previous
+/// inputs, most recent first, and must be as long as `coefficients`.
+fn fir(coefficients: &[i16], history: &mut [i16], samples: &mut [u16]) {
+    for sample in samples.iter_mut() {
+        history.copy_within(0..history.len() - 1, 1);
+        history[0] = to_signed(*sample) as i16;
+        let acc = coefficients
+            .iter()
+            .zip(history.iter())
+            .fold(0i64, |acc, (c, x)| acc + i64::from(*c) * i64::from(*x));
+        *sample = from_signed((acc >> 15) as i32);
+    }
+}
+
+/// Filter `samples` in place with a biquad section (direct form I).
+/// `state` holds `x[n-1]`, `x[n-2]`, `y[n-1]` and `y[n-2]`.
+fn iir(coefficients: &[i16; IIR_COEFFICIENTS], state: &mut [i32; 4], samples: &mut [u16]) {
+    let [b0, b1, b2, a1, a2] = coefficients.map(i64::from);
+    for sample in samples.iter_mut() {
+        let x = to_signed(*sample);
+        let acc = b0 * i64::from(x) + b1 * i64::from(state[0]) + b2 * i64::from(state[1])
+            - a1 * i64::from(state[2])
+            - a2 * i64::from(state[3]);
+        let y = ((acc >> 14) as i32).clamp(i16::MIN.into(), i16::MAX.into());
+        *state = [x, state[0], y, state[2]];
+        *sample = from_signed(y);
+    }
+}
+
+/// Return `(cos, sin)` of `2 * pi * index / MAX_FFT_SIZE` in Q1.15, for an
+/// index in the first half period.
+fn twiddle(index: usize) -> (i32, i32) {
+    let quarter = MAX_FFT_SIZE / 4;
+    if index <= quarter {
+        (SINE[quarter - index].into(), SINE[index].into())
+    } else {
+        (
+            -i32::from(SINE[index - quarter]),
+            SINE[2 * quarter - index].into(),
+        )
+    }
+}
+
+fn isqrt(value: u32) -> u32 {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 30;
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+/// Replace `samples` with their magnitude spectrum. `imaginary` is scratch
+/// space for the imaginary parts.
+fn spectrum(samples: &mut [u16], imaginary: &mut [i16]) {
+    let max = cmp::min(cmp::min(samples.len(), imaginary.len()), MAX_FFT_SIZE);
+    if max < 2 {
+        return;
+    }
+    // largest power of two that fits
+    let n: usize = 1 << (usize::BITS - 1 - max.leading_zeros());
+    let bits = n.trailing_zeros();
+
+    // The real parts are stored in `samples` as signed values. Load them in
+    // bit-reversed order.
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            samples.swap(i, j);
+        }
+    }
+    for i in 0..n {
+        samples[i] = to_signed(samples[i]) as u16;
+        imaginary[i] = 0;
+    }
+
+    let mut size = 2;
+    while size <= n {
+        let half = size / 2;
+        let step = MAX_FFT_SIZE / size;
+        for start in (0..n).step_by(size) {
+            for k in 0..half {
+                let (cos, sin) = twiddle(k * step);
+                let a = start + k;
+                let b = a + half;
+                let (re_a, im_a) = (i32::from(samples[a] as i16), i32::from(imaginary[a]));
+                let (re_b, im_b) = (i32::from(samples[b] as i16), i32::from(imaginary[b]));
+                // multiply by exp(-j * theta), then scale the butterfly by 1/2
+                // so that the values cannot overflow
+                let tr = (re_b * cos + im_b * sin) >> 15;
+                let ti = (im_b * cos - re_b * sin) >> 15;
+                samples[a] = ((re_a + tr) >> 1) as i16 as u16;
+                imaginary[a] = ((im_a + ti) >> 1) as i16;
+                samples[b] = ((re_a - tr) >> 1) as i16 as u16;
+                imaginary[b] = ((im_a - ti) >> 1) as i16;
+            }
+        }
+        size *= 2;
+    }
+
+    for k in 0..n / 2 {
+        let re = i32::from(samples[k] as i16);
+        let im = i32::from(imaginary[k]);
+        samples[k] = isqrt((re * re + im * im) as u32) as u16;
+    }
+    for sample in samples[n / 2..].iter_mut() {
+        *sample = 0;
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct AdcDsp<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> {
+    adc: &'a A,
+    client: OptionalCell<&'a dyn hil::adc::HighSpeedClient>,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    sampling: Cell<bool>,
+    processing: Cell<Processing>,
+    fir_coefficients: Cell<[i16; MAX_FIR_TAPS]>,
+    fir_taps: Cell<usize>,
+    fir_history: Cell<[i16; MAX_FIR_TAPS]>,
+    iir_coefficients: Cell<[i16; IIR_COEFFICIENTS]>,
+    iir_state: Cell<[i32; 4]>,
+    scratch: TakeCell<'static, [i16]>,
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDsp<'a, A> {
+    /// `scratch` is used by the FFT, and limits the number of samples it
+    /// transforms.
+    pub fn new(
+        adc: &'a A,
+        scratch: &'static mut [i16],
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    ) -> AdcDsp<'a, A> {
+        AdcDsp {
+            adc,
+            client: OptionalCell::empty(),
+            apps: grant,
+            sampling: Cell::new(false),
+            processing: Cell::new(Processing::PassThrough),
+            fir_coefficients: Cell::new([0; MAX_FIR_TAPS]),
+            fir_taps: Cell::new(0),
+            fir_history: Cell::new([0; MAX_FIR_TAPS]),
+            iir_coefficients: Cell::new([0; IIR_COEFFICIENTS]),
+            iir_state: Cell::new([0; 4]),
+            scratch: TakeCell::new(scratch),
+        }
+    }
+
+    /// Select the processing applied to the buffers. Filter coefficients
+    /// must have been set for the filter modes.
+    pub fn set_processing(&self, processing: Processing) -> Result<(), ErrorCode> {
+        if self.sampling.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.processing.set(processing);
+        Ok(())
+    }
+
+    /// Set the FIR filter coefficients, in Q1.15 fixed point.
+    pub fn set_fir_coefficients(&self, coefficients: &[i16]) -> Result<(), ErrorCode> {
+        if self.sampling.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if coefficients.is_empty() || coefficients.len() > MAX_FIR_TAPS {
+            return Err(ErrorCode::SIZE);
+        }
+        let mut taps = [0; MAX_FIR_TAPS];
+        taps[..coefficients.len()].copy_from_slice(coefficients);
+        self.fir_coefficients.set(taps);
+        self.fir_taps.set(coefficients.len());
+        Ok(())
+    }
+
+    /// Set the IIR biquad coefficients `b0, b1, b2, a1, a2`, in Q2.14 fixed
+    /// point.
+    pub fn set_iir_coefficients(
+        &self,
+        coefficients: [i16; IIR_COEFFICIENTS],
+    ) -> Result<(), ErrorCode> {
+        if self.sampling.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.iir_coefficients.set(coefficients);
+        Ok(())
+    }
+
+    /// Copy the filter coefficients for `processing` from the process's
+    /// allowed buffer.
+    fn load_coefficients(
+        &self,
+        processing: Processing,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
+        let mut coefficients = [0; MAX_FIR_TAPS];
+        let count = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::COEFFICIENTS)
+                    .and_then(|buffer| {
+                        buffer.enter(|data| {
+                            let count = cmp::min(data.len() / 2, MAX_FIR_TAPS);
+                            for (i, coefficient) in coefficients.iter_mut().take(count).enumerate()
+                            {
+                                *coefficient =
+                                    i16::from_le_bytes([data[2 * i].get(), data[2 * i + 1].get()]);
+                            }
+                            count
+                        })
+                    })
+                    .unwrap_or(0)
+            })
+            .map_err(ErrorCode::from)?;
+
+        match processing {
+            Processing::Fir => self.set_fir_coefficients(&coefficients[..count]),
+            Processing::Iir => {
+                if count < IIR_COEFFICIENTS {
+                    return Err(ErrorCode::SIZE);
+                }
+                let mut iir = [0; IIR_COEFFICIENTS];
+                iir.copy_from_slice(&coefficients[..IIR_COEFFICIENTS]);
+                self.set_iir_coefficients(iir)
+            }
+            Processing::PassThrough | Processing::Spectrum => Ok(()),
+        }
+    }
+
+    fn process(&self, buf: &mut [u16]) {
+        match self.processing.get() {
+            Processing::PassThrough => {}
+            Processing::Fir => {
+                let taps = self.fir_taps.get();
+                let mut history = self.fir_history.get();
+                fir(
+                    &self.fir_coefficients.get()[..taps],
+                    &mut history[..taps],
+                    buf,
+                );
+                self.fir_history.set(history);
+            }
+            Processing::Iir => {
+                let mut state = self.iir_state.get();
+                iir(&self.iir_coefficients.get(), &mut state, buf);
+                self.iir_state.set(state);
+            }
+            Processing::Spectrum => {
+                self.scratch.map(|scratch| spectrum(buf, scratch));
+            }
+        }
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::Adc<'a> for AdcDsp<'a, A> {
+    type Channel = A::Channel;
+
+    fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        self.adc.sample(channel)
+    }
+
+    fn sample_continuous(&self, channel: &Self::Channel, frequency: u32) -> Result<(), ErrorCode> {
+        self.adc.sample_continuous(channel, frequency)
+    }
+
+    fn stop_sampling(&self) -> Result<(), ErrorCode> {
+        let res = self.adc.stop_sampling();
+        if res.is_ok() {
+            self.sampling.set(false);
+        }
+        res
+    }
+
+    fn get_resolution_bits(&self) -> usize {
+        self.adc.get_resolution_bits()
+    }
+
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        self.adc.set_resolution_bits(bits)
+    }
+
+    fn get_voltage_reference_mv(&self) -> Option<usize> {
+        self.adc.get_voltage_reference_mv()
+    }
+
+    fn set_client(&self, client: &'a dyn hil::adc::Client) {
+        self.adc.set_client(client);
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::AdcHighSpeed<'a>
+    for AdcDsp<'a, A>
+{
+    fn sample_highspeed(
+        &self,
+        channel: &Self::Channel,
+        frequency: u32,
+        buffer1: &'static mut [u16],
+        length1: usize,
+        buffer2: &'static mut [u16],
+        length2: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16], &'static mut [u16])> {
+        self.fir_history.set([0; MAX_FIR_TAPS]);
+        self.iir_state.set([0; 4]);
+        let res = self
+            .adc
+            .sample_highspeed(channel, frequency, buffer1, length1, buffer2, length2);
+        if res.is_ok() {
+            self.sampling.set(true);
+        }
+        res
+    }
+
+    fn provide_buffer(
+        &self,
+        buf: &'static mut [u16],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])> {
+        self.adc.provide_buffer(buf, length)
+    }
+
+    fn retrieve_buffers(
+        &self,
+    ) -> Result<(Option<&'static mut [u16]>, Option<&'static mut [u16]>), ErrorCode> {
+        self.adc.retrieve_buffers()
+    }
+
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        self.adc.get_sampling_frequency_hz()
+    }
+
+    fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedClient
+    for AdcDsp<'a, A>
+{
+    fn samples_ready(&self, buf: &'static mut [u16], length: usize) {
+        let length = cmp::min(length, buf.len());
+        self.process(&mut buf[..length]);
+        self.client.map(|client| client.samples_ready(buf, length));
+    }
+}
+
+impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for AdcDsp<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match Processing::from_usize(data) {
+                Some(processing) => {
+                    let res = if self.sampling.get() {
+                        Err(ErrorCode::BUSY)
+                    } else {
+                        self.load_coefficients(processing, processid)
+                            .and_then(|()| self.set_processing(processing))
+                    };
+                    match res {
+                        Ok(()) => CommandReturn::success(),
+                        Err(err) => CommandReturn::failure(err),
+                    }
+                }
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            2 => CommandReturn::success_u32(self.processing.get() as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(index: usize) -> i32 {
+        let index = index % MAX_FFT_SIZE;
+        if index < MAX_FFT_SIZE / 2 {
+            twiddle(index).1
+        } else {
+            -twiddle(index - MAX_FFT_SIZE / 2).1
+        }
+    }
+
+    #[test]
+    fn spectrum_of_tone() {
+        // 8 periods over 64 samples, amplitude 16384
+        let mut samples = [0u16; 64];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = from_signed(sine(i * 8 * MAX_FFT_SIZE / 64) / 2);
+        }
+        let mut scratch = [0i16; 64];
+        spectrum(&mut samples, &mut scratch);
+        // a sine of amplitude A gives A / 2 in its bin once scaled by 1 / N
+        assert!((i32::from(samples[8]) - 8192).abs() < 16, "{}", samples[8]);
+        for (bin, magnitude) in samples.iter().enumerate() {
+            if bin != 8 {
+                assert!(*magnitude < 16, "bin {}: {}", bin, magnitude);
+            }
+        }
+    }
+
+    #[test]
+    fn moving_average() {
+        let coefficients = [16384, 16384];
+        let mut history = [0; 2];
+        let mut samples = [from_signed(1000), from_signed(3000), from_signed(-3000)];
+        fir(&coefficients, &mut history, &mut samples);
+        assert_eq!(samples.map(to_signed), [500, 2000, 0]);
+    }
+}
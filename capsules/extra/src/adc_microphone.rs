@@ -87,8 +87,9 @@ impl<'a, P: gpio::Pin> SoundPressure<'a> for AdcMicrophone<'a, P> {
 }
 
 impl<'a, P: gpio::Pin> adc::Client for AdcMicrophone<'a, P> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
         if self.state.get() == State::ReadingSPL {
+            let sample = adc::sample_to_u16(sample, self.adc.get_sample_width_bits());
             if self.spl_buffer.map_or(false, |buffer| {
                 if self.spl_pos.get() < buffer.len() {
                     buffer[self.spl_pos.get()] = sample;
@@ -0,0 +1,162 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Voltage and temperature readings from any ADC channel.
+//!
+//! The adapter converts the samples of an `AdcChannel` to millivolts, using
+//! the reference voltage of the channel, and applies a linear [`Scale`] to
+//! them. It implements the `VoltageDriver` and `TemperatureDriver` sensor
+//! HILs, so analog sensors, like voltage dividers or linear temperature
+//! sensors, plug into the capsules using these HILs without a capsule of
+//! their own.
+//!
+//! Usage
+//! -----
+//!
+//! An MCP9700 outputs 500 mV at 0 °C and 10 mV/°C, read in hundredths of
+//! degrees:
+//!
+//! ```rust,ignore
+//! let sensor = components::adc_sensor::AdcSensorComponent::new(
+//!     adc_mux,
+//!     adc_channel,
+//!     capsules_extra::adc_sensor::Scale::new(10, 1, -5000),
+//! )
+//! .finalize(components::adc_sensor_component_static!(AdcType));
+//! ```
+
+use kernel::hil::adc;
+use kernel::hil::sensors;
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Linear conversion of the voltage at the ADC input, in millivolts, to the
+/// reading: `millivolts * numerator / denominator + offset`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub numerator: i32,
+    pub denominator: i32,
+    pub offset: i32,
+}
+
+impl Scale {
+    pub const fn new(numerator: i32, denominator: i32, offset: i32) -> Scale {
+        Scale {
+            numerator,
+            denominator,
+            offset,
+        }
+    }
+
+    /// The reading for `millivolts` at the ADC input, or `None` if it does
+    /// not fit in an `i32` or the denominator is 0.
+    pub fn apply(&self, millivolts: i32) -> Option<i32> {
+        if self.denominator == 0 {
+            return None;
+        }
+        let value = millivolts as i64 * self.numerator as i64 / self.denominator as i64
+            + self.offset as i64;
+        i32::try_from(value).ok()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Reading {
+    Voltage,
+    Temperature,
+}
+
+pub struct AdcSensor<'a, A: adc::AdcChannel<'a>> {
+    adc: &'a A,
+    scale: Scale,
+    reading: OptionalCell<Reading>,
+    voltage_client: OptionalCell<&'a dyn sensors::VoltageClient>,
+    temperature_client: OptionalCell<&'a dyn sensors::TemperatureClient>,
+}
+
+impl<'a, A: adc::AdcChannel<'a>> AdcSensor<'a, A> {
+    pub fn new(adc: &'a A, scale: Scale) -> AdcSensor<'a, A> {
+        AdcSensor {
+            adc,
+            scale,
+            reading: OptionalCell::empty(),
+            voltage_client: OptionalCell::empty(),
+            temperature_client: OptionalCell::empty(),
+        }
+    }
+
+    fn start_reading(&self, reading: Reading) -> Result<(), ErrorCode> {
+        if self.reading.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        // differential samples are signed, and cannot be converted with the
+        // reference voltage alone
+        if self.adc.is_differential() {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.adc.sample()?;
+        self.reading.set(reading);
+        Ok(())
+    }
+
+    fn convert(&self, sample: u32) -> Result<i32, ErrorCode> {
+        let reference_mv = self.adc.get_voltage_reference_mv().ok_or(ErrorCode::FAIL)?;
+        let millivolts =
+            adc::sample_to_mv(sample, self.adc.get_resolution_bits(), reference_mv) as i32;
+        self.scale.apply(millivolts).ok_or(ErrorCode::SIZE)
+    }
+}
+
+impl<'a, A: adc::AdcChannel<'a>> adc::Client for AdcSensor<'a, A> {
+    fn sample_ready(&self, sample: u32) {
+        let value = self.convert(sample);
+        match self.reading.take() {
+            Some(Reading::Voltage) => self.voltage_client.map(|client| client.callback(value)),
+            Some(Reading::Temperature) => {
+                self.temperature_client.map(|client| client.callback(value))
+            }
+            None => None,
+        };
+    }
+}
+
+impl<'a, A: adc::AdcChannel<'a>> sensors::VoltageDriver<'a> for AdcSensor<'a, A> {
+    fn read_voltage(&self) -> Result<(), ErrorCode> {
+        self.start_reading(Reading::Voltage)
+    }
+
+    fn set_client(&self, client: &'a dyn sensors::VoltageClient) {
+        self.voltage_client.set(client);
+    }
+}
+
+impl<'a, A: adc::AdcChannel<'a>> sensors::TemperatureDriver<'a> for AdcSensor<'a, A> {
+    fn set_client(&self, client: &'a dyn sensors::TemperatureClient) {
+        self.temperature_client.set(client);
+    }
+
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        self.start_reading(Reading::Temperature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scale;
+
+    #[test]
+    fn scales() {
+        // MCP9700: 500 mV at 0 °C, 10 mV/°C, in hundredths of degrees
+        let mcp9700 = Scale::new(10, 1, -5000);
+        assert_eq!(mcp9700.apply(750), Some(2500));
+        assert_eq!(mcp9700.apply(400), Some(-1000));
+
+        // 100k/10k voltage divider
+        let divider = Scale::new(11, 1, 0);
+        assert_eq!(divider.apply(1100), Some(12100));
+
+        assert_eq!(Scale::new(1, 0, 0).apply(1000), None);
+        assert_eq!(Scale::new(i32::MAX, 1, 0).apply(2), None);
+    }
+}
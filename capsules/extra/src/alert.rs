@@ -0,0 +1,210 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Alerts signaling failures with blink or beep patterns.
+//!
+//! [`AlertPlayer`] implements [`Alert`]: it plays the pattern configured by
+//! the board for a class of failure on alert outputs, with an alarm timing
+//! the steps of the pattern. [`ScreenAlertOutput`] is an alert output
+//! inverting the colors of a screen while the pattern is on.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let led_output = static_init!(AlertLed<'static, LedLow<'static, GPIOPin>>, AlertLed::new(led));
+//! let outputs = static_init!([&'static dyn AlertOutput; 1], [led_output]);
+//! let alert = static_init!(
+//!     AlertPlayer<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     AlertPlayer::new(virtual_alarm, outputs, ALERT_PATTERNS)
+//! );
+//! virtual_alarm.set_alarm_client(alert);
+//! alert.init();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::alert::{Alert, AlertClass, AlertClient, AlertOutput, AlertPatterns};
+use kernel::hil::screen::{Screen, ScreenClient};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Plays alert patterns on outputs, timed with an alarm.
+pub struct AlertPlayer<'a, A: time::Alarm<'a>> {
+    alarm: &'a A,
+    outputs: &'a [&'a dyn AlertOutput],
+    patterns: AlertPatterns,
+    /// Class of the alert being played.
+    class: OptionalCell<AlertClass>,
+    /// Index of the step of the pattern being played.
+    step: Cell<usize>,
+    /// Number of times the pattern is still played, `None` until stopped.
+    remaining: Cell<Option<usize>>,
+    client: OptionalCell<&'a dyn AlertClient>,
+}
+
+impl<'a, A: time::Alarm<'a>> AlertPlayer<'a, A> {
+    pub fn new(
+        alarm: &'a A,
+        outputs: &'a [&'a dyn AlertOutput],
+        patterns: AlertPatterns,
+    ) -> AlertPlayer<'a, A> {
+        AlertPlayer {
+            alarm,
+            outputs,
+            patterns,
+            class: OptionalCell::empty(),
+            step: Cell::new(0),
+            remaining: Cell::new(None),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Initialize the outputs.
+    pub fn init(&self) {
+        self.outputs.iter().for_each(|output| output.init());
+    }
+
+    fn off(&self) {
+        self.outputs.iter().for_each(|output| output.off());
+    }
+
+    /// Play the current step of the pattern, or end the alert after its
+    /// last repetition.
+    fn play_step(&self, class: AlertClass) {
+        let pattern = self.patterns.get(class);
+        if pattern.duration_ms() == 0 {
+            // Nothing would ever be played.
+            self.finish(class);
+            return;
+        }
+
+        let mut step = self.step.get();
+        if step >= pattern.durations_ms.len() {
+            step = 0;
+            let remaining = self.remaining.get().map(|remaining| remaining - 1);
+            self.remaining.set(remaining);
+            if remaining == Some(0) {
+                self.finish(class);
+                return;
+            }
+        }
+        self.step.set(step + 1);
+
+        if step % 2 == 0 {
+            self.outputs
+                .iter()
+                .for_each(|output| output.on(pattern.tone_hz));
+        } else {
+            self.off();
+        }
+        let duration_ms = pattern.durations_ms[step] as u32;
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(duration_ms));
+    }
+
+    fn finish(&self, class: AlertClass) {
+        self.off();
+        self.class.clear();
+        self.client.map(|client| client.alert_done(class));
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> Alert<'a> for AlertPlayer<'a, A> {
+    fn alert(&self, class: AlertClass, count: usize) -> Result<(), ErrorCode> {
+        let _ = self.alarm.disarm();
+        self.class.set(class);
+        self.step.set(0);
+        self.remaining
+            .set(if count == 0 { None } else { Some(count) });
+        self.play_step(class);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        let class = self.class.get().ok_or(ErrorCode::OFF)?;
+        let _ = self.alarm.disarm();
+        self.finish(class);
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn AlertClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for AlertPlayer<'a, A> {
+    fn alarm(&self) {
+        self.class.map(|class| self.play_step(class));
+    }
+}
+
+/// A screen used as an alert output: its colors are inverted while the
+/// pattern is on.
+///
+/// The output sits between the screen and its client, and hides the
+/// completion of its own commands from the client. Steps of the pattern are
+/// skipped while the screen is busy with a command of the client. Screens
+/// need interrupts, so this output cannot be used by the panic handler.
+pub struct ScreenAlertOutput<'a, S: Screen<'a>> {
+    screen: &'a S,
+    /// Number of commands of the output not completed yet.
+    pending: Cell<usize>,
+    client: OptionalCell<&'a dyn ScreenClient>,
+}
+
+impl<'a, S: Screen<'a>> ScreenAlertOutput<'a, S> {
+    pub fn new(screen: &'a S) -> ScreenAlertOutput<'a, S> {
+        ScreenAlertOutput {
+            screen,
+            pending: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the client of the screen, which receives the callbacks of its own
+    /// commands.
+    pub fn set_client(&self, client: &'a dyn ScreenClient) {
+        self.client.set(client);
+    }
+
+    fn invert(&self, enabled: bool) {
+        if self.screen.set_invert(enabled).is_ok() {
+            self.pending.set(self.pending.get() + 1);
+        }
+    }
+}
+
+impl<'a, S: Screen<'a>> AlertOutput for ScreenAlertOutput<'a, S> {
+    fn init(&self) {}
+
+    fn on(&self, _tone_hz: u32) {
+        self.invert(true);
+    }
+
+    fn off(&self) {
+        self.invert(false);
+    }
+}
+
+impl<'a, S: Screen<'a>> ScreenClient for ScreenAlertOutput<'a, S> {
+    fn command_complete(&self, result: Result<(), ErrorCode>) {
+        if self.pending.get() > 0 {
+            self.pending.set(self.pending.get() - 1);
+        } else {
+            self.client.map(|client| client.command_complete(result));
+        }
+    }
+
+    fn write_complete(&self, buffer: SubSliceMut<'static, u8>, result: Result<(), ErrorCode>) {
+        self.client
+            .map(move |client| client.write_complete(buffer, result));
+    }
+
+    fn screen_is_ready(&self) {
+        self.client.map(|client| client.screen_is_ready());
+    }
+}
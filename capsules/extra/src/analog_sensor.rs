@@ -43,7 +43,8 @@ impl<'a, A: hil::adc::Adc<'a>> AnalogLightSensor<'a, A> {
 
 /// Callbacks from the ADC driver
 impl<'a, A: hil::adc::Adc<'a>> hil::adc::Client for AnalogLightSensor<'a, A> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
+        let sample = hil::adc::sample_to_u16(sample, self.adc.get_sample_width_bits());
         // TODO: calculate the actual light reading.
         let measurement: usize = match self.sensor_type {
             AnalogLightSensorType::LightDependentResistor => {
@@ -95,7 +96,8 @@ impl<'a, A: hil::adc::Adc<'a>> AnalogTemperatureSensor<'a, A> {
 
 /// Callbacks from the ADC driver
 impl<'a, A: hil::adc::Adc<'a>> hil::adc::Client for AnalogTemperatureSensor<'a, A> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
+        let sample = hil::adc::sample_to_u16(sample, self.adc.get_sample_width_bits());
         // TODO: calculate the actual temperature reading.
         let measurement = match self.sensor_type {
             // 𝑉out = 500𝑚𝑉 + 10𝑚𝑉/C ∗ 𝑇A
@@ -0,0 +1,212 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Lets a privileged process replace the credential accept list in flash.
+//!
+//! The accept list used by
+//! `capsules_system::process_checker::accept_list::AppCheckerAcceptList` is
+//! stored in a dedicated flash region. This driver allows the process with
+//! the configured ShortId to overwrite that region with a new list, so the
+//! keys used to sign applications can be rotated in the field. The new list
+//! takes effect the next time a process is checked (e.g. after a reboot).
+//!
+//! The image provided by userspace is validated before it is written; a
+//! malformed list is rejected with `INVAL` so a bad update cannot leave the
+//! region unparseable.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let accept_list_buffer = static_init!([u8; 1024], [0; 1024]);
+//! let accept_list_driver = static_init!(
+//!     capsules_extra::app_accept_list::AppAcceptList<'static>,
+//!     capsules_extra::app_accept_list::AppAcceptList::new(
+//!         nv_to_page,
+//!         accept_list,
+//!         ACCEPT_LIST_FLASH_ADDRESS,
+//!         kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1).unwrap()),
+//!         board_kernel.create_grant(&grant_cap),
+//!         accept_list_buffer,
+//!     )
+//! );
+//! nv_to_page.set_client(accept_list_driver);
+//! ```
+
+use capsules_system::process_checker::accept_list::AcceptList;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::process::ShortId;
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppAcceptList as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// `write_done` callback.
+    pub const WRITE_DONE: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// New accept list image.
+    pub const LIST: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct AppAcceptList<'a> {
+    driver: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+    list: AcceptList<'static>,
+    /// Address of the accept list region as seen by `driver`.
+    region_address: usize,
+    /// Only the process with this ShortId may update the list.
+    privileged: ShortId,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    current_app: OptionalCell<ProcessId>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a> AppAcceptList<'a> {
+    pub fn new(
+        driver: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+        list: AcceptList<'static>,
+        region_address: usize,
+        privileged: ShortId,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+        buffer: &'static mut [u8],
+    ) -> AppAcceptList<'a> {
+        AppAcceptList {
+            driver,
+            list,
+            region_address,
+            privileged,
+            apps: grant,
+            current_app: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        match self.privileged {
+            ShortId::Fixed(_) => processid.short_app_id() == self.privileged,
+            ShortId::LocallyUnique => false,
+        }
+    }
+
+    fn write_list(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current_app.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::LIST)
+                    .and_then(|list| {
+                        list.enter(|image| {
+                            self.buffer
+                                .take()
+                                .map_or(Err(ErrorCode::RESERVE), |buffer| {
+                                    let length = image.len();
+                                    if length > buffer.len() || length > self.list.region_len() {
+                                        self.buffer.replace(buffer);
+                                        return Err(ErrorCode::SIZE);
+                                    }
+
+                                    image.copy_to_slice(&mut buffer[0..length]);
+                                    if let Err(e) = AcceptList::validate(&buffer[0..length]) {
+                                        self.buffer.replace(buffer);
+                                        return Err(e);
+                                    }
+
+                                    self.driver
+                                        .write(buffer, self.region_address, length)
+                                        .map(|()| self.current_app.set(processid))
+                                })
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl hil::nonvolatile_storage::NonvolatileStorageClient for AppAcceptList<'_> {
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+
+        if let Some(processid) = self.current_app.take() {
+            let _ = self.apps.enter(processid, |_app, upcalls| {
+                upcalls
+                    .schedule_upcall(upcall::WRITE_DONE, (0, length, 0))
+                    .ok();
+            });
+        }
+    }
+}
+
+impl SyscallDriver for AppAcceptList<'_> {
+    /// Accept list control.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Validate the list in the `allow` buffer and write it to the
+    ///   accept list region. Only allowed for the privileged process.
+    /// - `2`: Return the number of entries in the current accept list.
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if !self.is_privileged(processid) {
+                    return CommandReturn::failure(ErrorCode::NOSUPPORT);
+                }
+                match self.write_list(processid) {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            2 => CommandReturn::success_u32(self.list.entries().count() as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
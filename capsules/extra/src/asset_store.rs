@@ -0,0 +1,351 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Read-only data blobs (assets) shared with processes from flash.
+//!
+//! Boards can bundle large read-only data, such as fonts, machine learning
+//! models or lookup tables, in a flash region next to the kernel. This
+//! capsule gives processes access to these assets without copying them into
+//! the process's RAM: an asset is mapped read-only into the process's address
+//! space with an MPU region, and the process reads it in place.
+//!
+//! A process may only access the assets listed in the Asset Access TLV of its
+//! TBF header.
+//!
+//! Asset Region Format
+//! -------------------
+//!
+//! All fields are 32-bit little-endian values.
+//!
+//! ```text
+//! 0          4          8
+//! +----------+----------+---------------------------------+-------
+//! | "TKAS"   | count    | count * { id, offset, length }  | data
+//! +----------+----------+---------------------------------+-------
+//! ```
+//!
+//! `offset` is relative to the start of the region. To be mapped, an asset
+//! must satisfy the alignment constraints of the MPU (on Cortex-M, its length
+//! must be a power of two of at least 32 bytes, and it must be aligned to
+//! its length). Assets that cannot be mapped can still be copied.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! Buffer `0` receives the data copied with command `5`.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Get the number of assets in the store.
+//! - `2`: Get the length in bytes of the asset with id `data`.
+//! - `3`: Map the asset with id `data` read-only into the process and return
+//!   its address. Returns `NOMEM` if the process cannot map more assets and
+//!   `FAIL` if the MPU cannot cover the asset.
+//! - `4`: Unmap the asset with id `data`.
+//! - `5`: Copy the asset with id `data`, starting at offset `data2`, into
+//!   buffer `0`. Returns the number of bytes copied.
+//!
+//! All commands taking an asset id return `INVAL` if the asset does not exist
+//! or the process is not allowed to access it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! extern "C" {
+//!     static _sassets: u8;
+//!     static _eassets: u8;
+//! }
+//!
+//! let assets = static_init!(
+//!     capsules_extra::asset_store::AssetTable,
+//!     capsules_extra::asset_store::AssetTable::new(core::slice::from_raw_parts(
+//!         core::ptr::addr_of!(_sassets),
+//!         core::ptr::addr_of!(_eassets) as usize - core::ptr::addr_of!(_sassets) as usize,
+//!     ))
+//! );
+//! let asset_store = static_init!(
+//!     capsules_extra::asset_store::AssetStore<'static, ProcessMgmtCap>,
+//!     capsules_extra::asset_store::AssetStore::new(
+//!         board_kernel,
+//!         ProcessMgmtCap,
+//!         assets,
+//!         board_kernel.create_grant(
+//!             capsules_extra::asset_store::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::platform::mpu;
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AssetStore as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Destination of asset copies.
+    pub const COPY: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Magic value at the start of an asset region.
+const MAGIC: &[u8; 4] = b"TKAS";
+
+/// Size of the region header (magic and asset count).
+const HEADER_LEN: usize = 8;
+
+/// Size of an entry of the asset table.
+const ENTRY_LEN: usize = 12;
+
+/// Maximum number of assets a process can map at the same time.
+const MAX_MAPPED_ASSETS: usize = 2;
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The table of the assets bundled in a flash region.
+pub struct AssetTable {
+    region: &'static [u8],
+    count: usize,
+}
+
+impl AssetTable {
+    /// Parse the asset region. A region with an invalid header is treated as
+    /// containing no assets.
+    pub fn new(region: &'static [u8]) -> AssetTable {
+        let count = match (region.get(0..4), read_u32(region, 4)) {
+            (Some(magic), Some(count)) if magic == MAGIC => count as usize,
+            _ => 0,
+        };
+        // Ignore a table that does not fit in the region.
+        let table_end = count
+            .checked_mul(ENTRY_LEN)
+            .and_then(|len| len.checked_add(HEADER_LEN));
+        let count = match table_end {
+            Some(end) if end <= region.len() => count,
+            _ => 0,
+        };
+        AssetTable { region, count }
+    }
+
+    /// Number of assets in the table.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The data of the asset with identifier `id`, if it exists and lies
+    /// within the region.
+    pub fn get(&self, id: u32) -> Option<&'static [u8]> {
+        let region = self.region;
+        (0..self.count)
+            .map(|index| HEADER_LEN + index * ENTRY_LEN)
+            .find(|&entry| read_u32(region, entry) == Some(id))
+            .and_then(|entry| {
+                let offset = read_u32(region, entry + 4)? as usize;
+                let length = read_u32(region, entry + 8)? as usize;
+                region.get(offset..offset.checked_add(length)?)
+            })
+    }
+
+    /// The data of the asset with identifier `id`, if it exists and
+    /// `processid` may access it.
+    pub fn get_for_process(&self, processid: ProcessId, id: u32) -> Option<&'static [u8]> {
+        if processid.has_asset_access(id) {
+            self.get(id)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Assets mapped into the process, with their MPU region.
+    mapped: [Option<(u32, mpu::Region)>; MAX_MAPPED_ASSETS],
+}
+
+pub struct AssetStore<'a, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    capability: C,
+    assets: &'a AssetTable,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl<'a, C: ProcessManagementCapability> AssetStore<'a, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        capability: C,
+        assets: &'a AssetTable,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> AssetStore<'a, C> {
+        AssetStore {
+            kernel,
+            capability,
+            assets,
+            apps: grant,
+        }
+    }
+
+    /// The table of the assets in the store.
+    pub fn assets(&self) -> &'a AssetTable {
+        self.assets
+    }
+
+    /// The data of the asset with identifier `id`, if `processid` may access
+    /// it.
+    pub fn asset(&self, processid: ProcessId, id: u32) -> Option<&'static [u8]> {
+        self.assets.get_for_process(processid, id)
+    }
+
+    fn map(&self, processid: ProcessId, id: u32) -> Result<u32, ErrorCode> {
+        let asset = self.asset(processid, id).ok_or(ErrorCode::INVAL)?;
+        self.apps
+            .enter(processid, |app, _| {
+                if app.mapped.iter().flatten().any(|&(mapped, _)| mapped == id) {
+                    return Ok(asset.as_ptr() as u32);
+                }
+                let slot = app
+                    .mapped
+                    .iter_mut()
+                    .find(|slot| slot.is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+                let region = self
+                    .kernel
+                    .process_map_or_external(
+                        None,
+                        processid,
+                        |process| process.add_read_only_mpu_region(asset.as_ptr(), asset.len()),
+                        &self.capability,
+                    )
+                    .ok_or(ErrorCode::FAIL)?;
+                *slot = Some((id, region));
+                Ok(asset.as_ptr() as u32)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn unmap(&self, processid: ProcessId, id: u32) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                let slot = app
+                    .mapped
+                    .iter_mut()
+                    .find(|slot| slot.map_or(false, |(mapped, _)| mapped == id))
+                    .ok_or(ErrorCode::INVAL)?;
+                let (_, region) = slot.take().ok_or(ErrorCode::INVAL)?;
+                self.kernel.process_map_or_external(
+                    Err(ErrorCode::FAIL),
+                    processid,
+                    |process| process.remove_mpu_region(region),
+                    &self.capability,
+                )
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn copy(&self, processid: ProcessId, id: u32, offset: usize) -> Result<u32, ErrorCode> {
+        let asset = self.asset(processid, id).ok_or(ErrorCode::INVAL)?;
+        let data = asset.get(offset..).ok_or(ErrorCode::INVAL)?;
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::COPY)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|dest| {
+                            let len = core::cmp::min(dest.len(), data.len());
+                            dest[..len].copy_from_slice(&data[..len]);
+                            len as u32
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<'a, C: ProcessManagementCapability> SyscallDriver for AssetStore<'a, C> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let id = data as u32;
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.assets.count() as u32),
+
+            2 => self
+                .asset(processid, id)
+                .map_or(CommandReturn::failure(ErrorCode::INVAL), |asset| {
+                    CommandReturn::success_u32(asset.len() as u32)
+                }),
+
+            3 => match self.map(processid, id) {
+                Ok(address) => CommandReturn::success_u32(address),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            4 => self.unmap(processid, id).into(),
+
+            5 => match self.copy(processid, id, data2) {
+                Ok(len) => CommandReturn::success_u32(len),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static REGION: [u8; 40] = [
+        b'T', b'K', b'A', b'S', // magic
+        2, 0, 0, 0, // count
+        7, 0, 0, 0, 32, 0, 0, 0, 4, 0, 0, 0, // asset 7
+        9, 0, 0, 0, 36, 0, 0, 0, 8, 0, 0, 0, // asset 9, out of the region
+        1, 2, 3, 4, 5, 6, 7, 8, // data
+    ];
+
+    #[test]
+    fn find_assets() {
+        let table = AssetTable::new(&REGION);
+        assert_eq!(table.count(), 2);
+        assert_eq!(table.get(7), Some(&REGION[32..36]));
+        assert_eq!(table.get(9), None);
+        assert_eq!(table.get(1), None);
+    }
+
+    #[test]
+    fn invalid_region() {
+        assert_eq!(AssetTable::new(&REGION[4..]).count(), 0);
+        assert_eq!(AssetTable::new(&REGION[..16]).count(), 0);
+        assert_eq!(AssetTable::new(&[]).count(), 0);
+    }
+}
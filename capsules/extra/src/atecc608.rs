@@ -0,0 +1,595 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the Microchip ATECC608 secure element.
+//!
+//! The ATECC608 stores ECC P-256 private keys that cannot be read out of the
+//! device. This driver uses it to:
+//!
+//! - sign SHA-256 hashes with the private key in a slot
+//!   (`SignatureSign`),
+//! - verify signatures of SHA-256 hashes with a public key imported with
+//!   `PubKey::import_public_key()` (`SignatureVerify`),
+//! - generate random numbers (`Entropy32`).
+//!
+//! The device must already be provisioned: its configuration and data zones
+//! must be locked (the random number generator only returns a fixed pattern
+//! otherwise), and the key slot must hold a private key that is allowed to
+//! sign external messages.
+//!
+//! The device sleeps between operations. To wake it up, SDA must be held low
+//! for at least 60 us, which the driver does by writing a zero byte to the
+//! I2C address 0x00. This requires a bus speed of at most 100 kHz.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let atecc608 = components::atecc608::Atecc608Component::new(
+//!     i2c_mux,
+//!     capsules_extra::atecc608::BASE_ADDR,
+//!     mux_alarm,
+//!     0, // key slot
+//! )
+//! .finalize(components::atecc608_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     nrf52840::i2c::TWI
+//! ));
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::entropy;
+use kernel::hil::i2c::{self, I2CDevice};
+use kernel::hil::public_key_crypto::keys::PubKey;
+use kernel::hil::public_key_crypto::signature;
+use kernel::hil::time::{self, Alarm, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Default I2C address of the ATECC608.
+pub const BASE_ADDR: u8 = 0x60;
+
+/// Length of the buffer for I2C transfers, large enough for the Verify
+/// command.
+pub const BUFFER_LEN: usize = 136;
+
+/// Length of a SHA-256 hash.
+pub const HASH_LEN: usize = 32;
+
+/// Length of a raw ECDSA P-256 signature, R followed by S.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Length of a raw P-256 public key, X followed by Y.
+pub const PUBLIC_KEY_LEN: usize = 64;
+
+/// Length of the random number returned by the Random command.
+const RANDOM_LEN: usize = 32;
+
+/// Status packet read after the device wakes up.
+const WAKE_RESPONSE: [u8; 4] = [0x04, 0x11, 0x33, 0x43];
+
+/// Time between the wake pulse and the first transfer (tWHI).
+const WAKE_DELAY_US: u32 = 1500;
+
+/// Word addresses, the first byte written to the device.
+const WORD_ADDRESS_SLEEP: u8 = 0x01;
+const WORD_ADDRESS_COMMAND: u8 = 0x03;
+
+/// Status codes of a status packet.
+const STATUS_SUCCESS: u8 = 0x00;
+const STATUS_MISCOMPARE: u8 = 0x01;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Command {
+    Random,
+    /// Load the hash to sign into TempKey.
+    SignNonce,
+    Sign,
+    /// Load the hash to verify into TempKey.
+    VerifyNonce,
+    Verify,
+}
+
+impl Command {
+    fn opcode(self) -> u8 {
+        match self {
+            Command::Random => 0x1B,
+            Command::SignNonce | Command::VerifyNonce => 0x16,
+            Command::Sign => 0x41,
+            Command::Verify => 0x45,
+        }
+    }
+
+    /// Maximum execution time in milliseconds.
+    fn execution_time_ms(self) -> u32 {
+        match self {
+            Command::Random => 23,
+            Command::SignNonce | Command::VerifyNonce => 7,
+            Command::Sign => 50,
+            Command::Verify => 58,
+        }
+    }
+
+    /// Length of the response packet, including the count and the CRC.
+    fn response_len(self) -> usize {
+        match self {
+            Command::Random => 1 + RANDOM_LEN + 2,
+            Command::Sign => 1 + SIGNATURE_LEN + 2,
+            Command::SignNonce | Command::VerifyNonce | Command::Verify => 4,
+        }
+    }
+
+    /// The command that follows this one in the same operation.
+    fn next(self) -> Option<Command> {
+        match self {
+            Command::SignNonce => Some(Command::Sign),
+            Command::VerifyNonce => Some(Command::Verify),
+            Command::Random | Command::Sign | Command::Verify => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Sending the wake pulse, before executing the command.
+    Wake(Command),
+    /// Waiting for the device to wake up.
+    WakeDelay(Command),
+    /// Reading the status packet sent after wake up.
+    WakeStatus(Command),
+    /// Writing a command packet.
+    Command(Command),
+    /// Waiting for the command to execute.
+    Execute(Command),
+    /// Reading the response of the command.
+    Response(Command),
+    /// Sending the device back to sleep.
+    Sleep,
+}
+
+/// CRC-16 of the command and response packets, polynomial 0x8005 with the
+/// bits of each byte processed LSB first.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        for shift in 0..8 {
+            let data_bit = (byte >> shift) & 1 != 0;
+            let crc_bit = crc & 0x8000 != 0;
+            crc <<= 1;
+            if data_bit != crc_bit {
+                crc ^= 0x8005;
+            }
+        }
+    }
+    crc
+}
+
+/// Check the count and the CRC of a response packet and return its data.
+fn response_data(packet: &[u8]) -> Result<&[u8], ErrorCode> {
+    let count = *packet.first().ok_or(ErrorCode::FAIL)? as usize;
+    if count < 4 || count > packet.len() {
+        return Err(ErrorCode::FAIL);
+    }
+    let crc = crc16(&packet[..count - 2]).to_le_bytes();
+    if packet[count - 2..count] != crc {
+        return Err(ErrorCode::FAIL);
+    }
+    Ok(&packet[1..count - 2])
+}
+
+pub struct Atecc608<'a, A: Alarm<'a>, I: I2CDevice> {
+    i2c: &'a I,
+    /// Device at the I2C address 0x00, used to send the wake pulse.
+    wake_i2c: &'a I,
+    alarm: &'a A,
+    key_slot: u16,
+    state: Cell<State>,
+    /// The first command of the current operation.
+    operation: Cell<Command>,
+    /// The result of the current operation, reported once the device is
+    /// asleep again.
+    result: Cell<Result<bool, ErrorCode>>,
+    buffer: TakeCell<'static, [u8]>,
+    hash: TakeCell<'static, [u8; HASH_LEN]>,
+    signature: TakeCell<'static, [u8; SIGNATURE_LEN]>,
+    public_key: OptionalCell<&'static [u8]>,
+    random: Cell<[u32; RANDOM_LEN / 4]>,
+    entropy_client: OptionalCell<&'a dyn entropy::Client32>,
+    sign_client: OptionalCell<&'a dyn signature::ClientSign<HASH_LEN, SIGNATURE_LEN>>,
+    verify_client: OptionalCell<&'a dyn signature::ClientVerify<HASH_LEN, SIGNATURE_LEN>>,
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> Atecc608<'a, A, I> {
+    /// `key_slot` is the slot holding the private key used to sign.
+    pub fn new(
+        i2c: &'a I,
+        wake_i2c: &'a I,
+        alarm: &'a A,
+        key_slot: u16,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Atecc608 {
+            i2c,
+            wake_i2c,
+            alarm,
+            key_slot,
+            state: Cell::new(State::Idle),
+            operation: Cell::new(Command::Random),
+            result: Cell::new(Ok(true)),
+            buffer: TakeCell::new(buffer),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            public_key: OptionalCell::empty(),
+            random: Cell::new([0; RANDOM_LEN / 4]),
+            entropy_client: OptionalCell::empty(),
+            sign_client: OptionalCell::empty(),
+            verify_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Wake the device up to execute `command` and the commands that follow
+    /// it.
+    fn start(&self, command: Command) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer[0] = 0;
+        match self.wake_i2c.write(buffer, 1) {
+            Ok(()) => {
+                self.result.set(Ok(true));
+                self.operation.set(command);
+                self.state.set(State::Wake(command));
+                Ok(())
+            }
+            Err((_, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(ErrorCode::FAIL)
+            }
+        }
+    }
+
+    /// Write the packet of `command` to the device.
+    fn send_command(&self, command: Command, buffer: &'static mut [u8]) {
+        let (param1, param2): (u8, u16) = match command {
+            Command::Random => (0x00, 0x0000),
+            // Pass-through mode: the input is written to TempKey as is.
+            Command::SignNonce | Command::VerifyNonce => (0x03, 0x0000),
+            // Sign the message in TempKey with the key in the slot.
+            Command::Sign => (0x80, self.key_slot),
+            // External mode with a P-256 public key.
+            Command::Verify => (0x02, 0x0004),
+        };
+
+        let mut data_len = 0;
+        let data = &mut buffer[6..];
+        match command {
+            Command::SignNonce | Command::VerifyNonce => {
+                self.hash
+                    .map(|hash| data[..HASH_LEN].copy_from_slice(&hash[..]));
+                data_len = HASH_LEN;
+            }
+            Command::Verify => {
+                self.signature
+                    .map(|signature| data[..SIGNATURE_LEN].copy_from_slice(&signature[..]));
+                self.public_key.map(|public_key| {
+                    data[SIGNATURE_LEN..SIGNATURE_LEN + PUBLIC_KEY_LEN].copy_from_slice(public_key)
+                });
+                data_len = SIGNATURE_LEN + PUBLIC_KEY_LEN;
+            }
+            Command::Random | Command::Sign => {}
+        }
+
+        // The count covers everything but the word address.
+        let count = 1 + 4 + data_len + 2;
+        buffer[0] = WORD_ADDRESS_COMMAND;
+        buffer[1] = count as u8;
+        buffer[2] = command.opcode();
+        buffer[3] = param1;
+        buffer[4..6].copy_from_slice(&param2.to_le_bytes());
+        let crc = crc16(&buffer[1..count - 1]);
+        buffer[count - 1..count + 1].copy_from_slice(&crc.to_le_bytes());
+
+        match self.i2c.write(buffer, count + 1) {
+            Ok(()) => self.state.set(State::Command(command)),
+            Err((_, buffer)) => self.fail(buffer),
+        }
+    }
+
+    /// Handle the response of `command` and continue with the next command,
+    /// or put the device back to sleep.
+    fn handle_response(&self, command: Command, buffer: &'static mut [u8]) {
+        let result = response_data(&buffer[..command.response_len()]).and_then(|data| {
+            match (command, data.len()) {
+                (Command::Random, RANDOM_LEN) => {
+                    let mut random = [0; RANDOM_LEN / 4];
+                    for (word, bytes) in random.iter_mut().zip(data.chunks_exact(4)) {
+                        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    }
+                    self.random.set(random);
+                    Ok(true)
+                }
+                (Command::Sign, SIGNATURE_LEN) => {
+                    self.signature
+                        .map(|signature| signature.copy_from_slice(data));
+                    Ok(true)
+                }
+                (Command::Verify, 1) if data[0] == STATUS_MISCOMPARE => Ok(false),
+                (_, 1) if data[0] == STATUS_SUCCESS => Ok(true),
+                _ => Err(ErrorCode::FAIL),
+            }
+        });
+
+        self.result.set(result);
+        match (result, command.next()) {
+            (Ok(_), Some(next)) => self.send_command(next, buffer),
+            _ => self.sleep(buffer),
+        }
+    }
+
+    /// Put the device back to sleep, then report the result.
+    fn sleep(&self, buffer: &'static mut [u8]) {
+        buffer[0] = WORD_ADDRESS_SLEEP;
+        match self.i2c.write(buffer, 1) {
+            Ok(()) => self.state.set(State::Sleep),
+            Err((_, buffer)) => self.fail(buffer),
+        }
+    }
+
+    /// Abort the operation after an I2C error. The watchdog of the device
+    /// puts it back to sleep.
+    fn fail(&self, buffer: &'static mut [u8]) {
+        self.result.set(Err(ErrorCode::FAIL));
+        self.done(buffer);
+    }
+
+    /// Report the result of the operation to its client.
+    fn done(&self, buffer: &'static mut [u8]) {
+        self.buffer.replace(buffer);
+        self.state.set(State::Idle);
+
+        let result = self.result.get();
+        match self.operation.get() {
+            Command::Random => {
+                let more = self
+                    .entropy_client
+                    .map_or(entropy::Continue::Done, |client| {
+                        let mut iter = RandomIter {
+                            random: self.random.get(),
+                            index: 0,
+                        };
+                        client.entropy_available(&mut iter, result.map(|_| ()))
+                    });
+                if more == entropy::Continue::More && result.is_ok() {
+                    if let Err(e) = self.start(Command::Random) {
+                        self.entropy_client.map(|client| {
+                            client.entropy_available(&mut core::iter::empty(), Err(e))
+                        });
+                    }
+                }
+            }
+            Command::SignNonce | Command::Sign => {
+                if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+                    self.sign_client
+                        .map(|client| client.signing_done(result.map(|_| ()), hash, signature));
+                }
+            }
+            Command::VerifyNonce | Command::Verify => {
+                if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+                    self.verify_client
+                        .map(|client| client.verification_done(result, hash, signature));
+                }
+            }
+        }
+    }
+}
+
+/// Iterator over the words of a random number.
+struct RandomIter {
+    random: [u32; RANDOM_LEN / 4],
+    index: usize,
+}
+
+impl Iterator for RandomIter {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let word = self.random.get(self.index).copied();
+        self.index += 1;
+        word
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> i2c::I2CClient for Atecc608<'a, A, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        match self.state.get() {
+            State::Wake(command) => {
+                // The wake pulse is not acknowledged, ignore the status.
+                self.buffer.replace(buffer);
+                self.state.set(State::WakeDelay(command));
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(WAKE_DELAY_US));
+            }
+            State::WakeStatus(command) => {
+                if status.is_ok() && buffer[..WAKE_RESPONSE.len()] == WAKE_RESPONSE {
+                    self.send_command(command, buffer);
+                } else {
+                    self.result.set(Err(ErrorCode::NODEVICE));
+                    self.done(buffer);
+                }
+            }
+            State::Command(command) => {
+                if status.is_ok() {
+                    self.buffer.replace(buffer);
+                    self.state.set(State::Execute(command));
+                    self.alarm.set_alarm(
+                        self.alarm.now(),
+                        self.alarm.ticks_from_ms(command.execution_time_ms()),
+                    );
+                } else {
+                    self.fail(buffer);
+                }
+            }
+            State::Response(command) => {
+                if status.is_ok() {
+                    self.handle_response(command, buffer);
+                } else {
+                    self.fail(buffer);
+                }
+            }
+            State::Sleep => self.done(buffer),
+            State::Idle | State::WakeDelay(_) | State::Execute(_) => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> time::AlarmClient for Atecc608<'a, A, I> {
+    fn alarm(&self) {
+        let (next_state, len) = match self.state.get() {
+            State::WakeDelay(command) => (State::WakeStatus(command), WAKE_RESPONSE.len()),
+            State::Execute(command) => (State::Response(command), command.response_len()),
+            _ => return,
+        };
+        if let Some(buffer) = self.buffer.take() {
+            match self.i2c.read(buffer, len) {
+                Ok(()) => self.state.set(next_state),
+                Err((_, buffer)) => self.fail(buffer),
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> entropy::Entropy32<'a> for Atecc608<'a, A, I> {
+    fn get(&self) -> Result<(), ErrorCode> {
+        self.start(Command::Random)
+    }
+
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle && self.operation.get() == Command::Random {
+            // The command cannot be aborted.
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_client(&'a self, client: &'a dyn entropy::Client32) {
+        self.entropy_client.set(client);
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+    for Atecc608<'a, A, I>
+{
+    fn set_sign_client(&self, client: &'a dyn signature::ClientSign<HASH_LEN, SIGNATURE_LEN>) {
+        self.sign_client.set(client);
+    }
+
+    fn sign(
+        &self,
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            &'static mut [u8; HASH_LEN],
+            &'static mut [u8; SIGNATURE_LEN],
+        ),
+    > {
+        if let Err(e) = self.start(Command::SignNonce) {
+            return Err((e, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> signature::SignatureVerify<'a, HASH_LEN, SIGNATURE_LEN>
+    for Atecc608<'a, A, I>
+{
+    fn set_verify_client(&self, client: &'a dyn signature::ClientVerify<HASH_LEN, SIGNATURE_LEN>) {
+        self.verify_client.set(client);
+    }
+
+    /// Verify the signature with the public key imported with
+    /// `import_public_key()`. Returns `NODEVICE` if no key was imported.
+    fn verify(
+        &self,
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            &'static mut [u8; HASH_LEN],
+            &'static mut [u8; SIGNATURE_LEN],
+        ),
+    > {
+        if self.public_key.is_none() {
+            return Err((ErrorCode::NODEVICE, hash, signature));
+        }
+        if let Err(e) = self.start(Command::VerifyNonce) {
+            return Err((e, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> PubKey for Atecc608<'a, A, I> {
+    /// Import the raw P-256 public key (X followed by Y) used to verify
+    /// signatures.
+    fn import_public_key(
+        &self,
+        public_key: &'static [u8],
+    ) -> Result<(), (ErrorCode, &'static [u8])> {
+        if self.state.get() != State::Idle {
+            Err((ErrorCode::BUSY, public_key))
+        } else if public_key.len() != PUBLIC_KEY_LEN {
+            Err((ErrorCode::SIZE, public_key))
+        } else {
+            self.public_key.set(public_key);
+            Ok(())
+        }
+    }
+
+    fn pub_key(&self) -> Result<&'static [u8], ErrorCode> {
+        self.public_key.get().ok_or(ErrorCode::NODEVICE)
+    }
+
+    fn len(&self) -> usize {
+        self.public_key.map_or(0, |public_key| public_key.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc16, response_data, WAKE_RESPONSE};
+
+    #[test]
+    fn crc() {
+        // The CRC of the wake response is part of the response itself.
+        let crc = crc16(&WAKE_RESPONSE[..2]).to_le_bytes();
+        assert_eq!(crc, WAKE_RESPONSE[2..]);
+    }
+
+    #[test]
+    fn response() {
+        assert_eq!(response_data(&WAKE_RESPONSE), Ok(&[0x11][..]));
+
+        let mut packet = WAKE_RESPONSE;
+        packet[3] ^= 1;
+        assert_eq!(response_data(&packet), Err(kernel::ErrorCode::FAIL));
+        assert_eq!(
+            response_data(&[0x08, 0, 0, 0]),
+            Err(kernel::ErrorCode::FAIL)
+        );
+    }
+}
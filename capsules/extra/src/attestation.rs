@@ -0,0 +1,432 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Remote attestation of the loaded processes over UDP.
+//!
+//! A verifier sends a random nonce to the attestation port. The capsule
+//! replies with the measurement register and the process measurements (see
+//! `measured_boot`), signed with the device key together with the nonce. The
+//! verifier checks the signature with the public key of the device, then
+//! compares the measurements with the binaries it expects.
+//!
+//! Request:
+//!
+//! ```text
+//! nonce (32) | index (1, optional)
+//! ```
+//!
+//! Response:
+//!
+//! ```text
+//! status (1) | nonce (32) | register (32) | count (1) | index (1) | n (1) |
+//!     n * (ShortId (4, big endian) | digest (32)) | signature (64)
+//! ```
+//!
+//! `status` is `0` if the register is valid, and `1` while processes are
+//! being measured or if a process could not be measured. `count` is the
+//! number of measurements, and the response holds the `n` measurements
+//! starting at `index`; a UDP datagram is too small for all of them, so the
+//! verifier requests the next ones with a new nonce. The signature is over
+//! the SHA-256 hash of everything before it.
+//!
+//! Only one request is handled at a time, and requests received while the
+//! capsule is busy are dropped. The verifier retries after a timeout.
+//!
+//! Boards set up the capsule with `components::attestation`, with a digest
+//! engine and a signer (for example the ATECC608) that have no other client.
+
+use core::cell::Cell;
+
+use crate::measured_boot::{MeasurementLog, DIGEST_LEN};
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_port_table::UdpPortManager;
+use crate::net::udp::udp_recv::{UDPReceiver, UDPRecvClient};
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+use kernel::hil::digest::{ClientData, ClientHash, DigestDataHash, Sha256};
+use kernel::hil::public_key_crypto::signature::{ClientSign, SignatureSign};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+/// UDP port the capsule listens on unless the board chooses another one.
+pub const DEFAULT_PORT: u16 = 5685;
+
+pub const NONCE_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+const HEADER_LEN: usize = 1 + NONCE_LEN + DIGEST_LEN + 3;
+const ENTRY_LEN: usize = 4 + DIGEST_LEN;
+
+/// The smallest buffer that holds a response with one measurement.
+pub const MIN_BUFFER_LEN: usize = HEADER_LEN + ENTRY_LEN + SIGNATURE_LEN;
+
+const STATUS_OK: u8 = 0;
+const STATUS_INVALID: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Hashing,
+    Signing,
+    Sending,
+}
+
+/// Write the response to the request `nonce` for the measurements starting
+/// at `index` into `buffer`, without the signature. Return the length of the
+/// response, or `None` if `buffer` is too small.
+fn encode_response(
+    buffer: &mut [u8],
+    nonce: &[u8],
+    index: usize,
+    log: &dyn MeasurementLog,
+) -> Option<usize> {
+    if buffer.len() < HEADER_LEN + SIGNATURE_LEN {
+        return None;
+    }
+    let count = log.count();
+    let room = (buffer.len() - HEADER_LEN - SIGNATURE_LEN) / ENTRY_LEN;
+    let entries = count.saturating_sub(index).min(room);
+
+    match log.register() {
+        Some(register) => {
+            buffer[0] = STATUS_OK;
+            buffer[1 + NONCE_LEN..1 + NONCE_LEN + DIGEST_LEN].copy_from_slice(&register);
+        }
+        None => {
+            buffer[0] = STATUS_INVALID;
+            buffer[1 + NONCE_LEN..1 + NONCE_LEN + DIGEST_LEN].fill(0);
+        }
+    }
+    buffer[1..1 + NONCE_LEN].copy_from_slice(nonce);
+    buffer[HEADER_LEN - 3] = count as u8;
+    buffer[HEADER_LEN - 2] = index as u8;
+    buffer[HEADER_LEN - 1] = entries as u8;
+
+    let mut len = HEADER_LEN;
+    for i in index..index + entries {
+        let (short_id, digest) = log.measurement(i).unwrap_or((0, [0; DIGEST_LEN]));
+        buffer[len..len + 4].copy_from_slice(&short_id.to_be_bytes());
+        buffer[len + 4..len + ENTRY_LEN].copy_from_slice(&digest);
+        len += ENTRY_LEN;
+    }
+    Some(len)
+}
+
+pub struct Attestation<
+    'a,
+    D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+    S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+> {
+    udp_sender: &'a dyn UDPSender<'a>,
+    udp_receiver: &'a UDPReceiver<'a>,
+    port_table: &'static UdpPortManager,
+    net_cap: &'static NetworkCapability,
+    log: &'a dyn MeasurementLog,
+    digest: &'a D,
+    signer: &'a S,
+    state: Cell<State>,
+    /// Address and port of the verifier being answered.
+    peer: OptionalCell<(IPAddr, u16)>,
+    /// Length of the response without the signature.
+    len: Cell<usize>,
+    buffer: TakeCell<'static, [u8]>,
+    hash: TakeCell<'static, [u8; DIGEST_LEN]>,
+    signature: TakeCell<'static, [u8; SIGNATURE_LEN]>,
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+        S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+    > Attestation<'a, D, S>
+{
+    /// `buffer` must be at least `MIN_BUFFER_LEN` bytes, and should not be
+    /// longer than the maximum UDP payload of the board.
+    pub fn new(
+        udp_sender: &'a dyn UDPSender<'a>,
+        udp_receiver: &'a UDPReceiver<'a>,
+        port_table: &'static UdpPortManager,
+        net_cap: &'static NetworkCapability,
+        log: &'a dyn MeasurementLog,
+        digest: &'a D,
+        signer: &'a S,
+        buffer: &'static mut [u8],
+        hash: &'static mut [u8; DIGEST_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) -> Self {
+        Attestation {
+            udp_sender,
+            udp_receiver,
+            port_table,
+            net_cap,
+            log,
+            digest,
+            signer,
+            state: Cell::new(State::Idle),
+            peer: OptionalCell::empty(),
+            len: Cell::new(0),
+            buffer: TakeCell::new(buffer),
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+        }
+    }
+
+    /// Listen for requests on `port`.
+    pub fn bind(&self, port: u16) -> Result<(), ErrorCode> {
+        let socket = self
+            .port_table
+            .create_socket()
+            .map_err(|_| ErrorCode::NOMEM)?;
+        let (send_bind, rcv_bind) = self
+            .port_table
+            .bind(socket, port, self.net_cap)
+            .map_err(|_socket| ErrorCode::BUSY)?;
+        self.udp_sender.set_binding(send_bind);
+        self.udp_receiver.set_binding(rcv_bind);
+        Ok(())
+    }
+
+    /// Drop the current request.
+    fn abort(&self) {
+        self.peer.clear();
+        self.state.set(State::Idle);
+    }
+
+    /// Hash the response to `nonce` held in `buffer`.
+    fn hash_response(&self, nonce: &[u8], index: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let len = match encode_response(buffer, nonce, index, self.log) {
+            Some(len) => len,
+            None => {
+                self.buffer.replace(buffer);
+                return Err(ErrorCode::SIZE);
+            }
+        };
+        self.len.set(len);
+
+        self.digest.clear_data();
+        if let Err(e) = self.digest.set_mode_sha256() {
+            self.buffer.replace(buffer);
+            return Err(e);
+        }
+        let mut data = SubSliceMut::new(buffer);
+        data.slice(..len);
+        self.digest.add_mut_data(data).map_err(|(e, data)| {
+            self.buffer.replace(data.take());
+            e
+        })
+    }
+
+    fn send_response(&self, signature: &[u8; SIGNATURE_LEN]) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::FAIL)?;
+        let len = self.len.get();
+        buffer[len..len + SIGNATURE_LEN].copy_from_slice(signature);
+        let (addr, port) = match self.peer.get() {
+            Some(peer) => peer,
+            None => {
+                self.buffer.replace(buffer);
+                return Err(ErrorCode::FAIL);
+            }
+        };
+
+        let mut dgram = SubSliceMut::new(buffer);
+        dgram.slice(..len + SIGNATURE_LEN);
+        self.udp_sender
+            .send_to(addr, port, dgram, self.net_cap)
+            .map_err(|dgram| {
+                self.buffer.replace(dgram.take());
+                ErrorCode::FAIL
+            })
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+        S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+    > UDPRecvClient for Attestation<'a, D, S>
+{
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        if self.state.get() != State::Idle || payload.len() < NONCE_LEN {
+            return;
+        }
+        let index = payload.get(NONCE_LEN).copied().unwrap_or(0) as usize;
+
+        self.peer.set((src_addr, src_port));
+        self.state.set(State::Hashing);
+        if self.hash_response(&payload[..NONCE_LEN], index).is_err() {
+            self.abort();
+        }
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+        S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+    > ClientData<DIGEST_LEN> for Attestation<'a, D, S>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {}
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.buffer.replace(data.take());
+        if self.state.get() != State::Hashing {
+            return;
+        }
+        let run = result.and_then(|()| {
+            let hash = self.hash.take().ok_or(ErrorCode::FAIL)?;
+            self.digest.run(hash).map_err(|(e, hash)| {
+                self.hash.replace(hash);
+                e
+            })
+        });
+        if run.is_err() {
+            self.abort();
+        }
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+        S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+    > ClientHash<DIGEST_LEN> for Attestation<'a, D, S>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; DIGEST_LEN]) {
+        if self.state.get() != State::Hashing || result.is_err() {
+            self.hash.replace(digest);
+            self.abort();
+            return;
+        }
+        let Some(signature) = self.signature.take() else {
+            self.hash.replace(digest);
+            self.abort();
+            return;
+        };
+
+        self.state.set(State::Signing);
+        if let Err((_e, hash, signature)) = self.signer.sign(digest, signature) {
+            self.hash.replace(hash);
+            self.signature.replace(signature);
+            self.abort();
+        }
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+        S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+    > ClientSign<DIGEST_LEN, SIGNATURE_LEN> for Attestation<'a, D, S>
+{
+    fn signing_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        hash: &'static mut [u8; DIGEST_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) {
+        self.hash.replace(hash);
+        let sent = result.and_then(|()| self.send_response(signature));
+        self.signature.replace(signature);
+
+        match sent {
+            Ok(()) => self.state.set(State::Sending),
+            Err(_) => self.abort(),
+        }
+    }
+}
+
+impl<
+        'a,
+        D: DigestDataHash<'a, DIGEST_LEN> + Sha256,
+        S: SignatureSign<'a, DIGEST_LEN, SIGNATURE_LEN>,
+    > UDPSendClient for Attestation<'a, D, S>
+{
+    fn send_done(&self, _result: Result<(), ErrorCode>, dgram: SubSliceMut<'static, u8>) {
+        self.buffer.replace(dgram.take());
+        self.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Log {
+        register: Option<[u8; DIGEST_LEN]>,
+        count: usize,
+    }
+
+    impl MeasurementLog for Log {
+        fn register(&self) -> Option<[u8; DIGEST_LEN]> {
+            self.register
+        }
+
+        fn count(&self) -> usize {
+            self.count
+        }
+
+        fn measurement(&self, index: usize) -> Option<(u32, [u8; DIGEST_LEN])> {
+            (index < self.count).then_some((index as u32 + 1, [index as u8; DIGEST_LEN]))
+        }
+    }
+
+    #[test]
+    fn response_layout() {
+        let log = Log {
+            register: Some([0xaa; DIGEST_LEN]),
+            count: 3,
+        };
+        let mut buffer = [0; MIN_BUFFER_LEN + ENTRY_LEN];
+        let len = encode_response(&mut buffer, &[0x55; NONCE_LEN], 1, &log).unwrap();
+
+        assert_eq!(len, HEADER_LEN + 2 * ENTRY_LEN);
+        assert_eq!(buffer[0], STATUS_OK);
+        assert_eq!(buffer[1..1 + NONCE_LEN], [0x55; NONCE_LEN]);
+        assert_eq!(buffer[1 + NONCE_LEN..HEADER_LEN - 3], [0xaa; DIGEST_LEN]);
+        assert_eq!(buffer[HEADER_LEN - 3..HEADER_LEN], [3, 1, 2]);
+        assert_eq!(buffer[HEADER_LEN..HEADER_LEN + 4], [0, 0, 0, 2]);
+        assert_eq!(
+            buffer[HEADER_LEN + 4..HEADER_LEN + ENTRY_LEN],
+            [1; DIGEST_LEN]
+        );
+        assert_eq!(
+            buffer[HEADER_LEN + ENTRY_LEN..HEADER_LEN + ENTRY_LEN + 4],
+            [0, 0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn response_invalid_register() {
+        let log = Log {
+            register: None,
+            count: 0,
+        };
+        let mut buffer = [0xff; MIN_BUFFER_LEN];
+        let len = encode_response(&mut buffer, &[0; NONCE_LEN], 0, &log).unwrap();
+
+        assert_eq!(len, HEADER_LEN);
+        assert_eq!(buffer[0], STATUS_INVALID);
+        assert_eq!(buffer[1 + NONCE_LEN..HEADER_LEN - 3], [0; DIGEST_LEN]);
+        assert_eq!(buffer[HEADER_LEN - 3..HEADER_LEN], [0, 0, 0]);
+    }
+
+    #[test]
+    fn response_buffer_too_small() {
+        let log = Log {
+            register: None,
+            count: 0,
+        };
+        let mut buffer = [0; HEADER_LEN];
+        assert_eq!(encode_response(&mut buffer, &[0; NONCE_LEN], 0, &log), None);
+    }
+}
@@ -108,11 +108,10 @@
 use core::cell::Cell;
 use core::cmp;
 
-use kernel::debug;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil::ble_advertising;
 use kernel::hil::ble_advertising::RadioChannel;
-use kernel::hil::time::{Frequency, Ticks};
+use kernel::hil::time::{self, Frequency, Ticks};
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::OptionalCell;
@@ -317,8 +316,10 @@ impl App {
     fn set_next_alarm<F: Frequency>(&mut self, now: u32) {
         let nonce = self.random_nonce() % 10;
 
-        let period_ms = (self.advertisement_interval_ms + nonce) * F::frequency() / 1000;
-        self.alarm_data.expiration = Expiration::Enabled(now, period_ms);
+        let period_ms = self.advertisement_interval_ms.saturating_add(nonce) as u64;
+        let period = time::checked_ticks_from_ms(period_ms, F::frequency())
+            .map_or(u32::MAX, |ticks| ticks.min(u32::MAX as u64) as u32);
+        self.alarm_data.expiration = Expiration::Enabled(now, period);
     }
 }
 
@@ -433,7 +434,7 @@ where
                         // operation at the appropriate time. Instead, reschedule the
                         // operation for later. This is _kind_ of simulating actual
                         // on-air interference. 3 seems like a small number of ticks.
-                        debug!("BLE: operation delayed for app {:?}", processid);
+                        kernel::log_trace!(Radio, "BLE: operation delayed for app {:?}", processid);
                         app.set_next_alarm::<A::Frequency>(self.alarm.now().into_u32());
                         return;
                     }
@@ -463,9 +464,11 @@ where
                             self.radio
                                 .receive_advertisement(RadioChannel::AdvertisingChannel37);
                         }
-                        _ => debug!(
-                            "app: {:?} \t invalid state {:?}",
-                            processid, app.process_status
+                        _ => kernel::log_warn!(
+                            Radio,
+                            "BLE: app {:?} in invalid state {:?}",
+                            processid,
+                            app.process_status
                         ),
                     }
                 }
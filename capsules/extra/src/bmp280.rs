@@ -11,7 +11,6 @@
 //! Not implemented: pressure
 
 use core::cell::Cell;
-use kernel::debug;
 use kernel::hil;
 use kernel::hil::i2c;
 use kernel::hil::time::{Alarm, ConvertTicks};
@@ -267,7 +266,7 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> Bmp280<'a, A, I> {
         match self.state.get() {
             State::WaitingForAlarm(calibration) => self.buffer.take().map_or_else(
                 || {
-                    debug!("BMP280 No buffer available!");
+                    kernel::log_error!(Sensors, "BMP280: no buffer available");
                     self.state.set(State::IrrecoverableError)
                 },
                 |buffer| {
@@ -284,7 +283,7 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> Bmp280<'a, A, I> {
             ),
             State::IrrecoverableError => {}
             other => {
-                debug!("BMP280 received unexpected alarm in state {:?}", other);
+                kernel::log_error!(Sensors, "BMP280: unexpected alarm in state {:?}", other);
                 self.state.set(other.to_bug())
             }
         }
@@ -403,7 +402,11 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> i2c::I2CClient for Bmp280<'a, A, I> {
                     State::Idle(calibration)
                 }
                 other => {
-                    debug!("BMP280 received unexpected i2c reply in state {:?}", other);
+                    kernel::log_error!(
+                        Sensors,
+                        "BMP280: unexpected i2c reply in state {:?}",
+                        other
+                    );
                     other.to_bug()
                 }
             },
@@ -419,7 +422,11 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> i2c::I2CClient for Bmp280<'a, A, I> {
                 | State::InitWaitingReady
                 | State::InitReadingCalibration => State::Error,
                 other => {
-                    debug!("BMP280 received unexpected i2c reply in state {:?}", other);
+                    kernel::log_error!(
+                        Sensors,
+                        "BMP280: unexpected i2c reply in state {:?}",
+                        other
+                    );
                     other.to_bug()
                 }
             },
@@ -0,0 +1,524 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Persistent boot configuration, similar to the u-boot environment.
+//!
+//! The boot configuration is a list of `key=value` settings (console baud
+//! rate, default radio channel, ...) stored as text lines in a dedicated
+//! flash region, for example:
+//!
+//! ```text
+//! console.baud=115200
+//! radio.channel=26
+//! ```
+//!
+//! The lines end with `\n` and the list ends at the first `0x00` or `0xFF`
+//! byte, so an erased or zeroed region is an empty configuration. Keys are
+//! made of ASCII letters, digits, `_`, `.` and `-`; values are printable
+//! ASCII.
+//!
+//! The board reads the settings with [`BootEnv`] directly from the
+//! memory-mapped region, before it configures the capsules that use them.
+//! [`BootConfigStore`] keeps a RAM copy of the settings that can be edited
+//! through the process console (`env` command) or by a privileged process,
+//! and writes it back to flash after every change. Changes take effect at
+//! the next boot, so the board can be reconfigured without reflashing the
+//! kernel.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! kernel::storage_volume!(BOOT_CONFIG, 4);
+//!
+//! let boot_env = capsules_extra::boot_config::BootEnv::new(&BOOT_CONFIG);
+//! let baud_rate = boot_env.get_u32("console.baud").unwrap_or(115200);
+//!
+//! let boot_config = static_init!(
+//!     capsules_extra::boot_config::BootConfigStore<'static>,
+//!     capsules_extra::boot_config::BootConfigStore::new(
+//!         nv_to_page,
+//!         &BOOT_CONFIG,
+//!         core::ptr::addr_of!(BOOT_CONFIG) as usize,
+//!         kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1).unwrap()),
+//!         board_kernel.create_grant(capsules_extra::boot_config::DRIVER_NUM, &grant_cap),
+//!         static_init!([u8; 4096], [0; 4096]),
+//!     )
+//! );
+//! nv_to_page.set_client(boot_config);
+//! process_console.set_boot_config(boot_config);
+//! ```
+
+use core::str;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::BootConfig as usize;
+
+/// Maximum length of a key.
+pub const MAX_KEY_LENGTH: usize = 32;
+/// Maximum length of a value.
+pub const MAX_VALUE_LENGTH: usize = 64;
+
+/// Value of the free space after the settings.
+const ERASED: u8 = 0xFF;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A change requested by the process was saved.
+    pub const SAVED: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Key of the setting.
+    pub const KEY: usize = 0;
+    /// New value of the setting.
+    pub const VALUE: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Receives the value of the setting.
+    pub const VALUE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+fn valid_key(key: &[u8]) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_KEY_LENGTH
+        && key
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || c == b'-')
+}
+
+fn valid_value(value: &[u8]) -> bool {
+    value.len() <= MAX_VALUE_LENGTH && value.iter().all(|&c| (0x20..0x7F).contains(&c))
+}
+
+/// Read-only view of the settings stored in a boot configuration image.
+#[derive(Clone, Copy)]
+pub struct BootEnv<'a> {
+    image: &'a [u8],
+}
+
+impl<'a> BootEnv<'a> {
+    pub fn new(image: &'a [u8]) -> BootEnv<'a> {
+        BootEnv { image }
+    }
+
+    /// Number of bytes used by the settings.
+    fn used(&self) -> usize {
+        self.image
+            .iter()
+            .position(|&c| c == 0x00 || c == ERASED)
+            .unwrap_or(self.image.len())
+    }
+
+    /// Iterate over the lines of the image, with their offset. The lines
+    /// include their `\n` terminator.
+    fn lines(&self) -> impl Iterator<Item = (usize, &'a [u8])> {
+        let image = &self.image[..self.used()];
+        image
+            .split_inclusive(|&c| c == b'\n')
+            .scan(0, |offset, line| {
+                let start = *offset;
+                *offset += line.len();
+                Some((start, line))
+            })
+    }
+
+    /// Split a line into its key and value, if it is well formed.
+    fn parse(line: &[u8]) -> Option<(&str, &str)> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let separator = line.iter().position(|&c| c == b'=')?;
+        let (key, value) = (&line[..separator], &line[separator + 1..]);
+        if !valid_key(key) || !valid_value(value) {
+            return None;
+        }
+        // Both parts are ASCII.
+        Some((str::from_utf8(key).ok()?, str::from_utf8(value).ok()?))
+    }
+
+    /// Iterate over the well-formed settings. Malformed lines are ignored.
+    pub fn entries(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.lines().filter_map(|(_, line)| Self::parse(line))
+    }
+
+    /// Value of `key`, if it is set.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.entries()
+            .find(|(entry, _)| *entry == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Value of `key` as a decimal or `0x`-prefixed hexadecimal number.
+    pub fn get_u32(&self, key: &str) -> Option<u32> {
+        let value = self.get(key)?;
+        match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        }
+    }
+
+    /// Offsets of the start and end of the line setting `key`.
+    fn find(&self, key: &str) -> Option<(usize, usize)> {
+        self.lines()
+            .find(|(_, line)| Self::parse(line).map_or(false, |(entry, _)| entry == key))
+            .map(|(start, line)| (start, start + line.len()))
+    }
+}
+
+/// Remove the line setting `key` from `image`. Returns `false` if the key is
+/// not set.
+fn remove(image: &mut [u8], key: &str) -> bool {
+    let env = BootEnv::new(image);
+    let used = env.used();
+    match env.find(key) {
+        Some((start, end)) => {
+            image.copy_within(end..used, start);
+            image[used - (end - start)..].fill(ERASED);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Set `key` to `value` in `image`, replacing the previous value.
+fn insert(image: &mut [u8], key: &str, value: &str) -> Result<(), ErrorCode> {
+    if !valid_key(key.as_bytes()) || !valid_value(value.as_bytes()) {
+        return Err(ErrorCode::INVAL);
+    }
+
+    let env = BootEnv::new(image);
+    let mut used = env.used();
+    let (replaced, last) = env
+        .find(key)
+        .map_or((0, false), |(start, end)| (end - start, end == used));
+    // A hand-written image may not end with a newline. The line before the
+    // replaced one always does.
+    let separator = !last && used > 0 && image[used - 1] != b'\n';
+    let length = key.len() + value.len() + 2;
+    if used - replaced + usize::from(separator) + length > image.len() {
+        return Err(ErrorCode::SIZE);
+    }
+
+    remove(image, key);
+    used -= replaced;
+    if separator {
+        image[used] = b'\n';
+        used += 1;
+    }
+    for part in [key.as_bytes(), b"=", value.as_bytes(), b"\n"] {
+        image[used..used + part.len()].copy_from_slice(part);
+        used += part.len();
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct BootConfigStore<'a> {
+    driver: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+    /// Address of the boot configuration region as seen by `driver`.
+    region_address: usize,
+    /// Only the process with this ShortId may change the settings.
+    privileged: ShortId,
+    /// RAM copy of the region. Taken while it is written to flash.
+    image: TakeCell<'static, [u8]>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Process waiting for its change to be saved.
+    current_app: OptionalCell<ProcessId>,
+}
+
+impl<'a> BootConfigStore<'a> {
+    /// `buffer` holds the RAM copy of `region`, and must be as large as the
+    /// region since it is written back in full.
+    pub fn new(
+        driver: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+        region: &[u8],
+        region_address: usize,
+        privileged: ShortId,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        buffer: &'static mut [u8],
+    ) -> BootConfigStore<'a> {
+        let length = core::cmp::min(region.len(), buffer.len());
+        buffer[..length].copy_from_slice(&region[..length]);
+        buffer[length..].fill(ERASED);
+
+        BootConfigStore {
+            driver,
+            region_address,
+            privileged,
+            image: TakeCell::new(buffer),
+            apps: grant,
+            current_app: OptionalCell::empty(),
+        }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        match self.privileged {
+            ShortId::Fixed(_) => processid.short_app_id() == self.privileged,
+            ShortId::LocallyUnique => false,
+        }
+    }
+
+    /// Apply `change` to the RAM copy and write it to flash.
+    fn modify(
+        &self,
+        change: impl FnOnce(&mut [u8]) -> Result<(), ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        let image = self.image.take().ok_or(ErrorCode::BUSY)?;
+        if let Err(e) = change(image) {
+            self.image.replace(image);
+            return Err(e);
+        }
+        let length = image.len();
+        self.driver.write(image, self.region_address, length)
+    }
+
+    /// Run `f` with the key in the read-only allow buffer of `processid`.
+    fn with_key<R>(
+        &self,
+        processid: ProcessId,
+        f: impl FnOnce(&str, &kernel::grant::GrantKernelData) -> Result<R, ErrorCode>,
+    ) -> Result<R, ErrorCode> {
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                let mut key = [0; MAX_KEY_LENGTH];
+                let length = kernel_data
+                    .get_readonly_processbuffer(ro_allow::KEY)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            if buffer.len() > MAX_KEY_LENGTH {
+                                return Err(ErrorCode::INVAL);
+                            }
+                            buffer.copy_to_slice(&mut key[..buffer.len()]);
+                            Ok(buffer.len())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))?;
+                let key = str::from_utf8(&key[..length]).map_err(|_| ErrorCode::INVAL)?;
+                f(key, kernel_data)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn get_value(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        self.with_key(processid, |key, kernel_data| {
+            let mut value = [0; MAX_VALUE_LENGTH];
+            let length = hil::boot_config::BootConfig::get(self, key, &mut value)?;
+            kernel_data
+                .get_readwrite_processbuffer(rw_allow::VALUE)
+                .and_then(|buffer| {
+                    buffer.mut_enter(|buffer| {
+                        if buffer.len() < length {
+                            return Err(ErrorCode::SIZE);
+                        }
+                        buffer[..length].copy_from_slice(&value[..length]);
+                        Ok(length)
+                    })
+                })
+                .unwrap_or(Err(ErrorCode::RESERVE))
+        })
+    }
+
+    fn set_value(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.with_key(processid, |key, kernel_data| {
+            let mut value = [0; MAX_VALUE_LENGTH];
+            let length = kernel_data
+                .get_readonly_processbuffer(ro_allow::VALUE)
+                .and_then(|buffer| {
+                    buffer.enter(|buffer| {
+                        if buffer.len() > MAX_VALUE_LENGTH {
+                            return Err(ErrorCode::INVAL);
+                        }
+                        buffer.copy_to_slice(&mut value[..buffer.len()]);
+                        Ok(buffer.len())
+                    })
+                })
+                .unwrap_or(Err(ErrorCode::RESERVE))?;
+            let value = str::from_utf8(&value[..length]).map_err(|_| ErrorCode::INVAL)?;
+            hil::boot_config::BootConfig::set(self, key, value)
+        })
+        .map(|()| self.current_app.set(processid))
+    }
+
+    fn unset_value(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.with_key(processid, |key, _| {
+            hil::boot_config::BootConfig::unset(self, key)
+        })
+        .map(|()| self.current_app.set(processid))
+    }
+}
+
+impl hil::boot_config::BootConfig for BootConfigStore<'_> {
+    fn count(&self) -> usize {
+        self.image
+            .map_or(0, |image| BootEnv::new(image).entries().count())
+    }
+
+    fn with_entry(&self, index: usize, f: &mut dyn FnMut(&str, &str)) -> bool {
+        self.image.map_or(false, |image| {
+            BootEnv::new(image)
+                .entries()
+                .nth(index)
+                .map(|(key, value)| f(key, value))
+                .is_some()
+        })
+    }
+
+    fn get(&self, key: &str, value: &mut [u8]) -> Result<usize, ErrorCode> {
+        self.image.map_or(Err(ErrorCode::BUSY), |image| {
+            let setting = BootEnv::new(image).get(key).ok_or(ErrorCode::FAIL)?;
+            value
+                .get_mut(..setting.len())
+                .ok_or(ErrorCode::SIZE)?
+                .copy_from_slice(setting.as_bytes());
+            Ok(setting.len())
+        })
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), ErrorCode> {
+        self.modify(|image| insert(image, key, value))
+    }
+
+    fn unset(&self, key: &str) -> Result<(), ErrorCode> {
+        self.modify(|image| {
+            if remove(image, key) {
+                Ok(())
+            } else {
+                Err(ErrorCode::FAIL)
+            }
+        })
+    }
+}
+
+impl hil::nonvolatile_storage::NonvolatileStorageClient for BootConfigStore<'_> {
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.image.replace(buffer);
+
+        if let Some(processid) = self.current_app.take() {
+            let _ = self.apps.enter(processid, |_app, upcalls| {
+                upcalls.schedule_upcall(upcall::SAVED, (0, 0, 0)).ok();
+            });
+        }
+    }
+}
+
+impl SyscallDriver for BootConfigStore<'_> {
+    /// Boot configuration access.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Copy the value of the key in read-only allow buffer `0` into
+    ///   read-write allow buffer `0`, and return its length.
+    /// - `2`: Set the key in read-only allow buffer `0` to the value in
+    ///   read-only allow buffer `1`. Only allowed for the privileged process.
+    /// - `3`: Remove the key in read-only allow buffer `0`. Only allowed for
+    ///   the privileged process.
+    /// - `4`: Return the number of settings.
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.get_value(processid) {
+                Ok(length) => CommandReturn::success_u32(length as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            2 | 3 => {
+                if !self.is_privileged(processid) {
+                    return CommandReturn::failure(ErrorCode::NOSUPPORT);
+                }
+                if self.current_app.is_some() {
+                    return CommandReturn::failure(ErrorCode::BUSY);
+                }
+                if command_num == 2 {
+                    self.set_value(processid).into()
+                } else {
+                    self.unset_value(processid).into()
+                }
+            }
+
+            4 => CommandReturn::success_u32(hil::boot_config::BootConfig::count(self) as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit() {
+        let mut image = [ERASED; 48];
+        image[..20].copy_from_slice(b"console.baud=9600\nx=");
+        assert_eq!(BootEnv::new(&image).get_u32("console.baud"), Some(9600));
+        assert_eq!(BootEnv::new(&image).get("x"), Some(""));
+
+        insert(&mut image, "radio.channel", "0x1a").unwrap();
+        insert(&mut image, "console.baud", "115200").unwrap();
+        let env = BootEnv::new(&image);
+        assert_eq!(env.get_u32("radio.channel"), Some(26));
+        assert_eq!(env.get_u32("console.baud"), Some(115200));
+        assert_eq!(
+            &image[..env.used()],
+            b"x=\nradio.channel=0x1a\nconsole.baud=115200\n"
+        );
+
+        assert_eq!(
+            insert(&mut image, "long", "0123456789"),
+            Err(ErrorCode::SIZE)
+        );
+        assert_eq!(insert(&mut image, "bad key", "1"), Err(ErrorCode::INVAL));
+
+        assert!(remove(&mut image, "radio.channel"));
+        assert!(!remove(&mut image, "radio.channel"));
+        let env = BootEnv::new(&image);
+        assert_eq!(&image[..env.used()], b"x=\nconsole.baud=115200\n");
+        assert_eq!(env.entries().count(), 2);
+    }
+}
@@ -28,6 +28,40 @@
 //! - if it's greater the 0, the message will be copied to the RW buffer
 //!   but no upcall will be done
 //!
+//! The configuration and the reception of messages are reserved to one
+//! process at a time, but all the processes can send messages. Each process
+//! has its own transmit queue, in its grant, holding the messages waiting
+//! for the controller. A process can queue up to its quota of messages,
+//! given by the board with a [`TxQuota`] policy, and the queues are served
+//! round-robin: a process sending many messages delays the messages of
+//! another process by at most one message.
+//!
+//! If the board gives the capsule the [`can::Filter`] implementation of the
+//! peripheral, the process receiving messages can also install filters, so
+//! that only the messages it is interested in are received. Each filter is
+//! described in a second RO buffer (see [`filter_parameters`]).
+//!
+//! Processes can also send remote frames (RTR), requesting the data of an
+//! identifier. The remote frames received are not copied to the RW buffer,
+//! as they carry no data: each one is reported with its own upcall.
+//!
+//! Processes can also receive messages without configuring the peripheral,
+//! by installing software filters (identifier and mask pairs): while the
+//! process using the capsule receives, each message is copied to the RW
+//! buffer of every process with a matching filter. The process using the
+//! capsule receives every message as long as it has no software filter.
+//! Software filters only see the messages accepted by the filters of the
+//! peripheral.
+//!
+//! In the silent loopback mode, the process using the capsule can run a
+//! self-test: a frame with a known payload is transmitted and must be
+//! received back unchanged, without any bit driven on the bus.
+//!
+//! When the peripheral goes bus-off, and when it is back on the bus, the
+//! process using the capsule is notified. Depending on the recovery it
+//! configured, the peripheral recovers by itself or when the process
+//! restarts it.
+//!
 //! Usage
 //! -----
 //!
@@ -46,12 +80,19 @@
 //! kernel::hil::can::Controller::set_client(can_peripheral, Some(can));
 //! kernel::hil::can::Transmit::set_client(can_peripheral, Some(can));
 //! kernel::hil::can::Receive::set_client(can_peripheral, Some(can));
+//! // Optional: let the receiving process install filters.
+//! can.set_filter(can_peripheral);
 //! ```
 //!
+//! The length of the transmit queues is a const generic parameter of the
+//! capsule, `DEFAULT_TX_QUEUE_LEN` by default.
+//!
 
+use core::cell::Cell;
+use core::cmp;
 use core::mem::size_of;
 
-use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil::can;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -66,6 +107,21 @@ pub const BYTE3_MASK: usize = 0xff0000;
 pub const BYTE2_MASK: usize = 0xff00;
 pub const BYTE1_MASK: usize = 0xff;
 
+/// Default number of messages each process can queue for transmission.
+pub const DEFAULT_TX_QUEUE_LEN: usize = 4;
+
+/// Payload of the frame transmitted by the loopback self-test, with bit
+/// patterns exercising the bit stuffing.
+const SELF_TEST_DATA: [u8; can::STANDARD_CAN_PACKET_SIZE] =
+    [0x00, 0xff, 0x55, 0xaa, 0x0f, 0xf0, 0x33, 0xcc];
+
+/// Number of software receive filters of each process.
+pub const RX_FILTER_COUNT: usize = 4;
+
+/// Bit of the identifier of a software filter selecting extended
+/// identifiers.
+pub const RX_FILTER_EXTENDED: usize = 1 << 31;
+
 mod error_upcalls {
     pub const ERROR_TX: usize = 100;
     pub const ERROR_RX: usize = 101;
@@ -78,12 +134,16 @@ mod up_calls {
     pub const UPCALL_MESSAGE_RECEIVED: usize = 3;
     pub const UPCALL_RECEIVED_STOPPED: usize = 4;
     pub const UPCALL_TRANSMISSION_ERROR: usize = 5;
-    pub const COUNT: u8 = 6;
+    pub const UPCALL_REMOTE_FRAME_RECEIVED: usize = 6;
+    pub const UPCALL_BUS_OFF: usize = 7;
+    pub const UPCALL_SELF_TEST: usize = 8;
+    pub const COUNT: u8 = 9;
 }
 
 mod ro_allow {
     pub const RO_ALLOW_BUFFER: usize = 0;
-    pub const COUNT: u8 = 1;
+    pub const FILTER: usize = 1;
+    pub const COUNT: u8 = 2;
 }
 
 mod rw_allow {
@@ -91,7 +151,94 @@ mod rw_allow {
     pub const COUNT: u8 = 1;
 }
 
-pub struct CanCapsule<'a, Can: can::Can> {
+/// Length of the description of a filter, in the filter RO buffer.
+pub const FILTER_DESCRIPTION_LEN: usize = 12;
+
+mod filter_flags {
+    pub const EXTENDED: u8 = 1 << 0;
+    pub const LIST: u8 = 1 << 1;
+    pub const SCALE_16: u8 = 1 << 2;
+    pub const FIFO_1: u8 = 1 << 3;
+}
+
+/// Parse the description of a filter written by a process:
+///
+/// ```text
+/// 0        1       2          4            8                     12
+/// +--------+-------+----------+------------+---------------------+
+/// | number | flags | reserved | id (u32le) | mask or id2 (u32le) |
+/// +--------+-------+----------+------------+---------------------+
+/// ```
+///
+/// The flags select an extended identifier (bit 0), the `List` mode
+/// (bit 1), a 16-bit scale (bit 2) and the receive FIFO 1 (bit 3).
+pub fn filter_parameters(description: &[u8; FILTER_DESCRIPTION_LEN]) -> can::FilterParameters {
+    let flags = description[1];
+    let id = u32::from_le_bytes([
+        description[4],
+        description[5],
+        description[6],
+        description[7],
+    ]);
+    let mask = u32::from_le_bytes([
+        description[8],
+        description[9],
+        description[10],
+        description[11],
+    ]);
+    can::FilterParameters {
+        number: description[0] as u32,
+        scale_bits: if flags & filter_flags::SCALE_16 != 0 {
+            can::ScaleBits::Bits16
+        } else {
+            can::ScaleBits::Bits32
+        },
+        identifier_mode: if flags & filter_flags::LIST != 0 {
+            can::IdentifierMode::List
+        } else {
+            can::IdentifierMode::Mask
+        },
+        fifo_number: usize::from(flags & filter_flags::FIFO_1 != 0),
+        id: if flags & filter_flags::EXTENDED != 0 {
+            can::Id::Extended(id)
+        } else {
+            // saturate, so that the driver rejects an out of range identifier
+            can::Id::Standard(u16::try_from(id).unwrap_or(u16::MAX))
+        },
+        mask,
+    }
+}
+
+/// A software receive filter, accepting the messages whose identifier has
+/// the same kind as `id` and equals it on the bits set in `mask`.
+#[derive(Copy, Clone)]
+struct RxFilter {
+    id: can::Id,
+    mask: u32,
+}
+
+impl RxFilter {
+    fn matches(&self, id: can::Id) -> bool {
+        match (self.id, id) {
+            (can::Id::Standard(filter), can::Id::Standard(id)) => {
+                (filter ^ id) as u32 & self.mask == 0
+            }
+            (can::Id::Extended(filter), can::Id::Extended(id)) => (filter ^ id) & self.mask == 0,
+            _ => false,
+        }
+    }
+}
+
+/// Board policy deciding how many messages each process can queue.
+pub trait TxQuota {
+    /// The number of messages `processid` can have waiting for the
+    /// controller, behind the message being transmitted. The quota is capped
+    /// to the length of the transmit queues; `0` only lets the process send
+    /// when the controller is idle.
+    fn tx_quota(&self, processid: ProcessId) -> usize;
+}
+
+pub struct CanCapsule<'a, Can: can::Can, const TX_QUEUE_LEN: usize = DEFAULT_TX_QUEUE_LEN> {
     // CAN driver
     can: &'a Can,
 
@@ -101,7 +248,7 @@ pub struct CanCapsule<'a, Can: can::Can> {
 
     // Process
     processes: Grant<
-        App,
+        App<TX_QUEUE_LEN>,
         UpcallCount<{ up_calls::COUNT }>,
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<{ rw_allow::COUNT }>,
@@ -111,84 +258,447 @@ pub struct CanCapsule<'a, Can: can::Can> {
     // Variable used to store the current state of the CAN peripheral
     // during an `enable` or `disable` command.
     peripheral_state: OptionalCell<can::State>,
+
+    // Whether the peripheral is bus-off.
+    bus_off: Cell<bool>,
+
+    // Process running the loopback self-test, and the identifier of the
+    // self-test frame.
+    self_test: OptionalCell<(ProcessId, can::Id)>,
+
+    // Process whose message is being transmitted.
+    tx_process: OptionalCell<ProcessId>,
+    // Whether the message being transmitted is the self-test frame.
+    tx_self_test: Cell<bool>,
+    tx_quota: OptionalCell<&'a dyn TxQuota>,
+
+    // Filter banks of the peripheral, if processes can configure them.
+    filter: OptionalCell<&'a dyn can::Filter>,
+
+    // Error counters and bus state of the peripheral, if it reports them.
+    diagnostics: OptionalCell<&'a dyn can::Diagnostics>,
+}
+
+/// A message waiting in a transmit queue.
+#[derive(Copy, Clone)]
+struct TxMessage {
+    id: can::Id,
+    length: usize,
+    remote: bool,
+    data: [u8; can::STANDARD_CAN_PACKET_SIZE],
+}
+
+/// Ring buffer of the messages a process queued for transmission.
+struct TxQueue<const N: usize> {
+    messages: [Option<TxMessage>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Default for TxQueue<N> {
+    fn default() -> Self {
+        TxQueue {
+            messages: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> TxQueue<N> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `false` if the queue is full.
+    fn push(&mut self, message: TxMessage) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.messages[(self.head + self.len) % N] = Some(message);
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<TxMessage> {
+        if self.len == 0 {
+            return None;
+        }
+        let message = self.messages[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        message
+    }
 }
 
 #[derive(Default)]
-pub struct App {
+pub struct App<const TX_QUEUE_LEN: usize = DEFAULT_TX_QUEUE_LEN> {
     receive_index: usize,
     lost_messages: u32,
+    tx_queue: TxQueue<TX_QUEUE_LEN>,
+    rx_filters: [Option<RxFilter>; RX_FILTER_COUNT],
+}
+
+impl<const TX_QUEUE_LEN: usize> App<TX_QUEUE_LEN> {
+    /// Whether the messages with `id` are copied to this process. `owner` is
+    /// whether the process uses the capsule.
+    fn receives(&self, id: can::Id, owner: bool) -> bool {
+        if self.rx_filters.iter().all(Option::is_none) {
+            owner
+        } else {
+            self.rx_filters
+                .iter()
+                .flatten()
+                .any(|filter| filter.matches(id))
+        }
+    }
+
+    /// Install a software filter, returning its index.
+    fn add_rx_filter(&mut self, id: usize, mask: usize) -> Result<usize, ErrorCode> {
+        let id = if id & RX_FILTER_EXTENDED != 0 {
+            let id = id & !RX_FILTER_EXTENDED;
+            if id > 0x1fff_ffff {
+                return Err(ErrorCode::INVAL);
+            }
+            can::Id::Extended(id as u32)
+        } else {
+            if id > 0x7ff {
+                return Err(ErrorCode::INVAL);
+            }
+            can::Id::Standard(id as u16)
+        };
+        let index = self
+            .rx_filters
+            .iter()
+            .position(Option::is_none)
+            .ok_or(ErrorCode::NOMEM)?;
+        self.rx_filters[index] = Some(RxFilter {
+            id,
+            mask: mask as u32,
+        });
+        Ok(index)
+    }
+
+    fn remove_rx_filter(&mut self, index: usize) -> Result<(), ErrorCode> {
+        self.rx_filters
+            .get_mut(index)
+            .and_then(Option::take)
+            .map(|_| ())
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    /// Copy a received message to the RW buffer of the process. Returns
+    /// whether the buffer was empty, and its length.
+    fn copy_message(
+        &mut self,
+        kernel_data: &GrantKernelData,
+        message: &[u8],
+    ) -> Result<(bool, usize), ErrorCode> {
+        let mut new_buffer = false;
+        let mut shared_len = 0;
+        kernel_data
+            .get_readwrite_processbuffer(rw_allow::RW_ALLOW_BUFFER)
+            .map_or_else(
+                |err| err.into(),
+                |buffer_ref| {
+                    buffer_ref
+                        .mut_enter(|user_buffer| {
+                            shared_len = user_buffer.len();
+                            // For now, the first 4 bytes (the size of u32) represent the number
+                            // of messages that the user has not read yet, represented as Little Endian.
+                            // When the userspace reads the buffer, the counter will be set
+                            // to 0 so that the capsule knows. This will be changed after
+                            // https://github.com/tock/tock/pull/3252 and
+                            // https://github.com/tock/tock/pull/3258 are merged.
+                            let mut tmp_buf: [u8; size_of::<u32>()] = [0; size_of::<u32>()];
+                            user_buffer[0..size_of::<u32>()].copy_to_slice(&mut tmp_buf);
+                            let contor = u32::from_le_bytes(tmp_buf);
+                            if contor == 0 {
+                                new_buffer = true;
+                                self.receive_index = size_of::<u32>();
+                            }
+                            user_buffer[0..size_of::<u32>()]
+                                .copy_from_slice(&(contor + 1).to_le_bytes());
+                            if self.receive_index + message.len() > user_buffer.len() {
+                                self.lost_messages += 1;
+                                Err(ErrorCode::SIZE)
+                            } else {
+                                let r = user_buffer
+                                    [self.receive_index..self.receive_index + message.len()]
+                                    .copy_from_slice_or_err(message);
+                                if r.is_ok() {
+                                    self.receive_index += message.len();
+                                }
+                                r
+                            }
+                        })
+                        .unwrap_or_else(|err| err.into())
+                },
+            )
+            .map(|()| (new_buffer, shared_len))
+    }
 }
 
-impl<'a, Can: can::Can> CanCapsule<'a, Can> {
+impl<'a, Can: can::Can, const TX_QUEUE_LEN: usize> CanCapsule<'a, Can, TX_QUEUE_LEN> {
     pub fn new(
         can: &'a Can,
         grant: Grant<
-            App,
+            App<TX_QUEUE_LEN>,
             UpcallCount<{ up_calls::COUNT }>,
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
         can_tx: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
         can_rx: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
-    ) -> CanCapsule<'a, Can> {
+    ) -> CanCapsule<'a, Can, TX_QUEUE_LEN> {
         CanCapsule {
             can,
             can_tx: TakeCell::new(can_tx),
             can_rx: TakeCell::new(can_rx),
             processes: grant,
             peripheral_state: OptionalCell::empty(),
+            bus_off: Cell::new(false),
+            self_test: OptionalCell::empty(),
             processid: OptionalCell::empty(),
+            tx_process: OptionalCell::empty(),
+            tx_self_test: Cell::new(false),
+            tx_quota: OptionalCell::empty(),
+            filter: OptionalCell::empty(),
+            diagnostics: OptionalCell::empty(),
         }
     }
 
+    /// Set the policy giving the quota of each process. Without a policy,
+    /// every process can fill its transmit queue.
+    pub fn set_tx_quota(&self, tx_quota: &'a dyn TxQuota) {
+        self.tx_quota.set(tx_quota);
+    }
+
+    /// Let the process receiving messages configure the filter banks of the
+    /// peripheral.
+    pub fn set_filter(&self, filter: &'a dyn can::Filter) {
+        self.filter.set(filter);
+    }
+
+    /// Let processes query the error counters and the bus state of the
+    /// peripheral.
+    pub fn set_diagnostics(&self, diagnostics: &'a dyn can::Diagnostics) {
+        self.diagnostics.set(diagnostics);
+    }
+
+    /// Enable the filter described in the filter buffer of `processid`.
+    fn enable_filter(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let filter = self.filter.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let mut description = [0; FILTER_DESCRIPTION_LEN];
+        self.processes
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::FILTER)
+                    .and_then(|buffer_ref| {
+                        buffer_ref.enter(|buffer| {
+                            buffer.get(0..FILTER_DESCRIPTION_LEN).map_or(
+                                Err(ErrorCode::SIZE),
+                                |buffer| {
+                                    buffer.copy_to_slice(&mut description);
+                                    Ok(())
+                                },
+                            )
+                        })
+                    })
+                    .unwrap_or_else(|err| err.into())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        filter.enable_filter(filter_parameters(&description))
+    }
+
     fn schedule_callback(&self, callback_number: usize, data: (usize, usize, usize)) {
         self.processid.map(|processid| {
-            let _ = self.processes.enter(processid, |_app, kernel_data| {
-                kernel_data
-                    .schedule_upcall(callback_number, (data.0, data.1, data.2))
-                    .ok();
-            });
+            self.schedule_process_callback(processid, callback_number, data);
+        });
+    }
+
+    fn schedule_process_callback(
+        &self,
+        processid: ProcessId,
+        callback_number: usize,
+        data: (usize, usize, usize),
+    ) {
+        let _ = self.processes.enter(processid, |_app, kernel_data| {
+            kernel_data
+                .schedule_upcall(callback_number, (data.0, data.1, data.2))
+                .ok();
         });
     }
 
+    fn get_tx_quota(&self, processid: ProcessId) -> usize {
+        self.tx_quota.map_or(TX_QUEUE_LEN, |tx_quota| {
+            cmp::min(tx_quota.tx_quota(processid), TX_QUEUE_LEN)
+        })
+    }
+
     /// This function makes a copy of the buffer in the grant and sends it
-    /// to the low-level hardware, in order for it to be sent on the bus.
+    /// to the low-level hardware, in order for it to be sent on the bus. If
+    /// the controller is transmitting another message, the message is queued
+    /// in the transmit queue of the process. A remote frame requesting
+    /// `length` bytes is sent without reading the buffer.
     pub fn process_send_command(
         &self,
         processid: ProcessId,
         id: can::Id,
         length: usize,
+        remote: bool,
     ) -> Result<(), ErrorCode> {
-        self.processes
-            .enter(processid, |_, kernel_data| {
-                kernel_data
-                    .get_readonly_processbuffer(ro_allow::RO_ALLOW_BUFFER)
-                    .map_or_else(
-                        |err| err.into(),
-                        |buffer_ref| {
-                            buffer_ref
-                                .enter(|buffer| {
-                                    self.can_tx.take().map_or(
-                                        Err(ErrorCode::NOMEM),
-                                        |dest_buffer| {
-                                            for i in 0..length {
-                                                dest_buffer[i] = buffer[i].get();
-                                            }
-                                            match self.can.send(id, dest_buffer, length) {
-                                                Ok(()) => Ok(()),
-                                                Err((err, buf)) => {
-                                                    self.can_tx.replace(buf);
-                                                    Err(err)
-                                                }
-                                            }
-                                        },
-                                    )
-                                })
-                                .unwrap_or_else(|err| err.into())
-                        },
-                    )
+        if length > can::STANDARD_CAN_PACKET_SIZE {
+            return Err(ErrorCode::SIZE);
+        }
+        let idle = self.tx_process.is_none();
+        let quota = self.get_tx_quota(processid);
+        let message = self
+            .processes
+            .enter(processid, |app, kernel_data| {
+                if !idle && app.tx_queue.len() >= quota {
+                    return Err(ErrorCode::BUSY);
+                }
+                let mut message = TxMessage {
+                    id,
+                    length,
+                    remote,
+                    data: [0; can::STANDARD_CAN_PACKET_SIZE],
+                };
+                if !remote {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::RO_ALLOW_BUFFER)
+                        .and_then(|buffer_ref| {
+                            buffer_ref.enter(|buffer| {
+                                buffer
+                                    .get(0..length)
+                                    .map_or(Err(ErrorCode::SIZE), |buffer| {
+                                        buffer.copy_to_slice(&mut message.data[..length]);
+                                        Ok(())
+                                    })
+                            })
+                        })
+                        .unwrap_or_else(|err| err.into())?;
+                }
+                if idle {
+                    Ok(Some(message))
+                } else {
+                    app.tx_queue.push(message);
+                    Ok(None)
+                }
             })
-            .unwrap_or_else(|err| err.into())
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        message.map_or(Ok(()), |message| self.transmit(processid, message))
+    }
+
+    fn transmit(&self, processid: ProcessId, message: TxMessage) -> Result<(), ErrorCode> {
+        let dest_buffer = self.can_tx.take().ok_or(ErrorCode::NOMEM)?;
+        dest_buffer[..message.length].copy_from_slice(&message.data[..message.length]);
+        let flags = if message.remote {
+            can::FrameFlags::REMOTE
+        } else {
+            can::FrameFlags::CLASSIC
+        };
+        match self
+            .can
+            .send_with_flags(message.id, dest_buffer, message.length, flags)
+        {
+            Ok(()) => {
+                self.tx_process.set(processid);
+                Ok(())
+            }
+            Err((err, buf)) => {
+                self.can_tx.replace(buf);
+                Err(err)
+            }
+        }
+    }
+
+    /// Start the loopback self-test, transmitting a frame with `id` that the
+    /// peripheral must receive back.
+    fn start_self_test(&self, processid: ProcessId, id: usize) -> Result<(), ErrorCode> {
+        if !matches!(
+            self.can.get_operation_mode(),
+            Ok(can::OperationMode::SilentLoopback)
+        ) || id > 0x7ff
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        // the frame could not be received back
+        if self.can_rx.is_some() {
+            return Err(ErrorCode::OFF);
+        }
+        if self.self_test.is_some() || self.tx_process.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let id = can::Id::Standard(id as u16);
+        self.transmit(
+            processid,
+            TxMessage {
+                id,
+                length: SELF_TEST_DATA.len(),
+                remote: false,
+                data: SELF_TEST_DATA,
+            },
+        )?;
+        self.tx_self_test.set(true);
+        self.self_test.set((processid, id));
+        Ok(())
+    }
+
+    fn finish_self_test(&self, result: Result<(), ErrorCode>) {
+        if let Some((processid, _)) = self.self_test.take() {
+            self.schedule_process_callback(
+                processid,
+                up_calls::UPCALL_SELF_TEST,
+                (result.map_or_else(|err| err as usize, |()| 0), 0, 0),
+            );
+        }
+    }
+
+    /// Transmit the next queued message.
+    ///
+    /// Processes are served round-robin, starting after the process whose
+    /// message was just transmitted.
+    fn transmit_next(&self, last: Option<ProcessId>) {
+        while self.tx_process.is_none() {
+            let mut first = None;
+            let mut next = None;
+            let mut after_last = last.is_none();
+            for cntr in self.processes.iter() {
+                let processid = cntr.processid();
+                if cntr.enter(|app, _| app.tx_queue.len() > 0) {
+                    if after_last {
+                        next = Some(processid);
+                        break;
+                    }
+                    first = first.or(Some(processid));
+                }
+                after_last = after_last || last == Some(processid);
+            }
+
+            match next.or(first) {
+                Some(processid) => {
+                    let message = self
+                        .processes
+                        .enter(processid, |app, _| app.tx_queue.pop())
+                        .ok()
+                        .flatten();
+                    // A message that cannot be transmitted is dropped, and
+                    // the next process is tried.
+                    if let Some(Err(err)) = message.map(|message| self.transmit(processid, message))
+                    {
+                        self.schedule_process_callback(
+                            processid,
+                            up_calls::UPCALL_TRANSMISSION_ERROR,
+                            (error_upcalls::ERROR_TX, err as usize, 0),
+                        );
+                    }
+                }
+                None => break,
+            }
+        }
     }
 
     pub fn is_valid_process(&self, processid: ProcessId) -> bool {
@@ -200,7 +710,9 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
     }
 }
 
-impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
+impl<'a, Can: can::Can, const TX_QUEUE_LEN: usize> SyscallDriver
+    for CanCapsule<'a, Can, TX_QUEUE_LEN>
+{
     fn command(
         &self,
         command_num: usize,
@@ -208,9 +720,93 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
         arg2: usize,
         processid: ProcessId,
     ) -> CommandReturn {
-        // This driver exists.
-        if command_num == 0 {
-            return CommandReturn::success();
+        match command_num {
+            // This driver exists.
+            0 => return CommandReturn::success(),
+
+            // Send a message with a 16-bit identifier
+            5 => {
+                let id = can::Id::Standard(arg1 as u16);
+                return self.process_send_command(processid, id, arg2, false).into();
+            }
+
+            // Send a message with a 32-bit identifier
+            6 => {
+                let id = can::Id::Extended(arg1 as u32);
+                return self.process_send_command(processid, id, arg2, false).into();
+            }
+
+            // Send a remote frame with a 16-bit identifier
+            14 => {
+                let id = can::Id::Standard(arg1 as u16);
+                return self.process_send_command(processid, id, arg2, true).into();
+            }
+
+            // Send a remote frame with a 32-bit identifier
+            15 => {
+                let id = can::Id::Extended(arg1 as u32);
+                return self.process_send_command(processid, id, arg2, true).into();
+            }
+
+            // Get the number of queued messages and the quota of the process
+            10 => {
+                return self
+                    .processes
+                    .enter(processid, |app, _| {
+                        CommandReturn::success_u32_u32(
+                            app.tx_queue.len() as u32,
+                            self.get_tx_quota(processid) as u32,
+                        )
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into()));
+            }
+
+            // Get the number of filter banks
+            13 => {
+                return self
+                    .filter
+                    .map_or(CommandReturn::failure(ErrorCode::NOSUPPORT), |filter| {
+                        CommandReturn::success_u32(filter.filter_count() as u32)
+                    });
+            }
+
+            // Install a software receive filter
+            19 => {
+                return self
+                    .processes
+                    .enter(processid, |app, _| app.add_rx_filter(arg1, arg2))
+                    .unwrap_or_else(|err| Err(err.into()))
+                    .map_or_else(CommandReturn::failure, |index| {
+                        CommandReturn::success_u32(index as u32)
+                    });
+            }
+
+            // Remove a software receive filter
+            20 => {
+                return self
+                    .processes
+                    .enter(processid, |app, _| app.remove_rx_filter(arg1))
+                    .unwrap_or_else(|err| Err(err.into()))
+                    .into();
+            }
+
+            // Get the error counters, the error state and the last error
+            16 => {
+                return self.diagnostics.map_or(
+                    CommandReturn::failure(ErrorCode::NOSUPPORT),
+                    |diagnostics| match diagnostics.bus_status() {
+                        Ok(status) => CommandReturn::success_u32_u32_u32(
+                            status.transmit_error_count as u32,
+                            status.receive_error_count as u32,
+                            status.state as u32
+                                | status.last_error.map_or(0, |err| err as u32 + 1) << 8,
+                        ),
+                        Err(err) => CommandReturn::failure(err),
+                    },
+                );
+            }
+
+            _ => {}
         }
 
         // Check to see if the process or no process at all
@@ -235,6 +831,7 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
                     0 => can::OperationMode::Loopback,
                     1 => can::OperationMode::Monitoring,
                     2 => can::OperationMode::Freeze,
+                    4 => can::OperationMode::SilentLoopback,
                     _ => can::OperationMode::Normal,
                 }) {
                     Ok(()) => CommandReturn::success(),
@@ -254,32 +851,6 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
                 Err(err) => CommandReturn::failure(err),
             },
 
-            // Send a message with a 16-bit identifier
-            5 => {
-                let id = can::Id::Standard(arg1 as u16);
-                self.processid
-                    .map_or(
-                        CommandReturn::failure(ErrorCode::BUSY),
-                        |processid| match self.process_send_command(processid, id, arg2) {
-                            Ok(()) => CommandReturn::success(),
-                            Err(err) => CommandReturn::failure(err),
-                        },
-                    )
-            }
-
-            // Send a message with a 32-bit identifier
-            6 => {
-                let id = can::Id::Extended(arg1 as u32);
-                self.processid
-                    .map_or(
-                        CommandReturn::failure(ErrorCode::BUSY),
-                        |processid| match self.process_send_command(processid, id, arg2) {
-                            Ok(()) => CommandReturn::success(),
-                            Err(err) => CommandReturn::failure(err),
-                        },
-                    )
-            }
-
             // Start receiving messages
             7 => {
                 self.can_rx
@@ -337,6 +908,26 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
                 }
             }
 
+            // Enable the filter described in the filter buffer
+            11 => self.enable_filter(processid).into(),
+
+            // Restart the peripheral after it went bus-off
+            17 => self.can.restart().into(),
+
+            // Set the automatic bus-off recovery
+            18 => self.can.set_automatic_bus_off_recovery(arg1 != 0).into(),
+
+            // Run the loopback self-test
+            21 => self.start_self_test(processid, arg1).into(),
+
+            // Disable a filter bank
+            12 => self
+                .filter
+                .map_or(Err(ErrorCode::NOSUPPORT), |filter| {
+                    filter.disable_filter(arg1 as u32)
+                })
+                .into(),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -346,10 +937,28 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
     }
 }
 
-impl<'a, Can: can::Can> can::ControllerClient for CanCapsule<'a, Can> {
+impl<'a, Can: can::Can, const TX_QUEUE_LEN: usize> can::ControllerClient
+    for CanCapsule<'a, Can, TX_QUEUE_LEN>
+{
     // This callback must be called after an `enable` or `disable` command was sent.
-    // It stores the new state of the peripheral.
+    // It stores the new state of the peripheral. It is also called when the
+    // peripheral goes bus-off and when it is back on the bus, which is
+    // reported to the process using the capsule.
     fn state_changed(&self, state: can::State) {
+        match state {
+            can::State::Error(can::Error::BusOff) => {
+                if !self.bus_off.replace(true) {
+                    self.schedule_callback(up_calls::UPCALL_BUS_OFF, (1, 0, 0));
+                }
+            }
+            can::State::Running => {
+                if self.bus_off.replace(false) {
+                    self.schedule_callback(up_calls::UPCALL_BUS_OFF, (0, 0, 0));
+                }
+            }
+            can::State::Disabled => self.bus_off.set(false),
+            can::State::Error(_) => {}
+        }
         self.peripheral_state.replace(state);
     }
 
@@ -407,35 +1016,50 @@ impl<'a, Can: can::Can> can::ControllerClient for CanCapsule<'a, Can> {
                 self.schedule_callback(up_calls::UPCALL_ENABLE, (err as usize, 0, 0));
             }
         }
+        self.finish_self_test(Err(ErrorCode::CANCEL));
         self.processid.clear();
     }
 }
 
-impl<'a, Can: can::Can> can::TransmitClient<{ can::STANDARD_CAN_PACKET_SIZE }>
-    for CanCapsule<'a, Can>
+impl<'a, Can: can::Can, const TX_QUEUE_LEN: usize>
+    can::TransmitClient<{ can::STANDARD_CAN_PACKET_SIZE }> for CanCapsule<'a, Can, TX_QUEUE_LEN>
 {
     // This callback is called when the hardware acknowledges that a message
-    // was sent. This callback also makes an upcall to the userspace.
+    // was sent. This callback also makes an upcall to the process that sent
+    // the message, and starts the transmission of the next queued message.
     fn transmit_complete(
         &self,
         status: Result<(), can::Error>,
         buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
     ) {
         self.can_tx.replace(buffer);
-        match status {
-            Ok(()) => self.schedule_callback(up_calls::UPCALL_MESSAGE_SENT, (0, 0, 0)),
+        let sender = self.tx_process.take();
+        if self.tx_self_test.take() {
+            // the self-test completes when the frame is received back
+            if let Err(err) = status {
+                self.finish_self_test(Err(err.into()));
+            }
+            self.transmit_next(sender);
+            return;
+        }
+        sender.map(|processid| match status {
+            Ok(()) => {
+                self.schedule_process_callback(processid, up_calls::UPCALL_MESSAGE_SENT, (0, 0, 0))
+            }
             Err(err) => {
-                self.schedule_callback(
+                self.schedule_process_callback(
+                    processid,
                     up_calls::UPCALL_TRANSMISSION_ERROR,
                     (error_upcalls::ERROR_TX, err as usize, 0),
                 );
             }
-        }
+        });
+        self.transmit_next(sender);
     }
 }
 
-impl<'a, Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }>
-    for CanCapsule<'a, Can>
+impl<'a, Can: can::Can, const TX_QUEUE_LEN: usize>
+    can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }> for CanCapsule<'a, Can, TX_QUEUE_LEN>
 {
     // This callback is called when a new message is received on any receiving
     // fifo.
@@ -446,78 +1070,40 @@ impl<'a, Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }>
         len: usize,
         status: Result<(), can::Error>,
     ) {
-        let mut new_buffer = false;
-        let mut shared_len = 0;
         match status {
+            Ok(()) if self.self_test.map_or(false, |(_, test_id)| test_id == id) => {
+                self.finish_self_test(if buffer[0..len] == SELF_TEST_DATA {
+                    Ok(())
+                } else {
+                    Err(ErrorCode::FAIL)
+                });
+            }
             Ok(()) => {
-                match self.processid.map_or(Err(ErrorCode::NOMEM), |processid| {
-                    self.processes
-                        .enter(processid, |app_data, kernel_data| {
-                            kernel_data
-                                .get_readwrite_processbuffer(rw_allow::RW_ALLOW_BUFFER)
-                                .map_or_else(
-                                    |err| err.into(),
-                                    |buffer_ref| {
-                                        buffer_ref
-                                            .mut_enter(|user_buffer| {
-                                                shared_len = user_buffer.len();
-                                                // For now, the first 4 bytes (the size of u32) represent the number
-                                                // of messages that the user has not read yet, represented as Little Endian.
-                                                // When the userspace reads the buffer, the counter will be set
-                                                // to 0 so that the capsule knows. This will be changed after
-                                                // https://github.com/tock/tock/pull/3252 and
-                                                // https://github.com/tock/tock/pull/3258 are merged.
-                                                let mut tmp_buf: [u8; size_of::<u32>()] =
-                                                    [0; size_of::<u32>()];
-                                                user_buffer[0..size_of::<u32>()]
-                                                    .copy_to_slice(&mut tmp_buf);
-                                                let contor = u32::from_le_bytes(tmp_buf);
-                                                if contor == 0 {
-                                                    new_buffer = true;
-                                                    app_data.receive_index = size_of::<u32>();
-                                                }
-                                                user_buffer[0..size_of::<u32>()]
-                                                    .copy_from_slice(&(contor + 1).to_le_bytes());
-                                                if app_data.receive_index + len > user_buffer.len()
-                                                {
-                                                    app_data.lost_messages += 1;
-                                                    Err(ErrorCode::SIZE)
-                                                } else {
-                                                    let r = user_buffer[app_data.receive_index
-                                                        ..app_data.receive_index + len]
-                                                        .copy_from_slice_or_err(&buffer[0..len]);
-                                                    if r.is_ok() {
-                                                        app_data.receive_index += len;
-                                                    }
-                                                    r
-                                                }
-                                            })
-                                            .unwrap_or_else(|err| err.into())
-                                    },
-                                )
-                        })
-                        .unwrap_or_else(|err| err.into())
-                }) {
-                    Err(err) => self.schedule_callback(
-                        up_calls::UPCALL_TRANSMISSION_ERROR,
-                        (error_upcalls::ERROR_RX, err as usize, 0),
-                    ),
-                    Ok(()) => {
-                        if new_buffer {
-                            self.schedule_callback(
-                                up_calls::UPCALL_MESSAGE_RECEIVED,
-                                (
-                                    0,
-                                    shared_len,
-                                    match id {
-                                        can::Id::Standard(u16) => u16 as usize,
-                                        can::Id::Extended(u32) => u32 as usize,
-                                    },
-                                ),
-                            )
-                        }
+                let owner = self.processid.get();
+                self.processes.each(|processid, app, kernel_data| {
+                    if !app.receives(id, Some(processid) == owner) {
+                        return;
                     }
-                }
+                    match app.copy_message(kernel_data, &buffer[0..len]) {
+                        Err(err) => kernel_data.schedule_upcall(
+                            up_calls::UPCALL_TRANSMISSION_ERROR,
+                            (error_upcalls::ERROR_RX, err as usize, 0),
+                        ),
+                        Ok((true, shared_len)) => kernel_data.schedule_upcall(
+                            up_calls::UPCALL_MESSAGE_RECEIVED,
+                            (
+                                0,
+                                shared_len,
+                                match id {
+                                    can::Id::Standard(u16) => u16 as usize,
+                                    can::Id::Extended(u32) => u32 as usize,
+                                },
+                            ),
+                        ),
+                        Ok((false, _)) => Ok(()),
+                    }
+                    .ok();
+                });
             }
             Err(err) => {
                 let kernel_err: ErrorCode = err.into();
@@ -529,8 +1115,129 @@ impl<'a, Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }>
         };
     }
 
+    // Remote frames carry no data, so they are reported to the userspace
+    // with the requested length and the identifier instead of being copied
+    // to the RW buffer.
+    fn message_received_with_flags(
+        &self,
+        id: can::Id,
+        buffer: &mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        len: usize,
+        flags: can::FrameFlags,
+        status: Result<(), can::Error>,
+    ) {
+        if !flags.remote || status.is_err() {
+            self.message_received(id, buffer, len, status);
+            return;
+        }
+        let (extended, raw_id) = match id {
+            can::Id::Standard(id) => (0, id as usize),
+            can::Id::Extended(id) => (1, id as usize),
+        };
+        let owner = self.processid.get();
+        self.processes.each(|processid, app, kernel_data| {
+            if app.receives(id, Some(processid) == owner) {
+                kernel_data
+                    .schedule_upcall(
+                        up_calls::UPCALL_REMOTE_FRAME_RECEIVED,
+                        (extended, len, raw_id),
+                    )
+                    .ok();
+            }
+        });
+    }
+
     fn stopped(&self, buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE]) {
         self.can_rx.replace(buffer);
+        self.finish_self_test(Err(ErrorCode::CANCEL));
         self.schedule_callback(up_calls::UPCALL_RECEIVED_STOPPED, (0, 0, 0));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u16) -> TxMessage {
+        TxMessage {
+            id: can::Id::Standard(id),
+            length: 0,
+            remote: false,
+            data: [0; can::STANDARD_CAN_PACKET_SIZE],
+        }
+    }
+
+    fn pop_id<const N: usize>(queue: &mut TxQueue<N>) -> Option<u16> {
+        queue.pop().map(|message| match message.id {
+            can::Id::Standard(id) => id,
+            can::Id::Extended(id) => id as u16,
+        })
+    }
+
+    #[test]
+    fn software_filters() {
+        let mut app: App = App::default();
+        let standard = can::Id::Standard(0x123);
+        let extended = can::Id::Extended(0x123);
+        // without filters, only the process using the capsule receives
+        assert!(app.receives(standard, true));
+        assert!(!app.receives(standard, false));
+
+        assert_eq!(app.add_rx_filter(0x120, 0x7f0), Ok(0));
+        assert!(app.receives(standard, false));
+        assert!(!app.receives(can::Id::Standard(0x133), true));
+        assert!(!app.receives(extended, false));
+
+        assert_eq!(app.add_rx_filter(RX_FILTER_EXTENDED | 0x123, 0), Ok(1));
+        assert!(app.receives(can::Id::Extended(0x1abc_def0), false));
+        assert_eq!(app.add_rx_filter(0x800, 0), Err(ErrorCode::INVAL));
+
+        assert_eq!(app.remove_rx_filter(1), Ok(()));
+        assert_eq!(app.remove_rx_filter(1), Err(ErrorCode::INVAL));
+        assert!(!app.receives(extended, false));
+        assert_eq!(app.remove_rx_filter(0), Ok(()));
+        assert!(app.receives(standard, true));
+    }
+
+    #[test]
+    fn filter_descriptions() {
+        let description = [3, 0, 0, 0, 0x23, 0x01, 0, 0, 0xff, 0x07, 0, 0];
+        let filter = filter_parameters(&description);
+        assert_eq!(filter.number, 3);
+        assert!(matches!(filter.scale_bits, can::ScaleBits::Bits32));
+        assert!(matches!(filter.identifier_mode, can::IdentifierMode::Mask));
+        assert_eq!(filter.fifo_number, 0);
+        assert!(matches!(filter.id, can::Id::Standard(0x123)));
+        assert_eq!(filter.mask, 0x7ff);
+
+        let flags = filter_flags::EXTENDED
+            | filter_flags::LIST
+            | filter_flags::SCALE_16
+            | filter_flags::FIFO_1;
+        let description = [0, flags, 0, 0, 0x67, 0x45, 0x23, 0x01, 1, 0, 0, 0];
+        let filter = filter_parameters(&description);
+        assert!(matches!(filter.scale_bits, can::ScaleBits::Bits16));
+        assert!(matches!(filter.identifier_mode, can::IdentifierMode::List));
+        assert_eq!(filter.fifo_number, 1);
+        assert!(matches!(filter.id, can::Id::Extended(0x01234567)));
+        assert_eq!(filter.mask, 1);
+    }
+
+    #[test]
+    fn tx_queue_is_fifo() {
+        let mut queue = TxQueue::<3>::default();
+        assert!(queue.push(message(1)));
+        assert!(queue.push(message(2)));
+        assert!(queue.push(message(3)));
+        assert!(!queue.push(message(4)));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(pop_id(&mut queue), Some(1));
+        // The ring wraps around.
+        assert!(queue.push(message(5)));
+        assert_eq!(pop_id(&mut queue), Some(2));
+        assert_eq!(pop_id(&mut queue), Some(3));
+        assert_eq!(pop_id(&mut queue), Some(5));
+        assert_eq!(pop_id(&mut queue), None);
+        assert_eq!(queue.len(), 0);
+    }
+}
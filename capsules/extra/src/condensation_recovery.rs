@@ -0,0 +1,188 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Condensation recovery for humidity sensors with a heater.
+//!
+//! Condensation on the sensing element of a humidity sensor makes it report
+//! saturated readings long after the air dried. [`CondensationRecovery`]
+//! sits between a humidity sensor and its client, and implements
+//! `recover_condensation` with the heater of the sensor:
+//!
+//! 1. The heater is switched on, and kept on for the requested duration.
+//! 2. The heater is switched off. Sensors heating in pulses, such as the
+//!    SHT4x, switch it off by themselves and return `ALREADY`.
+//! 3. The next readings are taken while the sensor cools down, and
+//!    discarded.
+//!
+//! `heater_done` is called on the client at the end of the sequence. A
+//! reading requested during the sequence is taken after it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let recovery = static_init!(
+//!     CondensationRecovery<'static, VirtualMuxAlarm<'static, Rtc>, SHT3xSensor>,
+//!     CondensationRecovery::new(sht3x, virtual_alarm, 3)
+//! );
+//! HumidityDriver::set_client(sht3x, recovery);
+//! virtual_alarm.set_alarm_client(recovery);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::sensors::{HumidityClient, HumidityDriver};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Switching the heater on, to heat for the given duration.
+    StartHeating(u32),
+    Heating,
+    StopHeating,
+    /// Discarding the given number of readings.
+    Discarding(usize),
+}
+
+pub struct CondensationRecovery<'a, A: time::Alarm<'a>, H: HumidityDriver<'a>> {
+    sensor: &'a H,
+    alarm: &'a A,
+    /// Number of readings discarded after the heater is switched off.
+    discard_count: usize,
+    state: Cell<State>,
+    /// The client requested a reading during the sequence.
+    pending_read: Cell<bool>,
+    client: OptionalCell<&'a dyn HumidityClient>,
+}
+
+impl<'a, A: time::Alarm<'a>, H: HumidityDriver<'a>> CondensationRecovery<'a, A, H> {
+    pub fn new(
+        sensor: &'a H,
+        alarm: &'a A,
+        discard_count: usize,
+    ) -> CondensationRecovery<'a, A, H> {
+        CondensationRecovery {
+            sensor,
+            alarm,
+            discard_count,
+            state: Cell::new(State::Idle),
+            pending_read: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn stop_heating(&self) {
+        self.state.set(State::StopHeating);
+        match self.sensor.set_heater(false) {
+            Ok(()) => {}
+            // The heater is already off, or cannot be switched off: the
+            // readings are discarded anyway.
+            Err(_) => self.discard(self.discard_count),
+        }
+    }
+
+    /// Take and discard `count` readings, then end the sequence.
+    fn discard(&self, count: usize) {
+        if count == 0 {
+            self.finish(Ok(()));
+            return;
+        }
+        self.state.set(State::Discarding(count));
+        if let Err(e) = self.sensor.read_humidity() {
+            self.finish(Err(e));
+        }
+    }
+
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.heater_done(result));
+        if self.pending_read.take() && self.sensor.read_humidity().is_err() {
+            // Report the failure as an empty reading, as sensors do.
+            self.client.map(|client| client.callback(0));
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: HumidityDriver<'a>> HumidityDriver<'a>
+    for CondensationRecovery<'a, A, H>
+{
+    fn set_client(&self, client: &'a dyn HumidityClient) {
+        self.client.set(client);
+    }
+
+    fn read_humidity(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Idle {
+            self.sensor.read_humidity()
+        } else if self.pending_read.get() {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.pending_read.set(true);
+            Ok(())
+        }
+    }
+
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.sensor.set_heater(enabled)
+    }
+
+    fn recover_condensation(&self, duration_ms: u32) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.sensor.set_heater(true)?;
+        self.state.set(State::StartHeating(duration_ms));
+        Ok(())
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: HumidityDriver<'a>> HumidityClient
+    for CondensationRecovery<'a, A, H>
+{
+    fn callback(&self, value: usize) {
+        match self.state.get() {
+            State::Discarding(count) => self.discard(count - 1),
+            // A reading requested before the sequence.
+            _ => {
+                self.client.map(|client| client.callback(value));
+            }
+        }
+    }
+
+    fn heater_done(&self, result: Result<(), ErrorCode>) {
+        match self.state.get() {
+            State::Idle => {
+                self.client.map(|client| client.heater_done(result));
+            }
+            State::StartHeating(duration_ms) => match result {
+                Ok(()) => {
+                    self.state.set(State::Heating);
+                    self.alarm
+                        .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(duration_ms));
+                }
+                Err(e) => self.finish(Err(e)),
+            },
+            State::StopHeating => match result {
+                Ok(()) => self.discard(self.discard_count),
+                Err(e) => self.finish(Err(e)),
+            },
+            State::Heating | State::Discarding(_) => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: HumidityDriver<'a>> time::AlarmClient
+    for CondensationRecovery<'a, A, H>
+{
+    fn alarm(&self) {
+        if self.state.get() == State::Heating {
+            self.stop_heating();
+        }
+    }
+}
@@ -0,0 +1,1015 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for UART fingerprint modules (ZFM-20, AS608, R30x and
+//! compatibles).
+//!
+//! These modules take an image of the finger, extract its features into one
+//! of two character buffers, and combine two captures of the same finger
+//! into a template. Templates are stored in the flash of the module, in
+//! numbered slots, where the module searches them for a match.
+//!
+//! The module is driven with command packets and answers each of them with
+//! an acknowledge packet. Templates are transferred in data packets:
+//!
+//! ```text
+//! 0xEF01 | address (4) | identifier (1) | length (2) | payload | checksum (2)
+//! ```
+//!
+//! The length counts the payload and the checksum, and the checksum is the
+//! sum of the identifier, length and payload bytes. All fields are big
+//! endian.
+//!
+//! The driver waits for a finger by polling the module every
+//! `POLL_INTERVAL_MS`, for at most `FINGER_TIMEOUT_MS`.
+//!
+//! If a key-value store is set with `set_template_store()`, enrolled
+//! templates are also read back from the module and saved in the store. They
+//! can then be restored into a replaced or erased module.
+//!
+//! Only one operation runs at a time, for the process that started it.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! - `0`: Enrollment progress, with the status, the stage, and the template
+//!   slot. The stages are `1`: the first image was taken, the finger must be
+//!   lifted; `2`: the finger was lifted and must be placed again; `3`: the
+//!   template was stored. On failure the third argument is the confirmation
+//!   code of the module, if any.
+//! - `1`: Match result, with the status, the matching template slot and the
+//!   match score. If the finger matches no template, the status is `FAIL`
+//!   and the third argument is the confirmation code `0x09`.
+//! - `2`: Completion of a delete or a restore, with the status and the
+//!   template slot, or the confirmation code of the module on failure.
+//!
+//! The status is `CANCEL` if no finger was presented in time.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Enroll a finger in template slot `data`.
+//! - `2`: Match a finger against the stored templates.
+//! - `3`: Delete the template in slot `data`.
+//! - `4`: Restore the template of slot `data` from the key-value store.
+//!   Returns `NOSUPPORT` without a key-value store.
+//! - `5`: Cancel the operation of the process.
+//! - `6`: Get the number of template slots.
+//!
+//! Commands `1` to `4` return `BUSY` while an operation is running, and
+//! `INVAL` if the slot is out of range.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let fingerprint = components::fingerprint::FingerprintComponent::new(
+//!     board_kernel,
+//!     capsules_extra::fingerprint::DRIVER_NUM,
+//!     uart_mux,
+//!     mux_alarm,
+//!     162,
+//! )
+//! .finalize(components::fingerprint_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::kv;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::uart;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Fingerprint as usize;
+
+/// Default address of the modules.
+pub const DEFAULT_ADDRESS: u32 = 0xFFFF_FFFF;
+
+/// Length of the payload of the data packets.
+pub const DATA_PACKET_LEN: usize = 128;
+/// Size of the transmit and receive buffers.
+pub const BUFFER_LEN: usize = HEADER_LEN + DATA_PACKET_LEN + CHECKSUM_LEN;
+/// Largest template read from a module.
+pub const TEMPLATE_LEN: usize = 512;
+/// Size of the template buffer, which also holds the header of the
+/// key-value store and the length of the template.
+pub const TEMPLATE_BUFFER_LEN: usize = TEMPLATE_LEN + 32;
+
+/// Prefix of the keys of the templates, followed by the slot in hexadecimal.
+pub const KEY_PREFIX: &[u8] = b"fingerprint.";
+/// Size of the key buffer.
+pub const KEY_LEN: usize = KEY_PREFIX.len() + 4;
+
+pub const POLL_INTERVAL_MS: u32 = 100;
+pub const FINGER_TIMEOUT_MS: u32 = 10_000;
+/// Longest time the module takes to answer a command.
+const RESPONSE_TIMEOUT_MS: u32 = 1000;
+
+const START_CODE: [u8; 2] = [0xEF, 0x01];
+/// Start code, address, packet identifier and length.
+const HEADER_LEN: usize = 9;
+const CHECKSUM_LEN: usize = 2;
+/// Length of the template, before the template in the template buffer.
+const TEMPLATE_LENGTH_LEN: usize = 2;
+
+/// Packet identifiers.
+mod pid {
+    pub const COMMAND: u8 = 0x01;
+    pub const DATA: u8 = 0x02;
+    pub const ACK: u8 = 0x07;
+    pub const END: u8 = 0x08;
+}
+
+/// Command instruction codes.
+mod instruction {
+    pub const GEN_IMG: u8 = 0x01;
+    pub const IMG_2_TZ: u8 = 0x02;
+    pub const SEARCH: u8 = 0x04;
+    pub const REG_MODEL: u8 = 0x05;
+    pub const STORE: u8 = 0x06;
+    pub const UP_CHAR: u8 = 0x08;
+    pub const DOWN_CHAR: u8 = 0x09;
+    pub const DELETE_CHAR: u8 = 0x0C;
+}
+
+/// Confirmation codes of the acknowledge packets.
+mod confirmation {
+    pub const OK: u8 = 0x00;
+    pub const NO_FINGER: u8 = 0x02;
+}
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// Enrollment progress.
+    pub const ENROLL: usize = 0;
+    /// Match result.
+    pub const MATCH: usize = 1;
+    /// Delete or restore completion.
+    pub const DONE: usize = 2;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 3;
+}
+
+/// Enrollment stages reported to the process.
+mod stage {
+    pub const LIFT_FINGER: usize = 1;
+    pub const PLACE_AGAIN: usize = 2;
+    pub const ENROLLED: usize = 3;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operation {
+    Idle,
+    Enroll(u16),
+    Match,
+    Delete(u16),
+    Restore(u16),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Step {
+    /// Taking an image of the finger for character buffer `n`.
+    Capture(u8),
+    /// Extracting the features of the image into character buffer `n`.
+    Convert(u8),
+    /// Waiting for the finger to be lifted between the two captures.
+    WaitRemoval,
+    CreateModel,
+    StoreModel,
+    /// Reading the template back from the module.
+    UploadModel,
+    SaveTemplate,
+    Search,
+    DeleteModel,
+    DeleteTemplate,
+    LoadTemplate,
+    /// Announcing the template written to the module.
+    DownloadModel,
+    SendTemplate,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Timer {
+    None,
+    /// Polling for a finger.
+    Poll,
+    /// Waiting for an answer.
+    Response,
+}
+
+/// Write a packet with `identifier` and `payload` in `buffer`, and return
+/// its length.
+fn encode_packet(buffer: &mut [u8], address: u32, identifier: u8, payload: &[u8]) -> usize {
+    let length = (payload.len() + CHECKSUM_LEN) as u16;
+    buffer[0..2].copy_from_slice(&START_CODE);
+    buffer[2..6].copy_from_slice(&address.to_be_bytes());
+    buffer[6] = identifier;
+    buffer[7..9].copy_from_slice(&length.to_be_bytes());
+    buffer[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+    let end = HEADER_LEN + payload.len();
+    let sum = checksum(&buffer[6..end]);
+    buffer[end..end + CHECKSUM_LEN].copy_from_slice(&sum.to_be_bytes());
+    end + CHECKSUM_LEN
+}
+
+fn checksum(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16))
+}
+
+/// Return the identifier of the packet whose header is `header`, and the
+/// length of the payload and checksum that follow.
+fn decode_header(header: &[u8]) -> Option<(u8, usize)> {
+    if header[0..2] != START_CODE {
+        return None;
+    }
+    let length = u16::from_be_bytes([header[7], header[8]]) as usize;
+    if length < CHECKSUM_LEN {
+        return None;
+    }
+    Some((header[6], length))
+}
+
+/// Whether the payload and checksum in `contents` match the packet
+/// `identifier`.
+fn check_contents(identifier: u8, contents: &[u8]) -> bool {
+    let length = (contents.len() as u16).to_be_bytes();
+    let payload_len = contents.len() - CHECKSUM_LEN;
+    let sum = checksum(&[identifier, length[0], length[1]])
+        .wrapping_add(checksum(&contents[..payload_len]));
+    contents[payload_len..] == sum.to_be_bytes()
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Fingerprint<'a, U: uart::UartData<'a>, A: Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    address: u32,
+    /// Number of template slots of the module.
+    capacity: u16,
+    kv: OptionalCell<&'a dyn kv::KVPermissions<'a>>,
+    permissions: OptionalCell<StoragePermissions>,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    owner: OptionalCell<ProcessId>,
+    operation: Cell<Operation>,
+    step: Cell<Step>,
+    timer: Cell<Timer>,
+    polls_left: Cell<u32>,
+    /// Identifier and length of the packet being received, once its header
+    /// was received.
+    receiving: OptionalCell<(u8, usize)>,
+    /// Length of the template in the template buffer.
+    template_len: Cell<usize>,
+    /// Bytes of the template already sent to the module.
+    template_sent: Cell<usize>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    template_buffer: TakeCell<'static, [u8]>,
+    key_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, U: uart::UartData<'a>, A: Alarm<'a>> Fingerprint<'a, U, A> {
+    /// `tx_buffer` and `rx_buffer` must be `BUFFER_LEN` long,
+    /// `template_buffer` `TEMPLATE_BUFFER_LEN` and `key_buffer` `KEY_LEN`.
+    pub fn new(
+        uart: &'a U,
+        alarm: &'a A,
+        address: u32,
+        capacity: u16,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        template_buffer: &'static mut [u8],
+        key_buffer: &'static mut [u8],
+    ) -> Fingerprint<'a, U, A> {
+        Fingerprint {
+            uart,
+            alarm,
+            address,
+            capacity,
+            kv: OptionalCell::empty(),
+            permissions: OptionalCell::empty(),
+            apps: grant,
+            owner: OptionalCell::empty(),
+            operation: Cell::new(Operation::Idle),
+            step: Cell::new(Step::Capture(1)),
+            timer: Cell::new(Timer::None),
+            polls_left: Cell::new(0),
+            receiving: OptionalCell::empty(),
+            template_len: Cell::new(0),
+            template_sent: Cell::new(0),
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            template_buffer: TakeCell::new(template_buffer),
+            key_buffer: TakeCell::new(key_buffer),
+        }
+    }
+
+    /// Save the enrolled templates in `kv`, with `permissions`. The client
+    /// of `kv` must be set to this driver.
+    pub fn set_template_store(
+        &self,
+        kv: &'a dyn kv::KVPermissions<'a>,
+        permissions: StoragePermissions,
+    ) {
+        self.kv.set(kv);
+        self.permissions.set(permissions);
+    }
+
+    fn start(&self, operation: Operation, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle
+            || self.tx_buffer.is_none()
+            || self.rx_buffer.is_none()
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        let res = match operation {
+            Operation::Enroll(_) | Operation::Match => self.capture(1),
+            Operation::Delete(slot) => self.send_command(
+                Step::DeleteModel,
+                &[
+                    instruction::DELETE_CHAR,
+                    (slot >> 8) as u8,
+                    slot as u8,
+                    0,
+                    1,
+                ],
+            ),
+            Operation::Restore(_) => {
+                self.operation.set(operation);
+                self.load_template()
+            }
+            Operation::Idle => Ok(()),
+        };
+        match res {
+            Ok(()) => {
+                self.operation.set(operation);
+                self.owner.set(processid);
+                Ok(())
+            }
+            Err(e) => {
+                self.operation.set(Operation::Idle);
+                Err(e)
+            }
+        }
+    }
+
+    /// Transmit a command packet and wait for its acknowledge packet.
+    fn send_command(&self, step: Step, payload: &[u8]) -> Result<(), ErrorCode> {
+        let tx_buffer = self.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let rx_buffer = match self.rx_buffer.take() {
+            Some(rx_buffer) => rx_buffer,
+            None => {
+                self.tx_buffer.replace(tx_buffer);
+                return Err(ErrorCode::BUSY);
+            }
+        };
+
+        let len = encode_packet(tx_buffer, self.address, pid::COMMAND, payload);
+        if let Err((e, tx_buffer)) = self.uart.transmit_buffer(tx_buffer, len) {
+            self.tx_buffer.replace(tx_buffer);
+            self.rx_buffer.replace(rx_buffer);
+            return Err(e);
+        }
+        self.step.set(step);
+        self.receiving.clear();
+        if let Err((e, rx_buffer)) = self.uart.receive_buffer(rx_buffer, HEADER_LEN) {
+            // The transmission completes on its own.
+            self.rx_buffer.replace(rx_buffer);
+            return Err(e);
+        }
+        self.set_timer(Timer::Response, RESPONSE_TIMEOUT_MS);
+        Ok(())
+    }
+
+    fn set_timer(&self, timer: Timer, ms: u32) {
+        self.timer.set(timer);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    fn stop_timer(&self) {
+        self.timer.set(Timer::None);
+        let _ = self.alarm.disarm();
+    }
+
+    /// Start waiting for a finger to capture in character buffer
+    /// `char_buffer`.
+    fn capture(&self, char_buffer: u8) -> Result<(), ErrorCode> {
+        self.polls_left.set(FINGER_TIMEOUT_MS / POLL_INTERVAL_MS);
+        self.send_command(Step::Capture(char_buffer), &[instruction::GEN_IMG])
+    }
+
+    /// Take another image after `POLL_INTERVAL_MS`, or give up.
+    fn poll(&self) -> Result<(), ErrorCode> {
+        let polls_left = self.polls_left.get();
+        if polls_left == 0 {
+            return Err(ErrorCode::CANCEL);
+        }
+        self.polls_left.set(polls_left - 1);
+        self.set_timer(Timer::Poll, POLL_INTERVAL_MS);
+        Ok(())
+    }
+
+    fn key(&self, slot: u16) -> Result<SubSliceMut<'static, u8>, ErrorCode> {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let key_buffer = self.key_buffer.take().ok_or(ErrorCode::BUSY)?;
+        key_buffer[..KEY_PREFIX.len()].copy_from_slice(KEY_PREFIX);
+        for (i, digit) in key_buffer[KEY_PREFIX.len()..KEY_LEN].iter_mut().enumerate() {
+            *digit = HEX[(slot >> (12 - 4 * i) & 0xF) as usize];
+        }
+        let mut key = SubSliceMut::new(key_buffer);
+        key.slice(..KEY_LEN);
+        Ok(key)
+    }
+
+    fn save_template(&self, slot: u16) -> Result<(), ErrorCode> {
+        let kv = self.kv.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let permissions = self.permissions.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let template_buffer = self.template_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let key = match self.key(slot) {
+            Ok(key) => key,
+            Err(e) => {
+                self.template_buffer.replace(template_buffer);
+                return Err(e);
+            }
+        };
+        let header_size = kv.header_size();
+        let len = self.template_len.get();
+        template_buffer[header_size..header_size + TEMPLATE_LENGTH_LEN]
+            .copy_from_slice(&(len as u16).to_le_bytes());
+        let mut value = SubSliceMut::new(template_buffer);
+        value.slice(..header_size + TEMPLATE_LENGTH_LEN + len);
+        match kv.set(key, value, permissions) {
+            Ok(()) => {
+                self.step.set(Step::SaveTemplate);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key_buffer.replace(key.take());
+                self.template_buffer.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    fn delete_template(&self, slot: u16) -> Result<(), ErrorCode> {
+        let kv = self.kv.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let permissions = self.permissions.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let key = self.key(slot)?;
+        match kv.delete(key, permissions) {
+            Ok(()) => {
+                self.step.set(Step::DeleteTemplate);
+                Ok(())
+            }
+            Err((key, e)) => {
+                self.key_buffer.replace(key.take());
+                Err(e)
+            }
+        }
+    }
+
+    fn load_template(&self) -> Result<(), ErrorCode> {
+        let slot = match self.operation.get() {
+            Operation::Restore(slot) => slot,
+            _ => return Err(ErrorCode::FAIL),
+        };
+        let kv = self.kv.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let permissions = self.permissions.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let template_buffer = self.template_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let key = match self.key(slot) {
+            Ok(key) => key,
+            Err(e) => {
+                self.template_buffer.replace(template_buffer);
+                return Err(e);
+            }
+        };
+        match kv.get(key, SubSliceMut::new(template_buffer), permissions) {
+            Ok(()) => {
+                self.step.set(Step::LoadTemplate);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key_buffer.replace(key.take());
+                self.template_buffer.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Transmit the next data packet of the template, the last one with the
+    /// end identifier.
+    fn send_template_packet(&self) -> Result<(), ErrorCode> {
+        let header_size = self.kv.map_or(0, |kv| kv.header_size());
+        let tx_buffer = self.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let sent = self.template_sent.get();
+        let chunk = core::cmp::min(DATA_PACKET_LEN, self.template_len.get() - sent);
+        let identifier = if sent + chunk == self.template_len.get() {
+            pid::END
+        } else {
+            pid::DATA
+        };
+        let mut payload = [0; DATA_PACKET_LEN];
+        self.template_buffer.map(|template| {
+            let start = header_size + TEMPLATE_LENGTH_LEN + sent;
+            payload[..chunk].copy_from_slice(&template[start..start + chunk]);
+        });
+        let len = encode_packet(tx_buffer, self.address, identifier, &payload[..chunk]);
+        match self.uart.transmit_buffer(tx_buffer, len) {
+            Ok(()) => {
+                self.step.set(Step::SendTemplate);
+                self.template_sent.set(sent + chunk);
+                Ok(())
+            }
+            Err((e, tx_buffer)) => {
+                self.tx_buffer.replace(tx_buffer);
+                Err(e)
+            }
+        }
+    }
+
+    /// Append a data packet read from the module to the template.
+    fn receive_template_packet(&self, data: &[u8]) -> Result<(), ErrorCode> {
+        let header_size = self.kv.map_or(0, |kv| kv.header_size());
+        let len = self.template_len.get();
+        if len + data.len() > TEMPLATE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        self.template_buffer
+            .map(|template| {
+                let start = header_size + TEMPLATE_LENGTH_LEN + len;
+                template[start..start + data.len()].copy_from_slice(data);
+            })
+            .ok_or(ErrorCode::BUSY)?;
+        self.template_len.set(len + data.len());
+        Ok(())
+    }
+
+    /// Go on after the module acknowledged the command of the current step.
+    fn acknowledged(&self, code: u8, parameters: &[u8; 4]) -> Result<(), (ErrorCode, u8)> {
+        let operation = self.operation.get();
+        let res = match (self.step.get(), code) {
+            (Step::Capture(_), confirmation::NO_FINGER) => self.poll(),
+            (Step::Capture(n), confirmation::OK) => {
+                self.send_command(Step::Convert(n), &[instruction::IMG_2_TZ, n])
+            }
+
+            (Step::Convert(1), confirmation::OK) => match operation {
+                Operation::Enroll(slot) => {
+                    self.notify(upcall::ENROLL, Ok((stage::LIFT_FINGER, slot as usize)));
+                    self.polls_left.set(FINGER_TIMEOUT_MS / POLL_INTERVAL_MS);
+                    self.send_command(Step::WaitRemoval, &[instruction::GEN_IMG])
+                }
+                _ => self.send_command(
+                    Step::Search,
+                    &[
+                        instruction::SEARCH,
+                        1,
+                        0,
+                        0,
+                        (self.capacity >> 8) as u8,
+                        self.capacity as u8,
+                    ],
+                ),
+            },
+            (Step::Convert(_), confirmation::OK) => {
+                self.send_command(Step::CreateModel, &[instruction::REG_MODEL])
+            }
+
+            (Step::WaitRemoval, confirmation::OK) => self.poll(),
+            (Step::WaitRemoval, confirmation::NO_FINGER) => {
+                if let Operation::Enroll(slot) = operation {
+                    self.notify(upcall::ENROLL, Ok((stage::PLACE_AGAIN, slot as usize)));
+                }
+                self.capture(2)
+            }
+
+            (Step::CreateModel, confirmation::OK) => match operation {
+                Operation::Enroll(slot) => self.send_command(
+                    Step::StoreModel,
+                    &[instruction::STORE, 1, (slot >> 8) as u8, slot as u8],
+                ),
+                _ => Err(ErrorCode::FAIL),
+            },
+
+            (Step::StoreModel, confirmation::OK) => match operation {
+                Operation::Enroll(_) if self.kv.is_some() => {
+                    self.template_len.set(0);
+                    self.send_command(Step::UploadModel, &[instruction::UP_CHAR, 1])
+                }
+                Operation::Enroll(slot) => {
+                    self.complete(Ok((stage::ENROLLED, slot as usize)));
+                    Ok(())
+                }
+                Operation::Restore(slot) => {
+                    self.complete(Ok((slot as usize, 0)));
+                    Ok(())
+                }
+                _ => Err(ErrorCode::FAIL),
+            },
+
+            // The template follows in data packets.
+            (Step::UploadModel, confirmation::OK) => self.receive_header(),
+
+            (Step::Search, confirmation::OK) => {
+                let slot = u16::from_be_bytes([parameters[0], parameters[1]]);
+                let score = u16::from_be_bytes([parameters[2], parameters[3]]);
+                self.complete(Ok((slot as usize, score as usize)));
+                Ok(())
+            }
+
+            (Step::DeleteModel, confirmation::OK) => match operation {
+                Operation::Delete(slot) => match self.delete_template(slot) {
+                    Err(ErrorCode::NOSUPPORT) => {
+                        self.complete(Ok((slot as usize, 0)));
+                        Ok(())
+                    }
+                    res => res,
+                },
+                _ => Err(ErrorCode::FAIL),
+            },
+
+            (Step::DownloadModel, confirmation::OK) => {
+                self.template_sent.set(0);
+                self.send_template_packet()
+            }
+
+            (_, code) => return Err((ErrorCode::FAIL, code)),
+        };
+        res.map_err(|e| (e, 0))
+    }
+
+    /// Receive the header of the next packet.
+    fn receive_header(&self) -> Result<(), ErrorCode> {
+        let rx_buffer = self.rx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.receiving.clear();
+        match self.uart.receive_buffer(rx_buffer, HEADER_LEN) {
+            Ok(()) => {
+                self.set_timer(Timer::Response, RESPONSE_TIMEOUT_MS);
+                Ok(())
+            }
+            Err((e, rx_buffer)) => {
+                self.rx_buffer.replace(rx_buffer);
+                Err(e)
+            }
+        }
+    }
+
+    fn notify(&self, upcall: usize, result: Result<(usize, usize), (ErrorCode, u8)>) {
+        let args = match result {
+            Ok((arg1, arg2)) => (0, arg1, arg2),
+            Err((e, code)) => (kernel::errorcode::into_statuscode(Err(e)), 0, code as usize),
+        };
+        self.owner.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, args).ok();
+            });
+        });
+    }
+
+    /// End the operation and report its result.
+    fn complete(&self, result: Result<(usize, usize), (ErrorCode, u8)>) {
+        let upcall = match self.operation.get() {
+            Operation::Enroll(_) => upcall::ENROLL,
+            Operation::Match => upcall::MATCH,
+            Operation::Delete(_) | Operation::Restore(_) => upcall::DONE,
+            Operation::Idle => return,
+        };
+        self.stop_timer();
+        self.notify(upcall, result);
+        self.operation.set(Operation::Idle);
+        self.owner.clear();
+    }
+
+    fn fail(&self, error: ErrorCode, code: u8) {
+        if self.rx_buffer.is_none() {
+            let _ = self.uart.receive_abort();
+        }
+        self.complete(Err((error, code)));
+    }
+
+    fn cancel(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if !self.owner.contains(&processid) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.stop_timer();
+        if self.rx_buffer.is_none() {
+            let _ = self.uart.receive_abort();
+        }
+        self.operation.set(Operation::Idle);
+        self.owner.clear();
+        Ok(())
+    }
+
+    fn slot(&self, data: usize) -> Result<u16, ErrorCode> {
+        match u16::try_from(data) {
+            Ok(slot) if slot < self.capacity => Ok(slot),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, A: Alarm<'a>> uart::TransmitClient for Fingerprint<'a, U, A> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        if self.operation.get() == Operation::Idle {
+            return;
+        }
+        if let Err(e) = rval {
+            return self.fail(e, 0);
+        }
+
+        if self.step.get() == Step::SendTemplate {
+            let res = if self.template_sent.get() < self.template_len.get() {
+                self.send_template_packet()
+            } else if let Operation::Restore(slot) = self.operation.get() {
+                self.send_command(
+                    Step::StoreModel,
+                    &[instruction::STORE, 1, (slot >> 8) as u8, slot as u8],
+                )
+            } else {
+                Err(ErrorCode::FAIL)
+            };
+            if let Err(e) = res {
+                self.fail(e, 0);
+            }
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, A: Alarm<'a>> uart::ReceiveClient for Fingerprint<'a, U, A> {
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        if self.operation.get() == Operation::Idle {
+            self.rx_buffer.replace(rx_buffer);
+            return;
+        }
+        if rval.is_err() {
+            self.rx_buffer.replace(rx_buffer);
+            return self.fail(ErrorCode::FAIL, 0);
+        }
+
+        let (identifier, length) = match self.receiving.take() {
+            None => {
+                // The header was received, receive the rest of the packet.
+                match decode_header(&rx_buffer[..rx_len]) {
+                    Some((identifier, length)) if length <= rx_buffer.len() => {
+                        self.receiving.set((identifier, length));
+                        if let Err((e, rx_buffer)) = self.uart.receive_buffer(rx_buffer, length) {
+                            self.rx_buffer.replace(rx_buffer);
+                            self.fail(e, 0);
+                        }
+                    }
+                    _ => {
+                        self.rx_buffer.replace(rx_buffer);
+                        self.fail(ErrorCode::FAIL, 0);
+                    }
+                }
+                return;
+            }
+            Some(packet) => packet,
+        };
+
+        self.stop_timer();
+        if !check_contents(identifier, &rx_buffer[..length]) {
+            self.rx_buffer.replace(rx_buffer);
+            return self.fail(ErrorCode::FAIL, 0);
+        }
+        let payload = &rx_buffer[..length - CHECKSUM_LEN];
+
+        match identifier {
+            pid::ACK if !payload.is_empty() => {
+                let code = payload[0];
+                let mut parameters = [0; 4];
+                let available = core::cmp::min(parameters.len(), payload.len() - 1);
+                parameters[..available].copy_from_slice(&payload[1..1 + available]);
+                self.rx_buffer.replace(rx_buffer);
+                if let Err((e, code)) = self.acknowledged(code, &parameters) {
+                    self.fail(e, code);
+                }
+            }
+            pid::DATA | pid::END if self.step.get() == Step::UploadModel => {
+                let res = self.receive_template_packet(payload);
+                self.rx_buffer.replace(rx_buffer);
+                let res = res.and_then(|()| {
+                    if identifier == pid::DATA {
+                        self.receive_header()
+                    } else if let Operation::Enroll(slot) = self.operation.get() {
+                        self.save_template(slot)
+                    } else {
+                        Err(ErrorCode::FAIL)
+                    }
+                });
+                if let Err(e) = res {
+                    self.fail(e, 0);
+                }
+            }
+            _ => {
+                self.rx_buffer.replace(rx_buffer);
+                self.fail(ErrorCode::FAIL, 0);
+            }
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, A: Alarm<'a>> AlarmClient for Fingerprint<'a, U, A> {
+    fn alarm(&self) {
+        match self.timer.replace(Timer::None) {
+            Timer::Poll => {
+                let step = self.step.get();
+                if let Err(e) = self.send_command(step, &[instruction::GEN_IMG]) {
+                    self.fail(e, 0);
+                }
+            }
+            // The module did not answer: `received_buffer` fails the
+            // operation.
+            Timer::Response => {
+                let _ = self.uart.receive_abort();
+            }
+            Timer::None => {}
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, A: Alarm<'a>> kv::KVClient for Fingerprint<'a, U, A> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        let len = match result {
+            Ok(()) => {
+                let data = value.as_slice();
+                let len = u16::from_le_bytes([data[0], data[1]]) as usize;
+                if len == 0 || len > TEMPLATE_LEN {
+                    Err(ErrorCode::FAIL)
+                } else {
+                    Ok(len)
+                }
+            }
+            Err(e) => Err(e),
+        };
+        self.template_buffer.replace(value.take());
+        if self.step.get() != Step::LoadTemplate {
+            return;
+        }
+
+        let res = len.and_then(|len| {
+            self.template_len.set(len);
+            self.send_command(Step::DownloadModel, &[instruction::DOWN_CHAR, 1])
+        });
+        if let Err(e) = res {
+            self.fail(e, 0);
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.template_buffer.replace(value.take());
+        if self.step.get() != Step::SaveTemplate {
+            return;
+        }
+        match (result, self.operation.get()) {
+            (Ok(()), Operation::Enroll(slot)) => {
+                self.complete(Ok((stage::ENROLLED, slot as usize)))
+            }
+            (Err(e), _) => self.fail(e, 0),
+            _ => {}
+        }
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.template_buffer.replace(value.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.template_buffer.replace(value.take());
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        if self.step.get() != Step::DeleteTemplate {
+            return;
+        }
+        // The template may never have been saved.
+        match (result, self.operation.get()) {
+            (Ok(()) | Err(ErrorCode::NOSUPPORT), Operation::Delete(slot)) => {
+                self.complete(Ok((slot as usize, 0)))
+            }
+            (Err(e), _) => self.fail(e, 0),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, A: Alarm<'a>> SyscallDriver for Fingerprint<'a, U, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let operation = match command_num {
+            0 => return CommandReturn::success(),
+            1 => self.slot(data).map(Operation::Enroll),
+            2 => Ok(Operation::Match),
+            3 => self.slot(data).map(Operation::Delete),
+            4 => {
+                if self.kv.is_none() {
+                    return CommandReturn::failure(ErrorCode::NOSUPPORT);
+                }
+                self.slot(data).map(Operation::Restore)
+            }
+            5 => return self.cancel(processid).into(),
+            6 => return CommandReturn::success_u32(self.capacity as u32),
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+        operation
+            .and_then(|operation| self.start(operation, processid))
+            .into()
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_packet() {
+        let mut buffer = [0; BUFFER_LEN];
+        let len = encode_packet(
+            &mut buffer,
+            DEFAULT_ADDRESS,
+            pid::COMMAND,
+            &[instruction::GEN_IMG],
+        );
+        assert_eq!(
+            buffer[..len],
+            [0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x03, 0x01, 0x00, 0x05]
+        );
+    }
+
+    #[test]
+    fn acknowledge_packet() {
+        // Search result: slot 5 with a score of 0x64.
+        let packet = [
+            0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x07, 0x00, 0x07, 0x00, 0x00, 0x05, 0x00, 0x64,
+            0x00, 0x77,
+        ];
+        let (identifier, length) = decode_header(&packet[..HEADER_LEN]).unwrap();
+        assert_eq!((identifier, length), (pid::ACK, 7));
+        assert!(check_contents(identifier, &packet[HEADER_LEN..]));
+
+        let mut corrupted = packet;
+        corrupted[12] ^= 1;
+        assert!(!check_contents(identifier, &corrupted[HEADER_LEN..]));
+        assert_eq!(decode_header(&[0; HEADER_LEN]), None);
+    }
+}
@@ -0,0 +1,857 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Power-loss safe file system on flash pages.
+//!
+//! This is a small file system in the spirit of littlefs, for application
+//! data that outgrows a raw nonvolatile region. It keeps littlefs' two
+//! guarantees, but not its on-flash format:
+//!
+//! - **Power-loss resilience.** The directory is stored in a metadata pair:
+//!   two pages written alternately, each with a revision count and a CRC.
+//!   File data is copy-on-write: a modified block is written to a free page,
+//!   and the new directory referencing it is committed last. If power is lost
+//!   at any point, the next mount finds the last committed directory, with
+//!   every file in its state before or after the operation.
+//! - **Wear leveling.** Free pages are allocated in a rotating order that
+//!   starts at a different page after every boot, so writes are spread over
+//!   the whole region.
+//!
+//! The directory is flat. A file is identified by an owner, such as the
+//! `ShortId` of an application, and a name. Files are made of up to
+//! `MAX_FILE_BLOCKS` pages.
+//!
+//! Layout
+//! ------
+//!
+//! The first two pages of the region hold the metadata pair, the others
+//! hold file data. A metadata page is:
+//!
+//! ```text
+//! +-------+----------+-------+-------------------------+-------+
+//! | magic | revision | count | count * directory entry | CRC32 |
+//! +-------+----------+-------+-------------------------+-------+
+//!     4        4        4              48 each             4
+//! ```
+//!
+//! A directory entry holds the owner, the size, the name and the page index
+//! of each block of the file, all little-endian.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let flash_fs = static_init!(
+//!     capsules_extra::flash_fs::FlashFs<'static, FlashUser<'static, Nvmc>>,
+//!     capsules_extra::flash_fs::FlashFs::new(
+//!         virtual_flash,
+//!         0xC0000, // Start address of the region
+//!         0x20000, // Size of the region
+//!         static_init!(NrfPage, NrfPage::default()),
+//!     )
+//! );
+//! virtual_flash.set_client(flash_fs);
+//! kernel::deferred_call::DeferredCallClient::register(flash_fs);
+//! flash_fs.mount();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::helpers::crc32_posix;
+use kernel::ErrorCode;
+
+/// Largest number of files.
+pub const MAX_FILES: usize = 16;
+/// Longest file name.
+pub const MAX_NAME_LEN: usize = 23;
+/// Largest number of pages in a file.
+pub const MAX_FILE_BLOCKS: usize = 8;
+
+const MAGIC: [u8; 4] = *b"TFS1";
+const HEADER_LEN: usize = 12;
+const ENTRY_LEN: usize = 48;
+const CRC_LEN: usize = 4;
+/// Pages of the metadata pair.
+const METADATA_PAGES: usize = 2;
+/// Marks an unused block slot of an entry.
+const NO_BLOCK: u16 = 0xFFFF;
+
+/// Called by the file system to move file data and report completion.
+pub trait FileSystemClient {
+    /// Copy the data of the write at `offset` into `buffer`. Returning an
+    /// error aborts the write, leaving the file unchanged.
+    fn write_data(&self, offset: usize, buffer: &mut [u8]) -> Result<(), ErrorCode>;
+
+    /// Receive the data of the read at `offset`.
+    fn read_data(&self, offset: usize, data: &[u8]);
+
+    /// A read, write or remove completed. On success, returns the number of
+    /// bytes read or written.
+    fn operation_complete(&self, result: Result<usize, ErrorCode>);
+}
+
+/// Name and size of a file.
+#[derive(Clone, Copy)]
+pub struct FileInfo {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    pub size: usize,
+}
+
+impl FileInfo {
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Entry {
+    owner: u32,
+    size: u32,
+    name_len: u8,
+    name: [u8; MAX_NAME_LEN],
+    blocks: [u16; MAX_FILE_BLOCKS],
+}
+
+impl Entry {
+    const EMPTY: Entry = Entry {
+        owner: 0,
+        size: 0,
+        name_len: 0,
+        name: [0; MAX_NAME_LEN],
+        blocks: [NO_BLOCK; MAX_FILE_BLOCKS],
+    };
+
+    fn new(owner: u32, name: &[u8]) -> Entry {
+        let mut entry = Entry::EMPTY;
+        entry.owner = owner;
+        entry.name_len = name.len() as u8;
+        entry.name[..name.len()].copy_from_slice(name);
+        entry
+    }
+
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len as usize]
+    }
+
+    fn matches(&self, owner: u32, name: &[u8]) -> bool {
+        self.owner == owner && self.name() == name
+    }
+
+    fn uses(&self, block: u16) -> bool {
+        self.blocks.contains(&block)
+    }
+
+    fn encode(&self, buffer: &mut [u8]) {
+        buffer[0..4].copy_from_slice(&self.owner.to_le_bytes());
+        buffer[4..8].copy_from_slice(&self.size.to_le_bytes());
+        buffer[8] = self.name_len;
+        buffer[9..9 + MAX_NAME_LEN].copy_from_slice(&self.name);
+        for (i, block) in self.blocks.iter().enumerate() {
+            let start = 9 + MAX_NAME_LEN + 2 * i;
+            buffer[start..start + 2].copy_from_slice(&block.to_le_bytes());
+        }
+    }
+
+    fn decode(buffer: &[u8]) -> Option<Entry> {
+        let mut entry = Entry::EMPTY;
+        entry.owner = u32::from_le_bytes(buffer.get(0..4)?.try_into().ok()?);
+        entry.size = u32::from_le_bytes(buffer.get(4..8)?.try_into().ok()?);
+        entry.name_len = *buffer.get(8)?;
+        if entry.name_len as usize > MAX_NAME_LEN {
+            return None;
+        }
+        entry.name.copy_from_slice(buffer.get(9..9 + MAX_NAME_LEN)?);
+        for (i, block) in entry.blocks.iter_mut().enumerate() {
+            let start = 9 + MAX_NAME_LEN + 2 * i;
+            *block = u16::from_le_bytes(buffer.get(start..start + 2)?.try_into().ok()?);
+        }
+        Some(entry)
+    }
+}
+
+/// The content of a metadata page.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Directory {
+    revision: u32,
+    count: usize,
+    entries: [Entry; MAX_FILES],
+}
+
+impl Directory {
+    const EMPTY: Directory = Directory {
+        revision: 0,
+        count: 0,
+        entries: [Entry::EMPTY; MAX_FILES],
+    };
+
+    /// Number of files that fit in a metadata page of `page_size` bytes.
+    fn capacity(page_size: usize) -> usize {
+        (page_size.saturating_sub(HEADER_LEN + CRC_LEN) / ENTRY_LEN).min(MAX_FILES)
+    }
+
+    fn entries(&self) -> &[Entry] {
+        &self.entries[..self.count]
+    }
+
+    fn find(&self, owner: u32, name: &[u8]) -> Option<usize> {
+        self.entries().iter().position(|e| e.matches(owner, name))
+    }
+
+    fn uses(&self, block: u16) -> bool {
+        self.entries().iter().any(|e| e.uses(block))
+    }
+
+    /// Return the directory after `change`, with the next revision.
+    fn apply(&self, change: Change) -> Directory {
+        let mut directory = *self;
+        directory.revision = self.revision.wrapping_add(1);
+        match change {
+            Change::Update(index, entry) => {
+                if index == directory.count {
+                    directory.count += 1;
+                }
+                directory.entries[index] = entry;
+            }
+            Change::Remove(index) => {
+                directory
+                    .entries
+                    .copy_within(index + 1..directory.count, index);
+                directory.count -= 1;
+                directory.entries[directory.count] = Entry::EMPTY;
+            }
+        }
+        directory
+    }
+
+    fn encode(&self, buffer: &mut [u8]) {
+        buffer.fill(0xFF);
+        buffer[0..4].copy_from_slice(&MAGIC);
+        buffer[4..8].copy_from_slice(&self.revision.to_le_bytes());
+        buffer[8..12].copy_from_slice(&(self.count as u32).to_le_bytes());
+        for (i, entry) in self.entries().iter().enumerate() {
+            let start = HEADER_LEN + i * ENTRY_LEN;
+            entry.encode(&mut buffer[start..start + ENTRY_LEN]);
+        }
+        let end = HEADER_LEN + self.count * ENTRY_LEN;
+        let crc = crc32_posix(&buffer[..end]);
+        buffer[end..end + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Decode a metadata page, or return `None` if it is erased or was not
+    /// completely written.
+    fn decode(buffer: &[u8], capacity: usize) -> Option<Directory> {
+        if buffer.get(0..4)? != MAGIC {
+            return None;
+        }
+        let revision = u32::from_le_bytes(buffer.get(4..8)?.try_into().ok()?);
+        let count = u32::from_le_bytes(buffer.get(8..12)?.try_into().ok()?) as usize;
+        if count > capacity {
+            return None;
+        }
+        let end = HEADER_LEN + count * ENTRY_LEN;
+        let crc = u32::from_le_bytes(buffer.get(end..end + CRC_LEN)?.try_into().ok()?);
+        if crc != crc32_posix(&buffer[..end]) {
+            return None;
+        }
+
+        let mut directory = Directory {
+            revision,
+            count,
+            ..Directory::EMPTY
+        };
+        for (i, entry) in directory.entries[..count].iter_mut().enumerate() {
+            let start = HEADER_LEN + i * ENTRY_LEN;
+            *entry = Entry::decode(&buffer[start..start + ENTRY_LEN])?;
+        }
+        Some(directory)
+    }
+
+    /// Whether this directory was committed after `other`.
+    fn newer_than(&self, other: &Directory) -> bool {
+        (self.revision.wrapping_sub(other.revision) as i32) > 0
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Change {
+    /// Replace the entry at the index, or add it if the index is the count.
+    Update(usize, Entry),
+    Remove(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Unmounted,
+    /// Reading one of the metadata pages.
+    Mounting(usize),
+    Idle,
+    Reading,
+    /// Reading the old content of a block that is partially overwritten.
+    WriteLoad,
+    WriteErase,
+    WriteProgram,
+    CommitErase,
+    CommitProgram,
+}
+
+pub struct FlashFs<'a, F: hil::flash::Flash + 'static> {
+    driver: &'a F,
+    /// First page of the region.
+    start_page: usize,
+    page_count: usize,
+    page_size: usize,
+    buffer: TakeCell<'static, F::Page>,
+    client: OptionalCell<&'a dyn FileSystemClient>,
+    deferred_call: DeferredCall,
+    state: Cell<State>,
+
+    /// The last committed directory.
+    directory: MapCell<Directory>,
+    /// The metadata page holding `directory`.
+    active_page: Cell<usize>,
+    /// A valid directory read while mounting.
+    candidate: MapCell<Directory>,
+    /// Next data block to try to allocate.
+    next_block: Cell<usize>,
+
+    /// The entry as modified by the operation in progress, and its index.
+    pending: Cell<Entry>,
+    pending_index: Cell<usize>,
+    offset: Cell<usize>,
+    length: Cell<usize>,
+    /// Bytes of the operation already processed.
+    progress: Cell<usize>,
+    /// Data block being written.
+    block: Cell<u16>,
+    /// Result of an operation completed without flash access.
+    result: Cell<Result<usize, ErrorCode>>,
+}
+
+impl<'a, F: hil::flash::Flash> FlashFs<'a, F> {
+    /// The file system uses the `length` bytes of flash starting at
+    /// `address`, which must be aligned on pages.
+    pub fn new(
+        driver: &'a F,
+        address: usize,
+        length: usize,
+        buffer: &'static mut F::Page,
+    ) -> FlashFs<'a, F> {
+        let page_size = buffer.as_mut().len();
+        FlashFs {
+            driver,
+            start_page: address / page_size,
+            page_count: (length / page_size).min(METADATA_PAGES + NO_BLOCK as usize),
+            page_size,
+            buffer: TakeCell::new(buffer),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            state: Cell::new(State::Unmounted),
+            directory: MapCell::new(Directory::EMPTY),
+            active_page: Cell::new(METADATA_PAGES - 1),
+            candidate: MapCell::empty(),
+            next_block: Cell::new(0),
+            pending: Cell::new(Entry::EMPTY),
+            pending_index: Cell::new(0),
+            offset: Cell::new(0),
+            length: Cell::new(0),
+            progress: Cell::new(0),
+            block: Cell::new(0),
+            result: Cell::new(Ok(0)),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn FileSystemClient) {
+        self.client.set(client);
+    }
+
+    fn data_blocks(&self) -> usize {
+        self.page_count.saturating_sub(METADATA_PAGES)
+    }
+
+    fn max_file_size(&self) -> usize {
+        MAX_FILE_BLOCKS * self.page_size
+    }
+
+    fn data_page(&self, block: u16) -> usize {
+        self.start_page + METADATA_PAGES + block as usize
+    }
+
+    /// Read the metadata pair to find the last committed directory.
+    pub fn mount(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Unmounted {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.data_blocks() == 0 || Directory::capacity(self.page_size) == 0 {
+            return Err(ErrorCode::SIZE);
+        }
+        self.read_metadata(0)
+    }
+
+    fn read_metadata(&self, index: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        match self.driver.read_page(self.start_page + index, buffer) {
+            Ok(()) => {
+                self.state.set(State::Mounting(index));
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.buffer.replace(buffer);
+                self.state.set(State::Unmounted);
+                Err(e)
+            }
+        }
+    }
+
+    fn mounted(&self) {
+        let (directory, page) = match self.candidate.take() {
+            Some(directory) => (directory, self.active_page.get()),
+            // A blank region is an empty file system. The first commit goes
+            // to page 0.
+            None => (Directory::EMPTY, METADATA_PAGES - 1),
+        };
+        self.active_page.set(page);
+        // Start allocating at a different block after every boot.
+        self.next_block
+            .set(directory.revision as usize % self.data_blocks());
+        self.directory.replace(directory);
+        self.state.set(State::Idle);
+    }
+
+    fn check_idle(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle => Ok(()),
+            State::Unmounted => Err(ErrorCode::OFF),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn find(&self, owner: u32, name: &[u8]) -> Option<(usize, Entry)> {
+        self.directory.map_or(None, |directory| {
+            directory
+                .find(owner, name)
+                .map(|index| (index, directory.entries[index]))
+        })
+    }
+
+    /// Return the size of a file.
+    pub fn size(&self, owner: u32, name: &[u8]) -> Result<usize, ErrorCode> {
+        self.find(owner, name)
+            .map(|(_, entry)| entry.size as usize)
+            .ok_or(ErrorCode::NOSUPPORT)
+    }
+
+    /// Return the `index`-th file of `owner`.
+    pub fn file(&self, owner: u32, index: usize) -> Option<FileInfo> {
+        self.directory.map_or(None, |directory| {
+            directory
+                .entries()
+                .iter()
+                .filter(|entry| entry.owner == owner)
+                .nth(index)
+                .map(|entry| FileInfo {
+                    name: entry.name,
+                    name_len: entry.name_len as usize,
+                    size: entry.size as usize,
+                })
+        })
+    }
+
+    /// Return the number of bytes available for new data.
+    pub fn free_space(&self) -> usize {
+        let used = (0..self.data_blocks() as u16)
+            .filter(|&block| self.directory.map_or(false, |d| d.uses(block)))
+            .count();
+        (self.data_blocks() - used) * self.page_size
+    }
+
+    /// Read up to `length` bytes of a file from `offset`. The data is passed
+    /// to `read_data()` of the client one page at a time.
+    pub fn read(
+        &self,
+        owner: u32,
+        name: &[u8],
+        offset: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        let (index, entry) = self.find(owner, name).ok_or(ErrorCode::NOSUPPORT)?;
+        let size = entry.size as usize;
+        if offset > size {
+            return Err(ErrorCode::INVAL);
+        }
+
+        self.pending.set(entry);
+        self.pending_index.set(index);
+        self.offset.set(offset);
+        self.length.set(length.min(size - offset));
+        self.progress.set(0);
+        if self.length.get() == 0 {
+            self.complete_later(Ok(0));
+            return Ok(());
+        }
+        self.state.set(State::Reading);
+        self.read_step().map_err(|e| {
+            self.state.set(State::Idle);
+            e
+        })
+    }
+
+    /// Write `length` bytes to a file at `offset`, creating it if needed.
+    /// The data is requested from `write_data()` of the client one page at a
+    /// time. If `truncate` is set, the file ends after the written data.
+    ///
+    /// The file is updated atomically: after a power loss, it either has its
+    /// old content or the new one.
+    pub fn write(
+        &self,
+        owner: u32,
+        name: &[u8],
+        offset: usize,
+        length: usize,
+        truncate: bool,
+    ) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(ErrorCode::INVAL);
+        }
+        let (index, entry) = match self.find(owner, name) {
+            Some(found) => found,
+            None => {
+                let count = self.directory.map_or(0, |d| d.count);
+                if count >= Directory::capacity(self.page_size) {
+                    return Err(ErrorCode::NOMEM);
+                }
+                (count, Entry::new(owner, name))
+            }
+        };
+        let size = entry.size as usize;
+        if offset > size {
+            return Err(ErrorCode::INVAL);
+        }
+        let end = offset.checked_add(length).ok_or(ErrorCode::SIZE)?;
+        let new_size = if truncate { end } else { end.max(size) };
+        if new_size > self.max_file_size() {
+            return Err(ErrorCode::SIZE);
+        }
+
+        let mut pending = entry;
+        pending.size = new_size as u32;
+        // Blocks past the end are released by the commit.
+        let blocks = new_size.div_ceil(self.page_size);
+        pending.blocks[blocks..].fill(NO_BLOCK);
+        self.pending.set(pending);
+        self.pending_index.set(index);
+        self.offset.set(offset);
+        self.length.set(length);
+        self.progress.set(0);
+
+        if length == 0 {
+            self.commit(Change::Update(index, pending))
+        } else {
+            self.write_step()
+        }
+    }
+
+    /// Remove a file.
+    pub fn remove(&self, owner: u32, name: &[u8]) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        let (index, _) = self.find(owner, name).ok_or(ErrorCode::NOSUPPORT)?;
+        self.length.set(0);
+        self.commit(Change::Remove(index))
+    }
+
+    /// Position of the next chunk of the operation: block index in the file,
+    /// offset in the block, and length.
+    fn chunk(&self) -> (usize, usize, usize) {
+        let position = self.offset.get() + self.progress.get();
+        let within = position % self.page_size;
+        let len = (self.page_size - within).min(self.length.get() - self.progress.get());
+        (position / self.page_size, within, len)
+    }
+
+    fn read_step(&self) -> Result<(), ErrorCode> {
+        let (index, _, _) = self.chunk();
+        let block = self.pending.get().blocks[index];
+        if block == NO_BLOCK {
+            return Err(ErrorCode::FAIL);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.driver
+            .read_page(self.data_page(block), buffer)
+            .map_err(|(e, buffer)| {
+                self.buffer.replace(buffer);
+                e
+            })
+    }
+
+    /// Start writing the next block, loading its old content first if it is
+    /// only partially overwritten.
+    fn write_step(&self) -> Result<(), ErrorCode> {
+        let (index, within, len) = self.chunk();
+        let pending = self.pending.get();
+        let old_block = pending.blocks[index];
+        let block_end = (pending.size as usize - index * self.page_size).min(self.page_size);
+        if old_block != NO_BLOCK && (within > 0 || within + len < block_end) {
+            let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+            match self.driver.read_page(self.data_page(old_block), buffer) {
+                Ok(()) => {
+                    self.state.set(State::WriteLoad);
+                    Ok(())
+                }
+                Err((e, buffer)) => {
+                    self.buffer.replace(buffer);
+                    Err(e)
+                }
+            }
+        } else {
+            self.buffer.map(|buffer| buffer.as_mut().fill(0));
+            self.program_block()
+        }
+    }
+
+    /// Fill the buffer with the data of the write and write it to a free
+    /// block.
+    fn program_block(&self) -> Result<(), ErrorCode> {
+        let (_, within, len) = self.chunk();
+        let progress = self.progress.get();
+        self.buffer
+            .map(|buffer| {
+                self.client.map_or(Err(ErrorCode::FAIL), |client| {
+                    client.write_data(progress, &mut buffer.as_mut()[within..within + len])
+                })
+            })
+            .ok_or(ErrorCode::BUSY)??;
+
+        let block = self.allocate().ok_or(ErrorCode::NOMEM)?;
+        self.block.set(block);
+        self.driver.erase_page(self.data_page(block))?;
+        self.state.set(State::WriteErase);
+        Ok(())
+    }
+
+    /// Find a block used neither by the committed directory nor by the
+    /// operation in progress.
+    fn allocate(&self) -> Option<u16> {
+        let count = self.data_blocks();
+        let start = self.next_block.get();
+        let pending = self.pending.get();
+        (0..count)
+            .map(|i| ((start + i) % count) as u16)
+            .find(|&block| !pending.uses(block) && !self.directory.map_or(true, |d| d.uses(block)))
+            .inspect(|&block| self.next_block.set((block as usize + 1) % count))
+    }
+
+    /// Write the directory with `change` to the inactive metadata page.
+    fn commit(&self, change: Change) -> Result<(), ErrorCode> {
+        let directory = self
+            .directory
+            .map(|directory| directory.apply(change))
+            .ok_or(ErrorCode::FAIL)?;
+        self.buffer
+            .map(|buffer| directory.encode(buffer.as_mut()))
+            .ok_or(ErrorCode::BUSY)?;
+        self.candidate.replace(directory);
+
+        let page = self.start_page + (self.active_page.get() ^ 1);
+        self.driver.erase_page(page)?;
+        self.state.set(State::CommitErase);
+        Ok(())
+    }
+
+    fn complete(&self, result: Result<usize, ErrorCode>) {
+        self.state.set(State::Idle);
+        self.candidate.take();
+        self.client.map(|client| client.operation_complete(result));
+    }
+
+    fn complete_later(&self, result: Result<usize, ErrorCode>) {
+        self.result.set(result);
+        self.deferred_call.set();
+    }
+
+    /// Continue the operation after a flash operation, or complete it.
+    fn advance(&self, result: Result<(), ErrorCode>) {
+        if let Err(e) = result.and_then(|()| self.next()) {
+            if let State::Mounting(_) = self.state.get() {
+                self.state.set(State::Unmounted);
+            } else {
+                self.complete(Err(e));
+            }
+        }
+    }
+
+    fn next(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Mounting(index) => {
+                let capacity = Directory::capacity(self.page_size);
+                let directory = self
+                    .buffer
+                    .map(|buffer| Directory::decode(buffer.as_mut(), capacity))
+                    .flatten();
+                if let Some(directory) = directory {
+                    let newer = self
+                        .candidate
+                        .map_or(true, |candidate| directory.newer_than(candidate));
+                    if newer {
+                        self.candidate.replace(directory);
+                        self.active_page.set(index);
+                    }
+                }
+                if index + 1 < METADATA_PAGES {
+                    self.read_metadata(index + 1)
+                } else {
+                    self.mounted();
+                    Ok(())
+                }
+            }
+            State::Reading => {
+                let (_, within, len) = self.chunk();
+                let progress = self.progress.get();
+                self.buffer.map(|buffer| {
+                    self.client.map(|client| {
+                        client.read_data(progress, &buffer.as_mut()[within..within + len])
+                    })
+                });
+                self.progress.set(progress + len);
+                if self.progress.get() < self.length.get() {
+                    self.read_step()
+                } else {
+                    self.complete(Ok(self.length.get()));
+                    Ok(())
+                }
+            }
+            State::WriteLoad => self.program_block(),
+            State::WriteErase => {
+                let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+                self.driver
+                    .write_page(self.data_page(self.block.get()), buffer)
+                    .map_err(|(e, buffer)| {
+                        self.buffer.replace(buffer);
+                        e
+                    })?;
+                self.state.set(State::WriteProgram);
+                Ok(())
+            }
+            State::WriteProgram => {
+                let (index, _, len) = self.chunk();
+                let mut pending = self.pending.get();
+                pending.blocks[index] = self.block.get();
+                self.pending.set(pending);
+                self.progress.set(self.progress.get() + len);
+                if self.progress.get() < self.length.get() {
+                    self.write_step()
+                } else {
+                    self.commit(Change::Update(self.pending_index.get(), pending))
+                }
+            }
+            State::CommitErase => {
+                let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+                let page = self.start_page + (self.active_page.get() ^ 1);
+                self.driver
+                    .write_page(page, buffer)
+                    .map_err(|(e, buffer)| {
+                        self.buffer.replace(buffer);
+                        e
+                    })?;
+                self.state.set(State::CommitProgram);
+                Ok(())
+            }
+            State::CommitProgram => {
+                if let Some(directory) = self.candidate.take() {
+                    self.directory.replace(directory);
+                }
+                self.active_page.set(self.active_page.get() ^ 1);
+                self.complete(Ok(self.length.get()));
+                Ok(())
+            }
+            State::Unmounted | State::Idle => Ok(()),
+        }
+    }
+}
+
+impl<F: hil::flash::Flash> hil::flash::Client<F> for FlashFs<'_, F> {
+    fn read_complete(
+        &self,
+        read_buffer: &'static mut F::Page,
+        result: Result<(), hil::flash::Error>,
+    ) {
+        self.buffer.replace(read_buffer);
+        self.advance(result.map_err(|_| ErrorCode::FAIL));
+    }
+
+    fn write_complete(
+        &self,
+        write_buffer: &'static mut F::Page,
+        result: Result<(), hil::flash::Error>,
+    ) {
+        self.buffer.replace(write_buffer);
+        self.advance(result.map_err(|_| ErrorCode::FAIL));
+    }
+
+    fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        self.advance(result.map_err(|_| ErrorCode::FAIL));
+    }
+}
+
+impl<F: hil::flash::Flash> DeferredCallClient for FlashFs<'_, F> {
+    fn handle_deferred_call(&self) {
+        self.complete(self.result.get());
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory() -> Directory {
+        let mut entry = Entry::new(7, b"config");
+        entry.size = 1500;
+        entry.blocks[0] = 3;
+        entry.blocks[1] = 9;
+        Directory::EMPTY.apply(Change::Update(0, entry))
+    }
+
+    #[test]
+    fn directory_round_trip() {
+        let directory = directory();
+        let mut page = [0; 1024];
+        directory.encode(&mut page);
+        assert_eq!(Directory::decode(&page, 16), Some(directory));
+        assert_eq!(directory.find(7, b"config"), Some(0));
+        assert_eq!(directory.find(8, b"config"), None);
+        assert!(directory.uses(9) && !directory.uses(4));
+    }
+
+    #[test]
+    fn torn_metadata_is_rejected() {
+        let mut page = [0; 1024];
+        directory().encode(&mut page);
+        page[20] ^= 1;
+        assert_eq!(Directory::decode(&page, 16), None);
+        assert_eq!(Directory::decode(&[0xFF; 1024], 16), None);
+        assert_eq!(Directory::capacity(512), 10);
+    }
+
+    #[test]
+    fn revisions_wrap() {
+        let old = Directory {
+            revision: u32::MAX,
+            ..Directory::EMPTY
+        };
+        let new = old.apply(Change::Update(0, Entry::new(1, b"log")));
+        assert_eq!(new.revision, 0);
+        assert!(new.newer_than(&old));
+        assert!(!old.newer_than(&new));
+    }
+}
@@ -0,0 +1,315 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Syscall driver for the flash file system.
+//!
+//! Each process has its own set of files, identified by the fixed `ShortId`
+//! of the process: files survive reboots and updates of the application.
+//! Processes without a fixed `ShortId` share a common set of files.
+//!
+//! The file system performs one operation at a time. A process that starts
+//! an operation while another one is in progress gets `BUSY` and should
+//! retry.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let file_system = static_init!(
+//!     capsules_extra::flash_fs_driver::FileSystemDriver<
+//!         'static,
+//!         capsules_extra::flash_fs::FlashFs<'static, FlashUser<'static, Nvmc>>,
+//!     >,
+//!     capsules_extra::flash_fs_driver::FileSystemDriver::new(
+//!         flash_fs,
+//!         board_kernel.create_grant(capsules_extra::flash_fs_driver::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! flash_fs.set_client(file_system);
+//! ```
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use crate::flash_fs::{FileSystemClient, FlashFs, MAX_NAME_LEN};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FileSystem as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A read, write or remove completed.
+    pub const DONE: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Name of the file.
+    pub const NAME: usize = 0;
+    /// Data to write.
+    pub const DATA: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Receives the data read, or the name of a listed file.
+    pub const DATA: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct FileSystemDriver<'a, F: hil::flash::Flash + 'static> {
+    file_system: &'a FlashFs<'a, F>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Process whose operation is in progress.
+    current_app: OptionalCell<ProcessId>,
+}
+
+impl<'a, F: hil::flash::Flash> FileSystemDriver<'a, F> {
+    pub fn new(
+        file_system: &'a FlashFs<'a, F>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> FileSystemDriver<'a, F> {
+        FileSystemDriver {
+            file_system,
+            apps: grant,
+            current_app: OptionalCell::empty(),
+        }
+    }
+
+    /// Owner of the files of `processid`.
+    fn owner(processid: ProcessId) -> u32 {
+        match processid.short_app_id() {
+            ShortId::Fixed(id) => id.get(),
+            ShortId::LocallyUnique => 0,
+        }
+    }
+
+    /// Run `f` with the file name allowed by `processid`, and the length of
+    /// its read-only or read-write data buffer.
+    fn with_name<R>(
+        &self,
+        processid: ProcessId,
+        f: impl FnOnce(&[u8], usize, usize) -> Result<R, ErrorCode>,
+    ) -> Result<R, ErrorCode> {
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                let mut name = [0; MAX_NAME_LEN];
+                let length = kernel_data
+                    .get_readonly_processbuffer(ro_allow::NAME)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            if buffer.len() > MAX_NAME_LEN {
+                                return Err(ErrorCode::INVAL);
+                            }
+                            buffer.copy_to_slice(&mut name[..buffer.len()]);
+                            Ok(buffer.len())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))?;
+                let ro_len = kernel_data
+                    .get_readonly_processbuffer(ro_allow::DATA)
+                    .map_or(0, |buffer| buffer.len());
+                let rw_len = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::DATA)
+                    .map_or(0, |buffer| buffer.len());
+                f(&name[..length], ro_len, rw_len)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Start an operation of `processid`.
+    fn start(
+        &self,
+        processid: ProcessId,
+        operation: impl FnOnce(u32, &[u8], usize, usize) -> Result<(), ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        if self.current_app.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let owner = Self::owner(processid);
+        self.with_name(processid, |name, ro_len, rw_len| {
+            operation(owner, name, ro_len, rw_len)
+        })?;
+        self.current_app.set(processid);
+        Ok(())
+    }
+
+    /// Copy the name of the `index`-th file of `processid` into its
+    /// read-write buffer, and return its length and the size of the file.
+    fn list(&self, processid: ProcessId, index: usize) -> Result<(u32, u32), ErrorCode> {
+        let file = self
+            .file_system
+            .file(Self::owner(processid), index)
+            .ok_or(ErrorCode::FAIL)?;
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::DATA)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| {
+                            let name = file.name();
+                            if buffer.len() < name.len() {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            buffer[..name.len()].copy_from_slice(name);
+                            Ok((name.len() as u32, file.size as u32))
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<F: hil::flash::Flash> FileSystemClient for FileSystemDriver<'_, F> {
+    fn write_data(&self, offset: usize, buffer: &mut [u8]) -> Result<(), ErrorCode> {
+        self.current_app.map_or(Err(ErrorCode::FAIL), |processid| {
+            self.apps
+                .enter(processid, |_app, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::DATA)
+                        .and_then(|data| {
+                            data.enter(|data| {
+                                // The process may have changed its buffer.
+                                let data = data
+                                    .get(offset..offset + buffer.len())
+                                    .ok_or(ErrorCode::SIZE)?;
+                                data.copy_to_slice(buffer);
+                                Ok(())
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+        })
+    }
+
+    fn read_data(&self, offset: usize, data: &[u8]) {
+        self.current_app.map(|processid| {
+            let _ = self.apps.enter(processid, |_app, kernel_data| {
+                let _ = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::DATA)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| {
+                            if let Some(buffer) = buffer.get(offset..offset + data.len()) {
+                                buffer.copy_from_slice(data);
+                            }
+                        })
+                    });
+            });
+        });
+    }
+
+    fn operation_complete(&self, result: Result<usize, ErrorCode>) {
+        if let Some(processid) = self.current_app.take() {
+            let _ = self.apps.enter(processid, |_app, upcalls| {
+                let (status, length) = match result {
+                    Ok(length) => (0, length),
+                    Err(e) => (kernel::errorcode::into_statuscode(Err(e)), 0),
+                };
+                upcalls
+                    .schedule_upcall(upcall::DONE, (status, length, 0))
+                    .ok();
+            });
+        }
+    }
+}
+
+impl<F: hil::flash::Flash> SyscallDriver for FileSystemDriver<'_, F> {
+    /// File system access.
+    ///
+    /// The name of the file is in read-only allow buffer `0`.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Read the file from offset `data1` into read-write allow buffer
+    ///   `0`. Upcall `0` returns the number of bytes read.
+    /// - `2`: Write read-only allow buffer `1` to the file at offset `data1`,
+    ///   creating the file if needed. If `data2` is not 0, the file ends
+    ///   after the written data. Upcall `0` returns the number of bytes
+    ///   written.
+    /// - `3`: Remove the file. Completion is signaled with upcall `0`.
+    /// - `4`: Return the size of the file.
+    /// - `5`: Copy the name of the `data1`-th file of the process into
+    ///   read-write allow buffer `0`, and return the length of the name and
+    ///   the size of the file.
+    /// - `6`: Return the number of free bytes.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self
+                .start(processid, |owner, name, _, rw_len| {
+                    self.file_system.read(owner, name, data1, rw_len)
+                })
+                .into(),
+
+            2 => self
+                .start(processid, |owner, name, ro_len, _| {
+                    self.file_system
+                        .write(owner, name, data1, ro_len, data2 != 0)
+                })
+                .into(),
+
+            3 => self
+                .start(processid, |owner, name, _, _| {
+                    self.file_system.remove(owner, name)
+                })
+                .into(),
+
+            4 => match self.with_name(processid, |name, _, _| {
+                self.file_system.size(Self::owner(processid), name)
+            }) {
+                Ok(size) => CommandReturn::success_u32(size as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            5 => match self.list(processid, data1) {
+                Ok((name_len, size)) => CommandReturn::success_u32_u32(name_len, size),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            6 => CommandReturn::success_u32(self.file_system.free_space() as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,449 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! 16-pin I2C GPIO expanders (MCP23017, PCA9555) as regular GPIO pins.
+//!
+//! - <https://www.microchip.com/en-us/product/MCP23017>
+//! - <https://www.nxp.com/part/PCA9555PW>
+//!
+//! Unlike the `mcp230xx` capsule, which exposes the expander through the
+//! asynchronous `gpio_async` interface, this capsule implements the regular
+//! `hil::gpio` traits for each pin of the expander. Expander pins can
+//! therefore be used by the `gpio` and `button` capsules, or any other
+//! capsule using GPIO pins, and appear to userspace like any other pin.
+//!
+//! The capsule keeps a copy of the expander registers in the kernel:
+//!
+//! - Configuration and output changes update the copy immediately and are
+//!   written to the expander over I2C in the background, in order.
+//! - Reading an input returns the level read from the expander at the last
+//!   change. The INT output of the expander, connected to an interrupt pin of
+//!   the microcontroller, signals every change of the inputs: the capsule
+//!   then reads the inputs and notifies the clients of the pins whose level
+//!   changed on the edges they selected.
+//!
+//! The INT output of both chips is configured as an active-low open-drain
+//! output, so the microcontroller pin is configured with a pull-up.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let expander = static_init!(
+//!     capsules_extra::gpio_expander::GpioExpander<'static, I2CDevice<'static, nrf52840::i2c::TWI>>,
+//!     capsules_extra::gpio_expander::GpioExpander::new(
+//!         expander_i2c,
+//!         capsules_extra::gpio_expander::Model::Mcp23017,
+//!         &nrf52840_peripherals.gpio_port[Pin::P1_08],
+//!         static_init!([u8; 3], [0; 3]),
+//!     )
+//! );
+//! expander_i2c.set_client(expander);
+//! nrf52840_peripherals.gpio_port[Pin::P1_08].set_client(expander);
+//! let expander_pins = static_init!(
+//!     [capsules_extra::gpio_expander::GpioExpanderPin<'static, I2CDevice<'static, nrf52840::i2c::TWI>>; 16],
+//!     core::array::from_fn(|pin| capsules_extra::gpio_expander::GpioExpanderPin::new(expander, pin))
+//! );
+//! expander.init();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::i2c::{self, I2CDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+/// Length of the buffer used for I2C transactions.
+pub const BUFFER_LENGTH: usize = 3;
+
+/// Number of pins of the expander.
+pub const NUM_PINS: usize = 16;
+
+/// MCP23017 IOCON value: INTA and INTB mirrored, open-drain INT outputs.
+const MCP23017_IOCON: u8 = 0x44;
+
+/// Supported expanders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    Mcp23017,
+    Pca9555,
+}
+
+/// Register pairs (port 0/A, then port 1/B) of the expander.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Register {
+    Config = 0,
+    Output = 1,
+    Direction = 2,
+    InterruptEnable = 3,
+    PullUp = 4,
+    Input = 5,
+}
+
+/// Order in which pending register changes are written. Outputs are written
+/// before directions so that new outputs start at the right level.
+const WRITE_ORDER: [Register; 5] = [
+    Register::Config,
+    Register::Output,
+    Register::Direction,
+    Register::InterruptEnable,
+    Register::PullUp,
+];
+
+impl Model {
+    /// Address of the first register of the pair, if the expander has it.
+    fn address(self, register: Register) -> Option<u8> {
+        match (self, register) {
+            (Model::Mcp23017, Register::Config) => Some(0x0A),
+            (Model::Mcp23017, Register::Output) => Some(0x14),
+            (Model::Mcp23017, Register::Direction) => Some(0x00),
+            (Model::Mcp23017, Register::InterruptEnable) => Some(0x04),
+            (Model::Mcp23017, Register::PullUp) => Some(0x0C),
+            (Model::Mcp23017, Register::Input) => Some(0x12),
+            (Model::Pca9555, Register::Output) => Some(0x02),
+            (Model::Pca9555, Register::Direction) => Some(0x06),
+            (Model::Pca9555, Register::Input) => Some(0x00),
+            (Model::Pca9555, _) => None,
+        }
+    }
+}
+
+/// Pins on which an interrupt fires when the inputs change from `old` to
+/// `new`.
+fn fired_pins(old: u16, new: u16, rising: u16, falling: u16) -> u16 {
+    let changed = old ^ new;
+    (changed & new & rising) | (changed & !new & falling)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Writing,
+    Reading,
+}
+
+pub struct GpioExpander<'a, I: I2CDevice> {
+    i2c: &'a I,
+    model: Model,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    /// Registers to write to the expander, one bit per `Register`.
+    dirty: Cell<u8>,
+    /// The inputs must be read from the expander.
+    read_pending: Cell<bool>,
+    /// Pin directions, one bit per pin, set for inputs.
+    direction: Cell<u16>,
+    output: Cell<u16>,
+    pull_up: Cell<u16>,
+    /// Input levels last read from the expander.
+    input: Cell<u16>,
+    /// Pins with interrupts on rising edges.
+    rising: Cell<u16>,
+    /// Pins with interrupts on falling edges.
+    falling: Cell<u16>,
+    clients: [OptionalCell<&'a dyn gpio::Client>; NUM_PINS],
+}
+
+impl<'a, I: I2CDevice> GpioExpander<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        model: Model,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        buffer: &'static mut [u8],
+    ) -> GpioExpander<'a, I> {
+        GpioExpander {
+            i2c,
+            model,
+            interrupt_pin,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            dirty: Cell::new(0),
+            read_pending: Cell::new(false),
+            // All pins are inputs after reset.
+            direction: Cell::new(0xFFFF),
+            output: Cell::new(0),
+            pull_up: Cell::new(0),
+            input: Cell::new(0),
+            rising: Cell::new(0),
+            falling: Cell::new(0),
+            clients: [(); NUM_PINS].map(|()| OptionalCell::empty()),
+        }
+    }
+
+    /// Configure the interrupt pin and write the initial configuration to the
+    /// expander.
+    pub fn init(&self) {
+        self.interrupt_pin.make_input();
+        self.interrupt_pin
+            .set_floating_state(gpio::FloatingState::PullUp);
+        self.interrupt_pin
+            .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+        for register in WRITE_ORDER {
+            self.mark_dirty(register);
+        }
+        self.read_pending.set(true);
+        self.next();
+    }
+
+    fn mark_dirty(&self, register: Register) {
+        self.dirty.set(self.dirty.get() | 1 << register as u8);
+        self.next();
+    }
+
+    fn value(&self, register: Register) -> u16 {
+        match register {
+            Register::Config => u16::from_le_bytes([MCP23017_IOCON, MCP23017_IOCON]),
+            Register::Output => self.output.get(),
+            Register::Direction => self.direction.get(),
+            // Keep the copy of the inputs up to date by enabling the change
+            // interrupt on every input. Clients are filtered in software.
+            Register::InterruptEnable => self.direction.get(),
+            Register::PullUp => self.pull_up.get(),
+            Register::Input => self.input.get(),
+        }
+    }
+
+    /// Start the next pending I2C transaction, if the bus is free.
+    fn next(&self) {
+        if self.state.get() != State::Idle {
+            return;
+        }
+        let write = WRITE_ORDER
+            .iter()
+            .copied()
+            .find(|&register| self.dirty.get() & 1 << register as u8 != 0);
+        if let Some(register) = write {
+            self.dirty.set(self.dirty.get() & !(1 << register as u8));
+            let Some(address) = self.model.address(register) else {
+                // The expander does not have this register.
+                return self.next();
+            };
+            let [low, high] = self.value(register).to_le_bytes();
+            self.buffer.take().map(|buffer| {
+                buffer[0] = address;
+                buffer[1] = low;
+                buffer[2] = high;
+                self.i2c.enable();
+                match self.i2c.write(buffer, 3) {
+                    Ok(()) => self.state.set(State::Writing),
+                    Err((_, buffer)) => {
+                        self.buffer.replace(buffer);
+                        self.i2c.disable();
+                    }
+                }
+            });
+        } else if self.read_pending.get() {
+            self.read_pending.set(false);
+            self.buffer.take().map(|buffer| {
+                buffer[0] = self.model.address(Register::Input).unwrap_or(0);
+                self.i2c.enable();
+                match self.i2c.write_read(buffer, 1, 2) {
+                    Ok(()) => self.state.set(State::Reading),
+                    Err((_, buffer)) => {
+                        self.buffer.replace(buffer);
+                        self.i2c.disable();
+                    }
+                }
+            });
+        }
+    }
+
+    fn bit(pin: usize) -> u16 {
+        1 << pin
+    }
+
+    fn update(cell: &Cell<u16>, pin: usize, set: bool) {
+        if set {
+            cell.set(cell.get() | Self::bit(pin));
+        } else {
+            cell.set(cell.get() & !Self::bit(pin));
+        }
+    }
+
+    fn is_input(&self, pin: usize) -> bool {
+        self.direction.get() & Self::bit(pin) != 0
+    }
+
+    fn set_direction(&self, pin: usize, input: bool) {
+        if self.is_input(pin) != input {
+            Self::update(&self.direction, pin, input);
+            self.mark_dirty(Register::Direction);
+            self.mark_dirty(Register::InterruptEnable);
+        }
+    }
+
+    fn set_output(&self, pin: usize, high: bool) {
+        Self::update(&self.output, pin, high);
+        self.mark_dirty(Register::Output);
+    }
+}
+
+impl<'a, I: I2CDevice> i2c::I2CClient for GpioExpander<'a, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        let state = self.state.replace(State::Idle);
+        let fired = if state == State::Reading && status.is_ok() {
+            let new = u16::from_le_bytes([buffer[0], buffer[1]]);
+            let old = self.input.replace(new);
+            fired_pins(old, new, self.rising.get(), self.falling.get())
+        } else {
+            0
+        };
+        self.buffer.replace(buffer);
+        self.i2c.disable();
+
+        for pin in (0..NUM_PINS).filter(|&pin| fired & Self::bit(pin) != 0) {
+            self.clients[pin].map(|client| client.fired());
+        }
+        self.next();
+    }
+}
+
+impl<'a, I: I2CDevice> gpio::Client for GpioExpander<'a, I> {
+    /// The INT output of the expander signals a change of its inputs.
+    fn fired(&self) {
+        self.read_pending.set(true);
+        self.next();
+    }
+}
+
+/// A pin of the expander.
+pub struct GpioExpanderPin<'a, I: I2CDevice> {
+    expander: &'a GpioExpander<'a, I>,
+    pin: usize,
+}
+
+impl<'a, I: I2CDevice> GpioExpanderPin<'a, I> {
+    /// Pin `pin` of `expander`: pins 0 to 7 are port A (port 0 on the
+    /// PCA9555) and pins 8 to 15 port B (port 1).
+    pub fn new(expander: &'a GpioExpander<'a, I>, pin: usize) -> GpioExpanderPin<'a, I> {
+        GpioExpanderPin {
+            expander,
+            pin: pin % NUM_PINS,
+        }
+    }
+}
+
+impl<'a, I: I2CDevice> gpio::Configure for GpioExpanderPin<'a, I> {
+    fn configuration(&self) -> gpio::Configuration {
+        if self.expander.is_input(self.pin) {
+            gpio::Configuration::Input
+        } else {
+            gpio::Configuration::Output
+        }
+    }
+
+    fn make_output(&self) -> gpio::Configuration {
+        self.expander.set_direction(self.pin, false);
+        gpio::Configuration::Output
+    }
+
+    /// Pins of the expander are always either inputs or outputs.
+    fn disable_output(&self) -> gpio::Configuration {
+        self.make_input()
+    }
+
+    fn make_input(&self) -> gpio::Configuration {
+        self.expander.set_direction(self.pin, true);
+        gpio::Configuration::Input
+    }
+
+    fn disable_input(&self) -> gpio::Configuration {
+        self.configuration()
+    }
+
+    fn deactivate_to_low_power(&self) {
+        self.make_input();
+        self.set_floating_state(gpio::FloatingState::PullNone);
+    }
+
+    /// Only the MCP23017 has pull-up resistors. Neither expander has
+    /// pull-down resistors.
+    fn set_floating_state(&self, state: gpio::FloatingState) {
+        let pull_up = matches!(state, gpio::FloatingState::PullUp);
+        GpioExpander::<I>::update(&self.expander.pull_up, self.pin, pull_up);
+        self.expander.mark_dirty(Register::PullUp);
+    }
+
+    fn floating_state(&self) -> gpio::FloatingState {
+        if self.expander.model == Model::Mcp23017
+            && self.expander.pull_up.get() & GpioExpander::<I>::bit(self.pin) != 0
+        {
+            gpio::FloatingState::PullUp
+        } else {
+            gpio::FloatingState::PullNone
+        }
+    }
+}
+
+impl<'a, I: I2CDevice> gpio::Output for GpioExpanderPin<'a, I> {
+    fn set(&self) {
+        self.expander.set_output(self.pin, true);
+    }
+
+    fn clear(&self) {
+        self.expander.set_output(self.pin, false);
+    }
+
+    fn toggle(&self) -> bool {
+        let high = self.expander.output.get() & GpioExpander::<I>::bit(self.pin) == 0;
+        self.expander.set_output(self.pin, high);
+        high
+    }
+}
+
+impl<'a, I: I2CDevice> gpio::Input for GpioExpanderPin<'a, I> {
+    /// Outputs read as the level they are set to, inputs as the level read
+    /// from the expander at their last change.
+    fn read(&self) -> bool {
+        let levels = if self.expander.is_input(self.pin) {
+            self.expander.input.get()
+        } else {
+            self.expander.output.get()
+        };
+        levels & GpioExpander::<I>::bit(self.pin) != 0
+    }
+}
+
+impl<'a, I: I2CDevice> gpio::Interrupt<'a> for GpioExpanderPin<'a, I> {
+    fn set_client(&self, client: &'a dyn gpio::Client) {
+        self.expander.clients[self.pin].set(client);
+    }
+
+    fn enable_interrupts(&self, mode: gpio::InterruptEdge) {
+        let (rising, falling) = match mode {
+            gpio::InterruptEdge::RisingEdge => (true, false),
+            gpio::InterruptEdge::FallingEdge => (false, true),
+            gpio::InterruptEdge::EitherEdge => (true, true),
+        };
+        GpioExpander::<I>::update(&self.expander.rising, self.pin, rising);
+        GpioExpander::<I>::update(&self.expander.falling, self.pin, falling);
+    }
+
+    fn disable_interrupts(&self) {
+        GpioExpander::<I>::update(&self.expander.rising, self.pin, false);
+        GpioExpander::<I>::update(&self.expander.falling, self.pin, false);
+    }
+
+    fn is_pending(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges() {
+        // Pin 0 rises, pin 1 falls, pin 2 stays high.
+        let (old, new) = (0b110, 0b101);
+        assert_eq!(fired_pins(old, new, 0xFFFF, 0), 0b001);
+        assert_eq!(fired_pins(old, new, 0, 0xFFFF), 0b010);
+        assert_eq!(fired_pins(old, new, 0xFFFF, 0xFFFF), 0b011);
+        assert_eq!(fired_pins(old, new, 0b100, 0b100), 0);
+    }
+}
@@ -0,0 +1,356 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides userspace with the heart rate measured by an optical pulse
+//! sensor.
+//!
+//! The capsule detects the heart beats in the infrared samples of a
+//! `hil::sensors::PulseOximeterDriver`, so that wearables do not have to
+//! stream the raw samples to a process.
+//!
+//! Each pulse of blood absorbs more infrared light, which makes the reflected
+//! light dip. The samples are first averaged down to `PROCESSING_RATE`, and
+//! their slowly varying DC level is removed. A beat is detected when the dip
+//! goes below half of the recent dips, at least `MIN_BEAT_INTERVAL_MS`
+//! after the previous one. The heart rate is the average of the last
+//! `AVERAGED_BEATS` beat intervals.
+//!
+//! While the DC level is below `FINGER_THRESHOLD`, there is no finger on the
+//! sensor and no beat is detected.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! Subscribe `0` is called on each heart beat, once enough beats were
+//! detected to compute a rate, with:
+//!
+//! - the heart rate, in beats per minute,
+//! - the duration of the last beat interval, in milliseconds.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Start measuring the heart rate.
+//! - `2`: Stop measuring the heart rate. The sensor is stopped when no
+//!   process measures it.
+//! - `3`: Get the last heart rate, in beats per minute, or `0` if there is
+//!   none.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let heart_rate = components::heart_rate::HeartRateComponent::new(
+//!     board_kernel,
+//!     capsules_extra::heart_rate::DRIVER_NUM,
+//!     max30102,
+//! )
+//! .finalize(components::heart_rate_component_static!(
+//!     capsules_extra::max30102::Max30102<'static, I2CDevice<'static, nrf52840::i2c::TWI>>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::sensors::{PulseOximeterClient, PulseOximeterDriver};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::HeartRate as usize;
+
+/// Rate, in Hz, of the averaged samples the beats are detected on.
+pub const PROCESSING_RATE: u32 = 50;
+/// Shortest beat interval, 200 beats per minute.
+pub const MIN_BEAT_INTERVAL_MS: u32 = 300;
+/// Longest beat interval, 30 beats per minute. Longer intervals restart the
+/// averaging.
+pub const MAX_BEAT_INTERVAL_MS: u32 = 2000;
+/// Number of beat intervals averaged.
+pub const AVERAGED_BEATS: usize = 4;
+/// Infrared DC level under which there is no finger on the sensor.
+pub const FINGER_THRESHOLD: u32 = 50_000;
+
+/// The DC level follows the samples with a time constant of `2^DC_SHIFT`
+/// processed samples.
+const DC_SHIFT: u32 = 5;
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// A heart beat was detected.
+    pub const BEAT: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Beat detection on the infrared samples of a pulse sensor.
+#[derive(Clone, Copy, Default)]
+pub struct BeatDetector {
+    /// Number of input samples averaged into a processed sample.
+    decimation: u32,
+    sum: u32,
+    summed: u32,
+    /// DC level, scaled by `2^DC_SHIFT`. Zero until the first sample.
+    dc: u32,
+    /// Depth of the recent dips, decaying over time.
+    peak: i32,
+    /// Whether the signal is below the detection threshold.
+    in_beat: bool,
+    /// Processed samples since the last beat, or `None` before the first
+    /// beat.
+    since_beat: Option<u32>,
+    intervals: [u32; AVERAGED_BEATS],
+    interval_count: usize,
+}
+
+impl BeatDetector {
+    /// Return a detector for samples taken at `sample_rate` Hz.
+    pub fn new(sample_rate: u32) -> BeatDetector {
+        BeatDetector {
+            decimation: core::cmp::max(1, sample_rate / PROCESSING_RATE),
+            ..Default::default()
+        }
+    }
+
+    /// Process an infrared sample. Returns the heart rate in beats per
+    /// minute and the last beat interval in milliseconds if the sample
+    /// completes a beat.
+    pub fn update(&mut self, infrared: u32) -> Option<(u32, u32)> {
+        self.sum += infrared;
+        self.summed += 1;
+        if self.summed < self.decimation {
+            return None;
+        }
+        let sample = self.sum / self.summed;
+        self.sum = 0;
+        self.summed = 0;
+        self.process(sample)
+    }
+
+    fn reset(&mut self) {
+        *self = BeatDetector {
+            decimation: self.decimation,
+            dc: self.dc,
+            ..Default::default()
+        };
+    }
+
+    fn process(&mut self, sample: u32) -> Option<(u32, u32)> {
+        if self.dc == 0 {
+            self.dc = sample << DC_SHIFT;
+        }
+        self.dc = self.dc - (self.dc >> DC_SHIFT) + sample;
+        let dc = self.dc >> DC_SHIFT;
+        if dc < FINGER_THRESHOLD {
+            self.reset();
+            return None;
+        }
+
+        // The dips are positive.
+        let dip = dc as i32 - sample as i32;
+        // Decay the reference dip by 1/64 per sample, about 1.3 s.
+        self.peak -= self.peak >> 6;
+        if dip > self.peak {
+            self.peak = dip;
+        }
+
+        let since_beat = self.since_beat.map(|samples| samples + 1);
+        self.since_beat = since_beat;
+        let elapsed_ms = since_beat.map(|samples| samples * 1000 / PROCESSING_RATE);
+        if elapsed_ms.map_or(false, |ms| ms > MAX_BEAT_INTERVAL_MS) {
+            self.since_beat = None;
+            self.interval_count = 0;
+        }
+
+        let threshold = self.peak / 2;
+        if self.in_beat {
+            if dip < threshold / 2 {
+                self.in_beat = false;
+            }
+            return None;
+        }
+        if self.peak == 0 || dip < threshold {
+            return None;
+        }
+        if elapsed_ms.map_or(false, |ms| ms < MIN_BEAT_INTERVAL_MS) {
+            return None;
+        }
+
+        self.in_beat = true;
+        let interval = self.since_beat.replace(0)?;
+        self.intervals.copy_within(1.., 0);
+        self.intervals[AVERAGED_BEATS - 1] = interval;
+        self.interval_count = core::cmp::min(self.interval_count + 1, AVERAGED_BEATS);
+        if self.interval_count < AVERAGED_BEATS {
+            return None;
+        }
+        let average: u32 = self.intervals.iter().sum::<u32>() / AVERAGED_BEATS as u32;
+        Some((
+            60 * PROCESSING_RATE / average,
+            interval * 1000 / PROCESSING_RATE,
+        ))
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    measuring: bool,
+}
+
+pub struct HeartRate<'a, S: PulseOximeterDriver<'a>> {
+    sensor: &'a S,
+    detector: Cell<BeatDetector>,
+    running: Cell<bool>,
+    /// Last heart rate, in beats per minute.
+    rate: Cell<u32>,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, S: PulseOximeterDriver<'a>> HeartRate<'a, S> {
+    pub fn new(
+        sensor: &'a S,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> HeartRate<'a, S> {
+        HeartRate {
+            sensor,
+            detector: Cell::new(BeatDetector::default()),
+            running: Cell::new(false),
+            rate: Cell::new(0),
+            apps: grant,
+        }
+    }
+
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Ok(());
+        }
+        self.detector
+            .set(BeatDetector::new(self.sensor.sample_rate()));
+        self.rate.set(0);
+        self.sensor.start_sampling()?;
+        self.running.set(true);
+        Ok(())
+    }
+
+    fn stop_if_unused(&self) {
+        let used = self
+            .apps
+            .iter()
+            .any(|app| app.enter(|app, _| app.measuring));
+        if self.running.get() && !used {
+            self.running.set(false);
+            let _ = self.sensor.stop_sampling();
+        }
+    }
+}
+
+impl<'a, S: PulseOximeterDriver<'a>> PulseOximeterClient for HeartRate<'a, S> {
+    fn sample(&self, _red: u32, infrared: u32) {
+        if !self.running.get() {
+            return;
+        }
+        let mut detector = self.detector.get();
+        let beat = detector.update(infrared);
+        self.detector.set(detector);
+
+        if let Some((rate, interval_ms)) = beat {
+            self.rate.set(rate);
+            self.apps.each(|_, app, kernel_data| {
+                if app.measuring {
+                    let _ = kernel_data
+                        .schedule_upcall(upcall::BEAT, (rate as usize, interval_ms as usize, 0));
+                }
+            });
+        }
+    }
+
+    fn sampling_error(&self, _error: ErrorCode) {
+        self.running.set(false);
+        self.rate.set(0);
+    }
+}
+
+impl<'a, S: PulseOximeterDriver<'a>> SyscallDriver for HeartRate<'a, S> {
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| app.measuring = true)
+                    .map_err(ErrorCode::from)
+                    .and_then(|()| self.start());
+                if let Err(error) = res {
+                    let _ = self.apps.enter(processid, |app, _| app.measuring = false);
+                    return CommandReturn::failure(error);
+                }
+                CommandReturn::success()
+            }
+
+            2 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| app.measuring = false)
+                    .map_err(ErrorCode::from);
+                self.stop_if_unused();
+                res.into()
+            }
+
+            3 => CommandReturn::success_u32(self.rate.get()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Infrared samples at 100 Hz with a pulse every `period` samples.
+    fn pulse(index: u32, period: u32) -> u32 {
+        let phase = index % period;
+        // A sharp dip of 2000 counts over 10 samples.
+        let dip = if phase < 10 { 2000 - phase * 200 } else { 0 };
+        100_000 - dip
+    }
+
+    #[test]
+    fn detects_heart_rate() {
+        let mut detector = BeatDetector::new(100);
+        // 75 beats per minute.
+        let beats: u32 = (0..1000)
+            .filter_map(|index| detector.update(pulse(index, 80)))
+            .map(|(rate, interval_ms)| {
+                assert_eq!(rate, 75);
+                assert_eq!(interval_ms, 800);
+            })
+            .count() as u32;
+        assert!(beats >= 7);
+    }
+
+    #[test]
+    fn no_finger() {
+        let mut detector = BeatDetector::new(100);
+        assert!((0..1000)
+            .filter_map(|index| detector.update(pulse(index, 80) / 10))
+            .next()
+            .is_none());
+    }
+}
@@ -76,6 +76,8 @@ use kernel::ErrorCode;
 
 const REG_AUTO_INCREMENT: u8 = 1 << 7;
 const CTRL_REG1: u8 = 0x20;
+const CTRL_REG2: u8 = 0x21;
+const CTRL_REG2_HEATER: u8 = 1 << 1;
 const STATUS_REG: u8 = 0x27;
 const HUMID0_REG: u8 = 0x28;
 const CALIB_REG_1ST: u8 = 0x30;
@@ -150,6 +152,51 @@ impl<'a, I: I2CDevice> Hts221<'a, I> {
             })
             .ok_or(ErrorCode::BUSY)
     }
+
+    /// Switch the heater on or off. Only possible between readings, when the
+    /// buffer is available.
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        let calibration_data = match self.state.get() {
+            State::Reset => None,
+            State::Idle(calibration_data, _, _) => Some(calibration_data),
+            _ => return Err(ErrorCode::BUSY),
+        };
+        self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+            buffer[0] = CTRL_REG2;
+            buffer[1] = if enabled { CTRL_REG2_HEATER } else { 0 };
+
+            self.i2c.enable();
+            match self.i2c.write(buffer, 2) {
+                Ok(()) => {
+                    self.state.set(State::Heater(calibration_data));
+                    Ok(())
+                }
+                Err((error, buffer)) => {
+                    self.buffer.replace(buffer);
+                    self.i2c.disable();
+                    Err(error.into())
+                }
+            }
+        })
+    }
+
+    /// Restore the state from before the heater was switched, and start the
+    /// readings requested meanwhile.
+    fn heater_done(
+        &self,
+        calibration_data: Option<CalibrationData>,
+        result: Result<(), ErrorCode>,
+    ) {
+        self.state.set(match calibration_data {
+            Some(calibration_data) => State::Idle(calibration_data, 0, 0),
+            None => State::Reset,
+        });
+        self.humidity_client
+            .map(|client| client.heater_done(result));
+        if self.pending_temperature.get() || self.pending_humidity.get() {
+            let _ = self.start_reading();
+        }
+    }
 }
 
 impl<'a, I: I2CDevice> TemperatureDriver<'a> for Hts221<'a, I> {
@@ -180,6 +227,10 @@ impl<'a, I: I2CDevice> HumidityDriver<'a> for Hts221<'a, I> {
             Ok(())
         }
     }
+
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        self.set_heater(enabled)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -190,10 +241,19 @@ enum State {
     CheckStatus(CalibrationData),
     Read(CalibrationData),
     Idle(CalibrationData, i32, usize),
+    /// Switching the heater, from the `Reset` or `Idle` state.
+    Heater(Option<CalibrationData>),
 }
 
 impl<'a, I: I2CDevice> I2CClient for Hts221<'a, I> {
     fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        if let State::Heater(calibration_data) = self.state.get() {
+            self.buffer.replace(buffer);
+            self.i2c.disable();
+            self.heater_done(calibration_data, status.map_err(|error| error.into()));
+            return;
+        }
+
         if let Err(i2c_err) = status {
             self.state.set(State::Idle(
                 CalibrationData {
@@ -375,7 +435,7 @@ impl<'a, I: I2CDevice> I2CClient for Hts221<'a, I> {
                     self.humidity_client.map(|client| client.callback(humidity));
                 }
             }
-            State::Reset => {} // should never happen
+            State::Reset | State::Heater(_) => {} // should never happen
         }
     }
 }
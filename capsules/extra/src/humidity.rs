@@ -9,9 +9,10 @@
 //!
 //! ### `subscribe` System Call
 //!
-//! The `subscribe` system call supports the single `subscribe_number` zero,
-//! which is used to provide a callback that will return back the result of
-//! a humidity reading.
+//! The `subscribe` system call supports two `subscribe_number`s: zero is
+//! used to provide a callback that will return back the result of a humidity
+//! reading, and one a callback called when the heater was switched or a
+//! condensation recovery sequence completed.
 //! The `subscribe`call return codes indicate the following:
 //!
 //! * `Ok(())`: the callback been successfully been configured.
@@ -27,12 +28,15 @@
 //!
 //! * `0`: check whether the driver exists
 //! * `1`: read humidity
+//! * `2`: switch the heater of the sensor off (`arg1` 0) or on (`arg1` 1)
+//! * `3`: recover from condensation, heating the sensor for `arg1` ms
 //!
 //!
 //! The possible return from the 'command' system call indicates the following:
 //!
 //! * `Ok(())`:    The operation has been successful.
-//! * `NOSUPPORT`: Invalid `cmd`.
+//! * `NOSUPPORT`: Invalid `cmd`, or the sensor has no heater.
+//! * `BUSY`:      The heater is being switched.
 //! * `NOMEM`:     Insufficient memory available.
 //! * `INVAL`:     Invalid address of the buffer or other error.
 //!
@@ -53,9 +57,11 @@
 
 use core::cell::Cell;
 
+use kernel::errorcode::into_statuscode;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -66,6 +72,8 @@ pub const DRIVER_NUM: usize = driver::NUM::Humidity as usize;
 pub enum HumidityCommand {
     Exists,
     ReadHumidity,
+    SetHeater,
+    RecoverCondensation,
 }
 
 #[derive(Default)]
@@ -75,22 +83,49 @@ pub struct App {
 
 pub struct HumiditySensor<'a, H: hil::sensors::HumidityDriver<'a>> {
     driver: &'a H,
-    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    apps: Grant<App, UpcallCount<2>, AllowRoCount<0>, AllowRwCount<0>>,
     busy: Cell<bool>,
+    /// Process which switched the heater or started a condensation recovery.
+    heater_process: OptionalCell<ProcessId>,
 }
 
 impl<'a, H: hil::sensors::HumidityDriver<'a>> HumiditySensor<'a, H> {
     pub fn new(
         driver: &'a H,
-        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+        grant: Grant<App, UpcallCount<2>, AllowRoCount<0>, AllowRwCount<0>>,
     ) -> HumiditySensor<'a, H> {
         HumiditySensor {
             driver: driver,
             apps: grant,
             busy: Cell::new(false),
+            heater_process: OptionalCell::empty(),
         }
     }
 
+    fn heater_command(
+        &self,
+        command: HumidityCommand,
+        arg1: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if self.heater_process.is_some() {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        let result = match command {
+            HumidityCommand::SetHeater => match arg1 {
+                0 => self.driver.set_heater(false),
+                1 => self.driver.set_heater(true),
+                _ => Err(ErrorCode::INVAL),
+            },
+            HumidityCommand::RecoverCondensation => self.driver.recover_condensation(arg1 as u32),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        if result.is_ok() {
+            self.heater_process.set(processid);
+        }
+        result.into()
+    }
+
     fn enqueue_command(
         &self,
         command: HumidityCommand,
@@ -134,6 +169,16 @@ impl<'a, H: hil::sensors::HumidityDriver<'a>> hil::sensors::HumidityClient
             });
         }
     }
+
+    fn heater_done(&self, result: Result<(), ErrorCode>) {
+        if let Some(processid) = self.heater_process.take() {
+            let _ = self.apps.enter(processid, |_, upcalls| {
+                upcalls
+                    .schedule_upcall(1, (into_statuscode(result), 0, 0))
+                    .ok();
+            });
+        }
+    }
 }
 
 impl<'a, H: hil::sensors::HumidityDriver<'a>> SyscallDriver for HumiditySensor<'a, H> {
@@ -151,6 +196,12 @@ impl<'a, H: hil::sensors::HumidityDriver<'a>> SyscallDriver for HumiditySensor<'
             // single humidity measurement
             1 => self.enqueue_command(HumidityCommand::ReadHumidity, arg1, processid),
 
+            // switch the heater
+            2 => self.heater_command(HumidityCommand::SetHeater, arg1, processid),
+
+            // condensation recovery sequence
+            3 => self.heater_command(HumidityCommand::RecoverCondensation, arg1, processid),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
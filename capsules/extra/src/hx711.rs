@@ -0,0 +1,188 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the Avia Semiconductor HX711 24-bit load cell ADC.
+//!
+//! <https://cdn.sparkfun.com/datasheets/Sensors/ForceFlex/hx711_english.pdf>
+//!
+//! The HX711 has no bus: it pulls `DOUT` low when a conversion is ready, and
+//! the result is shifted out MSB first on `DOUT` with 24 pulses on `PD_SCK`.
+//! One to three extra pulses select the input and the gain of the next
+//! conversion. This driver waits for the falling edge of `DOUT` and then
+//! bit-bangs the clock.
+//!
+//! Holding `PD_SCK` high for more than 60 µs powers the chip down, so the
+//! high phase of each pulse must stay short. It only lasts `delay` spins of a
+//! busy loop plus two GPIO accesses, and only interrupt handlers can run in
+//! between. `delay` must make each phase last at least 0.2 µs: a few spins
+//! are enough for CPUs up to 100 MHz.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let hx711 = static_init!(
+//!     capsules_extra::hx711::Hx711<'static>,
+//!     capsules_extra::hx711::Hx711::new(clock_pin, data_pin, 4)
+//! );
+//! data_pin.set_client(hx711);
+//! kernel::deferred_call::DeferredCallClient::register(hx711);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::gpio;
+use kernel::hil::sensors::{LoadCellClient, LoadCellDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Number of data bits in a conversion.
+const DATA_BITS: usize = 24;
+
+/// Input and gain of the next conversion, encoded as the total number of
+/// clock pulses of a reading.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gain {
+    /// Channel A, gain 128.
+    A128 = 25,
+    /// Channel B, gain 32.
+    B32 = 26,
+    /// Channel A, gain 64.
+    A64 = 27,
+}
+
+pub struct Hx711<'a> {
+    clock: &'a dyn gpio::Pin,
+    data: &'a dyn gpio::InterruptPin<'a>,
+    /// Busy loop iterations in each phase of a clock pulse.
+    delay: usize,
+    gain: Cell<Gain>,
+    reading: Cell<bool>,
+    powered: Cell<bool>,
+    deferred_call: DeferredCall,
+    client: OptionalCell<&'a dyn LoadCellClient>,
+}
+
+impl<'a> Hx711<'a> {
+    pub fn new(
+        clock: &'a dyn gpio::Pin,
+        data: &'a dyn gpio::InterruptPin<'a>,
+        delay: usize,
+    ) -> Hx711<'a> {
+        clock.make_output();
+        clock.clear();
+        data.make_input();
+        Hx711 {
+            clock,
+            data,
+            delay,
+            gain: Cell::new(Gain::A128),
+            reading: Cell::new(false),
+            powered: Cell::new(true),
+            deferred_call: DeferredCall::new(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Select the input and gain. The conversion in progress keeps the
+    /// previous setting, so it applies from the second reading on.
+    pub fn set_gain(&self, gain: Gain) {
+        self.gain.set(gain);
+    }
+
+    /// Power the chip down. Fails with `BUSY` while a reading is in progress.
+    pub fn power_down(&self) -> Result<(), ErrorCode> {
+        if self.reading.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.clock.set();
+        self.powered.set(false);
+        Ok(())
+    }
+
+    /// Power the chip up. The chip resets to channel A with gain 128, and
+    /// the first conversion is ready after the settling time (400 ms at
+    /// 10 samples per second).
+    pub fn power_up(&self) {
+        self.clock.clear();
+        self.gain.set(Gain::A128);
+        self.powered.set(true);
+    }
+
+    fn spin(&self) {
+        for _ in 0..self.delay {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Shift out the conversion result and select the next gain.
+    fn shift_in(&self) -> i32 {
+        let mut value: u32 = 0;
+        for pulse in 0..self.gain.get() as usize {
+            self.clock.set();
+            self.spin();
+            if pulse < DATA_BITS {
+                value = (value << 1) | self.data.read() as u32;
+            }
+            self.clock.clear();
+            self.spin();
+        }
+        // Sign-extend the 24-bit two's complement result.
+        ((value << 8) as i32) >> 8
+    }
+
+    fn complete(&self) {
+        let value = self.shift_in();
+        self.reading.set(false);
+        self.client.map(|client| client.callback(Ok(value)));
+    }
+}
+
+impl<'a> LoadCellDriver<'a> for Hx711<'a> {
+    fn read_load(&self) -> Result<(), ErrorCode> {
+        if !self.powered.get() {
+            return Err(ErrorCode::OFF);
+        }
+        if self.reading.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.reading.set(true);
+        if self.data.read() {
+            self.data
+                .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+        } else {
+            // The conversion is already waiting, there will be no edge.
+            self.deferred_call.set();
+        }
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn LoadCellClient) {
+        self.client.set(client);
+    }
+}
+
+impl gpio::Client for Hx711<'_> {
+    fn fired(&self) {
+        self.data.disable_interrupts();
+        if self.reading.get() {
+            self.complete();
+        }
+    }
+}
+
+impl DeferredCallClient for Hx711<'_> {
+    fn handle_deferred_call(&self) {
+        if self.reading.get() {
+            self.complete();
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -1041,7 +1041,10 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for RadioDriver<'a, M> {
                         if rbuf.len() <= RING_BUF_METADATA_SIZE
                             || (rbuf.len() - RING_BUF_METADATA_SIZE) % USER_FRAME_MAX_SIZE != 0
                         {
-                            // kernel::debug!("[15.4 Driver] Error - improperly formatted readwrite buffer provided");
+                            kernel::log_warn!(
+                                Radio,
+                                "Improperly formatted readwrite buffer provided"
+                            );
                             return false;
                         }
 
@@ -1056,7 +1059,7 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for RadioDriver<'a, M> {
 
                         // confirm user modifiable metadata is valid (i.e. within bounds of the provided buffer)
                         if read_index >= max_pending_rx || write_index >= max_pending_rx {
-                            // kernel::debug!("[15.4 driver] Invalid read or write index");
+                            kernel::log_warn!(Radio, "Invalid read or write index");
                             return false;
                         }
 
@@ -1081,7 +1084,7 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for RadioDriver<'a, M> {
                         if write_index == read_index {
                             read_index = (read_index + 1) % max_pending_rx;
                             rbuf[0].set(read_index as u8);
-                            // kernel::debug!("[15.4 driver] Provided RX buffer is full");
+                            kernel::log_trace!(Radio, "Provided RX buffer is full");
                         }
 
                         // update write index metadata (we do not modify the read index
@@ -458,12 +458,12 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                             Some(mac) => match mac {
                                 MacAddress::Long(val) => val,
                                 MacAddress::Short(_) => {
-                                    kernel::debug!("[15.4] DROPPED PACKET - error only short address provided on encrypted packet.");
+                                    kernel::log_warn!(Radio, "Dropped packet - only short address provided on encrypted packet");
                                     return None
                                 },
                             },
                             None => {
-                                kernel::debug!("[15.4] DROPPED PACKET - Malformed, no src address provided.");
+                                kernel::log_warn!(Radio, "Dropped packet - malformed, no src address provided");
                                 return None
                             },
                         };
@@ -614,8 +614,9 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                             {
                                 // No error is returned for the receive function because recv occurs implicitly
                                 // Log debug statement here so that this error does not occur silently
-                                kernel::debug!(
-                                    "[15.4 RECV FAIL] - Failed setting crypto key/nonce."
+                                kernel::log_error!(
+                                    Radio,
+                                    "Receive failed - failed setting crypto key/nonce"
                                 );
                                 self.mac.set_receive_buffer(buf);
                                 RxState::Idle
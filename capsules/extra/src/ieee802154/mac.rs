@@ -193,12 +193,12 @@ impl<'a, R: radio::Radio<'a>> radio::RxClient for AwakeMac<'a, R> {
             }
         }
         if addr_match {
-            // debug!("[AwakeMAC] Rcvd a 15.4 frame addressed to this device");
+            kernel::log_trace!(Radio, "Received a 15.4 frame addressed to this device");
             self.rx_client.map(move |c| {
                 c.receive(buf, frame_len, lqi, crc_valid, result);
             });
         } else {
-            // debug!("[AwakeMAC] Received a packet, but not addressed to us");
+            kernel::log_trace!(Radio, "Received a packet, but not addressed to us");
             self.radio.set_receive_buffer(buf);
         }
     }
@@ -0,0 +1,611 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Neural network inference offload.
+//!
+//! Processes run quantized (int8) neural network models stored in the asset
+//! store (see `asset_store`) without holding the model in their own memory.
+//! A process selects a model by its asset identifier, shares an input tensor,
+//! and receives the output tensor asynchronously.
+//!
+//! The layers are computed in the kernel by a
+//! `hil::neural_network::NeuralNetworkKernels` implementation: the portable
+//! `PortableKernels` in this file, or the CMSIS-NN backed kernels of the
+//! Cortex-M architecture crate. The inference is split in steps of a few
+//! output values, each run from a deferred call, so a large model does not
+//! block the kernel loop. One inference runs at a time, others are queued.
+//!
+//! Model Format
+//! ------------
+//!
+//! All fields are 32-bit little-endian values, and the model asset must be
+//! 4-byte aligned.
+//!
+//! ```text
+//! 0          4          8
+//! +----------+----------+---------+---------+-----
+//! | "TKNN"   | layers   | layer 0 | layer 1 | ...
+//! +----------+----------+---------+---------+-----
+//! ```
+//!
+//! Each layer is a fully connected layer, with a header followed by its
+//! `int32` bias (one value per output) and its `int8` weights (one row of
+//! `input length` weights per output), padded to 4 bytes:
+//!
+//! ```text
+//! op (0), input length, output length, input offset, output offset,
+//! multiplier, shift, activation min, activation max
+//! ```
+//!
+//! The quantization parameters follow TensorFlow Lite for Microcontrollers:
+//! see `hil::neural_network::FullyConnectedParams`. The input length of each
+//! layer must be the output length of the previous one.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `allow_readonly` System Call
+//!
+//! Buffer `0` holds the `int8` input tensor.
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! Buffer `0` receives the `int8` output tensor.
+//!
+//! ### `subscribe` System Call
+//!
+//! Subscribe `0` is called when an inference completes, with the status of
+//! the inference and the length of the output tensor.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Select the model with asset id `data`. Returns the lengths of the
+//!   input and output tensors. Returns `INVAL` if the asset does not exist,
+//!   the process may not access it or it is not a valid model, and `SIZE` if
+//!   its tensors do not fit in the kernel buffers.
+//! - `2`: Run the selected model on the input tensor. Returns `RESERVE` if no
+//!   model is selected and `BUSY` if an inference is already pending for the
+//!   process.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let inference = static_init!(
+//!     capsules_extra::inference::Inference<'static, cortexm::cmsis_nn::CmsisNn>,
+//!     capsules_extra::inference::Inference::new(
+//!         &cortexm::cmsis_nn::CmsisNn,
+//!         asset_store.assets(),
+//!         static_init!([u8; 1024], [0; 1024]),
+//!         static_init!([u8; 1024], [0; 1024]),
+//!         board_kernel.create_grant(
+//!             capsules_extra::inference::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! inference.register();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::neural_network::{FullyConnectedParams, NeuralNetworkKernels};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use crate::asset_store::AssetTable;
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Inference as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Input tensor.
+    pub const INPUT: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Output tensor.
+    pub const OUTPUT: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// Inference completed.
+    pub const DONE: usize = 0;
+    /// The number of subscribe upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Magic value at the start of a model.
+const MAGIC: &[u8; 4] = b"TKNN";
+
+/// Size of the model header (magic and layer count).
+const MODEL_HEADER_LEN: usize = 8;
+
+/// Size of the header of a layer.
+const LAYER_HEADER_LEN: usize = 36;
+
+/// Operation of a fully connected layer.
+const OP_FULLY_CONNECTED: u32 = 0;
+
+/// Number of output values computed in each step of an inference.
+const ROWS_PER_STEP: usize = 16;
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset.checked_add(4)?)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Multiply `value` by `multiplier * 2^(shift - 31)`, rounding like the
+/// `arm_nn_requantize` function of CMSIS-NN.
+fn requantize(value: i32, multiplier: i32, shift: i32) -> i32 {
+    let left_shift = shift.clamp(0, 31) as u32;
+    let right_shift = (-shift).clamp(0, 31) as u32;
+
+    // Doubling high multiply, rounding to nearest.
+    let value = value.wrapping_shl(left_shift) as i64;
+    let value = ((value * multiplier as i64 + (1 << 30)) >> 31) as i32;
+
+    // Divide by a power of two, rounding half away from zero.
+    let mask = ((1i64 << right_shift) - 1) as i32;
+    let remainder = value & mask;
+    let mut threshold = mask >> 1;
+    let mut result = value >> right_shift;
+    if result < 0 {
+        threshold += 1;
+    }
+    if remainder > threshold {
+        result += 1;
+    }
+    result
+}
+
+/// Portable implementation of the neural network kernels.
+pub struct PortableKernels;
+
+impl NeuralNetworkKernels for PortableKernels {
+    fn fully_connected_s8(
+        &self,
+        params: &FullyConnectedParams,
+        input: &[u8],
+        weights: &[u8],
+        bias: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), ErrorCode> {
+        if weights.len() != input.len() * output.len() || bias.len() != output.len() * 4 {
+            return Err(ErrorCode::SIZE);
+        }
+
+        for ((out, row), bias) in output
+            .iter_mut()
+            .zip(weights.chunks_exact(input.len().max(1)))
+            .zip(bias.chunks_exact(4))
+        {
+            let mut acc = i32::from_le_bytes([bias[0], bias[1], bias[2], bias[3]]);
+            for (&x, &w) in input.iter().zip(row) {
+                let x = x as i8 as i32 + params.input_offset;
+                acc = acc.wrapping_add(x.wrapping_mul(w as i8 as i32));
+            }
+            let value = requantize(acc, params.multiplier, params.shift)
+                .wrapping_add(params.output_offset)
+                .max(params.activation_min)
+                .min(params.activation_max);
+            *out = value as i8 as u8;
+        }
+        Ok(())
+    }
+}
+
+/// A fully connected layer of a model.
+struct Layer {
+    params: FullyConnectedParams,
+    input_len: usize,
+    output_len: usize,
+    bias: &'static [u8],
+    weights: &'static [u8],
+    /// Offset of the next layer in the model.
+    end: usize,
+}
+
+impl Layer {
+    fn parse(model: &'static [u8], offset: usize) -> Option<Layer> {
+        let word = |index: usize| read_u32(model, offset + 4 * index);
+        if word(0)? != OP_FULLY_CONNECTED {
+            return None;
+        }
+        let input_len = word(1)? as usize;
+        let output_len = word(2)? as usize;
+        let params = FullyConnectedParams {
+            input_offset: word(3)? as i32,
+            output_offset: word(4)? as i32,
+            multiplier: word(5)? as i32,
+            shift: word(6)? as i32,
+            activation_min: word(7)? as i32,
+            activation_max: word(8)? as i32,
+        };
+
+        let bias_start = offset + LAYER_HEADER_LEN;
+        let weights_start = bias_start.checked_add(output_len.checked_mul(4)?)?;
+        let weights_end = weights_start.checked_add(output_len.checked_mul(input_len)?)?;
+        Some(Layer {
+            params,
+            input_len,
+            output_len,
+            bias: model.get(bias_start..weights_start)?,
+            weights: model.get(weights_start..weights_end)?,
+            end: weights_end.checked_add(3)? & !3,
+        })
+    }
+}
+
+/// A validated model.
+#[derive(Copy, Clone)]
+struct Model {
+    data: &'static [u8],
+    layers: usize,
+    input_len: usize,
+    output_len: usize,
+    /// Length of the largest tensor of the model.
+    max_tensor_len: usize,
+}
+
+impl Model {
+    fn parse(data: &'static [u8]) -> Option<Model> {
+        if data.get(0..4)? != MAGIC || data.as_ptr() as usize % 4 != 0 {
+            return None;
+        }
+        let layers = read_u32(data, 4)? as usize;
+        if layers == 0 {
+            return None;
+        }
+
+        let first = Layer::parse(data, MODEL_HEADER_LEN)?;
+        let mut max_tensor_len = first.input_len;
+        let mut output_len = first.input_len;
+        let mut offset = MODEL_HEADER_LEN;
+        for _ in 0..layers {
+            let layer = Layer::parse(data, offset)?;
+            if layer.input_len != output_len || layer.input_len == 0 || layer.output_len == 0 {
+                return None;
+            }
+            max_tensor_len = cmp::max(max_tensor_len, layer.output_len);
+            output_len = layer.output_len;
+            offset = layer.end;
+        }
+
+        Some(Model {
+            data,
+            layers,
+            input_len: first.input_len,
+            output_len,
+            max_tensor_len,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    model: Option<Model>,
+    /// Whether the process waits for an inference.
+    pending: bool,
+}
+
+pub struct Inference<'a, K: NeuralNetworkKernels> {
+    kernels: &'a K,
+    assets: &'a AssetTable,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Input tensor of the current layer.
+    input_buffer: TakeCell<'static, [u8]>,
+    /// Output tensor of the current layer.
+    output_buffer: TakeCell<'static, [u8]>,
+    /// Length of the largest tensor the buffers can hold.
+    max_tensor_len: usize,
+    /// Process whose inference is running.
+    processid: OptionalCell<ProcessId>,
+    model: OptionalCell<Model>,
+    /// Offset of the current layer in the model.
+    layer: Cell<usize>,
+    /// Number of layers left to compute, including the current one.
+    layers_left: Cell<usize>,
+    /// Next output value of the current layer to compute.
+    row: Cell<usize>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, K: NeuralNetworkKernels> Inference<'a, K> {
+    pub fn new(
+        kernels: &'a K,
+        assets: &'a AssetTable,
+        input_buffer: &'static mut [u8],
+        output_buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Inference<'a, K> {
+        Inference {
+            kernels,
+            assets,
+            apps: grant,
+            max_tensor_len: cmp::min(input_buffer.len(), output_buffer.len()),
+            input_buffer: TakeCell::new(input_buffer),
+            output_buffer: TakeCell::new(output_buffer),
+            processid: OptionalCell::empty(),
+            model: OptionalCell::empty(),
+            layer: Cell::new(0),
+            layers_left: Cell::new(0),
+            row: Cell::new(0),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn select_model(&self, processid: ProcessId, id: u32) -> Result<Model, ErrorCode> {
+        let model = self
+            .assets
+            .get_for_process(processid, id)
+            .and_then(Model::parse)
+            .ok_or(ErrorCode::INVAL)?;
+        if model.max_tensor_len > self.max_tensor_len {
+            return Err(ErrorCode::SIZE);
+        }
+        self.apps
+            .enter(processid, |app, _| {
+                if app.pending {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.model = Some(model);
+                    Ok(model)
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Start the inference of `processid`, copying its input tensor.
+    fn start(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let model = self
+            .apps
+            .enter(processid, |app, kernel_data| {
+                let model = app.model.ok_or(ErrorCode::RESERVE)?;
+                self.input_buffer.map_or(Err(ErrorCode::BUSY), |buffer| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::INPUT)
+                        .and_then(|input| {
+                            input.enter(|input| {
+                                if input.len() < model.input_len {
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                input[..model.input_len]
+                                    .copy_to_slice(&mut buffer[..model.input_len]);
+                                Ok(())
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })?;
+                Ok::<Model, ErrorCode>(model)
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        self.processid.set(processid);
+        self.model.set(model);
+        self.layer.set(MODEL_HEADER_LEN);
+        self.layers_left.set(model.layers);
+        self.row.set(0);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    /// Start the next pending inference, if any.
+    fn start_next(&self) {
+        for app in self.apps.iter() {
+            let processid = app.processid();
+            if app.enter(|app, _| app.pending) {
+                match self.start(processid) {
+                    Ok(()) => return,
+                    Err(err) => self.report(processid, Err(err), 0),
+                }
+            }
+        }
+    }
+
+    /// Compute the next step of the current inference. Returns whether the
+    /// inference is complete.
+    fn step(&self) -> Result<bool, ErrorCode> {
+        let model = self.model.get().ok_or(ErrorCode::FAIL)?;
+        let layer = Layer::parse(model.data, self.layer.get()).ok_or(ErrorCode::FAIL)?;
+
+        let start = self.row.get();
+        let end = cmp::min(start + ROWS_PER_STEP, layer.output_len);
+        self.input_buffer.map_or(Err(ErrorCode::FAIL), |input| {
+            self.output_buffer.map_or(Err(ErrorCode::FAIL), |output| {
+                self.kernels.fully_connected_s8(
+                    &layer.params,
+                    &input[..layer.input_len],
+                    &layer.weights[start * layer.input_len..end * layer.input_len],
+                    &layer.bias[start * 4..end * 4],
+                    &mut output[start..end],
+                )
+            })
+        })?;
+
+        if end < layer.output_len {
+            self.row.set(end);
+            return Ok(false);
+        }
+
+        // The output of this layer is the input of the next one.
+        let input = self.input_buffer.take();
+        let output = self.output_buffer.take();
+        self.input_buffer.put(output);
+        self.output_buffer.put(input);
+
+        self.row.set(0);
+        self.layer.set(layer.end);
+        self.layers_left.set(self.layers_left.get() - 1);
+        Ok(self.layers_left.get() == 0)
+    }
+
+    /// Complete the current inference and start the next one.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        let output_len = self.model.take().map_or(0, |model| model.output_len);
+        if let Some(processid) = self.processid.take() {
+            let result = result.and_then(|()| {
+                self.apps
+                    .enter(processid, |_, kernel_data| {
+                        self.input_buffer.map_or(Err(ErrorCode::FAIL), |tensor| {
+                            kernel_data
+                                .get_readwrite_processbuffer(rw_allow::OUTPUT)
+                                .and_then(|output| {
+                                    output.mut_enter(|output| {
+                                        if output.len() < output_len {
+                                            return Err(ErrorCode::SIZE);
+                                        }
+                                        output[..output_len].copy_from_slice(&tensor[..output_len]);
+                                        Ok(())
+                                    })
+                                })
+                                .unwrap_or(Err(ErrorCode::RESERVE))
+                        })
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+            });
+            self.report(processid, result, output_len);
+        }
+        self.start_next();
+    }
+
+    /// Notify `processid` of the completion of its inference.
+    fn report(&self, processid: ProcessId, result: Result<(), ErrorCode>, output_len: usize) {
+        let _ = self.apps.enter(processid, |app, kernel_data| {
+            app.pending = false;
+            let _ =
+                kernel_data.schedule_upcall(upcall::DONE, (into_statuscode(result), output_len, 0));
+        });
+    }
+}
+
+impl<'a, K: NeuralNetworkKernels> DeferredCallClient for Inference<'a, K> {
+    fn handle_deferred_call(&self) {
+        match self.step() {
+            Ok(false) => self.deferred_call.set(),
+            Ok(true) => self.finish(Ok(())),
+            Err(err) => self.finish(Err(err)),
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, K: NeuralNetworkKernels> SyscallDriver for Inference<'a, K> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.select_model(processid, data as u32) {
+                Ok(model) => {
+                    CommandReturn::success_u32_u32(model.input_len as u32, model.output_len as u32)
+                }
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            2 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        if app.model.is_none() {
+                            Err(ErrorCode::RESERVE)
+                        } else if app.pending {
+                            Err(ErrorCode::BUSY)
+                        } else {
+                            app.pending = true;
+                            Ok(())
+                        }
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                if res.is_ok() && self.processid.is_none() {
+                    self.start_next();
+                }
+                res.into()
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requantize_rounding() {
+        // A multiplier of 2^30 is a scale of 0.5.
+        assert_eq!(requantize(100, 1 << 30, 0), 50);
+        assert_eq!(requantize(3, 1 << 30, 0), 2);
+        assert_eq!(requantize(100, 1 << 30, -1), 25);
+        assert_eq!(requantize(-100, 1 << 30, -2), -13);
+        assert_eq!(requantize(100, 1 << 30, 1), 100);
+    }
+
+    #[test]
+    fn fully_connected() {
+        // Scale of 1.0, ReLU-like activation above 5.
+        let params = FullyConnectedParams {
+            input_offset: 0,
+            output_offset: 0,
+            multiplier: 1 << 30,
+            shift: 1,
+            activation_min: 5,
+            activation_max: 127,
+        };
+        let input = [1, 2];
+        let weights = [1, 1, 2, (-1i8) as u8];
+        let mut bias = [0; 8];
+        bias[..4].copy_from_slice(&10i32.to_le_bytes());
+        let mut output = [0; 2];
+        assert_eq!(
+            PortableKernels.fully_connected_s8(&params, &input, &weights, &bias, &mut output),
+            Ok(())
+        );
+        assert_eq!(output, [13, 5]);
+        assert_eq!(
+            PortableKernels.fully_connected_s8(&params, &input, &weights[..3], &bias, &mut output),
+            Err(ErrorCode::SIZE)
+        );
+    }
+}
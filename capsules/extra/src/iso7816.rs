@@ -0,0 +1,816 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! ISO 7816-3 T=0 transport for smart cards, such as SIM cards and secure
+//! elements, connected to a UART in smart card mode.
+//!
+//! The UART provides the clock and the I/O line of the card, and a GPIO pin
+//! drives its reset line. Activating the card releases the reset and
+//! receives its answer to reset (ATR), from which the driver takes the extra
+//! guard time (TC1) and the work waiting time (TA1 and TC2). The card keeps
+//! the default transmission parameters (F = 372, D = 1): no PPS exchange is
+//! done.
+//!
+//! APDUs are exchanged with the T=0 protocol: the driver sends the command
+//! header, follows the procedure bytes of the card to send the command data
+//! or receive the response data, and ends with the status bytes `SW1 SW2`.
+//! It handles the T=0 specific status bytes itself: `61 XX` fetches the
+//! response with GET RESPONSE, and `6C XX` repeats the command with the
+//! length the card asked for. Case 4 APDUs (with data and `Le`) are sent as
+//! case 3 commands, as T=0 requires.
+//!
+//! Only cards using the direct convention (TS = `0x3B`) are supported. Only
+//! one operation runs at a time, for the process that started it.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! - `0`: The card was activated, with the status and the length of the ATR
+//!   copied to the read-write allow buffer `0`.
+//! - `1`: An APDU exchange completed, with the status, the length of the
+//!   response copied to the read-write allow buffer `1` (the response data
+//!   followed by `SW1 SW2`), and the status word `SW1 << 8 | SW2`.
+//!
+//! ### `allow_readonly` System Call
+//!
+//! - `0`: The command APDU.
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! - `0`: The ATR, up to `MAX_ATR_LEN` bytes.
+//! - `1`: The response APDU, up to `RESPONSE_LEN` bytes.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Activate the card: reset it and receive its ATR.
+//! - `2`: Send the APDU held in the first `data` bytes of the read-only
+//!   allow buffer, and receive the response. Returns `INVAL` if `data` is not
+//!   the length of a valid short APDU, `NOMEM` if the buffer is shorter, and
+//!   `OFF` if the card is not activated.
+//! - `3`: Deactivate the card, holding it in reset.
+//!
+//! Commands return `BUSY` while an operation is running.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let iso7816 = components::iso7816::Iso7816Component::new(
+//!     board_kernel,
+//!     capsules_extra::iso7816::DRIVER_NUM,
+//!     &base_peripherals.usart2,
+//!     card_reset_pin,
+//!     mux_alarm,
+//!     3_571_200,
+//! )
+//! .finalize(components::iso7816_component_static!(
+//!     stm32f429zi::usart::Usart<'static, stm32f429zi::dma::Dma1<'static>>,
+//!     stm32f429zi::tim2::Tim2
+//! ));
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::gpio;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::uart;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SmartCard as usize;
+
+/// Longest answer to reset.
+pub const MAX_ATR_LEN: usize = 33;
+/// Longest short command APDU: header, `Lc`, 255 data bytes and `Le`.
+pub const MAX_APDU_LEN: usize = 4 + 1 + 255 + 1;
+/// Longest response APDU: 256 data bytes and the status word.
+pub const RESPONSE_LEN: usize = MAX_RESPONSE_DATA + 2;
+const MAX_RESPONSE_DATA: usize = 256;
+
+/// Bit duration in card clock cycles, with the default F = 372 and D = 1.
+const DEFAULT_ETU_CLOCKS: u32 = 372;
+/// Default waiting integer, when the card does not give TC2.
+const DEFAULT_WAITING_INTEGER: u8 = 10;
+/// Time the reset line is held low. The card needs at least 400 clock
+/// cycles.
+const RESET_LOW_MS: u32 = 1;
+
+/// Clock rate conversion factors Fi, indexed by the high nibble of TA1.
+/// Reserved values read as the default 372.
+const FI: [u32; 16] = [
+    372, 372, 558, 744, 1116, 1488, 1860, 372, 372, 512, 768, 1024, 1536, 2048, 372, 372,
+];
+
+/// Initial character of the direct convention.
+const TS_DIRECT: u8 = 0x3B;
+
+/// Procedure byte asking the reader to wait.
+const NULL_BYTE: u8 = 0x60;
+
+/// Instruction of GET RESPONSE.
+const INS_GET_RESPONSE: u8 = 0xC0;
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// The card was activated.
+    pub const ATR: usize = 0;
+    /// An APDU exchange completed.
+    pub const APDU: usize = 1;
+    /// The number of subscribe upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const APDU: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const ATR: usize = 0;
+    pub const RESPONSE: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Transmission parameters given by the answer to reset.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct AtrParameters {
+    /// Clock rate conversion factor Fi, which scales the waiting time.
+    fi: u32,
+    /// Extra guard time N, in bit periods.
+    extra_guard_time: u8,
+    /// Waiting integer WI of T=0.
+    waiting_integer: u8,
+}
+
+/// Take the next interface byte of an ATR, if it is `present`.
+fn interface_byte(atr: &[u8], pos: &mut usize, present: bool) -> Option<u8> {
+    if !present {
+        return None;
+    }
+    *pos += 1;
+    atr.get(*pos - 1).copied()
+}
+
+/// Length of the answer to reset starting with `atr`, as far as the bytes
+/// received so far tell: its full length once they include all the
+/// interface bytes, and a lower bound otherwise.
+fn atr_len(atr: &[u8]) -> usize {
+    let Some(&t0) = atr.get(1) else {
+        return 2;
+    };
+    let historical = (t0 & 0x0F) as usize;
+    // Presence of TAi, TBi, TCi and TDi, in bits 0 to 3.
+    let mut presence = t0 >> 4;
+    let mut pos = 2;
+    // The check byte TCK is present if a protocol other than T=0 is offered.
+    let mut check = false;
+    loop {
+        pos += (presence & 0x7).count_ones() as usize;
+        if presence & 0x8 == 0 {
+            break;
+        }
+        pos += 1;
+        let Some(&td) = atr.get(pos - 1) else {
+            return pos;
+        };
+        check |= td & 0x0F != 0;
+        presence = td >> 4;
+    }
+    pos + historical + check as usize
+}
+
+/// Read the transmission parameters of a complete answer to reset.
+fn parse_atr(atr: &[u8]) -> AtrParameters {
+    let mut params = AtrParameters {
+        fi: FI[0],
+        extra_guard_time: 0,
+        waiting_integer: DEFAULT_WAITING_INTEGER,
+    };
+    let mut presence = atr.get(1).map_or(0, |t0| t0 >> 4);
+    let mut pos = 2;
+    let mut index = 1;
+    loop {
+        let ta = interface_byte(atr, &mut pos, presence & 0x1 != 0);
+        let _tb = interface_byte(atr, &mut pos, presence & 0x2 != 0);
+        let tc = interface_byte(atr, &mut pos, presence & 0x4 != 0);
+        let td = interface_byte(atr, &mut pos, presence & 0x8 != 0);
+        match index {
+            1 => {
+                if let Some(ta1) = ta {
+                    params.fi = FI[(ta1 >> 4) as usize];
+                }
+                // 255 means the minimum guard time, with no extra time.
+                if let Some(tc1) = tc.filter(|tc1| *tc1 != 0xFF) {
+                    params.extra_guard_time = tc1;
+                }
+            }
+            2 => {
+                if let Some(tc2) = tc.filter(|tc2| *tc2 != 0) {
+                    params.waiting_integer = tc2;
+                }
+            }
+            _ => {}
+        }
+        match td {
+            Some(td) => presence = td >> 4,
+            None => break,
+        }
+        index += 1;
+    }
+    params
+}
+
+/// Work waiting time, the longest delay between two characters from the
+/// card: `960 * WI * Fi` clock cycles, in milliseconds rounded up.
+fn work_waiting_time_ms(params: &AtrParameters, clock_hz: u32) -> u32 {
+    let cycles = 960 * u64::from(params.waiting_integer) * u64::from(params.fi);
+    (cycles * 1000).div_ceil(u64::from(clock_hz.max(1))) as u32
+}
+
+/// Length of the command data and expected response data of a short APDU.
+/// Case 1 and 2 commands have no data, case 3 and 4 ones carry `Lc` bytes;
+/// case 2 and 4 ones expect a response, of 256 bytes if `Le` is 0.
+fn apdu_lengths(apdu: &[u8]) -> Option<(usize, usize)> {
+    match apdu.len() {
+        4 => Some((0, 0)),
+        5 => Some((0, if apdu[4] == 0 { 256 } else { apdu[4] as usize })),
+        len => {
+            let lc = apdu[4] as usize;
+            if lc == 0 {
+                None
+            } else if len == 5 + lc {
+                Some((lc, 0))
+            } else if len == 6 + lc {
+                // T=0 sends case 4 commands as case 3 ones, the response
+                // is fetched with GET RESPONSE.
+                Some((lc, 0))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Holding the reset line low.
+    Resetting,
+    /// Receiving the answer to reset.
+    Atr,
+    /// Sending the command up to `send_end`.
+    Sending,
+    /// Waiting for a procedure byte.
+    Procedure,
+    /// Receiving response data up to `data_end`.
+    Data,
+    /// Waiting for SW2, after SW1.
+    StatusWord(u8),
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Iso7816<'a, U: uart::UartData<'a> + uart::SmartCard, A: Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    reset: &'a dyn gpio::Pin,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    owner: OptionalCell<ProcessId>,
+    state: Cell<State>,
+    /// Requested frequency of the card clock.
+    clock_hz: u32,
+    /// Frequency of the card clock, once the UART is in smart card mode.
+    actual_clock_hz: OptionalCell<u32>,
+    activated: Cell<bool>,
+    /// Longest delay between two characters from the card.
+    waiting_time_ms: Cell<u32>,
+    /// Command being sent: a header and its data.
+    command: TakeCell<'static, [u8]>,
+    /// Index of the next command byte to send.
+    sent: Cell<usize>,
+    /// End of the bytes to send before waiting for a procedure byte.
+    send_end: Cell<usize>,
+    /// End of the command data.
+    command_end: Cell<usize>,
+    /// Response data expected from the card.
+    expected: Cell<usize>,
+    /// ATR or response being received.
+    response: TakeCell<'static, [u8]>,
+    /// Number of bytes received in `response`.
+    received: Cell<usize>,
+    /// End of the response data to receive before waiting for a procedure
+    /// byte.
+    data_end: Cell<usize>,
+}
+
+impl<'a, U: uart::UartData<'a> + uart::SmartCard, A: Alarm<'a>> Iso7816<'a, U, A> {
+    /// `command` must be `MAX_APDU_LEN` long and `response` `RESPONSE_LEN`
+    /// long. The card is clocked at `clock_hz`, usually between 1 and 5 MHz.
+    pub fn new(
+        uart: &'a U,
+        alarm: &'a A,
+        reset: &'a dyn gpio::Pin,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        clock_hz: u32,
+        command: &'static mut [u8],
+        response: &'static mut [u8],
+    ) -> Iso7816<'a, U, A> {
+        reset.make_output();
+        reset.clear();
+        Iso7816 {
+            uart,
+            alarm,
+            reset,
+            apps: grant,
+            owner: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            clock_hz,
+            actual_clock_hz: OptionalCell::empty(),
+            activated: Cell::new(false),
+            waiting_time_ms: Cell::new(0),
+            command: TakeCell::new(command),
+            sent: Cell::new(0),
+            send_end: Cell::new(0),
+            command_end: Cell::new(0),
+            expected: Cell::new(0),
+            response: TakeCell::new(response),
+            received: Cell::new(0),
+            data_end: Cell::new(0),
+        }
+    }
+
+    /// Configure the UART, and hold the card in reset before receiving its
+    /// answer to reset.
+    fn activate(&self) -> Result<(), ErrorCode> {
+        let clock_hz = self.configure(0)?;
+        let params = parse_atr(&[]);
+        self.waiting_time_ms
+            .set(work_waiting_time_ms(&params, clock_hz));
+        self.activated.set(false);
+        self.reset.clear();
+        self.state.set(State::Resetting);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(RESET_LOW_MS));
+        Ok(())
+    }
+
+    fn configure(&self, extra_guard_time: u8) -> Result<u32, ErrorCode> {
+        let clock_hz = self.uart.configure_smartcard(uart::SmartCardParameters {
+            clock_hz: self.clock_hz,
+            etu_clocks: DEFAULT_ETU_CLOCKS,
+            extra_guard_time,
+        })?;
+        self.actual_clock_hz.set(clock_hz);
+        Ok(clock_hz)
+    }
+
+    /// Copy the APDU of the owner and start sending it.
+    fn exchange(&self, len: usize) -> Result<(), ErrorCode> {
+        let command = self.command.take().ok_or(ErrorCode::BUSY)?;
+        let res = self.load_apdu(command, len);
+        self.command.replace(command);
+        let (lc, le) = res?;
+        self.command_end.set(5 + lc);
+        self.expected.set(le);
+        self.received.set(0);
+        self.sent.set(0);
+        self.send(5)
+    }
+
+    /// Copy the APDU of the owner into `command`, with a header of 5 bytes.
+    fn load_apdu(&self, command: &mut [u8], len: usize) -> Result<(usize, usize), ErrorCode> {
+        self.owner.map_or(Err(ErrorCode::FAIL), |processid| {
+            self.apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::APDU)
+                        .and_then(|buffer| {
+                            buffer.enter(|apdu| {
+                                if apdu.len() < len {
+                                    return Err(ErrorCode::NOMEM);
+                                }
+                                apdu[..len].copy_to_slice(&mut command[..len]);
+                                let lengths =
+                                    apdu_lengths(&command[..len]).ok_or(ErrorCode::INVAL)?;
+                                if len == 4 {
+                                    command[4] = 0;
+                                }
+                                Ok(lengths)
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::NOMEM))
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+        })
+    }
+
+    /// Send the command bytes up to `end`.
+    fn send(&self, end: usize) -> Result<(), ErrorCode> {
+        self.send_end.set(end);
+        self.state.set(State::Sending);
+        let byte = self.command.map_or(0, |command| command[self.sent.get()]);
+        self.uart.transmit_word(byte.into())
+    }
+
+    /// Receive the next byte from the card, waiting at most the work waiting
+    /// time.
+    fn receive(&self, state: State) -> Result<(), ErrorCode> {
+        self.state.set(state);
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(self.waiting_time_ms.get()),
+        );
+        self.uart.receive_word()
+    }
+
+    /// Store a byte of the ATR, and complete the activation once the ATR is
+    /// complete.
+    fn atr_byte(&self, byte: u8) -> Result<(), ErrorCode> {
+        let received = self.received.get();
+        if received == 0 && byte != TS_DIRECT {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        if received >= MAX_ATR_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        self.response.map(|response| response[received] = byte);
+        self.received.set(received + 1);
+
+        let complete = self.response.map_or(false, |response| {
+            atr_len(&response[..=received]) == received + 1
+        });
+        if !complete {
+            return self.receive(State::Atr);
+        }
+
+        let params = self
+            .response
+            .map_or(parse_atr(&[]), |response| parse_atr(&response[..=received]));
+        let clock_hz = self.configure(params.extra_guard_time)?;
+        self.waiting_time_ms
+            .set(work_waiting_time_ms(&params, clock_hz));
+        self.activated.set(true);
+        self.copy_response(rw_allow::ATR, received + 1);
+        self.complete(Ok((received + 1, 0)));
+        Ok(())
+    }
+
+    /// Follow a procedure byte of the card.
+    fn procedure_byte(&self, byte: u8) -> Result<(), ErrorCode> {
+        let ins = self.command.map_or(0, |command| command[1]);
+        let outgoing = self.sent.get() < self.command_end.get();
+        if byte == NULL_BYTE {
+            self.receive(State::Procedure)
+        } else if byte == ins || byte == !ins {
+            // Transfer all the remaining data, or only the next byte.
+            let all = byte == ins;
+            if outgoing {
+                let end = if all {
+                    self.command_end.get()
+                } else {
+                    self.sent.get() + 1
+                };
+                self.send(end)
+            } else {
+                let received = self.received.get();
+                let end = if all {
+                    received + self.expected.get()
+                } else {
+                    received + 1
+                };
+                if end == received {
+                    // nothing left to receive
+                    return self.receive(State::Procedure);
+                }
+                if end > MAX_RESPONSE_DATA {
+                    return Err(ErrorCode::SIZE);
+                }
+                self.expected
+                    .set(self.expected.get().saturating_sub(end - received));
+                self.data_end.set(end);
+                self.receive(State::Data)
+            }
+        } else if byte & 0xF0 == 0x60 || byte & 0xF0 == 0x90 {
+            self.receive(State::StatusWord(byte))
+        } else {
+            Err(ErrorCode::FAIL)
+        }
+    }
+
+    fn data_byte(&self, byte: u8) -> Result<(), ErrorCode> {
+        let received = self.received.get();
+        self.response.map(|response| response[received] = byte);
+        self.received.set(received + 1);
+        if received + 1 < self.data_end.get() {
+            self.receive(State::Data)
+        } else {
+            self.receive(State::Procedure)
+        }
+    }
+
+    /// Handle the status word, which ends the command.
+    fn status_word(&self, sw1: u8, sw2: u8) -> Result<(), ErrorCode> {
+        let len = if sw2 == 0 { 256 } else { sw2 as usize };
+        match sw1 {
+            // Response bytes available: fetch them.
+            0x61 => {
+                self.command.map(|command| {
+                    let cla = command[0];
+                    command[..5].copy_from_slice(&[cla, INS_GET_RESPONSE, 0, 0, sw2]);
+                });
+                self.restart(len)
+            }
+            // Wrong length: repeat the command with the exact length.
+            0x6C => {
+                self.command.map(|command| command[4] = sw2);
+                self.restart(len)
+            }
+            _ => {
+                let received = self.received.get();
+                self.response.map(|response| {
+                    response[received] = sw1;
+                    response[received + 1] = sw2;
+                });
+                self.copy_response(rw_allow::RESPONSE, received + 2);
+                self.complete(Ok((received + 2, (sw1 as usize) << 8 | sw2 as usize)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Send the header again, expecting `len` response bytes.
+    fn restart(&self, len: usize) -> Result<(), ErrorCode> {
+        self.sent.set(0);
+        self.command_end.set(5);
+        self.expected.set(len);
+        self.send(5)
+    }
+
+    /// Copy the first `len` received bytes to the read-write allow buffer
+    /// `allow` of the owner, as far as they fit.
+    fn copy_response(&self, allow: usize, len: usize) {
+        self.response.map(|response| {
+            self.owner.map(|processid| {
+                let _ = self.apps.enter(processid, |_, kernel_data| {
+                    let _ = kernel_data
+                        .get_readwrite_processbuffer(allow)
+                        .and_then(|buffer| {
+                            buffer.mut_enter(|dest| {
+                                let len = cmp::min(dest.len(), len);
+                                dest[..len].copy_from_slice(&response[..len]);
+                            })
+                        });
+                });
+            });
+        });
+    }
+
+    /// End the operation and report its result.
+    fn complete(&self, result: Result<(usize, usize), ErrorCode>) {
+        let upcall = match self.state.get() {
+            State::Idle => return,
+            State::Resetting | State::Atr => upcall::ATR,
+            _ => upcall::APDU,
+        };
+        let _ = self.alarm.disarm();
+        self.state.set(State::Idle);
+        let args = match result {
+            Ok((arg1, arg2)) => (0, arg1, arg2),
+            Err(e) => (kernel::errorcode::into_statuscode(Err(e)), 0, 0),
+        };
+        if let Some(processid) = self.owner.take() {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, args).ok();
+            });
+        }
+    }
+
+    fn fail(&self, error: ErrorCode) {
+        if self.state.get() == State::Atr || self.state.get() == State::Resetting {
+            self.reset.clear();
+        }
+        let _ = self.uart.receive_abort();
+        self.complete(Err(error));
+    }
+
+    fn start(
+        &self,
+        command_num: usize,
+        data: usize,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.owner.set(processid);
+        let res = match command_num {
+            1 => self.activate(),
+            _ if !self.activated.get() => Err(ErrorCode::OFF),
+            _ if data > MAX_APDU_LEN => Err(ErrorCode::INVAL),
+            _ => self.exchange(data),
+        };
+        if res.is_err() {
+            self.state.set(State::Idle);
+            self.owner.clear();
+        }
+        res
+    }
+
+    fn deactivate(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.reset.clear();
+        self.activated.set(false);
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::UartData<'a> + uart::SmartCard, A: Alarm<'a>> uart::TransmitClient
+    for Iso7816<'a, U, A>
+{
+    fn transmitted_buffer(
+        &self,
+        _tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+    }
+
+    fn transmitted_word(&self, rval: Result<(), ErrorCode>) {
+        if self.state.get() != State::Sending {
+            return;
+        }
+        let res = rval.and_then(|()| {
+            let sent = self.sent.get() + 1;
+            self.sent.set(sent);
+            if sent < self.send_end.get() {
+                self.send(self.send_end.get())
+            } else {
+                self.receive(State::Procedure)
+            }
+        });
+        if let Err(e) = res {
+            self.fail(e);
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a> + uart::SmartCard, A: Alarm<'a>> uart::ReceiveClient
+    for Iso7816<'a, U, A>
+{
+    fn received_buffer(
+        &self,
+        _rx_buffer: &'static mut [u8],
+        _rx_len: usize,
+        _rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+    }
+
+    fn received_word(&self, word: u32, rval: Result<(), ErrorCode>, _error: uart::Error) {
+        let state = self.state.get();
+        if matches!(state, State::Idle | State::Resetting | State::Sending) {
+            return;
+        }
+        let _ = self.alarm.disarm();
+        // The ninth bit is the parity bit.
+        let byte = word as u8;
+        let res = rval.and_then(|()| match state {
+            State::Atr => self.atr_byte(byte),
+            State::Procedure => self.procedure_byte(byte),
+            State::Data => self.data_byte(byte),
+            State::StatusWord(sw1) => self.status_word(sw1, byte),
+            State::Idle | State::Resetting | State::Sending => Ok(()),
+        });
+        if let Err(e) = res {
+            self.fail(e);
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a> + uart::SmartCard, A: Alarm<'a>> AlarmClient for Iso7816<'a, U, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Resetting => {
+                // The card answers between 400 and 40000 clock cycles after
+                // the reset is released.
+                self.reset.set();
+                self.received.set(0);
+                if let Err(e) = self.receive(State::Atr) {
+                    self.fail(e);
+                }
+            }
+            // The card did not answer in time.
+            State::Atr | State::Procedure | State::Data | State::StatusWord(_) => {
+                self.fail(ErrorCode::NOACK)
+            }
+            State::Idle | State::Sending => {}
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a> + uart::SmartCard, A: Alarm<'a>> SyscallDriver
+    for Iso7816<'a, U, A>
+{
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 | 2 => self.start(command_num, data, processid).into(),
+
+            3 => self.deactivate().into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atr_length() {
+        // No interface bytes, two historical bytes, T=0 only.
+        assert_eq!(atr_len(&[0x3B, 0x02, 0x14, 0x50]), 4);
+        // TA1 and TD1, then TD2 offering T=15 with TA3: a check byte follows
+        // the 15 historical bytes.
+        let atr = [0x3B, 0x9F, 0x96, 0x80, 0x1F, 0xC7];
+        assert_eq!(atr_len(&atr[..2]), 4);
+        assert_eq!(atr_len(&atr[..4]), 5);
+        assert_eq!(atr_len(&atr), 2 + 4 + 15 + 1);
+    }
+
+    #[test]
+    fn atr_parameters() {
+        // TA1 = 0x95 (Fi = 512), TC1 = 2, TD1 = T=0 with TC2 = 20.
+        let params = parse_atr(&[0x3B, 0xD0, 0x95, 0x02, 0x40, 0x14]);
+        assert_eq!(
+            params,
+            AtrParameters {
+                fi: 512,
+                extra_guard_time: 2,
+                waiting_integer: 20,
+            }
+        );
+        // 960 * 10 * 372 cycles at 3.5712 MHz.
+        assert_eq!(
+            work_waiting_time_ms(&parse_atr(&[0x3B, 0x00]), 3_571_200),
+            1000
+        );
+    }
+
+    #[test]
+    fn apdu_cases() {
+        assert_eq!(apdu_lengths(&[0x00, 0xA4, 0x04, 0x00]), Some((0, 0)));
+        assert_eq!(
+            apdu_lengths(&[0x00, 0xB0, 0x00, 0x00, 0x00]),
+            Some((0, 256))
+        );
+        assert_eq!(
+            apdu_lengths(&[0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F, 0x00]),
+            Some((2, 0))
+        );
+        assert_eq!(
+            apdu_lengths(&[0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F, 0x00, 0x00]),
+            Some((2, 0))
+        );
+        assert_eq!(apdu_lengths(&[0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F]), None);
+    }
+}
@@ -0,0 +1,295 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Process isolation audit capsule for security testing.
+//!
+//! This capsule lets a dedicated test process verify that the MPU/PMP on a
+//! specific chip actually prevents it from touching memory it does not own.
+//! The process asks the capsule for a probe address (kernel RAM, kernel flash,
+//! another process's RAM or flash, or its own grant region), then performs a
+//! read of that address itself.
+//!
+//! - If the memory protection works, the read faults. This capsule is also
+//!   installed as the board's `ProcessFaultPolicy`, so it sees the fault,
+//!   records that the probe was blocked, and restarts the test process so it
+//!   can continue with the next probe.
+//! - If the read succeeds, the process reports this with command `2` and the
+//!   probe is recorded as not blocked.
+//!
+//! Faults that are not caused by an armed probe are forwarded to the wrapped
+//! fault policy. Results are kept in the capsule (not a grant) so they survive
+//! the restart of the test process.
+//!
+//! This capsule deliberately hands out addresses of kernel and foreign process
+//! memory, so it requires a `ProcessManagementCapability` to create and should
+//! only be included in test kernels. Only one process (the first to use it)
+//! can arm probes.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let audit = static_init!(
+//!     capsules_extra::isolation_audit::IsolationAudit<'static, ProcessMgmtCap>,
+//!     capsules_extra::isolation_audit::IsolationAudit::new(
+//!         board_kernel,
+//!         ProcessMgmtCap,
+//!         &FAULT_RESPONSE,
+//!         board_kernel.create_grant(
+//!             capsules_extra::isolation_audit::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! // Pass `audit` as the fault policy when loading processes.
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::process::{self, Process, ProcessFaultPolicy};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::IsolationAudit as usize;
+
+/// A word placed in kernel flash (read-only data) to use as the kernel flash
+/// probe target.
+static KERNEL_FLASH_MARKER: u32 = 0x7E57_15A0;
+
+/// Number of supported probe kinds.
+const NUM_PROBES: usize = 5;
+
+/// Memory regions a test process can try to access.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Probe {
+    /// Kernel RAM (this capsule's own state).
+    KernelRam = 0,
+    /// Kernel flash (a constant in the kernel's read-only data).
+    KernelFlash = 1,
+    /// The RAM of another running process.
+    OtherProcessRam = 2,
+    /// The flash of another process, including its TBF header.
+    OtherProcessFlash = 3,
+    /// The grant region the kernel allocated inside the test process's own
+    /// memory block.
+    OwnGrantRegion = 4,
+}
+
+impl Probe {
+    fn from_usize(value: usize) -> Option<Probe> {
+        match value {
+            0 => Some(Probe::KernelRam),
+            1 => Some(Probe::KernelFlash),
+            2 => Some(Probe::OtherProcessRam),
+            3 => Some(Probe::OtherProcessFlash),
+            4 => Some(Probe::OwnGrantRegion),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a probe.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProbeResult {
+    /// The probe has not completed yet.
+    Untested = 0,
+    /// The access faulted, so memory protection blocked it.
+    Blocked = 1,
+    /// The access succeeded: isolation is broken for this region.
+    NotBlocked = 2,
+}
+
+#[derive(Copy, Clone)]
+struct ArmedProbe {
+    processid: ProcessId,
+    probe: Probe,
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct IsolationAudit<'a, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    capability: C,
+    fault_policy: &'a dyn ProcessFaultPolicy,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    controlling_app: OptionalCell<ProcessId>,
+    armed: OptionalCell<ArmedProbe>,
+    results: [Cell<ProbeResult>; NUM_PROBES],
+}
+
+impl<'a, C: ProcessManagementCapability> IsolationAudit<'a, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        capability: C,
+        fault_policy: &'a dyn ProcessFaultPolicy,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            kernel,
+            capability,
+            fault_policy,
+            apps: grant,
+            controlling_app: OptionalCell::empty(),
+            armed: OptionalCell::empty(),
+            results: [
+                Cell::new(ProbeResult::Untested),
+                Cell::new(ProbeResult::Untested),
+                Cell::new(ProbeResult::Untested),
+                Cell::new(ProbeResult::Untested),
+                Cell::new(ProbeResult::Untested),
+            ],
+        }
+    }
+
+    /// Return the result recorded for `probe`.
+    pub fn result(&self, probe: Probe) -> ProbeResult {
+        self.results[probe as usize].get()
+    }
+
+    /// Claim the capsule for `processid`. A restarted test process gets a new
+    /// `ProcessId`, but the old one no longer exists, so it can reclaim the
+    /// capsule.
+    fn try_claim(&self, processid: ProcessId) -> bool {
+        let match_or_empty_or_nonexistant = self.controlling_app.map_or(true, |controlling_app| {
+            self.apps
+                .enter(controlling_app, |_, _| controlling_app == processid)
+                .unwrap_or(true)
+        });
+        if match_or_empty_or_nonexistant {
+            self.controlling_app.set(processid);
+        }
+        match_or_empty_or_nonexistant
+    }
+
+    /// Compute the address a process should read to exercise `probe`.
+    fn probe_address(&self, probe: Probe, processid: ProcessId) -> Option<usize> {
+        match probe {
+            Probe::KernelRam => Some(core::ptr::from_ref(self) as usize),
+            Probe::KernelFlash => Some(core::ptr::from_ref(&KERNEL_FLASH_MARKER) as usize),
+            Probe::OtherProcessRam | Probe::OtherProcessFlash => {
+                let mut address = None;
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if address.is_none() && process.processid() != processid {
+                            let addresses = process.get_addresses();
+                            address = Some(if probe == Probe::OtherProcessRam {
+                                addresses.sram_start
+                            } else {
+                                addresses.flash_start
+                            });
+                        }
+                    });
+                address
+            }
+            Probe::OwnGrantRegion => self.kernel.process_map_or_external(
+                None,
+                processid,
+                |process| Some(process.get_addresses().sram_grant_start),
+                &self.capability,
+            ),
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability> ProcessFaultPolicy for IsolationAudit<'_, C> {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        match self.armed.take() {
+            Some(armed) if armed.processid == process.processid() => {
+                self.results[armed.probe as usize].set(ProbeResult::Blocked);
+                process::FaultAction::Restart
+            }
+            armed => {
+                // Not caused by a probe, keep the probe armed for its owner.
+                if let Some(armed) = armed {
+                    self.armed.set(armed);
+                }
+                self.fault_policy.action(process)
+            }
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for IsolationAudit<'_, C> {
+    /// Control the isolation audit.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Arm probe `data` (see `Probe`) and return the address the
+    ///   process must now read.
+    /// - `2`: Report that the read of the armed probe address succeeded.
+    /// - `3`: Get the result of probe `data` (see `ProbeResult`).
+    /// - `4`: Reset all results.
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if !self.try_claim(processid) {
+                    return CommandReturn::failure(ErrorCode::RESERVE);
+                }
+                match Probe::from_usize(data) {
+                    Some(probe) => match self.probe_address(probe, processid) {
+                        Some(address) => {
+                            self.results[probe as usize].set(ProbeResult::Untested);
+                            self.armed.set(ArmedProbe { processid, probe });
+                            CommandReturn::success_u32(address as u32)
+                        }
+                        None => CommandReturn::failure(ErrorCode::NODEVICE),
+                    },
+                    None => CommandReturn::failure(ErrorCode::INVAL),
+                }
+            }
+
+            2 => match self.armed.take() {
+                Some(armed) if armed.processid == processid => {
+                    self.results[armed.probe as usize].set(ProbeResult::NotBlocked);
+                    CommandReturn::success()
+                }
+                armed => {
+                    if let Some(armed) = armed {
+                        self.armed.set(armed);
+                    }
+                    CommandReturn::failure(ErrorCode::INVAL)
+                }
+            },
+
+            3 => match Probe::from_usize(data) {
+                Some(probe) => CommandReturn::success_u32(self.result(probe) as u32),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            4 => {
+                if !self.try_claim(processid) {
+                    return CommandReturn::failure(ErrorCode::RESERVE);
+                }
+                self.armed.clear();
+                for result in self.results.iter() {
+                    result.set(ProbeResult::Untested);
+                }
+                CommandReturn::success()
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -11,41 +11,61 @@ pub mod tutorials;
 #[macro_use]
 pub mod net;
 
+pub mod adc_acquisition;
+pub mod adc_decimator;
+pub mod adc_dsp;
 pub mod adc_microphone;
+pub mod adc_sensor;
 pub mod air_quality;
+pub mod alert;
 pub mod ambient_light;
 pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
+pub mod app_accept_list;
 pub mod app_flash_driver;
+pub mod asset_store;
 pub mod at24c_eeprom;
+pub mod atecc608;
+pub mod attestation;
 pub mod ble_advertising_driver;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
+pub mod boot_config;
 pub mod bus;
 pub mod buzzer_driver;
 pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
+pub mod condensation_recovery;
 pub mod crc;
 pub mod cycle_count;
 pub mod dac;
 pub mod date_time;
 pub mod debug_process_restart;
 pub mod eui64;
+pub mod fingerprint;
+pub mod flash_fs;
+pub mod flash_fs_driver;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
 pub mod gpio_async;
+pub mod gpio_expander;
 pub mod hd44780;
+pub mod heart_rate;
 pub mod hmac;
 pub mod hmac_sha256;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
+pub mod hx711;
 pub mod ieee802154;
+pub mod inference;
 pub mod isl29035;
+pub mod iso7816;
+pub mod isolation_audit;
 pub mod kv_driver;
 pub mod kv_store_permissions;
 pub mod l3gd20;
@@ -60,22 +80,31 @@ pub mod lsm303xx;
 pub mod lsm6dsoxtr;
 pub mod ltc294x;
 pub mod max17205;
+pub mod max30102;
 pub mod mcp230xx;
+pub mod measured_boot;
 pub mod mlx90614;
+pub mod motor_control;
 pub mod mx25r6435f;
+pub mod net_stats;
 pub mod ninedof;
 pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
 pub mod panic_button;
 pub mod pca9544a;
+pub mod pn532;
+pub mod power_control;
 pub mod pressure;
+pub mod process_info;
 pub mod proximity;
 pub mod public_key_crypto;
 pub mod pwm;
 pub mod read_only_state;
 pub mod rf233;
 pub mod rf233_const;
+pub mod rs485;
+pub mod scale;
 pub mod screen;
 pub mod screen_shared;
 pub mod sdcard;
@@ -88,11 +117,18 @@ pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
 pub mod sip_hash;
+pub mod sntp;
+pub mod sound_level;
 pub mod sound_pressure;
 pub mod ssd1306;
 pub mod st77xx;
+pub mod supervisor;
 pub mod symmetric_encryption;
+pub mod system_config;
+pub mod tamper;
+pub mod tdm_capture;
 pub mod temperature;
+pub mod temperature_compensation;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod text_screen;
@@ -100,6 +136,7 @@ pub mod tickv;
 pub mod tickv_kv_store;
 pub mod touch;
 pub mod tsl2561;
+pub mod uptime;
 pub mod usb;
 pub mod usb_hid_driver;
 pub mod virtual_kv;
@@ -0,0 +1,646 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the Maxim MAX30102 pulse oximeter and heart rate sensor.
+//!
+//! <https://www.analog.com/media/en/technical-documentation/data-sheets/MAX30102.pdf>
+//!
+//! The MAX30102 pulses a red and an infrared LED and samples the reflected
+//! light with an 18-bit ADC. The samples are stored in a 32 entry FIFO. The
+//! driver runs the sensor in SpO2 mode, where each FIFO entry holds a red and
+//! an infrared sample, and lets the sensor raise its interrupt line when the
+//! FIFO is almost full. It then drains the whole FIFO in a single I2C read,
+//! so the CPU only wakes up every 17 samples.
+//!
+//! The samples are streamed to processes and, through
+//! `hil::sensors::PulseOximeterDriver`, to an in-kernel client such as the
+//! beat detection of `capsules_extra::heart_rate`. The sensor is shut down
+//! when nobody samples it.
+//!
+//! The LED currents and the sample rate are shared by all users, and can
+//! only be changed while the sensor is shut down.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! Buffer `0` receives the samples, as pairs of little-endian `u32`: the red
+//! sample, then the infrared sample.
+//!
+//! ### `subscribe` System Call
+//!
+//! Subscribe `0` is called every time the buffer is full, with:
+//!
+//! - the status,
+//! - the number of sample pairs in the buffer,
+//! - the number of sample pairs lost since the last upcall because the FIFO
+//!   overflowed.
+//!
+//! The next samples are written from the start of the buffer again. If
+//! sampling fails, the upcall is called with the error and streaming stops.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Start streaming samples.
+//! - `2`: Stop streaming samples.
+//! - `3`: Set the current of the red LED to `data1` and the one of the
+//!   infrared LED to `data2`, in steps of 0.2 mA (0 to 255).
+//! - `4`: Set the sample rate to `data1` Hz: 50, 100, 200, 400, 800, 1000
+//!   or 1600. Higher rates use shorter LED pulses and have a lower
+//!   resolution.
+//! - `5`: Get the sample rate in Hz.
+//!
+//! Commands `3` and `4` return `BUSY` while the sensor is sampling.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let max30102 = components::max30102::Max30102Component::new(
+//!     mux_i2c,
+//!     0x57,
+//!     interrupt_pin,
+//!     board_kernel,
+//!     capsules_extra::max30102::DRIVER_NUM,
+//! )
+//! .finalize(components::max30102_component_static!(nrf52840::i2c::TWI));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::gpio;
+use kernel::hil::i2c::{self, I2CClient, I2CDevice};
+use kernel::hil::sensors::{PulseOximeterClient, PulseOximeterDriver};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Max30102 as usize;
+
+/// Number of entries of the FIFO.
+const FIFO_DEPTH: usize = 32;
+/// Size of a FIFO entry in SpO2 mode: two 3-byte samples.
+const ENTRY_BYTES: usize = 6;
+/// Size of a sample pair in the process buffer.
+const PAIR_BYTES: usize = 8;
+
+/// Size of the I2C buffer, which receives the whole FIFO.
+pub const BUFFER_LEN: usize = FIFO_DEPTH * ENTRY_BYTES;
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// The sample buffer is full.
+    pub const SAMPLES: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers.
+mod rw_allow {
+    /// Sample pairs.
+    pub const SAMPLES: usize = 0;
+    /// Number of read-write allow buffers.
+    pub const COUNT: u8 = 1;
+}
+
+#[repr(u8)]
+enum Registers {
+    IntStatus1 = 0x00,
+    IntEnable1 = 0x02,
+    FifoWritePointer = 0x04,
+    FifoData = 0x07,
+    FifoConfig = 0x08,
+    ModeConfig = 0x09,
+    Led1PulseAmplitude = 0x0C,
+}
+
+/// Interrupt when the FIFO is almost full.
+const A_FULL_EN: u8 = 1 << 7;
+/// Interrupt when 15 entries are left in the FIFO, that is when it holds 17
+/// sample pairs.
+const FIFO_A_FULL: u8 = 0x0F;
+const MODE_SHDN: u8 = 1 << 7;
+const MODE_SPO2: u8 = 0x03;
+/// ADC full scale of 4096 nA.
+const SPO2_ADC_RGE_4096: u8 = 0b01 << 5;
+/// Only the 18 low bits of each FIFO sample are used.
+const SAMPLE_MASK: u32 = 0x3FFFF;
+
+/// Sample rates in SpO2 mode, with their `SPO2_SR` code and the longest LED
+/// pulse (`LED_PW` code) allowed at this rate.
+const SAMPLE_RATES: [(u32, u8, u8); 7] = [
+    (50, 0, 3),
+    (100, 1, 3),
+    (200, 2, 3),
+    (400, 3, 3),
+    (800, 4, 2),
+    (1000, 5, 1),
+    (1600, 6, 0),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    /// The sensor is shut down.
+    Off,
+    /// Enabling the interrupt and clearing the FIFO pointers.
+    ClearingFifo,
+    SettingLedCurrents,
+    /// Writing the sample rate and leaving shutdown.
+    SettingMode,
+    /// Waiting for the FIFO to fill.
+    Sampling,
+    ReadingStatus,
+    ReadingPointers,
+    ReadingFifo,
+    ShuttingDown,
+}
+
+#[derive(Default)]
+pub struct App {
+    streaming: bool,
+    /// Number of sample pairs already written in the buffer.
+    position: usize,
+    /// Sample pairs lost since the last upcall.
+    dropped: usize,
+}
+
+/// Return the red and infrared samples of a FIFO entry.
+fn sample_pair(entry: &[u8]) -> (u32, u32) {
+    let sample = |bytes: &[u8]| {
+        (u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2])) & SAMPLE_MASK
+    };
+    (sample(&entry[0..3]), sample(&entry[3..6]))
+}
+
+/// Return the number of entries in the FIFO from its write and read
+/// pointers and its overflow counter.
+fn fifo_entries(write_pointer: u8, overflow: u8, read_pointer: u8) -> usize {
+    if overflow != 0 {
+        FIFO_DEPTH
+    } else {
+        (write_pointer.wrapping_sub(read_pointer) as usize) % FIFO_DEPTH
+    }
+}
+
+pub struct Max30102<'a, I: I2CDevice> {
+    i2c: &'a I,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    state: Cell<State>,
+    buffer: TakeCell<'static, [u8]>,
+    /// Index in `SAMPLE_RATES`.
+    sample_rate: Cell<usize>,
+    red_current: Cell<u8>,
+    infrared_current: Cell<u8>,
+    /// Sample pairs lost to FIFO overflows during the current drain.
+    overflow: Cell<usize>,
+    /// Length of the FIFO read of the current drain.
+    fifo_len: Cell<usize>,
+    /// Whether the in-kernel client is sampling.
+    client_sampling: Cell<bool>,
+    client: OptionalCell<&'a dyn PulseOximeterClient>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, I: I2CDevice> Max30102<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Max30102<'a, I> {
+        Max30102 {
+            i2c,
+            interrupt_pin,
+            state: Cell::new(State::Off),
+            buffer: TakeCell::new(buffer),
+            // 100 Hz.
+            sample_rate: Cell::new(1),
+            // 7 mA, enough for a finger on the sensor.
+            red_current: Cell::new(0x24),
+            infrared_current: Cell::new(0x24),
+            overflow: Cell::new(0),
+            fifo_len: Cell::new(0),
+            client_sampling: Cell::new(false),
+            client: OptionalCell::empty(),
+            apps: grant,
+        }
+    }
+
+    /// Set the LED currents, in steps of 0.2 mA.
+    pub fn set_led_currents(&self, red: u8, infrared: u8) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Off {
+            return Err(ErrorCode::BUSY);
+        }
+        self.red_current.set(red);
+        self.infrared_current.set(infrared);
+        Ok(())
+    }
+
+    /// Set the sample rate, in Hz.
+    pub fn set_sample_rate(&self, rate: u32) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Off {
+            return Err(ErrorCode::BUSY);
+        }
+        let index = SAMPLE_RATES
+            .iter()
+            .position(|&(hz, _, _)| hz == rate)
+            .ok_or(ErrorCode::INVAL)?;
+        self.sample_rate.set(index);
+        Ok(())
+    }
+
+    fn is_used(&self) -> bool {
+        self.client_sampling.get()
+            || self
+                .apps
+                .iter()
+                .any(|app| app.enter(|app, _| app.streaming))
+    }
+
+    /// Start the sensor if it is shut down. If it is shutting down, it is
+    /// restarted once shut down.
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Off {
+            return Ok(());
+        }
+        self.i2c.enable();
+        let res = self.write(
+            State::ClearingFifo,
+            &[Registers::IntEnable1 as u8, A_FULL_EN, 0, 0, 0, 0],
+        );
+        if res.is_err() {
+            self.i2c.disable();
+        }
+        res
+    }
+
+    /// Shut the sensor down if nobody uses it and it is waiting for samples.
+    /// Otherwise, this is checked again at the end of the I2C transfer.
+    fn stop_if_unused(&self) {
+        if self.state.get() == State::Sampling && !self.is_used() {
+            self.shut_down();
+        }
+    }
+
+    fn shut_down(&self) {
+        self.interrupt_pin.disable_interrupts();
+        if self
+            .write(
+                State::ShuttingDown,
+                &[Registers::ModeConfig as u8, MODE_SHDN | MODE_SPO2],
+            )
+            .is_err()
+        {
+            self.state.set(State::Off);
+            self.i2c.disable();
+        }
+    }
+
+    fn write(&self, state: State, data: &[u8]) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer[..data.len()].copy_from_slice(data);
+        match self.i2c.write(buffer, data.len()) {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(error.into())
+            }
+        }
+    }
+
+    fn write_read(
+        &self,
+        state: State,
+        register: Registers,
+        read_len: usize,
+    ) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer[0] = register as u8;
+        match self.i2c.write_read(buffer, 1, read_len) {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Go on with the next step after `state`.
+    fn next(&self, state: State, buffer: &[u8]) -> Result<(), ErrorCode> {
+        match state {
+            State::ClearingFifo => self.write(
+                State::SettingLedCurrents,
+                &[
+                    Registers::Led1PulseAmplitude as u8,
+                    self.red_current.get(),
+                    self.infrared_current.get(),
+                ],
+            ),
+            State::SettingLedCurrents => {
+                let (_, rate, pulse_width) = SAMPLE_RATES[self.sample_rate.get()];
+                self.write(
+                    State::SettingMode,
+                    &[
+                        Registers::FifoConfig as u8,
+                        FIFO_A_FULL,
+                        MODE_SPO2,
+                        SPO2_ADC_RGE_4096 | rate << 2 | pulse_width,
+                    ],
+                )
+            }
+            State::SettingMode => {
+                self.interrupt_pin.make_input();
+                self.interrupt_pin
+                    .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+                self.sampled();
+                Ok(())
+            }
+            // Reading the status clears the interrupt.
+            State::ReadingStatus => {
+                self.write_read(State::ReadingPointers, Registers::FifoWritePointer, 3)
+            }
+            State::ReadingPointers => {
+                let entries = fifo_entries(buffer[0], buffer[1], buffer[2]);
+                self.overflow.set(buffer[1] as usize);
+                self.fifo_len.set(entries * ENTRY_BYTES);
+                if entries == 0 {
+                    self.sampled();
+                    Ok(())
+                } else {
+                    self.write_read(
+                        State::ReadingFifo,
+                        Registers::FifoData,
+                        entries * ENTRY_BYTES,
+                    )
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait for the next samples, or shut down if nobody uses the sensor
+    /// anymore.
+    fn sampled(&self) {
+        self.state.set(State::Sampling);
+        if !self.is_used() {
+            self.shut_down();
+        } else if !self.interrupt_pin.read() {
+            // The FIFO filled up again during the drain, and the interrupt
+            // line is still asserted: there will be no new edge.
+            self.drain();
+        }
+    }
+
+    fn drain(&self) {
+        if let Err(error) = self.write_read(State::ReadingStatus, Registers::IntStatus1, 1) {
+            self.failed(error);
+        }
+    }
+
+    /// Stop sampling for everyone and report `error`.
+    fn failed(&self, error: ErrorCode) {
+        self.interrupt_pin.disable_interrupts();
+        self.state.set(State::Off);
+        self.i2c.disable();
+        self.client_sampling.set(false);
+        self.client.map(|client| client.sampling_error(error));
+        self.apps.each(|_, app, kernel_data| {
+            if app.streaming {
+                app.streaming = false;
+                let _ = kernel_data
+                    .schedule_upcall(upcall::SAMPLES, (into_statuscode(Err(error)), 0, 0));
+            }
+        });
+    }
+
+    /// Hand the sample pairs of `entries` to the client and the processes.
+    fn deliver(&self, entries: &[u8]) {
+        self.client.map(|client| {
+            entries
+                .chunks_exact(ENTRY_BYTES)
+                .map(sample_pair)
+                .for_each(|(red, infrared)| client.sample(red, infrared))
+        });
+
+        let overflow = self.overflow.get();
+        self.apps.each(|_, app, kernel_data| {
+            if !app.streaming {
+                return;
+            }
+            app.dropped += overflow;
+
+            let capacity = kernel_data
+                .get_readwrite_processbuffer(rw_allow::SAMPLES)
+                .map_or(0, |buffer| buffer.len() / PAIR_BYTES);
+            if capacity == 0 {
+                return;
+            }
+            // The buffer may have been replaced by a smaller one.
+            if app.position >= capacity {
+                app.position = 0;
+            }
+
+            for entry in entries.chunks_exact(ENTRY_BYTES) {
+                let (red, infrared) = sample_pair(entry);
+                let position = app.position;
+                let _ = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::SAMPLES)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|dest| {
+                            let offset = position * PAIR_BYTES;
+                            dest[offset..offset + 4].copy_from_slice(&red.to_le_bytes());
+                            dest[offset + 4..offset + 8].copy_from_slice(&infrared.to_le_bytes());
+                        })
+                    });
+
+                app.position += 1;
+                if app.position >= capacity {
+                    app.position = 0;
+                    let _ =
+                        kernel_data.schedule_upcall(upcall::SAMPLES, (0, capacity, app.dropped));
+                    app.dropped = 0;
+                }
+            }
+        });
+    }
+}
+
+impl<I: I2CDevice> I2CClient for Max30102<'_, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        let state = self.state.get();
+        if let Err(error) = status {
+            self.buffer.replace(buffer);
+            if state == State::ShuttingDown {
+                self.state.set(State::Off);
+                self.i2c.disable();
+            } else {
+                self.failed(error.into());
+            }
+            return;
+        }
+
+        match state {
+            State::ShuttingDown => {
+                self.buffer.replace(buffer);
+                self.state.set(State::Off);
+                self.i2c.disable();
+                // Somebody started sampling again while shutting down.
+                if self.is_used() {
+                    if let Err(error) = self.start() {
+                        self.failed(error);
+                    }
+                }
+            }
+            State::ReadingFifo => {
+                self.deliver(&buffer[..self.fifo_len.get()]);
+                self.buffer.replace(buffer);
+                self.sampled();
+            }
+            _ => {
+                let mut pointers = [0; 3];
+                pointers.copy_from_slice(&buffer[..3]);
+                self.buffer.replace(buffer);
+                if let Err(error) = self.next(state, &pointers) {
+                    self.failed(error);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, I: I2CDevice> gpio::Client for Max30102<'a, I> {
+    fn fired(&self) {
+        // Otherwise the line is checked once the current transfer is done.
+        if self.state.get() == State::Sampling {
+            self.drain();
+        }
+    }
+}
+
+impl<'a, I: I2CDevice> PulseOximeterDriver<'a> for Max30102<'a, I> {
+    fn start_sampling(&self) -> Result<(), ErrorCode> {
+        self.client_sampling.set(true);
+        let res = self.start();
+        if res.is_err() {
+            self.client_sampling.set(false);
+        }
+        res
+    }
+
+    fn stop_sampling(&self) -> Result<(), ErrorCode> {
+        self.client_sampling.set(false);
+        self.stop_if_unused();
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATES[self.sample_rate.get()].0
+    }
+
+    fn set_client(&self, client: &'a dyn PulseOximeterClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, I: I2CDevice> SyscallDriver for Max30102<'a, I> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        app.streaming = true;
+                        app.position = 0;
+                        app.dropped = 0;
+                    })
+                    .map_err(ErrorCode::from)
+                    .and_then(|()| self.start());
+                if let Err(error) = res {
+                    let _ = self.apps.enter(processid, |app, _| app.streaming = false);
+                    return CommandReturn::failure(error);
+                }
+                CommandReturn::success()
+            }
+
+            2 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| app.streaming = false)
+                    .map_err(ErrorCode::from);
+                self.stop_if_unused();
+                res.into()
+            }
+
+            3 => match (u8::try_from(data1), u8::try_from(data2)) {
+                (Ok(red), Ok(infrared)) => self.set_led_currents(red, infrared).into(),
+                _ => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            4 => match u32::try_from(data1) {
+                Ok(rate) => self.set_sample_rate(rate).into(),
+                Err(_) => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            5 => CommandReturn::success_u32(self.sample_rate()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifo_entry() {
+        // The unused high bits are masked.
+        let entry = [0xC1, 0x23, 0x45, 0x00, 0x00, 0x7F];
+        assert_eq!(sample_pair(&entry), (0x12345, 0x7F));
+    }
+
+    #[test]
+    fn fifo_occupancy() {
+        assert_eq!(fifo_entries(5, 0, 5), 0);
+        assert_eq!(fifo_entries(20, 0, 3), 17);
+        // The pointers wrap around.
+        assert_eq!(fifo_entries(2, 0, 30), 4);
+        assert_eq!(fifo_entries(7, 3, 7), FIFO_DEPTH);
+    }
+}
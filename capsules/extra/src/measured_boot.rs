@@ -0,0 +1,394 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Measured boot of the loaded processes.
+//!
+//! The process loader gives this capsule the binary of each process it loads
+//! (see `ProcessBinaryClient`). The capsule hashes it with SHA-256 and
+//! extends a measurement register with the hash, like a TPM PCR:
+//!
+//! ```text
+//! register = SHA-256(register || SHA-256(binary))
+//! ```
+//!
+//! The register starts at zero. Its final value depends on the binaries of
+//! all processes and the order in which they were loaded, so an attestation
+//! protocol can use it to prove which applications run on the board. The
+//! individual measurements are also available, so a verifier can replay the
+//! register from a list of known binaries.
+//!
+//! The register is kept in RAM, and is only extended by the process loader.
+//! Only `SequentialProcessLoaderMachine` reports the binaries it loads, so
+//! boards must load processes with it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let measured_boot = components::measured_boot::MeasuredBootComponent::new(
+//!     board_kernel,
+//!     capsules_extra::measured_boot::DRIVER_NUM,
+//!     sha,
+//! )
+//! .finalize(components::measured_boot_component_static!(
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     NUM_PROCS
+//! ));
+//! loader.set_binary_client(measured_boot);
+//! ```
+
+use core::cell::Cell;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::digest::{ClientData, ClientHash, DigestDataHash, Sha256};
+use kernel::process::{Process, ProcessBinaryClient, ShortId};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{MapCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::MeasuredBoot as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer receiving the register or a measurement.
+    pub const DIGEST: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Length of a SHA-256 digest.
+pub const DIGEST_LEN: usize = 32;
+
+/// The measurement of one process binary.
+#[derive(Clone, Copy)]
+pub struct Measurement {
+    short_id: ShortId,
+    binary: &'static [u8],
+    digest: [u8; DIGEST_LEN],
+    measured: bool,
+}
+
+/// Read access to the measurements, for in-kernel attestation protocols.
+pub trait MeasurementLog {
+    /// Return the measurement register, or `None` while binaries are being
+    /// measured or if a binary could not be measured.
+    fn register(&self) -> Option<[u8; DIGEST_LEN]>;
+
+    /// Return the number of measured processes.
+    fn count(&self) -> usize;
+
+    /// Return the ShortId (0 if the process has none) and digest of the
+    /// measurement with index `index`.
+    fn measurement(&self, index: usize) -> Option<(u32, [u8; DIGEST_LEN])>;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Hashing the binary of a process.
+    Binary(usize),
+    /// Extending the register with the hash of the binary.
+    Extend(usize),
+}
+
+pub struct MeasuredBoot<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> {
+    digest: &'a D,
+    state: Cell<State>,
+    /// Set if a binary could not be measured, which invalidates the register.
+    failed: Cell<bool>,
+    register: Cell<[u8; DIGEST_LEN]>,
+    measurements: MapCell<&'static mut [Option<Measurement>]>,
+    /// Holds the register followed by the digest of a binary, to extend the
+    /// register.
+    data_buffer: TakeCell<'static, [u8]>,
+    digest_buffer: TakeCell<'static, [u8; DIGEST_LEN]>,
+    apps: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> MeasuredBoot<'a, D> {
+    /// `measurements` must have room for all the processes the board can
+    /// load, and `data_buffer` must be at least twice `DIGEST_LEN` bytes.
+    pub fn new(
+        digest: &'a D,
+        measurements: &'static mut [Option<Measurement>],
+        data_buffer: &'static mut [u8],
+        digest_buffer: &'static mut [u8; DIGEST_LEN],
+        grant: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> Self {
+        MeasuredBoot {
+            digest,
+            state: Cell::new(State::Idle),
+            failed: Cell::new(false),
+            register: Cell::new([0; DIGEST_LEN]),
+            measurements: MapCell::new(measurements),
+            data_buffer: TakeCell::new(data_buffer),
+            digest_buffer: TakeCell::new(digest_buffer),
+            apps: grant,
+        }
+    }
+
+    /// Return `true` while binaries are waiting to be measured.
+    pub fn busy(&self) -> bool {
+        self.state.get() != State::Idle
+    }
+
+    /// Start hashing the next binary that was not measured yet, if any.
+    fn measure_next(&self) {
+        let next = self.measurements.map_or(None, |measurements| {
+            measurements
+                .iter()
+                .enumerate()
+                .find_map(|(index, entry)| match entry {
+                    Some(measurement) if !measurement.measured => Some((index, measurement.binary)),
+                    _ => None,
+                })
+        });
+        match next {
+            Some((index, binary)) => {
+                self.state.set(State::Binary(index));
+                self.digest.clear_data();
+                let result = self.digest.set_mode_sha256().and_then(|()| {
+                    self.digest
+                        .add_data(SubSlice::new(binary))
+                        .map_err(|(e, _)| e)
+                });
+                if result.is_err() {
+                    self.measured(index, None);
+                }
+            }
+            None => self.state.set(State::Idle),
+        }
+    }
+
+    /// Record the digest of the binary `index`, or that it could not be
+    /// measured, and continue with the next binary.
+    fn measured(&self, index: usize, digest: Option<[u8; DIGEST_LEN]>) {
+        if digest.is_none() {
+            self.failed.set(true);
+        }
+        self.measurements.map(|measurements| {
+            if let Some(measurement) = &mut measurements[index] {
+                measurement.digest = digest.unwrap_or([0; DIGEST_LEN]);
+                measurement.measured = true;
+            }
+        });
+        self.measure_next();
+    }
+
+    /// Copy `digest` into the read-write allow buffer of `processid`.
+    fn copy_digest(
+        &self,
+        processid: ProcessId,
+        digest: &[u8; DIGEST_LEN],
+    ) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::DIGEST)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| {
+                            if buffer.len() < DIGEST_LEN {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            buffer[..DIGEST_LEN].copy_from_slice(digest);
+                            Ok(())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> MeasurementLog for MeasuredBoot<'a, D> {
+    fn register(&self) -> Option<[u8; DIGEST_LEN]> {
+        if self.busy() || self.failed.get() {
+            None
+        } else {
+            Some(self.register.get())
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.measurements
+            .map_or(0, |measurements| measurements.iter().flatten().count())
+    }
+
+    fn measurement(&self, index: usize) -> Option<(u32, [u8; DIGEST_LEN])> {
+        self.measurements
+            .map_or(None, |measurements| {
+                measurements.get(index).copied().flatten()
+            })
+            .map(|measurement| {
+                let short_id = match measurement.short_id {
+                    ShortId::LocallyUnique => 0,
+                    ShortId::Fixed(id) => id.get(),
+                };
+                (short_id, measurement.digest)
+            })
+    }
+}
+
+impl<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> ProcessBinaryClient for MeasuredBoot<'a, D> {
+    fn process_binary_loaded(&self, process: &dyn Process, binary: &'static [u8]) {
+        let measurement = Measurement {
+            short_id: process.short_app_id(),
+            binary,
+            digest: [0; DIGEST_LEN],
+            measured: false,
+        };
+        let stored = self.measurements.map_or(false, |measurements| {
+            measurements
+                .iter_mut()
+                .find(|entry| entry.is_none())
+                .map(|entry| *entry = Some(measurement))
+                .is_some()
+        });
+        if !stored {
+            // The register cannot account for this process.
+            self.failed.set(true);
+        } else if !self.busy() {
+            self.measure_next();
+        }
+    }
+}
+
+impl<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> ClientData<DIGEST_LEN>
+    for MeasuredBoot<'a, D>
+{
+    fn add_data_done(&self, result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        if let State::Binary(index) = self.state.get() {
+            let run = result.and_then(|()| {
+                let digest = self.digest_buffer.take().ok_or(ErrorCode::FAIL)?;
+                self.digest.run(digest).map_err(|(e, digest)| {
+                    self.digest_buffer.replace(digest);
+                    e
+                })
+            });
+            if run.is_err() {
+                self.measured(index, None);
+            }
+        }
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.data_buffer.replace(data.take());
+        if let State::Extend(index) = self.state.get() {
+            let run = result.and_then(|()| {
+                let digest = self.digest_buffer.take().ok_or(ErrorCode::FAIL)?;
+                self.digest.run(digest).map_err(|(e, digest)| {
+                    self.digest_buffer.replace(digest);
+                    e
+                })
+            });
+            if run.is_err() {
+                self.measured(index, None);
+            }
+        }
+    }
+}
+
+impl<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> ClientHash<DIGEST_LEN>
+    for MeasuredBoot<'a, D>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; DIGEST_LEN]) {
+        let value = *digest;
+        self.digest_buffer.replace(digest);
+
+        match (self.state.get(), result) {
+            (State::Binary(index), Ok(())) => {
+                // Record the digest of the binary, then extend the register
+                // with it.
+                self.measurements.map(|measurements| {
+                    if let Some(measurement) = &mut measurements[index] {
+                        measurement.digest = value;
+                    }
+                });
+                self.state.set(State::Extend(index));
+                self.digest.clear_data();
+                let result = self
+                    .data_buffer
+                    .take()
+                    .ok_or(ErrorCode::FAIL)
+                    .and_then(|buffer| {
+                        buffer[..DIGEST_LEN].copy_from_slice(&self.register.get());
+                        buffer[DIGEST_LEN..2 * DIGEST_LEN].copy_from_slice(&value);
+                        let mut data = SubSliceMut::new(buffer);
+                        data.slice(..2 * DIGEST_LEN);
+                        self.digest.add_mut_data(data).map_err(|(e, data)| {
+                            self.data_buffer.replace(data.take());
+                            e
+                        })
+                    });
+                if result.is_err() {
+                    self.measured(index, None);
+                }
+            }
+            (State::Extend(index), Ok(())) => {
+                self.register.set(value);
+                let digest = self
+                    .measurements
+                    .map_or(None, |measurements| measurements[index].map(|m| m.digest));
+                self.measured(index, digest);
+            }
+            (State::Binary(index), Err(_)) | (State::Extend(index), Err(_)) => {
+                self.measured(index, None);
+            }
+            (State::Idle, _) => {}
+        }
+    }
+}
+
+/// Provide an interface for userland.
+impl<'a, D: DigestDataHash<'a, DIGEST_LEN> + Sha256> SyscallDriver for MeasuredBoot<'a, D> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Number of measured processes.
+    /// - `2`: Copy the measurement register into the read-write allow buffer.
+    /// - `3`: Copy the measurement of the process with index `data1` into the
+    ///   read-write allow buffer, and return its ShortId.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num != 0 && self.busy() {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.count() as u32),
+
+            2 => {
+                if self.failed.get() {
+                    return CommandReturn::failure(ErrorCode::FAIL);
+                }
+                self.copy_digest(processid, &self.register.get()).into()
+            }
+
+            3 => match self.measurement(data1) {
+                Some((short_id, digest)) => match self.copy_digest(processid, &digest) {
+                    Ok(()) => CommandReturn::success_u32(short_id),
+                    Err(e) => CommandReturn::failure(e),
+                },
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
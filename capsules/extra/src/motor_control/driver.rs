@@ -0,0 +1,282 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Motor control loop synchronized with the PWM.
+//!
+//! This capsule ties together a center-aligned multi-phase PWM, phase
+//! current sampling triggered by the PWM timer, and an encoder. Each time the
+//! ADC completes the conversion of the phase currents, the capsule reads the
+//! encoder and calls a `ControlLoop` with the currents, the position and the
+//! setpoint of the application. The duty cycles it returns are applied at the
+//! next PWM period, so the loop runs once per period in interrupt context.
+//!
+//! The control loop is provided by the board, for example the field-oriented
+//! current loop in `foc`. Applications only start and stop the motor and
+//! change the setpoint, whose meaning depends on the control loop.
+//!
+//! The ADC trigger must be chosen so that the currents are sampled in the
+//! middle of the PWM period, when no transistor switches.
+//!
+//! Only one process can control the motor at a time. The motor is stopped if
+//! that process exits without stopping it.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Start the motor with a PWM frequency of `data` Hz. Returns `BUSY`
+//!   if another process controls the motor, `ALREADY` if it is running, and
+//!   `INVAL` if the frequency is not supported.
+//! - `2`: Stop the motor.
+//! - `3`: Set the setpoint to `data`, interpreted as an `i32`.
+//! - `4`: Get the position of the encoder, in counts.
+//! - `5`: Get the number of encoder counts per revolution.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let motor_control = components::motor_control::MotorControlComponent::new(
+//!     board_kernel,
+//!     capsules_extra::motor_control::driver::DRIVER_NUM,
+//!     phase_pwm,
+//!     adc,
+//!     encoder,
+//!     &CURRENT_CHANNELS,
+//!     stm32f429zi::adc::Trigger::Tim1Cc4,
+//! )
+//! .finalize(components::motor_control_component_static!(
+//!     PhasePwm,
+//!     stm32f429zi::adc::Adc<'static>,
+//!     Encoder
+//! ));
+//! motor_control.set_control_loop(current_loop);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::adc::{AdcTriggered, TriggeredClient};
+use kernel::hil::motor_control::{Encoder, PhasePwm};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::MotorControl as usize;
+
+/// Largest number of phases the capsule drives.
+pub const MAX_PHASES: usize = 4;
+
+/// Number of PWM periods between checks that the controlling process still
+/// exists.
+const OWNER_CHECK_PERIODS: u32 = 1024;
+
+/// The measurements given to the control loop at each PWM period.
+pub struct ControlInput<'b> {
+    /// The samples of the current channels, in the order the channels were
+    /// given to the capsule, left-justified.
+    pub currents: &'b [u16],
+    /// The position of the encoder, in counts.
+    pub position: u32,
+    /// The number of encoder counts in one revolution.
+    pub counts_per_revolution: u32,
+    /// The setpoint of the application.
+    pub setpoint: i32,
+}
+
+/// A control loop called once per PWM period.
+pub trait ControlLoop {
+    /// Compute the duty cycles of the next PWM period, as a portion of
+    /// `max_duty_cycle`. `duty_cycles` has one entry per phase and holds the
+    /// current duty cycles.
+    fn control(&self, input: &ControlInput, duty_cycles: &mut [usize], max_duty_cycle: usize);
+
+    /// Reset the state of the loop before the motor starts.
+    fn reset(&self);
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct MotorControl<'a, P: PhasePwm, A: AdcTriggered<'a>, E: Encoder> {
+    pwm: &'a P,
+    adc: &'a A,
+    encoder: &'a E,
+    channels: &'a [A::Channel],
+    trigger: A::Trigger,
+    control_loop: OptionalCell<&'a dyn ControlLoop>,
+    owner: OptionalCell<ProcessId>,
+    running: Cell<bool>,
+    setpoint: Cell<i32>,
+    duty_cycles: Cell<[usize; MAX_PHASES]>,
+    /// PWM periods since the owner was last checked.
+    periods: Cell<u32>,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, P: PhasePwm, A: AdcTriggered<'a>, E: Encoder> MotorControl<'a, P, A, E> {
+    /// `channels` are the ADC channels measuring the phase currents.
+    pub fn new(
+        pwm: &'a P,
+        adc: &'a A,
+        encoder: &'a E,
+        channels: &'a [A::Channel],
+        trigger: A::Trigger,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> MotorControl<'a, P, A, E> {
+        MotorControl {
+            pwm,
+            adc,
+            encoder,
+            channels,
+            trigger,
+            control_loop: OptionalCell::empty(),
+            owner: OptionalCell::empty(),
+            running: Cell::new(false),
+            setpoint: Cell::new(0),
+            duty_cycles: Cell::new([0; MAX_PHASES]),
+            periods: Cell::new(0),
+            apps: grant,
+        }
+    }
+
+    pub fn set_control_loop(&self, control_loop: &'a dyn ControlLoop) {
+        self.control_loop.set(control_loop);
+    }
+
+    fn phase_count(&self) -> usize {
+        self.pwm.phase_count().min(MAX_PHASES)
+    }
+
+    /// Whether a process other than `processid` controls the motor.
+    fn owned_by_other(&self, processid: ProcessId) -> bool {
+        self.owner.map_or(false, |owner| {
+            // The owner may have terminated without stopping the motor.
+            owner != processid && self.apps.enter(owner, |_, _| {}).is_ok()
+        })
+    }
+
+    fn start(&self, processid: ProcessId, frequency_hz: usize) -> Result<(), ErrorCode> {
+        if self.owned_by_other(processid) {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.running.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if frequency_hz == 0 || frequency_hz > self.pwm.get_maximum_frequency_hz() {
+            return Err(ErrorCode::INVAL);
+        }
+
+        self.control_loop.map(|control_loop| control_loop.reset());
+        self.duty_cycles
+            .set([self.pwm.get_maximum_duty_cycle() / 2; MAX_PHASES]);
+        self.pwm.start(frequency_hz)?;
+        if let Err(e) = self.adc.sample_triggered(self.channels, &self.trigger) {
+            let _ = self.pwm.stop();
+            return Err(e);
+        }
+
+        self.owner.set(processid);
+        self.periods.set(0);
+        self.running.set(true);
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.running.set(false);
+        self.owner.clear();
+        let _ = self.adc.stop_sampling();
+        let _ = self.pwm.stop();
+    }
+}
+
+impl<'a, P: PhasePwm, A: AdcTriggered<'a>, E: Encoder> TriggeredClient
+    for MotorControl<'a, P, A, E>
+{
+    fn samples_triggered(&self, samples: &[u16]) {
+        if !self.running.get() {
+            return;
+        }
+
+        let periods = self.periods.get() + 1;
+        if periods >= OWNER_CHECK_PERIODS {
+            self.periods.set(0);
+            let alive = self
+                .owner
+                .map_or(false, |owner| self.apps.enter(owner, |_, _| {}).is_ok());
+            if !alive {
+                self.stop();
+                return;
+            }
+        } else {
+            self.periods.set(periods);
+        }
+
+        let input = ControlInput {
+            currents: samples,
+            position: self.encoder.position(),
+            counts_per_revolution: self.encoder.counts_per_revolution(),
+            setpoint: self.setpoint.get(),
+        };
+        let phases = self.phase_count();
+        let mut duty_cycles = self.duty_cycles.get();
+        if let Some(control_loop) = self.control_loop.get() {
+            control_loop.control(
+                &input,
+                &mut duty_cycles[..phases],
+                self.pwm.get_maximum_duty_cycle(),
+            );
+        }
+        self.duty_cycles.set(duty_cycles);
+        let _ = self.pwm.set_duty_cycles(&duty_cycles[..phases]);
+    }
+}
+
+impl<'a, P: PhasePwm, A: AdcTriggered<'a>, E: Encoder> SyscallDriver for MotorControl<'a, P, A, E> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.start(processid, data).into(),
+
+            2 => {
+                if self.owner.contains(&processid) {
+                    self.stop();
+                    CommandReturn::success()
+                } else {
+                    CommandReturn::failure(ErrorCode::RESERVE)
+                }
+            }
+
+            3 => {
+                if self.owned_by_other(processid) {
+                    CommandReturn::failure(ErrorCode::BUSY)
+                } else {
+                    self.setpoint.set(data as i32);
+                    CommandReturn::success()
+                }
+            }
+
+            4 => CommandReturn::success_u32(self.encoder.position()),
+
+            5 => CommandReturn::success_u32(self.encoder.counts_per_revolution()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
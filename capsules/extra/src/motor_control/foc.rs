@@ -0,0 +1,304 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fixed-point building blocks for field-oriented control.
+//!
+//! Values are in Q15: `32767` is close to 1.0 and `-32768` is -1.0. Angles
+//! are electrical angles where a full turn is 65536, so they wrap naturally.
+//!
+//! A current loop built from these blocks runs, once per PWM period:
+//!
+//! ```text
+//! (alpha, beta) = clarke(ia, ib)
+//! (d, q)        = park(alpha, beta, sin_cos(angle))
+//! vd, vq        = PI controllers on (id_ref - d, iq_ref - q)
+//! (alpha, beta) = inverse_park(vd, vq, sin_cos(angle))
+//! duty cycles   = space_vector(alpha, beta, max_duty_cycle)
+//! ```
+//!
+//! `CurrentLoop` implements it as a `ControlLoop` for the motor control
+//! driver.
+
+use core::cell::Cell;
+
+use super::driver::{ControlInput, ControlLoop};
+
+/// Quarter sine wave, `sin(i * 90° / 64)` in Q15.
+const SINE: [i16; 65] = [
+    0, 804, 1608, 2411, 3212, 4011, 4808, 5602, 6393, 7180, 7962, 8740, 9512, 10279, 11039, 11793,
+    12540, 13279, 14010, 14733, 15447, 16151, 16846, 17531, 18205, 18868, 19520, 20160, 20788,
+    21403, 22006, 22595, 23170, 23732, 24279, 24812, 25330, 25833, 26320, 26791, 27246, 27684,
+    28106, 28511, 28899, 29269, 29622, 29957, 30274, 30572, 30853, 31114, 31357, 31581, 31786,
+    31972, 32138, 32286, 32413, 32522, 32610, 32679, 32729, 32758, 32767,
+];
+
+/// 1/sqrt(3) in Q15.
+const INV_SQRT3: i32 = 18919;
+/// sqrt(3)/2 in Q15.
+const SQRT3_2: i32 = 28378;
+
+fn saturate(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+/// Multiply two Q15 values.
+fn mul(a: i32, b: i32) -> i32 {
+    (a * b) >> 15
+}
+
+/// Return the sine of `angle`, interpolated from a table.
+pub fn sin(angle: u16) -> i16 {
+    let quadrant = angle >> 14;
+    // Position in the quadrant, in 1/256 of a table step.
+    let mut position = (angle & 0x3fff) as i32;
+    if quadrant & 1 == 1 {
+        position = 0x4000 - position;
+    }
+    let index = (position >> 8) as usize;
+    let fraction = position & 0xff;
+    let low = SINE[index] as i32;
+    let value = match SINE.get(index + 1) {
+        Some(&high) => low + (((high as i32 - low) * fraction) >> 8),
+        None => low,
+    };
+    if quadrant >= 2 {
+        -value as i16
+    } else {
+        value as i16
+    }
+}
+
+/// Return the sine and cosine of `angle`.
+pub fn sin_cos(angle: u16) -> (i16, i16) {
+    (sin(angle), sin(angle.wrapping_add(0x4000)))
+}
+
+/// Convert the currents of phases a and b of a balanced three-phase system to
+/// the stationary (alpha, beta) frame.
+pub fn clarke(a: i16, b: i16) -> (i16, i16) {
+    let beta = mul(a as i32 + 2 * b as i32, INV_SQRT3);
+    (a, saturate(beta))
+}
+
+/// Rotate (alpha, beta) into the (d, q) frame of the rotor.
+pub fn park(alpha: i16, beta: i16, (sin, cos): (i16, i16)) -> (i16, i16) {
+    let (alpha, beta, sin, cos) = (alpha as i32, beta as i32, sin as i32, cos as i32);
+    let d = mul(alpha, cos) + mul(beta, sin);
+    let q = mul(beta, cos) - mul(alpha, sin);
+    (saturate(d), saturate(q))
+}
+
+/// Rotate (d, q) back into the stationary (alpha, beta) frame.
+pub fn inverse_park(d: i16, q: i16, (sin, cos): (i16, i16)) -> (i16, i16) {
+    let (d, q, sin, cos) = (d as i32, q as i32, sin as i32, cos as i32);
+    let alpha = mul(d, cos) - mul(q, sin);
+    let beta = mul(d, sin) + mul(q, cos);
+    (saturate(alpha), saturate(beta))
+}
+
+/// Convert a voltage vector in the (alpha, beta) frame to the duty cycles of
+/// a three-phase bridge, with `max_duty_cycle` representing 100%.
+///
+/// The voltages are relative to half the bus voltage. The common-mode
+/// voltage is centered (min-max injection), which is equivalent to space
+/// vector modulation and reaches 15% more voltage than sine modulation.
+pub fn space_vector(alpha: i16, beta: i16, max_duty_cycle: usize) -> [usize; 3] {
+    let (alpha, beta) = (alpha as i32, beta as i32);
+    let a = alpha;
+    let b = -alpha / 2 + mul(beta, SQRT3_2);
+    let c = -alpha / 2 - mul(beta, SQRT3_2);
+    let offset = (a.max(b).max(c) + a.min(b).min(c)) / 2;
+
+    let max = max_duty_cycle as i64;
+    [a, b, c].map(|phase| {
+        let duty = max / 2 + (phase - offset) as i64 * max / 65536;
+        duty.clamp(0, max) as usize
+    })
+}
+
+/// Return the electrical angle of a motor with `pole_pairs` pole pairs from
+/// the position of an encoder on its shaft. `offset` is the electrical angle
+/// at position 0.
+pub fn electrical_angle(
+    position: u32,
+    counts_per_revolution: u32,
+    pole_pairs: u32,
+    offset: u16,
+) -> u16 {
+    if counts_per_revolution == 0 {
+        return offset;
+    }
+    let angle = (position as u64 * pole_pairs as u64 * 65536) / counts_per_revolution as u64;
+    (angle as u16).wrapping_add(offset)
+}
+
+/// Proportional-integral controller with output clamping and anti-windup.
+#[derive(Clone, Copy)]
+pub struct PiController {
+    /// Proportional gain, in Q15.
+    kp: i32,
+    /// Integral gain per period, in Q15.
+    ki: i32,
+    limit: i16,
+    integral: i32,
+}
+
+impl PiController {
+    pub const fn new(kp: i16, ki: i16, limit: i16) -> PiController {
+        PiController {
+            kp: kp as i32,
+            ki: ki as i32,
+            limit,
+            integral: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0;
+    }
+
+    /// Return the output for `error`, and integrate it unless the output is
+    /// saturated.
+    pub fn update(&mut self, error: i16) -> i16 {
+        let limit = self.limit as i32;
+        let proportional = mul(self.kp, error as i32);
+        let integral = self.integral + mul(self.ki, error as i32);
+        let output = proportional + integral;
+        if output.abs() <= limit {
+            self.integral = integral;
+        }
+        (proportional + self.integral).clamp(-limit, limit) as i16
+    }
+}
+
+/// Field-oriented current loop for a three-phase motor.
+///
+/// The first two current samples are the currents of phases a and b. The
+/// setpoint is the torque-producing (q axis) current in Q15, and the d axis
+/// current is regulated to zero.
+pub struct CurrentLoop {
+    pole_pairs: u32,
+    angle_offset: u16,
+    /// ADC sample corresponding to zero current.
+    current_zero: u16,
+    d: Cell<PiController>,
+    q: Cell<PiController>,
+}
+
+impl CurrentLoop {
+    pub const fn new(
+        pole_pairs: u32,
+        angle_offset: u16,
+        current_zero: u16,
+        d: PiController,
+        q: PiController,
+    ) -> CurrentLoop {
+        CurrentLoop {
+            pole_pairs,
+            angle_offset,
+            current_zero,
+            d: Cell::new(d),
+            q: Cell::new(q),
+        }
+    }
+
+    fn current(&self, sample: u16) -> i16 {
+        saturate(sample as i32 - self.current_zero as i32)
+    }
+}
+
+impl ControlLoop for CurrentLoop {
+    fn control(&self, input: &ControlInput, duty_cycles: &mut [usize], max_duty_cycle: usize) {
+        if input.currents.len() < 2 || duty_cycles.len() < 3 {
+            return;
+        }
+
+        let angle = sin_cos(electrical_angle(
+            input.position,
+            input.counts_per_revolution,
+            self.pole_pairs,
+            self.angle_offset,
+        ));
+        let (alpha, beta) = clarke(
+            self.current(input.currents[0]),
+            self.current(input.currents[1]),
+        );
+        let (d, q) = park(alpha, beta, angle);
+
+        let mut d_controller = self.d.get();
+        let mut q_controller = self.q.get();
+        let vd = d_controller.update(saturate(-(d as i32)));
+        let vq = q_controller.update(saturate(input.setpoint.clamp(-32768, 32767) - q as i32));
+        self.d.set(d_controller);
+        self.q.set(q_controller);
+
+        let (alpha, beta) = inverse_park(vd, vq, angle);
+        duty_cycles[..3].copy_from_slice(&space_vector(alpha, beta, max_duty_cycle));
+    }
+
+    fn reset(&self) {
+        for controller in [&self.d, &self.q] {
+            let mut pi = controller.get();
+            pi.reset();
+            controller.set(pi);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_quadrants() {
+        assert_eq!(sin(0), 0);
+        assert_eq!(sin(0x4000), 32767);
+        assert_eq!(sin(0x8000), 0);
+        assert_eq!(sin(0xc000), -32767);
+        // sin(30°) = 0.5
+        assert!((sin(0x1555) as i32 - 16384).abs() < 16);
+        assert_eq!(sin_cos(0), (0, 32767));
+    }
+
+    #[test]
+    fn park_round_trip() {
+        let angle = sin_cos(0x2345);
+        let (alpha, beta) = clarke(10000, -3000);
+        let (d, q) = park(alpha, beta, angle);
+        let (alpha2, beta2) = inverse_park(d, q, angle);
+        assert!((alpha2 as i32 - alpha as i32).abs() < 8);
+        assert!((beta2 as i32 - beta as i32).abs() < 8);
+    }
+
+    #[test]
+    fn electrical_angle_pole_pairs() {
+        assert_eq!(electrical_angle(0, 4096, 7, 0x1000), 0x1000);
+        // A quarter turn of a 4 pole pair motor is one electrical turn.
+        assert_eq!(electrical_angle(1024, 4096, 4, 0), 0);
+        assert_eq!(electrical_angle(512, 4096, 4, 0), 0x8000);
+    }
+
+    #[test]
+    fn space_vector_centered() {
+        assert_eq!(space_vector(0, 0, 1000), [500, 500, 500]);
+        let duty = space_vector(16384, 0, 1000);
+        assert!(duty[0] > 500 && duty[1] < 500 && duty[2] < 500);
+        // The common mode stays centered.
+        assert_eq!(
+            duty.iter().max().unwrap() + duty.iter().min().unwrap(),
+            1000
+        );
+    }
+
+    #[test]
+    fn pi_anti_windup() {
+        let mut pi = PiController::new(16384, 1024, 1000);
+        for _ in 0..1000 {
+            assert_eq!(pi.update(10000), 1000);
+        }
+        // The integral did not wind up, so the output follows a sign change
+        // immediately.
+        assert!(pi.update(-10000) < 0);
+    }
+}
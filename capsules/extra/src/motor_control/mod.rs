@@ -0,0 +1,8 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Motor control with a control loop running at the PWM frequency.
+
+pub mod driver;
+pub mod foc;
@@ -56,7 +56,6 @@
 
 use core::cell::Cell;
 use core::ops::{Index, IndexMut};
-use kernel::debug;
 use kernel::hil;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::OptionalCell;
@@ -378,9 +377,12 @@ impl<
             State::ReadId => {
                 self.txbuffer.replace(write_buffer);
                 read_buffer.map(|read_buffer| {
-                    debug!(
-                        "id 0x{:02x}{:02x}{:02x}",
-                        read_buffer[1], read_buffer[2], read_buffer[3]
+                    kernel::log_info!(
+                        Storage,
+                        "MX25R6435F id 0x{:02x}{:02x}{:02x}",
+                        read_buffer[1],
+                        read_buffer[2],
+                        read_buffer[3]
                     );
                     self.rxbuffer.replace(read_buffer);
                 });
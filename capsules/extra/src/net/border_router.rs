@@ -0,0 +1,396 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Minimal border router between a 6LoWPAN mesh and an external interface.
+//!
+//! The border router forwards IPv6 packets between the 6LoWPAN network, for
+//! example a Thread network, and a secondary interface implementing
+//! [`IP6Interface`], such as [`Slip`](crate::net::slip::Slip) or an
+//! Ethernet driver:
+//!
+//! - packets received from the mesh whose destination is off-mesh, i.e. not
+//!   in the mesh prefix, not link-local and not multicast, are sent on the
+//!   external interface;
+//! - packets received from the external interface whose destination is in
+//!   the mesh prefix are sent on the mesh.
+//!
+//! The hop limit of forwarded packets is decremented, and packets whose hop
+//! limit expires are dropped. Packets are also dropped, rather than queued,
+//! when the outgoing interface is busy.
+//!
+//! A [`PrefixTranslation`] hook can rewrite the addresses of forwarded
+//! packets, for example to map the mesh prefix to a prefix routed to the
+//! border router by the external network ([`PrefixMapping`], as in NPTv6),
+//! or to map destinations of a NAT64 prefix such as `64:ff9b::/96`. The
+//! UDP, TCP and ICMPv6 checksums of packets sent on the external interface
+//! are updated after the translation.
+//!
+//! Only UDP and ICMPv6 packets can be sent on the mesh, as the 6LoWPAN
+//! sender does not support other transport protocols. The border router
+//! does not send Router Advertisements, so mesh nodes must use it as their
+//! default gateway.
+
+use core::cell::Cell;
+
+use crate::net::icmpv6::ICMP6Header;
+use crate::net::ipv6::ip_utils::{compute_ipv6_ph_sum, ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::UDPHeader;
+
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Length of the IPv6 header.
+const IP6_HDR_LEN: usize = 40;
+
+/// A network interface carrying raw IPv6 packets.
+pub trait IP6Interface<'a> {
+    fn set_client(&self, client: &'a dyn IP6InterfaceClient);
+
+    /// Send the IPv6 `packet`, which is copied before returning. Returns
+    /// `BUSY` if a packet is being sent, and `SIZE` if `packet` is too large.
+    fn send(&self, packet: &[u8]) -> Result<(), ErrorCode>;
+}
+
+/// Client of an [`IP6Interface`].
+pub trait IP6InterfaceClient {
+    /// An IPv6 packet was received.
+    fn packet_received(&self, packet: &[u8]);
+
+    /// The packet passed to `send()` was sent.
+    fn send_done(&self, result: Result<(), ErrorCode>);
+}
+
+/// Hook translating the addresses of forwarded packets.
+pub trait PrefixTranslation {
+    /// Translate a packet leaving the mesh. Returns `false` to drop it.
+    fn to_external(&self, header: &mut IP6Header) -> bool;
+
+    /// Translate a packet entering the mesh. Returns `false` to drop it.
+    fn to_mesh(&self, header: &mut IP6Header) -> bool;
+}
+
+/// Whether the first `prefix_len` bits of `addr` and `prefix` are equal.
+pub fn has_prefix(addr: &IPAddr, prefix: &IPAddr, prefix_len: u8) -> bool {
+    let prefix_len = (prefix_len as usize).min(128);
+    let full_bytes = prefix_len / 8;
+    let remainder_bits = prefix_len % 8;
+    if addr.0[..full_bytes] != prefix.0[..full_bytes] {
+        false
+    } else if remainder_bits == 0 {
+        true
+    } else {
+        let mask = 0xffu8 << (8 - remainder_bits);
+        addr.0[full_bytes] & mask == prefix.0[full_bytes] & mask
+    }
+}
+
+/// Translation of the mesh prefix to an external prefix of the same
+/// length: the source addresses of packets leaving the mesh and the
+/// destination addresses of packets entering it are rewritten.
+pub struct PrefixMapping {
+    mesh_prefix: IPAddr,
+    external_prefix: IPAddr,
+    prefix_len: u8,
+}
+
+impl PrefixMapping {
+    pub fn new(mesh_prefix: IPAddr, external_prefix: IPAddr, prefix_len: u8) -> PrefixMapping {
+        PrefixMapping {
+            mesh_prefix,
+            external_prefix,
+            prefix_len,
+        }
+    }
+}
+
+impl PrefixTranslation for PrefixMapping {
+    fn to_external(&self, header: &mut IP6Header) -> bool {
+        if has_prefix(&header.src_addr, &self.mesh_prefix, self.prefix_len) {
+            header
+                .src_addr
+                .set_prefix(&self.external_prefix.0, self.prefix_len);
+        }
+        true
+    }
+
+    fn to_mesh(&self, header: &mut IP6Header) -> bool {
+        if has_prefix(&header.dst_addr, &self.external_prefix, self.prefix_len) {
+            header
+                .dst_addr
+                .set_prefix(&self.mesh_prefix.0, self.prefix_len);
+        }
+        true
+    }
+}
+
+/// Compute the UDP, TCP or ICMPv6 checksum of the transport `payload` of a
+/// packet, whose checksum field must be zero.
+fn transport_checksum(header: &IP6Header, payload: &[u8]) -> u16 {
+    let mut sum = compute_ipv6_ph_sum(header);
+    for chunk in payload.chunks(2) {
+        sum += ((chunk[0] as u32) << 8) | chunk.get(1).copied().unwrap_or(0) as u32;
+    }
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    !(sum as u16)
+}
+
+/// Update the transport checksum of `payload` after the addresses of
+/// `header` changed.
+fn update_transport_checksum(header: &IP6Header, payload: &mut [u8]) {
+    let offset = match header.get_next_header() {
+        ip6_nh::UDP => 6,
+        ip6_nh::TCP => 16,
+        ip6_nh::ICMP => 2,
+        _ => return,
+    };
+    if payload.len() < offset + 2 {
+        return;
+    }
+    payload[offset..offset + 2].copy_from_slice(&[0, 0]);
+    let mut checksum = transport_checksum(header, payload);
+    if checksum == 0 && header.get_next_header() == ip6_nh::UDP {
+        // A zero UDP checksum means that the checksum is absent.
+        checksum = 0xffff;
+    }
+    payload[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+}
+
+pub struct BorderRouter<'a, S: IP6Sender<'a>> {
+    mesh: &'a S,
+    external: &'a dyn IP6Interface<'a>,
+    mesh_prefix: IPAddr,
+    mesh_prefix_len: u8,
+    translation: OptionalCell<&'a dyn PrefixTranslation>,
+    net_cap: &'static NetworkCapability,
+    /// Buffer to build the forwarded packets. Its size also bounds the
+    /// payloads forwarded to the mesh, so it must not be larger than the
+    /// payload buffer of the mesh sender.
+    buffer: TakeCell<'static, [u8]>,
+    mesh_busy: Cell<bool>,
+}
+
+impl<'a, S: IP6Sender<'a>> BorderRouter<'a, S> {
+    pub fn new(
+        mesh: &'a S,
+        external: &'a dyn IP6Interface<'a>,
+        mesh_prefix: IPAddr,
+        mesh_prefix_len: u8,
+        net_cap: &'static NetworkCapability,
+        buffer: &'static mut [u8],
+    ) -> BorderRouter<'a, S> {
+        BorderRouter {
+            mesh,
+            external,
+            mesh_prefix,
+            mesh_prefix_len,
+            translation: OptionalCell::empty(),
+            net_cap,
+            buffer: TakeCell::new(buffer),
+            mesh_busy: Cell::new(false),
+        }
+    }
+
+    /// Set the hook translating the addresses of forwarded packets.
+    pub fn set_translation(&self, translation: &'a dyn PrefixTranslation) {
+        self.translation.set(translation);
+    }
+
+    fn is_on_mesh(&self, addr: &IPAddr) -> bool {
+        has_prefix(addr, &self.mesh_prefix, self.mesh_prefix_len)
+    }
+
+    /// Decrement the hop limit of a forwarded packet. Returns `false` if the
+    /// packet must be dropped.
+    fn decrement_hop_limit(header: &mut IP6Header) -> bool {
+        // TODO: send an ICMPv6 Time Exceeded message.
+        if header.get_hop_limit() <= 1 {
+            return false;
+        }
+        header.set_hop_limit(header.get_hop_limit() - 1);
+        true
+    }
+
+    fn forward_to_external(&self, mut header: IP6Header, payload: &[u8]) {
+        if !Self::decrement_hop_limit(&mut header) {
+            return;
+        }
+        let (src_addr, dst_addr) = (header.src_addr, header.dst_addr);
+        if !self
+            .translation
+            .map_or(true, |translation| translation.to_external(&mut header))
+        {
+            return;
+        }
+        let translated = header.src_addr != src_addr || header.dst_addr != dst_addr;
+
+        self.buffer.map(|buffer| {
+            let len = IP6_HDR_LEN + payload.len();
+            if len > buffer.len() || header.encode(buffer).done().is_none() {
+                return;
+            }
+            buffer[IP6_HDR_LEN..len].copy_from_slice(payload);
+            if translated {
+                update_transport_checksum(&header, &mut buffer[IP6_HDR_LEN..len]);
+            }
+            let _ = self.external.send(&buffer[..len]);
+        });
+    }
+
+    fn forward_to_mesh(&self, packet: &[u8]) -> Result<(), ErrorCode> {
+        if self.mesh_busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let (offset, mut header) = IP6Header::decode(packet).done().ok_or(ErrorCode::INVAL)?;
+        let len = offset + header.get_payload_len() as usize;
+        if len > packet.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        if !Self::decrement_hop_limit(&mut header) {
+            return Err(ErrorCode::FAIL);
+        }
+        if !self
+            .translation
+            .map_or(true, |translation| translation.to_mesh(&mut header))
+        {
+            return Err(ErrorCode::FAIL);
+        }
+        if !self.is_on_mesh(&header.dst_addr) {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let transport = &packet[offset..len];
+        let (transport_header, header_len) = match header.get_next_header() {
+            ip6_nh::UDP => {
+                let (header_len, udp_header) = UDPHeader::decode(transport)
+                    .done()
+                    .ok_or(ErrorCode::INVAL)?;
+                (TransportHeader::UDP(udp_header), header_len)
+            }
+            ip6_nh::ICMP => {
+                let (header_len, icmp_header) = ICMP6Header::decode(transport)
+                    .done()
+                    .ok_or(ErrorCode::INVAL)?;
+                (TransportHeader::ICMP(icmp_header), header_len)
+            }
+            _ => return Err(ErrorCode::NOSUPPORT),
+        };
+        let data = &transport[header_len..];
+
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        if data.len() > buffer.len() {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[..data.len()].copy_from_slice(data);
+        let mut payload = SubSliceMut::new(buffer);
+        payload.slice(..data.len());
+
+        // The sender may complete synchronously, so mark it busy first.
+        self.mesh_busy.set(true);
+        let result = self
+            .mesh
+            .forward(header, transport_header, &payload, self.net_cap);
+        if result.is_err() {
+            self.mesh_busy.set(false);
+        }
+        self.buffer.replace(payload.take());
+        result
+    }
+}
+
+impl<'a, S: IP6Sender<'a>> IP6RecvClient for BorderRouter<'a, S> {
+    fn receive(&self, header: IP6Header, payload: &[u8]) {
+        let dst_addr = header.get_dst_addr();
+        if dst_addr.is_multicast() || dst_addr.is_unicast_link_local() || self.is_on_mesh(&dst_addr)
+        {
+            // Not forwarded: handled by the local IPv6 stack.
+            return;
+        }
+        self.forward_to_external(header, payload);
+    }
+}
+
+impl<'a, S: IP6Sender<'a>> IP6SendClient for BorderRouter<'a, S> {
+    fn send_done(&self, result: Result<(), ErrorCode>) {
+        self.mesh_busy.set(false);
+        if result.is_err() {
+            kernel::log_warn!(
+                Net,
+                "Border router: forwarding to mesh failed: {:?}",
+                result
+            );
+        }
+    }
+}
+
+impl<'a, S: IP6Sender<'a>> IP6InterfaceClient for BorderRouter<'a, S> {
+    fn packet_received(&self, packet: &[u8]) {
+        // Packets that cannot be forwarded are dropped.
+        let _ = self.forward_to_mesh(packet);
+    }
+
+    fn send_done(&self, _result: Result<(), ErrorCode>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESH: IPAddr = IPAddr([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    const EXTERNAL: IPAddr = IPAddr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    #[test]
+    fn prefix_match() {
+        let mut addr = MESH;
+        addr.0[15] = 1;
+        assert!(has_prefix(&addr, &MESH, 64));
+        assert!(!has_prefix(&EXTERNAL, &MESH, 64));
+        addr.0[0] = 0xfc;
+        assert!(has_prefix(&addr, &MESH, 7));
+        assert!(!has_prefix(&addr, &MESH, 8));
+        assert!(has_prefix(&EXTERNAL, &MESH, 0));
+    }
+
+    #[test]
+    fn prefix_mapping() {
+        let mapping = PrefixMapping::new(MESH, EXTERNAL, 64);
+        let mut header = IP6Header::new();
+        header.src_addr = MESH;
+        header.src_addr.0[15] = 1;
+        header.dst_addr = EXTERNAL;
+        header.dst_addr.0[15] = 2;
+        assert!(mapping.to_external(&mut header));
+        assert_eq!(header.src_addr.0[..8], EXTERNAL.0[..8]);
+        assert_eq!(header.src_addr.0[15], 1);
+        assert_eq!(header.dst_addr.0[..8], EXTERNAL.0[..8]);
+
+        core::mem::swap(&mut header.src_addr, &mut header.dst_addr);
+        assert!(mapping.to_mesh(&mut header));
+        assert_eq!(header.dst_addr.0[..8], MESH.0[..8]);
+        assert_eq!(header.dst_addr.0[15], 1);
+        assert_eq!(header.src_addr.0[..8], EXTERNAL.0[..8]);
+    }
+
+    #[test]
+    fn checksum_update() {
+        let mut header = IP6Header::new();
+        header.src_addr = MESH;
+        header.dst_addr = EXTERNAL;
+        header.set_next_header(ip6_nh::UDP);
+        header.set_payload_len(11);
+        // UDP header and an odd-sized payload.
+        let mut payload = [0x12, 0x34, 0x00, 0x35, 0x00, 0x0b, 0xaa, 0xaa, 1, 2, 3];
+        update_transport_checksum(&header, &mut payload);
+        assert_ne!(payload[6..8], [0xaa, 0xaa]);
+        // The checksum of a packet with a valid checksum is zero.
+        assert_eq!(transport_checksum(&header, &payload), 0);
+    }
+}
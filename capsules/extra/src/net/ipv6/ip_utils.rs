@@ -33,6 +33,12 @@ pub mod ip6_nh {
 #[derive(Copy, Clone, Debug)]
 pub struct IPAddr(pub [u8; 16]);
 
+/// The link-local all-nodes multicast address (ff02::1), of which every node
+/// is a member.
+pub const ALL_NODES_MULTICAST: IPAddr = IPAddr([
+    0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+]);
+
 impl PartialEq for IPAddr {
     fn eq(&self, other: &IPAddr) -> bool {
         self.0 == other.0
@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+use crate::net::ipv6::ip_utils::{IPAddr, ALL_NODES_MULTICAST};
 use crate::net::ipv6::IP6Header;
 use crate::net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
 
-use kernel::debug;
+use core::cell::Cell;
+
 use kernel::utilities::cells::OptionalCell;
 use kernel::ErrorCode;
 
@@ -32,8 +34,17 @@ use kernel::ErrorCode;
   packets up to userland.
 */
 
+/// Maximum number of multicast groups joined by the kernel.
+pub const MAX_MULTICAST_GROUPS: usize = 4;
+
 pub trait IP6RecvClient {
     fn receive(&self, header: IP6Header, payload: &[u8]);
+
+    /// Whether the client wants to receive the packets sent to the
+    /// multicast `group`, for example because an application joined it.
+    fn is_multicast_listener(&self, _group: IPAddr) -> bool {
+        false
+    }
 }
 
 /// Currently only one implementation of this trait should exist,
@@ -45,8 +56,29 @@ pub trait IP6Receiver<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient);
 }
 
+/// Multicast group membership of the IPv6 layer.
+///
+/// This is a minimal version of MLD (RFC 3810): the receiver only keeps the
+/// list of joined groups to filter received packets, and sends no listener
+/// report, as 6LoWPAN delivers multicast packets in link-layer broadcast
+/// frames. The all-nodes group is always joined.
+pub trait IP6MulticastGroups {
+    /// Join the multicast `group`. Joins are counted, so a group joined twice
+    /// must be left twice. Returns `INVAL` if `group` is not a multicast
+    /// address, and `NOMEM` if too many groups are joined.
+    fn join_group(&self, group: IPAddr) -> Result<(), ErrorCode>;
+
+    /// Leave the multicast `group`. Returns `INVAL` if it was not joined.
+    fn leave_group(&self, group: IPAddr) -> Result<(), ErrorCode>;
+
+    /// Whether packets sent to the multicast `group` are received.
+    fn is_group_member(&self, group: IPAddr) -> bool;
+}
+
 pub struct IP6RecvStruct<'a> {
     client: OptionalCell<&'a dyn IP6RecvClient>,
+    /// Multicast groups joined by the kernel, with their join count.
+    groups: [Cell<Option<(IPAddr, usize)>>; MAX_MULTICAST_GROUPS],
 }
 
 impl<'a> IP6Receiver<'a> for IP6RecvStruct<'a> {
@@ -59,7 +91,53 @@ impl<'a> IP6RecvStruct<'a> {
     pub fn new() -> IP6RecvStruct<'a> {
         IP6RecvStruct {
             client: OptionalCell::empty(),
+            groups: Default::default(),
+        }
+    }
+
+    fn find_group(&self, group: IPAddr) -> Option<&Cell<Option<(IPAddr, usize)>>> {
+        self.groups
+            .iter()
+            .find(|slot| slot.get().map_or(false, |(addr, _)| addr == group))
+    }
+}
+
+impl<'a> IP6MulticastGroups for IP6RecvStruct<'a> {
+    fn join_group(&self, group: IPAddr) -> Result<(), ErrorCode> {
+        if !group.is_multicast() {
+            return Err(ErrorCode::INVAL);
         }
+        if let Some(slot) = self.find_group(group) {
+            slot.set(slot.get().map(|(addr, count)| (addr, count + 1)));
+            return Ok(());
+        }
+        let slot = self
+            .groups
+            .iter()
+            .find(|slot| slot.get().is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        slot.set(Some((group, 1)));
+        Ok(())
+    }
+
+    fn leave_group(&self, group: IPAddr) -> Result<(), ErrorCode> {
+        let slot = self.find_group(group).ok_or(ErrorCode::INVAL)?;
+        slot.set(slot.get().and_then(|(addr, count)| {
+            if count > 1 {
+                Some((addr, count - 1))
+            } else {
+                None
+            }
+        }));
+        Ok(())
+    }
+
+    fn is_group_member(&self, group: IPAddr) -> bool {
+        group == ALL_NODES_MULTICAST
+            || self.find_group(group).is_some()
+            || self
+                .client
+                .map_or(false, |client| client.is_multicast_listener(group))
     }
 }
 
@@ -73,17 +151,23 @@ impl<'a> SixlowpanRxClient for IP6RecvStruct<'a> {
             Some((offset, ip6_header)) => {
                 let checksum_result = ip6_header.check_transport_checksum(&buf[offset..len]);
                 if checksum_result == Err(ErrorCode::FAIL) {
-                    debug!("cksum fail!: {:?}", checksum_result);
+                    kernel::log_warn!(Net, "IPv6: checksum failed: {:?}", checksum_result);
                     return; //Dropped.
                 }
                 // Note: Protocols for which checksum verification is not implemented (TCP, etc.)
                 // are automatically assumed as fine, rather than dropped
 
+                let dst_addr = ip6_header.get_dst_addr();
+                if dst_addr.is_multicast() && !self.is_group_member(dst_addr) {
+                    // Dropped: no one listens to this multicast group.
+                    return;
+                }
+
                 self.client
                     .map(|client| client.receive(ip6_header, &buf[offset..len]));
             }
             None => {
-                debug!("failed to decode ipv6 header");
+                kernel::log_warn!(Net, "IPv6: failed to decode header");
                 // TODO: Report the error somewhere...
             }
         }
@@ -30,7 +30,6 @@ use crate::net::thread::thread_utils::{mac_from_ipv6, MULTICAST_IPV6};
 
 use core::cell::Cell;
 
-use kernel::debug;
 use kernel::hil::time::{self, ConvertTicks};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
@@ -93,6 +92,24 @@ pub trait IP6Sender<'a> {
         payload: &SubSliceMut<'static, u8>,
         net_cap: &'static NetworkCapability,
     ) -> Result<(), ErrorCode>;
+
+    /// This method sends a packet that is forwarded from another interface.
+    /// Unlike `send_to`, the source address, hop limit, traffic class and
+    /// flow label of the provided `IP6Header` are kept. The next header,
+    /// payload length and transport checksum are set from the
+    /// `transport_header` and `payload`.
+    ///
+    /// # Arguments
+    /// `ip6_header` - The `IP6Header` of the forwarded packet
+    /// `transport_header` - The `TransportHeader` of the forwarded packet
+    /// `payload` - The transport payload of the forwarded packet
+    fn forward(
+        &self,
+        ip6_header: IP6Header,
+        transport_header: TransportHeader,
+        payload: &SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode>;
 }
 
 /// This struct is a specific implementation of the `IP6Sender` trait. This
@@ -143,22 +160,7 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
             return Err(ErrorCode::FAIL);
         }
 
-        // This logic is used to update the dst mac address
-        // the given packet should be sent to. This complies
-        // with the manner in which Thread addresses packets,
-        // but may conflict with some other or future protocol
-        // that sits above and uses IPV6
-        let dst_mac_addr;
-        if dst == MULTICAST_IPV6 {
-            // use short multicast ipv6 for dst mac address
-            dst_mac_addr = MacAddress::Short(0xFFFF)
-        } else if dst.0[0..8] == [0xfe, 0x80, 0, 0, 0, 0, 0, 0] {
-            // ipv6 address is of form fe80::MAC; use mac_from_ipv6
-            // helper function to determine ipv6 to send to
-            dst_mac_addr = MacAddress::Long(mac_from_ipv6(dst))
-        } else {
-            dst_mac_addr = self.dst_mac_addr;
-        }
+        let dst_mac_addr = self.dst_mac_addr(dst);
 
         // TODO: add error handling here
         let _ = self
@@ -169,6 +171,32 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         let ret = self.send_next_fragment();
         ret
     }
+
+    fn forward(
+        &self,
+        ip6_header: IP6Header,
+        transport_header: TransportHeader,
+        payload: &SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode> {
+        let dst = ip6_header.get_dst_addr();
+        if !net_cap.remote_addr_valid(dst, self.ip_vis) {
+            return Err(ErrorCode::FAIL);
+        }
+        let dst_mac_addr = self.dst_mac_addr(dst);
+
+        // The payload stays with the caller, so it gets its buffer back with
+        // the error if 6LoWPAN is still sending a packet.
+        self.sixlowpan
+            .init(self.src_mac_addr, dst_mac_addr, self.radio.get_pan(), None)?;
+
+        self.ip6_packet.map(|ip6_packet| {
+            ip6_packet.header = ip6_header;
+            ip6_packet.set_payload(transport_header, payload);
+            ip6_packet.set_transport_checksum();
+        });
+        self.send_next_fragment()
+    }
 }
 
 impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
@@ -197,6 +225,24 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
         }
     }
 
+    fn dst_mac_addr(&self, dst: IPAddr) -> MacAddress {
+        // This logic is used to update the dst mac address
+        // the given packet should be sent to. This complies
+        // with the manner in which Thread addresses packets,
+        // but may conflict with some other or future protocol
+        // that sits above and uses IPV6
+        if dst == MULTICAST_IPV6 {
+            // use short multicast ipv6 for dst mac address
+            MacAddress::Short(0xFFFF)
+        } else if dst.0[0..8] == [0xfe, 0x80, 0, 0, 0, 0, 0, 0] {
+            // ipv6 address is of form fe80::MAC; use mac_from_ipv6
+            // helper function to determine ipv6 to send to
+            MacAddress::Long(mac_from_ipv6(dst))
+        } else {
+            self.dst_mac_addr
+        }
+    }
+
     fn init_packet(
         &self,
         dst_addr: IPAddr,
@@ -205,7 +251,7 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
     ) {
         self.ip6_packet.map_or_else(
             || {
-                debug!("init packet failed.");
+                kernel::log_error!(Net, "IPv6: init packet failed");
             },
             |ip6_packet| {
                 ip6_packet.header = IP6Header::default();
@@ -252,7 +298,7 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
                     }
                 }
                 None => {
-                    debug!("Missing tx_buf");
+                    kernel::log_error!(Net, "IPv6: missing tx_buf");
                     (Err(ErrorCode::BUSY), false)
                 }
             })
@@ -284,7 +330,7 @@ impl<'a, A: time::Alarm<'a>> TxClient for IP6SendStruct<'a, A> {
     fn send_done(&self, tx_buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
         self.tx_buf.replace(tx_buf);
         if result != Ok(()) {
-            debug!("Send Failed: {:?}, acked: {}", result, acked);
+            kernel::log_warn!(Net, "IPv6: send failed: {:?}, acked: {}", result, acked);
             self.client.map(move |client| {
                 client.send_done(result);
             });
@@ -4,6 +4,7 @@
 
 //! Modules for IPv6 over 6LoWPAN stack
 
+pub mod border_router;
 pub mod frag_utils;
 pub mod sixlowpan;
 pub mod util;
@@ -13,6 +14,7 @@ pub mod icmpv6;
 pub mod ieee802154;
 pub mod ipv6;
 pub mod network_capabilities;
+pub mod slip;
 pub mod tcp;
 pub mod thread;
 pub mod udp;
@@ -0,0 +1,360 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SLIP (RFC 1055) network interface over a UART.
+//!
+//! SLIP carries raw IPv6 packets on a serial line: each packet is followed
+//! by an `END` byte, and the `END` and `ESC` bytes inside packets are
+//! escaped. On a host, the other end of the line is attached with, for
+//! example, `slattach` or `tunslip6`.
+//!
+//! [`Slip`] implements [`IP6Interface`], so that it can be used as the
+//! external interface of a
+//! [`BorderRouter`](crate::net::border_router::BorderRouter).
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let slip = static_init!(
+//!     Slip<'static>,
+//!     Slip::new(uart_device, tx_buffer, rx_byte, rx_packet)
+//! );
+//! uart_device.set_transmit_client(slip);
+//! uart_device.set_receive_client(slip);
+//! slip.start();
+//! ```
+
+use core::cell::Cell;
+
+use crate::net::border_router::{IP6Interface, IP6InterfaceClient};
+
+use kernel::hil::network_statistics::{Counter, Counters, NetworkStatistics};
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Byte ending a packet.
+pub const END: u8 = 0xC0;
+/// Byte starting an escape sequence.
+pub const ESC: u8 = 0xDB;
+/// Escaped `END` byte, following `ESC`.
+pub const ESC_END: u8 = 0xDC;
+/// Escaped `ESC` byte, following `ESC`.
+pub const ESC_ESC: u8 = 0xDD;
+
+/// Size of the transmit buffer needed for packets of `mtu` bytes, when every
+/// byte is escaped.
+pub const fn tx_buffer_len(mtu: usize) -> usize {
+    2 * mtu + 2
+}
+
+/// Encode `packet` as a SLIP frame in `buf`, starting with an `END` byte to
+/// flush any noise received by the peer. Returns the length of the frame, or
+/// `None` if `buf` is too small.
+pub fn encode(packet: &[u8], buf: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    let mut push = |byte: u8| -> Option<()> {
+        *buf.get_mut(len)? = byte;
+        len += 1;
+        Some(())
+    };
+    push(END)?;
+    for &byte in packet {
+        match byte {
+            END => {
+                push(ESC)?;
+                push(ESC_END)?;
+            }
+            ESC => {
+                push(ESC)?;
+                push(ESC_ESC)?;
+            }
+            _ => push(byte)?,
+        }
+    }
+    push(END)?;
+    Some(len)
+}
+
+/// State of the decoding of a received SLIP frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decoder {
+    len: usize,
+    escaped: bool,
+    /// The frame did not fit in the buffer, or contained an invalid escape
+    /// sequence, and is dropped.
+    invalid: bool,
+}
+
+/// Result of decoding one byte.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    /// The frame is not complete yet.
+    Pending,
+    /// A packet of this length was decoded in the buffer.
+    Packet(usize),
+    /// An invalid frame ended and was dropped.
+    Dropped,
+}
+
+impl Decoder {
+    pub const fn new() -> Decoder {
+        Decoder {
+            len: 0,
+            escaped: false,
+            invalid: false,
+        }
+    }
+
+    /// Discard the frame being decoded.
+    pub fn reset(&mut self) {
+        *self = Decoder::new();
+    }
+
+    /// Drop the frame being decoded, up to the next `END` byte.
+    pub fn invalidate(&mut self) {
+        self.invalid = true;
+    }
+
+    /// Decode the received `byte` into `buf`, which must be passed unchanged
+    /// until a packet is returned.
+    pub fn push(&mut self, byte: u8, buf: &mut [u8]) -> Decoded {
+        if byte == END {
+            let decoded = if self.invalid || self.escaped {
+                Decoded::Dropped
+            } else if self.len == 0 {
+                // Empty frames are sent to flush the line.
+                Decoded::Pending
+            } else {
+                Decoded::Packet(self.len)
+            };
+            self.reset();
+            return decoded;
+        }
+        if self.invalid {
+            return Decoded::Pending;
+        }
+        let byte = if self.escaped {
+            self.escaped = false;
+            match byte {
+                ESC_END => END,
+                ESC_ESC => ESC,
+                _ => {
+                    self.invalid = true;
+                    return Decoded::Pending;
+                }
+            }
+        } else if byte == ESC {
+            self.escaped = true;
+            return Decoded::Pending;
+        } else {
+            byte
+        };
+        match buf.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => self.invalid = true,
+        }
+        Decoded::Pending
+    }
+}
+
+pub struct Slip<'a> {
+    uart: &'a dyn uart::UartData<'a>,
+    /// Buffer of the encoded frame being transmitted.
+    tx_buffer: TakeCell<'static, [u8]>,
+    /// One byte buffer, as frames are received byte by byte.
+    rx_byte: TakeCell<'static, [u8]>,
+    /// Buffer of the packet being decoded.
+    rx_packet: TakeCell<'static, [u8]>,
+    decoder: Cell<Decoder>,
+    client: OptionalCell<&'a dyn IP6InterfaceClient>,
+    counters: Cell<Counters>,
+}
+
+impl<'a> Slip<'a> {
+    /// `tx_buffer` should be [`tx_buffer_len()`] long for packets of the
+    /// size of `rx_packet`, and `rx_byte` one byte long.
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        tx_buffer: &'static mut [u8],
+        rx_byte: &'static mut [u8],
+        rx_packet: &'static mut [u8],
+    ) -> Slip<'a> {
+        Slip {
+            uart,
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_byte: TakeCell::new(rx_byte),
+            rx_packet: TakeCell::new(rx_packet),
+            decoder: Cell::new(Decoder::new()),
+            client: OptionalCell::empty(),
+            counters: Cell::new(Counters::default()),
+        }
+    }
+
+    /// Start receiving packets.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        let rx_byte = self.rx_byte.take().ok_or(ErrorCode::ALREADY)?;
+        self.uart.receive_buffer(rx_byte, 1).map_err(|(err, buf)| {
+            self.rx_byte.replace(buf);
+            err
+        })
+    }
+
+    fn increment(&self, counter: Counter) {
+        let mut counters = self.counters.get();
+        counters.increment(counter);
+        self.counters.set(counters);
+    }
+}
+
+impl<'a> IP6Interface<'a> for Slip<'a> {
+    fn set_client(&self, client: &'a dyn IP6InterfaceClient) {
+        self.client.set(client);
+    }
+
+    fn send(&self, packet: &[u8]) -> Result<(), ErrorCode> {
+        let tx_buffer = match self.tx_buffer.take() {
+            Some(tx_buffer) => tx_buffer,
+            None => {
+                self.increment(Counter::TxDropped);
+                return Err(ErrorCode::BUSY);
+            }
+        };
+        let len = match encode(packet, tx_buffer) {
+            Some(len) => len,
+            None => {
+                self.tx_buffer.replace(tx_buffer);
+                self.increment(Counter::TxDropped);
+                return Err(ErrorCode::SIZE);
+            }
+        };
+        self.uart
+            .transmit_buffer(tx_buffer, len)
+            .map_err(|(err, buf)| {
+                self.tx_buffer.replace(buf);
+                self.increment(Counter::TxErrors);
+                err
+            })
+    }
+}
+
+impl uart::TransmitClient for Slip<'_> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        self.increment(if rval.is_ok() {
+            Counter::TxFrames
+        } else {
+            Counter::TxErrors
+        });
+        self.client.map(|client| client.send_done(rval));
+    }
+}
+
+impl uart::ReceiveClient for Slip<'_> {
+    fn received_buffer(
+        &self,
+        rx_byte: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        error: uart::Error,
+    ) {
+        if rval.is_err() || error != uart::Error::None {
+            // The frame lost bytes, drop it up to the next END.
+            let mut decoder = self.decoder.get();
+            decoder.invalidate();
+            self.decoder.set(decoder);
+        } else if rx_len == 1 {
+            self.rx_packet.map(|rx_packet| {
+                let mut decoder = self.decoder.get();
+                let decoded = decoder.push(rx_byte[0], rx_packet);
+                self.decoder.set(decoder);
+                match decoded {
+                    Decoded::Pending => {}
+                    Decoded::Packet(len) => {
+                        self.increment(Counter::RxFrames);
+                        self.client
+                            .map(|client| client.packet_received(&rx_packet[..len]));
+                    }
+                    Decoded::Dropped => self.increment(Counter::RxErrors),
+                }
+            });
+        }
+
+        if let Err((_, buf)) = self.uart.receive_buffer(rx_byte, 1) {
+            self.rx_byte.replace(buf);
+        }
+    }
+}
+
+impl NetworkStatistics for Slip<'_> {
+    fn interface_name(&self) -> &'static str {
+        "slip"
+    }
+
+    fn counters(&self) -> Counters {
+        self.counters.get()
+    }
+
+    fn reset_counters(&self) {
+        self.counters.set(Counters::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(frame: &[u8], buf: &mut [u8]) -> Decoded {
+        let mut decoder = Decoder::new();
+        let mut decoded = Decoded::Pending;
+        for &byte in frame {
+            match decoder.push(byte, buf) {
+                Decoded::Pending => {}
+                other => decoded = other,
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn encode_escapes_special_bytes() {
+        let mut buf = [0; 10];
+        let len = encode(&[1, END, 2, ESC], &mut buf).unwrap();
+        assert_eq!(&buf[..len], &[END, 1, ESC, ESC_END, 2, ESC, ESC_ESC, END]);
+        assert_eq!(encode(&[END; 5], &mut buf), None);
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let packet = [0x60, END, 0, ESC, ESC_END, 0xff];
+        let mut frame = [0; tx_buffer_len(6)];
+        let len = encode(&packet, &mut frame).unwrap();
+        let mut buf = [0; 6];
+        assert_eq!(decode(&frame[..len], &mut buf), Decoded::Packet(6));
+        assert_eq!(buf, packet);
+    }
+
+    #[test]
+    fn decode_drops_invalid_frames() {
+        let mut buf = [0; 2];
+        assert_eq!(decode(&[1, 2, 3, END], &mut buf), Decoded::Dropped);
+        assert_eq!(decode(&[ESC, 1, END], &mut buf), Decoded::Dropped);
+        assert_eq!(decode(&[END, END], &mut buf), Decoded::Pending);
+        // The decoder recovers at the next frame.
+        assert_eq!(
+            decode(&[1, 2, 3, END, 4, END], &mut buf),
+            Decoded::Packet(1)
+        );
+        assert_eq!(buf[0], 4);
+    }
+}
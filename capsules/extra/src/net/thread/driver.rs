@@ -185,8 +185,7 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
     }
 
     fn send_parent_req(&self) {
-        // UNCOMMENT TO DEBUG THREAD //
-        // kernel::debug!("[Thread] Sending parent request...");
+        kernel::log_trace!(Net, "Thread: sending parent request");
 
         // Panicking on unwrap indicates the state was taken without replacement
         // (unreachable with proper state machine implementation)
@@ -207,10 +206,10 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
                         // to a detached state.
                         self.state.replace(ThreadState::Detached);
 
-                        // UNCOMMENT TO DEBUG THREAD //
-                        // kernel::debug!(
-                        //     "[Thread] Failed sending MLE parent request - crypto operation error."
-                        // );
+                        kernel::log_warn!(
+                            Net,
+                            "Thread: failed sending MLE parent request - crypto operation error"
+                        );
                         self.terminate_child_join(Err(code));
                     });
             }
@@ -273,9 +272,8 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
                 if recv_buf[0] == MleCommand::ParentResponse as u8 {
                     // Received Parent Response -> form Child ID Request
 
-                    // UNCOMMENT TO DEBUG THREAD //
-                    // kernel::debug!("[Thread] Received Parent Response.");
-                    // kernel::debug!("[Thread] Sending Child ID Request...");
+                    kernel::log_trace!(Net, "Thread: received parent response");
+                    kernel::log_trace!(Net, "Thread: sending child ID request");
 
                     let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
 
@@ -336,8 +334,7 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         // Obtain and unwrap frame counter
         let frame_counter = security.frame_counter;
         if frame_counter.is_none() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] Malformed auxiliary security header");
+            kernel::log_warn!(Net, "Thread: malformed auxiliary security header");
             return Err((ErrorCode::INVAL, buf));
         }
 
@@ -354,14 +351,12 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
                 if self.aes_crypto.set_key(&netkey.mle_key).is_err()
                     || self.aes_crypto.set_nonce(&nonce).is_err()
                 {
-                    // UNCOMMENT TO DEBUG THREAD //
-                    // kernel::debug!("[Thread] Failure setting networkkey and/or nonce.");
+                    kernel::log_error!(Net, "Thread: failure setting networkkey and/or nonce");
                     return Err((ErrorCode::FAIL, buf));
                 }
             }
             None => {
-                // UNCOMMENT TO DEBUG THREAD //
-                // kernel::debug!("[Thread] Attempt to access networkkey when no networkkey set.");
+                kernel::log_warn!(Net, "Thread: no networkkey set");
                 return Err((ErrorCode::NOSUPPORT, buf));
             }
         }
@@ -395,8 +390,7 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
 
         // Error check on result from encoding, failure likely means buf was not large enough
         if encode_res.is_none() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] Error encoding cryptographic data into buffer");
+            kernel::log_error!(Net, "Thread: error encoding cryptographic data into buffer");
             return Err((ErrorCode::FAIL, buf));
         }
 
@@ -419,10 +413,10 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         // The sizelock is empty except when a crypto operation
         // is underway. If the sizelock is not empty, return error
         if self.crypto_sizelock.is_some() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!(
-            //     "[Thread] Error - cryptographic resources in use; crypto_sizelock occupied"
-            // );
+            kernel::log_warn!(
+                Net,
+                "Thread: cryptographic resources in use; crypto_sizelock occupied"
+            );
             return Err((ErrorCode::BUSY, buf));
         }
 
@@ -539,8 +533,10 @@ impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
             ThreadState::SendUDPMsg => unimplemented!(),
             ThreadState::SendChildIdReq(_) => ThreadState::WaitingChildRsp,
             ThreadState::SendParentReq => {
-                // UNCOMMENT TO DEBUG THREAD //
-                // kernel::debug!("[Thread] Completed sending parent request to multicast IP");
+                kernel::log_trace!(
+                    Net,
+                    "Thread: completed sending parent request to multicast IP"
+                );
                 ThreadState::WaitingParentRsp
             }
             _ => panic!("Thread state machine diverged"),
@@ -587,8 +583,10 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
             // Tock's current implementation of Thread ignores all messages that do not possess MLE encryption. This
             // is due to the Thread spec stating "Except for when specifically indicated, incoming
             // messages that are not secured with either MLE or link-layer security SHOULD be ignored." (v.1.3.0 sect 4.10)
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] DROPPED PACKET - Received unencrypted MLE packet.");
+            kernel::log_warn!(
+                Net,
+                "Thread: dropped packet - received unencrypted MLE packet"
+            );
         }
 
         // decode aux security header from packet into Security data type
@@ -596,8 +594,10 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
 
         // Guard statement for improperly formated aux sec header
         if sec_res.is_none() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] DROPPED PACKET - Malformed auxiliary security header.");
+            kernel::log_warn!(
+                Net,
+                "Thread: dropped packet - malformed auxiliary security header"
+            );
             return;
         }
 
@@ -608,8 +608,7 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
         // crypto operation.
         self.recv_buffer.take().map_or_else(
             || {
-                // UNCOMMENT TO DEBUG THREAD //
-                // kernel::debug!("[Thread] DROPPED PACKET - Receive buffer not available")
+                kernel::log_warn!(Net, "Thread: dropped packet - receive buffer not available");
             },
             |recv_buf| {
                 self.perform_crypt_op(
@@ -624,12 +623,12 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
                     // Error check on crypto operation. If the crypto operation
                     // fails, we log the error and replace the receive buffer for
                     // future receptions
-                    |(_code, buf)| {
-                        // UNCOMMENT TO DEBUG THREAD alter _code to code//
-                        // kernel::debug!(
-                        //     "[Thread] DROPPED PACKET - Crypto Operation Error *{:?}",
-                        //     code
-                        // );
+                    |(code, buf)| {
+                        kernel::log_warn!(
+                            Net,
+                            "Thread: dropped packet - crypto operation error {:?}",
+                            code
+                        );
                         self.recv_buffer.replace(SubSliceMut::new(buf));
                     },
                     |()| (),
@@ -8,9 +8,10 @@
 //! Processes use this driver to send UDP packets from a common interface
 //! and bind to UDP ports for receiving packets.
 //! Also exposes a list of interface addresses to the application (currently
-//! hard-coded).
+//! hard-coded), and lets processes join multicast groups to receive the
+//! packets sent to them.
 
-use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ip_utils::{IPAddr, ALL_NODES_MULTICAST};
 use crate::net::network_capabilities::NetworkCapability;
 use crate::net::stream::encode_u16;
 use crate::net::stream::encode_u8;
@@ -25,7 +26,6 @@ use core::mem::size_of;
 use core::{cmp, mem};
 
 use kernel::capabilities::UdpDriverCapability;
-use kernel::debug;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -76,6 +76,9 @@ mod rw_allow {
     pub const COUNT: u8 = 3;
 }
 
+/// Maximum number of multicast groups joined by each process.
+pub const MAX_MULTICAST_GROUPS: usize = 2;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct UDPEndpoint {
     addr: IPAddr,
@@ -115,6 +118,18 @@ impl UDPEndpoint {
 pub struct App {
     pending_tx: Option<[UDPEndpoint; 2]>,
     bound_port: Option<UDPEndpoint>,
+    multicast_groups: [Option<IPAddr>; MAX_MULTICAST_GROUPS],
+}
+
+impl App {
+    /// Whether the process receives the packets sent to `dst_addr`.
+    fn receives_from(&self, dst_addr: IPAddr) -> bool {
+        self.bound_port
+            .map_or(false, |bound| bound.addr == dst_addr)
+            || (dst_addr.is_multicast()
+                && (dst_addr == ALL_NODES_MULTICAST
+                    || self.multicast_groups.contains(&Some(dst_addr))))
+    }
 }
 
 #[allow(dead_code)]
@@ -310,8 +325,9 @@ impl<'a> UDPDriver<'a> {
     #[inline]
     fn parse_ip_port_pair(&self, buf: &[u8]) -> Option<UDPEndpoint> {
         if buf.len() != size_of::<UDPEndpoint>() {
-            debug!(
-                "[parse] len is {:?}, not {:?} as expected",
+            kernel::log_warn!(
+                Net,
+                "UDP: endpoint length is {:?}, not {:?} as expected",
                 buf.len(),
                 size_of::<UDPEndpoint>()
             );
@@ -328,6 +344,64 @@ impl<'a> UDPDriver<'a> {
             Some(pair)
         }
     }
+
+    /// Join (if `join`) or leave the multicast group whose address is in the
+    /// config buffer of `processid`.
+    fn update_multicast_group(&self, processid: ProcessId, join: bool) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                let group = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.enter(|cfg| {
+                            if cfg.len() != size_of::<IPAddr>() {
+                                return None;
+                            }
+                            let mut group = IPAddr::new();
+                            cfg.copy_to_slice(&mut group.0);
+                            Some(group)
+                        })
+                    })
+                    .unwrap_or(None)
+                    .filter(|group| group.is_multicast())
+                    .ok_or(ErrorCode::INVAL)?;
+                let joined = app
+                    .multicast_groups
+                    .iter_mut()
+                    .find(|joined| **joined == Some(group));
+                match (join, joined) {
+                    (true, Some(_)) => Ok(()),
+                    (true, None) => {
+                        let free = app
+                            .multicast_groups
+                            .iter_mut()
+                            .find(|free| free.is_none())
+                            .ok_or(ErrorCode::NOMEM)?;
+                        *free = Some(group);
+                        Ok(())
+                    }
+                    (false, Some(joined)) => {
+                        *joined = None;
+                        Ok(())
+                    }
+                    (false, None) => Err(ErrorCode::INVAL),
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Whether a process joined the multicast `group`.
+    pub fn is_multicast_listener(&self, group: IPAddr) -> bool {
+        let mut listener = false;
+        for app in self.apps.iter() {
+            app.enter(|app, _| {
+                if app.multicast_groups.contains(&Some(group)) {
+                    listener = true;
+                }
+            });
+        }
+        listener
+    }
 }
 
 impl<'a> SyscallDriver for UDPDriver<'a> {
@@ -376,6 +450,13 @@ impl<'a> SyscallDriver for UDPDriver<'a> {
     ///        /// - `4`: Returns the maximum payload that can be transmitted by apps using this driver.
     ///        This represents the size of the payload buffer in the kernel. Apps can use this
     ///        syscall to ensure they do not attempt to send too-large messages.
+    /// - `5`: Join the multicast group whose 16-byte address is in the cfg buffer. The
+    ///        packets sent to the group and to the port bound with command `3` are then
+    ///        received. Returns INVAL if the cfg buffer is the wrong size or does not contain
+    ///        a multicast address, and NOMEM if the process already joined
+    ///        `MAX_MULTICAST_GROUPS` groups.
+    /// - `6`: Leave the multicast group whose 16-byte address is in the cfg buffer. Returns
+    ///        INVAL if the process did not join the group.
 
     fn command(
         &self,
@@ -550,6 +631,8 @@ impl<'a> SyscallDriver for UDPDriver<'a> {
                 }
             }
             4 => CommandReturn::success_u32(self.max_tx_pyld_len as u32),
+            5 => self.update_multicast_group(processid, true).into(),
+            6 => self.update_multicast_group(processid, false).into(),
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -592,7 +675,7 @@ impl<'a> UDPRecvClient for UDPDriver<'a> {
             if app.bound_port.is_some() {
                 let mut for_me = false;
                 app.bound_port.as_ref().map(|requested_addr| {
-                    if requested_addr.addr == dst_addr && requested_addr.port == dst_port {
+                    if requested_addr.port == dst_port && app.receives_from(dst_addr) {
                         for_me = true;
                     }
                 });
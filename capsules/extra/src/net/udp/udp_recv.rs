@@ -17,7 +17,6 @@ use crate::net::udp::udp_port_table::{PortQuery, UdpPortBindingRx};
 use crate::net::udp::UDPHeader;
 
 use kernel::collections::list::{List, ListLink, ListNode};
-use kernel::debug;
 use kernel::utilities::cells::{MapCell, OptionalCell};
 
 pub struct MuxUdpReceiver<'a> {
@@ -48,8 +47,11 @@ impl<'a> IP6RecvClient for MuxUdpReceiver<'a> {
             Some((offset, udp_header)) => {
                 let len = udp_header.get_len() as usize;
                 let dst_port = udp_header.get_dst_port();
+                // Multicast packets are delivered to every receiver bound to
+                // the port, unicast ones to the first one.
+                let multicast = ip_header.get_dst_addr().is_multicast();
                 if len > payload.len() {
-                    debug!("[UDP_RECV] Error: Received UDP length too long");
+                    kernel::log_warn!(Net, "UDP: received UDP length too long");
                     return;
                 }
                 for rcvr in self.rcvr_list.iter() {
@@ -66,7 +68,10 @@ impl<'a> IP6RecvClient for MuxUdpReceiver<'a> {
                                     );
                                 });
                                 rcvr.binding.replace(binding);
-                                break;
+                                if !multicast {
+                                    break;
+                                }
+                                continue;
                             }
                             rcvr.binding.replace(binding);
                         }
@@ -82,7 +87,10 @@ impl<'a> IP6RecvClient for MuxUdpReceiver<'a> {
                                         &payload[offset..],
                                     );
                                     self.driver.replace(driver);
-                                    break;
+                                    if !multicast {
+                                        break;
+                                    }
+                                    continue;
                                 }
                                 self.driver.replace(driver);
                             }
@@ -94,6 +102,11 @@ impl<'a> IP6RecvClient for MuxUdpReceiver<'a> {
             None => {}
         }
     }
+
+    fn is_multicast_listener(&self, group: IPAddr) -> bool {
+        self.driver
+            .map_or(false, |driver| driver.is_multicast_listener(group))
+    }
 }
 
 /// The UDP driver implements this client interface trait to receive
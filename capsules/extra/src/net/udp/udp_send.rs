@@ -30,7 +30,6 @@ use core::cell::Cell;
 
 use kernel::capabilities::UdpDriverCapability;
 use kernel::collections::list::{List, ListLink, ListNode};
-use kernel::debug;
 use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::ErrorCode;
@@ -72,7 +71,7 @@ impl<'a, T: IP6Sender<'a>> MuxUdpSender<'a, T> {
                     ret
                 }
                 None => {
-                    debug!("No buffer available to take.");
+                    kernel::log_warn!(Net, "UDP: no buffer available to take");
                     Err(ErrorCode::FAIL)
                 }
             }
@@ -104,7 +103,7 @@ impl<'a, T: IP6Sender<'a>> IP6SendClient for MuxUdpSender<'a, T> {
                         client.send_done(result, buf);
                     }
                     None => {
-                        debug!("ERROR: Missing buffer in send done.");
+                        kernel::log_error!(Net, "UDP: missing buffer in send done");
                     }
                 })
         });
@@ -124,19 +123,19 @@ impl<'a, T: IP6Sender<'a>> IP6SendClient for MuxUdpSender<'a, T> {
                                 );
                                 next_sender.tx_buffer.replace(buf);
                                 if ret != Ok(()) {
-                                    debug!("IP send_to failed: {:?}", ret);
+                                    kernel::log_warn!(Net, "UDP: IP send_to failed: {:?}", ret);
                                 }
                                 ret
                             }
                             None => Err(ErrorCode::FAIL),
                         },
                         None => {
-                            debug!("Missing transport header.");
+                            kernel::log_error!(Net, "UDP: missing transport header");
                             Err(ErrorCode::FAIL)
                         }
                     },
                     None => {
-                        debug!("No buffer available to take.");
+                        kernel::log_warn!(Net, "UDP: no buffer available to take");
                         Err(ErrorCode::FAIL)
                     }
                 }
@@ -144,7 +143,7 @@ impl<'a, T: IP6Sender<'a>> IP6SendClient for MuxUdpSender<'a, T> {
             None => Ok(()), //No more packets queued.
         };
         if success != Ok(()) {
-            debug!("Error in udp_send send_done() callback.");
+            kernel::log_error!(Net, "UDP: error in send_done() callback");
         }
     }
 }
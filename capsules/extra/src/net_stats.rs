@@ -0,0 +1,74 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides userspace with the traffic and error counters of the network
+//! interfaces of the board.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let interfaces = static_init!(
+//!     [&'static dyn kernel::hil::network_statistics::NetworkStatistics; 1],
+//!     [&peripherals.can1]
+//! );
+//! let net_stats = static_init!(
+//!     capsules_extra::net_stats::NetStats<'static>,
+//!     capsules_extra::net_stats::NetStats::new(interfaces)
+//! );
+//! ```
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::NetStats as usize;
+
+use kernel::hil::network_statistics::{Counter, NetworkStatistics};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+pub struct NetStats<'a> {
+    interfaces: &'a [&'a dyn NetworkStatistics],
+}
+
+impl<'a> NetStats<'a> {
+    pub fn new(interfaces: &'a [&'a dyn NetworkStatistics]) -> NetStats<'a> {
+        NetStats { interfaces }
+    }
+}
+
+impl SyscallDriver for NetStats<'_> {
+    /// Read the network statistics.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Number of network interfaces.
+    /// - `2`: Value of counter `data2` of interface `data1`. Counters are, in
+    ///   order: transmitted frames, received frames, dropped transmit frames,
+    ///   dropped received frames, transmit errors and receive errors.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        _: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.interfaces.len() as u32),
+            2 => match (self.interfaces.get(data1), Counter::from_index(data2)) {
+                (Some(interface), Some(counter)) => {
+                    CommandReturn::success_u32(interface.counters().get(counter))
+                }
+                _ => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,951 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the PN532 NFC controller, in reader mode.
+//!
+//! The driver polls for ISO14443A tags (MIFARE, NTAG, contactless smart
+//! cards), reports their UID, and exchanges data, such as APDUs, with the
+//! selected tag.
+//!
+//! The PN532 is driven with information frames, on I2C or SPI:
+//!
+//! ```text
+//! 0x00 0x00 0xFF | LEN | LCS | TFI | data | DCS | 0x00
+//! ```
+//!
+//! `LEN` counts the TFI and the data, `LEN + LCS` and the sum of the TFI, the
+//! data and `DCS` are zero modulo 256. The TFI is `0xD4` from the host and
+//! `0xD5` from the PN532. The PN532 acknowledges each command with an ACK
+//! frame, then answers it once it completed.
+//!
+//! The host polls the PN532 every `READY_POLL_MS` until a frame is ready. On
+//! I2C, each read starts with the ready status byte. On SPI, the status is
+//! read with a separate status read, and bytes are sent LSB first, so this
+//! driver reverses their bits.
+//!
+//! A tag is searched with a few activation attempts every
+//! `TAG_POLL_INTERVAL_MS`, until a tag is found or the polling is stopped.
+//!
+//! Only one operation runs at a time, for the process that started it.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! - `0`: A tag was found, with the status, the length of its UID, and its
+//!   `SEL_RES` and `SENS_RES` bytes (`SEL_RES | SENS_RES << 8`). The UID is
+//!   copied to the read-write allow buffer `0`.
+//! - `1`: A data exchange completed, with the status, the length of the
+//!   response copied to the read-write allow buffer `1`, and on failure the
+//!   error code of the PN532.
+//!
+//! ### `allow_readonly` System Call
+//!
+//! - `0`: The data to send to the tag.
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! - `0`: The UID of the tag, up to `MAX_UID_LEN` bytes.
+//! - `1`: The response of the tag, up to `MAX_DATA_LEN` bytes.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Poll for a tag.
+//! - `2`: Send the first `data` bytes of the read-only allow buffer to the
+//!   last tag found, and receive its response. Returns `SIZE` if `data` is
+//!   more than `MAX_DATA_LEN`, and `NOMEM` if the buffer is shorter.
+//! - `3`: Stop the operation of the process.
+//!
+//! Commands `1` and `2` return `BUSY` while an operation is running.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pn532_bus = components::pn532::Pn532I2CBusComponent::new(mux_i2c, 0x24)
+//!     .finalize(components::pn532_i2c_bus_component_static!(nrf52840::i2c::TWI));
+//! let pn532 = components::pn532::Pn532Component::new(
+//!     board_kernel,
+//!     capsules_extra::pn532::DRIVER_NUM,
+//!     pn532_bus,
+//!     mux_alarm,
+//! )
+//! .finalize(components::pn532_component_static!(
+//!     capsules_extra::pn532::I2CBus<'static, I2CDevice<'static, nrf52840::i2c::TWI>>,
+//!     nrf52840::rtc::Rtc
+//! ));
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::i2c;
+use kernel::hil::spi;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Pn532 as usize;
+
+/// Default I2C address of the PN532.
+pub const DEFAULT_I2C_ADDRESS: u8 = 0x24;
+
+/// Largest UID of an ISO14443A tag.
+pub const MAX_UID_LEN: usize = 10;
+/// Largest data sent to or received from a tag in one exchange.
+pub const MAX_DATA_LEN: usize = 200;
+/// Size of the frame buffer: the byte reserved for the bus, the frame
+/// overhead, and the command code and target of a data exchange.
+pub const BUFFER_LEN: usize = 1 + FRAME_OVERHEAD + 2 + MAX_DATA_LEN;
+
+pub const READY_POLL_MS: u32 = 5;
+pub const TAG_POLL_INTERVAL_MS: u32 = 200;
+/// Longest time the PN532 takes to complete a command.
+const RESPONSE_TIMEOUT_MS: u32 = 1000;
+
+/// Preamble, start code, length, length checksum, TFI, data checksum and
+/// postamble.
+const FRAME_OVERHEAD: usize = 8;
+const ACK_FRAME: [u8; 6] = [0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00];
+/// Bytes read for the answers of the commands other than data exchanges.
+const SHORT_RESPONSE_LEN: usize = 32;
+
+const TFI_HOST: u8 = 0xD4;
+const TFI_PN532: u8 = 0xD5;
+
+/// Number of passive activation attempts of each tag poll.
+const PASSIVE_ACTIVATION_RETRIES: u8 = 2;
+
+/// Command codes. The PN532 answers with the code plus one.
+mod command {
+    pub const SAM_CONFIGURATION: u8 = 0x14;
+    pub const RF_CONFIGURATION: u8 = 0x32;
+    pub const IN_DATA_EXCHANGE: u8 = 0x40;
+    pub const IN_LIST_PASSIVE_TARGET: u8 = 0x4A;
+}
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// A tag was found.
+    pub const TAG: usize = 0;
+    /// A data exchange completed.
+    pub const EXCHANGE: usize = 1;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers.
+mod ro_allow {
+    /// Data to send to the tag.
+    pub const DATA: usize = 0;
+    /// Number of read-only allow buffers.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers.
+mod rw_allow {
+    /// UID of the tag.
+    pub const UID: usize = 0;
+    /// Response of the tag.
+    pub const RESPONSE: usize = 1;
+    /// Number of read-write allow buffers.
+    pub const COUNT: u8 = 2;
+}
+
+/// The host interface of the PN532.
+///
+/// Frames are stored from the second byte of the buffers, the first one is
+/// reserved for the bus.
+pub trait Pn532Bus<'a> {
+    /// Send the frame of `len` bytes in `buffer`.
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Read a frame of `len` bytes into `buffer`, if the PN532 has one
+    /// ready. `buffer` must be at least `len + 1` bytes long.
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    fn set_client(&self, client: &'a dyn Pn532BusClient);
+}
+
+pub trait Pn532BusClient {
+    fn write_done(&self, buffer: &'static mut [u8], status: Result<(), ErrorCode>);
+
+    /// Called when a read completed, with whether a frame was ready and read.
+    fn read_done(&self, buffer: &'static mut [u8], status: Result<bool, ErrorCode>);
+}
+
+/*********** I2C ************/
+
+/// The PN532 on I2C, where each read starts with the ready status byte.
+pub struct I2CBus<'a, I: i2c::I2CDevice> {
+    i2c: &'a I,
+    client: OptionalCell<&'a dyn Pn532BusClient>,
+    /// Whether the operation is a read.
+    reading: Cell<bool>,
+}
+
+impl<'a, I: i2c::I2CDevice> I2CBus<'a, I> {
+    pub fn new(i2c: &'a I) -> I2CBus<'a, I> {
+        I2CBus {
+            i2c,
+            client: OptionalCell::empty(),
+            reading: Cell::new(false),
+        }
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> Pn532Bus<'a> for I2CBus<'a, I> {
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        buffer.copy_within(1..len + 1, 0);
+        self.reading.set(false);
+        self.i2c.enable();
+        self.i2c.write(buffer, len).map_err(|(e, buffer)| {
+            self.i2c.disable();
+            (e.into(), buffer)
+        })
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.reading.set(true);
+        self.i2c.enable();
+        self.i2c.read(buffer, len + 1).map_err(|(e, buffer)| {
+            self.i2c.disable();
+            (e.into(), buffer)
+        })
+    }
+
+    fn set_client(&self, client: &'a dyn Pn532BusClient) {
+        self.client.set(client);
+    }
+}
+
+impl<I: i2c::I2CDevice> i2c::I2CClient for I2CBus<'_, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        self.i2c.disable();
+        let status = status.map_err(ErrorCode::from);
+        self.client.map(move |client| {
+            if self.reading.get() {
+                // The status byte is in place of the reserved byte.
+                let ready = buffer[0] & 0x01 != 0;
+                client.read_done(buffer, status.map(|()| ready));
+            } else {
+                client.write_done(buffer, status);
+            }
+        });
+    }
+}
+
+/*********** SPI ************/
+
+/// First byte of the SPI transfers.
+mod spi_op {
+    pub const DATA_WRITE: u8 = 0x01;
+    pub const STATUS_READ: u8 = 0x02;
+    pub const DATA_READ: u8 = 0x03;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SpiState {
+    Idle,
+    Write,
+    Status,
+    Read,
+}
+
+/// The PN532 on SPI, in mode 0. The PN532 sends and receives bytes LSB
+/// first, so their bits are reversed around the transfers.
+pub struct SpiBus<'a, S: spi::SpiMasterDevice<'a>> {
+    spi: &'a S,
+    client: OptionalCell<&'a dyn Pn532BusClient>,
+    state: Cell<SpiState>,
+    /// Length of the frame being read.
+    read_len: Cell<usize>,
+    /// Bytes sent while reading.
+    read_command: TakeCell<'static, [u8]>,
+}
+
+impl<'a, S: spi::SpiMasterDevice<'a>> SpiBus<'a, S> {
+    /// `read_command` must be `BUFFER_LEN` long.
+    pub fn new(spi: &'a S, read_command: &'static mut [u8]) -> SpiBus<'a, S> {
+        SpiBus {
+            spi,
+            client: OptionalCell::empty(),
+            state: Cell::new(SpiState::Idle),
+            read_len: Cell::new(0),
+            read_command: TakeCell::new(read_command),
+        }
+    }
+
+    /// Send `op` followed by zeros, and read `len` bytes into `buffer`.
+    fn read_transfer(
+        &self,
+        state: SpiState,
+        op: u8,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let command = match self.read_command.take() {
+            Some(command) if command.len() >= len && buffer.len() >= len => command,
+            Some(command) => {
+                self.read_command.replace(command);
+                return Err((ErrorCode::SIZE, buffer));
+            }
+            None => return Err((ErrorCode::BUSY, buffer)),
+        };
+        command[0] = op.reverse_bits();
+        command[1..len].fill(0);
+        match self.spi.read_write_bytes(command, Some(buffer), len) {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((e, command, buffer)) => {
+                self.read_command.replace(command);
+                // The read buffer passed in is always given back.
+                Err((e, buffer.unwrap_or(&mut [])))
+            }
+        }
+    }
+}
+
+impl<'a, S: spi::SpiMasterDevice<'a>> Pn532Bus<'a> for SpiBus<'a, S> {
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        buffer[0] = spi_op::DATA_WRITE;
+        for byte in buffer[..len + 1].iter_mut() {
+            *byte = byte.reverse_bits();
+        }
+        match self.spi.read_write_bytes(buffer, None, len + 1) {
+            Ok(()) => {
+                self.state.set(SpiState::Write);
+                Ok(())
+            }
+            Err((e, buffer, _)) => Err((e, buffer)),
+        }
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.read_len.set(len);
+        self.read_transfer(SpiState::Status, spi_op::STATUS_READ, buffer, 2)
+    }
+
+    fn set_client(&self, client: &'a dyn Pn532BusClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, S: spi::SpiMasterDevice<'a>> spi::SpiMasterClient for SpiBus<'a, S> {
+    fn read_write_done(
+        &self,
+        write_buffer: &'static mut [u8],
+        read_buffer: Option<&'static mut [u8]>,
+        _len: usize,
+        status: Result<(), ErrorCode>,
+    ) {
+        let state = self.state.replace(SpiState::Idle);
+        if state == SpiState::Write {
+            self.client
+                .map(move |client| client.write_done(write_buffer, status));
+            return;
+        }
+        self.read_command.replace(write_buffer);
+        let buffer = match read_buffer {
+            Some(buffer) => buffer,
+            None => return,
+        };
+
+        match state {
+            // The status follows the status read byte.
+            SpiState::Status if status.is_ok() && buffer[1].reverse_bits() & 0x01 != 0 => {
+                let len = self.read_len.get() + 1;
+                if let Err((e, buffer)) =
+                    self.read_transfer(SpiState::Read, spi_op::DATA_READ, buffer, len)
+                {
+                    self.client
+                        .map(move |client| client.read_done(buffer, Err(e)));
+                }
+            }
+            SpiState::Status => {
+                self.client
+                    .map(move |client| client.read_done(buffer, status.map(|()| false)));
+            }
+            // The frame follows the data read byte.
+            SpiState::Read => {
+                for byte in buffer.iter_mut() {
+                    *byte = byte.reverse_bits();
+                }
+                self.client
+                    .map(move |client| client.read_done(buffer, status.map(|()| true)));
+            }
+            SpiState::Idle | SpiState::Write => {}
+        }
+    }
+}
+
+/*********** Frames ************/
+
+/// Write the frame of the command `data` from the second byte of `buffer`,
+/// and return its length.
+fn encode_frame(buffer: &mut [u8], data: &[u8]) -> usize {
+    let frame = &mut buffer[1..];
+    let len = data.len() as u8 + 1;
+    frame[0..3].copy_from_slice(&[0x00, 0x00, 0xFF]);
+    frame[3] = len;
+    frame[4] = len.wrapping_neg();
+    frame[5] = TFI_HOST;
+    frame[6..6 + data.len()].copy_from_slice(data);
+    let sum = checksum(&frame[5..6 + data.len()]);
+    frame[6 + data.len()] = sum.wrapping_neg();
+    frame[7 + data.len()] = 0x00;
+    data.len() + FRAME_OVERHEAD
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Return the data of the frame answered by the PN532, from the response
+/// code.
+fn decode_frame(frame: &[u8]) -> Option<&[u8]> {
+    // Skip the preamble.
+    let start = frame.windows(2).position(|code| code == [0x00, 0xFF])? + 2;
+    let header = frame.get(start..start + 2)?;
+    let len = header[0] as usize;
+    if len == 0 || header[0].wrapping_add(header[1]) != 0 {
+        return None;
+    }
+    // TFI, data and checksum.
+    let body = frame.get(start + 2..start + 3 + len)?;
+    if body[0] != TFI_PN532 || checksum(body) != 0 {
+        return None;
+    }
+    Some(&body[1..len])
+}
+
+/*********** Driver ************/
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operation {
+    Idle,
+    Poll,
+    Exchange(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Step {
+    /// Configuring the PN532 after reset.
+    Configure,
+    /// Limiting the activation attempts of each tag poll.
+    SetRetries,
+    ListTarget,
+    Exchange,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Phase {
+    Write,
+    Ack,
+    Response,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Timer {
+    None,
+    /// Waiting for a frame to be ready.
+    Ready,
+    /// Waiting before polling for a tag again.
+    Poll,
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Pn532<'a, B: Pn532Bus<'a>, A: Alarm<'a>> {
+    bus: &'a B,
+    alarm: &'a A,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    owner: OptionalCell<ProcessId>,
+    operation: Cell<Operation>,
+    step: Cell<Step>,
+    phase: Cell<Phase>,
+    timer: Cell<Timer>,
+    ready_polls_left: Cell<u32>,
+    configured: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, B: Pn532Bus<'a>, A: Alarm<'a>> Pn532<'a, B, A> {
+    /// `buffer` must be `BUFFER_LEN` long.
+    pub fn new(
+        bus: &'a B,
+        alarm: &'a A,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        buffer: &'static mut [u8],
+    ) -> Pn532<'a, B, A> {
+        Pn532 {
+            bus,
+            alarm,
+            apps: grant,
+            owner: OptionalCell::empty(),
+            operation: Cell::new(Operation::Idle),
+            step: Cell::new(Step::Configure),
+            phase: Cell::new(Phase::Write),
+            timer: Cell::new(Timer::None),
+            ready_polls_left: Cell::new(0),
+            configured: Cell::new(false),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    fn start(&self, operation: Operation, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle || self.buffer.is_none() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.operation.set(operation);
+        let step = if self.configured.get() {
+            self.operation_step()
+        } else {
+            Step::Configure
+        };
+        match self.send(step) {
+            Ok(()) => {
+                self.owner.set(processid);
+                Ok(())
+            }
+            Err(e) => {
+                self.operation.set(Operation::Idle);
+                Err(e)
+            }
+        }
+    }
+
+    fn operation_step(&self) -> Step {
+        match self.operation.get() {
+            Operation::Exchange(_) => Step::Exchange,
+            Operation::Poll | Operation::Idle => Step::ListTarget,
+        }
+    }
+
+    /// Send the command of `step`.
+    fn send(&self, step: Step) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let len = match step {
+            Step::Configure => encode_frame(
+                buffer,
+                // Normal mode, with a timeout of 1 s, using the IRQ pin.
+                &[command::SAM_CONFIGURATION, 0x01, 0x14, 0x01],
+            ),
+            Step::SetRetries => encode_frame(
+                buffer,
+                // MaxRetries: default ATR_REQ and PSL_REQ retries.
+                &[
+                    command::RF_CONFIGURATION,
+                    0x05,
+                    0xFF,
+                    0x01,
+                    PASSIVE_ACTIVATION_RETRIES,
+                ],
+            ),
+            // One target, at 106 kbps type A.
+            Step::ListTarget => encode_frame(buffer, &[command::IN_LIST_PASSIVE_TARGET, 1, 0x00]),
+            Step::Exchange => match self.encode_exchange(buffer) {
+                Ok(len) => len,
+                Err(e) => {
+                    self.buffer.replace(buffer);
+                    return Err(e);
+                }
+            },
+        };
+        match self.bus.write(buffer, len) {
+            Ok(()) => {
+                self.step.set(step);
+                self.phase.set(Phase::Write);
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(e)
+            }
+        }
+    }
+
+    /// Write the data exchange frame with the data of the owner.
+    fn encode_exchange(&self, buffer: &mut [u8]) -> Result<usize, ErrorCode> {
+        let len = match self.operation.get() {
+            Operation::Exchange(len) => len,
+            _ => return Err(ErrorCode::FAIL),
+        };
+        let mut data = [0; 2 + MAX_DATA_LEN];
+        data[0] = command::IN_DATA_EXCHANGE;
+        data[1] = 1;
+        self.owner
+            .map_or(Err(ErrorCode::FAIL), |processid| {
+                self.apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readonly_processbuffer(ro_allow::DATA)
+                            .and_then(|buffer| {
+                                buffer.enter(|source| {
+                                    if source.len() < len {
+                                        return Err(ErrorCode::NOMEM);
+                                    }
+                                    source[..len].copy_to_slice(&mut data[2..2 + len]);
+                                    Ok(())
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::NOMEM))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()))
+            })
+            .map(|()| encode_frame(buffer, &data[..2 + len]))
+    }
+
+    fn set_timer(&self, timer: Timer, ms: u32) {
+        self.timer.set(timer);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    fn stop_timer(&self) {
+        self.timer.set(Timer::None);
+        let _ = self.alarm.disarm();
+    }
+
+    /// Poll the ready status until the next frame can be read.
+    fn wait_ready(&self, phase: Phase) {
+        self.phase.set(phase);
+        self.ready_polls_left
+            .set(RESPONSE_TIMEOUT_MS / READY_POLL_MS);
+        self.set_timer(Timer::Ready, READY_POLL_MS);
+    }
+
+    /// Length of the next frame to read.
+    fn frame_len(&self) -> usize {
+        match (self.phase.get(), self.step.get()) {
+            (Phase::Ack, _) => ACK_FRAME.len(),
+            (_, Step::Exchange) => BUFFER_LEN - 1,
+            _ => SHORT_RESPONSE_LEN,
+        }
+    }
+
+    /// Go on after the PN532 answered the command of the current step.
+    fn response(&self, data: &[u8]) -> Result<(), (ErrorCode, u8)> {
+        let step = self.step.get();
+        let code = match step {
+            Step::Configure => command::SAM_CONFIGURATION,
+            Step::SetRetries => command::RF_CONFIGURATION,
+            Step::ListTarget => command::IN_LIST_PASSIVE_TARGET,
+            Step::Exchange => command::IN_DATA_EXCHANGE,
+        };
+        if data.first() != Some(&(code + 1)) {
+            return Err((ErrorCode::FAIL, 0));
+        }
+        let res = match step {
+            Step::Configure => self.send(Step::SetRetries),
+            Step::SetRetries => {
+                self.configured.set(true);
+                self.send(self.operation_step())
+            }
+            Step::ListTarget => match data.get(1) {
+                Some(0) => {
+                    self.set_timer(Timer::Poll, TAG_POLL_INTERVAL_MS);
+                    Ok(())
+                }
+                // Target number, SENS_RES, SEL_RES, UID length and UID.
+                Some(_) if data.len() >= 7 => {
+                    let sens_res = u16::from_be_bytes([data[3], data[4]]) as usize;
+                    let sel_res = data[5] as usize;
+                    let uid_len = data[6] as usize;
+                    let uid = data
+                        .get(7..7 + uid_len)
+                        .filter(|uid| uid.len() <= MAX_UID_LEN)
+                        .ok_or((ErrorCode::FAIL, 0))?;
+                    self.copy_to_owner(rw_allow::UID, uid);
+                    self.complete(Ok((uid_len, sel_res | sens_res << 8)));
+                    Ok(())
+                }
+                _ => Err(ErrorCode::FAIL),
+            },
+            Step::Exchange => match data.get(1).map(|status| status & 0x3F) {
+                Some(0) => {
+                    let response = &data[2..];
+                    self.copy_to_owner(rw_allow::RESPONSE, response);
+                    self.complete(Ok((response.len(), 0)));
+                    Ok(())
+                }
+                Some(status) => return Err((ErrorCode::FAIL, status)),
+                None => Err(ErrorCode::FAIL),
+            },
+        };
+        res.map_err(|e| (e, 0))
+    }
+
+    /// Copy `data` to the read-write allow buffer `allow` of the owner, as
+    /// far as it fits.
+    fn copy_to_owner(&self, allow: usize, data: &[u8]) {
+        self.owner.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data
+                    .get_readwrite_processbuffer(allow)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|dest| {
+                            let len = cmp::min(dest.len(), data.len());
+                            dest[..len].copy_from_slice(&data[..len]);
+                        })
+                    });
+            });
+        });
+    }
+
+    /// End the operation and report its result.
+    fn complete(&self, result: Result<(usize, usize), (ErrorCode, u8)>) {
+        let upcall = match self.operation.get() {
+            Operation::Poll => upcall::TAG,
+            Operation::Exchange(_) => upcall::EXCHANGE,
+            Operation::Idle => return,
+        };
+        self.stop_timer();
+        let args = match result {
+            Ok((arg1, arg2)) => (0, arg1, arg2),
+            Err((e, code)) => (kernel::errorcode::into_statuscode(Err(e)), 0, code as usize),
+        };
+        if let Some(processid) = self.owner.take() {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, args).ok();
+            });
+        }
+        self.operation.set(Operation::Idle);
+    }
+
+    fn fail(&self, error: ErrorCode, code: u8) {
+        // The PN532 may still run the command, which an ACK frame aborts.
+        self.abort();
+        self.complete(Err((error, code)));
+    }
+
+    fn cancel(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if !self.owner.contains(&processid) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.stop_timer();
+        self.abort();
+        self.operation.set(Operation::Idle);
+        self.owner.clear();
+        Ok(())
+    }
+
+    /// Send an ACK frame, which makes the PN532 abort its command.
+    fn abort(&self) {
+        if self.phase.get() == Phase::Write {
+            return;
+        }
+        if let Some(buffer) = self.buffer.take() {
+            buffer[1..1 + ACK_FRAME.len()].copy_from_slice(&ACK_FRAME);
+            match self.bus.write(buffer, ACK_FRAME.len()) {
+                Ok(()) => self.phase.set(Phase::Write),
+                Err((_, buffer)) => {
+                    self.buffer.replace(buffer);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, B: Pn532Bus<'a>, A: Alarm<'a>> Pn532BusClient for Pn532<'a, B, A> {
+    fn write_done(&self, buffer: &'static mut [u8], status: Result<(), ErrorCode>) {
+        self.buffer.replace(buffer);
+        if self.operation.get() == Operation::Idle {
+            return;
+        }
+        match status {
+            Ok(()) => self.wait_ready(Phase::Ack),
+            Err(e) => self.fail(e, 0),
+        }
+    }
+
+    fn read_done(&self, buffer: &'static mut [u8], status: Result<bool, ErrorCode>) {
+        if self.operation.get() == Operation::Idle {
+            self.buffer.replace(buffer);
+            return;
+        }
+        match status {
+            Ok(true) => {}
+            Ok(false) => {
+                self.buffer.replace(buffer);
+                let polls_left = self.ready_polls_left.get();
+                if polls_left == 0 {
+                    return self.fail(ErrorCode::FAIL, 0);
+                }
+                self.ready_polls_left.set(polls_left - 1);
+                return self.set_timer(Timer::Ready, READY_POLL_MS);
+            }
+            Err(e) => {
+                self.buffer.replace(buffer);
+                return self.fail(e, 0);
+            }
+        }
+
+        let frame = &buffer[1..1 + self.frame_len()];
+        if self.phase.get() == Phase::Ack {
+            let acknowledged = frame == ACK_FRAME;
+            self.buffer.replace(buffer);
+            if acknowledged {
+                self.wait_ready(Phase::Response);
+            } else {
+                self.fail(ErrorCode::FAIL, 0);
+            }
+            return;
+        }
+
+        let mut data = [0; 2 + MAX_DATA_LEN];
+        let len = match decode_frame(frame) {
+            Some(decoded) if decoded.len() <= data.len() => {
+                data[..decoded.len()].copy_from_slice(decoded);
+                Some(decoded.len())
+            }
+            _ => None,
+        };
+        self.buffer.replace(buffer);
+        // The command completed, a new one can be sent.
+        self.phase.set(Phase::Write);
+        let res = len
+            .ok_or((ErrorCode::FAIL, 0))
+            .and_then(|len| self.response(&data[..len]));
+        if let Err((e, code)) = res {
+            self.fail(e, code);
+        }
+    }
+}
+
+impl<'a, B: Pn532Bus<'a>, A: Alarm<'a>> AlarmClient for Pn532<'a, B, A> {
+    fn alarm(&self) {
+        let res = match self.timer.replace(Timer::None) {
+            Timer::Ready => match self.buffer.take() {
+                Some(buffer) => self
+                    .bus
+                    .read(buffer, self.frame_len())
+                    .map_err(|(e, buffer)| {
+                        self.buffer.replace(buffer);
+                        e
+                    }),
+                None => Err(ErrorCode::BUSY),
+            },
+            Timer::Poll => self.send(Step::ListTarget),
+            Timer::None => Ok(()),
+        };
+        if let Err(e) = res {
+            self.fail(e, 0);
+        }
+    }
+}
+
+impl<'a, B: Pn532Bus<'a>, A: Alarm<'a>> SyscallDriver for Pn532<'a, B, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.start(Operation::Poll, processid).into(),
+
+            2 => {
+                if data > MAX_DATA_LEN {
+                    return CommandReturn::failure(ErrorCode::SIZE);
+                }
+                self.start(Operation::Exchange(data), processid).into()
+            }
+
+            3 => self.cancel(processid).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_frame() {
+        let mut buffer = [0xAA; 16];
+        let len = encode_frame(&mut buffer, &[command::SAM_CONFIGURATION, 0x01, 0x14, 0x01]);
+        assert_eq!(len, 12);
+        assert_eq!(
+            buffer[1..1 + len],
+            [0x00, 0x00, 0xFF, 0x05, 0xFB, 0xD4, 0x14, 0x01, 0x14, 0x01, 0x02, 0x00]
+        );
+    }
+
+    #[test]
+    fn decodes_frame() {
+        // InListPassiveTarget answer for a tag with a 4 byte UID, padded.
+        let frame = [
+            0x00, 0x00, 0xFF, 0x0C, 0xF4, 0xD5, 0x4B, 0x01, 0x01, 0x00, 0x04, 0x08, 0x04, 0xDE,
+            0xAD, 0xBE, 0xEF, 0x96, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            decode_frame(&frame),
+            Some(&[0x4B, 0x01, 0x01, 0x00, 0x04, 0x08, 0x04, 0xDE, 0xAD, 0xBE, 0xEF][..])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let frame = [0x00, 0x00, 0xFF, 0x02, 0xFE, 0xD5, 0x15, 0x16, 0x00];
+        assert_eq!(decode_frame(&frame), Some(&[0x15][..]));
+        let frame = [0x00, 0x00, 0xFF, 0x02, 0xFE, 0xD5, 0x15, 0x17, 0x00];
+        assert_eq!(decode_frame(&frame), None);
+        let frame = [0x00, 0x00, 0xFF, 0x02, 0xFD, 0xD5, 0x15, 0x16, 0x00];
+        assert_eq!(decode_frame(&frame), None);
+    }
+}
@@ -0,0 +1,239 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Runtime control of peripheral power domains by a management process.
+//!
+//! Boards group their peripherals into power domains (for example the radio,
+//! or the ADC and its clock), each with the drivers that depend on it. A
+//! privileged process can switch entire domains off to reach a lower sleep
+//! current in the field, and switch them back on later. Before a domain is
+//! switched off, each dependent driver is asked through
+//! `hil::power::PowerDependent::powering_off`, and any of them can keep the
+//! domain on by refusing. Other processes can query the state of the domains
+//! and be notified when it changes.
+//!
+//! [`ClockDomain`] is a power domain made of peripheral clocks, for chips
+//! without finer-grained power control.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let adc_domain = static_init!(
+//!     capsules_extra::power_control::ClockDomain<'static>,
+//!     capsules_extra::power_control::ClockDomain::new(adc_clocks)
+//! );
+//! let domains = static_init!(
+//!     [capsules_extra::power_control::Domain<'static>; 1],
+//!     [capsules_extra::power_control::Domain::new(adc_domain, adc_dependents)]
+//! );
+//! let power_control = static_init!(
+//!     capsules_extra::power_control::PowerControl<'static>,
+//!     capsules_extra::power_control::PowerControl::new(
+//!         domains,
+//!         kernel::process::ShortId::Fixed(core::num::NonZeroU32::new(0x1).unwrap()),
+//!         board_kernel.create_grant(capsules_extra::power_control::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! ```
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::power::{PowerDependent, PowerDomain};
+use kernel::platform::chip::ClockInterface;
+use kernel::process::ShortId;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::PowerControl as usize;
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// A domain was switched off or on.
+    pub const CHANGED: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// A power domain and the drivers that depend on it.
+pub struct Domain<'a> {
+    domain: &'a dyn PowerDomain,
+    dependents: &'a [&'a dyn PowerDependent],
+}
+
+impl<'a> Domain<'a> {
+    pub const fn new(
+        domain: &'a dyn PowerDomain,
+        dependents: &'a [&'a dyn PowerDependent],
+    ) -> Domain<'a> {
+        Domain { domain, dependents }
+    }
+}
+
+/// A power domain made of peripheral clocks: switching it off gates the
+/// clocks.
+pub struct ClockDomain<'a> {
+    clocks: &'a [&'a dyn ClockInterface],
+}
+
+impl<'a> ClockDomain<'a> {
+    pub const fn new(clocks: &'a [&'a dyn ClockInterface]) -> ClockDomain<'a> {
+        ClockDomain { clocks }
+    }
+}
+
+impl PowerDomain for ClockDomain<'_> {
+    fn power_off(&self) -> Result<(), ErrorCode> {
+        if !self.is_powered() {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.clocks.iter().for_each(|clock| clock.disable());
+        Ok(())
+    }
+
+    fn power_on(&self) -> Result<(), ErrorCode> {
+        if self.is_powered() {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.clocks.iter().for_each(|clock| clock.enable());
+        Ok(())
+    }
+
+    fn is_powered(&self) -> bool {
+        self.clocks.iter().any(|clock| clock.is_enabled())
+    }
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct PowerControl<'a> {
+    domains: &'a [Domain<'a>],
+    /// Only the process with this ShortId may switch domains off and on.
+    privileged: ShortId,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a> PowerControl<'a> {
+    pub fn new(
+        domains: &'a [Domain<'a>],
+        privileged: ShortId,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> PowerControl<'a> {
+        PowerControl {
+            domains,
+            privileged,
+            apps: grant,
+        }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        match self.privileged {
+            ShortId::Fixed(_) => processid.short_app_id() == self.privileged,
+            ShortId::LocallyUnique => false,
+        }
+    }
+
+    /// Switch a domain off, if none of its dependents refuses.
+    pub fn power_off(&self, index: usize) -> Result<(), ErrorCode> {
+        let domain = self.domains.get(index).ok_or(ErrorCode::INVAL)?;
+        if !domain.domain.is_powered() {
+            return Err(ErrorCode::ALREADY);
+        }
+
+        for (notified, dependent) in domain.dependents.iter().enumerate() {
+            if let Err(e) = dependent.powering_off() {
+                // Let the dependents that agreed resume.
+                domain.dependents[..notified]
+                    .iter()
+                    .for_each(|dependent| dependent.powered_on());
+                return Err(e);
+            }
+        }
+
+        if let Err(e) = domain.domain.power_off() {
+            domain
+                .dependents
+                .iter()
+                .for_each(|dependent| dependent.powered_on());
+            return Err(e);
+        }
+
+        self.notify_change(index, false);
+        Ok(())
+    }
+
+    /// Switch a domain back on and tell its dependents.
+    pub fn power_on(&self, index: usize) -> Result<(), ErrorCode> {
+        let domain = self.domains.get(index).ok_or(ErrorCode::INVAL)?;
+        domain.domain.power_on()?;
+        domain
+            .dependents
+            .iter()
+            .for_each(|dependent| dependent.powered_on());
+
+        self.notify_change(index, true);
+        Ok(())
+    }
+
+    fn notify_change(&self, index: usize, powered: bool) {
+        self.apps.each(|_, _, upcalls| {
+            upcalls
+                .schedule_upcall(upcall::CHANGED, (index, powered as usize, 0))
+                .ok();
+        });
+    }
+}
+
+impl SyscallDriver for PowerControl<'_> {
+    /// Control of the power domains.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Return the number of power domains.
+    /// - `2`: Return `1` if domain `data1` is on, `0` otherwise.
+    /// - `3`: Switch domain `data1` off. Only allowed for the privileged
+    ///   process.
+    /// - `4`: Switch domain `data1` on. Only allowed for the privileged
+    ///   process.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.domains.len() as u32),
+
+            2 => match self.domains.get(data1) {
+                Some(domain) => CommandReturn::success_u32(domain.domain.is_powered() as u32),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            3 | 4 => {
+                if !self.is_privileged(processid) {
+                    return CommandReturn::failure(ErrorCode::NOSUPPORT);
+                }
+                if command_num == 3 {
+                    self.power_off(data1).into()
+                } else {
+                    self.power_on(data1).into()
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,214 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Lets a privileged process enumerate the processes on the board.
+//!
+//! A supervisor process (e.g. a userspace watchdog or updater) can read the
+//! name, binary version, ShortId and state of every process the kernel has
+//! loaded. The names and versions come from the TBF headers of the
+//! processes. Processes are addressed by their index among the loaded
+//! processes, from `0` to the count returned by command `1`; the indices can
+//! change when processes are loaded or removed.
+//!
+//! Only the process with the configured ShortId can read this information,
+//! as it reveals which applications are installed. The capsule needs a
+//! `ProcessManagementCapability` to iterate over the processes.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let process_info = static_init!(
+//!     capsules_extra::process_info::ProcessInfo<ProcessMgmtCap>,
+//!     capsules_extra::process_info::ProcessInfo::new(
+//!         board_kernel,
+//!         ProcessMgmtCap,
+//!         ShortId::Fixed(core::num::NonZeroU32::new(0x5550).unwrap()),
+//!         board_kernel.create_grant(
+//!             capsules_extra::process_info::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::process::{Process, ShortId, State};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessInfo as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the name of a process is copied into.
+    pub const NAME: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// State of a process, as reported to userspace.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProcessState {
+    Running = 0,
+    Yielded = 1,
+    Stopped = 2,
+    Faulted = 3,
+    Terminated = 4,
+}
+
+impl From<State> for ProcessState {
+    fn from(state: State) -> ProcessState {
+        match state {
+            State::Running => ProcessState::Running,
+            State::Yielded => ProcessState::Yielded,
+            State::StoppedRunning | State::StoppedYielded => ProcessState::Stopped,
+            State::Faulted => ProcessState::Faulted,
+            State::Terminated => ProcessState::Terminated,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct ProcessInfo<C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    capability: C,
+    /// Only the process with this ShortId may read the process information.
+    privileged: ShortId,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl<C: ProcessManagementCapability> ProcessInfo<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        capability: C,
+        privileged: ShortId,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> ProcessInfo<C> {
+        ProcessInfo {
+            kernel,
+            capability,
+            privileged,
+            apps: grant,
+        }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        match self.privileged {
+            ShortId::Fixed(_) => processid.short_app_id() == self.privileged,
+            ShortId::LocallyUnique => false,
+        }
+    }
+
+    fn count(&self) -> usize {
+        let mut count = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |_| count += 1);
+        count
+    }
+
+    /// Call `f` with the process at `index` among the loaded processes.
+    fn with_process<R, F: FnOnce(&dyn Process) -> R>(&self, index: usize, f: F) -> Option<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        let mut current = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if current == index {
+                    result = f.take().map(|f| f(process));
+                }
+                current += 1;
+            });
+        result
+    }
+
+    /// Copy the name of the process at `index` into the name buffer of
+    /// `processid`, truncated to the length of the buffer. Returns the length
+    /// of the full name.
+    fn copy_name(&self, index: usize, processid: ProcessId) -> Result<usize, ErrorCode> {
+        let name = self
+            .with_process(index, |process| process.get_process_name())
+            .ok_or(ErrorCode::INVAL)?;
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::NAME)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|dest| {
+                            let length = name.len().min(dest.len());
+                            dest[0..length].copy_from_slice(&name.as_bytes()[0..length]);
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+            .map(|()| name.len())
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for ProcessInfo<C> {
+    /// Process information.
+    ///
+    /// All commands but `0` are only allowed for the privileged process.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Return the number of loaded processes.
+    /// - `2`: Return the ShortId (`0` if the process has none), the binary
+    ///   version (`0` if the TBF header has none) and the state of the process
+    ///   at index `arg1`.
+    /// - `3`: Copy the name of the process at index `arg1` into the `allow`
+    ///   buffer, truncated to the length of the buffer, and return the length
+    ///   of the full name.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.is_privileged(processid) {
+            return CommandReturn::failure(ErrorCode::NOSUPPORT);
+        }
+
+        match command_num {
+            1 => CommandReturn::success_u32(self.count() as u32),
+
+            2 => self
+                .with_process(arg1, |process| {
+                    let short_id = match process.short_app_id() {
+                        ShortId::Fixed(id) => id.get(),
+                        ShortId::LocallyUnique => 0,
+                    };
+                    let version = process.binary_version().map_or(0, |v| v.get());
+                    let state = ProcessState::from(process.get_state());
+                    CommandReturn::success_u32_u32_u32(short_id, version, state as u32)
+                })
+                .unwrap_or(CommandReturn::failure(ErrorCode::INVAL)),
+
+            3 => match self.copy_name(arg1, processid) {
+                Ok(length) => CommandReturn::success_u32(length as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,303 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! RS-485 half-duplex direction control for a UART.
+//!
+//! An RS-485 transceiver only drives the bus while its driver enable (DE)
+//! input is asserted, and the receiver is usually disabled at the same time
+//! (/RE tied to DE). This capsule wraps a UART and asserts the DE pin around
+//! each transmission, so the bus is released for the other nodes as soon as
+//! the last word is sent. It implements the UART HIL itself, so it can sit
+//! below a UART mux or a protocol capsule such as Modbus.
+//!
+//! The DE pin is asserted `pre_delay_us` before the transmission, to give the
+//! transceiver and the other nodes time to turn around, and released
+//! `post_delay_us` after it. If the UART reports the end of a transmission
+//! before the stop bit of the last word left the shift register, the post
+//! delay must cover one word time.
+//!
+//! Reception is not affected: receive calls go to the UART directly.
+//!
+//! On multi-drop buses that use a ninth bit to mark address words, configure
+//! the UART with `Width::Nine` and send words with `transmit_word`, if the
+//! UART supports it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let rs485 = components::rs485::Rs485Component::new(
+//!     &base_peripherals.usart3,
+//!     mux_alarm,
+//!     driver_enable_pin,
+//!     20,
+//!     0,
+//! )
+//! .finalize(components::rs485_component_static!(
+//!     stm32f429zi::usart::Usart<'static, stm32f429zi::dma::Dma1<'static>>,
+//!     stm32f429zi::tim2::Tim2
+//! ));
+//! let uart_mux = components::console::UartMuxComponent::new(rs485, 19200)
+//!     .finalize(components::uart_mux_component_static!());
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::time::{self, Alarm, ConvertTicks};
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Transfer {
+    Buffer,
+    Word(u32),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// DE is asserted, waiting before the transmission.
+    PreDelay(Transfer),
+    Transmitting,
+    /// The transmission completed, waiting before releasing DE.
+    PostDelay(Transfer),
+}
+
+pub struct Rs485<'a, U: uart::Uart<'a>, A: Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    driver_enable: &'a dyn gpio::Pin,
+    pre_delay_us: Cell<u32>,
+    post_delay_us: Cell<u32>,
+    state: Cell<State>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_result: Cell<Result<(), ErrorCode>>,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> Rs485<'a, U, A> {
+    pub fn new(
+        uart: &'a U,
+        alarm: &'a A,
+        driver_enable: &'a dyn gpio::Pin,
+        pre_delay_us: u32,
+        post_delay_us: u32,
+    ) -> Rs485<'a, U, A> {
+        driver_enable.make_output();
+        driver_enable.clear();
+        Rs485 {
+            uart,
+            alarm,
+            driver_enable,
+            pre_delay_us: Cell::new(pre_delay_us),
+            post_delay_us: Cell::new(post_delay_us),
+            state: Cell::new(State::Idle),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_result: Cell::new(Ok(())),
+            tx_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Change the delays between DE and the transmission. They apply from
+    /// the next transmission.
+    pub fn set_delays(&self, pre_delay_us: u32, post_delay_us: u32) {
+        self.pre_delay_us.set(pre_delay_us);
+        self.post_delay_us.set(post_delay_us);
+    }
+
+    fn wait(&self, us: u32) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(us));
+    }
+
+    /// Start the transmission that waited for the pre delay.
+    fn start(&self, transfer: Transfer) -> Result<(), ErrorCode> {
+        match transfer {
+            Transfer::Buffer => {
+                let buffer = self.tx_buffer.take().ok_or(ErrorCode::FAIL)?;
+                self.uart
+                    .transmit_buffer(buffer, self.tx_len.get())
+                    .map_err(|(e, buffer)| {
+                        self.tx_buffer.replace(buffer);
+                        e
+                    })
+            }
+            Transfer::Word(word) => self.uart.transmit_word(word),
+        }
+    }
+
+    /// Wait for the post delay after a transmission.
+    fn transmitted(&self, transfer: Transfer, rval: Result<(), ErrorCode>) {
+        let post_delay_us = self.post_delay_us.get();
+        if post_delay_us == 0 {
+            self.finish(transfer, rval);
+        } else {
+            self.tx_result.set(rval);
+            self.state.set(State::PostDelay(transfer));
+            self.wait(post_delay_us);
+        }
+    }
+
+    /// Release the bus and notify the client.
+    fn finish(&self, transfer: Transfer, rval: Result<(), ErrorCode>) {
+        self.driver_enable.clear();
+        self.state.set(State::Idle);
+        self.tx_client.map(|client| match transfer {
+            Transfer::Buffer => {
+                if let Some(buffer) = self.tx_buffer.take() {
+                    client.transmitted_buffer(buffer, self.tx_len.get(), rval);
+                }
+            }
+            Transfer::Word(_) => client.transmitted_word(rval),
+        });
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> uart::Configure for Rs485<'a, U, A> {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.uart.configure(params)
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> uart::Transmit<'a> for Rs485<'a, U, A> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        if tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+
+        self.driver_enable.set();
+        self.tx_len.set(tx_len);
+        let pre_delay_us = self.pre_delay_us.get();
+        if pre_delay_us == 0 {
+            self.state.set(State::Transmitting);
+            self.uart
+                .transmit_buffer(tx_buffer, tx_len)
+                .map_err(|(e, tx_buffer)| {
+                    self.driver_enable.clear();
+                    self.state.set(State::Idle);
+                    (e, tx_buffer)
+                })?;
+        } else {
+            self.tx_buffer.replace(tx_buffer);
+            self.state.set(State::PreDelay(Transfer::Buffer));
+            self.wait(pre_delay_us);
+        }
+        Ok(())
+    }
+
+    fn transmit_word(&self, word: u32) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.driver_enable.set();
+        let pre_delay_us = self.pre_delay_us.get();
+        if pre_delay_us == 0 {
+            self.state.set(State::Transmitting);
+            self.uart.transmit_word(word).map_err(|e| {
+                self.driver_enable.clear();
+                self.state.set(State::Idle);
+                e
+            })?;
+        } else {
+            self.state.set(State::PreDelay(Transfer::Word(word)));
+            self.wait(pre_delay_us);
+        }
+        Ok(())
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle => Ok(()),
+            State::PreDelay(transfer) => {
+                // Nothing was sent, complete with CANCEL from the alarm.
+                self.tx_len.set(0);
+                self.tx_result.set(Err(ErrorCode::CANCEL));
+                self.state.set(State::PostDelay(transfer));
+                self.wait(0);
+                Err(ErrorCode::BUSY)
+            }
+            State::Transmitting => {
+                let result = self.uart.transmit_abort();
+                if result.is_ok() {
+                    self.driver_enable.clear();
+                    self.state.set(State::Idle);
+                }
+                result
+            }
+            // The transmission is complete, the callback follows the delay.
+            State::PostDelay(_) => Err(ErrorCode::FAIL),
+        }
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> uart::Receive<'a> for Rs485<'a, U, A> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.uart.set_receive_client(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.uart.receive_buffer(rx_buffer, rx_len)
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_word()
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_abort()
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> uart::TransmitClient for Rs485<'a, U, A> {
+    fn transmitted_word(&self, rval: Result<(), ErrorCode>) {
+        self.transmitted(Transfer::Word(0), rval);
+    }
+
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        self.tx_len.set(tx_len);
+        self.transmitted(Transfer::Buffer, rval);
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> time::AlarmClient for Rs485<'a, U, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::PreDelay(transfer) => {
+                self.state.set(State::Transmitting);
+                if let Err(e) = self.start(transfer) {
+                    self.tx_len.set(0);
+                    self.finish(transfer, Err(e));
+                }
+            }
+            State::PostDelay(transfer) => self.finish(transfer, self.tx_result.get()),
+            State::Idle | State::Transmitting => {}
+        }
+    }
+}
@@ -0,0 +1,574 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides userspace with a weight scale built on a load cell.
+//!
+//! The capsule converts the raw readings of a `hil::sensors::LoadCellDriver`
+//! to a weight with a linear calibration:
+//!
+//! ```text
+//! weight = (reading - tare) * reference_weight / reference_counts
+//! ```
+//!
+//! The tare is the reading of the empty scale. The slope is measured by
+//! placing a known reference weight on the scale, which gives
+//! `reference_counts` above the tare. Both are averaged over
+//! `CALIBRATION_SAMPLES` readings and saved in the key-value store, so the
+//! scale stays calibrated across reboots. `load_calibration()` restores
+//! them at boot.
+//!
+//! Each process chooses the unit its readings are reported in.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! - `0`: Weight reading. The upcall receives the status and the weight, as
+//!   an `i32`, in the unit of the process.
+//! - `1`: Tare or calibration complete. The upcall receives the status.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Read the weight. Fails with `INVAL` in the upcall if the scale was
+//!   never calibrated.
+//! - `2`: Tare: take the current load as zero.
+//! - `3`: Calibrate the slope with a reference weight of `data` milligrams
+//!   placed on the tared scale.
+//! - `4`: Set the unit of the readings of this process: `0` milligrams, `1`
+//!   grams, `2` hundredths of an ounce, `3` thousandths of a pound.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let scale = components::scale::ScaleComponent::new(
+//!     board_kernel,
+//!     capsules_extra::scale::DRIVER_NUM,
+//!     hx711,
+//!     kv_scale,
+//! )
+//! .finalize(components::scale_component_static!(
+//!     capsules_extra::hx711::Hx711<'static>,
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<'static, KVStorePermissionsType>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::kv;
+use kernel::hil::sensors::{LoadCellClient, LoadCellDriver};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Scale as usize;
+
+/// Key of the calibration in the key-value store.
+pub const KEY: &[u8] = b"scale.calibration";
+/// Size of the buffer holding the calibration and the header of the
+/// key-value store.
+pub const VALUE_BUFFER_LEN: usize = 64;
+
+/// Number of readings averaged by a tare or a calibration.
+pub const CALIBRATION_SAMPLES: usize = 8;
+
+/// Length of an encoded calibration.
+const CALIBRATION_LEN: usize = 12;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A weight reading completed.
+    pub const WEIGHT: usize = 0;
+    /// A tare or a calibration was saved.
+    pub const CALIBRATED: usize = 1;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 2;
+}
+
+/// Unit of the weights reported to a process.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Unit {
+    #[default]
+    Milligram,
+    Gram,
+    /// Hundredths of an avoirdupois ounce.
+    CentiOunce,
+    /// Thousandths of an avoirdupois pound.
+    MilliPound,
+}
+
+impl Unit {
+    fn from_usize(unit: usize) -> Option<Unit> {
+        match unit {
+            0 => Some(Unit::Milligram),
+            1 => Some(Unit::Gram),
+            2 => Some(Unit::CentiOunce),
+            3 => Some(Unit::MilliPound),
+            _ => None,
+        }
+    }
+
+    /// Convert a weight in milligrams to this unit, rounding to the nearest.
+    pub fn convert(self, milligrams: i64) -> i64 {
+        // Milligrams in 100000 units.
+        let divisor: i64 = match self {
+            Unit::Milligram => return milligrams,
+            Unit::Gram => 100_000_000,
+            Unit::CentiOunce => 28_349_523,
+            Unit::MilliPound => 45_359_237,
+        };
+        let scaled = milligrams * 100_000;
+        if scaled >= 0 {
+            (scaled + divisor / 2) / divisor
+        } else {
+            (scaled - divisor / 2) / divisor
+        }
+    }
+}
+
+/// Linear calibration of the load cell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Calibration {
+    /// Reading of the empty scale.
+    pub tare: i32,
+    /// Reading of the reference weight, above the tare.
+    pub reference_counts: i32,
+    /// The reference weight, in milligrams.
+    pub reference_milligrams: u32,
+}
+
+impl Calibration {
+    /// Return the weight in milligrams corresponding to `reading`, or `None`
+    /// if the slope was never calibrated.
+    pub fn milligrams(&self, reading: i32) -> Option<i64> {
+        if self.reference_counts == 0 {
+            return None;
+        }
+        Some(
+            (reading as i64 - self.tare as i64) * self.reference_milligrams as i64
+                / self.reference_counts as i64,
+        )
+    }
+
+    fn encode(&self, buffer: &mut [u8]) {
+        buffer[0..4].copy_from_slice(&self.tare.to_le_bytes());
+        buffer[4..8].copy_from_slice(&self.reference_counts.to_le_bytes());
+        buffer[8..12].copy_from_slice(&self.reference_milligrams.to_le_bytes());
+    }
+
+    fn decode(buffer: &[u8]) -> Option<Calibration> {
+        let field = |i: usize| buffer.get(i..i + 4)?.try_into().ok();
+        Some(Calibration {
+            tare: i32::from_le_bytes(field(0)?),
+            reference_counts: i32::from_le_bytes(field(4)?),
+            reference_milligrams: u32::from_le_bytes(field(8)?),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Operation {
+    Idle,
+    /// Restoring the calibration from the key-value store.
+    Loading,
+    Reading,
+    Tare,
+    /// Calibrating with a reference weight in milligrams.
+    Calibrate(u32),
+    /// Saving the calibration to the key-value store.
+    Saving,
+}
+
+#[derive(Default)]
+pub struct App {
+    unit: Unit,
+    reading: bool,
+}
+
+pub struct Scale<'a, L: LoadCellDriver<'a>, K: kv::KVPermissions<'a>> {
+    load_cell: &'a L,
+    kv: &'a K,
+    permissions: StoragePermissions,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    calibration: Cell<Calibration>,
+    operation: Cell<Operation>,
+    /// Readings left to average and their sum.
+    samples: Cell<usize>,
+    sum: Cell<i64>,
+    /// The process that requested the tare or the calibration.
+    calibrating: OptionalCell<ProcessId>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, L: LoadCellDriver<'a>, K: kv::KVPermissions<'a>> Scale<'a, L, K> {
+    /// `key_buffer` must hold `KEY`, and `value_buffer` must be
+    /// `VALUE_BUFFER_LEN` long.
+    pub fn new(
+        load_cell: &'a L,
+        kv: &'a K,
+        permissions: StoragePermissions,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+    ) -> Scale<'a, L, K> {
+        Scale {
+            load_cell,
+            kv,
+            permissions,
+            apps: grant,
+            calibration: Cell::new(Calibration::default()),
+            operation: Cell::new(Operation::Idle),
+            samples: Cell::new(0),
+            sum: Cell::new(0),
+            calibrating: OptionalCell::empty(),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    fn key(&self) -> Result<SubSliceMut<'static, u8>, ErrorCode> {
+        let key_buffer = self.key_buffer.take().ok_or(ErrorCode::BUSY)?;
+        key_buffer[..KEY.len()].copy_from_slice(KEY);
+        let mut key = SubSliceMut::new(key_buffer);
+        key.slice(..KEY.len());
+        Ok(key)
+    }
+
+    /// Restore the calibration saved in the key-value store.
+    pub fn load_calibration(&self) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let key = self.key()?;
+        let value = match self.value_buffer.take() {
+            Some(value_buffer) => SubSliceMut::new(value_buffer),
+            None => {
+                self.key_buffer.replace(key.take());
+                return Err(ErrorCode::BUSY);
+            }
+        };
+        match self.kv.get(key, value, self.permissions) {
+            Ok(()) => {
+                self.operation.set(Operation::Loading);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key_buffer.replace(key.take());
+                self.value_buffer.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Start the readings of `operation`.
+    fn start(&self, operation: Operation, samples: usize) -> Result<(), ErrorCode> {
+        self.load_cell.read_load()?;
+        self.operation.set(operation);
+        self.samples.set(samples);
+        self.sum.set(0);
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), ErrorCode> {
+        let key = self.key()?;
+        let value_buffer = match self.value_buffer.take() {
+            Some(value_buffer) => value_buffer,
+            None => {
+                self.key_buffer.replace(key.take());
+                return Err(ErrorCode::BUSY);
+            }
+        };
+        let header_size = self.kv.header_size();
+        self.calibration
+            .get()
+            .encode(&mut value_buffer[header_size..header_size + CALIBRATION_LEN]);
+        let mut value = SubSliceMut::new(value_buffer);
+        value.slice(..header_size + CALIBRATION_LEN);
+        match self.kv.set(key, value, self.permissions) {
+            Ok(()) => {
+                self.operation.set(Operation::Saving);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key_buffer.replace(key.take());
+                self.value_buffer.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Notify the process that requested the tare or calibration.
+    fn calibration_done(&self, result: Result<(), ErrorCode>) {
+        self.operation.set(Operation::Idle);
+        if let Some(processid) = self.calibrating.take() {
+            let _ = self.apps.enter(processid, |_, upcalls| {
+                upcalls
+                    .schedule_upcall(
+                        upcall::CALIBRATED,
+                        (kernel::errorcode::into_statuscode(result), 0, 0),
+                    )
+                    .ok();
+            });
+        }
+        self.start_pending_reading();
+    }
+
+    /// Start a reading if a process waits for one.
+    fn start_pending_reading(&self) {
+        let pending = self
+            .apps
+            .iter()
+            .any(|cntr| cntr.enter(|app, _| app.reading));
+        if pending {
+            if let Err(e) = self.start(Operation::Reading, 1) {
+                self.deliver_weight(Err(e));
+            }
+        }
+    }
+
+    fn deliver_weight(&self, reading: Result<i32, ErrorCode>) {
+        let milligrams = reading.and_then(|reading| {
+            self.calibration
+                .get()
+                .milligrams(reading)
+                .ok_or(ErrorCode::INVAL)
+        });
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, upcalls| {
+                if app.reading {
+                    app.reading = false;
+                    let result = match milligrams {
+                        Ok(milligrams) => (
+                            kernel::errorcode::into_statuscode(Ok(())),
+                            app.unit.convert(milligrams) as i32 as usize,
+                            0,
+                        ),
+                        Err(e) => (kernel::errorcode::into_statuscode(Err(e)), 0, 0),
+                    };
+                    upcalls.schedule_upcall(upcall::WEIGHT, result).ok();
+                }
+            })
+        }
+    }
+
+    fn read(&self, processid: ProcessId) -> CommandReturn {
+        self.apps
+            .enter(processid, |app, _| {
+                if self.operation.get() == Operation::Idle {
+                    if let Err(e) = self.start(Operation::Reading, 1) {
+                        return CommandReturn::failure(e);
+                    }
+                }
+                // Other operations start the reading when they complete.
+                app.reading = true;
+                CommandReturn::success()
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+
+    fn calibrate(&self, processid: ProcessId, operation: Operation) -> CommandReturn {
+        if self.operation.get() != Operation::Idle {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        match self.start(operation, CALIBRATION_SAMPLES) {
+            Ok(()) => {
+                self.calibrating.set(processid);
+                CommandReturn::success()
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+}
+
+impl<'a, L: LoadCellDriver<'a>, K: kv::KVPermissions<'a>> LoadCellClient for Scale<'a, L, K> {
+    fn callback(&self, load: Result<i32, ErrorCode>) {
+        let operation = self.operation.get();
+        match operation {
+            Operation::Reading => {
+                self.operation.set(Operation::Idle);
+                self.deliver_weight(load);
+                // Processes that asked during the reading get the next one.
+                self.start_pending_reading();
+            }
+            Operation::Tare | Operation::Calibrate(_) => {
+                let load = match load {
+                    Ok(load) => load,
+                    Err(e) => return self.calibration_done(Err(e)),
+                };
+                self.sum.set(self.sum.get() + load as i64);
+                let samples = self.samples.get() - 1;
+                self.samples.set(samples);
+                if samples > 0 {
+                    if let Err(e) = self.load_cell.read_load() {
+                        self.calibration_done(Err(e));
+                    }
+                    return;
+                }
+
+                let average = (self.sum.get() / CALIBRATION_SAMPLES as i64) as i32;
+                let mut calibration = self.calibration.get();
+                if let Operation::Calibrate(milligrams) = operation {
+                    let counts = average.wrapping_sub(calibration.tare);
+                    if counts == 0 {
+                        return self.calibration_done(Err(ErrorCode::INVAL));
+                    }
+                    calibration.reference_counts = counts;
+                    calibration.reference_milligrams = milligrams;
+                } else {
+                    calibration.tare = average;
+                }
+                self.calibration.set(calibration);
+                if let Err(e) = self.save() {
+                    self.calibration_done(Err(e));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, L: LoadCellDriver<'a>, K: kv::KVPermissions<'a>> kv::KVClient for Scale<'a, L, K> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        if result.is_ok() {
+            if let Some(calibration) = Calibration::decode(value.as_slice()) {
+                self.calibration.set(calibration);
+            }
+        }
+        self.value_buffer.replace(value.take());
+        self.operation.set(Operation::Idle);
+        self.start_pending_reading();
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.calibration_done(result);
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+    }
+}
+
+impl<'a, L: LoadCellDriver<'a>, K: kv::KVPermissions<'a>> SyscallDriver for Scale<'a, L, K> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.read(processid),
+
+            2 => self.calibrate(processid, Operation::Tare),
+
+            3 => match u32::try_from(data) {
+                Ok(milligrams) if milligrams > 0 => {
+                    self.calibrate(processid, Operation::Calibrate(milligrams))
+                }
+                _ => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            4 => match Unit::from_usize(data) {
+                Some(unit) => self
+                    .apps
+                    .enter(processid, |app, _| {
+                        app.unit = unit;
+                        CommandReturn::success()
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_slope() {
+        let calibration = Calibration {
+            tare: 1000,
+            reference_counts: 20000,
+            reference_milligrams: 500_000,
+        };
+        assert_eq!(calibration.milligrams(1000), Some(0));
+        assert_eq!(calibration.milligrams(21000), Some(500_000));
+        assert_eq!(calibration.milligrams(-9000), Some(-250_000));
+        assert_eq!(Calibration::default().milligrams(1000), None);
+    }
+
+    #[test]
+    fn calibration_encoding() {
+        let calibration = Calibration {
+            tare: -123456,
+            reference_counts: 98765,
+            reference_milligrams: 1_000_000,
+        };
+        let mut buffer = [0; CALIBRATION_LEN];
+        calibration.encode(&mut buffer);
+        assert_eq!(Calibration::decode(&buffer), Some(calibration));
+        assert_eq!(Calibration::decode(&buffer[..8]), None);
+    }
+
+    #[test]
+    fn unit_conversion() {
+        assert_eq!(Unit::Milligram.convert(1234), 1234);
+        assert_eq!(Unit::Gram.convert(1500), 2);
+        assert_eq!(Unit::Gram.convert(-1499), -1);
+        // One ounce and one pound.
+        assert_eq!(Unit::CentiOunce.convert(28350), 100);
+        assert_eq!(Unit::MilliPound.convert(453_592), 1000);
+    }
+}
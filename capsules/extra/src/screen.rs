@@ -13,6 +13,14 @@
 //! let screen =
 //!     components::screen::ScreenComponent::new(board_kernel, tft).finalize();
 //! ```
+//!
+//! If the screen also provides `hil::screen::ScreenAccelerated`, fills and
+//! writes use it whenever possible and fall back to software otherwise:
+//!
+//! ```rust,ignore
+//! screen.set_accelerated(dma2d);
+//! kernel::hil::screen::ScreenAccelerated::set_client(dma2d, screen);
+//! ```
 
 use core::cell::Cell;
 
@@ -93,6 +101,8 @@ pub struct App {
     write_position: usize,
     write_len: usize,
     command: ScreenCommand,
+    x: usize,
+    y: usize,
     width: usize,
     height: usize,
 }
@@ -102,6 +112,8 @@ impl Default for App {
         App {
             pending_command: false,
             command: ScreenCommand::Nop,
+            x: 0,
+            y: 0,
             width: 0,
             height: 0,
             write_len: 0,
@@ -113,6 +125,7 @@ impl Default for App {
 pub struct Screen<'a> {
     screen: &'a dyn hil::screen::Screen<'a>,
     screen_setup: Option<&'a dyn hil::screen::ScreenSetup<'a>>,
+    accelerated: OptionalCell<&'a dyn hil::screen::ScreenAccelerated<'a>>,
     apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
     current_process: OptionalCell<ProcessId>,
     pixel_format: Cell<ScreenPixelFormat>,
@@ -129,6 +142,7 @@ impl<'a> Screen<'a> {
         Screen {
             screen: screen,
             screen_setup: screen_setup,
+            accelerated: OptionalCell::empty(),
             apps: grant,
             current_process: OptionalCell::empty(),
             pixel_format: Cell::new(screen.get_pixel_format()),
@@ -136,6 +150,11 @@ impl<'a> Screen<'a> {
         }
     }
 
+    /// Use `accelerated` for fills and writes when it supports them.
+    pub fn set_accelerated(&self, accelerated: &'a dyn hil::screen::ScreenAccelerated<'a>) {
+        self.accelerated.set(accelerated);
+    }
+
     // Check to see if we are doing something. If not,
     // go ahead and do this command. If so, this is queued
     // and will be run when the pending command completes.
@@ -205,9 +224,10 @@ impl<'a> Screen<'a> {
                 match self
                     .apps
                     .enter(process_id, |app, kernel_data| {
-                        let len = kernel_data
-                            .get_readonly_processbuffer(ro_allow::SHARED)
-                            .map_or(0, |shared| shared.len());
+                        let bytes_per_pixel =
+                            pixels_in_bytes(1, self.pixel_format.get().get_bits_per_pixel());
+                        let shared = kernel_data.get_readonly_processbuffer(ro_allow::SHARED);
+                        let len = shared.as_ref().map_or(0, |shared| shared.len());
                         // Ensure we have a buffer that is the correct size
                         if len == 0 {
                             Err(ErrorCode::NOMEM)
@@ -219,24 +239,37 @@ impl<'a> Screen<'a> {
                                 app.width * app.height,
                                 self.pixel_format.get().get_bits_per_pixel(),
                             );
-                            Ok(())
+                            // The first pixel of the buffer, as expected by
+                            // the accelerated fill.
+                            let color = shared
+                                .and_then(|shared| {
+                                    shared.enter(|data| {
+                                        data.iter().take(bytes_per_pixel).enumerate().fold(
+                                            0,
+                                            |color, (i, byte)| {
+                                                color | (byte.get() as u32) << (8 * i)
+                                            },
+                                        )
+                                    })
+                                })
+                                .unwrap_or(0);
+                            Ok((app.x, app.y, app.width, app.height, color))
                         }
                     })
-                    .unwrap_or_else(|err| err.into())
+                    .unwrap_or_else(|err| Err(err.into()))
                 {
                     Err(e) => Err(e),
-                    Ok(()) => self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
-                        let len = self.fill_next_buffer_for_write(buffer);
-                        if len > 0 {
-                            let mut data = SubSliceMut::new(buffer);
-                            data.slice(..len);
-                            self.screen.write(data, false)
-                        } else {
-                            self.buffer.replace(buffer);
-                            self.run_next_command(kernel::errorcode::into_statuscode(Ok(())), 0, 0);
-                            Ok(())
-                        }
-                    }),
+                    Ok((x, y, width, height, color)) => match self
+                        .accelerated
+                        .map_or(Err(ErrorCode::NOSUPPORT), |accelerated| {
+                            accelerated.fill(x, y, width, height, color)
+                        }) {
+                        Err(ErrorCode::NOSUPPORT) => self
+                            .buffer
+                            .take()
+                            .map_or(Err(ErrorCode::NOMEM), |buffer| self.software_write(buffer)),
+                        r => r,
+                    },
                 }
             }
 
@@ -261,18 +294,18 @@ impl<'a> Screen<'a> {
                     })
                     .unwrap_or_else(|err| err.into())
                 {
-                    Ok(()) => self.buffer.take().map_or(Err(ErrorCode::FAIL), |buffer| {
-                        let len = self.fill_next_buffer_for_write(buffer);
-                        if len > 0 {
-                            let mut data = SubSliceMut::new(buffer);
-                            data.slice(..len);
-                            self.screen.write(data, false)
-                        } else {
-                            self.buffer.replace(buffer);
-                            self.run_next_command(kernel::errorcode::into_statuscode(Ok(())), 0, 0);
-                            Ok(())
-                        }
-                    }),
+                    Ok(()) => {
+                        self.buffer.take().map_or(Err(ErrorCode::FAIL), |buffer| {
+                            match self.start_accelerated_write(buffer) {
+                                Ok(()) => Ok(()),
+                                Err((ErrorCode::NOSUPPORT, buffer)) => self.software_write(buffer),
+                                Err((e, buffer)) => {
+                                    self.buffer.replace(buffer);
+                                    Err(e)
+                                }
+                            }
+                        })
+                    }
                     Err(e) => Err(e),
                 }
             }
@@ -285,6 +318,8 @@ impl<'a> Screen<'a> {
                 .apps
                 .enter(process_id, |app, _| {
                     app.write_position = 0;
+                    app.x = x;
+                    app.y = y;
                     app.width = width;
                     app.height = height;
 
@@ -295,6 +330,109 @@ impl<'a> Screen<'a> {
         }
     }
 
+    /// Send the first chunk of the current fill or write through the
+    /// screen.
+    fn software_write(&self, buffer: &'static mut [u8]) -> Result<(), ErrorCode> {
+        let len = self.fill_next_buffer_for_write(buffer);
+        if len > 0 {
+            let mut data = SubSliceMut::new(buffer);
+            data.slice(..len);
+            self.screen.write(data, false)
+        } else {
+            self.buffer.replace(buffer);
+            self.run_next_command(kernel::errorcode::into_statuscode(Ok(())), 0, 0);
+            Ok(())
+        }
+    }
+
+    /// Start writing the shared buffer through the accelerator, whole rows of
+    /// the write frame at a time. Fails with `NOSUPPORT` if the write cannot
+    /// be split in rows that fit in `buffer`.
+    fn start_accelerated_write(
+        &self,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let bits_per_pixel = self.pixel_format.get().get_bits_per_pixel();
+        let rows_fit = self.current_process.map_or(false, |process_id| {
+            self.apps
+                .enter(process_id, |app, _| {
+                    let row_len = pixels_in_bytes(app.width, bits_per_pixel);
+                    row_len > 0 && row_len <= buffer.len() && app.write_len % row_len == 0
+                })
+                .unwrap_or(false)
+        });
+        if self.accelerated.is_none() || bits_per_pixel % 8 != 0 || !rows_fit {
+            return Err((ErrorCode::NOSUPPORT, buffer));
+        }
+
+        self.blit_next_rows(buffer).map_err(|(e, buffer)| {
+            // Let the software write start from the beginning.
+            self.current_process.map(|process_id| {
+                let _ = self.apps.enter(process_id, |app, _| app.write_position = 0);
+            });
+            (e, buffer)
+        })
+    }
+
+    /// Copy the next rows of the current write into `buffer` and pass them to
+    /// the accelerator.
+    fn blit_next_rows(
+        &self,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let bits_per_pixel = self.pixel_format.get().get_bits_per_pixel();
+        let rows = self.current_process.map_or(None, |process_id| {
+            self.apps
+                .enter(process_id, |app, kernel_data| {
+                    let row_len = pixels_in_bytes(app.width, bits_per_pixel);
+                    let position = app.write_position;
+                    let len = (buffer.len() / row_len * row_len).min(app.write_len - position);
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::SHARED)
+                        .and_then(|shared| {
+                            shared.enter(|data| {
+                                data.get(position..position + len)
+                                    .map(|rows| rows.copy_to_slice(&mut buffer[..len]))
+                            })
+                        })
+                        .ok()
+                        .flatten()
+                        .map(|()| {
+                            app.write_position += len;
+                            (
+                                len,
+                                app.x,
+                                app.y + position / row_len,
+                                app.width,
+                                len / row_len,
+                            )
+                        })
+                })
+                .ok()
+                .flatten()
+        });
+
+        match (rows, self.accelerated.get()) {
+            (Some((len, x, y, width, height)), Some(accelerated)) => {
+                let mut data = SubSliceMut::new(buffer);
+                data.slice(..len);
+                accelerated
+                    .blit(data, x, y, width, height)
+                    .map_err(|(e, data)| (e, data.take()))
+            }
+            _ => Err((ErrorCode::FAIL, buffer)),
+        }
+    }
+
+    /// Whether the current write still has data to send.
+    fn write_remaining(&self) -> bool {
+        self.current_process.map_or(false, |process_id| {
+            self.apps
+                .enter(process_id, |app, _| app.write_position < app.write_len)
+                .unwrap_or(false)
+        })
+    }
+
     fn schedule_callback(&self, data1: usize, data2: usize, data3: usize) {
         self.current_process.take().map(|process_id| {
             let _ = self.apps.enter(process_id, |app, upcalls| {
@@ -445,6 +583,29 @@ impl<'a> hil::screen::ScreenClient for Screen<'a> {
     }
 }
 
+impl<'a> hil::screen::ScreenAcceleratedClient for Screen<'a> {
+    fn fill_complete(&self, r: Result<(), ErrorCode>) {
+        self.run_next_command(kernel::errorcode::into_statuscode(r), 0, 0);
+    }
+
+    fn blit_complete(&self, data: SubSliceMut<'static, u8>, r: Result<(), ErrorCode>) {
+        let buffer = data.take();
+        let r = if r.is_ok() && self.write_remaining() {
+            match self.blit_next_rows(buffer) {
+                Ok(()) => return,
+                Err((e, buffer)) => {
+                    self.buffer.replace(buffer);
+                    Err(e)
+                }
+            }
+        } else {
+            self.buffer.replace(buffer);
+            r
+        };
+        self.run_next_command(kernel::errorcode::into_statuscode(r), 0, 0);
+    }
+}
+
 impl<'a> hil::screen::ScreenSetupClient for Screen<'a> {
     fn command_complete(&self, r: Result<(), ErrorCode>) {
         self.run_next_command(kernel::errorcode::into_statuscode(r), 0, 0);
@@ -8,8 +8,13 @@
 //! algorithm. It performs the hash using 32-bit native values,
 //! translating the input data into the endianness of the processor
 //! and translating the output into big endian format.
+//!
+//! Data is hashed in bounded chunks: each deferred call processes at most
+//! `blocks_per_call` 64-byte blocks and then reschedules itself, so hashing a
+//! large buffer does not monopolize the kernel loop and delay other drivers.
 
 use core::cell::Cell;
+use core::cmp;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 
 use kernel::hil::digest::Sha256;
@@ -34,6 +39,8 @@ pub enum State {
 }
 
 const SHA_BLOCK_LEN_BYTES: usize = 64;
+/// Default number of blocks hashed in a single deferred call.
+pub const DEFAULT_BLOCKS_PER_CALL: usize = 4;
 const SHA_256_OUTPUT_LEN_BYTES: usize = 32;
 const NUM_ROUND_CONSTANTS: usize = 64;
 
@@ -61,6 +68,7 @@ pub struct Sha256Software<'a> {
     output_data: Cell<Option<&'static mut [u8; SHA_256_OUTPUT_LEN_BYTES]>>,
 
     hash_values: Cell<[u32; 8]>,
+    blocks_per_call: Cell<usize>,
     deferred_call: DeferredCall,
 }
 
@@ -76,6 +84,7 @@ impl<'a> Sha256Software<'a> {
 
             output_data: Cell::new(None),
             hash_values: Cell::new([0; 8]),
+            blocks_per_call: Cell::new(DEFAULT_BLOCKS_PER_CALL),
 
             deferred_call: DeferredCall::new(),
         };
@@ -83,6 +92,13 @@ impl<'a> Sha256Software<'a> {
         s
     }
 
+    /// Set the maximum number of 64-byte blocks hashed in one deferred call.
+    /// Smaller values bound the time spent in the kernel loop per call at the
+    /// cost of more deferred calls per operation.
+    pub fn set_blocks_per_call(&self, blocks: usize) {
+        self.blocks_per_call.set(cmp::max(blocks, 1));
+    }
+
     pub fn busy(&self) -> bool {
         match self.state.get() {
             State::Idle => false,
@@ -178,15 +194,18 @@ impl<'a> Sha256Software<'a> {
     // on it, then operates on input_data. If the end of
     // input_data does not complete a block then the remainder
     // is stored in data_buffer.
-    fn compute_sha256(&self) {
+    //
+    // At most `max_blocks` full blocks are computed. Returns
+    // `true` once all of input_data has been consumed.
+    fn compute_sha256(&self, max_blocks: usize) -> bool {
         if let Some(mut data) = self.input_data.take() {
-            let data_length = data.len();
-            self.total_length.set(self.total_length.get() + data_length);
+            let mut blocks = 0;
             let mut buffered_length = self.buffered_length.get();
             if buffered_length != 0 {
                 // Copy bytes into the front of the temp buffer and
                 // compute if it fills.
                 self.data_buffer.map(|b| {
+                    let data_length = data.len();
                     let copy_len = if data_length + buffered_length >= SHA_BLOCK_LEN_BYTES {
                         SHA_BLOCK_LEN_BYTES - buffered_length
                     } else {
@@ -198,32 +217,41 @@ impl<'a> Sha256Software<'a> {
                     }
                     data.slice(copy_len..data.len());
                     buffered_length += copy_len;
+                    self.total_length.set(self.total_length.get() + copy_len);
 
                     if buffered_length == SHA_BLOCK_LEN_BYTES {
                         self.compute_block(b);
                         buffered_length = 0;
+                        blocks += 1;
                     }
                 });
             }
             // Process blocks
-            while data.len() >= 64 {
+            while data.len() >= 64 && blocks < max_blocks {
                 self.compute_buffer(&data[0..64]);
                 data.slice(64..data.len());
+                self.total_length.set(self.total_length.get() + 64);
+                blocks += 1;
             }
             // Process tail end of block
-            if data.len() != 0 {
+            let done = data.len() < 64;
+            if done && data.len() != 0 {
                 self.data_buffer.map(|b| {
                     for i in 0..data.len() {
                         b[i] = data[i];
                     }
                     buffered_length = data.len();
+                    self.total_length.set(self.total_length.get() + data.len());
                     // Go to end of data.
                     data.slice(data.len()..data.len());
                 });
             }
             self.input_data.set(data);
             self.buffered_length.set(buffered_length);
-        } else { /* do nothing, no data */
+            done
+        } else {
+            /* do nothing, no data */
+            true
         }
     }
 
@@ -307,7 +335,6 @@ impl<'a> DigestData<'a, 32> for Sha256Software<'a> {
             self.state.set(State::Data);
             self.deferred_call.set();
             self.input_data.set(SubSliceMutImmut::Immutable(data));
-            self.compute_sha256();
             Ok(())
         }
     }
@@ -322,7 +349,6 @@ impl<'a> DigestData<'a, 32> for Sha256Software<'a> {
             self.state.set(State::Data);
             self.deferred_call.set();
             self.input_data.set(SubSliceMutImmut::Mutable(data));
-            self.compute_sha256();
             Ok(())
         }
     }
@@ -420,7 +446,13 @@ impl<'a> DeferredCallClient for Sha256Software<'a> {
                 });
             }
             State::Data => {
-                // Data already computed in method call
+                // Hash the next chunk of data, and come back later if
+                // there is more to do.
+                if !self.compute_sha256(self.blocks_per_call.get()) {
+                    self.state.set(State::Data);
+                    self.deferred_call.set();
+                    return;
+                }
                 let data = self.input_data.take().unwrap();
                 self.state.set(State::Idle);
                 match data {
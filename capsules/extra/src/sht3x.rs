@@ -52,6 +52,7 @@ enum State {
     Idle,
     Read,
     ReadData,
+    Heater,
 }
 
 fn crc8(data: &[u8]) -> u8 {
@@ -141,6 +142,39 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> SHT3x<'a, A, I> {
             },
         )
     }
+
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+            let command = if enabled {
+                Registers::HEATEREN as u16
+            } else {
+                Registers::HEATERDIS as u16
+            };
+            buffer[0] = (command >> 8) as u8;
+            buffer[1] = (command & 0xff) as u8;
+
+            self.state.set(State::Heater);
+            self.i2c.enable();
+            self.i2c.write(buffer, 2).map_err(|(error, buffer)| {
+                self.buffer.replace(buffer);
+                self.state.set(State::Idle);
+                self.i2c.disable();
+                error.into()
+            })
+        })
+    }
+
+    /// Start the readings requested while the heater was being switched.
+    fn heater_done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.humidity_client.map(|cb| cb.heater_done(result));
+        if self.read_temp.get() || self.read_hum.get() {
+            let _ = self.read_temp_hum();
+        }
+    }
 }
 
 impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> time::AlarmClient for SHT3x<'a, A, I> {
@@ -205,12 +239,21 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> i2c::I2CClient for SHT3x<'a, A, I> {
                         let interval = self.alarm.ticks_from_ms(20);
                         self.alarm.set_alarm(self.alarm.now(), interval);
                     }
+                    State::Heater => {
+                        self.buffer.replace(buffer);
+                        self.i2c.disable();
+                        self.heater_done(Ok(()));
+                    }
                     _ => {}
                 }
             }
             Err(i2c_err) => {
                 self.buffer.replace(buffer);
                 self.i2c.disable();
+                if self.state.get() == State::Heater {
+                    self.heater_done(Err(i2c_err.into()));
+                    return;
+                }
                 if self.read_temp.get() {
                     self.read_temp.set(false);
                     self.temperature_client
@@ -235,6 +278,10 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> kernel::hil::sensors::HumidityDriver<'
     fn read_humidity(&self) -> Result<(), ErrorCode> {
         self.read_humidity()
     }
+
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        self.set_heater(enabled)
+    }
 }
 
 impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> kernel::hil::sensors::TemperatureDriver<'a>
@@ -47,6 +47,10 @@ enum State {
     Idle,
     Read,
     ReadData,
+    /// Heater pulse command sent.
+    Heater,
+    /// Reading the measurement taken at the end of the heater pulse.
+    HeaterData,
 }
 
 fn crc8(data: &[u8]) -> u8 {
@@ -144,6 +148,38 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> SHT4x<'a, A, I> {
             }
         })
     }
+
+    /// The heater of the SHT4x only runs in pulses: enabling it heats the
+    /// sensor at 200 mW for a second, after which it switches off by itself.
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        if !enabled {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+            buffer[0] = Registers::HEATER200MW1S as u8;
+
+            self.state.set(State::Heater);
+            self.i2c.enable();
+            self.i2c.write(buffer, 1).map_err(|(error, buffer)| {
+                self.buffer.replace(buffer);
+                self.state.set(State::Idle);
+                self.i2c.disable();
+                error.into()
+            })
+        })
+    }
+
+    /// Start the readings requested during the heater pulse.
+    fn heater_done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.humidity_client.map(|cb| cb.heater_done(result));
+        if self.read_temp.get() || self.read_hum.get() {
+            let _ = self.read_temp_hum();
+        }
+    }
 }
 
 impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> time::AlarmClient for SHT4x<'a, A, I> {
@@ -156,6 +192,14 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> time::AlarmClient for SHT4x<'a, A, I>
                     let _res = self.i2c.read(buffer, 6);
                 });
             }
+            State::Heater => {
+                // The measurement taken by the pulse is read and dropped, as
+                // the sensor is hot.
+                self.state.set(State::HeaterData);
+                self.buffer.take().map(|buffer| {
+                    let _res = self.i2c.read(buffer, 6);
+                });
+            }
             _ => {
                 // This should never happen
                 panic!("SHT4x Invalid alarm!");
@@ -218,12 +262,27 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> i2c::I2CClient for SHT4x<'a, A, I> {
                         let interval = self.alarm.ticks_from_ms(20);
                         self.alarm.set_alarm(self.alarm.now(), interval);
                     }
+                    State::Heater => {
+                        self.buffer.replace(buffer);
+                        let interval = self.alarm.ticks_from_ms(1100);
+                        self.alarm.set_alarm(self.alarm.now(), interval);
+                    }
+                    State::HeaterData => {
+                        self.buffer.replace(buffer);
+                        self.i2c.disable();
+                        self.heater_done(Ok(()));
+                    }
                     _ => {}
                 }
             }
             Err(i2c_err) => {
                 self.buffer.replace(buffer);
                 self.i2c.disable();
+                let state = self.state.get();
+                if state == State::Heater || state == State::HeaterData {
+                    self.heater_done(Err(i2c_err.into()));
+                    return;
+                }
                 self.state.set(State::Idle);
                 if self.read_temp.get() {
                     self.read_temp.set(false);
@@ -249,6 +308,10 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> kernel::hil::sensors::HumidityDriver<'
     fn read_humidity(&self) -> Result<(), ErrorCode> {
         self.read_humidity()
     }
+
+    fn set_heater(&self, enabled: bool) -> Result<(), ErrorCode> {
+        self.set_heater(enabled)
+    }
 }
 
 impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> kernel::hil::sensors::TemperatureDriver<'a>
@@ -0,0 +1,582 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SNTP client keeping a real time clock synchronized over UDP.
+//!
+//! The capsule periodically sends an SNTP (RFC 4330) request to a server,
+//! and compares the server time, corrected by half of the round-trip delay,
+//! with the date and time of the `DateTime` device.
+//!
+//! Large offsets are corrected at once. Offsets up to `SLEW_LIMIT_SECONDS`
+//! are slewed instead: the clock is moved by one second every
+//! `SLEW_INTERVAL_SECONDS` until it is synchronized, so that the time seen
+//! by the rest of the system never jumps by more than a second. The clock
+//! resolution of the `DateTime` interface is a second, so offsets below a
+//! second are not corrected.
+//!
+//! The synchronization status is available with [`Sntp::status`], and is
+//! reported to the [`SntpClient`] when it changes.
+//!
+//! Boards set up the capsule with `components::sntp`, with a `DateTime`
+//! device that has no other client.
+
+use core::cell::Cell;
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_port_table::UdpPortManager;
+use crate::net::udp::udp_recv::{UDPReceiver, UDPRecvClient};
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+use kernel::hil::date_time::{DateTime, DateTimeClient, DateTimeValues, DayOfWeek, Month};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// UDP port of NTP servers.
+pub const NTP_PORT: u16 = 123;
+
+/// Local UDP port of the client unless the board chooses another one.
+pub const DEFAULT_PORT: u16 = 50123;
+
+/// Length of an SNTP packet without authentication.
+pub const PACKET_LEN: usize = 48;
+
+/// Offsets larger than this, in seconds, are corrected at once.
+pub const SLEW_LIMIT_SECONDS: i64 = 8;
+
+/// Time between two one-second corrections while slewing.
+pub const SLEW_INTERVAL_SECONDS: u32 = 60;
+
+/// Time to wait for the response of the server.
+const RESPONSE_TIMEOUT_SECONDS: u32 = 5;
+
+/// Time before querying again after a failure.
+const RETRY_INTERVAL_SECONDS: u32 = 30;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Leap indicator 0, version 4, client mode.
+const REQUEST_HEADER: u8 = (4 << 3) | 3;
+const MODE_SERVER: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+/// Synchronization status of the clock.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SyncStatus {
+    /// No server answered yet.
+    Unsynchronized,
+    /// The clock is within a second of the server.
+    Synchronized,
+    /// The clock is this many seconds behind the server (or ahead if
+    /// negative), and is being slewed.
+    Slewing(i32),
+    /// The last synchronization failed. The clock keeps its last
+    /// correction, and the capsule retries later.
+    Failed(ErrorCode),
+}
+
+pub trait SntpClient {
+    /// The synchronization status changed.
+    fn sync_status(&self, status: SyncStatus);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Waiting for the next query or slewing step.
+    Idle,
+    /// Waiting for the response of the server.
+    Querying,
+    /// Reading the clock to compute the offset.
+    Measuring,
+    /// Setting the clock to the server time.
+    Stepping,
+    /// Reading the clock to move it by a second.
+    SlewReading,
+    /// Moving the clock by a second.
+    SlewSetting,
+}
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Date `(year, month, day)` of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+const MONTHS: [Month; 12] = [
+    Month::January,
+    Month::February,
+    Month::March,
+    Month::April,
+    Month::May,
+    Month::June,
+    Month::July,
+    Month::August,
+    Month::September,
+    Month::October,
+    Month::November,
+    Month::December,
+];
+
+const DAYS_OF_WEEK: [DayOfWeek; 7] = [
+    DayOfWeek::Sunday,
+    DayOfWeek::Monday,
+    DayOfWeek::Tuesday,
+    DayOfWeek::Wednesday,
+    DayOfWeek::Thursday,
+    DayOfWeek::Friday,
+    DayOfWeek::Saturday,
+];
+
+/// Seconds since the Unix epoch of a date and time.
+fn unix_seconds(date_time: &DateTimeValues) -> i64 {
+    let month = MONTHS
+        .iter()
+        .position(|month| *month == date_time.month)
+        .unwrap_or(0) as i64
+        + 1;
+    let days = days_from_civil(date_time.year as i64, month, date_time.day as i64);
+    days * 86400
+        + date_time.hour as i64 * 3600
+        + date_time.minute as i64 * 60
+        + date_time.seconds as i64
+}
+
+/// Date and time of a number of seconds since the Unix epoch, or `None` if
+/// the year does not fit.
+fn date_time_from_unix_seconds(seconds: i64) -> Option<DateTimeValues> {
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    Some(DateTimeValues {
+        year: u16::try_from(year).ok()?,
+        month: MONTHS[(month - 1) as usize],
+        day: day as u8,
+        // 1970-01-01 was a Thursday.
+        day_of_week: DAYS_OF_WEEK[(days + 4).rem_euclid(7) as usize],
+        hour: (time / 3600) as u8,
+        minute: (time / 60 % 60) as u8,
+        seconds: (time % 60) as u8,
+    })
+}
+
+/// Milliseconds since the Unix epoch of an NTP timestamp.
+fn ntp_to_unix_ms(timestamp: &[u8]) -> u64 {
+    let mut seconds =
+        u32::from_be_bytes([timestamp[0], timestamp[1], timestamp[2], timestamp[3]]) as u64;
+    let fraction = u32::from_be_bytes([timestamp[4], timestamp[5], timestamp[6], timestamp[7]]);
+    // Timestamps with the most significant bit clear are in the era that
+    // starts in 2036.
+    if seconds & 0x8000_0000 == 0 {
+        seconds += 1 << 32;
+    }
+    (seconds - NTP_UNIX_OFFSET) * 1000 + ((fraction as u64 * 1000) >> 32)
+}
+
+/// Write the request identified by `nonce` into `buffer`.
+fn encode_request(buffer: &mut [u8], nonce: u32) {
+    buffer[..PACKET_LEN].fill(0);
+    buffer[0] = REQUEST_HEADER;
+    // The server copies the transmit timestamp into the originate timestamp
+    // of the response.
+    buffer[44..48].copy_from_slice(&nonce.to_be_bytes());
+}
+
+/// Return the receive and transmit times of the server, in milliseconds
+/// since the Unix epoch, from the response to the request `nonce`.
+fn decode_response(packet: &[u8], nonce: u32) -> Result<(u64, u64), ErrorCode> {
+    if packet.len() < PACKET_LEN
+        || packet[0] & 0x7 != MODE_SERVER
+        || packet[28..32] != nonce.to_be_bytes()
+    {
+        return Err(ErrorCode::INVAL);
+    }
+    if packet[0] >> 6 == LEAP_UNSYNCHRONIZED || packet[1] == 0 {
+        // The server is not synchronized, or sent a kiss-o'-death.
+        return Err(ErrorCode::BUSY);
+    }
+    Ok((
+        ntp_to_unix_ms(&packet[32..40]),
+        ntp_to_unix_ms(&packet[40..48]),
+    ))
+}
+
+pub struct Sntp<'a, A: Alarm<'a>, D: DateTime<'a>> {
+    alarm: &'a A,
+    date_time: &'a D,
+    udp_sender: &'a dyn UDPSender<'a>,
+    udp_receiver: &'a UDPReceiver<'a>,
+    port_table: &'static UdpPortManager,
+    net_cap: &'static NetworkCapability,
+    client: OptionalCell<&'a dyn SntpClient>,
+    server: Cell<IPAddr>,
+    poll_interval_s: Cell<u32>,
+    state: Cell<State>,
+    status: Cell<SyncStatus>,
+    /// Seconds still to be slewed.
+    slew: Cell<i32>,
+    /// Alarm time when the request was sent, also used as its nonce.
+    sent: Cell<A::Ticks>,
+    /// Alarm time when the response was received.
+    received: Cell<A::Ticks>,
+    /// Server time when the response was received, in milliseconds since the
+    /// Unix epoch.
+    server_time_ms: Cell<u64>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, A: Alarm<'a>, D: DateTime<'a>> Sntp<'a, A, D> {
+    /// `buffer` must be at least `PACKET_LEN` bytes.
+    pub fn new(
+        alarm: &'a A,
+        date_time: &'a D,
+        udp_sender: &'a dyn UDPSender<'a>,
+        udp_receiver: &'a UDPReceiver<'a>,
+        port_table: &'static UdpPortManager,
+        net_cap: &'static NetworkCapability,
+        server: IPAddr,
+        poll_interval_s: u32,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Sntp {
+            alarm,
+            date_time,
+            udp_sender,
+            udp_receiver,
+            port_table,
+            net_cap,
+            client: OptionalCell::empty(),
+            server: Cell::new(server),
+            poll_interval_s: Cell::new(poll_interval_s),
+            state: Cell::new(State::Idle),
+            status: Cell::new(SyncStatus::Unsynchronized),
+            slew: Cell::new(0),
+            sent: Cell::new(A::Ticks::from(0)),
+            received: Cell::new(A::Ticks::from(0)),
+            server_time_ms: Cell::new(0),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn SntpClient) {
+        self.client.set(client);
+    }
+
+    /// Send the requests from the local `port`.
+    pub fn bind(&self, port: u16) -> Result<(), ErrorCode> {
+        let socket = self
+            .port_table
+            .create_socket()
+            .map_err(|_| ErrorCode::NOMEM)?;
+        let (send_bind, rcv_bind) = self
+            .port_table
+            .bind(socket, port, self.net_cap)
+            .map_err(|_socket| ErrorCode::BUSY)?;
+        self.udp_sender.set_binding(send_bind);
+        self.udp_receiver.set_binding(rcv_bind);
+        Ok(())
+    }
+
+    /// Query the server after `delay_s` seconds, then every poll interval.
+    pub fn start(&self, delay_s: u32) {
+        self.schedule(delay_s);
+    }
+
+    /// Change the server. It is used from the next query.
+    pub fn set_server(&self, server: IPAddr) {
+        self.server.set(server);
+    }
+
+    /// Change the time between two queries.
+    pub fn set_poll_interval(&self, poll_interval_s: u32) {
+        self.poll_interval_s.set(poll_interval_s);
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.get()
+    }
+
+    fn schedule(&self, delay_s: u32) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_seconds(delay_s));
+    }
+
+    fn set_status(&self, status: SyncStatus) {
+        if self.status.replace(status) != status {
+            self.client.map(|client| client.sync_status(status));
+        }
+    }
+
+    /// The synchronization ended with `result`: wait for the next one.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        match result {
+            Ok(()) => {
+                self.set_status(SyncStatus::Synchronized);
+                self.schedule(self.poll_interval_s.get());
+            }
+            Err(e) => {
+                self.slew.set(0);
+                self.set_status(SyncStatus::Failed(e));
+                self.schedule(RETRY_INTERVAL_SECONDS);
+            }
+        }
+    }
+
+    fn query(&self) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let now = self.alarm.now();
+        encode_request(buffer, now.into_u32());
+
+        let mut dgram = SubSliceMut::new(buffer);
+        dgram.slice(..PACKET_LEN);
+        self.udp_sender
+            .send_to(self.server.get(), NTP_PORT, dgram, self.net_cap)
+            .map_err(|dgram| {
+                self.buffer.replace(dgram.take());
+                ErrorCode::FAIL
+            })?;
+        self.sent.set(now);
+        self.state.set(State::Querying);
+        self.schedule(RESPONSE_TIMEOUT_SECONDS);
+        Ok(())
+    }
+
+    /// Compute the offset of the clock read at the end of a query, and
+    /// start correcting it.
+    fn correct(&self, clock: &DateTimeValues) -> Result<(), ErrorCode> {
+        let elapsed = self
+            .alarm
+            .ticks_to_ms(self.alarm.now().wrapping_sub(self.received.get()));
+        let server_s = ((self.server_time_ms.get() + elapsed as u64) / 1000) as i64;
+        let offset = server_s - unix_seconds(clock);
+
+        if offset == 0 {
+            self.finish(Ok(()));
+        } else if offset.abs() > SLEW_LIMIT_SECONDS {
+            let date_time = date_time_from_unix_seconds(server_s).ok_or(ErrorCode::INVAL)?;
+            self.date_time.set_date_time(date_time)?;
+            self.state.set(State::Stepping);
+        } else {
+            self.slew.set(offset as i32);
+            self.set_status(SyncStatus::Slewing(offset as i32));
+            self.state.set(State::Idle);
+            self.schedule(SLEW_INTERVAL_SECONDS);
+        }
+        Ok(())
+    }
+
+    /// Move the clock read while slewing by a second.
+    fn slew_step(&self, clock: &DateTimeValues) -> Result<(), ErrorCode> {
+        let step = self.slew.get().signum() as i64;
+        let date_time =
+            date_time_from_unix_seconds(unix_seconds(clock) + step).ok_or(ErrorCode::INVAL)?;
+        self.date_time.set_date_time(date_time)?;
+        self.state.set(State::SlewSetting);
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, D: DateTime<'a>> AlarmClient for Sntp<'a, A, D> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Idle if self.slew.get() != 0 => {
+                self.state.set(State::SlewReading);
+                if let Err(e) = self.date_time.get_date_time() {
+                    self.finish(Err(e));
+                }
+            }
+            State::Idle => {
+                if let Err(e) = self.query() {
+                    self.finish(Err(e));
+                }
+            }
+            // The server did not answer.
+            State::Querying => self.finish(Err(ErrorCode::NOACK)),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, D: DateTime<'a>> UDPRecvClient for Sntp<'a, A, D> {
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        if self.state.get() != State::Querying
+            || src_addr != self.server.get()
+            || src_port != NTP_PORT
+        {
+            return;
+        }
+        let now = self.alarm.now();
+        let sent = self.sent.get();
+        let (receive_ms, transmit_ms) = match decode_response(payload, sent.into_u32()) {
+            Ok(times) => times,
+            // Not an answer to the request.
+            Err(ErrorCode::INVAL) => return,
+            Err(e) => {
+                let _ = self.alarm.disarm();
+                self.finish(Err(e));
+                return;
+            }
+        };
+        let _ = self.alarm.disarm();
+
+        // The network delay is the round trip time without the time spent
+        // in the server, and the response took half of it.
+        let round_trip_ms = self.alarm.ticks_to_ms(now.wrapping_sub(sent)) as u64;
+        let delay_ms = round_trip_ms.saturating_sub(transmit_ms.saturating_sub(receive_ms));
+        self.server_time_ms.set(transmit_ms + delay_ms / 2);
+        self.received.set(now);
+
+        self.state.set(State::Measuring);
+        if let Err(e) = self.date_time.get_date_time() {
+            self.finish(Err(e));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, D: DateTime<'a>> UDPSendClient for Sntp<'a, A, D> {
+    fn send_done(&self, result: Result<(), ErrorCode>, dgram: SubSliceMut<'static, u8>) {
+        self.buffer.replace(dgram.take());
+        if result.is_err() && self.state.get() == State::Querying {
+            let _ = self.alarm.disarm();
+            self.finish(result);
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, D: DateTime<'a>> DateTimeClient for Sntp<'a, A, D> {
+    fn get_date_time_done(&self, datetime: Result<DateTimeValues, ErrorCode>) {
+        let result = match self.state.get() {
+            State::Measuring => datetime.and_then(|clock| self.correct(&clock)),
+            State::SlewReading => datetime.and_then(|clock| self.slew_step(&clock)),
+            _ => return,
+        };
+        if result.is_err() {
+            self.finish(result);
+        }
+    }
+
+    fn set_date_time_done(&self, result: Result<(), ErrorCode>) {
+        match self.state.get() {
+            State::Stepping => self.finish(result),
+            State::SlewSetting => {
+                if result.is_err() {
+                    self.finish(result);
+                    return;
+                }
+                let slew = self.slew.get() - self.slew.get().signum();
+                self.slew.set(slew);
+                if slew == 0 {
+                    self.finish(Ok(()));
+                } else {
+                    self.set_status(SyncStatus::Slewing(slew));
+                    self.state.set(State::Idle);
+                    self.schedule(SLEW_INTERVAL_SECONDS);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calendar_round_trip() {
+        // 2024-02-29 12:34:56, a Thursday.
+        let date_time = date_time_from_unix_seconds(1_709_210_096).unwrap();
+        assert_eq!(
+            date_time,
+            DateTimeValues {
+                year: 2024,
+                month: Month::February,
+                day: 29,
+                day_of_week: DayOfWeek::Thursday,
+                hour: 12,
+                minute: 34,
+                seconds: 56,
+            }
+        );
+        assert_eq!(unix_seconds(&date_time), 1_709_210_096);
+
+        let epoch = date_time_from_unix_seconds(0).unwrap();
+        assert_eq!(
+            (epoch.year, epoch.month, epoch.day),
+            (1970, Month::January, 1)
+        );
+        assert_eq!(epoch.day_of_week, DayOfWeek::Thursday);
+        let before_epoch = date_time_from_unix_seconds(-1).unwrap();
+        assert_eq!((before_epoch.year, before_epoch.day), (1969, 31));
+        assert_eq!(before_epoch.seconds, 59);
+        // Beyond the year 65535.
+        assert!(date_time_from_unix_seconds(1 << 42).is_none());
+    }
+
+    #[test]
+    fn response() {
+        let mut packet = [0; PACKET_LEN];
+        encode_request(&mut packet, 0x1234_5678);
+        assert_eq!(packet[0], 0x23);
+
+        // Server in mode 4 and stratum 2, answering the request.
+        packet[0] = (4 << 3) | MODE_SERVER;
+        packet[1] = 2;
+        packet[28..32].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+        // 2024-01-01 00:00:00 and half a second later.
+        let seconds = (1_704_067_200 + NTP_UNIX_OFFSET) as u32;
+        packet[32..36].copy_from_slice(&seconds.to_be_bytes());
+        packet[40..44].copy_from_slice(&seconds.to_be_bytes());
+        packet[44..48].copy_from_slice(&0x8000_0000u32.to_be_bytes());
+        assert_eq!(
+            decode_response(&packet, 0x1234_5678),
+            Ok((1_704_067_200_000, 1_704_067_200_500))
+        );
+
+        assert_eq!(decode_response(&packet, 0), Err(ErrorCode::INVAL));
+        packet[1] = 0;
+        assert_eq!(decode_response(&packet, 0x1234_5678), Err(ErrorCode::BUSY));
+    }
+
+    #[test]
+    fn next_era() {
+        // 2040-01-01 00:00:00, after the NTP seconds wrap around in 2036.
+        let seconds = ((2_208_988_800 + NTP_UNIX_OFFSET) & 0xffff_ffff) as u32;
+        let mut timestamp = [0; 8];
+        timestamp[..4].copy_from_slice(&seconds.to_be_bytes());
+        assert_eq!(ntp_to_unix_ms(&timestamp), 2_208_988_800_000);
+    }
+}
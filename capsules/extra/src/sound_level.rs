@@ -0,0 +1,466 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! A-weighted sound level (dBA) metering from a digital microphone.
+//!
+//! This capsule consumes the PCM stream of an `hil::audio::AudioSource` (a PDM
+//! or I2S microphone), applies an A-weighting filter in fixed point, and
+//! computes the RMS level over a configurable window. At the end of every
+//! window the level is reported to all processes that enabled metering, so
+//! noise-monitoring applications never have to handle the raw audio.
+//!
+//! The A-weighting filter is made of three biquad sections obtained with the
+//! bilinear transform of the IEC 61672 analog filter. Coefficients are provided
+//! for 16 kHz, 32 kHz and 48 kHz sample rates. At 16 kHz the response above
+//! 5 kHz falls off faster than the standard requires.
+//!
+//! Levels are reported in hundredths of a dB, as a signed 32-bit value. The
+//! level is `full_scale_cdb` for a full-scale sine wave, so boards pass the
+//! sound pressure level (in hundredths of a dB SPL) that saturates their
+//! microphone to get readings in dB(A) SPL. For example, a microphone with a
+//! sensitivity of -26 dBFS at 94 dB SPL saturates at 120 dB SPL (`12000`).
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! Subscribe `0` is called at the end of every window with:
+//!
+//! - the level in hundredths of a dB (a signed 32-bit value),
+//! - the window length in milliseconds.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Enable metering for the process, with a window of `data`
+//!   milliseconds. Returns `BUSY` if metering is already running with a
+//!   different window, and `NOSUPPORT` if the sample rate of the microphone
+//!   is not supported.
+//! - `2`: Disable metering for the process. The microphone is stopped when no
+//!   process uses it.
+//! - `3`: Get the last level, or `OFF` if no window has completed yet.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let buffer1 = static_init!([i16; 256], [0; 256]);
+//! let buffer2 = static_init!([i16; 256], [0; 256]);
+//! let sound_level = static_init!(
+//!     capsules_extra::sound_level::SoundLevel<'static, Pdm<'static>>,
+//!     capsules_extra::sound_level::SoundLevel::new(
+//!         &pdm,
+//!         12000,
+//!         buffer1,
+//!         buffer2,
+//!         board_kernel.create_grant(
+//!             capsules_extra::sound_level::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! kernel::hil::audio::AudioSource::set_client(&pdm, sound_level);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::audio::{AudioClient, AudioSource};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SoundLevel as usize;
+
+/// Number of fractional bits of the filter coefficients.
+const COEFFICIENT_BITS: u32 = 28;
+
+/// Extra fractional bits carried by the samples inside the filter, so that
+/// rounding errors of the low-frequency sections stay below one LSB.
+const GUARD_BITS: u32 = 8;
+
+/// Number of biquad sections of the A-weighting filter.
+const SECTIONS: usize = 3;
+
+/// Mean square of a full-scale sine wave, `32768^2 / 2`, as a power of two.
+const FULL_SCALE_LOG2: i64 = 29;
+
+/// A second order IIR section, with coefficients in Q4.28 fixed point.
+///
+/// `y[n] = b0 x[n] + b1 x[n-1] + b2 x[n-2] - a1 y[n-1] - a2 y[n-2]`
+#[derive(Copy, Clone)]
+struct Biquad {
+    b: [i32; 3],
+    a: [i32; 2],
+}
+
+/// The delayed samples of a biquad: `x[n-1]`, `x[n-2]`, `y[n-1]`, `y[n-2]`.
+type BiquadState = [i32; 4];
+
+const A_WEIGHTING_16KHZ: [Biquad; SECTIONS] = [
+    Biquad {
+        b: [266277129, -532554258, 266277129],
+        a: [-532545546, 264127514],
+    },
+    Biquad {
+        b: [229612791, -459225582, 229612791],
+        a: [-457819256, 192196451],
+    },
+    Biquad {
+        b: [167386922, 334773844, 167386922],
+        a: [220536858, 45296276],
+    },
+];
+
+const A_WEIGHTING_32KHZ: [Biquad; SECTIONS] = [
+    Biquad {
+        b: [267353026, -534706051, 267353026],
+        a: [-534703864, 266272782],
+    },
+    Biquad {
+        b: [247685864, -495371727, 247685864],
+        a: [-494992473, 227315526],
+    },
+    Biquad {
+        b: [100210863, 200421726, 100210863],
+        a: [48176576, 2161583],
+    },
+];
+
+const A_WEIGHTING_48KHZ: [Biquad; SECTIONS] = [
+    Biquad {
+        b: [267713107, -535426214, 267713107],
+        a: [-535425241, 266991731],
+    },
+    Biquad {
+        b: [254277517, -508555033, 254277517],
+        a: [-508381990, 240292621],
+    },
+    Biquad {
+        b: [66542250, 133084501, 66542250],
+        a: [-60279452, 3384065],
+    },
+];
+
+/// Return the A-weighting filter for `sample_rate`, if it is supported.
+fn a_weighting(sample_rate: u32) -> Option<&'static [Biquad; SECTIONS]> {
+    match sample_rate {
+        16000 => Some(&A_WEIGHTING_16KHZ),
+        32000 => Some(&A_WEIGHTING_32KHZ),
+        48000 => Some(&A_WEIGHTING_48KHZ),
+        _ => None,
+    }
+}
+
+/// Run one sample through the filter and return the filtered sample.
+fn filter_sample(
+    filter: &[Biquad; SECTIONS],
+    state: &mut [BiquadState; SECTIONS],
+    sample: i16,
+) -> i32 {
+    let mut x = i32::from(sample) << GUARD_BITS;
+    for (section, delayed) in filter.iter().zip(state.iter_mut()) {
+        let acc = i64::from(section.b[0]) * i64::from(x)
+            + i64::from(section.b[1]) * i64::from(delayed[0])
+            + i64::from(section.b[2]) * i64::from(delayed[1])
+            - i64::from(section.a[0]) * i64::from(delayed[2])
+            - i64::from(section.a[1]) * i64::from(delayed[3]);
+        let y = (acc >> COEFFICIENT_BITS) as i32;
+        *delayed = [x, delayed[0], y, delayed[2]];
+        x = y;
+    }
+    x >> GUARD_BITS
+}
+
+/// Base 2 logarithm of `value` in Q16 fixed point. `value` must not be 0.
+fn log2_q16(value: u64) -> i64 {
+    let integer = 63 - value.leading_zeros();
+    // Normalize the value to [1, 2) in Q30 and compute the fractional bits
+    // one at a time by repeated squaring.
+    let mut mantissa = if integer >= 30 {
+        value >> (integer - 30)
+    } else {
+        value << (30 - integer)
+    };
+    let mut fraction = 0;
+    for bit in (0..16).rev() {
+        mantissa = (mantissa * mantissa) >> 30;
+        if mantissa >= 2 << 30 {
+            mantissa >>= 1;
+            fraction |= 1 << bit;
+        }
+    }
+    (i64::from(integer) << 16) | fraction
+}
+
+/// Level of a signal with mean square `mean_square`, in hundredths of a dB
+/// relative to a full-scale sine wave.
+fn level_cdb(mean_square: u64) -> i32 {
+    // 1000 * log10(x) = 1000 * log10(2) * log2(x)
+    let log2 = log2_q16(mean_square.max(1)) - (FULL_SCALE_LOG2 << 16);
+    ((log2 * 30103) / (100 << 16)) as i32
+}
+
+#[derive(Default)]
+pub struct App {
+    enabled: bool,
+}
+
+pub struct SoundLevel<'a, S: AudioSource<'a>> {
+    source: &'a S,
+    full_scale_cdb: i32,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    buffer1: TakeCell<'static, [i16]>,
+    buffer2: TakeCell<'static, [i16]>,
+    running: Cell<bool>,
+    filter: OptionalCell<&'static [Biquad; SECTIONS]>,
+    filter_state: Cell<[BiquadState; SECTIONS]>,
+    window_ms: Cell<usize>,
+    window_samples: Cell<usize>,
+    energy: Cell<u64>,
+    samples: Cell<usize>,
+    last_level: OptionalCell<i32>,
+}
+
+impl<'a, S: AudioSource<'a>> SoundLevel<'a, S> {
+    pub fn new(
+        source: &'a S,
+        full_scale_cdb: i32,
+        buffer1: &'static mut [i16],
+        buffer2: &'static mut [i16],
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> SoundLevel<'a, S> {
+        SoundLevel {
+            source,
+            full_scale_cdb,
+            apps: grant,
+            buffer1: TakeCell::new(buffer1),
+            buffer2: TakeCell::new(buffer2),
+            running: Cell::new(false),
+            filter: OptionalCell::empty(),
+            filter_state: Cell::new([[0; 4]; SECTIONS]),
+            window_ms: Cell::new(0),
+            window_samples: Cell::new(0),
+            energy: Cell::new(0),
+            samples: Cell::new(0),
+            last_level: OptionalCell::empty(),
+        }
+    }
+
+    fn start(&self, window_ms: usize) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return if window_ms == self.window_ms.get() {
+                Ok(())
+            } else {
+                Err(ErrorCode::BUSY)
+            };
+        }
+
+        let sample_rate = self.source.sample_rate();
+        let filter = a_weighting(sample_rate).ok_or(ErrorCode::NOSUPPORT)?;
+        let window_samples = (sample_rate as usize * window_ms) / 1000;
+        if window_samples == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let (buffer1, buffer2) = match (self.buffer1.take(), self.buffer2.take()) {
+            (Some(buffer1), Some(buffer2)) => (buffer1, buffer2),
+            (buffer1, buffer2) => {
+                self.buffer1.put(buffer1);
+                self.buffer2.put(buffer2);
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+
+        self.filter.set(filter);
+        self.filter_state.set([[0; 4]; SECTIONS]);
+        self.window_ms.set(window_ms);
+        self.window_samples.set(window_samples);
+        self.energy.set(0);
+        self.samples.set(0);
+        self.source
+            .start(buffer1, buffer2)
+            .map(|()| self.running.set(true))
+            .map_err(|(err, buffer1, buffer2)| {
+                self.buffer1.replace(buffer1);
+                self.buffer2.replace(buffer2);
+                err
+            })
+    }
+
+    /// Stop the microphone if no process is using it anymore.
+    fn stop_if_unused(&self) {
+        let mut in_use = false;
+        for app in self.apps.iter() {
+            app.enter(|app, _| in_use |= app.enabled);
+        }
+        if !in_use && self.running.get() {
+            self.running.set(false);
+            let _ = self.source.stop();
+            if let Ok((buffer1, buffer2)) = self.source.retrieve_buffers() {
+                buffer1.map(|buffer| self.store_buffer(buffer));
+                buffer2.map(|buffer| self.store_buffer(buffer));
+            }
+        }
+    }
+
+    fn store_buffer(&self, buffer: &'static mut [i16]) {
+        if self.buffer1.is_none() {
+            self.buffer1.replace(buffer);
+        } else {
+            self.buffer2.replace(buffer);
+        }
+    }
+
+    /// A window is complete: compute its level and report it.
+    fn window_complete(&self) {
+        let mean_square = self.energy.get() / self.samples.get() as u64;
+        let level = self.full_scale_cdb + level_cdb(mean_square);
+        self.energy.set(0);
+        self.samples.set(0);
+        self.last_level.set(level);
+
+        let window_ms = self.window_ms.get();
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                if app.enabled {
+                    let _ = kernel_data.schedule_upcall(0, (level as usize, window_ms, 0));
+                }
+            });
+        }
+    }
+}
+
+impl<'a, S: AudioSource<'a>> AudioClient for SoundLevel<'a, S> {
+    fn samples_ready(&self, buffer: &'static mut [i16], length: usize) {
+        if !self.running.get() {
+            self.store_buffer(buffer);
+            return;
+        }
+
+        if let Some(filter) = self.filter.get() {
+            let mut state = self.filter_state.get();
+            for sample in buffer.iter().take(length) {
+                let filtered = filter_sample(filter, &mut state, *sample);
+                let square = (i64::from(filtered) * i64::from(filtered)) as u64;
+                self.energy.set(self.energy.get().saturating_add(square));
+                self.samples.set(self.samples.get() + 1);
+                if self.samples.get() >= self.window_samples.get() {
+                    self.window_complete();
+                }
+            }
+            self.filter_state.set(state);
+        }
+
+        if let Err((_, buffer)) = self.source.provide_buffer(buffer) {
+            self.store_buffer(buffer);
+        }
+    }
+}
+
+impl<'a, S: AudioSource<'a>> SyscallDriver for SoundLevel<'a, S> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let res = self.start(data).and_then(|()| {
+                    self.apps
+                        .enter(processid, |app, _| app.enabled = true)
+                        .map_err(ErrorCode::from)
+                });
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => {
+                        self.stop_if_unused();
+                        CommandReturn::failure(err)
+                    }
+                }
+            }
+
+            2 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| app.enabled = false)
+                    .map_err(ErrorCode::from);
+                self.stop_if_unused();
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => CommandReturn::failure(err),
+                }
+            }
+
+            3 => self
+                .last_level
+                .get()
+                .map_or(CommandReturn::failure(ErrorCode::OFF), |level| {
+                    CommandReturn::success_u32(level as u32)
+                }),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One period of a 1 kHz sine wave at 16 kHz, with an amplitude of half
+    /// the full scale (-6.02 dB).
+    const SINE_1KHZ: [i16; 16] = [
+        0, 6270, 11585, 15137, 16384, 15137, 11585, 6270, 0, -6270, -11585, -15137, -16384, -15137,
+        -11585, -6270,
+    ];
+
+    #[test]
+    fn log2() {
+        assert_eq!(log2_q16(1), 0);
+        assert_eq!(log2_q16(1 << 29), 29 << 16);
+        // log2(3) = 1.58496
+        assert!((log2_q16(3) - 103872).abs() <= 1);
+    }
+
+    #[test]
+    fn level_of_full_scale_sine() {
+        assert_eq!(level_cdb(1 << 29), 0);
+        assert_eq!(level_cdb(1 << 27), -602);
+    }
+
+    #[test]
+    fn a_weighted_1khz_is_unchanged() {
+        let filter = a_weighting(16000).unwrap();
+        let mut state = [[0; 4]; SECTIONS];
+        // Let the filter settle.
+        for _ in 0..1000 {
+            for sample in SINE_1KHZ {
+                filter_sample(filter, &mut state, sample);
+            }
+        }
+        let mut energy = 0u64;
+        for _ in 0..100 {
+            for sample in SINE_1KHZ {
+                let filtered = i64::from(filter_sample(filter, &mut state, sample));
+                energy += (filtered * filtered) as u64;
+            }
+        }
+        let level = level_cdb(energy / (100 * SINE_1KHZ.len() as u64));
+        assert!((level + 602).abs() <= 5, "level {}", level);
+    }
+}
@@ -0,0 +1,266 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Lets a supervisor process control the lifecycle of the other processes.
+//!
+//! A supervisor (e.g. a software update orchestrator) can stop, start and
+//! restart other processes, and be notified when a process faults, to
+//! implement self-healing deployments in userspace. Processes are addressed
+//! by their index among the loaded processes, the same index as the
+//! `process_info` driver, which gives their names and versions.
+//!
+//! A process may only use a command if the permissions header of its TBF
+//! explicitly allows this command of this driver. Processes without a
+//! permissions header cannot use the commands, unlike with the kernel's
+//! syscall filtering, where they can use any driver.
+//!
+//! The capsule is also installed as the board's `ProcessFaultPolicy`: the
+//! action taken for a faulted process is decided by the wrapped fault
+//! policy, and the supervisors that enabled fault notifications are then
+//! notified. The capsule requires a `ProcessManagementCapability` to
+//! create.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let supervisor = static_init!(
+//!     capsules_extra::supervisor::Supervisor<'static, ProcessMgmtCap>,
+//!     capsules_extra::supervisor::Supervisor::new(
+//!         board_kernel,
+//!         ProcessMgmtCap,
+//!         &FAULT_RESPONSE,
+//!         board_kernel.create_grant(
+//!             capsules_extra::supervisor::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! // Pass `supervisor` as the fault policy when loading processes.
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::process::{self, Process, ProcessFaultPolicy, ShortId, State};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+use tock_tbf::types::CommandPermissions;
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Supervisor as usize;
+
+/// Ids for subscribe upcalls
+mod upcall {
+    /// A process faulted. Arguments are the index of the process, its
+    /// ShortId (`0` if it has none), and the `FaultAction` taken.
+    pub const FAULT: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Notify this process of the faults of the other processes.
+    notify_faults: bool,
+}
+
+pub struct Supervisor<'a, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    capability: C,
+    fault_policy: &'a dyn ProcessFaultPolicy,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, C: ProcessManagementCapability> Supervisor<'a, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        capability: C,
+        fault_policy: &'a dyn ProcessFaultPolicy,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Self {
+            kernel,
+            capability,
+            fault_policy,
+            apps: grant,
+        }
+    }
+
+    /// Whether the TBF header of `processid` allows it to use `command_num`.
+    fn is_permitted(&self, processid: ProcessId, command_num: usize) -> bool {
+        self.kernel.process_map_or_external(
+            false,
+            processid,
+            |process| match process.get_command_permissions(DRIVER_NUM, command_num / 64) {
+                CommandPermissions::Mask(allowed) => allowed & (1 << (command_num % 64)) != 0,
+                CommandPermissions::NoPermsAtAll | CommandPermissions::NoPermsThisDriver => false,
+            },
+            &self.capability,
+        )
+    }
+
+    /// Call `f` with the process at `index` among the loaded processes.
+    fn with_process<R, F: FnOnce(&dyn Process) -> R>(&self, index: usize, f: F) -> Option<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        let mut current = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if current == index {
+                    result = f.take().map(|f| f(process));
+                }
+                current += 1;
+            });
+        result
+    }
+
+    /// The index of `processid` among the loaded processes.
+    fn index_of(&self, processid: ProcessId) -> Option<usize> {
+        let mut index = None;
+        let mut current = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid() == processid {
+                    index = Some(current);
+                }
+                current += 1;
+            });
+        index
+    }
+
+    /// Apply `operation` to the process at `index`, which cannot be the
+    /// calling process.
+    fn control(
+        &self,
+        index: usize,
+        caller: ProcessId,
+        operation: fn(&dyn Process) -> Result<(), ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        self.with_process(index, |process| {
+            if process.processid() == caller {
+                Err(ErrorCode::INVAL)
+            } else {
+                operation(process)
+            }
+        })
+        .unwrap_or(Err(ErrorCode::INVAL))
+    }
+}
+
+/// Stop a running or yielded process.
+fn stop(process: &dyn Process) -> Result<(), ErrorCode> {
+    match process.get_state() {
+        State::Running | State::Yielded => {
+            process.stop();
+            Ok(())
+        }
+        State::StoppedRunning | State::StoppedYielded => Err(ErrorCode::ALREADY),
+        State::Faulted | State::Terminated => Err(ErrorCode::OFF),
+    }
+}
+
+/// Resume a stopped process, or boot a terminated one.
+fn start(process: &dyn Process) -> Result<(), ErrorCode> {
+    match process.get_state() {
+        State::StoppedRunning | State::StoppedYielded => {
+            process.resume();
+            Ok(())
+        }
+        State::Terminated => {
+            process.try_restart(None);
+            Ok(())
+        }
+        State::Running | State::Yielded => Err(ErrorCode::ALREADY),
+        State::Faulted => Err(ErrorCode::OFF),
+    }
+}
+
+/// Terminate a process and start it again.
+fn restart(process: &dyn Process) -> Result<(), ErrorCode> {
+    process.try_restart(None);
+    Ok(())
+}
+
+impl<C: ProcessManagementCapability> ProcessFaultPolicy for Supervisor<'_, C> {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        let action = self.fault_policy.action(process);
+
+        let faulted = process.processid();
+        let index = self.index_of(faulted).unwrap_or(0);
+        let short_id = match process.short_app_id() {
+            ShortId::Fixed(id) => id.get() as usize,
+            ShortId::LocallyUnique => 0,
+        };
+        let action_code = match action {
+            process::FaultAction::Panic => 0,
+            process::FaultAction::Restart => 1,
+            process::FaultAction::Stop => 2,
+        };
+        self.apps.each(|processid, app, kernel_data| {
+            if app.notify_faults && processid != faulted {
+                kernel_data
+                    .schedule_upcall(upcall::FAULT, (index, short_id, action_code))
+                    .ok();
+            }
+        });
+
+        action
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for Supervisor<'_, C> {
+    /// Process lifecycle control.
+    ///
+    /// Commands other than `0` are only allowed if the TBF permissions
+    /// header of the process allows them. A process cannot control itself.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Stop the running process at index `arg1`.
+    /// - `2`: Resume the stopped process at index `arg1`, or start it again
+    ///   if it terminated.
+    /// - `3`: Terminate the process at index `arg1` and start it again.
+    /// - `4`: Enable (`arg1` = 1) or disable (`arg1` = 0) the notification
+    ///   of the faults of the other processes.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.is_permitted(processid, command_num) {
+            return CommandReturn::failure(ErrorCode::NOSUPPORT);
+        }
+
+        match command_num {
+            1 => self.control(arg1, processid, stop).into(),
+
+            2 => self.control(arg1, processid, start).into(),
+
+            3 => self.control(arg1, processid, restart).into(),
+
+            4 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.notify_faults = arg1 != 0;
+                })
+                .map_err(ErrorCode::from)
+                .into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
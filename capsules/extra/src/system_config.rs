@@ -0,0 +1,209 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides userspace with read-only metadata about the board and kernel.
+//!
+//! Applications and host tools can use this driver to discover which board
+//! and chip they are running on, the kernel version, the core clock
+//! frequency, and which syscall drivers the board exposes, instead of
+//! hardcoding per-board assumptions.
+//!
+//! The same information is also available as a text blob of `key=value`
+//! lines that is copied into a buffer the process shares with the kernel.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let system_config = static_init!(
+//!     capsules_extra::system_config::SystemConfig,
+//!     capsules_extra::system_config::SystemConfig::new(
+//!         capsules_extra::system_config::SystemInfo {
+//!             board: "nucleo_f429zi",
+//!             chip: "stm32f429zi",
+//!             cpu_frequency: 16_000_000,
+//!             drivers: &[
+//!                 capsules_core::console::DRIVER_NUM,
+//!                 capsules_core::led::DRIVER_NUM,
+//!             ],
+//!         },
+//!         board_kernel.create_grant(capsules_extra::system_config::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! ```
+
+use core::fmt::{self, Write};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SystemConfig as usize;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{WriteableProcessBuffer, WriteableProcessSlice};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the configuration blob is copied into.
+    pub const BLOB: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Static description of the board provided by the board's `main.rs`.
+pub struct SystemInfo {
+    /// Name of the board.
+    pub board: &'static str,
+    /// Name of the microcontroller.
+    pub chip: &'static str,
+    /// Frequency of the core clock in Hz.
+    pub cpu_frequency: u32,
+    /// Syscall driver numbers available on the board.
+    pub drivers: &'static [usize],
+}
+
+/// Writes formatted text into a process buffer, counting the bytes of the
+/// full output even when they do not fit.
+struct BlobWriter<'a> {
+    dest: Option<&'a WriteableProcessSlice>,
+    length: usize,
+}
+
+impl Write for BlobWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Some(dest) = self.dest {
+            let start = core::cmp::min(self.length, dest.len());
+            let end = core::cmp::min(self.length + s.len(), dest.len());
+            dest[start..end].copy_from_slice(&s.as_bytes()[..end - start]);
+        }
+        self.length += s.len();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct SystemConfig {
+    info: SystemInfo,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl SystemConfig {
+    pub fn new(
+        info: SystemInfo,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> SystemConfig {
+        SystemConfig { info, apps: grant }
+    }
+
+    fn write_blob(&self, writer: &mut BlobWriter) -> fmt::Result {
+        write!(
+            writer,
+            "board={}\nchip={}\nkernel={}.{}\ncommit={}\nclock={}\ndrivers=",
+            self.info.board,
+            self.info.chip,
+            kernel::KERNEL_MAJOR_VERSION,
+            kernel::KERNEL_MINOR_VERSION,
+            option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown"),
+            self.info.cpu_frequency,
+        )?;
+        for (i, driver_num) in self.info.drivers.iter().enumerate() {
+            if i > 0 {
+                writer.write_char(',')?;
+            }
+            write!(writer, "{:#x}", driver_num)?;
+        }
+        writer.write_char('\n')
+    }
+
+    fn blob_length(&self) -> usize {
+        let mut writer = BlobWriter {
+            dest: None,
+            length: 0,
+        };
+        let _ = self.write_blob(&mut writer);
+        writer.length
+    }
+
+    fn copy_blob(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        let length = self.blob_length();
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::BLOB)
+                    .and_then(|blob| {
+                        blob.mut_enter(|dest| {
+                            if dest.len() < length {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            let mut writer = BlobWriter {
+                                dest: Some(dest),
+                                length: 0,
+                            };
+                            let _ = self.write_blob(&mut writer);
+                            Ok(length)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl SyscallDriver for SystemConfig {
+    /// Read the system configuration.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Core clock frequency in Hz.
+    /// - `2`: Kernel major and minor version.
+    /// - `3`: Number of syscall drivers available on the board.
+    /// - `4`: Driver number at index `data1` of the driver list.
+    /// - `5`: Whether driver number `data1` is available on the board.
+    /// - `6`: Length in bytes of the configuration blob.
+    /// - `7`: Copy the configuration blob into the read-write allow buffer.
+    ///   Returns the number of bytes written.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.info.cpu_frequency),
+            2 => CommandReturn::success_u32_u32(
+                kernel::KERNEL_MAJOR_VERSION as u32,
+                kernel::KERNEL_MINOR_VERSION as u32,
+            ),
+            3 => CommandReturn::success_u32(self.info.drivers.len() as u32),
+            4 => match self.info.drivers.get(data1) {
+                Some(driver_num) => CommandReturn::success_u32(*driver_num as u32),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            5 => {
+                if self.info.drivers.contains(&data1) {
+                    CommandReturn::success()
+                } else {
+                    CommandReturn::failure(ErrorCode::NODEVICE)
+                }
+            }
+            6 => CommandReturn::success_u32(self.blob_length() as u32),
+            7 => match self.copy_blob(processid) {
+                Ok(length) => CommandReturn::success_u32(length as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,543 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Tamper detection for secure devices.
+//!
+//! Payment terminals and other secure devices must notice when their
+//! enclosure is opened, and destroy their secrets before an attacker can read
+//! them. This capsule watches tamper sensors armed as deep-sleep wakeup
+//! sources (`hil::wakeup_source`), such as a case switch on a GPIO pin, a
+//! light sensor behind a comparator, or the threshold interrupt of an
+//! accelerometer, so the device keeps detecting tampering while it sleeps.
+//!
+//! On each tamper event, the capsule:
+//!
+//! - records the sensor and the time of the event in a log in nonvolatile
+//!   storage. The board should give the capsule a storage region that
+//!   processes cannot access, so the log cannot be erased to hide the event.
+//! - deletes the keys listed by the board from the key-value store.
+//! - notifies the processes.
+//!
+//! Events are timestamped in milliseconds since boot. Events of a sensor that
+//! occur while its previous event is being logged are merged with it.
+//!
+//! Log Format
+//! ----------
+//!
+//! All fields are 32-bit little-endian values. The records form a ring
+//! buffer: record `n` is stored in slot `n % capacity`.
+//!
+//! ```text
+//! 0          4          8
+//! +----------+----------+------------------------------------------
+//! | "TMPR"   | count    | capacity * { time (ms), sensor index }
+//! +----------+----------+------------------------------------------
+//! ```
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! - `0`: Tamper event. The upcall receives the index of the sensor and the
+//!   time of the event.
+//! - `1`: Log record read. The upcall receives the status, the index of the
+//!   sensor and the time of the event.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Get the number of events recorded since the log was created.
+//! - `2`: Read record `data` of the log. Only the last `capacity` records are
+//!   kept, older ones return `INVAL`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let case_switch = static_init!(
+//!     capsules_extra::tamper::TamperInput<'static>,
+//!     capsules_extra::tamper::TamperInput::new(
+//!         &nrf52840_peripherals.gpio_port[CASE_SWITCH_PIN],
+//!         kernel::hil::wakeup_source::WakeupEdge::Rising,
+//!     )
+//! );
+//! let tamper = components::tamper::TamperComponent::new(
+//!     board_kernel,
+//!     capsules_extra::tamper::DRIVER_NUM,
+//!     alarm,
+//!     tamper_storage,
+//!     kv_tamper,
+//!     static_init!([&'static TamperInput<'static>; 1], [case_switch]),
+//!     &[b"payment-key"],
+//!     0x60000,
+//!     64,
+//! )
+//! .finalize(components::tamper_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::mx25r6435f::MX25R6435F<'static, ...>,
+//!     capsules_extra::virtual_kv::VirtualKVPermissions<'static, KVStorePermissionsType>
+//! ));
+//! tamper.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::kv;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::hil::time::{ConvertTicks, Time};
+use kernel::hil::wakeup_source::{WakeupClient, WakeupEdge, WakeupSource};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tamper as usize;
+
+/// Ids for subscribe upcalls.
+mod upcall {
+    /// A tamper sensor fired.
+    pub const TAMPERED: usize = 0;
+    /// A log record was read.
+    pub const RECORD: usize = 1;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 2;
+}
+
+/// Identifies the log in storage.
+const MAGIC: [u8; 4] = *b"TMPR";
+/// Length of the log header and of each record.
+pub const HEADER_LEN: usize = 8;
+pub const RECORD_LEN: usize = 8;
+/// Length of the buffer used to access the log.
+pub const BUFFER_LEN: usize = 8;
+/// Longest key that can be deleted from the key-value store.
+pub const KEY_BUFFER_LEN: usize = 64;
+
+/// Client of the tamper sensors.
+pub trait TamperInputClient {
+    /// One of the sensors fired.
+    fn input_fired(&self);
+}
+
+/// A tamper sensor: a wakeup source and the edge signaling tampering.
+pub struct TamperInput<'a> {
+    source: &'a dyn WakeupSource<'a>,
+    edge: WakeupEdge,
+    /// The sensor fired and the event was not handled yet.
+    fired: Cell<bool>,
+    /// Time of an event not logged yet.
+    pending: OptionalCell<u32>,
+    client: OptionalCell<&'a dyn TamperInputClient>,
+}
+
+impl<'a> TamperInput<'a> {
+    pub fn new(source: &'a dyn WakeupSource<'a>, edge: WakeupEdge) -> TamperInput<'a> {
+        TamperInput {
+            source,
+            edge,
+            fired: Cell::new(false),
+            pending: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the client, and register the sensor as the client of its wakeup
+    /// source.
+    pub fn set_client(&'a self, client: &'a dyn TamperInputClient) {
+        self.client.set(client);
+        self.source.set_wakeup_client(self);
+    }
+
+    fn arm(&self) -> Result<(), ErrorCode> {
+        self.source.enable_wakeup(self.edge)
+    }
+}
+
+impl WakeupClient for TamperInput<'_> {
+    fn woken(&self) {
+        self.fired.set(true);
+        self.client.map(|client| client.input_fired());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// The log header was not read yet.
+    Uninitialized,
+    ReadingHeader,
+    Idle,
+    WritingRecord,
+    WritingHeader,
+    /// Reading a record for a process.
+    ReadingRecord,
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Tamper<'a, T: Time, S: NonvolatileStorage<'a>, K: kv::KVPermissions<'a>> {
+    time: &'a T,
+    storage: &'a S,
+    kv: &'a K,
+    permissions: StoragePermissions,
+    inputs: &'a [&'a TamperInput<'a>],
+    /// Keys deleted from the key-value store on tamper.
+    keys: &'a [&'a [u8]],
+    /// Address of the log in the storage, and number of records it holds.
+    log_address: usize,
+    log_capacity: usize,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    state: Cell<State>,
+    /// Number of events recorded since the log was created.
+    count: Cell<u32>,
+    /// The process reading a record.
+    reader: OptionalCell<ProcessId>,
+    /// Index of the next key to delete, while keys are being deleted.
+    zeroizing: OptionalCell<usize>,
+    buffer: TakeCell<'static, [u8]>,
+    key_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, T: Time, S: NonvolatileStorage<'a>, K: kv::KVPermissions<'a>> Tamper<'a, T, S, K> {
+    /// `buffer` must be `BUFFER_LEN` long and `key_buffer` `KEY_BUFFER_LEN`
+    /// long. The log takes `HEADER_LEN + log_capacity * RECORD_LEN` bytes
+    /// of storage starting at `log_address`.
+    pub fn new(
+        time: &'a T,
+        storage: &'a S,
+        kv: &'a K,
+        permissions: StoragePermissions,
+        inputs: &'a [&'a TamperInput<'a>],
+        keys: &'a [&'a [u8]],
+        log_address: usize,
+        log_capacity: usize,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+        buffer: &'static mut [u8],
+        key_buffer: &'static mut [u8],
+    ) -> Tamper<'a, T, S, K> {
+        Tamper {
+            time,
+            storage,
+            kv,
+            permissions,
+            inputs,
+            keys,
+            log_address,
+            log_capacity,
+            apps: grant,
+            state: Cell::new(State::Uninitialized),
+            count: Cell::new(0),
+            reader: OptionalCell::empty(),
+            zeroizing: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            key_buffer: TakeCell::new(key_buffer),
+        }
+    }
+
+    /// Arm the sensors and read the log header. Returns the first error of
+    /// the sensors that could not be armed; the other sensors are armed
+    /// anyway.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        let mut armed = Ok(());
+        for input in self.inputs.iter() {
+            armed = armed.and(input.arm());
+        }
+
+        if self.state.get() == State::Uninitialized {
+            self.buffer.take().map(|buffer| {
+                match self.storage.read(buffer, self.log_address, HEADER_LEN) {
+                    Ok(()) => self.state.set(State::ReadingHeader),
+                    // The log cannot be read: start a new one, which
+                    // overwrites it.
+                    Err(_) => self.state.set(State::Idle),
+                }
+            });
+        }
+        armed
+    }
+
+    fn record_address(&self, index: u32) -> usize {
+        self.log_address + HEADER_LEN + (index as usize % self.log_capacity) * RECORD_LEN
+    }
+
+    /// Log the next pending event, if the log is idle.
+    fn write_next(&self) {
+        if self.state.get() != State::Idle {
+            return;
+        }
+        let event = self
+            .inputs
+            .iter()
+            .enumerate()
+            .find_map(|(index, input)| input.pending.get().map(|time| (index, time)));
+        if let Some((index, time)) = event {
+            self.buffer.take().map(|buffer| {
+                encode_record(buffer, time, index as u32);
+                let address = self.record_address(self.count.get());
+                match self.storage.write(buffer, address, RECORD_LEN) {
+                    Ok(()) => self.state.set(State::WritingRecord),
+                    // The storage is unusable, the event is only reported
+                    // to the processes.
+                    Err(_) => {
+                        self.inputs[index].pending.clear();
+                    }
+                }
+            });
+        }
+    }
+
+    /// Delete the next key from the key-value store.
+    fn zeroize_next(&self) {
+        while let Some(index) = self.zeroizing.get() {
+            let key = match self.keys.get(index) {
+                Some(key) => key,
+                None => {
+                    self.zeroizing.clear();
+                    return;
+                }
+            };
+            self.zeroizing.set(index + 1);
+            if key.len() > KEY_BUFFER_LEN {
+                continue;
+            }
+            let key_buffer = match self.key_buffer.take() {
+                Some(key_buffer) => key_buffer,
+                None => return,
+            };
+            key_buffer[..key.len()].copy_from_slice(key);
+            let mut key_slice = SubSliceMut::new(key_buffer);
+            key_slice.slice(..key.len());
+            match self.kv.delete(key_slice, self.permissions) {
+                Ok(()) => return,
+                Err((key_slice, _)) => {
+                    self.key_buffer.replace(key_slice.take());
+                }
+            }
+        }
+    }
+
+    fn read_record(&self, index: u32, processid: ProcessId) -> Result<(), ErrorCode> {
+        let count = self.count.get();
+        if index >= count || count - index > self.log_capacity as u32 {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        match self
+            .storage
+            .read(buffer, self.record_address(index), RECORD_LEN)
+        {
+            Ok(()) => {
+                self.state.set(State::ReadingRecord);
+                self.reader.set(processid);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<'a, T: Time, S: NonvolatileStorage<'a>, K: kv::KVPermissions<'a>> TamperInputClient
+    for Tamper<'a, T, S, K>
+{
+    fn input_fired(&self) {
+        let now = self.time.ticks_to_ms(self.time.now());
+        for (index, input) in self.inputs.iter().enumerate() {
+            if !input.fired.replace(false) {
+                continue;
+            }
+            if input.pending.is_none() {
+                input.pending.set(now);
+            }
+            self.apps.each(|_, _, upcalls| {
+                upcalls
+                    .schedule_upcall(upcall::TAMPERED, (index, now as usize, 0))
+                    .ok();
+            });
+        }
+
+        if self.zeroizing.is_none() {
+            self.zeroizing.set(0);
+            self.zeroize_next();
+        }
+        self.write_next();
+    }
+}
+
+impl<'a, T: Time, S: NonvolatileStorage<'a>, K: kv::KVPermissions<'a>> NonvolatileStorageClient
+    for Tamper<'a, T, S, K>
+{
+    fn read_done(&self, buffer: &'static mut [u8], _length: usize) {
+        match self.state.get() {
+            State::ReadingHeader => {
+                self.count.set(decode_header(buffer).unwrap_or(0));
+            }
+            State::ReadingRecord => {
+                let (time, index) = decode_record(buffer);
+                self.reader.take().map(|processid| {
+                    let _ = self.apps.enter(processid, |_, upcalls| {
+                        upcalls
+                            .schedule_upcall(upcall::RECORD, (0, index as usize, time as usize))
+                            .ok();
+                    });
+                });
+            }
+            _ => {}
+        }
+        self.buffer.replace(buffer);
+        self.state.set(State::Idle);
+        self.write_next();
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        match self.state.get() {
+            State::WritingRecord => {
+                let (_, index) = decode_record(buffer);
+                self.inputs
+                    .get(index as usize)
+                    .map(|input| input.pending.clear());
+                self.count.set(self.count.get().wrapping_add(1));
+                encode_header(buffer, self.count.get());
+                match self.storage.write(buffer, self.log_address, HEADER_LEN) {
+                    Ok(()) => self.state.set(State::WritingHeader),
+                    Err(_) => self.state.set(State::Idle),
+                }
+            }
+            _ => {
+                self.buffer.replace(buffer);
+                self.state.set(State::Idle);
+            }
+        }
+        self.write_next();
+    }
+}
+
+impl<'a, T: Time, S: NonvolatileStorage<'a>, K: kv::KVPermissions<'a>> kv::KVClient
+    for Tamper<'a, T, S, K>
+{
+    fn get_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+    }
+
+    fn set_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+        self.key_buffer.replace(key.take());
+    }
+
+    /// A key was deleted, or did not exist: delete the next one.
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        self.zeroize_next();
+    }
+}
+
+impl<'a, T: Time, S: NonvolatileStorage<'a>, K: kv::KVPermissions<'a>> SyscallDriver
+    for Tamper<'a, T, S, K>
+{
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.count.get()),
+
+            2 => self.read_record(data as u32, processid).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+fn encode_header(buffer: &mut [u8], count: u32) {
+    buffer[0..4].copy_from_slice(&MAGIC);
+    buffer[4..8].copy_from_slice(&count.to_le_bytes());
+}
+
+/// The number of recorded events, or `None` if the storage does not hold a
+/// log.
+fn decode_header(buffer: &[u8]) -> Option<u32> {
+    if buffer[0..4] != MAGIC {
+        return None;
+    }
+    Some(u32::from_le_bytes([
+        buffer[4], buffer[5], buffer[6], buffer[7],
+    ]))
+}
+
+fn encode_record(buffer: &mut [u8], time: u32, index: u32) {
+    buffer[0..4].copy_from_slice(&time.to_le_bytes());
+    buffer[4..8].copy_from_slice(&index.to_le_bytes());
+}
+
+fn decode_record(buffer: &[u8]) -> (u32, u32) {
+    (
+        u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]),
+        u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let mut buffer = [0xFF; BUFFER_LEN];
+        assert_eq!(decode_header(&buffer), None);
+        encode_header(&mut buffer, 42);
+        assert_eq!(decode_header(&buffer), Some(42));
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let mut buffer = [0; BUFFER_LEN];
+        encode_record(&mut buffer, 123_456, 2);
+        assert_eq!(decode_record(&buffer), (123_456, 2));
+    }
+}
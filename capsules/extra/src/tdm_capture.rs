@@ -0,0 +1,330 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Multi-channel audio capture from a TDM microphone array.
+//!
+//! This capsule captures the frames of an `hil::audio::TdmAudioSource` (for
+//! example a microphone array on an I2S/TDM bus) and de-interleaves them in
+//! the kernel: each channel (TDM slot) is written to its own buffer shared by
+//! the process, so beamforming applications get one contiguous array per
+//! microphone.
+//!
+//! All processes capturing at the same time share the same slot count and
+//! sample rate. A process selects the channels it is interested in by only
+//! sharing buffers for them.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `allow_readwrite` System Call
+//!
+//! Buffer `n` (0 to 7) receives the samples of channel `n`, as signed 16-bit
+//! little-endian values. Channels without a buffer are skipped.
+//!
+//! ### `subscribe` System Call
+//!
+//! Subscribe `0` is called every time the channel buffers are full, with:
+//!
+//! - the number of samples written in each channel buffer,
+//! - the number of channels (slots) of the capture.
+//!
+//! The next samples are written from the start of the buffers again.
+//!
+//! ### `command` System Call
+//!
+//! - `0`: Driver check.
+//! - `1`: Get the sample rate in Hz.
+//! - `2`: Start capturing with `data` channels (1 to 8). Returns `BUSY` if
+//!   another process is capturing with a different number of channels, and
+//!   `INVAL` or `NOSUPPORT` if the source cannot capture `data` channels.
+//! - `3`: Stop capturing for the process. The source is stopped when no
+//!   process uses it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let buffer1 = static_init!([i16; 512], [0; 512]);
+//! let buffer2 = static_init!([i16; 512], [0; 512]);
+//! let tdm_capture = static_init!(
+//!     capsules_extra::tdm_capture::TdmCapture<'static, Sai<'static>>,
+//!     capsules_extra::tdm_capture::TdmCapture::new(
+//!         &peripherals.sai1,
+//!         buffer1,
+//!         buffer2,
+//!         board_kernel.create_grant(
+//!             capsules_extra::tdm_capture::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! kernel::hil::audio::AudioSource::set_client(&peripherals.sai1, tdm_capture);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::audio::{AudioClient, TdmAudioSource, MAX_TDM_SLOTS};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::TakeCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::TdmCapture as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// One buffer per channel, `0` to `MAX_TDM_SLOTS - 1`.
+    pub const COUNT: u8 = kernel::hil::audio::MAX_TDM_SLOTS as u8;
+}
+
+/// Size of a sample in the process buffers.
+const SAMPLE_BYTES: usize = 2;
+
+/// The samples of `channel` in a buffer of interleaved `slots`-slot frames.
+fn channel_samples(frames: &[i16], slots: usize, channel: usize) -> impl Iterator<Item = i16> + '_ {
+    frames.iter().skip(channel).step_by(slots).copied()
+}
+
+#[derive(Default)]
+pub struct App {
+    enabled: bool,
+    /// Number of samples already written in each channel buffer.
+    position: usize,
+}
+
+pub struct TdmCapture<'a, S: TdmAudioSource<'a>> {
+    source: &'a S,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    buffer1: TakeCell<'static, [i16]>,
+    buffer2: TakeCell<'static, [i16]>,
+    running: Cell<bool>,
+}
+
+impl<'a, S: TdmAudioSource<'a>> TdmCapture<'a, S> {
+    pub fn new(
+        source: &'a S,
+        buffer1: &'static mut [i16],
+        buffer2: &'static mut [i16],
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> TdmCapture<'a, S> {
+        TdmCapture {
+            source,
+            apps: grant,
+            buffer1: TakeCell::new(buffer1),
+            buffer2: TakeCell::new(buffer2),
+            running: Cell::new(false),
+        }
+    }
+
+    fn start(&self, slots: usize) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return if slots == self.source.slots() {
+                Ok(())
+            } else {
+                Err(ErrorCode::BUSY)
+            };
+        }
+
+        self.source.set_slots(slots)?;
+
+        let (buffer1, buffer2) = match (self.buffer1.take(), self.buffer2.take()) {
+            (Some(buffer1), Some(buffer2)) => (buffer1, buffer2),
+            (buffer1, buffer2) => {
+                self.buffer1.put(buffer1);
+                self.buffer2.put(buffer2);
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+
+        self.source
+            .start(buffer1, buffer2)
+            .map(|()| self.running.set(true))
+            .map_err(|(err, buffer1, buffer2)| {
+                self.buffer1.replace(buffer1);
+                self.buffer2.replace(buffer2);
+                err
+            })
+    }
+
+    /// Stop the source if no process is using it anymore.
+    fn stop_if_unused(&self) {
+        let mut in_use = false;
+        for app in self.apps.iter() {
+            app.enter(|app, _| in_use |= app.enabled);
+        }
+        if !in_use && self.running.get() {
+            self.running.set(false);
+            let _ = self.source.stop();
+            if let Ok((buffer1, buffer2)) = self.source.retrieve_buffers() {
+                buffer1.map(|buffer| self.store_buffer(buffer));
+                buffer2.map(|buffer| self.store_buffer(buffer));
+            }
+        }
+    }
+
+    fn store_buffer(&self, buffer: &'static mut [i16]) {
+        if self.buffer1.is_none() {
+            self.buffer1.replace(buffer);
+        } else {
+            self.buffer2.replace(buffer);
+        }
+    }
+}
+
+impl<'a, S: TdmAudioSource<'a>> AudioClient for TdmCapture<'a, S> {
+    fn samples_ready(&self, buffer: &'static mut [i16], length: usize) {
+        if !self.running.get() {
+            self.store_buffer(buffer);
+            return;
+        }
+
+        let slots = self.source.slots();
+        let frames = &buffer[..length - length % slots];
+
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                if !app.enabled {
+                    return;
+                }
+
+                // The channel buffers fill up together, so their capacity is
+                // the one of the smallest buffer shared.
+                let capacity = (0..slots)
+                    .filter_map(|channel| {
+                        kernel_data
+                            .get_readwrite_processbuffer(channel)
+                            .map(|buffer| buffer.len() / SAMPLE_BYTES)
+                            .ok()
+                            .filter(|&len| len > 0)
+                    })
+                    .min();
+                let capacity = match capacity {
+                    Some(capacity) => capacity,
+                    None => return,
+                };
+
+                // The buffers may have been replaced by smaller ones.
+                if app.position >= capacity {
+                    app.position = 0;
+                }
+
+                let mut remaining = frames;
+                while !remaining.is_empty() {
+                    let position = app.position;
+                    let count = core::cmp::min(capacity - position, remaining.len() / slots);
+                    let (chunk, rest) = remaining.split_at(count * slots);
+
+                    for channel in 0..slots {
+                        let _ =
+                            kernel_data
+                                .get_readwrite_processbuffer(channel)
+                                .and_then(|buffer| {
+                                    buffer.mut_enter(|dest| {
+                                        if dest.len() < capacity * SAMPLE_BYTES {
+                                            return;
+                                        }
+                                        for (i, sample) in
+                                            channel_samples(chunk, slots, channel).enumerate()
+                                        {
+                                            let offset = (position + i) * SAMPLE_BYTES;
+                                            dest[offset..offset + SAMPLE_BYTES]
+                                                .copy_from_slice(&sample.to_le_bytes());
+                                        }
+                                    })
+                                });
+                    }
+
+                    app.position += count;
+                    if app.position >= capacity {
+                        app.position = 0;
+                        let _ = kernel_data.schedule_upcall(0, (capacity, slots, 0));
+                    }
+                    remaining = rest;
+                }
+            });
+        }
+
+        if let Err((_, buffer)) = self.source.provide_buffer(buffer) {
+            self.store_buffer(buffer);
+        }
+    }
+}
+
+impl<'a, S: TdmAudioSource<'a>> SyscallDriver for TdmCapture<'a, S> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.source.sample_rate()),
+
+            2 => {
+                if data == 0 || data > MAX_TDM_SLOTS {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                let res = self.start(data).and_then(|()| {
+                    self.apps
+                        .enter(processid, |app, _| {
+                            app.enabled = true;
+                            app.position = 0;
+                        })
+                        .map_err(ErrorCode::from)
+                });
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => {
+                        self.stop_if_unused();
+                        CommandReturn::failure(err)
+                    }
+                }
+            }
+
+            3 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| app.enabled = false)
+                    .map_err(ErrorCode::from);
+                self.stop_if_unused();
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => CommandReturn::failure(err),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deinterleave_channels() {
+        // Three frames of four slots.
+        let frames = [0, 10, 20, 30, 1, 11, 21, 31, 2, 12, 22, 32];
+        let mut channel = [0; 3];
+        for (dst, src) in channel.iter_mut().zip(channel_samples(&frames, 4, 2)) {
+            *dst = src;
+        }
+        assert_eq!(channel, [20, 21, 22]);
+        assert_eq!(channel_samples(&frames, 4, 3).count(), 3);
+        assert_eq!(channel_samples(&frames, 1, 0).count(), 12);
+    }
+}
@@ -0,0 +1,186 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Temperature compensation service based on the chip's internal temperature
+//! sensor.
+//!
+//! This capsule periodically samples a `TemperatureDriver` (for example the
+//! nRF52 `TEMP` peripheral or the STM32 internal temperature ADC channel),
+//! caches the last reading, and implements
+//! `hil::sensors::TemperatureCompensation` so that other capsules (ADC
+//! drivers, RTC trimming, ...) can query the die temperature and correct
+//! their measurements for temperature drift without owning the sensor.
+//!
+//! The capsule also implements `TemperatureDriver` itself, so the temperature
+//! syscall driver can be stacked on top of it: userspace reads are forwarded
+//! to the sensor and also refresh the cached value.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let compensation = static_init!(
+//!     capsules_extra::temperature_compensation::TemperatureCompensator<
+//!         'static,
+//!         nrf52840::temperature::Temp<'static>,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     >,
+//!     capsules_extra::temperature_compensation::TemperatureCompensator::new(
+//!         &base_peripherals.temp,
+//!         compensation_alarm,
+//!         10_000,
+//!     )
+//! );
+//! base_peripherals.temp.set_client(compensation);
+//! compensation_alarm.set_alarm_client(compensation);
+//! compensation.start();
+//!
+//! // In a consumer, e.g. for a 32.768 kHz crystal:
+//! let drift = compensation.parabolic_drift_ppb(2500, -34).unwrap_or(0);
+//! let ticks = compensation.compensate(ticks, drift);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::sensors::{TemperatureClient, TemperatureCompensation, TemperatureDriver};
+use kernel::hil::time::{self, Alarm, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+pub struct TemperatureCompensator<'a, T: TemperatureDriver<'a>, A: Alarm<'a>> {
+    sensor: &'a T,
+    alarm: &'a A,
+    /// Interval between two readings of the sensor, in milliseconds.
+    period_ms: u32,
+    /// Last temperature read, in hundredths of degrees centigrade.
+    temperature: OptionalCell<i32>,
+    /// Whether a reading of the sensor is in progress.
+    reading: Cell<bool>,
+    /// Whether `client` is waiting for the reading in progress.
+    client_pending: Cell<bool>,
+    client: OptionalCell<&'a dyn TemperatureClient>,
+}
+
+impl<'a, T: TemperatureDriver<'a>, A: Alarm<'a>> TemperatureCompensator<'a, T, A> {
+    pub fn new(sensor: &'a T, alarm: &'a A, period_ms: u32) -> TemperatureCompensator<'a, T, A> {
+        TemperatureCompensator {
+            sensor,
+            alarm,
+            period_ms,
+            temperature: OptionalCell::empty(),
+            reading: Cell::new(false),
+            client_pending: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Take a first reading and start the periodic sampling.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.arm();
+        self.read()
+    }
+
+    fn arm(&self) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+    }
+
+    fn read(&self) -> Result<(), ErrorCode> {
+        if self.reading.get() {
+            return Ok(());
+        }
+        self.sensor
+            .read_temperature()
+            .map(|()| self.reading.set(true))
+    }
+}
+
+impl<'a, T: TemperatureDriver<'a>, A: Alarm<'a>> time::AlarmClient
+    for TemperatureCompensator<'a, T, A>
+{
+    fn alarm(&self) {
+        self.arm();
+        // If the sensor is busy, the cached value stays valid until the next
+        // period.
+        let _ = self.read();
+    }
+}
+
+impl<'a, T: TemperatureDriver<'a>, A: Alarm<'a>> TemperatureClient
+    for TemperatureCompensator<'a, T, A>
+{
+    fn callback(&self, value: Result<i32, ErrorCode>) {
+        self.reading.set(false);
+        if let Ok(temperature) = value {
+            self.temperature.set(temperature);
+        }
+
+        if self.client_pending.take() {
+            self.client.map(|client| client.callback(value));
+        }
+    }
+}
+
+impl<'a, T: TemperatureDriver<'a>, A: Alarm<'a>> TemperatureDriver<'a>
+    for TemperatureCompensator<'a, T, A>
+{
+    fn set_client(&self, client: &'a dyn TemperatureClient) {
+        self.client.set(client);
+    }
+
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        self.read().map(|()| self.client_pending.set(true))
+    }
+}
+
+impl<'a, T: TemperatureDriver<'a>, A: Alarm<'a>> TemperatureCompensation
+    for TemperatureCompensator<'a, T, A>
+{
+    fn temperature(&self) -> Option<i32> {
+        self.temperature.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedTemperature(Option<i32>);
+
+    impl TemperatureCompensation for FixedTemperature {
+        fn temperature(&self) -> Option<i32> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn linear_drift() {
+        // 10 °C above the reference with a 50 ppm/°C coefficient.
+        let sensor = FixedTemperature(Some(3500));
+        assert_eq!(sensor.linear_drift_ppb(2500, 50), Some(500_000));
+        assert_eq!(sensor.linear_drift_ppb(4500, 50), Some(-500_000));
+        assert_eq!(FixedTemperature(None).linear_drift_ppb(2500, 50), None);
+    }
+
+    #[test]
+    fn parabolic_drift() {
+        // A 32.768 kHz crystal runs 3.4 ppm slow 10 °C away from turnover.
+        let sensor = FixedTemperature(Some(1500));
+        assert_eq!(sensor.parabolic_drift_ppb(2500, -34), Some(-3400));
+        assert_eq!(
+            FixedTemperature(Some(2500)).parabolic_drift_ppb(2500, -34),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn compensate() {
+        let sensor = FixedTemperature(Some(3500));
+        assert_eq!(sensor.compensate(1_000_000, 500_000), 999_500);
+        assert_eq!(sensor.compensate(1_000_000, -3400), 1_000_003);
+        assert_eq!(sensor.compensate(-4_000_000, 500_000), -3_998_000);
+    }
+}
@@ -42,8 +42,9 @@ impl<'a, A: adc::AdcChannel<'a>> TemperatureRp2040<'a, A> {
 }
 
 impl<'a, A: adc::AdcChannel<'a>> adc::Client for TemperatureRp2040<'a, A> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
         self.status.set(Status::Idle);
+        let sample = adc::sample_to_u16(sample, self.adc.get_sample_width_bits());
         self.temperature_client.map(|client| {
             client.callback(Ok(((27.0
                 - (((sample as f32 * 3.3 / 65535.0) - self.v_27) * 1000.0 / self.slope))
@@ -42,8 +42,9 @@ impl<'a, A: adc::AdcChannel<'a>> TemperatureSTM<'a, A> {
 }
 
 impl<'a, A: adc::AdcChannel<'a>> adc::Client for TemperatureSTM<'a, A> {
-    fn sample_ready(&self, sample: u16) {
+    fn sample_ready(&self, sample: u32) {
         self.status.set(Status::Idle);
+        let sample = adc::sample_to_u16(sample, self.adc.get_sample_width_bits());
         self.temperature_client.map(|client| {
             client.callback(Ok(
                 ((((self.v_25 - (sample as f32 * 3.3 / 65535.0)) * 1000.0 / self.slope) + 25.0)
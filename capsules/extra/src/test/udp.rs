@@ -17,7 +17,7 @@ use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
 use core::cell::Cell;
 
 use kernel::debug;
-use kernel::hil::time::{self, Alarm, Frequency};
+use kernel::hil::time::{self, Alarm, ConvertTicks};
 use kernel::utilities::cells::MapCell;
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::ErrorCode;
@@ -73,9 +73,8 @@ impl<'a, A: Alarm<'a>> MockUdp<'a, A> {
     pub fn start_sending(&self) {
         // Set alarm bc if you try to send immediately there are initialization issues
         self.send_loop.set(true);
-        let delay = <A::Frequency>::frequency() * SEND_INTERVAL_SECONDS;
-        self.alarm
-            .set_alarm(self.alarm.now(), A::Ticks::from(delay));
+        let delay = self.alarm.ticks_from_seconds(SEND_INTERVAL_SECONDS);
+        self.alarm.set_alarm(self.alarm.now(), delay);
     }
 
     pub fn update_capability(&self, new_cap: &'static NetworkCapability) {
@@ -198,9 +197,8 @@ impl<'a, A: Alarm<'a>> UDPSendClient for MockUdp<'a, A> {
         dgram.reset();
         self.udp_dgram.replace(dgram);
         debug!("");
-        let delay = <A::Frequency>::frequency() * SEND_INTERVAL_SECONDS;
-        self.alarm
-            .set_alarm(self.alarm.now(), A::Ticks::from(delay));
+        let delay = self.alarm.ticks_from_seconds(SEND_INTERVAL_SECONDS);
+        self.alarm.set_alarm(self.alarm.now(), delay);
     }
 }
 
@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Monotonic 64-bit uptime counter.
+//!
+//! Hardware counters are often only 24 or 32 bits wide and wrap after hours
+//! or days. This capsule extends such a counter to 64 bits by counting its
+//! wraps, so long-running deployments get timestamps that never wrap.
+//!
+//! A wrap is detected whenever the counter is read and its value is lower
+//! than the previous reading. To make sure no wrap is missed, the capsule
+//! reads the counter from a periodic alarm firing twice per wrap period.
+//! The underlying counter must be at most 32 bits wide.
+//!
+//! Kernel clients use the capsule through `hil::time::Time`, with
+//! `Ticks64` ticks at the frequency of the underlying alarm.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let uptime = static_init!(
+//!     capsules_extra::uptime::Uptime<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::uptime::Uptime::new(
+//!         uptime_alarm,
+//!         board_kernel.create_grant(capsules_extra::uptime::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! uptime_alarm.set_alarm_client(uptime);
+//! uptime.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::{self, Frequency, PeriodicAlarm, Ticks, Ticks64, Time};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Uptime as usize;
+
+/// Convert a number of ticks at `frequency` Hz to microseconds, saturating
+/// at `u64::MAX`.
+fn ticks_to_us(ticks: u64, frequency: u32) -> u64 {
+    time::checked_us_from_ticks(ticks, frequency).unwrap_or(u64::MAX)
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Uptime<'a, A: PeriodicAlarm<'a>> {
+    alarm: &'a A,
+    /// Number of times the underlying counter wrapped.
+    wraps: Cell<u64>,
+    /// Last value read from the underlying counter.
+    last: Cell<u32>,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, A: PeriodicAlarm<'a>> Uptime<'a, A> {
+    pub fn new(
+        alarm: &'a A,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Uptime<'a, A> {
+        Uptime {
+            alarm,
+            wraps: Cell::new(0),
+            last: Cell::new(0),
+            apps: grant,
+        }
+    }
+
+    /// Start tracking the wraps of the underlying counter. This must be
+    /// called before the counter wraps for the first time.
+    pub fn start(&self) {
+        let now = self.alarm.now();
+        self.last.set(now.into_u32());
+        self.alarm
+            .set_periodic_alarm(now, A::Ticks::half_max_value());
+    }
+
+    /// Read the underlying counter, accounting for a wrap since the previous
+    /// reading.
+    fn read(&self) -> u64 {
+        let now = self.alarm.now().into_u32();
+        if now < self.last.get() {
+            self.wraps.set(self.wraps.get() + 1);
+        }
+        self.last.set(now);
+        (self.wraps.get() << A::Ticks::width()) + now as u64
+    }
+}
+
+impl<'a, A: PeriodicAlarm<'a>> Time for Uptime<'a, A> {
+    type Frequency = A::Frequency;
+    type Ticks = Ticks64;
+
+    fn now(&self) -> Ticks64 {
+        Ticks64::from(self.read())
+    }
+}
+
+impl<'a, A: PeriodicAlarm<'a>> time::AlarmClient for Uptime<'a, A> {
+    fn alarm(&self) {
+        // Only read the counter so that a wrap is never missed.
+        self.read();
+    }
+}
+
+impl<'a, A: PeriodicAlarm<'a>> SyscallDriver for Uptime<'a, A> {
+    /// Read the uptime.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Frequency of the uptime counter in Hz.
+    /// - `2`: Uptime in ticks, as a 64-bit value.
+    /// - `3`: Uptime in microseconds, as a 64-bit value.
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(A::Frequency::frequency()),
+            2 => CommandReturn::success_u64(self.read()),
+            3 => CommandReturn::success_u64(ticks_to_us(self.read(), A::Frequency::frequency())),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_ticks_to_us() {
+        assert_eq!(ticks_to_us(32768, 32768), 1_000_000);
+        assert_eq!(ticks_to_us(1, 32768), 30);
+        assert_eq!(ticks_to_us(16, 16_000_000), 1);
+        // More than 2^64 / 10^6 ticks.
+        assert_eq!(ticks_to_us(u64::MAX, 1_000_000), u64::MAX);
+    }
+}
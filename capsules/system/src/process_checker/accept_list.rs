@@ -0,0 +1,316 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Credential checking policy backed by an accept list stored in flash.
+//!
+//! The accept list lives in a dedicated flash region that the board maps into
+//! the kernel as a `&'static [u8]`. It holds the public keys that may sign
+//! applications and the ShortIds that applications are allowed to keep.
+//! Because the list is read from flash every time a process is checked, a
+//! privileged capsule can rewrite the region to rotate signing keys without
+//! rebuilding the kernel.
+//!
+//! Region layout (all integers little endian):
+//!
+//! ```text
+//! +0  magic "TKAL"
+//! +4  u32 number of entries
+//! +8  entries, each:
+//!       u8  kind (0 = ShortId, 1 = public key)
+//!       u8  reserved (0)
+//!       u16 payload length in bytes
+//!       payload, padded with zeros to a multiple of 4 bytes
+//! ```
+//!
+//! A region that does not start with the magic value (e.g. erased flash) is
+//! treated as an empty list.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let list = AcceptList::new(unsafe {
+//!     core::slice::from_raw_parts(&_saccept_list as *const u8, ACCEPT_LIST_LEN)
+//! });
+//! let checker = static_init!(
+//!     AppCheckerAcceptList<SignatureChecker, EcdsaP256>,
+//!     AppCheckerAcceptList::new(list, signature_checker, ecdsa)
+//! );
+//! signature_checker.set_client(checker);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::public_key_crypto::keys::PubKey;
+use kernel::process::{ProcessBinary, ShortId};
+use kernel::process_checker::CheckResult;
+use kernel::process_checker::Compress;
+use kernel::process_checker::{AppCredentialsPolicy, AppCredentialsPolicyClient};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+use tock_tbf::types::TbfFooterV2Credentials;
+
+/// Magic value at the start of a valid accept list region.
+pub const MAGIC: [u8; 4] = *b"TKAL";
+
+/// Size of the region header (magic and entry count).
+const HEADER_LEN: usize = 8;
+/// Size of the header in front of each entry payload.
+const ENTRY_HEADER_LEN: usize = 4;
+
+const KIND_SHORT_ID: u8 = 0;
+const KIND_PUBLIC_KEY: u8 = 1;
+
+/// One entry of an accept list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AcceptListEntry<'a> {
+    /// A ShortId applications are allowed to be assigned.
+    ShortId(u32),
+    /// A public key applications may be signed with.
+    PublicKey(&'a [u8]),
+}
+
+/// A read-only view of an accept list region.
+#[derive(Clone, Copy)]
+pub struct AcceptList<'a> {
+    region: &'a [u8],
+}
+
+impl<'a> AcceptList<'a> {
+    pub fn new(region: &'a [u8]) -> AcceptList<'a> {
+        AcceptList { region }
+    }
+
+    /// Size of the underlying region in bytes.
+    pub fn region_len(&self) -> usize {
+        self.region.len()
+    }
+
+    /// Check that `image` is a well-formed accept list.
+    ///
+    /// Returns the number of entries, or `INVAL` if the image is malformed.
+    pub fn validate(image: &[u8]) -> Result<usize, ErrorCode> {
+        let count = Self::entry_count(image).ok_or(ErrorCode::INVAL)?;
+        let mut offset = HEADER_LEN;
+        for _ in 0..count {
+            let (_, next) = Self::parse_entry(image, offset).ok_or(ErrorCode::INVAL)?;
+            offset = next;
+        }
+        Ok(count)
+    }
+
+    /// Iterate over the entries of the list. Parsing stops at the first
+    /// malformed entry.
+    pub fn entries(&self) -> AcceptListIter<'a> {
+        AcceptListIter {
+            region: self.region,
+            offset: HEADER_LEN,
+            remaining: Self::entry_count(self.region).unwrap_or(0),
+        }
+    }
+
+    /// Return the `index`th public key in the list.
+    pub fn public_key(&self, index: usize) -> Option<&'a [u8]> {
+        self.entries()
+            .filter_map(|entry| match entry {
+                AcceptListEntry::PublicKey(key) => Some(key),
+                AcceptListEntry::ShortId(_) => None,
+            })
+            .nth(index)
+    }
+
+    /// Whether `id` is one of the ShortIds in the list.
+    pub fn contains_short_id(&self, id: u32) -> bool {
+        self.entries()
+            .any(|entry| entry == AcceptListEntry::ShortId(id))
+    }
+
+    fn entry_count(region: &[u8]) -> Option<usize> {
+        if region.len() < HEADER_LEN || region[0..4] != MAGIC {
+            return None;
+        }
+        Some(u32::from_le_bytes([region[4], region[5], region[6], region[7]]) as usize)
+    }
+
+    fn parse_entry(region: &[u8], offset: usize) -> Option<(AcceptListEntry<'_>, usize)> {
+        let header = region.get(offset..offset + ENTRY_HEADER_LEN)?;
+        let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let start = offset + ENTRY_HEADER_LEN;
+        let payload = region.get(start..start + length)?;
+        let next = start + ((length + 3) & !3);
+        if next > region.len() {
+            return None;
+        }
+
+        let entry = match header[0] {
+            KIND_SHORT_ID if length == 4 => AcceptListEntry::ShortId(u32::from_le_bytes([
+                payload[0], payload[1], payload[2], payload[3],
+            ])),
+            KIND_PUBLIC_KEY if length > 0 => AcceptListEntry::PublicKey(payload),
+            _ => return None,
+        };
+        Some((entry, next))
+    }
+}
+
+/// Iterator over the entries of an [`AcceptList`].
+pub struct AcceptListIter<'a> {
+    region: &'a [u8],
+    offset: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for AcceptListIter<'a> {
+    type Item = AcceptListEntry<'a>;
+
+    fn next(&mut self) -> Option<AcceptListEntry<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match AcceptList::parse_entry(self.region, self.offset) {
+            Some((entry, next)) => {
+                self.remaining -= 1;
+                self.offset = next;
+                Some(entry)
+            }
+            None => {
+                self.remaining = 0;
+                None
+            }
+        }
+    }
+}
+
+/// Credentials Checking Policy that accepts applications signed by any of the
+/// public keys in an accept list.
+///
+/// The actual signature check is delegated to `checker` (e.g. an
+/// `AppCheckerSignature`), whose verifier reads its key from `key`. For each
+/// credential this policy imports the keys from the list into `key` one at a
+/// time and re-runs `checker` until one of them accepts the credential or the
+/// list is exhausted.
+pub struct AppCheckerAcceptList<P: AppCredentialsPolicy<'static> + 'static, K: PubKey + 'static> {
+    list: AcceptList<'static>,
+    checker: &'static P,
+    key: &'static K,
+    client: OptionalCell<&'static dyn AppCredentialsPolicyClient<'static>>,
+    key_index: Cell<usize>,
+}
+
+impl<P: AppCredentialsPolicy<'static>, K: PubKey> AppCheckerAcceptList<P, K> {
+    pub fn new(
+        list: AcceptList<'static>,
+        checker: &'static P,
+        key: &'static K,
+    ) -> AppCheckerAcceptList<P, K> {
+        AppCheckerAcceptList {
+            list,
+            checker,
+            key,
+            client: OptionalCell::empty(),
+            key_index: Cell::new(0),
+        }
+    }
+
+    /// Start checking `credentials` with the first key at or after
+    /// `key_index` that the key store accepts.
+    fn check_with_next_key(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'static [u8])> {
+        loop {
+            let index = self.key_index.get();
+            let public_key = match self.list.public_key(index) {
+                Some(public_key) => public_key,
+                None => return Err((ErrorCode::NOSUPPORT, credentials, binary)),
+            };
+
+            // Release the previously imported key, if any.
+            let _ = self.key.pub_key();
+            match self.key.import_public_key(public_key) {
+                Ok(()) => return self.checker.check_credentials(credentials, binary),
+                // This key does not fit the verifier, try the next one.
+                Err(_) => self.key_index.set(index + 1),
+            }
+        }
+    }
+}
+
+impl<P: AppCredentialsPolicy<'static>, K: PubKey> AppCredentialsPolicy<'static>
+    for AppCheckerAcceptList<P, K>
+{
+    fn require_credentials(&self) -> bool {
+        true
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'static [u8])> {
+        self.key_index.set(0);
+        self.check_with_next_key(credentials, binary)
+    }
+
+    fn set_client(&self, client: &'static dyn AppCredentialsPolicyClient<'static>) {
+        self.client.replace(client);
+    }
+}
+
+impl<P: AppCredentialsPolicy<'static>, K: PubKey> AppCredentialsPolicyClient<'static>
+    for AppCheckerAcceptList<P, K>
+{
+    fn check_done(
+        &self,
+        result: Result<CheckResult, ErrorCode>,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) {
+        let (result, credentials, binary) = match result {
+            Ok(CheckResult::Reject) => {
+                // Not signed with this key, move on to the next one.
+                self.key_index.set(self.key_index.get() + 1);
+                match self.check_with_next_key(credentials, binary) {
+                    Ok(()) => return,
+                    Err((ErrorCode::NOSUPPORT, credentials, binary)) => {
+                        (Ok(CheckResult::Reject), credentials, binary)
+                    }
+                    Err((e, credentials, binary)) => (Err(e), credentials, binary),
+                }
+            }
+            result => (result, credentials, binary),
+        };
+
+        let _ = self.key.pub_key();
+        self.client.map(|c| {
+            c.check_done(result, credentials, binary);
+        });
+    }
+}
+
+/// ShortId assigner that only hands out ShortIds present in an accept list.
+///
+/// ShortIds are computed by the wrapped `assigner`. A fixed ShortId that is
+/// not in the list is replaced by `ShortId::LocallyUnique`, so the process
+/// still runs but does not get the identity (and any privileges tied to it).
+pub struct AppIdAssignerAcceptList<C: Compress + 'static> {
+    list: AcceptList<'static>,
+    assigner: &'static C,
+}
+
+impl<C: Compress> AppIdAssignerAcceptList<C> {
+    pub fn new(list: AcceptList<'static>, assigner: &'static C) -> AppIdAssignerAcceptList<C> {
+        AppIdAssignerAcceptList { list, assigner }
+    }
+}
+
+impl<C: Compress> Compress for AppIdAssignerAcceptList<C> {
+    fn to_short_id(&self, process: &ProcessBinary) -> ShortId {
+        match self.assigner.to_short_id(process) {
+            ShortId::Fixed(id) if self.list.contains_short_id(id.get()) => ShortId::Fixed(id),
+            _ => ShortId::LocallyUnique,
+        }
+    }
+}
@@ -2,5 +2,6 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2024.
 
+pub mod accept_list;
 pub mod basic;
 pub mod signature;
@@ -0,0 +1,286 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! ADC emulated with a sample generator.
+//!
+//! Each channel produces a deterministic 12-bit sawtooth, left-justified in
+//! 16 bits as required by the ADC HIL. Samples are delivered when the chip
+//! services its pending interrupts after the sampling period has passed, so
+//! the requested frequency is honored without busy-looping the kernel.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Number of channels of the emulated ADC.
+pub const NUM_CHANNELS: usize = 4;
+
+const RESOLUTION_BITS: usize = 12;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Channel(usize);
+
+impl Channel {
+    pub const fn new(index: usize) -> Channel {
+        Channel(index)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Idle,
+    Single,
+    Continuous,
+    HighSpeed,
+}
+
+pub struct HostAdc<'a> {
+    client: OptionalCell<&'a dyn hil::adc::Client>,
+    highspeed_client: OptionalCell<&'a dyn hil::adc::HighSpeedClient>,
+
+    mode: Cell<Mode>,
+    channel: Cell<usize>,
+    frequency: Cell<u32>,
+    deadline: Cell<Option<Instant>>,
+    counter: Cell<u16>,
+
+    buffer: TakeCell<'static, [u16]>,
+    length: Cell<usize>,
+    next_buffer: TakeCell<'static, [u16]>,
+    next_length: Cell<usize>,
+}
+
+impl<'a> HostAdc<'a> {
+    pub fn new() -> HostAdc<'a> {
+        HostAdc {
+            client: OptionalCell::empty(),
+            highspeed_client: OptionalCell::empty(),
+            mode: Cell::new(Mode::Idle),
+            channel: Cell::new(0),
+            frequency: Cell::new(0),
+            deadline: Cell::new(None),
+            counter: Cell::new(0),
+            buffer: TakeCell::empty(),
+            length: Cell::new(0),
+            next_buffer: TakeCell::empty(),
+            next_length: Cell::new(0),
+        }
+    }
+
+    /// Time until the next samples are ready, or `None` if not sampling.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .get()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether samples are ready to be delivered.
+    pub fn is_pending(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    pub fn handle_interrupt(&self) {
+        if self.is_pending() {
+            self.deliver();
+        }
+    }
+
+    /// Deliver the next samples now, even if the sampling period has not
+    /// passed. Returns `false` if not sampling.
+    pub fn force_interrupt(&self) -> bool {
+        if self.deadline.get().is_none() {
+            return false;
+        }
+        self.deliver();
+        true
+    }
+
+    fn deliver(&self) {
+        match self.mode.get() {
+            Mode::Idle => {
+                self.deadline.set(None);
+            }
+            Mode::Single => {
+                self.stop();
+                let sample = self.next_sample();
+                self.client.map(|client| client.sample_ready(sample.into()));
+            }
+            Mode::Continuous => {
+                self.schedule(1);
+                let sample = self.next_sample();
+                self.client.map(|client| client.sample_ready(sample.into()));
+            }
+            Mode::HighSpeed => {
+                let length = self.length.get();
+                self.buffer.take().map(|buf| {
+                    for sample in buf[..length].iter_mut() {
+                        *sample = self.next_sample();
+                    }
+
+                    // Continue with the next buffer, if the client provided
+                    // one with samples to take. An empty one is kept until
+                    // the buffers are retrieved.
+                    if self.next_length.get() > 0 {
+                        self.next_buffer.take().map(|next| {
+                            self.buffer.replace(next);
+                            self.length.set(self.next_length.replace(0));
+                            self.schedule(self.length.get());
+                        });
+                    }
+                    if self.buffer.is_none() {
+                        self.stop();
+                    }
+
+                    self.highspeed_client
+                        .map(move |client| client.samples_ready(buf, length));
+                });
+            }
+        }
+    }
+
+    fn next_sample(&self) -> u16 {
+        let count = self.counter.get();
+        self.counter.set(count.wrapping_add(1));
+        let max = 1 << RESOLUTION_BITS;
+        let offset = self.channel.get() as u16 * (max / NUM_CHANNELS as u16);
+        let value = offset.wrapping_add(count.wrapping_mul(37)) % max;
+        value << (16 - RESOLUTION_BITS)
+    }
+
+    /// Set the deadline for `samples` samples at the current frequency.
+    fn schedule(&self, samples: usize) {
+        let frequency = self.frequency.get().max(1) as u64;
+        let period = Duration::from_nanos(samples as u64 * 1_000_000_000 / frequency);
+        self.deadline.set(Some(Instant::now() + period));
+    }
+
+    fn start(&self, channel: &Channel, mode: Mode, frequency: u32) -> Result<(), ErrorCode> {
+        if self.mode.get() != Mode::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if channel.0 >= NUM_CHANNELS {
+            return Err(ErrorCode::INVAL);
+        }
+        if mode != Mode::Single && frequency == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.mode.set(mode);
+        self.channel.set(channel.0);
+        self.frequency.set(frequency);
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.mode.set(Mode::Idle);
+        self.deadline.set(None);
+    }
+}
+
+impl Default for HostAdc<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> hil::adc::Adc<'a> for HostAdc<'a> {
+    type Channel = Channel;
+
+    fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        self.start(channel, Mode::Single, 0)?;
+        self.deadline.set(Some(Instant::now()));
+        Ok(())
+    }
+
+    fn sample_continuous(&self, channel: &Self::Channel, frequency: u32) -> Result<(), ErrorCode> {
+        self.start(channel, Mode::Continuous, frequency)?;
+        self.schedule(1);
+        Ok(())
+    }
+
+    fn stop_sampling(&self) -> Result<(), ErrorCode> {
+        self.stop();
+        Ok(())
+    }
+
+    fn get_resolution_bits(&self) -> usize {
+        RESOLUTION_BITS
+    }
+
+    fn get_voltage_reference_mv(&self) -> Option<usize> {
+        Some(3300)
+    }
+
+    fn set_client(&self, client: &'a dyn hil::adc::Client) {
+        self.client.set(client);
+    }
+}
+
+impl<'a> hil::adc::AdcHighSpeed<'a> for HostAdc<'a> {
+    fn sample_highspeed(
+        &self,
+        channel: &Self::Channel,
+        frequency: u32,
+        buffer1: &'static mut [u16],
+        length1: usize,
+        buffer2: &'static mut [u16],
+        length2: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16], &'static mut [u16])> {
+        if self.buffer.is_some() || self.next_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer1, buffer2));
+        }
+        if length1 == 0 || length1 > buffer1.len() || length2 > buffer2.len() {
+            return Err((ErrorCode::INVAL, buffer1, buffer2));
+        }
+        if let Err(e) = self.start(channel, Mode::HighSpeed, frequency) {
+            return Err((e, buffer1, buffer2));
+        }
+
+        self.buffer.replace(buffer1);
+        self.length.set(length1);
+        self.next_buffer.replace(buffer2);
+        self.next_length.set(length2);
+        self.schedule(length1);
+        Ok(())
+    }
+
+    fn provide_buffer(
+        &self,
+        buf: &'static mut [u16],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])> {
+        if self.mode.get() != Mode::HighSpeed {
+            return Err((ErrorCode::OFF, buf));
+        }
+        if self.next_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if length == 0 || length > buf.len() {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        self.next_buffer.replace(buf);
+        self.next_length.set(length);
+        Ok(())
+    }
+
+    fn retrieve_buffers(
+        &self,
+    ) -> Result<(Option<&'static mut [u16]>, Option<&'static mut [u16]>), ErrorCode> {
+        if self.mode.get() != Mode::Idle {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok((self.buffer.take(), self.next_buffer.take()))
+    }
+
+    /// Samples are generated at exactly the requested frequency.
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        Some(self.frequency.get()).filter(|&frequency| frequency != 0)
+    }
+
+    fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
+        self.highspeed_client.set(client);
+    }
+}
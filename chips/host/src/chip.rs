@@ -0,0 +1,232 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Chip trait setup.
+
+use core::fmt::Write;
+use std::fs::File;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use kernel::debug::{self, Event};
+use kernel::deferred_call::DeferredCall;
+use kernel::platform::chip::Chip;
+
+use crate::adc::HostAdc;
+use crate::flash::FileFlash;
+use crate::syscall::SysCall;
+use crate::time::HostAlarm;
+use crate::uart::StdioUart;
+
+/// Longest time the chip sleeps before polling standard input again.
+const MAX_SLEEP: Duration = Duration::from_millis(10);
+
+/// Numbers of the emulated interrupts, as recorded in `Event::Interrupt`.
+pub mod interrupts {
+    pub const ALARM: usize = 0;
+    pub const ADC: usize = 1;
+    pub const UART: usize = 2;
+
+    /// Number of interrupts.
+    pub const COUNT: usize = 3;
+}
+
+pub struct HostDefaultPeripherals<'a> {
+    pub uart: StdioUart<'a>,
+    pub alarm: HostAlarm<'a>,
+    pub adc: HostAdc<'a>,
+    pub flash: FileFlash<'a>,
+}
+
+impl<'a> HostDefaultPeripherals<'a> {
+    /// Create the peripherals, with `flash_file` holding `flash_pages` pages
+    /// of flash.
+    pub fn new(flash_file: File, flash_pages: usize) -> io::Result<Self> {
+        Ok(Self {
+            uart: StdioUart::new(),
+            alarm: HostAlarm::new(),
+            adc: HostAdc::new(),
+            flash: FileFlash::new(flash_file, flash_pages)?,
+        })
+    }
+
+    // Necessary for setting up circular dependencies and registering deferred
+    // calls
+    pub fn init(&'static self) {
+        kernel::deferred_call::DeferredCallClient::register(&self.uart);
+        kernel::deferred_call::DeferredCallClient::register(&self.flash);
+    }
+}
+
+pub struct Host<'a> {
+    userspace_kernel_boundary: SysCall,
+    peripherals: &'a HostDefaultPeripherals<'a>,
+}
+
+impl<'a> Host<'a> {
+    pub fn new(peripherals: &'a HostDefaultPeripherals<'a>) -> Self {
+        Self {
+            userspace_kernel_boundary: SysCall::new(),
+            peripherals,
+        }
+    }
+
+    /// The boundary replaying the system calls of processes.
+    pub fn syscall(&self) -> &SysCall {
+        &self.userspace_kernel_boundary
+    }
+
+    /// Deliver `events` to the capsules in order, whether or not the time
+    /// they were waiting for has passed, e.g. events recorded with the
+    /// kernel's `debug_event_record` feature.
+    ///
+    /// This reproduces bugs that depend on the order in which interrupts and
+    /// deferred calls are delivered, independently of the timing of the
+    /// host. Returns the index of the first event that cannot be delivered,
+    /// because its peripheral is not active or its deferred call is not
+    /// pending, which means that the capsules diverged from the recording.
+    pub fn replay(&self, events: &[Event]) -> Result<(), usize> {
+        for (index, &event) in events.iter().enumerate() {
+            let delivered = match event {
+                Event::Interrupt(interrupt) => {
+                    debug::record_event(event);
+                    self.deliver_interrupt(interrupt)
+                }
+                Event::DeferredCall(idx) => DeferredCall::service_pending(idx),
+            };
+            if !delivered {
+                return Err(index);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_interrupt_pending(&self, interrupt: usize) -> bool {
+        match interrupt {
+            interrupts::ALARM => self.peripherals.alarm.is_pending(),
+            interrupts::ADC => self.peripherals.adc.is_pending(),
+            interrupts::UART => self.peripherals.uart.is_pending(),
+            _ => false,
+        }
+    }
+
+    fn deliver_interrupt(&self, interrupt: usize) -> bool {
+        match interrupt {
+            interrupts::ALARM => self.peripherals.alarm.force_interrupt(),
+            interrupts::ADC => self.peripherals.adc.force_interrupt(),
+            interrupts::UART => self.peripherals.uart.force_interrupt(),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Chip for Host<'a> {
+    type MPU = ();
+    type UserspaceKernelBoundary = SysCall;
+
+    fn service_pending_interrupts(&self) {
+        while self.has_pending_interrupts() {
+            for interrupt in 0..interrupts::COUNT {
+                if self.is_interrupt_pending(interrupt) {
+                    debug::record_event(Event::Interrupt(interrupt));
+                    self.deliver_interrupt(interrupt);
+                }
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        (0..interrupts::COUNT).any(|interrupt| self.is_interrupt_pending(interrupt))
+    }
+
+    fn mpu(&self) -> &Self::MPU {
+        &()
+    }
+
+    fn userspace_kernel_boundary(&self) -> &Self::UserspaceKernelBoundary {
+        &self.userspace_kernel_boundary
+    }
+
+    fn sleep(&self) {
+        let duration = [
+            self.peripherals.alarm.remaining(),
+            self.peripherals.adc.remaining(),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(MAX_SLEEP, Duration::min);
+        thread::sleep(duration);
+    }
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Peripheral events are only delivered from the kernel loop, so
+        // nothing can interrupt `f`.
+        f()
+    }
+
+    unsafe fn print_state(&self, writer: &mut dyn Write) {
+        let _ = writer.write_str("\r\n---| Host chip |---\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs::OpenOptions;
+
+    use kernel::debug::Event;
+    use kernel::hil::adc::{Adc, Client};
+
+    use super::{interrupts, Host, HostDefaultPeripherals};
+    use crate::adc::Channel;
+
+    struct Samples(RefCell<Vec<u32>>);
+
+    impl Client for Samples {
+        fn sample_ready(&self, sample: u32) {
+            self.0.borrow_mut().push(sample);
+        }
+    }
+
+    #[test]
+    fn replay() {
+        let path = std::env::temp_dir().join(format!("host-replay-{}.bin", std::process::id()));
+        let flash_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let peripherals: &'static _ = Box::leak(Box::new(
+            HostDefaultPeripherals::new(flash_file, 1).unwrap(),
+        ));
+        let samples: &'static _ = Box::leak(Box::new(Samples(RefCell::new(Vec::new()))));
+        let chip = Host::new(peripherals);
+
+        // At 1 Hz, no sample is due before the replay ends.
+        peripherals.adc.set_client(samples);
+        peripherals
+            .adc
+            .sample_continuous(&Channel::new(0), 1)
+            .unwrap();
+        let adc = Event::Interrupt(interrupts::ADC);
+        assert_eq!(chip.replay(&[adc, adc]), Ok(()));
+        assert_eq!(*samples.0.borrow(), [0, 37 << 4]);
+
+        // Events of inactive peripherals or deferred calls that are not
+        // pending diverge from the recording.
+        peripherals.adc.stop_sampling().unwrap();
+        assert_eq!(chip.replay(&[adc]), Err(0));
+        assert_eq!(chip.replay(&[Event::Interrupt(interrupts::ALARM)]), Err(0));
+        assert_eq!(chip.replay(&[Event::DeferredCall(0)]), Err(0));
+        assert_eq!(samples.0.borrow().len(), 2);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
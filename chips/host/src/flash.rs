@@ -0,0 +1,215 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Flash emulated with a file on the host.
+//!
+//! Pages are stored back to back in the file, which keeps its contents
+//! between runs. Operations complete immediately and the client is called
+//! back from a deferred call.
+
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::{Index, IndexMut};
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub const PAGE_SIZE: usize = 4096;
+
+pub struct HostPage(pub [u8; PAGE_SIZE]);
+
+impl Default for HostPage {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl HostPage {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Index<usize> for HostPage {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        &self.0[idx]
+    }
+}
+
+impl IndexMut<usize> for HostPage {
+    fn index_mut(&mut self, idx: usize) -> &mut u8 {
+        &mut self.0[idx]
+    }
+}
+
+impl AsMut<[u8]> for HostPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlashState {
+    Ready,
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct FileFlash<'a> {
+    file: RefCell<File>,
+    num_pages: usize,
+    client: OptionalCell<&'a dyn hil::flash::Client<FileFlash<'a>>>,
+    buffer: TakeCell<'static, HostPage>,
+    state: Cell<FlashState>,
+    result: Cell<Result<(), hil::flash::Error>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> FileFlash<'a> {
+    /// Use `file` as a flash of `num_pages` pages. The file is extended
+    /// with erased pages if it is too short.
+    pub fn new(file: File, num_pages: usize) -> io::Result<FileFlash<'a>> {
+        let mut file = file;
+        let size = file.seek(SeekFrom::End(0))?;
+        let erased = [0xFF; PAGE_SIZE];
+        let mut missing = (num_pages * PAGE_SIZE).saturating_sub(size as usize);
+        while missing > 0 {
+            let len = missing.min(PAGE_SIZE);
+            file.write_all(&erased[..len])?;
+            missing -= len;
+        }
+
+        Ok(FileFlash {
+            file: RefCell::new(file),
+            num_pages,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            state: Cell::new(FlashState::Ready),
+            result: Cell::new(Ok(())),
+            deferred_call: DeferredCall::new(),
+        })
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    fn start(&self, page_number: usize, state: FlashState) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        if page_number >= self.num_pages {
+            return Err(ErrorCode::INVAL);
+        }
+        self.state.set(state);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn seek(&self, page_number: usize) -> io::Result<()> {
+        self.file
+            .borrow_mut()
+            .seek(SeekFrom::Start((page_number * PAGE_SIZE) as u64))
+            .map(|_| ())
+    }
+
+    fn read(&self, page_number: usize, buf: &mut HostPage) -> io::Result<()> {
+        self.seek(page_number)?;
+        self.file.borrow_mut().read_exact(&mut buf.0)
+    }
+
+    fn write(&self, page_number: usize, data: &[u8]) -> io::Result<()> {
+        self.seek(page_number)?;
+        let mut file = self.file.borrow_mut();
+        file.write_all(data)?;
+        file.flush()
+    }
+
+    fn complete(&self, result: io::Result<()>) {
+        self.result
+            .set(result.map_err(|_| hil::flash::Error::FlashError));
+    }
+}
+
+impl<'a, C: hil::flash::Client<Self>> hil::flash::HasClient<'a, C> for FileFlash<'a> {
+    fn set_client(&self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for FileFlash<'_> {
+    type Page = HostPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(page_number, FlashState::Read) {
+            Ok(()) => {
+                self.complete(self.read(page_number, buf));
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(page_number, FlashState::Write) {
+            Ok(()) => {
+                let len = buf.len();
+                self.complete(self.write(page_number, &buf.0[..len]));
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(page_number, FlashState::Erase)?;
+        self.complete(self.write(page_number, &[0xFF; PAGE_SIZE]));
+        Ok(())
+    }
+}
+
+impl<'a> DeferredCallClient for FileFlash<'a> {
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+
+    fn handle_deferred_call(&self) {
+        let state = self.state.replace(FlashState::Ready);
+        let result = self.result.get();
+        match state {
+            FlashState::Read => {
+                self.buffer.take().map(|buf| {
+                    self.client
+                        .map(move |client| client.read_complete(buf, result));
+                });
+            }
+            FlashState::Write => {
+                self.buffer.take().map(|buf| {
+                    self.client
+                        .map(move |client| client.write_complete(buf, result));
+                });
+            }
+            FlashState::Erase => {
+                self.client.map(|client| client.erase_complete(result));
+            }
+            FlashState::Ready => {}
+        }
+    }
+}
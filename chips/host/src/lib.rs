@@ -0,0 +1,30 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Chip support for running the Tock kernel as a process on the host.
+//!
+//! Peripherals are emulated with the host operating system: the UART reads
+//! from standard input and writes to standard output, the alarm counts time
+//! with `std::time`, the ADC generates samples, and the flash is backed by a
+//! file. This lets capsules be exercised in integration tests and fuzzers
+//! without hardware or QEMU.
+//!
+//! Process code cannot run on the host. Processes instead replay system calls
+//! scripted through [`syscall::SysCall`].
+//!
+//! The chip records the interrupts it delivers if the kernel's
+//! `debug_event_record` feature is enabled, and [`chip::Host::replay`]
+//! delivers a recorded order of interrupts and deferred calls again, without
+//! waiting for the emulated peripherals, to reproduce bugs that depend on
+//! the order of events.
+
+#![crate_name = "host"]
+#![crate_type = "rlib"]
+
+pub mod adc;
+pub mod chip;
+pub mod flash;
+pub mod syscall;
+pub mod time;
+pub mod uart;
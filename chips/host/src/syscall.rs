@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Userspace/kernel boundary of the host chip.
+//!
+//! Process code cannot run on the host. Instead, each process replays a
+//! script of system calls queued with [`SysCall::push_syscall`]: every switch
+//! to a process issues the next queued system call, and the value the kernel
+//! returns is recorded. Once the script is exhausted the process yields and
+//! waits, so the kernel goes back to sleep. This lets tests and fuzzers drive
+//! capsules through their system call interface.
+//!
+//! Upcalls are accepted but not executed; the process simply continues with
+//! its script.
+
+use core::fmt::Write;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use kernel::process;
+use kernel::syscall::{ContextSwitchReason, Syscall, SyscallReturn, UserspaceKernelBoundary};
+use kernel::ErrorCode;
+
+/// Value of `which` for a `yield-wait` system call.
+const YIELD_WAIT: usize = 1;
+
+#[derive(Default)]
+pub struct HostStoredState {
+    /// Number of system calls issued by the process.
+    syscall_count: usize,
+}
+
+#[derive(Default)]
+pub struct SysCall {
+    syscalls: RefCell<VecDeque<Syscall>>,
+    last_return: Cell<Option<SyscallReturn>>,
+    memory: Cell<Option<(*const u8, *const u8)>>,
+}
+
+impl SysCall {
+    pub fn new() -> SysCall {
+        SysCall {
+            syscalls: RefCell::new(VecDeque::new()),
+            last_return: Cell::new(None),
+            memory: Cell::new(None),
+        }
+    }
+
+    /// Queue a system call to be issued by the next process that runs.
+    pub fn push_syscall(&self, syscall: Syscall) {
+        self.syscalls.borrow_mut().push_back(syscall);
+    }
+
+    /// Number of queued system calls that have not been issued yet.
+    pub fn pending_syscalls(&self) -> usize {
+        self.syscalls.borrow().len()
+    }
+
+    /// Drop all queued system calls.
+    pub fn clear_syscalls(&self) {
+        self.syscalls.borrow_mut().clear();
+    }
+
+    /// Value returned by the kernel for the last system call, if any.
+    pub fn last_return(&self) -> Option<SyscallReturn> {
+        self.last_return.get()
+    }
+
+    /// Start and break of the accessible memory of the last process that was
+    /// set up or run, for building allow system calls.
+    pub fn process_memory(&self) -> Option<(*const u8, *const u8)> {
+        self.memory.get()
+    }
+}
+
+impl UserspaceKernelBoundary for SysCall {
+    type StoredState = HostStoredState;
+
+    fn initial_process_app_brk_size(&self) -> usize {
+        0
+    }
+
+    unsafe fn initialize_process(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &mut Self::StoredState,
+    ) -> Result<(), ()> {
+        state.syscall_count = 0;
+        self.memory.set(Some((accessible_memory_start, app_brk)));
+        Ok(())
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut Self::StoredState,
+        return_value: SyscallReturn,
+    ) -> Result<(), ()> {
+        self.last_return.set(Some(return_value));
+        Ok(())
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut Self::StoredState,
+        _upcall: process::FunctionCall,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &mut Self::StoredState,
+    ) -> (ContextSwitchReason, Option<*const u8>) {
+        self.memory.set(Some((accessible_memory_start, app_brk)));
+        let syscall = self
+            .syscalls
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(Syscall::Yield {
+                which: YIELD_WAIT,
+                address: core::ptr::null_mut(),
+                timeout: 0,
+            });
+        state.syscall_count += 1;
+        (ContextSwitchReason::SyscallFired { syscall }, None)
+    }
+
+    unsafe fn print_context(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &Self::StoredState,
+        writer: &mut dyn Write,
+    ) {
+        let _ = writer.write_fmt(format_args!(
+            " Scripted process, {} system calls issued, {} queued.\r\n",
+            state.syscall_count,
+            self.pending_syscalls()
+        ));
+    }
+
+    fn store_context(
+        &self,
+        _state: &Self::StoredState,
+        _out: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        Ok(0)
+    }
+}
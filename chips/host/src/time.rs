@@ -0,0 +1,120 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Alarm emulated with the monotonic clock of the host.
+//!
+//! The counter is the number of microseconds since the alarm was created. An
+//! armed alarm "interrupts" when the chip services its pending interrupts
+//! after the expiration time has passed.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use kernel::hil::time::{self, Alarm, Freq1MHz, Ticks, Ticks64, Time};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+pub struct HostAlarm<'a> {
+    start: Instant,
+    client: OptionalCell<&'a dyn time::AlarmClient>,
+    reference: Cell<Ticks64>,
+    dt: Cell<Ticks64>,
+    armed: Cell<bool>,
+}
+
+impl<'a> HostAlarm<'a> {
+    pub fn new() -> HostAlarm<'a> {
+        HostAlarm {
+            start: Instant::now(),
+            client: OptionalCell::empty(),
+            reference: Cell::new(0u64.into()),
+            dt: Cell::new(0u64.into()),
+            armed: Cell::new(false),
+        }
+    }
+
+    /// Time until the alarm expires, or `None` if it is not armed.
+    pub fn remaining(&self) -> Option<Duration> {
+        if !self.armed.get() {
+            return None;
+        }
+        let elapsed = self.now().wrapping_sub(self.reference.get());
+        let remaining = if elapsed >= self.dt.get() {
+            0
+        } else {
+            self.dt.get().wrapping_sub(elapsed).into_u64()
+        };
+        Some(Duration::from_micros(remaining))
+    }
+
+    /// Whether the alarm is armed and has expired.
+    pub fn is_pending(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    pub fn handle_interrupt(&self) {
+        if self.is_pending() {
+            self.fire();
+        }
+    }
+
+    /// Fire the alarm now, even if it has not expired. Returns `false` if it
+    /// is not armed.
+    pub fn force_interrupt(&self) -> bool {
+        if !self.armed.get() {
+            return false;
+        }
+        self.fire();
+        true
+    }
+
+    fn fire(&self) {
+        self.armed.set(false);
+        self.client.map(|client| client.alarm());
+    }
+}
+
+impl Default for HostAlarm<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Time for HostAlarm<'_> {
+    type Frequency = Freq1MHz;
+    type Ticks = Ticks64;
+
+    fn now(&self) -> Ticks64 {
+        (self.start.elapsed().as_micros() as u64).into()
+    }
+}
+
+impl<'a> Alarm<'a> for HostAlarm<'a> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+        self.reference.set(reference);
+        self.dt.set(dt);
+        self.armed.set(true);
+    }
+
+    fn get_alarm(&self) -> Self::Ticks {
+        self.reference.get().wrapping_add(self.dt.get())
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.armed.set(false);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        1u64.into()
+    }
+}
@@ -0,0 +1,262 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! UART emulated with the standard input and output of the host process.
+//!
+//! Transmitted bytes are written to standard output. A background thread
+//! reads standard input and queues the bytes, which are delivered to the
+//! receive client when the chip services its pending interrupts.
+
+use std::cell::{Cell, RefCell};
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct StdioUart<'a> {
+    tx_client: OptionalCell<&'a dyn hil::uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn hil::uart::ReceiveClient>,
+
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_quiet: Cell<bool>,
+
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_index: Cell<usize>,
+    rx_aborted: Cell<bool>,
+    rx_bytes: RefCell<Option<Receiver<u8>>>,
+    rx_pending: RefCell<Option<u8>>,
+
+    deferred_call: DeferredCall,
+}
+
+impl<'a> StdioUart<'a> {
+    pub fn new() -> StdioUart<'a> {
+        StdioUart {
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_quiet: Cell::new(false),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_index: Cell::new(0),
+            rx_aborted: Cell::new(false),
+            rx_bytes: RefCell::new(None),
+            rx_pending: RefCell::new(None),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Start reading standard input. Bytes read before a receive buffer is
+    /// provided are kept until one is.
+    pub fn start_input(&self) {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut byte = [0];
+            while let Ok(1) = stdin.read(&mut byte) {
+                if sender.send(byte[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        self.rx_bytes.replace(Some(receiver));
+    }
+
+    /// Discard transmitted bytes instead of writing them to standard output.
+    pub fn set_quiet(&self, quiet: bool) {
+        self.tx_quiet.set(quiet);
+    }
+
+    /// Return the next byte read from standard input, if any.
+    fn next_byte(&self) -> Option<u8> {
+        self.rx_pending.take().or_else(|| {
+            self.rx_bytes
+                .borrow()
+                .as_ref()
+                .and_then(|receiver| match receiver.try_recv() {
+                    Ok(byte) => Some(byte),
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+                })
+        })
+    }
+
+    /// Whether bytes are waiting to be delivered to a pending receive.
+    pub fn is_pending(&self) -> bool {
+        if self.rx_buffer.is_none() {
+            return false;
+        }
+        if self.rx_pending.borrow().is_some() {
+            return true;
+        }
+        let byte = self.next_byte();
+        let pending = byte.is_some();
+        self.rx_pending.replace(byte);
+        pending
+    }
+
+    /// Deliver the bytes read so far to the pending receive. Returns `false`
+    /// if no receive is pending.
+    pub fn force_interrupt(&self) -> bool {
+        if self.rx_buffer.is_none() {
+            return false;
+        }
+        self.handle_interrupt();
+        true
+    }
+
+    pub fn handle_interrupt(&self) {
+        while self.rx_buffer.is_some() && self.rx_index.get() < self.rx_len.get() {
+            match self.next_byte() {
+                Some(byte) => {
+                    let index = self.rx_index.get();
+                    self.rx_buffer.map(|buf| buf[index] = byte);
+                    self.rx_index.set(index + 1);
+                }
+                None => break,
+            }
+        }
+
+        if self.rx_buffer.is_some() && self.rx_index.get() == self.rx_len.get() {
+            self.rx_buffer.take().map(|buf| {
+                self.rx_client.map(move |client| {
+                    client.received_buffer(buf, self.rx_len.get(), Ok(()), hil::uart::Error::None);
+                });
+            });
+        }
+    }
+}
+
+impl Default for StdioUart<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> hil::uart::Configure for StdioUart<'a> {
+    fn configure(&self, params: hil::uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> hil::uart::Transmit<'a> for StdioUart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn hil::uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+
+        let mut stdout = std::io::stdout();
+        if !self.tx_quiet.get()
+            && stdout
+                .write_all(&tx_buffer[..tx_len])
+                .and_then(|()| stdout.flush())
+                .is_err()
+        {
+            return Err((ErrorCode::FAIL, tx_buffer));
+        }
+
+        self.tx_buffer.replace(tx_buffer);
+        self.tx_len.set(tx_len);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // Bytes are written synchronously, only the callback is pending.
+        if self.tx_buffer.is_some() {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> hil::uart::Receive<'a> for StdioUart<'a> {
+    fn set_receive_client(&self, client: &'a dyn hil::uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+
+        self.rx_buffer.replace(rx_buffer);
+        self.rx_len.set(rx_len);
+        self.rx_index.set(0);
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_some() {
+            self.rx_aborted.set(true);
+            self.deferred_call.set();
+            Err(ErrorCode::BUSY)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> DeferredCallClient for StdioUart<'a> {
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+
+    fn handle_deferred_call(&self) {
+        self.tx_buffer.take().map(|buf| {
+            self.tx_client.map(move |client| {
+                client.transmitted_buffer(buf, self.tx_len.get(), Ok(()));
+            });
+        });
+
+        if self.rx_aborted.take() {
+            self.rx_buffer.take().map(|buf| {
+                self.rx_client.map(move |client| {
+                    client.received_buffer(
+                        buf,
+                        self.rx_index.get(),
+                        Err(ErrorCode::CANCEL),
+                        hil::uart::Error::Aborted,
+                    );
+                });
+            });
+        }
+    }
+}
@@ -13,6 +13,7 @@ use crate::litex_registers::{LiteXSoCRegisterConfiguration, Read, Write};
 use core::cell::Cell;
 use core::slice;
 use kernel::debug;
+use kernel::hil::network_statistics::{Counter, Counters, NetworkStatistics};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
@@ -100,6 +101,7 @@ pub struct LiteEth<'a, R: LiteXSoCRegisterConfiguration> {
     tx_packet: TakeCell<'static, [u8]>,
     rx_buffer: TakeCell<'static, [u8]>,
     initialized: Cell<bool>,
+    counters: Cell<Counters>,
 }
 
 impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
@@ -123,6 +125,7 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
             tx_packet: TakeCell::empty(),
             rx_buffer: TakeCell::new(rx_buffer),
             initialized: Cell::new(false),
+            counters: Cell::new(Counters::default()),
         }
     }
 
@@ -130,6 +133,13 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
         self.client.set(client);
     }
 
+    /// Update the network statistics.
+    fn count(&self, counter: Counter) {
+        let mut counters = self.counters.get();
+        counters.increment(counter);
+        self.counters.set(counters);
+    }
+
     pub fn initialize(&self) {
         // Sanity check the memory parameters
         //
@@ -207,6 +217,7 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
             let pkt_len = self.mac_regs.rx_length.get() as usize;
             if pkt_len > rx_buffer.len() {
                 debug!("LiteEth: discarding ethernet packet with len {}", pkt_len);
+                self.count(Counter::RxDropped);
 
                 // Acknowledge the interrupt so that the HW may use the slot again
                 self.mac_regs.rx_ev().clear_event(LITEETH_RX_EVENT);
@@ -228,6 +239,7 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
                 // Since all data is copied, acknowledge the interrupt
                 // so that the slot is ready for use again
                 self.mac_regs.rx_ev().clear_event(LITEETH_RX_EVENT);
+                self.count(Counter::RxFrames);
 
                 self.client
                     .map(move |client| client.rx_packet(rx_buffer, pkt_len));
@@ -250,6 +262,7 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
         }
 
         if self.tx_packet.is_some() {
+            self.count(Counter::TxDropped);
             return Err((Err(ErrorCode::BUSY), packet));
         }
 
@@ -291,6 +304,7 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
 
         // We use only one slot, so this event is unambiguous
         let packet = self.tx_packet.take().unwrap(); // Unwrap fail = LiteEth: TakeCell empty in tx callback
+        self.count(Counter::TxFrames);
         self.client
             .map(move |client| client.tx_done(Ok(()), packet));
     }
@@ -309,3 +323,17 @@ impl<'a, R: LiteXSoCRegisterConfiguration> LiteEth<'a, R> {
         }
     }
 }
+
+impl<'a, R: LiteXSoCRegisterConfiguration> NetworkStatistics for LiteEth<'a, R> {
+    fn interface_name(&self) -> &'static str {
+        "eth0"
+    }
+
+    fn counters(&self) -> Counters {
+        self.counters.get()
+    }
+
+    fn reset_counters(&self) {
+        self.counters.set(Counters::default());
+    }
+}
@@ -495,6 +495,8 @@ pub struct Adc<'a> {
     resolution: AdcResolution,
     mode: Cell<AdcMode>,
     active_channel: Cell<Channel>,
+    /// The active channel samples a differential pair.
+    differential: Cell<bool>,
     ref_module: OptionalCell<&'a dyn ref_module::AnalogReference>,
     timer: OptionalCell<&'a dyn timer::InternalTimer>,
     dma: OptionalCell<&'a dma::DmaChannel<'a>>,
@@ -513,6 +515,7 @@ impl Adc<'_> {
             resolution: DEFAULT_ADC_RESOLUTION,
             mode: Cell::new(AdcMode::Disabled),
             active_channel: Cell::new(Channel::Channel0),
+            differential: Cell::new(false),
             ref_module: OptionalCell::empty(),
             timer: OptionalCell::empty(), // must be TIMER_A3!
             dma: OptionalCell::empty(),
@@ -680,12 +683,69 @@ impl<'a> Adc<'a> {
         self.registers.ctl0.modify(CTL0::ON::SET);
     }
 
+    /// Sample `channel` once, or the differential pair it starts if
+    /// `differential`.
+    fn sample_once(&self, channel: Channel, differential: bool) -> Result<(), ErrorCode> {
+        if !self.is_enabled() {
+            self.setup();
+        }
+
+        if self.mode.get() != AdcMode::Disabled {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.mode.set(AdcMode::Repeated);
+        self.active_channel.set(channel);
+        self.set_differential(channel, differential);
+
+        // Set the channel-number where to start sampling
+        self.registers
+            .ctl1
+            .modify(CTL1::STARTADDx.val(channel as u32));
+
+        self.enable_interrupt(channel);
+
+        // Set ADC to mode where a single channel gets sampled once
+        // Set the sample-and-hold source select to software-based
+        // Set the sampling-timer for generating the sample-period
+        // Enable conversation
+        // Start conversation
+        self.registers.ctl0.modify(
+            CTL0::CONSEQx::SingleChannelSingleConversion
+                + CTL0::SHSx::SCBit
+                + CTL0::SHP::SET
+                + CTL0::ENC::SET
+                + CTL0::SC::SET,
+        );
+
+        Ok(())
+    }
+
+    /// Configure the conversions of `channel`. A differential channel is
+    /// paired with the next channel, which must be odd.
+    fn set_differential(&self, channel: Channel, differential: bool) {
+        self.differential.set(differential);
+        self.registers.mctl[channel as usize].modify(if differential {
+            MCTLx::DIF::Differential
+        } else {
+            MCTLx::DIF::SingleEnded
+        });
+    }
+
     fn get_sample(&self, chan: Channel) -> u16 {
         // calculate the number of shifts which are necessary to align the sample to u16
         let shift = 8 - 2 * (self.resolution as usize);
 
         // Align the sample
-        (self.registers.mem[chan as usize].get() << shift) as u16
+        let sample = (self.registers.mem[chan as usize].get() << shift) as u16;
+
+        // Differential samples are offset by half the range: convert them to
+        // two's complement.
+        if self.differential.get() {
+            sample ^ 0x8000
+        } else {
+            sample
+        }
     }
 
     fn enable_interrupt(&self, chan: Channel) {
@@ -732,7 +792,7 @@ impl<'a> Adc<'a> {
 
             // Throw callback
             self.client.map(|client| {
-                client.sample_ready(self.get_sample(chan));
+                client.sample_ready(self.get_sample(chan).into());
             });
         } else {
             panic!("ADC: unhandled interrupt: channel {}", chan_nr);
@@ -769,38 +829,7 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     type Channel = Channel;
 
     fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
-        if !self.is_enabled() {
-            self.setup();
-        }
-
-        if self.mode.get() != AdcMode::Disabled {
-            return Err(ErrorCode::BUSY);
-        }
-
-        self.mode.set(AdcMode::Repeated);
-        self.active_channel.set(*channel);
-
-        // Set the channel-number where to start sampling
-        self.registers
-            .ctl1
-            .modify(CTL1::STARTADDx.val(*channel as u32));
-
-        self.enable_interrupt(*channel);
-
-        // Set ADC to mode where a single channel gets sampled once
-        // Set the sample-and-hold source select to software-based
-        // Set the sampling-timer for generating the sample-period
-        // Enable conversation
-        // Start conversation
-        self.registers.ctl0.modify(
-            CTL0::CONSEQx::SingleChannelSingleConversion
-                + CTL0::SHSx::SCBit
-                + CTL0::SHP::SET
-                + CTL0::ENC::SET
-                + CTL0::SC::SET,
-        );
-
-        Ok(())
+        self.sample_once(*channel, false)
     }
 
     fn sample_continuous(&self, channel: &Self::Channel, frequency: u32) -> Result<(), ErrorCode> {
@@ -820,6 +849,7 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
 
         self.mode.set(AdcMode::Repeated);
         self.active_channel.set(*channel);
+        self.set_differential(*channel, false);
 
         // Setup timer
         self.timer
@@ -890,6 +920,20 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
         self.ref_module.map(|ref_mod| ref_mod.ref_voltage_mv())
     }
 
+    /// The inputs are paired in hardware: an even channel is the positive
+    /// input and the next channel the negative one.
+    fn sample_differential(
+        &self,
+        positive: &Self::Channel,
+        negative: &Self::Channel,
+    ) -> Result<(), ErrorCode> {
+        let positive_nr = *positive as u32;
+        if positive_nr % 2 != 0 || *negative as u32 != positive_nr + 1 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.sample_once(*positive, true)
+    }
+
     fn set_client(&self, client: &'a dyn hil::adc::Client) {
         self.client.set(client);
     }
@@ -920,6 +964,7 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
 
         self.mode.set(AdcMode::Highspeed);
         self.active_channel.set(*channel);
+        self.set_differential(*channel, false);
 
         // Set the channel-number where to start sampling
         self.registers
@@ -994,6 +1039,10 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
         }
     }
 
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        None
+    }
+
     fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
         self.highspeed_client.set(client);
     }
@@ -8,7 +8,7 @@ use core::cell::Cell;
 use core::cmp;
 use kernel::hil;
 use kernel::utilities::cells::{OptionalCell, TakeCell, VolatileCell};
-use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
@@ -249,8 +249,48 @@ const SAADC_BASE: StaticRef<AdcRegisters> =
 // Buffer to save completed sample to.
 static mut SAMPLE: [u16; 1] = [0; 1];
 
+/// The SAADC has 8 channels, which are all converted on each sample task.
+const MAX_GROUP_LEN: usize = 8;
+
+/// Largest number of conversions averaged in one sample.
+const MAX_OVERSAMPLING: usize = 256;
+
+/// Number of values of the positive input selection, the gain and reference
+/// of a channel are stored by input.
+const NUM_INPUTS: usize = AdcChannel::VDDHDIV5 as usize + 1;
+
+/// Voltage of the internal reference.
+const INTERNAL_REFERENCE_MV: usize = 600;
+
+/// The gains of the SAADC, as fractions.
+const GAINS: [(AdcChannelGain, u8, u8); 8] = [
+    (AdcChannelGain::Gain1_6, 1, 6),
+    (AdcChannelGain::Gain1_5, 1, 5),
+    (AdcChannelGain::Gain1_4, 1, 4),
+    (AdcChannelGain::Gain1_3, 1, 3),
+    (AdcChannelGain::Gain1_2, 1, 2),
+    (AdcChannelGain::Gain1, 1, 1),
+    (AdcChannelGain::Gain2, 2, 1),
+    (AdcChannelGain::Gain4, 4, 1),
+];
+
+// Buffer to save the samples of a triggered group to.
+static mut GROUP: [u16; MAX_GROUP_LEN] = [0; MAX_GROUP_LEN];
+
+/// A hardware event that starts the conversion of a triggered group. The
+/// event is connected to the SAMPLE task of the SAADC through a PPI channel.
+#[derive(Copy, Clone)]
+pub struct Trigger {
+    /// Programmable PPI channel used by the ADC while sampling.
+    pub ppi_channel: usize,
+    /// Address of the event register, for example from
+    /// `Timer::compare_event_address()` or
+    /// `Pwm::period_end_event_address()`.
+    pub event: u32,
+}
+
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AdcChannelGain {
     Gain1_6 = 0,
     Gain1_5 = 1,
@@ -332,6 +372,7 @@ enum AdcMode {
     Calibrate,
     Single,
     HighSpeed,
+    Triggered,
 }
 
 pub struct Adc<'a> {
@@ -345,6 +386,24 @@ pub struct Adc<'a> {
     length: Cell<usize>,
     next_buffer: TakeCell<'static, [u16]>,
     next_length: Cell<usize>,
+    /// Sampling frequency achieved with the current sample rate setting.
+    sampling_frequency: OptionalCell<u32>,
+
+    ppi: crate::ppi::Ppi,
+    triggered_client: OptionalCell<&'a dyn hil::adc::TriggeredClient>,
+    /// PPI channel and number of channels of the triggered group.
+    trigger_channel: Cell<usize>,
+    group_len: Cell<usize>,
+    /// Base 2 logarithm of the oversampling ratio of single channel samples.
+    oversampling: Cell<u8>,
+    /// Resolution of the conversions, in bits.
+    resolution: Cell<usize>,
+    /// The single sample is differential, and signed.
+    differential: Cell<bool>,
+    /// Gain and reference of each input set with
+    /// `set_channel_gain_and_reference`, overriding the gain of its
+    /// `AdcChannelSetup` and the VDD/4 reference.
+    input_config: [Cell<Option<(AdcChannelGain, hil::adc::Reference)>>; NUM_INPUTS],
 }
 
 impl<'a> Adc<'a> {
@@ -359,11 +418,26 @@ impl<'a> Adc<'a> {
             length: Cell::new(0),
             next_buffer: TakeCell::empty(),
             next_length: Cell::new(0),
+            sampling_frequency: OptionalCell::empty(),
+            ppi: crate::ppi::Ppi::new(),
+            triggered_client: OptionalCell::empty(),
+            trigger_channel: Cell::new(0),
+            group_len: Cell::new(0),
+            oversampling: Cell::new(0),
+            resolution: Cell::new(12),
+            differential: Cell::new(false),
+            input_config: [const { Cell::new(None) }; NUM_INPUTS],
         }
     }
 
     // Calibrate and measure the actual VDD of the board.
     pub fn calibrate(&self) {
+        self.start_calibration();
+    }
+
+    /// Start the offset calibration. The client is notified once VDD is
+    /// measured after it.
+    fn start_calibration(&self) {
         self.mode.set(AdcMode::Calibrate);
 
         // Enable the ADC
@@ -394,7 +468,9 @@ impl<'a> Adc<'a> {
                             + CONFIG::MODE::SE,
                     );
 
-                    self.setup_resolution();
+                    // The VDD computation below expects 12 bit readings.
+                    self.registers.resolution.write(RESOLUTION::VAL::bit12);
+                    self.setup_oversampling(0);
                     self.setup_sample_count(1);
 
                     // Where to put the reading.
@@ -442,6 +518,9 @@ impl<'a> Adc<'a> {
                     if val > 1000 && val < 5100 {
                         self.reference.set(val);
                     }
+
+                    self.mode.set(AdcMode::Idle);
+                    self.client.map(|client| client.calibration_done(Ok(())));
                 }
             }
 
@@ -464,11 +543,17 @@ impl<'a> Adc<'a> {
                     self.registers.events_stopped.write(EVENT::EVENT::CLEAR);
                     // ADC is stopped. Disable and return value.
                     self.registers.enable.write(ENABLE::ENABLE::CLEAR);
+                    self.mode.set(AdcMode::Idle);
 
                     let val = unsafe { SAMPLE[0] as i16 };
                     self.client.map(|client| {
-                        // shift left to meet the ADC HIL requirement
-                        client.sample_ready(if val < 0 { 0 } else { val << 4 } as u16);
+                        // shift left to meet the ADC HIL requirement, keeping
+                        // the sign of differential samples
+                        client.sample_ready(if val < 0 && !self.differential.get() {
+                            0
+                        } else {
+                            (val << self.sample_shift()) as u16 as u32
+                        });
                     });
                 }
             }
@@ -498,8 +583,9 @@ impl<'a> Adc<'a> {
                     // differences in resolution between ADC chips and meets the
                     // ADC HIL requirement.
                     let length = self.length.get();
+                    let shift = self.sample_shift();
                     for i in 0..length {
-                        ret_buf[i] <<= 4;
+                        ret_buf[i] <<= shift;
                     }
 
                     self.highspeed_client.map(|client| {
@@ -515,13 +601,45 @@ impl<'a> Adc<'a> {
                         self.registers
                             .result_maxcnt
                             .write(RESULT_MAXCNT::MAXCNT.val(length2 as u32));
-                        kernel::debug!("len2 {}", length2);
+                        kernel::log_trace!(
+                            Sensors,
+                            "ADC: continuing with second buffer of {}",
+                            length2
+                        );
 
                         // self.registers.tasks_sample.write(TASK::TASK::SET);
                         self.registers.tasks_start.write(TASK::TASK::SET);
                     }
                 } else if self.registers.events_stopped.is_set(EVENT::EVENT) {
                     self.registers.events_stopped.write(EVENT::EVENT::CLEAR);
+                    self.mode.set(AdcMode::Idle);
+                }
+            }
+
+            AdcMode::Triggered => {
+                if self.registers.events_end.is_set(EVENT::EVENT) {
+                    self.registers.events_end.write(EVENT::EVENT::CLEAR);
+
+                    let len = self.group_len.get();
+                    let shift = self.sample_shift();
+                    let mut samples = [0; MAX_GROUP_LEN];
+                    for (sample, &val) in samples.iter_mut().zip(unsafe { GROUP.iter() }).take(len)
+                    {
+                        // shift left to meet the ADC HIL requirement
+                        let val = val as i16;
+                        *sample = if val < 0 { 0 } else { val << shift } as u16;
+                    }
+
+                    // Prepare the buffer for the next trigger before the
+                    // client runs, so that no trigger is missed.
+                    self.registers.tasks_start.write(TASK::TASK::SET);
+
+                    self.triggered_client
+                        .map(|client| client.samples_triggered(&samples[..len]));
+                } else if self.registers.events_stopped.is_set(EVENT::EVENT) {
+                    self.registers.events_stopped.write(EVENT::EVENT::CLEAR);
+                    self.registers.enable.write(ENABLE::ENABLE::CLEAR);
+                    self.mode.set(AdcMode::Idle);
                 }
             }
 
@@ -530,26 +648,103 @@ impl<'a> Adc<'a> {
     }
 
     fn setup_channel(&self, channel: &AdcChannelSetup) {
+        let oversampling = self.oversampling.get();
+        self.setup_group_channel(0, channel, oversampling > 0);
+        self.setup_oversampling(oversampling);
+
+        // Disconnect the other channels, so that only this one is sampled.
+        for registers in self.registers.ch.iter().skip(1) {
+            registers.pselp.write(PSEL::PSEL::NotConnected);
+        }
+    }
+
+    fn setup_group_channel(&self, index: usize, channel: &AdcChannelSetup, burst: bool) {
         // Positive goes to the channel passed in, negative not connected.
-        self.registers.ch[0]
+        self.registers.ch[index]
             .pselp
             .write(PSEL::PSEL.val(channel.channel as u32));
-        self.registers.ch[0].pseln.write(PSEL::PSEL::NotConnected);
+        self.registers.ch[index]
+            .pseln
+            .write(PSEL::PSEL::NotConnected);
+
+        let (gain, reference) = self.channel_config(channel);
 
         // Configure the ADC for a single read.
-        self.registers.ch[0].config.write(
-            CONFIG::GAIN.val(channel.gain as u32)
-                + CONFIG::REFSEL::VDD1_4
+        self.registers.ch[index].config.write(
+            CONFIG::GAIN.val(gain as u32)
+                + match reference {
+                    hil::adc::Reference::Internal => CONFIG::REFSEL::Internal,
+                    _ => CONFIG::REFSEL::VDD1_4,
+                }
                 + CONFIG::TACQ.val(channel.sampling_time as u32)
                 + CONFIG::RESP.val(channel.resp as u32)
                 + CONFIG::RESN.val(channel.resn as u32)
-                + CONFIG::MODE::SE,
+                + CONFIG::MODE::SE
+                + if burst {
+                    CONFIG::BURST::Enable
+                } else {
+                    CONFIG::BURST::Disable
+                },
         );
     }
 
+    /// The gain and reference `channel` is converted with.
+    fn channel_config(&self, channel: &AdcChannelSetup) -> (AdcChannelGain, hil::adc::Reference) {
+        self.input_config[channel.channel as usize]
+            .get()
+            .unwrap_or((channel.gain, hil::adc::Reference::Vdd))
+    }
+
+    /// Take one sample on channel 0, once it is configured.
+    fn start_single_sample(&self) {
+        self.setup_resolution();
+
+        // Do one measurement.
+        self.registers
+            .result_maxcnt
+            .write(RESULT_MAXCNT::MAXCNT.val(1));
+        // Where to put the reading.
+        unsafe {
+            self.registers.result_ptr.set(SAMPLE.as_ptr());
+        }
+
+        // No automatic sampling, will trigger manually.
+        self.registers.samplerate.write(SAMPLERATE::MODE::Task);
+
+        // Enable the ADC
+        self.registers.enable.write(ENABLE::ENABLE::SET);
+
+        // Enable started, sample end, and stopped interrupts.
+        self.registers
+            .inten
+            .write(INTEN::STARTED::SET + INTEN::END::SET + INTEN::STOPPED::SET);
+
+        self.mode.set(AdcMode::Single);
+
+        // Start the SAADC and wait for the started interrupt.
+        self.registers.tasks_start.write(TASK::TASK::SET);
+    }
+
     fn setup_resolution(&self) {
-        // Set max resolution (with oversampling).
-        self.registers.resolution.write(RESOLUTION::VAL::bit12);
+        self.registers
+            .resolution
+            .write(match self.resolution.get() {
+                8 => RESOLUTION::VAL::bit8,
+                10 => RESOLUTION::VAL::bit10,
+                14 => RESOLUTION::VAL::bit14,
+                _ => RESOLUTION::VAL::bit12,
+            });
+    }
+
+    /// Left shift of the samples to the MSB of the u16.
+    fn sample_shift(&self) -> usize {
+        16 - self.resolution.get()
+    }
+
+    /// With burst enabled on the channel, each SAMPLE task takes
+    /// `2^log2_ratio` conversions and stores their average.
+    fn setup_oversampling(&self, log2_ratio: u8) {
+        self.registers.oversample.set(log2_ratio as u32);
     }
 
     fn setup_sample_count(&self, count: usize) {
@@ -571,6 +766,7 @@ impl<'a> Adc<'a> {
         self.registers
             .samplerate
             .write(SAMPLERATE::MODE::Timers + SAMPLERATE::CC.val(cc));
+        self.sampling_frequency.set(16000000 / cc);
     }
 }
 
@@ -580,33 +776,8 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
 
     fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
         self.setup_channel(channel);
-        self.setup_resolution();
-
-        // Do one measurement.
-        self.registers
-            .result_maxcnt
-            .write(RESULT_MAXCNT::MAXCNT.val(1));
-        // Where to put the reading.
-        unsafe {
-            self.registers.result_ptr.set(SAMPLE.as_ptr());
-        }
-
-        // No automatic sampling, will trigger manually.
-        self.registers.samplerate.write(SAMPLERATE::MODE::Task);
-
-        // Enable the ADC
-        self.registers.enable.write(ENABLE::ENABLE::SET);
-
-        // Enable started, sample end, and stopped interrupts.
-        self.registers
-            .inten
-            .write(INTEN::STARTED::SET + INTEN::END::SET + INTEN::STOPPED::SET);
-
-        self.mode.set(AdcMode::Single);
-
-        // Start the SAADC and wait for the started interrupt.
-        self.registers.tasks_start.write(TASK::TASK::SET);
-
+        self.differential.set(false);
+        self.start_single_sample();
         Ok(())
     }
 
@@ -619,18 +790,113 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     }
 
     fn stop_sampling(&self) -> Result<(), ErrorCode> {
+        if let AdcMode::Triggered = self.mode.get() {
+            self.ppi.disconnect(self.trigger_channel.get());
+        }
         self.registers.tasks_stop.write(TASK::TASK::SET);
         Ok(())
     }
 
     fn get_resolution_bits(&self) -> usize {
-        12
+        self.resolution.get()
+    }
+
+    /// Calibrate the offset of the SAADC, and measure VDD again to update the
+    /// voltage reference.
+    fn calibrate(&self) -> Result<(), ErrorCode> {
+        if !matches!(self.mode.get(), AdcMode::Idle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.start_calibration();
+        Ok(())
+    }
+
+    /// The SAADC converts with 8, 10, 12 or 14 bits. Lower resolutions
+    /// shorten the conversions, and 14 bits are only reached with
+    /// oversampling.
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        if !matches!(bits, 8 | 10 | 12 | 14) {
+            return Err(ErrorCode::INVAL);
+        }
+        if !matches!(self.mode.get(), AdcMode::Idle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.resolution.set(bits);
+        Ok(())
     }
 
     fn get_voltage_reference_mv(&self) -> Option<usize> {
         Some(self.reference.get())
     }
 
+    /// Each input can use any gain from 1/6 to 4, with the internal 0.6 V
+    /// reference or VDD/4. The input voltage must stay below VDD whatever
+    /// the full scale.
+    fn set_channel_gain_and_reference(
+        &self,
+        channel: &Self::Channel,
+        gain: hil::adc::Gain,
+        reference: hil::adc::Reference,
+    ) -> Result<(), ErrorCode> {
+        if reference == hil::adc::Reference::External {
+            return Err(ErrorCode::INVAL);
+        }
+        let (gain, _, _) = GAINS
+            .iter()
+            .find(|(_, numerator, denominator)| gain.matches(*numerator, *denominator))
+            .ok_or(ErrorCode::INVAL)?;
+        self.input_config[channel.channel as usize].set(Some((*gain, reference)));
+        Ok(())
+    }
+
+    fn get_channel_voltage_reference_mv(&self, channel: &Self::Channel) -> Option<usize> {
+        let (gain, reference) = self.channel_config(channel);
+        let reference_mv = match reference {
+            hil::adc::Reference::Internal => INTERNAL_REFERENCE_MV,
+            _ => self.reference.get() / 4,
+        };
+        GAINS
+            .iter()
+            .find(|(g, _, _)| *g == gain)
+            .map(|(_, numerator, denominator)| {
+                hil::adc::Gain::new(*numerator, *denominator).full_scale_mv(reference_mv)
+            })
+    }
+
+    /// The SAADC averages up to 256 conversions per sample. Triggered
+    /// samples of groups are never oversampled.
+    fn set_oversampling(&self, ratio: usize) -> Result<(), ErrorCode> {
+        if !ratio.is_power_of_two() || ratio > MAX_OVERSAMPLING {
+            return Err(ErrorCode::INVAL);
+        }
+        if !matches!(self.mode.get(), AdcMode::Idle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.oversampling.set(ratio.trailing_zeros() as u8);
+        Ok(())
+    }
+
+    fn get_oversampling(&self) -> usize {
+        1 << self.oversampling.get()
+    }
+
+    /// Any two analog inputs can be paired. Differential samples have a sign
+    /// bit and one bit less than the resolution.
+    fn sample_differential(
+        &self,
+        positive: &Self::Channel,
+        negative: &Self::Channel,
+    ) -> Result<(), ErrorCode> {
+        self.setup_channel(positive);
+        self.registers.ch[0]
+            .pseln
+            .write(PSEL::PSEL.val(negative.channel as u32));
+        self.registers.ch[0].config.modify(CONFIG::MODE::Diff);
+        self.differential.set(true);
+        self.start_single_sample();
+        Ok(())
+    }
+
     fn set_client(&self, client: &'a dyn hil::adc::Client) {
         self.client.set(client);
     }
@@ -710,7 +976,75 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
         Ok((self.buffer.take(), self.next_buffer.take()))
     }
 
+    /// The sample rate is 16 MHz divided by an integer between 80 and 2047,
+    /// so the achieved frequency is the closest one above the requested
+    /// frequency, clamped to 7.8-200 kHz.
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        self.sampling_frequency.get()
+    }
+
     fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
         self.highspeed_client.set(client);
     }
 }
+
+impl<'a> hil::adc::AdcTriggered<'a> for Adc<'a> {
+    type Trigger = Trigger;
+
+    fn max_group_len(&self) -> usize {
+        MAX_GROUP_LEN
+    }
+
+    /// The channels of the group are converted in scan mode, each channel
+    /// taking its acquisition time plus 2 us.
+    fn sample_triggered(
+        &self,
+        channels: &[Self::Channel],
+        trigger: &Self::Trigger,
+    ) -> Result<(), ErrorCode> {
+        if channels.is_empty() || channels.len() > MAX_GROUP_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        if !matches!(self.mode.get(), AdcMode::Idle) {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // Oversampling would average the samples of all the channels of the
+        // group, so it is not used in scan mode.
+        for (index, channel) in channels.iter().enumerate() {
+            self.setup_group_channel(index, channel, false);
+        }
+        self.setup_oversampling(0);
+        for registers in self.registers.ch.iter().skip(channels.len()) {
+            registers.pselp.write(PSEL::PSEL::NotConnected);
+        }
+        self.setup_resolution();
+
+        // One sample per channel on each trigger.
+        self.setup_sample_count(channels.len());
+        unsafe {
+            self.registers.result_ptr.set(GROUP.as_ptr());
+        }
+
+        // The trigger starts the sampling through the PPI.
+        self.registers.samplerate.write(SAMPLERATE::MODE::Task);
+        let sample_task = core::ptr::from_ref(&self.registers.tasks_sample) as u32;
+        self.ppi
+            .connect(trigger.ppi_channel, trigger.event, sample_task)?;
+        self.trigger_channel.set(trigger.ppi_channel);
+        self.group_len.set(channels.len());
+
+        self.registers.enable.write(ENABLE::ENABLE::SET);
+        self.registers
+            .inten
+            .write(INTEN::END::SET + INTEN::STOPPED::SET);
+
+        self.mode.set(AdcMode::Triggered);
+        self.registers.tasks_start.write(TASK::TASK::SET);
+        Ok(())
+    }
+
+    fn set_triggered_client(&self, client: &'a dyn hil::adc::TriggeredClient) {
+        self.triggered_client.set(client);
+    }
+}
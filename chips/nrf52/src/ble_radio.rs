@@ -39,6 +39,7 @@
 
 use core::cell::Cell;
 use core::ptr::addr_of_mut;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::ble_advertising;
 use kernel::hil::ble_advertising::RadioChannel;
 use kernel::utilities::cells::OptionalCell;
@@ -534,25 +535,88 @@ register_bitfields! [u32,
 static mut PAYLOAD: [u8; nrf5x::constants::RADIO_PAYLOAD_LENGTH] =
     [0x00; nrf5x::constants::RADIO_PAYLOAD_LENGTH];
 
+/// Arbitration of the radio peripheral with another protocol, such as
+/// IEEE 802.15.4 on the nRF52840.
+pub trait RadioArbitration {
+    /// Request the radio for a BLE operation. Returns `false` if the request
+    /// is denied, in which case the operation fails with `BUSY`.
+    fn request_ble(&self) -> bool;
+
+    /// The BLE operations completed, and the radio can be used by another
+    /// protocol.
+    fn release_ble(&self);
+}
+
+/// A BLE operation of the radio.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operation {
+    Transmit,
+    Receive,
+}
+
 pub struct Radio<'a> {
     registers: StaticRef<RadioRegisters>,
     tx_power: Cell<TxPower>,
     rx_client: OptionalCell<&'a dyn ble_advertising::RxClient>,
     tx_client: OptionalCell<&'a dyn ble_advertising::TxClient>,
     buffer: TakeCell<'static, [u8]>,
+    arbiter: OptionalCell<&'a dyn RadioArbitration>,
+    /// Operation in progress.
+    operation: OptionalCell<Operation>,
+    /// Operation that was denied or cancelled, and the error reported to the
+    /// client in a deferred call.
+    failed_operation: OptionalCell<(Operation, ErrorCode)>,
+    deferred_call: DeferredCall,
 }
 
 impl<'a> Radio<'a> {
-    pub const fn new() -> Radio<'a> {
+    pub fn new() -> Radio<'a> {
         Radio {
             registers: RADIO_BASE,
             tx_power: Cell::new(TxPower::ZerodBm),
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
+            arbiter: OptionalCell::empty(),
+            operation: OptionalCell::empty(),
+            failed_operation: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Share the radio peripheral with another protocol through `arbiter`.
+    pub fn set_arbiter(&self, arbiter: &'a dyn RadioArbitration) {
+        self.arbiter.set(arbiter);
+    }
+
+    /// Cancel the operation in progress, which fails with `CANCEL`, for
+    /// example to let another protocol use the radio.
+    pub fn cancel(&self) {
+        if let Some(operation) = self.operation.take() {
+            self.disable_all_interrupts();
+            self.radio_off();
+            self.fail(operation, ErrorCode::CANCEL);
         }
     }
 
+    /// Request the radio from the arbiter, if any, for `operation`. Returns
+    /// `false` if the operation is denied.
+    fn request(&self, operation: Operation) -> bool {
+        if self.arbiter.map_or(true, |arbiter| arbiter.request_ble()) {
+            self.operation.set(operation);
+            true
+        } else {
+            self.fail(operation, ErrorCode::BUSY);
+            false
+        }
+    }
+
+    /// Report the failure of `operation` to the client in a deferred call.
+    fn fail(&self, operation: Operation, error: ErrorCode) {
+        self.failed_operation.set((operation, error));
+        self.deferred_call.set();
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.registers.mode.matches_all(Mode::MODE::BLE_1MBIT)
     }
@@ -626,6 +690,7 @@ impl<'a> Radio<'a> {
                 Err(ErrorCode::FAIL)
             };
 
+            self.operation.clear();
             match self.registers.state.get() {
                 nrf5x::constants::RADIO_STATE_TXRU
                 | nrf5x::constants::RADIO_STATE_TXIDLE
@@ -657,7 +722,13 @@ impl<'a> Radio<'a> {
                 _ => (),
             }
         }
-        self.enable_interrupts();
+
+        if self.operation.is_some() {
+            self.enable_interrupts();
+        } else {
+            // No new operation was started by the clients.
+            self.arbiter.map(|arbiter| arbiter.release_ble());
+        }
     }
 
     pub fn enable_interrupts(&self) {
@@ -799,12 +870,18 @@ impl<'a> ble_advertising::BleAdvertisementDriver<'a> for Radio<'a> {
     fn transmit_advertisement(&self, buf: &'static mut [u8], _len: usize, channel: RadioChannel) {
         let res = self.replace_radio_buffer(buf);
         self.buffer.replace(res);
+        if !self.request(Operation::Transmit) {
+            return;
+        }
         self.ble_initialize(channel);
         self.tx();
         self.enable_interrupts();
     }
 
     fn receive_advertisement(&self, channel: RadioChannel) {
+        if !self.request(Operation::Receive) {
+            return;
+        }
         self.ble_initialize(channel);
         self.rx();
         self.enable_interrupts();
@@ -835,3 +912,30 @@ impl ble_advertising::BleConfig for Radio<'_> {
         }
     }
 }
+
+impl DeferredCallClient for Radio<'_> {
+    fn handle_deferred_call(&self) {
+        self.failed_operation
+            .take()
+            .map(|(operation, error)| match operation {
+                Operation::Transmit => {
+                    self.buffer.take().map(|buf| {
+                        self.tx_client
+                            .map(|client| client.transmit_event(buf, Err(error)));
+                    });
+                }
+                Operation::Receive => unsafe {
+                    self.rx_client.map(|client| {
+                        client.receive_event(&mut *addr_of_mut!(PAYLOAD), 0, Err(error))
+                    });
+                },
+            });
+        if self.operation.is_none() {
+            self.arbiter.map(|arbiter| arbiter.release_ble());
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
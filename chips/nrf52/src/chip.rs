@@ -29,6 +29,7 @@ impl<'a, I: InterruptService + 'a> NRF52<'a, I> {
 /// constructed manually in main.rs.
 pub struct Nrf52DefaultPeripherals<'a> {
     pub acomp: crate::acomp::Comparator<'a>,
+    pub lpcomp: crate::lpcomp::Lpcomp<'a>,
     pub ecb: crate::aes::AesECB<'a>,
     pub pwr_clk: crate::power::Power<'a>,
     pub ble_radio: crate::ble_radio::Radio<'a>,
@@ -54,6 +55,7 @@ impl<'a> Nrf52DefaultPeripherals<'a> {
     pub fn new() -> Self {
         Self {
             acomp: crate::acomp::Comparator::new(),
+            lpcomp: crate::lpcomp::Lpcomp::new(),
             ecb: crate::aes::AesECB::new(),
             pwr_clk: crate::power::Power::new(),
             ble_radio: crate::ble_radio::Radio::new(),
@@ -79,12 +81,20 @@ impl<'a> Nrf52DefaultPeripherals<'a> {
     // Necessary for setting up circular dependencies
     pub fn init(&'static self) {
         kernel::deferred_call::DeferredCallClient::register(&self.nvmc);
+        kernel::deferred_call::DeferredCallClient::register(&self.ble_radio);
     }
 }
 impl<'a> kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'a> {
     unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
         match interrupt {
-            crate::peripheral_interrupts::COMP => self.acomp.handle_interrupt(),
+            crate::peripheral_interrupts::COMP => {
+                // COMP and LPCOMP share the interrupt, only one is enabled.
+                if self.lpcomp.is_enabled() {
+                    self.lpcomp.handle_interrupt()
+                } else {
+                    self.acomp.handle_interrupt()
+                }
+            }
             crate::peripheral_interrupts::ECB => self.ecb.handle_interrupt(),
             crate::peripheral_interrupts::POWER_CLOCK => self.pwr_clk.handle_interrupt(),
             crate::peripheral_interrupts::RADIO => match self.ble_radio.is_enabled() {
@@ -153,6 +163,7 @@ impl<'a, I: InterruptService + 'a> kernel::platform::chip::Chip for NRF52<'a, I>
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt {}", interrupt);
                     }
+                    cortexm4::interrupt_latency::complete(interrupt);
                     let n = nvic::Nvic::new(interrupt);
                     n.clear_pending();
                     n.enable();
@@ -337,6 +337,12 @@ impl Ficr {
         }
     }
 
+    /// Whether the NVMC supports partially erasing a page. The nRF52832 can
+    /// only erase a whole page at once.
+    pub(crate) fn has_partial_erase(&self) -> bool {
+        matches!(self.part(), Part::N52833 | Part::N52840)
+    }
+
     pub(crate) fn variant(&self) -> Variant {
         // If you update this, make sure to update
         // `has_updated_approtect_logic()` as well.
@@ -15,6 +15,7 @@ pub mod clock;
 pub mod crt1;
 pub mod ficr;
 pub mod i2c;
+pub mod lpcomp;
 pub mod nvmc;
 pub mod power;
 pub mod ppi;
@@ -0,0 +1,242 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Low-power comparator (LPCOMP), for nrf52
+//!
+//! The LPCOMP compares an analog input pin (AIN0-AIN7) against a fraction of
+//! VDD or an external reference, using a few hundred nanoamps. Unlike COMP, it
+//! keeps running without the high-frequency clock, so this driver only
+//! exposes it as a deep-sleep wakeup source: the chip sleeps until the input
+//! crosses the reference.
+//!
+//! LPCOMP and COMP share their registers and their interrupt, and only one of
+//! them can be enabled at a time. Arming the LPCOMP while COMP is in use
+//! returns `BUSY`.
+
+use kernel::hil::wakeup_source::{WakeupClient, WakeupEdge, WakeupSource};
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use core::cell::Cell;
+
+register_structs! {
+    LpcompRegisters {
+        /// Start comparator
+        (0x000 => tasks_start: WriteOnly<u32>),
+        /// Stop comparator
+        (0x004 => tasks_stop: WriteOnly<u32>),
+        /// Sample comparator value
+        (0x008 => tasks_sample: WriteOnly<u32>),
+        (0x00c => _reserved0),
+        /// LPCOMP is ready and output is valid
+        (0x100 => events_ready: ReadWrite<u32>),
+        /// Downward crossing
+        (0x104 => events_down: ReadWrite<u32>),
+        /// Upward crossing
+        (0x108 => events_up: ReadWrite<u32>),
+        /// Downward or upward crossing
+        (0x10c => events_cross: ReadWrite<u32>),
+        (0x110 => _reserved1),
+        /// Enable interrupt
+        (0x304 => intenset: ReadWrite<u32, Interrupt::Register>),
+        /// Disable interrupt
+        (0x308 => intenclr: ReadWrite<u32, Interrupt::Register>),
+        (0x30c => _reserved2),
+        /// Compare result
+        (0x400 => result: ReadOnly<u32>),
+        (0x404 => _reserved3),
+        /// Enable LPCOMP
+        (0x500 => enable: ReadWrite<u32, Enable::Register>),
+        /// Input pin select
+        (0x504 => psel: ReadWrite<u32>),
+        /// Reference select
+        (0x508 => refsel: ReadWrite<u32>),
+        /// External reference select
+        (0x50c => extrefsel: ReadWrite<u32>),
+        (0x510 => _reserved4),
+        /// Analog detect configuration, for waking from System OFF
+        (0x520 => anadetect: ReadWrite<u32, AnaDetect::Register>),
+        (0x524 => _reserved5),
+        /// Comparator hysteresis enable
+        (0x538 => hyst: ReadWrite<u32>),
+        (0x53c => @END),
+    }
+}
+
+register_bitfields! [u32,
+    Interrupt [
+        READY OFFSET(0) NUMBITS(1) [],
+        DOWN OFFSET(1) NUMBITS(1) [],
+        UP OFFSET(2) NUMBITS(1) [],
+        CROSS OFFSET(3) NUMBITS(1) []
+    ],
+    Enable [
+        ENABLE OFFSET(0) NUMBITS(2) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+    AnaDetect [
+        ANADETECT OFFSET(0) NUMBITS(2) [
+            Cross = 0,
+            Up = 1,
+            Down = 2
+        ]
+    ]
+];
+
+const LPCOMP_BASE: StaticRef<LpcompRegisters> =
+    unsafe { StaticRef::new(0x40013000 as *const LpcompRegisters) };
+
+/// Analog input compared against the reference.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AnalogInput {
+    AIN0 = 0,
+    AIN1 = 1,
+    AIN2 = 2,
+    AIN3 = 3,
+    AIN4 = 4,
+    AIN5 = 5,
+    AIN6 = 6,
+    AIN7 = 7,
+}
+
+/// Reference of the comparator: a fraction of VDD, or an external
+/// reference on AIN0 or AIN1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reference {
+    Vdd1_8 = 0,
+    Vdd2_8 = 1,
+    Vdd3_8 = 2,
+    Vdd4_8 = 3,
+    Vdd5_8 = 4,
+    Vdd6_8 = 5,
+    Vdd7_8 = 6,
+    ExternalAin0 = 7,
+    ExternalAin1 = 0x17,
+    Vdd1_16 = 8,
+    Vdd3_16 = 9,
+    Vdd5_16 = 10,
+    Vdd7_16 = 11,
+    Vdd9_16 = 12,
+    Vdd11_16 = 13,
+    Vdd13_16 = 14,
+    Vdd15_16 = 15,
+}
+
+pub struct Lpcomp<'a> {
+    registers: StaticRef<LpcompRegisters>,
+    input: Cell<AnalogInput>,
+    reference: Cell<Reference>,
+    hysteresis: Cell<bool>,
+    edge: OptionalCell<WakeupEdge>,
+    client: OptionalCell<&'a dyn WakeupClient>,
+}
+
+impl<'a> Lpcomp<'a> {
+    pub const fn new() -> Self {
+        Lpcomp {
+            registers: LPCOMP_BASE,
+            input: Cell::new(AnalogInput::AIN0),
+            reference: Cell::new(Reference::Vdd4_8),
+            hysteresis: Cell::new(false),
+            edge: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Select the input and the reference. Takes effect the next time the
+    /// comparator is armed.
+    pub fn configure(&self, input: AnalogInput, reference: Reference, hysteresis: bool) {
+        self.input.set(input);
+        self.reference.set(reference);
+        self.hysteresis.set(hysteresis);
+    }
+
+    /// Whether the LPCOMP, rather than COMP, owns the shared registers.
+    pub fn is_enabled(&self) -> bool {
+        self.registers.enable.matches_all(Enable::ENABLE::Enabled)
+    }
+
+    pub fn handle_interrupt(&self) {
+        let up = self.registers.events_up.get() != 0;
+        let down = self.registers.events_down.get() != 0;
+        self.registers.events_up.set(0);
+        self.registers.events_down.set(0);
+        self.registers.events_cross.set(0);
+
+        let matched = self.edge.map_or(false, |edge| match edge {
+            WakeupEdge::Rising => up,
+            WakeupEdge::Falling => down,
+            WakeupEdge::Either => up || down,
+        });
+        if matched {
+            self.client.map(|client| client.woken());
+        }
+    }
+}
+
+impl<'a> WakeupSource<'a> for Lpcomp<'a> {
+    fn enable_wakeup(&self, edge: WakeupEdge) -> Result<(), ErrorCode> {
+        if !self.registers.enable.matches_all(Enable::ENABLE::Disabled) && !self.is_enabled() {
+            // COMP is enabled.
+            return Err(ErrorCode::BUSY);
+        }
+        if self.is_enabled() {
+            self.disable_wakeup();
+        }
+
+        let reference = self.reference.get() as u32;
+        self.registers.psel.set(self.input.get() as u32);
+        self.registers.refsel.set(reference & 0xF);
+        self.registers.extrefsel.set(reference >> 4);
+        self.registers.hyst.set(self.hysteresis.get() as u32);
+        let (detect, interrupt) = match edge {
+            WakeupEdge::Rising => (AnaDetect::ANADETECT::Up, Interrupt::UP::SET),
+            WakeupEdge::Falling => (AnaDetect::ANADETECT::Down, Interrupt::DOWN::SET),
+            WakeupEdge::Either => (AnaDetect::ANADETECT::Cross, Interrupt::CROSS::SET),
+        };
+        self.registers.anadetect.write(detect);
+        self.edge.set(edge);
+
+        self.registers.enable.write(Enable::ENABLE::Enabled);
+        self.registers.events_ready.set(0);
+        self.registers.tasks_start.set(1);
+        // The comparator is ready within a few tens of microseconds.
+        while self.registers.events_ready.get() == 0 {}
+        self.registers.events_up.set(0);
+        self.registers.events_down.set(0);
+        self.registers.events_cross.set(0);
+        self.registers.intenset.write(interrupt);
+        Ok(())
+    }
+
+    fn disable_wakeup(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.registers.intenclr.write(
+            Interrupt::READY::SET
+                + Interrupt::DOWN::SET
+                + Interrupt::UP::SET
+                + Interrupt::CROSS::SET,
+        );
+        self.registers.tasks_stop.set(1);
+        self.registers.enable.write(Enable::ENABLE::Disabled);
+        self.edge.clear();
+    }
+
+    fn is_wakeup_enabled(&self) -> bool {
+        self.edge.is_some()
+    }
+
+    fn set_wakeup_client(&self, client: &'a dyn WakeupClient) {
+        self.client.set(client);
+    }
+}
@@ -7,6 +7,7 @@
 //! Used in order read and write to internal flash.
 
 use core::cell::Cell;
+use core::cmp;
 use core::ops::{Index, IndexMut};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil;
@@ -18,6 +19,8 @@ use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
+use crate::ficr;
+
 const NVMC_BASE: StaticRef<NvmcRegisters> =
     unsafe { StaticRef::new(0x4001E400 as *const NvmcRegisters) };
 
@@ -45,8 +48,14 @@ struct NvmcRegisters {
     /// Register for erasing User Information Configuration Registers
     /// Address: 0x514 - 0x518
     pub eraseuicr: ReadWrite<u32, EraseUicr::Register>,
+    /// Register for partial erase of a page in Code area
+    /// Address: 0x518 - 0x51C
+    pub erasepagepartial: ReadWrite<u32, ErasePage::Register>,
+    /// Register for partial erase configuration
+    /// Address: 0x51C - 0x520
+    pub erasepagepartialcfg: ReadWrite<u32, ErasePagePartialCfg::Register>,
     /// Reserved
-    _reserved3: [u32; 10],
+    _reserved3: [u32; 8],
     /// Configuration register
     /// Address: 0x540 - 0x544
     pub icachecnf: ReadWrite<u32, CacheConfiguration::Register>,
@@ -114,6 +123,11 @@ register_bitfields! [u32,
             ERASE = 1
         ]
     ],
+    /// Register for partial erase configuration
+    ErasePagePartialCfg [
+        /// Duration of the partial erase in milliseconds
+        DURATION OFFSET(0) NUMBITS(7) []
+    ],
     /// I-Code cache configuration register
     CacheConfiguration [
         /// Cache enabled
@@ -200,11 +214,29 @@ pub enum FlashState {
     Erase, // Performing an erase operation.
 }
 
+/// Number of words programmed per deferred call when writing a page. Each
+/// word write stalls the CPU for up to 41 us, so writing a whole 4 kB page in
+/// one go would block the kernel loop for tens of milliseconds.
+const WRITE_WORDS_PER_CALL: usize = 64;
+
+/// Duration of each partial erase, in milliseconds. A page is erased over
+/// several deferred calls so that the 85 ms page erase time does not block the
+/// kernel loop in one go.
+const ERASE_SLICE_MS: u32 = 10;
+
+/// Number of partial erases needed to erase a whole page (tERASEPAGE is
+/// 85 ms).
+const ERASE_SLICES: usize = 85_usize.div_ceil(ERASE_SLICE_MS as usize);
+
 pub struct Nvmc {
     registers: StaticRef<NvmcRegisters>,
     client: OptionalCell<&'static dyn hil::flash::Client<Nvmc>>,
     buffer: TakeCell<'static, NrfPage>,
     state: Cell<FlashState>,
+    write_page_number: Cell<usize>,
+    write_offset: Cell<usize>,
+    erase_page_number: Cell<usize>,
+    erase_slices_left: Cell<usize>,
     deferred_call: DeferredCall,
 }
 
@@ -215,6 +247,10 @@ impl Nvmc {
             client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
             state: Cell::new(FlashState::Ready),
+            write_page_number: Cell::new(0),
+            write_offset: Cell::new(0),
+            erase_page_number: Cell::new(0),
+            erase_slices_left: Cell::new(0),
             deferred_call: DeferredCall::new(),
         }
     }
@@ -270,19 +306,46 @@ impl Nvmc {
         }
     }
 
-    fn erase_page_helper(&self, page_number: usize) {
+    /// Prepare to erase `page_number`. The erase itself is done by
+    /// `erase_slice()` from deferred calls.
+    fn start_erase(&self, page_number: usize) {
+        self.erase_page_number.set(page_number);
+
+        if ficr::Ficr::new().has_partial_erase() {
+            self.registers
+                .erasepagepartialcfg
+                .write(ErasePagePartialCfg::DURATION.val(ERASE_SLICE_MS));
+            self.erase_slices_left.set(ERASE_SLICES);
+        } else {
+            // Without partial erase the whole page is erased in one slice.
+            self.erase_slices_left.set(1);
+        }
+    }
+
+    /// Run the next slice of the pending page erase.
+    fn erase_slice(&self) {
+        let address = (self.erase_page_number.get() * PAGE_SIZE) as u32;
+
         // Put the NVMC in erase mode.
         self.registers.config.write(Configuration::WEN::Een);
 
         // Tell the NVMC to erase the correct page by passing in the correct
         // address.
-        self.registers
-            .erasepage
-            .write(ErasePage::ERASEPAGE.val((page_number * PAGE_SIZE) as u32));
+        if ficr::Ficr::new().has_partial_erase() {
+            self.registers
+                .erasepagepartial
+                .write(ErasePage::ERASEPAGE.val(address));
+        } else {
+            self.registers
+                .erasepage
+                .write(ErasePage::ERASEPAGE.val(address));
+        }
 
         // Make sure that the NVMC is done. The CPU should be blocked while the
         // erase is happening, but it doesn't hurt to check too.
         while !self.registers.ready.is_set(Ready::READY) {}
+
+        self.erase_slices_left.set(self.erase_slices_left.get() - 1);
     }
 
     fn read_range(
@@ -316,44 +379,57 @@ impl Nvmc {
         data: &'static mut NrfPage,
     ) -> Result<(), (ErrorCode, &'static mut NrfPage)> {
         // Need to erase the page first.
-        self.erase_page_helper(page_number);
-
-        // Put the NVMC in write mode.
-        self.registers.config.write(Configuration::WEN::Wen);
-
-        for i in (0..data.len()).step_by(4) {
-            let word: u32 = (data[i + 0] as u32) << 0
-                | (data[i + 1] as u32) << 8
-                | (data[i + 2] as u32) << 16
-                | (data[i + 3] as u32) << 24;
+        self.start_erase(page_number);
 
-            let address = ((page_number * PAGE_SIZE) + i) as u32;
-            let location = unsafe { &*(address as *const VolatileCell<u32>) };
-            location.set(word);
-            while !self.registers.ready.is_set(Ready::READY) {}
-        }
-
-        // Make sure that the NVMC is done. The CPU should be blocked while the
-        // write is happening, but it doesn't hurt to check too.
-        while !self.registers.ready.is_set(Ready::READY) {}
-
-        // Save the buffer so we can return it with the callback.
+        // Save the buffer, the page is erased and then the words are written
+        // in chunks from deferred calls so the write does not monopolize the
+        // kernel loop.
         self.buffer.replace(data);
+        self.write_page_number.set(page_number);
+        self.write_offset.set(0);
 
-        // Mark the need for an interrupt so we can call the write done
-        // callback.
+        // Mark the need for an interrupt so we can start erasing the page.
         self.state.set(FlashState::Write);
         self.deferred_call.set();
 
         Ok(())
     }
 
-    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
-        // Do the basic erase.
-        self.erase_page_helper(page_number);
+    /// Write the next chunk of the page being written. Returns `true` once
+    /// the whole page has been programmed.
+    fn write_chunk(&self) -> bool {
+        self.buffer.map_or(true, |data| {
+            let start = self.write_offset.get();
+            let end = cmp::min(start + WRITE_WORDS_PER_CALL * 4, data.len());
+
+            // Put the NVMC in write mode.
+            self.registers.config.write(Configuration::WEN::Wen);
+
+            for i in (start..end).step_by(4) {
+                let word: u32 = (data[i + 0] as u32) << 0
+                    | (data[i + 1] as u32) << 8
+                    | (data[i + 2] as u32) << 16
+                    | (data[i + 3] as u32) << 24;
+
+                let address = ((self.write_page_number.get() * PAGE_SIZE) + i) as u32;
+                let location = unsafe { &*(address as *const VolatileCell<u32>) };
+                location.set(word);
+                while !self.registers.ready.is_set(Ready::READY) {}
+            }
+
+            // Make sure that the NVMC is done. The CPU should be blocked while
+            // the write is happening, but it doesn't hurt to check too.
+            while !self.registers.ready.is_set(Ready::READY) {}
 
-        // Mark that we want to trigger a pseudo interrupt so that we can issue
-        // the callback even though the NVMC is completely blocking.
+            self.write_offset.set(end);
+            end == data.len()
+        })
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        // The page is erased in slices from deferred calls, and the callback
+        // is issued once the last slice is done.
+        self.start_erase(page_number);
         self.state.set(FlashState::Erase);
         self.deferred_call.set();
 
@@ -393,6 +469,18 @@ impl hil::flash::Flash for Nvmc {
 
 impl DeferredCallClient for Nvmc {
     fn handle_deferred_call(&self) {
+        let more = if self.erase_slices_left.get() > 0 {
+            // Erase the page before writing it, one slice per call.
+            self.erase_slice();
+            self.erase_slices_left.get() > 0 || self.state.get() == FlashState::Write
+        } else {
+            // More of the page left to write.
+            self.state.get() == FlashState::Write && !self.write_chunk()
+        };
+        if more {
+            self.deferred_call.set();
+            return;
+        }
         self.handle_interrupt();
     }
 
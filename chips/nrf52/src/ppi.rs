@@ -40,10 +40,14 @@
 use kernel::utilities::registers::interfaces::Writeable;
 use kernel::utilities::registers::{register_bitfields, FieldValue, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 const PPI_BASE: StaticRef<PpiRegisters> =
     unsafe { StaticRef::new(0x4001F000 as *const PpiRegisters) };
 
+/// Number of channels whose end points can be programmed.
+pub const NUM_CHANNELS: usize = 20;
+
 #[repr(C)]
 struct PpiRegisters {
     tasks_chg0_en: ReadWrite<u32, Control::Register>,
@@ -62,52 +66,19 @@ struct PpiRegisters {
     chen: ReadWrite<u32, Channel::Register>,
     chenset: ReadWrite<u32, Channel::Register>,
     chenclr: ReadWrite<u32, Channel::Register>,
-    ch0_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch0_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch1_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch1_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch2_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch2_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch3_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch3_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch4_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch4_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch5_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch5_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch6_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch6_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch7_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch7_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch8_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch8_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch9_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch9_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch10_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch10_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch11_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch11_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch12_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch12_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch13_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch13_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch14_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch14_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch15_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch15_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch16_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch16_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch17_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch17_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch18_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch18_tep: ReadWrite<u32, TaskEndPoint::Register>,
-    ch19_eep: ReadWrite<u32, EventEndPoint::Register>,
-    ch19_tep: ReadWrite<u32, TaskEndPoint::Register>,
+    ch: [PpiChannelRegisters; NUM_CHANNELS],
     _reserved2: [u32; 148],
     chg: [ReadWrite<u32, Channel::Register>; 6],
     _reserved3: [u32; 62],
     fork_tep: [ReadWrite<u32, TaskEndPoint::Register>; 32],
 }
 
+#[repr(C)]
+struct PpiChannelRegisters {
+    eep: ReadWrite<u32, EventEndPoint::Register>,
+    tep: ReadWrite<u32, TaskEndPoint::Register>,
+}
+
 register_bitfields! [u32,
     Control [
         ENABLE OFFSET(0) NUMBITS(1)
@@ -172,4 +143,22 @@ impl Ppi {
     pub fn disable(&self, channels: FieldValue<u32, Channel::Register>) {
         self.registers.chenclr.write(channels);
     }
+
+    /// Connect the event register at address `event` to the task register at
+    /// address `task` through the programmable channel `channel`, and enable
+    /// the channel.
+    pub fn connect(&self, channel: usize, event: u32, task: u32) -> Result<(), ErrorCode> {
+        let registers = self.registers.ch.get(channel).ok_or(ErrorCode::INVAL)?;
+        registers.eep.write(EventEndPoint::ADDRESS.val(event));
+        registers.tep.write(TaskEndPoint::ADDRESS.val(task));
+        self.registers.chenset.set(1 << channel);
+        Ok(())
+    }
+
+    /// Disable the programmable channel `channel`.
+    pub fn disconnect(&self, channel: usize) {
+        if channel < NUM_CHANNELS {
+            self.registers.chenclr.set(1 << channel);
+        }
+    }
 }
@@ -186,6 +186,12 @@ impl Pwm {
         }
     }
 
+    /// Address of the `EVENTS_PWMPERIODEND` register, to connect the end of
+    /// each PWM period to a task of another peripheral through the PPI.
+    pub fn period_end_event_address(&self) -> u32 {
+        core::ptr::from_ref(&self.registers.events_pwmperiodend) as u32
+    }
+
     fn start_pwm(
         &self,
         pin: &nrf5x::pinmux::Pinmux,
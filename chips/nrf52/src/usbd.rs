@@ -18,36 +18,36 @@ use kernel::utilities::StaticRef;
 
 use crate::power;
 
-// The following macros provide some diagnostics and panics(!)
-// while this module is experimental and should eventually be removed or
-// replaced with better error handling.
+// The following macros provide some diagnostics, enabled at runtime with the
+// `usb` log level, and panics(!) while this module is experimental and should
+// eventually be replaced with better error handling.
 macro_rules! debug_events {
     [ $( $arg:expr ),+ ] => {
-        {} // kernel::debug!($( $arg ),+)
+        kernel::log_trace!(Usb, $( $arg ),+)
     };
 }
 
 macro_rules! debug_tasks {
     [ $( $arg:expr ),+ ] => {
-        {} // kernel::debug!($( $arg ),+)
+        kernel::log_trace!(Usb, $( $arg ),+)
     };
 }
 
 macro_rules! debug_packets {
     [ $( $arg:expr ),+ ] => {
-        {} // kernel::debug!($( $arg ),+)
+        kernel::log_trace!(Usb, $( $arg ),+)
     };
 }
 
 macro_rules! debug_info {
     [ $( $arg:expr ),+ ] => {
-        {} // kernel::debug!($( $arg ),+)
+        kernel::log_info!(Usb, $( $arg ),+)
     };
 }
 
 macro_rules! internal_warn {
     [ $( $arg:expr ),+ ] => {
-        {} // kernel::debug!($( $arg ),+)
+        kernel::log_warn!(Usb, $( $arg ),+)
     };
 }
 
@@ -5,7 +5,7 @@
 #![no_std]
 
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, nvmc,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, lpcomp, nvmc,
     peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
     timer, trng, uart, uicr,
 };
@@ -7,7 +7,7 @@
 // FIXME: Move ieee802154_radio to an nrf528xx crate so this can access it.
 
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, nvmc,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, lpcomp, nvmc,
     peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
     timer, trng, uart, uicr,
 };
@@ -63,9 +63,11 @@
 // Author: Tyler Potyondy
 // 8/21/23
 
+use crate::radio_arbiter::RadioArbiter;
 use crate::timer::TimerAlarm;
 use core::cell::Cell;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::network_statistics::{Counter, Counters, NetworkStatistics};
 use kernel::hil::radio::{self, PowerClient, RadioChannel, RadioConfig, RadioData};
 use kernel::hil::time::{Alarm, AlarmClient, Time};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -699,6 +701,11 @@ pub struct Radio<'a> {
     state: Cell<RadioState>,
     deferred_call: DeferredCall,
     deferred_call_operation: OptionalCell<DeferredOperation>,
+    counters: Cell<Counters>,
+    arbiter: OptionalCell<&'a RadioArbiter<'a>>,
+    /// The radio hardware is lent to BLE by the arbiter. The driver keeps its
+    /// state, and configures the hardware again when resumed.
+    suspended: Cell<bool>,
 }
 
 impl<'a> AlarmClient for Radio<'a> {
@@ -732,6 +739,9 @@ impl<'a> Radio<'a> {
             state: Cell::new(RadioState::OFF),
             deferred_call: DeferredCall::new(),
             deferred_call_operation: OptionalCell::empty(),
+            counters: Cell::new(Counters::default()),
+            arbiter: OptionalCell::empty(),
+            suspended: Cell::new(false),
         }
     }
 
@@ -739,6 +749,40 @@ impl<'a> Radio<'a> {
         self.timer0.set(timer);
     }
 
+    pub(crate) fn set_arbiter(&self, arbiter: &'a RadioArbiter<'a>) {
+        self.arbiter.set(arbiter);
+    }
+
+    /// Whether a frame or an acknowledgement is being transmitted.
+    pub(crate) fn is_transmitting(&self) -> bool {
+        self.tx_buf.is_some() || self.state.get() == RadioState::ACK
+    }
+
+    /// Stop using the radio hardware, so that BLE can use it. Frames are not
+    /// received until `resume()` is called.
+    pub(crate) fn suspend(&self) {
+        self.suspended.set(true);
+        if self.state.get() != RadioState::OFF {
+            self.disable_all_interrupts();
+            self.registers.power.write(Task::ENABLE::CLEAR);
+        }
+    }
+
+    /// Use the radio hardware again after `suspend()`.
+    pub(crate) fn resume(&self) {
+        self.suspended.set(false);
+        if self.state.get() != RadioState::OFF {
+            self.radio_initialize();
+        }
+    }
+
+    /// Update the network statistics.
+    fn count(&self, counter: Counter) {
+        let mut counters = self.counters.get();
+        counters.increment(counter);
+        self.counters.set(counters);
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.registers
             .mode
@@ -769,11 +813,17 @@ impl<'a> Radio<'a> {
     fn radio_off(&self) {
         self.state.set(RadioState::OFF);
 
-        self.registers.power.write(Task::ENABLE::CLEAR);
+        if !self.suspended.get() {
+            self.registers.power.write(Task::ENABLE::CLEAR);
+        }
     }
 
     fn radio_is_on(&self) -> bool {
-        self.registers.power.is_set(Task::ENABLE)
+        if self.suspended.get() {
+            self.state.get() != RadioState::OFF
+        } else {
+            self.registers.power.is_set(Task::ENABLE)
+        }
     }
 
     fn set_dma_ptr(&self, buffer: &'static mut [u8]) -> &'static mut [u8] {
@@ -806,8 +856,7 @@ impl<'a> Radio<'a> {
             // It should not be possible to receive an interrupt while the
             // tracked radio state is OFF.
             RadioState::OFF => {
-                kernel::debug!("[ERROR]--15.4 state machine");
-                kernel::debug!("Received interrupt while off");
+                kernel::log_error!(Radio, "15.4 state machine received interrupt while off");
             }
             RadioState::RX => {
                 ////////////////////////////////////////////////////////////////
@@ -824,6 +873,11 @@ impl<'a> Radio<'a> {
                 if self.registers.event_end.is_set(Event::READY) {
                     self.registers.event_end.write(Event::READY::CLEAR);
                     let crc = self.crc_check();
+                    self.count(if crc.is_ok() {
+                        Counter::RxFrames
+                    } else {
+                        Counter::RxErrors
+                    });
 
                     // Unwrap fail = Radio RX Buffer is missing (may be due to
                     // receive client not replacing in receive(...) method, or
@@ -909,8 +963,9 @@ impl<'a> Radio<'a> {
                                     );
                                 });
 
-                                kernel::debug!(
-                                    "[ACKFail] Failed sending ACK in response to received packet."
+                                kernel::log_warn!(
+                                    Radio,
+                                    "Failed sending ACK in response to received packet"
                                 );
                             });
                     } else {
@@ -976,6 +1031,7 @@ impl<'a> Radio<'a> {
                         // sending client.
 
                         let result = Err(ErrorCode::BUSY);
+                        self.count(Counter::TxErrors);
                         self.tx_client.map(|client| {
                             // Unwrap fail = TX Buffer is missing and was
                             // mistakenly not replaced after completion of
@@ -992,6 +1048,7 @@ impl<'a> Radio<'a> {
                 if self.registers.event_end.is_set(Event::READY) {
                     self.registers.event_end.write(Event::READY::CLEAR);
                     let result = Ok(());
+                    self.count(Counter::TxFrames);
 
                     // TODO: Acked is hardcoded to always return false; add
                     // support to receive tx ACK.
@@ -1091,6 +1148,12 @@ impl<'a> Radio<'a> {
     }
 
     fn radio_initialize(&self) {
+        if self.suspended.get() {
+            // Configured when resumed.
+            self.state.set(RadioState::RX);
+            return;
+        }
+
         self.radio_on();
 
         // CONFIGURE RADIO //
@@ -1254,9 +1317,12 @@ impl<'a> kernel::hil::radio::RadioConfig<'a> for Radio<'a> {
     ///
     /// Issues a callback to the config client when done.
     fn config_commit(&self) {
-        // All we can configure is TX power and channel frequency.
-        self.ieee802154_set_tx_power();
-        self.ieee802154_set_channel_freq();
+        // All we can configure is TX power and channel frequency. While
+        // suspended, they are configured when resumed.
+        if !self.suspended.get() {
+            self.ieee802154_set_tx_power();
+            self.ieee802154_set_channel_freq();
+        }
 
         // Enable deferred call so we can generate a `ConfigClient` callback.
         self.deferred_call_operation
@@ -1347,6 +1413,14 @@ impl<'a> kernel::hil::radio::RadioData<'a> for Radio<'a> {
             return Err((ErrorCode::OFF, buf));
         } else if self.busy() {
             return Err((ErrorCode::BUSY, buf));
+        } else if self.suspended.get()
+            && !self
+                .arbiter
+                .map_or(false, |arbiter| arbiter.request_ieee802154())
+        {
+            // The radio is used by BLE.
+            self.count(Counter::TxDropped);
+            return Err((ErrorCode::BUSY, buf));
         } else if buf.len() < radio::PSDU_OFFSET + frame_len + radio::MFR_SIZE {
             // Not enough room for CRC or PHR or reserved byte
             return Err((ErrorCode::SIZE, buf));
@@ -1410,3 +1484,17 @@ impl DeferredCallClient for Radio<'_> {
         self.deferred_call.register(self);
     }
 }
+
+impl NetworkStatistics for Radio<'_> {
+    fn interface_name(&self) -> &'static str {
+        "ieee802154"
+    }
+
+    fn counters(&self) -> Counters {
+        self.counters.get()
+    }
+
+    fn reset_counters(&self) {
+        self.counters.set(Counters::default());
+    }
+}
@@ -51,7 +51,8 @@ impl<'a> kernel::platform::chip::InterruptService for Nrf52840DefaultPeripherals
                     (false, false) => (),
                     (true, false) => self.ieee802154_radio.handle_interrupt(),
                     (false, true) => self.nrf52.ble_radio.handle_interrupt(),
-                    (true, true) => kernel::debug!(
+                    (true, true) => kernel::log_error!(
+                        Radio,
                         "nRF 802.15.4 and BLE radios cannot be simultaneously enabled!"
                     ),
                 }
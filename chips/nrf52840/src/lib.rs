@@ -4,7 +4,7 @@
 
 #![no_std]
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, nvmc,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, init, lpcomp, nvmc,
     peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
     timer, trng, uart, uicr, usbd,
 };
@@ -17,3 +17,5 @@ pub mod interrupt_service;
 pub mod ieee802154_radio;
 
 pub mod peripheral_interrupts;
+pub mod qspi;
+pub mod radio_arbiter;
@@ -0,0 +1,242 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Quad SPI flash interface, used to execute in place (XIP).
+//!
+//! <https://infocenter.nordicsemi.com/topic/ps_nrf52840/qspi.html>
+//!
+//! This driver only supports the memory-mapped mode of the QSPI peripheral:
+//! the external flash is read through the XIP window starting at
+//! `0x12000000`, for example to store processes. The flash is written
+//! through other interfaces (e.g. a debugger), not with this driver.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let qspi = static_init!(nrf52840::qspi::Qspi, nrf52840::qspi::Qspi::new());
+//! qspi.configure(
+//!     nrf52840::qspi::QspiPins {
+//!         sck: Pinmux::new(Pin::P0_19 as u32),
+//!         csn: Pinmux::new(Pin::P0_17 as u32),
+//!         io0: Pinmux::new(Pin::P0_20 as u32),
+//!         io1: Pinmux::new(Pin::P0_21 as u32),
+//!         io2: Pinmux::new(Pin::P0_22 as u32),
+//!         io3: Pinmux::new(Pin::P0_23 as u32),
+//!     },
+//!     nrf52840::qspi::ReadMode::FastRead,
+//!     8_000_000,
+//! );
+//! let external_apps = qspi.map(0, 0x100000).unwrap_or(&[]);
+//! ```
+
+use kernel::hil::xip::ExecuteInPlace;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+use nrf52::pinmux::Pinmux;
+
+const QSPI_BASE: StaticRef<QspiRegisters> =
+    unsafe { StaticRef::new(0x40029000 as *const QspiRegisters) };
+
+/// Start of the window the external flash is mapped into.
+const XIP_BASE: usize = 0x1200_0000;
+
+/// Size of the XIP window.
+pub const XIP_WINDOW_SIZE: usize = 0x0800_0000;
+
+/// Frequency of the clock the SCK frequency is divided from.
+const BASE_CLOCK_HZ: u32 = 32_000_000;
+
+/// Number of polls of the READY event after activation, about a few
+/// milliseconds at 64 MHz.
+const ACTIVATE_TIMEOUT: usize = 100_000;
+
+register_structs! {
+    QspiRegisters {
+        (0x000 => tasks_activate: WriteOnly<u32, Task::Register>),
+        (0x004 => _reserved0),
+        (0x010 => tasks_deactivate: WriteOnly<u32, Task::Register>),
+        (0x014 => _reserved1),
+        (0x100 => events_ready: ReadWrite<u32, Event::Register>),
+        (0x104 => _reserved2),
+        (0x308 => intenclr: ReadWrite<u32, Interrupt::Register>),
+        (0x30c => _reserved3),
+        (0x500 => enable: ReadWrite<u32, Enable::Register>),
+        (0x504 => _reserved4),
+        (0x524 => psel_sck: ReadWrite<u32>),
+        (0x528 => psel_csn: ReadWrite<u32>),
+        (0x52c => _reserved5),
+        (0x530 => psel_io0: ReadWrite<u32>),
+        (0x534 => psel_io1: ReadWrite<u32>),
+        (0x538 => psel_io2: ReadWrite<u32>),
+        (0x53c => psel_io3: ReadWrite<u32>),
+        (0x540 => xipoffset: ReadWrite<u32>),
+        (0x544 => ifconfig0: ReadWrite<u32, InterfaceConfig0::Register>),
+        (0x548 => _reserved6),
+        (0x600 => ifconfig1: ReadWrite<u32, InterfaceConfig1::Register>),
+        (0x604 => @END),
+    }
+}
+
+register_bitfields! [u32,
+    Task [
+        TRIGGER OFFSET(0) NUMBITS(1) []
+    ],
+    Event [
+        READY OFFSET(0) NUMBITS(1) []
+    ],
+    Interrupt [
+        READY OFFSET(0) NUMBITS(1) []
+    ],
+    Enable [
+        ENABLE OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+    InterfaceConfig0 [
+        /// Opcode of the read operations
+        READOC OFFSET(0) NUMBITS(3) [],
+        /// Opcode of the write operations
+        WRITEOC OFFSET(3) NUMBITS(3) [
+            PP = 0
+        ],
+        /// Addressing mode
+        ADDRMODE OFFSET(6) NUMBITS(1) [
+            Bit24 = 0,
+            Bit32 = 1
+        ],
+        /// Deep power-down mode
+        DPMENABLE OFFSET(7) NUMBITS(1) []
+    ],
+    InterfaceConfig1 [
+        /// Minimum time CSN stays high, in 62.5 ns steps
+        SCKDELAY OFFSET(0) NUMBITS(8) [],
+        /// Enter deep power-down mode
+        DPMEN OFFSET(24) NUMBITS(1) [],
+        /// SPI mode
+        SPIMODE OFFSET(25) NUMBITS(1) [
+            Mode0 = 0,
+            Mode3 = 1
+        ],
+        /// SCK frequency, 32 MHz / (SCKFREQ + 1)
+        SCKFREQ OFFSET(28) NUMBITS(4) []
+    ]
+];
+
+/// Read instruction of the external flash. The quad modes require the quad
+/// enable bit of the flash to be set.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReadMode {
+    /// Single data line (opcode 0x0B).
+    FastRead = 0,
+    /// Dual data lines (opcode 0x3B).
+    Read2O = 1,
+    /// Dual data and address lines (opcode 0xBB).
+    Read2IO = 2,
+    /// Quad data lines (opcode 0x6B).
+    Read4O = 3,
+    /// Quad data and address lines (opcode 0xEB).
+    Read4IO = 4,
+}
+
+/// Pins of the external flash. IO2 and IO3 are also driven in the single and
+/// dual read modes, as the write protect and hold lines of the flash.
+pub struct QspiPins {
+    pub sck: Pinmux,
+    pub csn: Pinmux,
+    pub io0: Pinmux,
+    pub io1: Pinmux,
+    pub io2: Pinmux,
+    pub io3: Pinmux,
+}
+
+pub struct Qspi {
+    registers: StaticRef<QspiRegisters>,
+    mapped: OptionalCell<&'static [u8]>,
+}
+
+impl Qspi {
+    pub const fn new() -> Qspi {
+        Qspi {
+            registers: QSPI_BASE,
+            mapped: OptionalCell::empty(),
+        }
+    }
+
+    /// Configure the pins and the interface to the external flash, with
+    /// 24-bit addresses. The SCK frequency is the highest frequency up to
+    /// `sck_frequency_hz` the peripheral supports, at most 32 MHz.
+    pub fn configure(&self, pins: QspiPins, read_mode: ReadMode, sck_frequency_hz: u32) {
+        let regs = &*self.registers;
+        regs.intenclr.write(Interrupt::READY::SET);
+
+        regs.psel_sck.set(pins.sck.into());
+        regs.psel_csn.set(pins.csn.into());
+        regs.psel_io0.set(pins.io0.into());
+        regs.psel_io1.set(pins.io1.into());
+        regs.psel_io2.set(pins.io2.into());
+        regs.psel_io3.set(pins.io3.into());
+
+        regs.ifconfig0.write(
+            InterfaceConfig0::READOC.val(read_mode as u32)
+                + InterfaceConfig0::WRITEOC::PP
+                + InterfaceConfig0::ADDRMODE::Bit24,
+        );
+        let divider = BASE_CLOCK_HZ.div_ceil(sck_frequency_hz.max(1)).clamp(1, 16);
+        regs.ifconfig1.write(
+            InterfaceConfig1::SCKDELAY.val(1)
+                + InterfaceConfig1::SPIMODE::Mode0
+                + InterfaceConfig1::SCKFREQ.val(divider - 1),
+        );
+    }
+
+    /// Enable the peripheral and wait until the flash is ready.
+    fn activate(&self) -> Result<(), ErrorCode> {
+        let regs = &*self.registers;
+        regs.enable.write(Enable::ENABLE::Enabled);
+        regs.events_ready.set(0);
+        regs.tasks_activate.write(Task::TRIGGER::SET);
+        for _ in 0..ACTIVATE_TIMEOUT {
+            if regs.events_ready.is_set(Event::READY) {
+                regs.events_ready.set(0);
+                return Ok(());
+            }
+        }
+        regs.tasks_deactivate.write(Task::TRIGGER::SET);
+        regs.enable.write(Enable::ENABLE::Disabled);
+        Err(ErrorCode::FAIL)
+    }
+}
+
+impl ExecuteInPlace for Qspi {
+    fn window_size(&self) -> usize {
+        XIP_WINDOW_SIZE
+    }
+
+    fn map(&self, offset: usize, length: usize) -> Result<&'static [u8], ErrorCode> {
+        if self.mapped.is_some() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if offset % 4 != 0 || length == 0 || length > XIP_WINDOW_SIZE {
+            return Err(ErrorCode::INVAL);
+        }
+        self.registers.xipoffset.set(offset as u32);
+        self.activate()?;
+
+        // Safety: the window is mapped to the flash from now on, and it is
+        // never unmapped, so the region is valid for the lifetime of the
+        // kernel.
+        let region = unsafe { core::slice::from_raw_parts(XIP_BASE as *const u8, length) };
+        self.mapped.set(region);
+        Ok(region)
+    }
+
+    fn mapped_region(&self) -> Option<&'static [u8]> {
+        self.mapped.get()
+    }
+}
@@ -0,0 +1,145 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Arbitration of the radio between BLE and IEEE 802.15.4.
+//!
+//! The nRF52840 has a single radio peripheral, used by both the BLE and the
+//! 802.15.4 drivers. Without arbitration, the two drivers cannot be enabled
+//! together: each one reconfigures the radio under the other.
+//!
+//! With the arbiter, the radio is time-sliced. The 802.15.4 driver owns the
+//! radio by default and listens for frames. When BLE transmits or receives an
+//! advertisement, the arbiter opens a BLE window: the 802.15.4 driver is
+//! suspended, and resumed when the BLE operations complete. Frames sent to
+//! the device during a BLE window are lost.
+//!
+//! The priority decides which requests are denied:
+//!
+//! - A BLE request is always denied while an 802.15.4 frame or
+//!   acknowledgement is being transmitted.
+//! - An 802.15.4 transmission during a BLE window is denied if BLE has the
+//!   priority, which is the default. Otherwise, the BLE operation is cancelled
+//!   and the frame is transmitted.
+//!
+//! Denied and cancelled operations fail with `BUSY` and `CANCEL` in the
+//! callbacks of the drivers, and the [`ArbiterClient`] is notified of every
+//! denied request.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let radio_arbiter = static_init!(
+//!     nrf52840::radio_arbiter::RadioArbiter<'static>,
+//!     nrf52840::radio_arbiter::RadioArbiter::new(
+//!         &nrf52840_peripherals.ieee802154_radio,
+//!         &base_peripherals.ble_radio,
+//!     )
+//! );
+//! radio_arbiter.init();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::utilities::cells::OptionalCell;
+use nrf52::ble_radio::{self, RadioArbitration};
+
+use crate::ieee802154_radio;
+
+/// A user of the radio.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RadioUser {
+    Ble,
+    Ieee802154,
+}
+
+/// Client notified when the arbiter denies the radio.
+pub trait ArbiterClient {
+    /// A request of `user` for the radio was denied.
+    fn window_denied(&self, user: RadioUser);
+}
+
+pub struct RadioArbiter<'a> {
+    ieee802154: &'a ieee802154_radio::Radio<'a>,
+    ble: &'a ble_radio::Radio<'a>,
+    priority: Cell<RadioUser>,
+    /// The radio is used by BLE.
+    ble_window: Cell<bool>,
+    client: OptionalCell<&'a dyn ArbiterClient>,
+}
+
+impl<'a> RadioArbiter<'a> {
+    pub fn new(
+        ieee802154: &'a ieee802154_radio::Radio<'a>,
+        ble: &'a ble_radio::Radio<'a>,
+    ) -> RadioArbiter<'a> {
+        RadioArbiter {
+            ieee802154,
+            ble,
+            priority: Cell::new(RadioUser::Ble),
+            ble_window: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Register the arbiter with the radio drivers.
+    pub fn init(&'a self) {
+        self.ble.set_arbiter(self);
+        self.ieee802154.set_arbiter(self);
+    }
+
+    pub fn set_client(&self, client: &'a dyn ArbiterClient) {
+        self.client.set(client);
+    }
+
+    /// Set the user whose transmissions are not interrupted.
+    pub fn set_priority(&self, priority: RadioUser) {
+        self.priority.set(priority);
+    }
+
+    pub fn get_priority(&self) -> RadioUser {
+        self.priority.get()
+    }
+
+    fn deny(&self, user: RadioUser) -> bool {
+        self.client.map(|client| client.window_denied(user));
+        false
+    }
+
+    /// Request the radio for an 802.15.4 transmission during a BLE window.
+    /// Returns `false` if the request is denied.
+    pub(crate) fn request_ieee802154(&self) -> bool {
+        if !self.ble_window.get() {
+            return true;
+        }
+        if self.priority.get() != RadioUser::Ieee802154 {
+            return self.deny(RadioUser::Ieee802154);
+        }
+        self.ble_window.set(false);
+        self.ble.cancel();
+        self.ieee802154.resume();
+        true
+    }
+}
+
+impl RadioArbitration for RadioArbiter<'_> {
+    fn request_ble(&self) -> bool {
+        if self.ble_window.get() {
+            return true;
+        }
+        if self.ieee802154.is_transmitting() {
+            return self.deny(RadioUser::Ble);
+        }
+        self.ieee802154.suspend();
+        self.ble_window.set(true);
+        true
+    }
+
+    fn release_ble(&self) {
+        if self.ble_window.get() {
+            self.ble_window.set(false);
+            self.ieee802154.resume();
+        }
+    }
+}
@@ -8,15 +8,18 @@
 //! * Philip Levis <pal@cs.stanford.edu>
 //! * Date: August 18, 2016
 
+use core::cell::Cell;
 use core::ops::{Index, IndexMut};
 use enum_primitive::cast::FromPrimitive;
 use enum_primitive::enum_from_primitive;
 use kernel::debug;
 use kernel::hil;
+use kernel::hil::wakeup_source::WakeupEdge;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 #[cfg(feature = "nrf51")]
 const NUM_GPIOTE: usize = 4;
@@ -365,6 +368,11 @@ pub struct GPIOPin<'a> {
     gpiote_registers: StaticRef<GpioteRegisters>,
     gpio_registers: StaticRef<GpioRegisters>,
     allocated_channel: OptionalCell<usize>,
+    wakeup_client: OptionalCell<&'a dyn hil::wakeup_source::WakeupClient>,
+    wakeup_edge: OptionalCell<WakeupEdge>,
+    /// Level of the pin when SENSE was last armed. SENSE detects the
+    /// opposite level.
+    wakeup_level: Cell<bool>,
 }
 
 impl<'a> GPIOPin<'a> {
@@ -381,6 +389,9 @@ impl<'a> GPIOPin<'a> {
             },
             gpiote_registers: GPIOTE_BASE,
             allocated_channel: OptionalCell::empty(),
+            wakeup_client: OptionalCell::empty(),
+            wakeup_edge: OptionalCell::empty(),
+            wakeup_level: Cell::new(false),
         }
     }
 
@@ -562,6 +573,64 @@ impl GPIOPin<'_> {
             client.fired();
         });
     }
+
+    /// Sense the level opposite to the current one, so that the next change
+    /// of the pin raises DETECT.
+    fn arm_sense(&self) {
+        let level = hil::gpio::Input::read(self);
+        self.wakeup_level.set(level);
+        self.gpio_registers.pin_cnf[self.pin as usize].modify(if level {
+            PinConfig::SENSE::Low
+        } else {
+            PinConfig::SENSE::High
+        });
+    }
+
+    /// PORT event: if the pin changed since SENSE was armed, re-arm it and
+    /// notify the client if the change matches the armed edge.
+    fn handle_wakeup(&self) {
+        if let Some(edge) = self.wakeup_edge.get() {
+            let previous = self.wakeup_level.get();
+            self.arm_sense();
+            let level = self.wakeup_level.get();
+            let matched = match edge {
+                WakeupEdge::Rising => !previous && level,
+                WakeupEdge::Falling => previous && !level,
+                WakeupEdge::Either => previous != level,
+            };
+            if matched {
+                self.wakeup_client.map(|client| client.woken());
+            }
+        }
+    }
+}
+
+/// Pins are woken through SENSE and the GPIOTE PORT event rather than a
+/// GPIOTE IN channel, because IN channels keep the high-frequency clock
+/// running during sleep. The PORT event is shared by all pins and only
+/// reports that some sensed pin changed, so the edge is recovered by
+/// comparing the level of each armed pin with its level when SENSE was
+/// armed: pulses shorter than the interrupt latency are missed.
+impl<'a> hil::wakeup_source::WakeupSource<'a> for GPIOPin<'a> {
+    fn enable_wakeup(&self, edge: WakeupEdge) -> Result<(), ErrorCode> {
+        self.wakeup_edge.set(edge);
+        self.arm_sense();
+        self.gpiote_registers.intenset.write(Intenset::PORT::SET);
+        Ok(())
+    }
+
+    fn disable_wakeup(&self) {
+        self.wakeup_edge.clear();
+        self.gpio_registers.pin_cnf[self.pin as usize].modify(PinConfig::SENSE::Disabled);
+    }
+
+    fn is_wakeup_enabled(&self) -> bool {
+        self.wakeup_edge.is_some()
+    }
+
+    fn set_wakeup_client(&self, client: &'a dyn hil::wakeup_source::WakeupClient) {
+        self.wakeup_client.set(client);
+    }
 }
 
 pub struct Port<'a, const N: usize> {
@@ -588,7 +657,8 @@ impl<'a, const N: usize> Port<'a, N> {
     }
 
     /// GPIOTE interrupt: check each GPIOTE channel, if any has
-    /// fired then trigger its corresponding pin's interrupt handler.
+    /// fired then trigger its corresponding pin's interrupt handler. Then
+    /// check the PORT event of the pins armed as wakeup sources.
     pub fn handle_interrupt(&self) {
         // do this just to get a pointer the memory map
         // doesn't matter which pin is used because it is the same
@@ -602,5 +672,16 @@ impl<'a, const N: usize> Port<'a, N> {
                 self.pins[pin].handle_interrupt();
             }
         }
+
+        if pin_registers
+            .event_port
+            .any_matching_bits_set(EventsPort::PINS::Ready)
+        {
+            pin_registers.event_port.write(EventsPort::PINS::NotReady);
+            self.pins.iter().for_each(|pin| pin.handle_wakeup());
+            if !self.pins.iter().any(|pin| pin.wakeup_edge.is_some()) {
+                pin_registers.intenclr.write(Intenclr::PORT::SET);
+            }
+        }
     }
 }
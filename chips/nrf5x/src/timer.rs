@@ -231,6 +231,12 @@ impl Timer {
         self.client.set(client);
     }
 
+    /// Address of the `EVENTS_COMPARE[index]` register, to connect the
+    /// compare event to a task of another peripheral through the PPI.
+    pub fn compare_event_address(&self, index: usize) -> u32 {
+        core::ptr::from_ref(&self.registers.events_compare[index]) as u32
+    }
+
     /// When an interrupt occurs, check if any of the 4 compares have
     /// created an event, and if so, add it to the bitmask of triggered
     /// events that is passed to the client.
@@ -393,6 +393,7 @@ impl hil::uart::Configure for Uart16550<'_> {
             Width::Six => lcr.modify(LCR::DataWordLength::Bits6),
             Width::Seven => lcr.modify(LCR::DataWordLength::Bits7),
             Width::Eight => lcr.modify(LCR::DataWordLength::Bits8),
+            Width::Nine => return Err(ErrorCode::NOSUPPORT),
         };
 
         match params.stop_bits {
@@ -186,7 +186,7 @@ impl<'a> Adc<'a> {
             }
             self.client.map(|client| {
                 self.disable_interrupt();
-                client.sample_ready((self.registers.fifo.read(FIFO::VAL) << 4) as u16)
+                client.sample_ready(self.registers.fifo.read(FIFO::VAL) << 4)
             });
         }
     }
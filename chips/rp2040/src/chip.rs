@@ -35,13 +35,13 @@ pub struct Rp2040<'a, I: InterruptService + 'a> {
     mpu: cortexm0p::mpu::MPU,
     userspace_kernel_boundary: cortexm0p::syscall::SysCall,
     interrupt_service: &'a I,
-    sio: &'a SIO,
+    sio: &'a SIO<'a>,
     processor0_interrupt_mask: (u128, u128),
     processor1_interrupt_mask: (u128, u128),
 }
 
 impl<'a, I: InterruptService> Rp2040<'a, I> {
-    pub unsafe fn new(interrupt_service: &'a I, sio: &'a SIO) -> Self {
+    pub unsafe fn new(interrupt_service: &'a I, sio: &'a SIO<'a>) -> Self {
         Self {
             mpu: cortexm0p::mpu::MPU::new(),
             userspace_kernel_boundary: cortexm0p::syscall::SysCall::new(),
@@ -125,7 +125,7 @@ pub struct Rp2040DefaultPeripherals<'a> {
     pub pins: RPPins<'a>,
     pub pwm: pwm::Pwm<'a>,
     pub resets: Resets,
-    pub sio: SIO,
+    pub sio: SIO<'a>,
     pub spi0: spi::Spi<'a>,
     pub sysinfo: sysinfo::SysInfo,
     pub timer: RPTimer<'a>,
@@ -14,6 +14,7 @@ use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 use crate::chip::Processor;
 #[repr(C)]
@@ -100,8 +101,17 @@ register_structs! {
         /// FIFO read
         (0x058 => fifo_rd: ReadOnly<u32, FIFO_RD::Register>),
 
+        /// Spinlock state, one bit per locked spinlock
+        (0x05c => spinlock_st: ReadOnly<u32>),
+
+        /// Not used
+        (0x060 => _reserved3),
+
+        /// Spinlocks, claimed by reading and released by writing
+        (0x100 => spinlock: [ReadWrite<u32>; 32]),
+
         /// End
-        (0x05c => @END),
+        (0x180 => @END),
     }
 }
 
@@ -648,22 +658,31 @@ impl hil::gpio::Input for RPGpioPin<'_> {
     }
 }
 
-pub struct SIO {
+/// Number of hardware spinlocks.
+const SPINLOCK_COUNT: usize = 32;
+
+pub struct SIO<'a> {
     registers: StaticRef<SIORegisters>,
+    mailbox_client: OptionalCell<&'a dyn hil::hw_mailbox::MailboxClient>,
 }
 
-impl SIO {
+impl<'a> SIO<'a> {
     pub const fn new() -> Self {
         Self {
             registers: SIO_BASE,
+            mailbox_client: OptionalCell::empty(),
         }
     }
 
     pub fn handle_proc_interrupt(&self, for_processor: Processor) {
         match for_processor {
             Processor::Processor0 => {
-                // read data from the fifo
-                self.registers.fifo_rd.get();
+                // The interrupt is pending as long as the fifo holds data.
+                while self.registers.fifo_st.is_set(FIFO_ST::VLD) {
+                    let word = self.registers.fifo_rd.get();
+                    self.mailbox_client.map(|client| client.received(word));
+                }
+                // Clear the overflow and underflow flags.
                 self.registers.fifo_st.set(0xff);
             }
             Processor::Processor1 => {
@@ -685,3 +704,53 @@ impl SIO {
         }
     }
 }
+
+/// The inter-processor fifos, 8 words deep in each direction.
+impl<'a> hil::hw_mailbox::Mailbox<'a> for SIO<'a> {
+    fn send(&self, word: u32) -> Result<(), ErrorCode> {
+        if !self.can_send() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.registers.fifo_wr.set(word);
+        Ok(())
+    }
+
+    fn can_send(&self) -> bool {
+        self.registers.fifo_st.is_set(FIFO_ST::RDY)
+    }
+
+    fn set_client(&self, client: &'a dyn hil::hw_mailbox::MailboxClient) {
+        self.mailbox_client.set(client);
+    }
+}
+
+/// The spinlocks do not record which core holds them, so `unlock` releases
+/// a spinlock held by any core.
+impl hil::hw_mailbox::HwSemaphore for SIO<'_> {
+    fn count(&self) -> usize {
+        SPINLOCK_COUNT
+    }
+
+    fn try_lock(&self, index: usize) -> Result<(), ErrorCode> {
+        let spinlock = self.registers.spinlock.get(index).ok_or(ErrorCode::INVAL)?;
+        // Reading a free spinlock claims it and returns a non-zero value.
+        if spinlock.get() != 0 {
+            Ok(())
+        } else {
+            Err(ErrorCode::BUSY)
+        }
+    }
+
+    fn unlock(&self, index: usize) -> Result<(), ErrorCode> {
+        let spinlock = self.registers.spinlock.get(index).ok_or(ErrorCode::INVAL)?;
+        if !self.is_locked(index) {
+            return Err(ErrorCode::ALREADY);
+        }
+        spinlock.set(1);
+        Ok(())
+    }
+
+    fn is_locked(&self, index: usize) -> bool {
+        index < SPINLOCK_COUNT && self.registers.spinlock_st.get() & (1 << index) != 0
+    }
+}
@@ -626,6 +626,7 @@ impl Configure for Uart<'_> {
             Width::Six => self.registers.uartlcr_h.modify(UARTLCR_H::WLEN::BITS_6),
             Width::Seven => self.registers.uartlcr_h.modify(UARTLCR_H::WLEN::BITS_7),
             Width::Eight => self.registers.uartlcr_h.modify(UARTLCR_H::WLEN::BITS_8),
+            Width::Nine => return Err(ErrorCode::NOSUPPORT),
         }
 
         // Configure parity
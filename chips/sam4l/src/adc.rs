@@ -15,7 +15,8 @@
 //! - are left justified
 //!
 //! Samples can either be collected individually or continuously at a specified
-//! frequency.
+//! frequency. The window monitor can also watch a channel and only report the
+//! first sample outside of a window.
 //!
 //! - Author: Philip Levis <pal@cs.stanford.edu>, Branden Ghena <brghena@umich.edu>
 //! - Updated: May 1, 2017
@@ -89,8 +90,11 @@ pub struct Adc<'a> {
     adc_clk_freq: Cell<u32>,
     active: Cell<bool>,
     continuous: Cell<bool>,
+    window: Cell<bool>,
     dma_running: Cell<bool>,
     cpu_clock: Cell<bool>,
+    // achieved frequency of the last continuous sampling
+    sampling_freq: OptionalCell<u32>,
 
     // timer fire counting for slow sampling rates
     timer_repeats: Cell<u8>,
@@ -317,6 +321,9 @@ register_bitfields![u32,
     ]
 ];
 
+/// Rate at which the window monitor samples a channel, in Hz.
+const WINDOW_SAMPLING_FREQUENCY: u32 = 100;
+
 // Page 59 of SAM4L data sheet
 const BASE_ADDRESS: StaticRef<AdcRegisters> =
     unsafe { StaticRef::new(0x40038000 as *const AdcRegisters) };
@@ -336,8 +343,10 @@ impl<'a> Adc<'a> {
             adc_clk_freq: Cell::new(0),
             active: Cell::new(false),
             continuous: Cell::new(false),
+            window: Cell::new(false),
             dma_running: Cell::new(false),
             cpu_clock: Cell::new(false),
+            sampling_freq: OptionalCell::empty(),
 
             // timer repeating state for slow sampling rates
             timer_repeats: Cell::new(0),
@@ -370,7 +379,19 @@ impl<'a> Adc<'a> {
         let status = self.registers.sr.is_set(Status::SEOC);
 
         if self.enabled.get() && self.active.get() {
-            if status {
+            if self.window.get() {
+                // only samples outside of the window are reported
+                if self.registers.sr.is_set(Status::WM) {
+                    let val = self.registers.lcv.read(SequencerLastConvertedValue::LCV);
+                    let _ = hil::adc::Adc::stop_sampling(self);
+                    self.client.map(|client| {
+                        client.sample_ready(val);
+                    });
+                }
+                self.registers
+                    .scr
+                    .write(Interrupt::WM::SET + Interrupt::SEOC::SET);
+            } else if status {
                 // sample complete interrupt
 
                 // should we deal with this sample now, or wait for the next
@@ -379,7 +400,7 @@ impl<'a> Adc<'a> {
                     // we actually care about this sample
 
                     // single sample complete. Send value to client
-                    let val = self.registers.lcv.read(SequencerLastConvertedValue::LCV) as u16;
+                    let val = self.registers.lcv.read(SequencerLastConvertedValue::LCV);
                     self.client.map(|client| {
                         client.sample_ready(val);
                     });
@@ -720,10 +741,19 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
                 self.registers
                     .itimer
                     .write(InternalTimer::ITMC.val(counter));
+
+                // a sample is reported every `timer_repeats + 1` timer fires
+                self.sampling_freq.set(
+                    self.adc_clk_freq.get() / (counter + 1) / (self.timer_repeats.get() as u32 + 1),
+                );
             } else {
                 // we can sample at this frequency directly with the timer
                 self.timer_repeats.set(0);
                 self.timer_counts.set(0);
+
+                // in continuous mode, the ADC clock was chosen to sample at
+                // the requested rate
+                self.sampling_freq.set(self.adc_clk_freq.get());
             }
 
             // clear any current status
@@ -753,13 +783,19 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
             // clean up state
             self.active.set(false);
             self.continuous.set(false);
+            self.window.set(false);
             self.dma_running.set(false);
 
             // stop internal timer
             self.registers.cr.write(Control::TSTOP::SET);
 
-            // disable sample interrupts
-            self.registers.idr.write(Interrupt::SEOC::SET);
+            // disable sample and window monitor interrupts
+            self.registers
+                .idr
+                .write(Interrupt::SEOC::SET + Interrupt::WM::SET);
+            self.registers
+                .wcfg
+                .write(WindowMonitorConfiguration::WM.val(0));
 
             // reset the ADC peripheral
             self.registers.cr.write(Control::SWRST::SET);
@@ -792,6 +828,38 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
         }
     }
 
+    /// Watch a channel with the window monitor. The channel is sampled
+    /// continuously at `WINDOW_SAMPLING_FREQUENCY` Hz, but only the first
+    /// sample outside of the window raises an interrupt.
+    ///
+    /// - `channel`: the ADC channel to watch
+    /// - `low`: lowest sample inside the window
+    /// - `high`: highest sample inside the window
+    fn sample_window(&self, channel: &Self::Channel, low: u32, high: u32) -> Result<(), ErrorCode> {
+        if low > high || high > u16::MAX as u32 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.sample_continuous(channel, WINDOW_SAMPLING_FREQUENCY)?;
+        self.window.set(true);
+
+        // the thresholds are compared to the 12-bit result, before it is
+        // left justified; mode 4 flags results outside of [LT, HT]
+        self.registers.wth.write(
+            WindowMonitorThresholdConfiguration::LT.val(low >> 4)
+                + WindowMonitorThresholdConfiguration::HT.val(high >> 4),
+        );
+        self.registers
+            .wcfg
+            .write(WindowMonitorConfiguration::WM.val(4));
+
+        // only interrupt on samples outside of the window
+        self.registers.idr.write(Interrupt::SEOC::SET);
+        self.registers.scr.write(Interrupt::WM::SET);
+        self.registers.ier.write(Interrupt::WM::SET);
+
+        Ok(())
+    }
+
     /// Resolution of the reading.
     fn get_resolution_bits(&self) -> usize {
         12
@@ -889,6 +957,10 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
                 self.registers
                     .itimer
                     .write(InternalTimer::ITMC.val(counter));
+                self.sampling_freq
+                    .set(self.adc_clk_freq.get() / (counter + 1));
+            } else {
+                self.sampling_freq.set(self.adc_clk_freq.get());
             }
 
             // clear any current status
@@ -967,6 +1039,12 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
         }
     }
 
+    /// Achieved sampling frequency, after rounding of the ADC clock prescaler
+    /// and of the internal timer.
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        self.sampling_freq.get()
+    }
+
     fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
         self.highspeed_client.set(client);
     }
@@ -15,7 +15,6 @@
 use crate::pm;
 use crate::scif;
 use core::cell::Cell;
-use kernel::debug;
 use kernel::hil;
 use kernel::hil::symmetric_encryption::{AES128_BLOCK_SIZE, AES128_KEY_SIZE};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -285,7 +284,7 @@ impl<'a> Aes<'a> {
                 // The source and destination are the same buffer
                 self.dest.map_or_else(
                     || {
-                        debug!("Called write_block() with no data");
+                        kernel::log_warn!(Crypto, "AES: called write_block() with no data");
                         false
                     },
                     |dest| {
@@ -339,7 +338,7 @@ impl<'a> Aes<'a> {
     fn read_block(&self) -> bool {
         self.dest.map_or_else(
             || {
-                debug!("Called read_block() with no data");
+                kernel::log_warn!(Crypto, "AES: called read_block() with no data");
                 false
             },
             |dest| {
@@ -497,6 +497,7 @@ enum ADCStatus {
     PoweringOn,
     OneSample,
     Continuous,
+    Calibrating,
 }
 
 pub struct Adc<'a> {
@@ -551,6 +552,14 @@ impl<'a> Adc<'a> {
             cortexm4::support::nop()
         }
 
+        self.calibrate_and_enable();
+        // Enable overrun to overwrite old datas
+        self.registers.cfgr.modify(CFGR::OVRMOD::SET);
+    }
+
+    /// Calibrate the disabled ADC, then enable it. The ADC ready interrupt
+    /// signals the end of the sequence.
+    fn calibrate_and_enable(&self) {
         // Enable ADC Ready interrupt
         self.registers.ier.modify(IER::ADRDYIE::SET);
 
@@ -565,8 +574,6 @@ impl<'a> Adc<'a> {
 
         // Enable ADC
         self.registers.cr.modify(CR::ADEN::SET);
-        // Enable overrun to overwrite old datas
-        self.registers.cfgr.modify(CFGR::OVRMOD::SET);
     }
 
     pub fn handle_interrupt(&self) {
@@ -582,6 +589,11 @@ impl<'a> Adc<'a> {
                         let _ = self.sample_u32(self.requested_channel.get());
                         return;
                     }
+                    ADCStatus::Calibrating => {
+                        self.requested.set(ADCStatus::Idle);
+                        self.client.map(|client| client.calibration_done(Ok(())));
+                        return;
+                    }
                     _ => {}
                 }
             }
@@ -592,7 +604,7 @@ impl<'a> Adc<'a> {
             self.registers.ier.modify(IER::EOCIE::CLEAR);
             let data = self.registers.dr.read(DR::RDATA);
             self.client
-                .map(|client| client.sample_ready((data as u16) << 4));
+                .map(|client| client.sample_ready((data as u16 as u32) << 4));
             if self.status.get() == ADCStatus::Continuous {
                 self.registers.ier.modify(IER::EOCIE::SET);
             }
@@ -723,6 +735,28 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
         Some(3300)
     }
 
+    /// The ADC is disabled during the calibration, and enabled again after
+    /// it.
+    fn calibrate(&self) -> Result<(), ErrorCode> {
+        match self.status.get() {
+            ADCStatus::Off => {
+                // Enabling the ADC calibrates it.
+                self.requested.set(ADCStatus::Calibrating);
+                self.enable();
+                Ok(())
+            }
+            ADCStatus::Idle => {
+                self.status.set(ADCStatus::PoweringOn);
+                self.requested.set(ADCStatus::Calibrating);
+                self.registers.cr.modify(CR::ADDIS::SET);
+                while self.registers.cr.is_set(CR::ADEN) {}
+                self.calibrate_and_enable();
+                Ok(())
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
     fn set_client(&self, client: &'a dyn hil::adc::Client) {
         self.client.set(client);
     }
@@ -776,5 +810,9 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
         Err(ErrorCode::NOSUPPORT)
     }
 
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        None
+    }
+
     fn set_highspeed_client(&self, _client: &'a dyn hil::adc::HighSpeedClient) {}
 }
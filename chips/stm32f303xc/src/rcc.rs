@@ -2,9 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+use cortexm4::support::atomic;
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
-use kernel::utilities::registers::{register_bitfields, ReadWrite};
+use kernel::utilities::registers::{register_bitfields, FieldValue, ReadWrite, RegisterLongName};
 use kernel::utilities::StaticRef;
 
 /// Reset and clock control
@@ -397,6 +398,18 @@ impl Rcc {
         }
     }
 
+    // Read-modify-write a register with interrupts disabled. The enable,
+    // reset and configuration registers are shared by the drivers of many
+    // peripherals: if an interrupt handler modified the same register between
+    // the read and the write, its update would be lost.
+    fn modify_shared<R: RegisterLongName>(
+        &self,
+        register: &ReadWrite<u32, R>,
+        field: FieldValue<u32, R>,
+    ) {
+        unsafe { atomic(|| register.modify(field)) }
+    }
+
     // TIM2 clock
 
     fn is_enabled_tim2_clock(&self) -> bool {
@@ -404,11 +417,11 @@ impl Rcc {
     }
 
     fn enable_tim2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::TIM2EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM2EN::SET)
     }
 
     fn disable_tim2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::TIM2EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM2EN::CLEAR)
     }
 
     // SYSCFG clock
@@ -418,11 +431,11 @@ impl Rcc {
     }
 
     fn enable_syscfg_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::SYSCFGEN::SET)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SYSCFGEN::SET)
     }
 
     fn disable_syscfg_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::SYSCFGEN::CLEAR)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SYSCFGEN::CLEAR)
     }
 
     // DMA1 clock
@@ -432,11 +445,11 @@ impl Rcc {
     }
 
     fn enable_dma1_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::DMA1EN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::DMA1EN::SET)
     }
 
     fn disable_dma1_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::DMA1EN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::DMA1EN::CLEAR)
     }
 
     // GPIOF clock
@@ -446,11 +459,11 @@ impl Rcc {
     }
 
     fn enable_gpiof_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPFEN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPFEN::SET)
     }
 
     fn disable_gpiof_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPFEN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPFEN::CLEAR)
     }
 
     // GPIOE clock
@@ -460,11 +473,11 @@ impl Rcc {
     }
 
     fn enable_gpioe_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPEEN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPEEN::SET)
     }
 
     fn disable_gpioe_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPEEN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPEEN::CLEAR)
     }
 
     // GPIOD clock
@@ -474,11 +487,11 @@ impl Rcc {
     }
 
     fn enable_gpiod_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPDEN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPDEN::SET)
     }
 
     fn disable_gpiod_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPDEN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPDEN::CLEAR)
     }
 
     // GPIOC clock
@@ -488,11 +501,11 @@ impl Rcc {
     }
 
     fn enable_gpioc_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPCEN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPCEN::SET)
     }
 
     fn disable_gpioc_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPCEN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPCEN::CLEAR)
     }
 
     // GPIOB clock
@@ -502,11 +515,11 @@ impl Rcc {
     }
 
     fn enable_gpiob_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPBEN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPBEN::SET)
     }
 
     fn disable_gpiob_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPBEN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPBEN::CLEAR)
     }
 
     // GPIOA clock
@@ -516,11 +529,11 @@ impl Rcc {
     }
 
     fn enable_gpioa_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPAEN::SET)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPAEN::SET)
     }
 
     fn disable_gpioa_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::IOPAEN::CLEAR)
+        self.modify_shared(&self.registers.ahbenr, AHBENR::IOPAEN::CLEAR)
     }
 
     // USART1 clock
@@ -530,11 +543,11 @@ impl Rcc {
     }
 
     fn enable_usart1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::USART1EN::SET)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::USART1EN::SET)
     }
 
     fn disable_usart1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::USART1EN::CLEAR)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::USART1EN::CLEAR)
     }
 
     // USART2 clock
@@ -544,11 +557,11 @@ impl Rcc {
     }
 
     fn enable_usart2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART2EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART2EN::SET)
     }
 
     fn disable_usart2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART2EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART2EN::CLEAR)
     }
 
     // USART3 clock
@@ -558,11 +571,11 @@ impl Rcc {
     }
 
     fn enable_usart3_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART3EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART3EN::SET)
     }
 
     fn disable_usart3_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART3EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART3EN::CLEAR)
     }
 
     // SPI1 clock
@@ -572,11 +585,11 @@ impl Rcc {
     }
 
     fn enable_spi1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::SPI1EN::SET)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SPI1EN::SET)
     }
 
     fn disable_spi1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::SPI1EN::CLEAR)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SPI1EN::CLEAR)
     }
 
     // I2C1 clock
@@ -586,16 +599,16 @@ impl Rcc {
     }
 
     fn enable_i2c1_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::I2C1EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::I2C1EN::SET)
     }
 
     fn disable_i2c1_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::I2C1EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::I2C1EN::CLEAR)
     }
 
     fn reset_i2c1(&self) {
-        self.registers.apb1rstr.modify(APB1RSTR::I2C1RST::SET);
-        self.registers.apb1rstr.modify(APB1RSTR::I2C1RST::CLEAR);
+        self.modify_shared(&self.registers.apb1rstr, APB1RSTR::I2C1RST::SET);
+        self.modify_shared(&self.registers.apb1rstr, APB1RSTR::I2C1RST::CLEAR);
     }
 
     // ADC12 clock
@@ -605,13 +618,13 @@ impl Rcc {
     }
 
     fn enable_adc12_clock(&self) {
-        self.registers.cfgr.modify(CFGR::HPRE.val(0b0000));
-        self.registers.cfgr2.modify(CFGR2::ADC12PRES.val(0b10111));
-        self.registers.ahbenr.modify(AHBENR::ADC12EN::SET);
+        self.modify_shared(&self.registers.cfgr, CFGR::HPRE.val(0b0000));
+        self.modify_shared(&self.registers.cfgr2, CFGR2::ADC12PRES.val(0b10111));
+        self.modify_shared(&self.registers.ahbenr, AHBENR::ADC12EN::SET);
     }
 
     fn disable_adc12_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::ADC12EN::CLEAR);
+        self.modify_shared(&self.registers.ahbenr, AHBENR::ADC12EN::CLEAR);
     }
 
     // ADC34 clock
@@ -620,11 +633,11 @@ impl Rcc {
     }
 
     fn enable_adc34_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::ADC34EN::SET);
+        self.modify_shared(&self.registers.ahbenr, AHBENR::ADC34EN::SET);
     }
 
     fn disable_adc34_clock(&self) {
-        self.registers.ahbenr.modify(AHBENR::ADC34EN::CLEAR);
+        self.modify_shared(&self.registers.ahbenr, AHBENR::ADC34EN::CLEAR);
     }
 
     // WWDG clock
@@ -634,11 +647,11 @@ impl Rcc {
     }
 
     fn enable_wwdg_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::WWDGEN::SET);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::WWDGEN::SET);
     }
 
     fn disable_wwdg_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::WWDGEN::CLEAR);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::WWDGEN::CLEAR);
     }
 }
 
@@ -0,0 +1,11 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! DMA2D
+
+use kernel::utilities::StaticRef;
+use stm32f4xx::dma2d::Dma2dRegisters;
+
+pub(crate) const DMA2D_BASE: StaticRef<Dma2dRegisters> =
+    unsafe { StaticRef::new(0x4002_B000 as *const Dma2dRegisters) };
@@ -0,0 +1,11 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FMC SDRAM controller
+
+use kernel::utilities::StaticRef;
+use stm32f4xx::fmc_sdram::FmcSdramRegisters;
+
+pub(crate) const FMC_SDRAM_BASE: StaticRef<FmcSdramRegisters> =
+    unsafe { StaticRef::new(0xA000_0140 as *const FmcSdramRegisters) };
@@ -5,7 +5,10 @@
 use crate::chip_specs::Stm32f429Specs;
 use stm32f4xx::chip::Stm32f4xxDefaultPeripherals;
 
-use crate::{can_registers, stm32f429zi_nvic, trng_registers};
+use crate::{
+    can_registers, dma2d_registers, fmc_sdram_registers, ltdc_registers, sai_registers,
+    stm32f429zi_nvic, trng_registers,
+};
 
 pub struct Stm32f429ziDefaultPeripherals<'a> {
     pub stm32f4: Stm32f4xxDefaultPeripherals<'a, Stm32f429Specs>,
@@ -13,6 +16,13 @@ pub struct Stm32f429ziDefaultPeripherals<'a> {
     pub trng: stm32f4xx::trng::Trng<'a>,
     pub can1: stm32f4xx::can::Can<'a>,
     pub rtc: crate::rtc::Rtc<'a>,
+    pub dma2d: stm32f4xx::dma2d::Dma2d<'a>,
+    pub fmc_sdram: stm32f4xx::fmc_sdram::FmcSdram<'a>,
+    pub ltdc: stm32f4xx::ltdc::Ltdc<'a>,
+    pub sai1: stm32f4xx::sai::Sai<'a>,
+    pub tim12: stm32f4xx::tim::Tim<'a>,
+    pub tim13: stm32f4xx::tim::Tim<'a>,
+    pub tim14: stm32f4xx::tim::Tim<'a>,
 }
 
 impl<'a> Stm32f429ziDefaultPeripherals<'a> {
@@ -27,6 +37,16 @@ impl<'a> Stm32f429ziDefaultPeripherals<'a> {
             trng: stm32f4xx::trng::Trng::new(trng_registers::RNG_BASE, clocks),
             can1: stm32f4xx::can::Can::new(clocks, can_registers::CAN1_BASE),
             rtc: crate::rtc::Rtc::new(clocks),
+            dma2d: stm32f4xx::dma2d::Dma2d::new(dma2d_registers::DMA2D_BASE, clocks),
+            fmc_sdram: stm32f4xx::fmc_sdram::FmcSdram::new(
+                fmc_sdram_registers::FMC_SDRAM_BASE,
+                clocks,
+            ),
+            ltdc: stm32f4xx::ltdc::Ltdc::new(ltdc_registers::LTDC_BASE, clocks),
+            sai1: stm32f4xx::sai::Sai::new(sai_registers::SAI1_BASE, clocks),
+            tim12: stm32f4xx::tim::Tim::new_tim12(clocks),
+            tim13: stm32f4xx::tim::Tim::new_tim13(clocks),
+            tim14: stm32f4xx::tim::Tim::new_tim14(clocks),
         }
     }
     // Necessary for setting up circular dependencies and registering deferred calls
@@ -34,6 +54,7 @@ impl<'a> Stm32f429ziDefaultPeripherals<'a> {
         self.stm32f4.setup_circular_deps();
         kernel::deferred_call::DeferredCallClient::register(&self.can1);
         kernel::deferred_call::DeferredCallClient::register(&self.rtc);
+        kernel::deferred_call::DeferredCallClient::register(&self.ltdc);
     }
 }
 impl<'a> kernel::platform::chip::InterruptService for Stm32f429ziDefaultPeripherals<'a> {
@@ -44,6 +65,30 @@ impl<'a> kernel::platform::chip::InterruptService for Stm32f429ziDefaultPeripher
                 self.trng.handle_interrupt();
                 true
             }
+            stm32f429zi_nvic::LTDC | stm32f429zi_nvic::LTDCE => {
+                self.ltdc.handle_interrupt();
+                true
+            }
+            stm32f4xx::nvic::SAI1 => {
+                self.sai1.handle_interrupt();
+                true
+            }
+            stm32f429zi_nvic::DMA2D => {
+                self.dma2d.handle_interrupt();
+                true
+            }
+            stm32f4xx::nvic::TIM8_BRK_TIM12 => {
+                self.tim12.handle_interrupt();
+                true
+            }
+            stm32f4xx::nvic::TIM8_UP_TIM13 => {
+                self.tim13.handle_interrupt();
+                true
+            }
+            stm32f4xx::nvic::TIM8_TRG_COM_TIM14 => {
+                self.tim14.handle_interrupt();
+                true
+            }
             stm32f4xx::nvic::CAN1_TX => {
                 self.can1.handle_transmit_interrupt();
                 true
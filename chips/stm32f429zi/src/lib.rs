@@ -7,13 +7,17 @@
 use cortexm4::{CortexM4, CortexMVariant};
 
 pub use stm32f4xx::{
-    adc, can, chip, clocks, dac, dbg, dma, exti, flash, gpio, nvic, rcc, spi, syscfg, tim2, trng,
-    usart,
+    adc, can, chip, clocks, dac, dbg, dma, dma2d, exti, flash, fmc_sdram, gpio, ltdc, nvic, rcc,
+    sai, spi, syscfg, tim2, trng, usart,
 };
 
 pub mod can_registers;
 pub mod chip_specs;
+pub mod dma2d_registers;
+pub mod fmc_sdram_registers;
 pub mod interrupt_service;
+pub mod ltdc_registers;
+pub mod sai_registers;
 pub mod stm32f429zi_nvic;
 pub mod trng_registers;
 
@@ -0,0 +1,11 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! LTDC
+
+use kernel::utilities::StaticRef;
+use stm32f4xx::ltdc::LtdcRegisters;
+
+pub(crate) const LTDC_BASE: StaticRef<LtdcRegisters> =
+    unsafe { StaticRef::new(0x4001_6800 as *const LtdcRegisters) };
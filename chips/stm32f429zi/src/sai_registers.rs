@@ -0,0 +1,11 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SAI
+
+use kernel::utilities::StaticRef;
+use stm32f4xx::sai::SaiRegisters;
+
+pub(crate) const SAI1_BASE: StaticRef<SaiRegisters> =
+    unsafe { StaticRef::new(0x4001_5800 as *const SaiRegisters) };
@@ -3,11 +3,13 @@
 // Copyright Tock Contributors 2022.
 
 use crate::clocks::{phclk, Stm32f4Clocks};
+use crate::dma;
 use core::cell::Cell;
+use core::{cmp, slice};
 use kernel::hil;
 use kernel::platform::chip::ClockInterface;
-use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
@@ -87,7 +89,7 @@ register_bitfields![u32,
         /// Interrupt enable for EOC
         EOCIE OFFSET(5) NUMBITS(1) [],
         /// Analog watchdog channel select bits
-        AWDCH OFFSET(0) NUMBITS(4) []
+        AWDCH OFFSET(0) NUMBITS(5) []
     ],
     /// Control register 2
     CR2 [
@@ -216,11 +218,11 @@ register_bitfields![u32,
         /// 4th conversion in injected sequence
         JSQ4 OFFSET(15) NUMBITS(5) [],
         /// 3rd conversion in injected sequence
-        JSQ3 OFFSET(15) NUMBITS(5) [],
+        JSQ3 OFFSET(10) NUMBITS(5) [],
         /// 2nd conversion in injected sequence
-        JSQ2 OFFSET(15) NUMBITS(5) [],
+        JSQ2 OFFSET(5) NUMBITS(5) [],
         /// 1st conversion in injected sequence
-        JSQ1 OFFSET(15) NUMBITS(5) []
+        JSQ1 OFFSET(0) NUMBITS(5) []
     ],
     /// Injected data register x
     JDR [
@@ -264,6 +266,11 @@ const ADC1_BASE: StaticRef<AdcRegisters> =
 const ADC_COMMON_BASE: StaticRef<AdcCommonRegisters> =
     unsafe { StaticRef::new(0x4001_2300 as *const AdcCommonRegisters) };
 
+/// Address of the regular data register, read by the DMA.
+pub(crate) fn get_address_dr() -> u32 {
+    core::ptr::addr_of!(ADC1_BASE.dr) as u32
+}
+
 #[allow(dead_code)]
 #[repr(u32)]
 #[derive(Copy, Clone, PartialEq)]
@@ -287,9 +294,68 @@ pub enum Channel {
     Channel16 = 0b10000,
     Channel17 = 0b10001,
     Channel18 = 0b10010,
+    /// Internal reference voltage (VREFINT), on input 17.
+    Vrefint,
+    /// Internal temperature sensor, on input 18 with VBAT disabled.
+    Temperature,
+    /// Backup battery voltage divided by 4 (VBAT/4), on input 18.
+    Vbat,
+}
+
+impl Channel {
+    /// The ADC input the channel is connected to.
+    fn input(&self) -> u32 {
+        match self {
+            Channel::Vrefint => Channel::Channel17 as u32,
+            Channel::Temperature | Channel::Vbat => Channel::Channel18 as u32,
+            channel => *channel as u32,
+        }
+    }
+
+    /// Whether the channel measures one of the internal sources.
+    fn is_internal(&self) -> bool {
+        self.input() >= Channel::Channel16 as u32
+    }
+}
+
+/// Hardware events that can start the conversion of the injected group.
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq)]
+pub enum Trigger {
+    Tim1Cc4 = 0b0000,
+    Tim1Trgo = 0b0001,
+    Tim2Cc1 = 0b0010,
+    Tim2Trgo = 0b0011,
+    Tim3Cc2 = 0b0100,
+    Tim3Cc4 = 0b0101,
+    Tim4Cc1 = 0b0110,
+    Tim4Cc2 = 0b0111,
+    Tim4Cc3 = 0b1000,
+    Tim4Trgo = 0b1001,
+    Tim5Cc4 = 0b1010,
+    Tim5Trgo = 0b1011,
+    Tim8Cc2 = 0b1100,
+    Tim8Cc3 = 0b1101,
+    Tim8Cc4 = 0b1110,
+    Exti15 = 0b1111,
 }
 
+/// The injected group holds up to 4 conversions.
+const MAX_GROUP_LEN: usize = 4;
+
+/// Dividers of PCLK2 selectable for the ADC clock, and the fastest ADC clock.
+const ADC_PRESCALERS: [usize; 4] = [2, 4, 6, 8];
+const MAX_ADC_CLOCK_HZ: usize = 36_000_000;
+
+/// Selectable sampling times, in ADC clock cycles. A conversion takes the
+/// sampling time plus one cycle per bit of resolution.
+const SAMPLING_CYCLES: [usize; 8] = [3, 15, 28, 56, 84, 112, 144, 480];
+
+/// Startup time of the temperature sensor and VREFINT after TSVREFE is set.
+const TSVREFE_STARTUP_US: usize = 10;
+
 #[allow(dead_code)]
+#[derive(Copy, Clone)]
 #[repr(u32)]
 enum DataResolution {
     Bit12 = 0b00,
@@ -298,58 +364,150 @@ enum DataResolution {
     Bit6 = 0b11,
 }
 
+impl DataResolution {
+    fn bits(self) -> usize {
+        match self {
+            DataResolution::Bit12 => 12,
+            DataResolution::Bit10 => 10,
+            DataResolution::Bit8 => 8,
+            DataResolution::Bit6 => 6,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 enum ADCStatus {
     Idle,
     Off,
     OneSample,
+    Triggered,
+    Window,
+    HighSpeed,
 }
 
 pub struct Adc<'a> {
     registers: StaticRef<AdcRegisters>,
     common_registers: StaticRef<AdcCommonRegisters>,
+    clocks: &'a dyn Stm32f4Clocks,
     clock: AdcClock<'a>,
     status: Cell<ADCStatus>,
     client: OptionalCell<&'a dyn hil::adc::Client>,
+    triggered_client: OptionalCell<&'a dyn hil::adc::TriggeredClient>,
+    /// Number of channels in the injected group.
+    group_len: Cell<usize>,
+    resolution: Cell<DataResolution>,
+
+    // High-speed sampling
+    dma: OptionalCell<&'a dma::Stream<'a, dma::Dma2<'a>>>,
+    highspeed_client: OptionalCell<&'a dyn hil::adc::HighSpeedClient>,
+    /// Number of samples of the running DMA transfer, 0 if none is running.
+    dma_length: Cell<usize>,
+    next_buffer: TakeCell<'static, [u16]>,
+    next_length: Cell<usize>,
+    /// Buffer of the transfer aborted by `stop_sampling`.
+    stopped_buffer: TakeCell<'static, [u16]>,
+    sampling_frequency: OptionalCell<u32>,
 }
 
 impl<'a> Adc<'a> {
-    pub const fn new(clocks: &'a dyn Stm32f4Clocks) -> Adc {
+    pub const fn new(clocks: &'a dyn Stm32f4Clocks) -> Adc<'a> {
         Adc {
             registers: ADC1_BASE,
             common_registers: ADC_COMMON_BASE,
+            clocks,
             clock: AdcClock(phclk::PeripheralClock::new(
                 phclk::PeripheralClockType::APB2(phclk::PCLK2::ADC1),
                 clocks,
             )),
             status: Cell::new(ADCStatus::Off),
             client: OptionalCell::empty(),
+            triggered_client: OptionalCell::empty(),
+            group_len: Cell::new(0),
+            resolution: Cell::new(DataResolution::Bit12),
+            dma: OptionalCell::empty(),
+            highspeed_client: OptionalCell::empty(),
+            dma_length: Cell::new(0),
+            next_buffer: TakeCell::empty(),
+            next_length: Cell::new(0),
+            stopped_buffer: TakeCell::empty(),
+            sampling_frequency: OptionalCell::empty(),
         }
     }
 
+    /// Set the DMA stream used for high-speed sampling. The board must also
+    /// set the ADC as the client of the stream, and set the stream up for
+    /// `dma::Dma2Peripheral::ADC1`.
+    pub fn set_dma(&self, dma: &'a dma::Stream<'a, dma::Dma2<'a>>) {
+        self.dma.set(dma);
+    }
+
     pub fn enable(&self) {
         // Enable adc clock
         self.enable_clock();
 
         // Enable ADC
         self.registers.cr2.modify(CR2::ADON::SET);
+        self.registers
+            .cr1
+            .modify(CR1::RES.val(self.resolution.get() as u32));
 
         // set idle state
         self.status.set(ADCStatus::Idle);
     }
 
     pub fn handle_interrupt(&self) {
-        // Check if regular group conversion ended
-        if self.registers.sr.is_set(SR::EOC) {
+        // Check if the analog watchdog saw a sample outside of the window
+        if self.registers.sr.is_set(SR::AWD) && self.status.get() == ADCStatus::Window {
+            // Reading the data register also clears EOC
+            let sample = ((self.registers.dr.read(DR::DATA) as u16) << self.sample_shift()) as u32;
+            self.stop_window();
+            self.client.map(|client| client.sample_ready(sample));
+        }
+
+        // Check if regular group conversion ended. While the watchdog is
+        // armed, conversions only matter if they leave the window, and
+        // high-speed samples are read by the DMA.
+        if self.registers.sr.is_set(SR::EOC)
+            && self.status.get() != ADCStatus::Window
+            && self.status.get() != ADCStatus::HighSpeed
+        {
             // Clear interrupt
             self.registers.cr1.modify(CR1::EOCIE::CLEAR);
+            // Disconnect VBAT, if it was being measured
+            self.common_registers.ccr.modify(CCR::VBATE::CLEAR);
             if self.status.get() == ADCStatus::OneSample {
                 // set state
                 self.status.set(ADCStatus::Idle);
             }
-            self.client
-                .map(|client| client.sample_ready((self.registers.dr.read(DR::DATA) as u16) << 4));
+            let sample = ((self.registers.dr.read(DR::DATA) as u16) << self.sample_shift()) as u32;
+            self.client.map(|client| client.sample_ready(sample));
         }
+
+        // Check if the triggered injected group conversion ended
+        if self.registers.sr.is_set(SR::JEOC) {
+            self.registers.sr.modify(SR::JEOC::CLEAR + SR::JSTRT::CLEAR);
+            if self.status.get() == ADCStatus::Triggered {
+                let data = [
+                    &self.registers.jdr1,
+                    &self.registers.jdr2,
+                    &self.registers.jdr3,
+                    &self.registers.jdr4,
+                ];
+                let mut samples = [0; MAX_GROUP_LEN];
+                let len = self.group_len.get();
+                let shift = self.sample_shift();
+                for (sample, jdr) in samples.iter_mut().zip(data.iter()).take(len) {
+                    *sample = (jdr.read(JDR::JDATA) as u16) << shift;
+                }
+                self.triggered_client
+                    .map(|client| client.samples_triggered(&samples[..len]));
+            }
+        }
+    }
+
+    /// Left shift of the right-aligned samples to the MSB of the u16.
+    fn sample_shift(&self) -> usize {
+        16 - self.resolution.get().bits()
     }
 
     pub fn is_enabled_clock(&self) -> bool {
@@ -364,8 +522,147 @@ impl<'a> Adc<'a> {
         self.clock.disable();
     }
 
+    /// Power the temperature sensor and VREFINT. The first time, wait for
+    /// their startup time before returning, as conversions started earlier
+    /// read garbage.
     pub fn enable_temperature(&self) {
+        if self.common_registers.ccr.is_set(CCR::TSVREFE) {
+            return;
+        }
         self.common_registers.ccr.modify(CCR::TSVREFE::SET);
+
+        // A loop iteration takes at least one cycle of the core clock.
+        let cycles = self.clocks.get_ahb_frequency() / 1_000_000 * TSVREFE_STARTUP_US;
+        for _ in 0..cycles {
+            cortexm4::support::nop();
+        }
+    }
+
+    /// Connect the internal source measured by `channel` to its ADC input.
+    ///
+    /// VBAT and the temperature sensor share input 18: VBAT is measured when
+    /// VBATE is set, so it is only enabled for the time of a VBAT conversion
+    /// (this also avoids draining the battery through the bridge divider).
+    fn select_internal_source(&self, channel: &Channel) {
+        match channel {
+            Channel::Vbat => self.common_registers.ccr.modify(CCR::VBATE::SET),
+            Channel::Channel18 | Channel::Temperature | Channel::Vrefint => {
+                self.common_registers.ccr.modify(CCR::VBATE::CLEAR);
+                self.enable_temperature();
+            }
+            // On STM32F40x/41x the temperature sensor is on input 16.
+            Channel::Channel16 | Channel::Channel17 => self.enable_temperature(),
+            _ => {}
+        }
+
+        // The internal sources need a sampling time of at least 10 us, use
+        // the longest one (480 cycles).
+        self.registers
+            .smpr1
+            .modify(SMPR1::SMP16.val(0b111) + SMPR1::SMP17.val(0b111) + SMPR1::SMP18.val(0b111));
+    }
+
+    /// The ADC prescaler and sampling time whose conversion rate is the
+    /// closest to `frequency`, with that rate, or `None` if `frequency` is
+    /// out of the range of the ADC.
+    fn highspeed_configuration(&self, frequency: u32) -> Option<(u32, u32, u32)> {
+        let apb2_prescaler: usize = self.clocks.get_rcc().get_apb2_prescaler().into();
+        let pclk2 = self.clocks.get_ahb_frequency() / apb2_prescaler;
+        let frequency = frequency as usize;
+        let conversion_cycles = self.resolution.get().bits();
+
+        let mut best: Option<(u32, u32, usize)> = None;
+        let (mut slowest, mut fastest) = (usize::MAX, 0);
+        for (prescaler, divider) in ADC_PRESCALERS.iter().enumerate() {
+            let adc_clock = pclk2 / divider;
+            if adc_clock > MAX_ADC_CLOCK_HZ {
+                continue;
+            }
+            for (sampling_time, cycles) in SAMPLING_CYCLES.iter().enumerate() {
+                let rate = adc_clock / (cycles + conversion_cycles);
+                slowest = cmp::min(slowest, rate);
+                fastest = cmp::max(fastest, rate);
+                if best.map_or(true, |(_, _, best_rate)| {
+                    rate.abs_diff(frequency) < best_rate.abs_diff(frequency)
+                }) {
+                    best = Some((prescaler as u32, sampling_time as u32, rate));
+                }
+            }
+        }
+
+        if frequency < slowest || frequency > fastest {
+            return None;
+        }
+        best.map(|(prescaler, sampling_time, rate)| (prescaler, sampling_time, rate as u32))
+    }
+
+    /// Set the sampling time of an ADC input.
+    fn set_sampling_time(&self, input: u32, sampling_time: u32) {
+        if input >= 10 {
+            let shift = (input - 10) * 3;
+            let smpr1 = self.registers.smpr1.get();
+            self.registers
+                .smpr1
+                .set((smpr1 & !(0b111 << shift)) | (sampling_time << shift));
+        } else {
+            let shift = input * 3;
+            let smpr2 = self.registers.smpr2.get();
+            self.registers
+                .smpr2
+                .set((smpr2 & !(0b111 << shift)) | (sampling_time << shift));
+        }
+    }
+
+    /// Start a DMA transfer of `length` samples into `buffer`.
+    fn start_dma(&self, buffer: &'static mut [u16], length: usize) {
+        let length = cmp::min(buffer.len(), length);
+        if length == 0 {
+            // Keep the buffer to return it when sampling stops.
+            self.next_buffer.replace(buffer);
+            self.next_length.set(0);
+            return;
+        }
+        self.dma.map(|dma| {
+            self.dma_length.set(length);
+            dma.do_transfer(to_dma_buffer(buffer), length);
+        });
+    }
+
+    /// Convert continuously, with the samples read by the DMA. Conversions
+    /// stop when the DMA misses a sample (overrun), so they are restarted
+    /// after each new transfer.
+    fn start_conversions(&self) {
+        self.registers.sr.modify(SR::OVR::CLEAR + SR::EOC::CLEAR);
+        self.registers.cr2.modify(CR2::DMA::CLEAR);
+        self.registers
+            .cr2
+            .modify(CR2::DMA::SET + CR2::DDS::SET + CR2::CONT::SET);
+        self.registers.cr2.modify(CR2::SWSTART::SET);
+    }
+
+    /// Stop high-speed sampling, and keep the buffer of the running transfer.
+    fn stop_highspeed(&self) {
+        self.registers
+            .cr2
+            .modify(CR2::CONT::CLEAR + CR2::DMA::CLEAR + CR2::DDS::CLEAR + CR2::ALIGN::CLEAR);
+        self.registers.sr.modify(SR::OVR::CLEAR + SR::EOC::CLEAR);
+        let buffer = self.dma.and_then(|dma| dma.abort_transfer().0);
+        if let Some(buffer) = buffer {
+            self.stopped_buffer.replace(from_dma_buffer(buffer));
+        }
+        self.dma_length.set(0);
+        self.status.set(ADCStatus::Idle);
+    }
+
+    /// Stop continuous conversions and disarm the analog watchdog.
+    fn stop_window(&self) {
+        self.registers.cr2.modify(CR2::CONT::CLEAR);
+        self.registers
+            .cr1
+            .modify(CR1::AWDEN::CLEAR + CR1::AWDIE::CLEAR + CR1::AWDSGL::CLEAR);
+        self.registers.sr.modify(SR::AWD::CLEAR + SR::EOC::CLEAR);
+        self.common_registers.ccr.modify(CCR::VBATE::CLEAR);
+        self.status.set(ADCStatus::Idle);
     }
 }
 
@@ -392,13 +689,13 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
         if self.status.get() == ADCStatus::Off {
             self.enable();
         }
-        if *channel as u32 == 18 {
-            self.enable_temperature();
-        }
         if self.status.get() == ADCStatus::Idle {
+            if channel.is_internal() {
+                self.select_internal_source(channel);
+            }
             self.status.set(ADCStatus::OneSample);
             self.registers.sqr1.modify(SQR1::L.val(0b0000));
-            self.registers.sqr3.modify(SQR3::SQ1.val(*channel as u32));
+            self.registers.sqr3.modify(SQR3::SQ1.val(channel.input()));
             self.registers.cr1.modify(CR1::EOCIE::SET);
             self.registers.cr2.modify(CR2::SWSTART::SET);
             Ok(())
@@ -416,30 +713,108 @@ impl<'a> hil::adc::Adc<'a> for Adc<'a> {
     }
 
     fn stop_sampling(&self) -> Result<(), ErrorCode> {
-        Err(ErrorCode::NOSUPPORT)
+        if self.status.get() == ADCStatus::Window {
+            self.stop_window();
+            return Ok(());
+        }
+        if self.status.get() == ADCStatus::HighSpeed {
+            self.stop_highspeed();
+            return Ok(());
+        }
+        if self.status.get() != ADCStatus::Triggered {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.registers
+            .cr2
+            .modify(CR2::JEXTEN.val(0b00) + CR2::JEXTSEL.val(0));
+        self.registers
+            .cr1
+            .modify(CR1::JEOCIE::CLEAR + CR1::SCAN::CLEAR);
+        self.registers.sr.modify(SR::JEOC::CLEAR + SR::JSTRT::CLEAR);
+        self.status.set(ADCStatus::Idle);
+        Ok(())
     }
 
     fn get_resolution_bits(&self) -> usize {
-        12
+        self.resolution.get().bits()
+    }
+
+    /// The ADC converts with 12, 10 or 8 bits. The 6 bit results are not
+    /// supported, as they are left-aligned on a byte rather than on the u16.
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        let resolution = match bits {
+            12 => DataResolution::Bit12,
+            10 => DataResolution::Bit10,
+            8 => DataResolution::Bit8,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        match self.status.get() {
+            ADCStatus::Off => {}
+            ADCStatus::Idle => {
+                self.registers.cr1.modify(CR1::RES.val(resolution as u32));
+            }
+            _ => return Err(ErrorCode::BUSY),
+        }
+        self.resolution.set(resolution);
+        Ok(())
     }
 
     fn get_voltage_reference_mv(&self) -> Option<usize> {
         Some(3300)
     }
 
+    /// The channel is converted continuously, and the analog watchdog
+    /// interrupt fires on the first conversion outside of the window.
+    fn sample_window(&self, channel: &Self::Channel, low: u32, high: u32) -> Result<(), ErrorCode> {
+        if low > high || high > u16::MAX as u32 {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.status.get() == ADCStatus::Off {
+            self.enable();
+        }
+        if self.status.get() != ADCStatus::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if channel.is_internal() {
+            self.select_internal_source(channel);
+        }
+        self.status.set(ADCStatus::Window);
+
+        // The thresholds are compared to the result aligned on 12 bits,
+        // whatever the resolution
+        self.registers.ltr.write(LTR::LT.val(low >> 4));
+        self.registers.htr.write(HTR::HT.val(high >> 4));
+        self.registers.sqr1.modify(SQR1::L.val(0b0000));
+        self.registers.sqr3.modify(SQR3::SQ1.val(channel.input()));
+        self.registers.sr.modify(SR::AWD::CLEAR);
+        self.registers.cr1.modify(
+            CR1::AWDCH.val(channel.input())
+                + CR1::AWDSGL::SET
+                + CR1::AWDEN::SET
+                + CR1::AWDIE::SET
+                + CR1::EOCIE::CLEAR,
+        );
+        self.registers
+            .cr2
+            .modify(CR2::CONT::SET + CR2::SWSTART::SET);
+        Ok(())
+    }
+
     fn set_client(&self, client: &'a dyn hil::adc::Client) {
         self.client.set(client);
     }
 }
 
-/// Not yet supported
+/// Buffered sampling runs the ADC continuously, with the samples read by the
+/// DMA stream set with `set_dma`. Its rate is set by the ADC clock prescaler
+/// and the sampling time of the channel, so it ranges from a few tens of kHz
+/// to a few MHz depending on PCLK2; `get_sampling_frequency_hz` returns the
+/// achieved rate. Internal channels cannot be sampled this way.
 impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
     /// Capture buffered samples from the ADC continuously at a given
     /// frequency, calling the client whenever a buffer fills up. The client is
     /// then expected to either stop sampling or provide an additional buffer
-    /// to sample into. Note that due to hardware constraints the maximum
-    /// frequency range of the ADC is from 187 kHz to 23 Hz (although its
-    /// precision is limited at higher frequencies due to aliasing).
+    /// to sample into.
     ///
     /// - `channel`: the ADC channel to sample
     /// - `frequency`: frequency to sample at
@@ -449,14 +824,47 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
     /// - `length2`: number of samples to collect (up to buffer length)
     fn sample_highspeed(
         &self,
-        _channel: &Self::Channel,
-        _frequency: u32,
+        channel: &Self::Channel,
+        frequency: u32,
         buffer1: &'static mut [u16],
-        _length1: usize,
+        length1: usize,
         buffer2: &'static mut [u16],
-        _length2: usize,
+        length2: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u16], &'static mut [u16])> {
-        Err((ErrorCode::NOSUPPORT, buffer1, buffer2))
+        if self.dma.is_none() {
+            return Err((ErrorCode::NOSUPPORT, buffer1, buffer2));
+        }
+        if channel.is_internal() || cmp::min(buffer1.len(), length1) == 0 {
+            return Err((ErrorCode::INVAL, buffer1, buffer2));
+        }
+        if self.status.get() == ADCStatus::Off {
+            self.enable();
+        }
+        if self.status.get() != ADCStatus::Idle {
+            return Err((ErrorCode::BUSY, buffer1, buffer2));
+        }
+        let (prescaler, sampling_time, rate) = match self.highspeed_configuration(frequency) {
+            Some(configuration) => configuration,
+            None => return Err((ErrorCode::INVAL, buffer1, buffer2)),
+        };
+
+        self.status.set(ADCStatus::HighSpeed);
+        self.sampling_frequency.set(rate);
+        self.common_registers
+            .ccr
+            .modify(CCR::ADCPRE.val(prescaler) + CCR::VBATE::CLEAR);
+        self.set_sampling_time(channel.input(), sampling_time);
+        self.registers.sqr1.modify(SQR1::L.val(0b0000));
+        self.registers.sqr3.modify(SQR3::SQ1.val(channel.input()));
+        self.registers.cr1.modify(CR1::EOCIE::CLEAR);
+        // Left-align the samples, as expected by the clients
+        self.registers.cr2.modify(CR2::ALIGN::SET);
+
+        self.next_buffer.replace(buffer2);
+        self.next_length.set(length2);
+        self.start_dma(buffer1, length1);
+        self.start_conversions();
+        Ok(())
     }
 
     /// Provide a new buffer to send on-going buffered continuous samples to.
@@ -467,9 +875,27 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
     fn provide_buffer(
         &self,
         buf: &'static mut [u16],
-        _length: usize,
+        length: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u16])> {
-        Err((ErrorCode::NOSUPPORT, buf))
+        if self.status.get() != ADCStatus::HighSpeed {
+            // cannot continue sampling that isn't running
+            return Err((ErrorCode::INVAL, buf));
+        }
+        if self.dma_length.get() == 0 {
+            // conversions were paused for lack of a buffer
+            self.start_dma(buf, length);
+            if self.dma_length.get() != 0 {
+                self.start_conversions();
+            }
+            Ok(())
+        } else if self.next_buffer.is_some() {
+            // we've already got a second buffer, we don't need a third yet
+            Err((ErrorCode::BUSY, buf))
+        } else {
+            self.next_buffer.replace(buf);
+            self.next_length.set(length);
+            Ok(())
+        }
     }
 
     /// Reclaim buffers after the ADC is stopped.
@@ -477,8 +903,133 @@ impl<'a> hil::adc::AdcHighSpeed<'a> for Adc<'a> {
     fn retrieve_buffers(
         &self,
     ) -> Result<(Option<&'static mut [u16]>, Option<&'static mut [u16]>), ErrorCode> {
-        Err(ErrorCode::NOSUPPORT)
+        if self.status.get() == ADCStatus::HighSpeed {
+            // cannot return buffers while running
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok((self.next_buffer.take(), self.stopped_buffer.take()))
+        }
+    }
+
+    /// Achieved sampling frequency, after rounding of the ADC clock prescaler
+    /// and of the sampling time.
+    fn get_sampling_frequency_hz(&self) -> Option<u32> {
+        self.sampling_frequency.get()
     }
 
-    fn set_highspeed_client(&self, _client: &'a dyn hil::adc::HighSpeedClient) {}
+    fn set_highspeed_client(&self, client: &'a dyn hil::adc::HighSpeedClient) {
+        self.highspeed_client.set(client);
+    }
+}
+
+impl<'a> dma::StreamClient<'a, dma::Dma2<'a>> for Adc<'a> {
+    /// A buffer of samples is full: continue with the next buffer, so as to
+    /// miss as few samples as possible, and pass the full one to the client.
+    fn transfer_done(&self, pid: dma::Dma2Peripheral) {
+        if pid != dma::Dma2Peripheral::ADC1 || self.status.get() != ADCStatus::HighSpeed {
+            return;
+        }
+        let buffer = self.dma.and_then(|dma| dma.return_buffer());
+        let length = self.dma_length.replace(0);
+
+        match self.next_buffer.take() {
+            Some(next) => {
+                self.start_dma(next, self.next_length.get());
+                if self.dma_length.get() != 0 {
+                    self.start_conversions();
+                }
+            }
+            None => {
+                // Pause until the client provides a buffer
+                self.registers
+                    .cr2
+                    .modify(CR2::CONT::CLEAR + CR2::DMA::CLEAR);
+            }
+        }
+
+        if let Some(buffer) = buffer {
+            let buffer = from_dma_buffer(buffer);
+            self.highspeed_client
+                .map(move |client| client.samples_ready(buffer, length));
+        }
+    }
+}
+
+impl<'a> hil::adc::AdcTriggered<'a> for Adc<'a> {
+    type Trigger = Trigger;
+
+    fn max_group_len(&self) -> usize {
+        MAX_GROUP_LEN
+    }
+
+    /// The group is converted as the injected group. Single samples are
+    /// refused until sampling stops, and internal channels cannot be part of
+    /// a group.
+    fn sample_triggered(
+        &self,
+        channels: &[Self::Channel],
+        trigger: &Self::Trigger,
+    ) -> Result<(), ErrorCode> {
+        if channels.is_empty() || channels.len() > MAX_GROUP_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        if channels.iter().any(|channel| channel.is_internal()) {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.status.get() == ADCStatus::Off {
+            self.enable();
+        }
+        if self.status.get() != ADCStatus::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // With a sequence of length n, the injected sequencer converts
+        // JSQ(5-n) to JSQ4, and stores the results in JDR1 to JDRn.
+        let mut inputs = [0; MAX_GROUP_LEN];
+        let first = MAX_GROUP_LEN - channels.len();
+        for (input, channel) in inputs[first..].iter_mut().zip(channels.iter()) {
+            *input = channel.input();
+        }
+        self.registers.jsqr.write(
+            JSQR::JL.val(channels.len() as u32 - 1)
+                + JSQR::JSQ1.val(inputs[0])
+                + JSQR::JSQ2.val(inputs[1])
+                + JSQR::JSQ3.val(inputs[2])
+                + JSQR::JSQ4.val(inputs[3]),
+        );
+        self.group_len.set(channels.len());
+        self.status.set(ADCStatus::Triggered);
+
+        self.registers.sr.modify(SR::JEOC::CLEAR + SR::JSTRT::CLEAR);
+        self.registers
+            .cr1
+            .modify(CR1::SCAN::SET + CR1::JEOCIE::SET + CR1::JAUTO::CLEAR);
+        // Convert on the rising edge of the trigger.
+        self.registers
+            .cr2
+            .modify(CR2::JEXTSEL.val(*trigger as u32) + CR2::JEXTEN.val(0b01));
+        Ok(())
+    }
+
+    fn set_triggered_client(&self, client: &'a dyn hil::adc::TriggeredClient) {
+        self.triggered_client.set(client);
+    }
+}
+
+/// The DMA streams transfer byte buffers: the buffers of samples are passed
+/// to them as bytes, and the stream writes half-words as configured for
+/// `dma::Dma2Peripheral::ADC1`.
+fn to_dma_buffer(buffer: &'static mut [u16]) -> &'static mut [u8] {
+    let len = buffer.len() * 2;
+    // Safety: the bytes cover the memory of the samples, and have a weaker
+    // alignment.
+    unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr().cast::<u8>(), len) }
+}
+
+/// Turn a buffer passed to the DMA back into samples.
+fn from_dma_buffer(buffer: &'static mut [u8]) -> &'static mut [u16] {
+    let len = buffer.len() / 2;
+    // Safety: the buffer was created from samples by `to_dma_buffer`, so it is
+    // aligned for u16.
+    unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr().cast::<u16>(), len) }
 }
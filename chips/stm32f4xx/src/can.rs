@@ -7,15 +7,70 @@
 
 //! Low-level CAN driver for STM32F4XX chips
 //!
+//! Wake-up on bus activity
+//! -----------------------
+//!
+//! If automatic wake-up is configured (`set_wake_up(true)`) before the
+//! peripheral is enabled, a running controller can be parked with
+//! [`Can::park`]. The bxCAN peripheral then enters its Sleep mode with the
+//! AWUM bit set, and the peripheral clock is kept running while the core
+//! sleeps. When a start of frame is detected on the bus, the hardware leaves
+//! Sleep mode on its own and raises the WKUI status change interrupt, which
+//! wakes the core from the `wfi` in `Chip::sleep`. The driver then moves back
+//! to Normal mode and reports `State::Running` to the `ControllerClient`.
+//!
+//! Transmit priority
+//! -----------------
+//!
+//! Up to three frames can be pending in the transmit mailboxes, each
+//! returned through its own `transmit_complete` callback. With
+//! [`TransmitPriority::Identifier`] (the default), the hardware sends the
+//! pending frame with the lowest identifier first. When all the mailboxes are
+//! busy and a frame with a higher priority than one of the pending frames is
+//! sent, the lowest priority pending frame is aborted and requeued behind it,
+//! which bounds how long a high priority frame waits behind low priority
+//! ones on a busy bus. With [`TransmitPriority::Request`], frames are sent in
+//! the order they were requested and are never preempted.
+//!
+//! Filters
+//! -------
+//!
+//! Without filters enabled through the `Filter` trait, the driver receives
+//! every message, with two accept-all filters in banks 0 and 1. Enabling a
+//! filter removes them, and they are restored when the last filter is
+//! disabled while receiving. CAN1 owns the filter banks below the start
+//! bank of CAN2 (`CAN_FMR.CANSB`). Filter banks with a 16-bit scale only
+//! match standard identifiers, and hold the filter twice.
+//!
+//! Test modes
+//! ----------
+//!
+//! The `Loopback` and `Monitoring` operation modes set the LBKM and SILM bits
+//! of `CAN_BTR`, and `SilentLoopback` sets both: the transmitted frames are
+//! received back without driving the bus, which only sees recessive bits.
+//! The `Freeze` mode is not supported.
+//!
+//! Bus-off
+//! -------
+//!
+//! When the transmit error counter exceeds 255, the peripheral goes bus-off:
+//! the pending frames are dropped and `State::Error(Error::BusOff)` is
+//! reported to the `ControllerClient`. With the automatic recovery
+//! (`set_automatic_bus_off_recovery(true)`, the ABOM bit), the hardware
+//! leaves the bus-off state by itself, and `State::Running` is reported with
+//! the next frame transmitted or received. Otherwise, frames cannot be sent
+//! until `Controller::restart` is called.
+//!
 
 use crate::clocks::{phclk, Stm32f4Clocks};
 use core::cell::Cell;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::can::{self, StandardBitTiming};
+use kernel::hil::network_statistics::{Counter, Counters, NetworkStatistics};
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
-use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
-use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, Field, ReadWrite};
 use kernel::utilities::StaticRef;
 
 pub const BRP_MIN_STM32: u32 = 0;
@@ -392,16 +447,19 @@ enum CanState {
     Initialization,
     Normal,
     Sleep,
+    /// Enabled, but in the hardware Sleep mode until the bus becomes active.
+    Parked,
     RunningError(can::Error),
 }
 
-// The 4 possbile actions that the deferred call task can do.
+// The 5 possible actions that the deferred call task can do.
 #[derive(Copy, Clone, PartialEq)]
 enum AsyncAction {
     Enable,
     AbortReceive,
     Disabled,
     EnableError(kernel::ErrorCode),
+    Restarted,
 }
 
 #[repr(u32)]
@@ -422,6 +480,183 @@ enum SynchronizationJumpWidth {
     CanBtrSjwMax = 0b11,
 }
 
+/// Order in which the frames pending in the transmit mailboxes are sent.
+#[derive(Copy, Clone, PartialEq)]
+pub enum TransmitPriority {
+    /// The frame with the lowest identifier is sent first, and a higher
+    /// priority frame preempts a lower priority one when no mailbox is free.
+    Identifier,
+    /// The frames are sent in the order in which they were requested.
+    Request,
+}
+
+/// Per-mailbox fields of the transmit status register.
+const TSR_RQCP: [Field<u32, CAN_TSR::Register>; TX_MAILBOX_COUNT] =
+    [CAN_TSR::RQCP0, CAN_TSR::RQCP1, CAN_TSR::RQCP2];
+const TSR_TXOK: [Field<u32, CAN_TSR::Register>; TX_MAILBOX_COUNT] =
+    [CAN_TSR::TXOK0, CAN_TSR::TXOK1, CAN_TSR::TXOK2];
+const TSR_ALST: [Field<u32, CAN_TSR::Register>; TX_MAILBOX_COUNT] =
+    [CAN_TSR::ALST0, CAN_TSR::ALST1, CAN_TSR::ALST2];
+const TSR_TERR: [Field<u32, CAN_TSR::Register>; TX_MAILBOX_COUNT] =
+    [CAN_TSR::TERR0, CAN_TSR::TERR1, CAN_TSR::TERR2];
+const TSR_ABRQ: [Field<u32, CAN_TSR::Register>; TX_MAILBOX_COUNT] =
+    [CAN_TSR::ABRQ0, CAN_TSR::ABRQ1, CAN_TSR::ABRQ2];
+const TSR_TME: [Field<u32, CAN_TSR::Register>; TX_MAILBOX_COUNT] =
+    [CAN_TSR::TME0, CAN_TSR::TME1, CAN_TSR::TME2];
+
+/// Value of the arbitration field of a frame. Lower values win the
+/// arbitration on the bus: a data frame wins over a remote frame with the
+/// same identifier, and a standard frame wins over an extended frame with
+/// the same base identifier.
+fn arbitration_value(id: can::Id, remote: bool) -> u32 {
+    match id {
+        can::Id::Standard(id) => (id as u32 & 0x7ff) << 21 | (remote as u32) << 20,
+        can::Id::Extended(id) => {
+            ((id & 0x1fffffff) >> 18) << 21
+                | 1 << 20
+                | 1 << 19
+                | (id & 0x3ffff) << 1
+                | remote as u32
+        }
+    }
+}
+
+/// The error of a last error code of `CAN_ESR`.
+fn last_error(lec: u32) -> Option<can::Error> {
+    match lec {
+        1 => Some(can::Error::Stuff),
+        2 => Some(can::Error::Form),
+        3 => Some(can::Error::Ack),
+        4 => Some(can::Error::BitRecessive),
+        5 => Some(can::Error::BitDominant),
+        6 => Some(can::Error::Crc),
+        7 => Some(can::Error::SetBySoftware),
+        _ => None,
+    }
+}
+
+/// Maximum values of the standard and extended identifiers.
+const STANDARD_ID_MAX: u32 = 0x7ff;
+const EXTENDED_ID_MAX: u32 = 0x1fffffff;
+
+/// Identifier extension bit of the 32-bit and 16-bit filter layouts.
+const FILTER32_IDE: u32 = 1 << 2;
+const FILTER16_IDE: u32 = 1 << 3;
+
+/// Value of an identifier in the 32-bit layout of a filter bank.
+fn filter32_id(id: can::Id) -> Result<u32, kernel::ErrorCode> {
+    match id {
+        can::Id::Standard(id) if id as u32 <= STANDARD_ID_MAX => Ok((id as u32) << 21),
+        can::Id::Extended(id) if id <= EXTENDED_ID_MAX => Ok(id << 3 | FILTER32_IDE),
+        _ => Err(kernel::ErrorCode::INVAL),
+    }
+}
+
+/// Value of a standard identifier in the 16-bit layout of a filter bank.
+fn filter16_id(id: can::Id) -> Result<u32, kernel::ErrorCode> {
+    match id {
+        can::Id::Standard(id) if id as u32 <= STANDARD_ID_MAX => Ok((id as u32) << 5),
+        _ => Err(kernel::ErrorCode::INVAL),
+    }
+}
+
+/// Values of the two `CAN_FiRx` registers of a filter bank: the identifier
+/// and the mask, or the two identifiers.
+fn filter_bank_values(filter: &can::FilterParameters) -> Result<(u32, u32), kernel::ErrorCode> {
+    // the second identifier of a list has the format of the first one
+    let second_id = match filter.id {
+        can::Id::Standard(_) => u16::try_from(filter.mask)
+            .map(can::Id::Standard)
+            .map_err(|_| kernel::ErrorCode::INVAL),
+        can::Id::Extended(_) => Ok(can::Id::Extended(filter.mask)),
+    };
+    match (filter.scale_bits, filter.identifier_mode) {
+        (can::ScaleBits::Bits32, can::IdentifierMode::Mask) => {
+            let id = filter32_id(filter.id)?;
+            // a mask also compares the format of the identifiers, unless it
+            // accepts every message
+            let mask = match filter.id {
+                _ if filter.mask == 0 => 0,
+                can::Id::Standard(_) => (filter.mask & STANDARD_ID_MAX) << 21 | FILTER32_IDE,
+                can::Id::Extended(_) => (filter.mask & EXTENDED_ID_MAX) << 3 | FILTER32_IDE,
+            };
+            Ok((id, mask))
+        }
+        (can::ScaleBits::Bits32, can::IdentifierMode::List) => {
+            Ok((filter32_id(filter.id)?, filter32_id(second_id?)?))
+        }
+        (can::ScaleBits::Bits16, can::IdentifierMode::Mask) => {
+            let id = filter16_id(filter.id)?;
+            let mask = if filter.mask == 0 {
+                0
+            } else {
+                (filter.mask & STANDARD_ID_MAX) << 5 | FILTER16_IDE
+            };
+            let value = mask << 16 | id;
+            Ok((value, value))
+        }
+        (can::ScaleBits::Bits16, can::IdentifierMode::List) => {
+            let value = filter16_id(second_id?)? << 16 | filter16_id(filter.id)?;
+            Ok((value, value))
+        }
+    }
+}
+
+/// Status and buffer of a frame to return to the transmit client.
+type TxCompletion = (
+    Result<(), can::Error>,
+    &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+);
+
+/// A frame to be transmitted, owned by the driver until it is sent.
+struct TxFrame {
+    buffer: TakeCell<'static, [u8; can::STANDARD_CAN_PACKET_SIZE]>,
+    id: Cell<can::Id>,
+    len: Cell<usize>,
+    remote: Cell<bool>,
+}
+
+impl TxFrame {
+    const fn new() -> TxFrame {
+        TxFrame {
+            buffer: TakeCell::empty(),
+            id: Cell::new(can::Id::Standard(0)),
+            len: Cell::new(0),
+            remote: Cell::new(false),
+        }
+    }
+
+    fn set(
+        &self,
+        id: can::Id,
+        buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        len: usize,
+        remote: bool,
+    ) {
+        self.buffer.replace(buffer);
+        self.id.set(id);
+        self.len.set(len);
+        self.remote.set(remote);
+    }
+
+    fn arbitration_value(&self) -> u32 {
+        arbitration_value(self.id.get(), self.remote.get())
+    }
+
+    fn swap(&self, other: &TxFrame) {
+        let buffer = self.buffer.take();
+        if let Some(other_buffer) = other.buffer.take() {
+            self.buffer.replace(other_buffer);
+        }
+        if let Some(buffer) = buffer {
+            other.buffer.replace(buffer);
+        }
+        self.id.swap(&other.id);
+        self.len.swap(&other.len);
+        self.remote.swap(&other.remote);
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum CanInterruptMode {
     TransmitInterrupt,
@@ -433,7 +668,7 @@ pub enum CanInterruptMode {
 impl From<CanState> for can::State {
     fn from(state: CanState) -> Self {
         match state {
-            CanState::Initialization | CanState::Sleep => can::State::Disabled,
+            CanState::Initialization | CanState::Sleep | CanState::Parked => can::State::Disabled,
             CanState::Normal => can::State::Running,
             CanState::RunningError(err) => can::State::Error(err),
         }
@@ -448,13 +683,21 @@ pub struct Can<'a> {
     fifo0_interrupt_counter: Cell<u32>,
     fifo1_interrupt_counter: Cell<u32>,
     failed_messages: Cell<u32>,
+    counters: Cell<Counters>,
 
     // communication parameters
     automatic_retransmission: Cell<bool>,
     automatic_wake_up: Cell<bool>,
+    automatic_bus_off_recovery: Cell<bool>,
+    transmit_priority: Cell<TransmitPriority>,
     operating_mode: OptionalCell<can::OperationMode>,
     bit_timing: OptionalCell<can::BitTiming>,
 
+    // filter banks enabled through the `Filter` trait
+    filters: Cell<u32>,
+    // whether the accept-all filters are enabled
+    default_filters: Cell<bool>,
+
     // clients
     controller_client: OptionalCell<&'static dyn can::ControllerClient>,
     receive_client:
@@ -464,7 +707,11 @@ pub struct Can<'a> {
 
     // buffers for transmission and reception
     rx_buffer: TakeCell<'static, [u8; can::STANDARD_CAN_PACKET_SIZE]>,
-    tx_buffer: TakeCell<'static, [u8; can::STANDARD_CAN_PACKET_SIZE]>,
+    tx_mailboxes: [TxFrame; TX_MAILBOX_COUNT],
+    // frame waiting for a mailbox: either one preempting the frame of
+    // `tx_preempted`, or the preempted frame once it has been aborted
+    tx_waiting: TxFrame,
+    tx_preempted: OptionalCell<usize>,
 
     deferred_call: DeferredCall,
     // deferred call task action
@@ -484,15 +731,22 @@ impl<'a> Can<'a> {
             fifo0_interrupt_counter: Cell::new(0),
             fifo1_interrupt_counter: Cell::new(0),
             failed_messages: Cell::new(0),
+            counters: Cell::new(Counters::default()),
             automatic_retransmission: Cell::new(false),
             automatic_wake_up: Cell::new(false),
+            automatic_bus_off_recovery: Cell::new(false),
+            transmit_priority: Cell::new(TransmitPriority::Identifier),
             operating_mode: OptionalCell::empty(),
             bit_timing: OptionalCell::empty(),
+            filters: Cell::new(0),
+            default_filters: Cell::new(false),
             controller_client: OptionalCell::empty(),
             receive_client: OptionalCell::empty(),
             transmit_client: OptionalCell::empty(),
             rx_buffer: TakeCell::empty(),
-            tx_buffer: TakeCell::empty(),
+            tx_mailboxes: [TxFrame::new(), TxFrame::new(), TxFrame::new()],
+            tx_waiting: TxFrame::new(),
+            tx_preempted: OptionalCell::empty(),
             deferred_call: DeferredCall::new(),
             deferred_action: OptionalCell::empty(),
         }
@@ -518,6 +772,10 @@ impl<'a> Can<'a> {
     /// Enable the peripheral with the stored communication parameters:
     /// bit timing settings and communication mode
     pub fn enable(&self) -> Result<(), kernel::ErrorCode> {
+        // keep the peripheral clocked while the core sleeps, so that received
+        // frames and bus wake-up events can raise interrupts
+        self.clock.0.configure_can1_sleep_clock(true);
+
         // leave Sleep Mode
         self.registers.can_mcr.modify(CAN_MCR::SLEEP::CLEAR);
 
@@ -544,27 +802,41 @@ impl<'a> Can<'a> {
 
         // set communication mode
         self.registers.can_mcr.modify(CAN_MCR::TTCM::CLEAR);
-        self.registers.can_mcr.modify(CAN_MCR::ABOM::CLEAR);
+        match self.automatic_bus_off_recovery.get() {
+            true => self.registers.can_mcr.modify(CAN_MCR::ABOM::SET),
+            false => self.registers.can_mcr.modify(CAN_MCR::ABOM::CLEAR),
+        }
         self.registers.can_mcr.modify(CAN_MCR::RFLM::CLEAR);
-        self.registers.can_mcr.modify(CAN_MCR::TXFP::CLEAR);
+        match self.transmit_priority.get() {
+            TransmitPriority::Identifier => self.registers.can_mcr.modify(CAN_MCR::TXFP::CLEAR),
+            TransmitPriority::Request => self.registers.can_mcr.modify(CAN_MCR::TXFP::SET),
+        }
 
         match self.automatic_retransmission.get() {
-            true => self.registers.can_mcr.modify(CAN_MCR::AWUM::SET),
-            false => self.registers.can_mcr.modify(CAN_MCR::AWUM::CLEAR),
+            true => self.registers.can_mcr.modify(CAN_MCR::NART::CLEAR),
+            false => self.registers.can_mcr.modify(CAN_MCR::NART::SET),
         }
 
         match self.automatic_wake_up.get() {
-            true => self.registers.can_mcr.modify(CAN_MCR::NART::CLEAR),
-            false => self.registers.can_mcr.modify(CAN_MCR::NART::SET),
+            true => self.registers.can_mcr.modify(CAN_MCR::AWUM::SET),
+            false => self.registers.can_mcr.modify(CAN_MCR::AWUM::CLEAR),
         }
 
         if let Some(operating_mode_settings) = self.operating_mode.get() {
-            match operating_mode_settings {
-                can::OperationMode::Loopback => self.registers.can_btr.modify(CAN_BTR::LBKM::SET),
-                can::OperationMode::Monitoring => self.registers.can_btr.modify(CAN_BTR::SILM::SET),
-                can::OperationMode::Freeze => return Err(kernel::ErrorCode::INVAL),
-                _ => {}
-            }
+            // the loopback and silent bits of the previous mode are cleared
+            let (loopback, silent) = match operating_mode_settings {
+                can::OperationMode::Normal => (false, false),
+                can::OperationMode::Loopback => (true, false),
+                can::OperationMode::Monitoring => (false, true),
+                can::OperationMode::SilentLoopback => (true, true),
+                can::OperationMode::Freeze => {
+                    self.enter_sleep_mode();
+                    return Err(kernel::ErrorCode::INVAL);
+                }
+            };
+            self.registers
+                .can_btr
+                .modify(CAN_BTR::LBKM.val(loopback as u32) + CAN_BTR::SILM.val(silent as u32));
         }
 
         // set bit timing mode
@@ -590,7 +862,13 @@ impl<'a> Can<'a> {
     }
 
     /// Configure a filter to receive messages
-    pub fn config_filter(&self, filter_info: can::FilterParameters, enable: bool) {
+    pub fn config_filter(
+        &self,
+        filter_info: can::FilterParameters,
+        enable: bool,
+    ) -> Result<(), kernel::ErrorCode> {
+        let (first, second) = filter_bank_values(&filter_info)?;
+
         // get position of the filter number
         let filter_number = 1 << filter_info.number;
 
@@ -616,8 +894,9 @@ impl<'a> Can<'a> {
             }
         }
 
-        self.registers.can_firx[(filter_info.number as usize) * 2].modify(CAN_FiRx::FB.val(0));
-        self.registers.can_firx[(filter_info.number as usize) * 2 + 1].modify(CAN_FiRx::FB.val(0));
+        self.registers.can_firx[(filter_info.number as usize) * 2].modify(CAN_FiRx::FB.val(first));
+        self.registers.can_firx[(filter_info.number as usize) * 2 + 1]
+            .modify(CAN_FiRx::FB.val(second));
 
         // request filter mode to be mask or list
         match filter_info.identifier_mode {
@@ -653,6 +932,37 @@ impl<'a> Can<'a> {
                 CAN_FA1R::FACT.val(self.registers.can_fa1r.read(CAN_FA1R::FACT) & !filter_number),
             );
         }
+        Ok(())
+    }
+
+    /// Deactivate a filter bank, without changing its configuration
+    fn deactivate_filter(&self, number: u32) {
+        self.registers.can_fmr.modify(CAN_FMR::FINIT::SET);
+        self.registers.can_fa1r.modify(
+            CAN_FA1R::FACT.val(self.registers.can_fa1r.read(CAN_FA1R::FACT) & !(1 << number)),
+        );
+        self.enable_filter_config();
+    }
+
+    /// Enable or disable the accept-all filters of banks 0 and 1, one for
+    /// each receive FIFO
+    fn set_default_filters(&self, enable: bool) {
+        for fifo_number in 0..RX_MAILBOX_COUNT {
+            // accept-all filters are always valid
+            let _ = self.config_filter(
+                can::FilterParameters {
+                    number: fifo_number as u32,
+                    scale_bits: can::ScaleBits::Bits32,
+                    identifier_mode: can::IdentifierMode::Mask,
+                    fifo_number,
+                    id: can::Id::Standard(0),
+                    mask: 0,
+                },
+                enable,
+            );
+        }
+        self.enable_filter_config();
+        self.default_filters.set(enable);
     }
 
     pub fn enable_filter_config(&self) {
@@ -669,9 +979,11 @@ impl<'a> Can<'a> {
         // (as explained in RM0090 Reference Manual, Chapter 32.4.2).
         // This is done by checking the INAK bit 20_000 times or until it is cleared.
         if !Can::wait_for(20000, || !self.registers.can_msr.is_set(CAN_MSR::INAK)) {
+            kernel::log_error!(Can, "Timeout while entering normal mode");
             return Err(kernel::ErrorCode::FAIL);
         }
 
+        kernel::log_trace!(Can, "Entered normal mode");
         self.can_state.set(CanState::Normal);
         Ok(())
     }
@@ -679,107 +991,261 @@ impl<'a> Can<'a> {
     pub fn enter_sleep_mode(&self) {
         // request to enter sleep mode by setting SLEEP bit
         self.disable_irqs();
+        // a disabled controller must not be woken up by bus activity
+        self.registers.can_mcr.modify(CAN_MCR::AWUM::CLEAR);
         self.registers.can_mcr.modify(CAN_MCR::SLEEP::SET);
+        self.clock.0.configure_can1_sleep_clock(false);
         self.can_state.set(CanState::Sleep);
     }
 
-    /// This function sends an 8-byte message
+    /// Put a running controller in the hardware Sleep mode until a frame is
+    /// detected on the bus.
+    ///
+    /// Requires automatic wake-up to be configured. Only the wake-up status
+    /// change interrupt stays enabled, and the peripheral clock is kept
+    /// running while the core sleeps so that the interrupt can wake the chip.
+    /// Once the bus becomes active, the controller returns to Normal mode and
+    /// the `ControllerClient` receives `state_changed(State::Running)`.
+    pub fn park(&self) -> Result<(), kernel::ErrorCode> {
+        match self.can_state.get() {
+            CanState::Normal | CanState::RunningError(_) => {
+                if !self.automatic_wake_up.get() {
+                    return Err(kernel::ErrorCode::INVAL);
+                }
+                if self.is_transmitting() {
+                    // a transmission is in progress
+                    return Err(kernel::ErrorCode::BUSY);
+                }
+
+                self.disable_irqs();
+                self.registers.can_mcr.modify(CAN_MCR::AWUM::SET);
+                self.registers.can_mcr.modify(CAN_MCR::SLEEP::SET);
+
+                // The peripheral acknowledges the Sleep mode by setting the
+                // SLAK bit (as explained in RM0090 Reference Manual,
+                // Chapter 32.4.3). This is done by checking the SLAK bit
+                // 20_000 times or until it is set.
+                if !Can::wait_for(20000, || self.registers.can_msr.is_set(CAN_MSR::SLAK)) {
+                    self.registers.can_mcr.modify(CAN_MCR::SLEEP::CLEAR);
+                    self.enable_running_irqs();
+                    return Err(kernel::ErrorCode::FAIL);
+                }
+
+                // clear a stale wake-up flag before enabling its interrupt
+                self.registers.can_msr.modify(CAN_MSR::WKUI::SET);
+                self.registers.can_ier.modify(CAN_IER::WKUIE::SET);
+                self.clock.0.configure_can1_sleep_clock(true);
+                self.can_state.set(CanState::Parked);
+                Ok(())
+            }
+            CanState::Parked => Err(kernel::ErrorCode::ALREADY),
+            CanState::Sleep | CanState::Initialization => Err(kernel::ErrorCode::OFF),
+        }
+    }
+
+    /// Enable the interrupts needed by a running controller: the status
+    /// change interrupts and, if a reception is in progress, the FIFO ones.
+    fn enable_running_irqs(&self) {
+        self.enable_irq(CanInterruptMode::ErrorAndStatusChangeInterrupt);
+        if self.rx_buffer.is_some() {
+            self.enable_irq(CanInterruptMode::Fifo0Interrupt);
+            self.enable_irq(CanInterruptMode::Fifo1Interrupt);
+        }
+    }
+
+    /// Returns true if the controller is parked waiting for bus activity.
+    pub fn is_parked(&self) -> bool {
+        self.can_state.get() == CanState::Parked
+    }
+
+    /// Finish the automatic wake-up of a parked controller.
+    fn handle_wake_up(&self) {
+        // With AWUM set, the hardware clears the SLEEP bit by itself and
+        // leaves Sleep mode once it has synchronized with the bus (SLAK
+        // cleared, as explained in RM0090 Reference Manual, Chapter 32.4.3).
+        self.registers.can_mcr.modify(CAN_MCR::SLEEP::CLEAR);
+        if !Can::wait_for(20000, || !self.registers.can_msr.is_set(CAN_MSR::SLAK)) {
+            // still asleep, wait for the next wake-up interrupt
+            return;
+        }
+
+        kernel::log_trace!(Can, "Woken up by bus activity");
+        self.can_state.set(CanState::Normal);
+        self.enable_running_irqs();
+        self.controller_client.map(|controller_client| {
+            controller_client.state_changed(can::State::Running);
+        });
+    }
+
+    /// Update the network statistics.
+    fn count(&self, counter: Counter) {
+        let mut counters = self.counters.get();
+        counters.increment(counter);
+        self.counters.set(counters);
+    }
+
+    /// Configure the order in which pending frames are transmitted. This
+    /// must be called before the peripheral is enabled.
+    pub fn set_transmit_priority(
+        &self,
+        priority: TransmitPriority,
+    ) -> Result<(), kernel::ErrorCode> {
+        match self.can_state.get() {
+            CanState::Sleep => {
+                self.transmit_priority.set(priority);
+                Ok(())
+            }
+            CanState::Normal
+            | CanState::Initialization
+            | CanState::Parked
+            | CanState::RunningError(_) => Err(kernel::ErrorCode::BUSY),
+        }
+    }
+
+    /// This function sends an 8-byte message from a transmit mailbox
     pub fn send_8byte_message(
         &self,
+        tx_mailbox: usize,
         id: can::Id,
         dlc: usize,
         rtr: u8,
-    ) -> Result<(), kernel::ErrorCode> {
-        self.enable_irq(CanInterruptMode::ErrorAndStatusChangeInterrupt);
-        if self.can_state.get() == CanState::Normal {
-            if let Some(tx_mailbox) = self.find_empty_mailbox() {
-                // set extended or standard id in registers
-                match id {
-                    can::Id::Standard(id) => {
-                        self.registers.can_tx_mailbox[tx_mailbox]
-                            .can_tir
-                            .modify(CAN_TIxR::IDE::CLEAR);
-                        self.registers.can_tx_mailbox[tx_mailbox]
-                            .can_tir
-                            .modify(CAN_TIxR::STID.val(id as u32 & 0xeff));
-                        self.registers.can_tx_mailbox[tx_mailbox]
-                            .can_tir
-                            .modify(CAN_TIxR::EXID.val(0));
-                    }
-                    can::Id::Extended(id) => {
-                        self.registers.can_tx_mailbox[tx_mailbox]
-                            .can_tir
-                            .modify(CAN_TIxR::IDE::SET);
-                        self.registers.can_tx_mailbox[tx_mailbox]
-                            .can_tir
-                            .modify(CAN_TIxR::STID.val((id & 0xffc0000) >> 18));
-                        self.registers.can_tx_mailbox[tx_mailbox]
-                            .can_tir
-                            .modify(CAN_TIxR::EXID.val(id & 0x003fffff));
-                    }
-                }
-                // write rtr
+        tx: &[u8; can::STANDARD_CAN_PACKET_SIZE],
+    ) {
+        // set extended or standard id in registers
+        match id {
+            can::Id::Standard(id) => {
                 self.registers.can_tx_mailbox[tx_mailbox]
                     .can_tir
-                    .modify(CAN_TIxR::RTR.val(rtr.into()));
-                // write dlc
+                    .modify(CAN_TIxR::IDE::CLEAR);
                 self.registers.can_tx_mailbox[tx_mailbox]
-                    .can_tdtr
-                    .modify(CAN_TDTxR::DLC.val(dlc as u32));
-                // write first 4 bytes of the data
-                match self.tx_buffer.map(|tx| {
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdlr
-                        .modify(CAN_TDLxR::DATA0.val(tx[0].into()));
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdlr
-                        .modify(CAN_TDLxR::DATA1.val(tx[1].into()));
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdlr
-                        .modify(CAN_TDLxR::DATA2.val(tx[2].into()));
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdlr
-                        .modify(CAN_TDLxR::DATA3.val(tx[3].into()));
-                    // write the last 4 bytes of the data
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdhr
-                        .modify(CAN_TDHxR::DATA4.val(tx[4].into()));
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdhr
-                        .modify(CAN_TDHxR::DATA5.val(tx[5].into()));
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdhr
-                        .modify(CAN_TDHxR::DATA6.val(tx[6].into()));
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tdhr
-                        .modify(CAN_TDHxR::DATA7.val(tx[7].into()));
-
-                    self.registers.can_tx_mailbox[tx_mailbox]
-                        .can_tir
-                        .modify(CAN_TIxR::TXRQ::SET);
-                }) {
-                    Some(()) => Ok(()),
-                    None => Err(kernel::ErrorCode::FAIL),
-                }
-            } else {
-                // no mailbox empty
-                self.failed_messages.replace(self.failed_messages.get() + 1);
-                Err(kernel::ErrorCode::BUSY)
+                    .can_tir
+                    .modify(CAN_TIxR::STID.val(id as u32 & 0x7ff));
+                self.registers.can_tx_mailbox[tx_mailbox]
+                    .can_tir
+                    .modify(CAN_TIxR::EXID.val(0));
             }
-        } else {
-            Err(kernel::ErrorCode::OFF)
+            can::Id::Extended(id) => {
+                self.registers.can_tx_mailbox[tx_mailbox]
+                    .can_tir
+                    .modify(CAN_TIxR::IDE::SET);
+                self.registers.can_tx_mailbox[tx_mailbox]
+                    .can_tir
+                    .modify(CAN_TIxR::STID.val((id & 0x1ffc0000) >> 18));
+                self.registers.can_tx_mailbox[tx_mailbox]
+                    .can_tir
+                    .modify(CAN_TIxR::EXID.val(id & 0x3ffff));
+            }
+        }
+        // write rtr
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tir
+            .modify(CAN_TIxR::RTR.val(rtr.into()));
+        // write dlc
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdtr
+            .modify(CAN_TDTxR::DLC.val(dlc as u32));
+        // write first 4 bytes of the data
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdlr
+            .modify(CAN_TDLxR::DATA0.val(tx[0].into()));
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdlr
+            .modify(CAN_TDLxR::DATA1.val(tx[1].into()));
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdlr
+            .modify(CAN_TDLxR::DATA2.val(tx[2].into()));
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdlr
+            .modify(CAN_TDLxR::DATA3.val(tx[3].into()));
+        // write the last 4 bytes of the data
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdhr
+            .modify(CAN_TDHxR::DATA4.val(tx[4].into()));
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdhr
+            .modify(CAN_TDHxR::DATA5.val(tx[5].into()));
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdhr
+            .modify(CAN_TDHxR::DATA6.val(tx[6].into()));
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tdhr
+            .modify(CAN_TDHxR::DATA7.val(tx[7].into()));
+
+        self.registers.can_tx_mailbox[tx_mailbox]
+            .can_tir
+            .modify(CAN_TIxR::TXRQ::SET);
+    }
+
+    /// Request the transmission of the frame stored for a mailbox.
+    fn load_mailbox(&self, tx_mailbox: usize) {
+        let frame = &self.tx_mailboxes[tx_mailbox];
+        frame.buffer.map(|tx| {
+            self.send_8byte_message(
+                tx_mailbox,
+                frame.id.get(),
+                frame.len.get(),
+                frame.remote.get() as u8,
+                tx,
+            )
+        });
+    }
+
+    /// Move the waiting frame to a free mailbox, if there is one.
+    fn load_waiting_frame(&self) {
+        if self.tx_waiting.buffer.is_some() && self.tx_preempted.is_none() {
+            if let Some(tx_mailbox) = self.find_empty_mailbox() {
+                self.tx_mailboxes[tx_mailbox].swap(&self.tx_waiting);
+                self.load_mailbox(tx_mailbox);
+            }
+        }
+    }
+
+    /// Find the mailbox holding the lowest priority pending frame, if the
+    /// frame with `id` has a higher priority and preemption is allowed.
+    fn preemptible_mailbox(&self, id: can::Id, remote: bool) -> Option<usize> {
+        if self.transmit_priority.get() != TransmitPriority::Identifier
+            || self.tx_preempted.is_some()
+        {
+            return None;
         }
+        self.tx_mailboxes
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| frame.buffer.is_some())
+            .map(|(tx_mailbox, frame)| (tx_mailbox, frame.arbitration_value()))
+            .max_by_key(|&(_, value)| value)
+            .filter(|&(_, value)| arbitration_value(id, remote) < value)
+            .map(|(tx_mailbox, _)| tx_mailbox)
+    }
+
+    /// Whether frames are pending transmission.
+    fn is_transmitting(&self) -> bool {
+        self.tx_waiting.buffer.is_some()
+            || self.tx_mailboxes.iter().any(|frame| frame.buffer.is_some())
     }
 
+    /// Find a mailbox that is empty and whose previous frame has been
+    /// returned to the client.
     pub fn find_empty_mailbox(&self) -> Option<usize> {
-        if self.registers.can_tsr.read(CAN_TSR::TME0) == 1 {
-            Some(0)
-        } else if self.registers.can_tsr.read(CAN_TSR::TME1) == 1 {
-            Some(1)
-        } else if self.registers.can_tsr.read(CAN_TSR::TME2) == 1 {
-            Some(2)
-        } else {
-            None
+        (0..TX_MAILBOX_COUNT).find(|&tx_mailbox| {
+            self.registers.can_tsr.read(TSR_TME[tx_mailbox]) == 1
+                && self.tx_mailboxes[tx_mailbox].buffer.is_none()
+        })
+    }
+
+    fn is_bus_off(&self) -> bool {
+        self.can_state.get() == CanState::RunningError(can::Error::BusOff)
+    }
+
+    /// Report that the peripheral is back on the bus, once it transmits or
+    /// receives frames after an automatic bus-off recovery.
+    fn check_bus_off_recovery(&self) {
+        if self.is_bus_off() && self.registers.can_esr.read(CAN_ESR::BOFF) == 0 {
+            self.can_state.set(CanState::Normal);
+            kernel::log_info!(Can, "Recovered from bus-off");
+            self.controller_client.map(|controller_client| {
+                controller_client.state_changed(can::State::Running);
+            });
         }
     }
 
@@ -797,69 +1263,100 @@ impl<'a> Can<'a> {
 
     /// Handle the transmit interrupt. Check the status register for each
     /// transmit mailbox to find out the mailbox that the message was sent from.
+    /// An aborted preempted frame is requeued behind the frame that preempted
+    /// it.
     pub fn handle_transmit_interrupt(&self) {
-        let mut state = Ok(());
+        let mut completed: [Option<TxCompletion>; TX_MAILBOX_COUNT + 1] = [None, None, None, None];
+
         if self.registers.can_esr.read(CAN_ESR::BOFF) == 1 {
-            state = Err(can::Error::BusOff)
+            // drop all the pending frames
+            for tx_mailbox in 0..TX_MAILBOX_COUNT {
+                self.registers.can_tsr.write(TSR_ABRQ[tx_mailbox].val(1));
+                completed[tx_mailbox] = self.tx_mailboxes[tx_mailbox]
+                    .buffer
+                    .take()
+                    .map(|buf| (Err(can::Error::BusOff), buf));
+            }
+            completed[TX_MAILBOX_COUNT] = self
+                .tx_waiting
+                .buffer
+                .take()
+                .map(|buf| (Err(can::Error::BusOff), buf));
+            self.tx_preempted.clear();
+            self.can_state
+                .set(CanState::RunningError(can::Error::BusOff));
+            kernel::log_warn!(Can, "Bus-off, pending frames dropped");
         } else {
-            if self.registers.can_tsr.read(CAN_TSR::RQCP0) == 1 {
+            self.check_bus_off_recovery();
+            for tx_mailbox in 0..TX_MAILBOX_COUNT {
+                if self.registers.can_tsr.read(TSR_RQCP[tx_mailbox]) == 0 {
+                    continue;
+                }
+
                 // check status
-                state = if self.registers.can_tsr.read(CAN_TSR::TXOK0) == 1 {
+                let transmitted = self.registers.can_tsr.read(TSR_TXOK[tx_mailbox]) == 1;
+                let state = if transmitted {
                     Ok(())
-                } else if self.registers.can_tsr.read(CAN_TSR::TERR0) == 1 {
+                } else if self.registers.can_tsr.read(TSR_TERR[tx_mailbox]) == 1 {
                     Err(can::Error::Transmission)
-                } else if self.registers.can_tsr.read(CAN_TSR::ALST0) == 1 {
+                } else if self.registers.can_tsr.read(TSR_ALST[tx_mailbox]) == 1 {
                     Err(can::Error::ArbitrationLost)
                 } else {
                     Ok(())
                 };
                 // mark the interrupt as handled
-                self.registers.can_tsr.modify(CAN_TSR::RQCP0::SET);
-            }
-            if self.registers.can_tsr.read(CAN_TSR::RQCP1) == 1 {
-                state = if self.registers.can_tsr.read(CAN_TSR::TXOK1) == 1 {
-                    Ok(())
-                } else if self.registers.can_tsr.read(CAN_TSR::TERR1) == 1 {
-                    Err(can::Error::Transmission)
-                } else if self.registers.can_tsr.read(CAN_TSR::ALST1) == 1 {
-                    Err(can::Error::ArbitrationLost)
-                } else {
-                    Ok(())
-                };
-                // mark the interrupt as handled
-                self.registers.can_tsr.modify(CAN_TSR::RQCP1::SET);
-            }
-            if self.registers.can_tsr.read(CAN_TSR::RQCP2) == 1 {
-                state = if self.registers.can_tsr.read(CAN_TSR::TXOK2) == 1 {
-                    Ok(())
-                } else if self.registers.can_tsr.read(CAN_TSR::TERR2) == 1 {
-                    Err(can::Error::Transmission)
-                } else if self.registers.can_tsr.read(CAN_TSR::ALST2) == 1 {
-                    Err(can::Error::ArbitrationLost)
+                self.registers.can_tsr.write(TSR_RQCP[tx_mailbox].val(1));
+
+                let preempted = self.tx_preempted.contains(&tx_mailbox);
+                if preempted {
+                    self.tx_preempted.clear();
+                }
+                if preempted && !transmitted {
+                    // the preempting frame takes the mailbox and the aborted
+                    // frame waits for the next free one
+                    self.tx_mailboxes[tx_mailbox].swap(&self.tx_waiting);
+                    self.load_mailbox(tx_mailbox);
                 } else {
-                    Ok(())
-                };
-                // mark the interrupt as handled
-                self.registers.can_tsr.modify(CAN_TSR::RQCP2::SET);
+                    match state {
+                        Ok(()) => self.count(Counter::TxFrames),
+                        Err(err) => {
+                            self.count(Counter::TxErrors);
+                            self.can_state.set(CanState::RunningError(err));
+                            kernel::log_trace!(
+                                Can,
+                                "Transmission from mailbox {} failed: {:?}",
+                                tx_mailbox,
+                                err
+                            );
+                        }
+                    }
+                    completed[tx_mailbox] = self.tx_mailboxes[tx_mailbox]
+                        .buffer
+                        .take()
+                        .map(|buf| (state, buf));
+                }
             }
+            self.load_waiting_frame();
         }
 
-        match state {
-            Err(err) => self.can_state.set(CanState::RunningError(err)),
-            _ => {}
+        for (state, buf) in completed.into_iter().flatten() {
+            if state == Err(can::Error::BusOff) {
+                self.count(Counter::TxErrors);
+            }
+            self.transmit_client
+                .map(|transmit_client| transmit_client.transmit_complete(state, buf));
         }
-
-        self.transmit_client
-            .map(|transmit_client| match self.tx_buffer.take() {
-                Some(buf) => transmit_client.transmit_complete(state, buf),
-                None => {}
-            });
     }
 
     pub fn process_received_message(
         &self,
         rx_mailbox: usize,
-    ) -> (can::Id, usize, [u8; can::STANDARD_CAN_PACKET_SIZE]) {
+    ) -> (
+        can::Id,
+        usize,
+        can::FrameFlags,
+        [u8; can::STANDARD_CAN_PACKET_SIZE],
+    ) {
         let message_id = if self.registers.can_rx_mailbox[rx_mailbox]
             .can_rir
             .read(CAN_RIxR::IDE)
@@ -884,6 +1381,14 @@ impl<'a> Can<'a> {
         let message_length = self.registers.can_rx_mailbox[rx_mailbox]
             .can_rdtr
             .read(CAN_RDTxR::DLC) as usize;
+        let flags = if self.registers.can_rx_mailbox[rx_mailbox]
+            .can_rir
+            .is_set(CAN_RIxR::RTR)
+        {
+            can::FrameFlags::REMOTE
+        } else {
+            can::FrameFlags::CLASSIC
+        };
         let recv: u64 = ((self.registers.can_rx_mailbox[0].can_rdhr.get() as u64) << 32)
             | (self.registers.can_rx_mailbox[0].can_rdlr.get() as u64);
         let rx_buf = recv.to_le_bytes();
@@ -891,7 +1396,7 @@ impl<'a> Can<'a> {
             rx[..8].copy_from_slice(&rx_buf[..8]);
         });
 
-        (message_id, message_length, rx_buf)
+        (message_id, message_length, flags, rx_buf)
     }
 
     pub fn handle_fifo0_interrupt(&self) {
@@ -901,13 +1406,22 @@ impl<'a> Can<'a> {
 
         if self.registers.can_rf0r.read(CAN_RF0R::FOVR0) == 1 {
             self.registers.can_rf0r.modify(CAN_RF0R::FOVR0::SET);
+            self.count(Counter::RxDropped);
         }
 
         if self.registers.can_rf0r.read(CAN_RF0R::FMP0) != 0 {
-            let (message_id, message_length, mut rx_buf) = self.process_received_message(0);
+            self.check_bus_off_recovery();
+            let (message_id, message_length, flags, mut rx_buf) = self.process_received_message(0);
+            self.count(Counter::RxFrames);
 
             self.receive_client.map(|receive_client| {
-                receive_client.message_received(message_id, &mut rx_buf, message_length, Ok(()))
+                receive_client.message_received_with_flags(
+                    message_id,
+                    &mut rx_buf,
+                    message_length,
+                    flags,
+                    Ok(()),
+                )
             });
             self.fifo0_interrupt_counter
                 .replace(self.fifo0_interrupt_counter.get() + 1);
@@ -924,14 +1438,23 @@ impl<'a> Can<'a> {
 
         if self.registers.can_rf1r.read(CAN_RF1R::FOVR1) == 1 {
             self.registers.can_rf1r.modify(CAN_RF1R::FOVR1::SET);
+            self.count(Counter::RxDropped);
         }
 
         if self.registers.can_rf1r.read(CAN_RF1R::FMP1) != 0 {
+            self.check_bus_off_recovery();
             self.fifo1_interrupt_counter
                 .replace(self.fifo1_interrupt_counter.get() + 1);
-            let (message_id, message_length, mut rx_buf) = self.process_received_message(1);
+            let (message_id, message_length, flags, mut rx_buf) = self.process_received_message(1);
+            self.count(Counter::RxFrames);
             self.receive_client.map(|receive_client| {
-                receive_client.message_received(message_id, &mut rx_buf, message_length, Ok(()))
+                receive_client.message_received_with_flags(
+                    message_id,
+                    &mut rx_buf,
+                    message_length,
+                    flags,
+                    Ok(()),
+                )
             });
 
             // mark the interrupt as handled
@@ -944,6 +1467,10 @@ impl<'a> Can<'a> {
         if self.registers.can_msr.read(CAN_MSR::WKUI) == 1 {
             // mark the interrupt as handled
             self.registers.can_msr.modify(CAN_MSR::WKUI::SET);
+            if self.can_state.get() == CanState::Parked {
+                self.handle_wake_up();
+                return;
+            }
         }
         if self.registers.can_msr.read(CAN_MSR::SLAKI) == 1 {
             // mark the interrupt as handled
@@ -961,36 +1488,23 @@ impl<'a> Can<'a> {
             self.can_state
                 .set(CanState::RunningError(can::Error::Passive));
         }
-        // Bus-off flag
+        // Last Error Code
+        if let Some(err) = last_error(self.registers.can_esr.read(CAN_ESR::LEC)) {
+            self.count(Counter::RxErrors);
+            self.can_state.set(CanState::RunningError(err));
+        }
+        // Bus-off flag, reported over the error that caused it
         if self.registers.can_esr.read(CAN_ESR::BOFF) == 1 {
             self.can_state
                 .set(CanState::RunningError(can::Error::BusOff));
         }
-        // Last Error Code
-        match self.registers.can_esr.read(CAN_ESR::LEC) {
-            0x001 => self
-                .can_state
-                .set(CanState::RunningError(can::Error::Stuff)),
-            0x010 => self.can_state.set(CanState::RunningError(can::Error::Form)),
-            0x011 => self.can_state.set(CanState::RunningError(can::Error::Ack)),
-            0x100 => self
-                .can_state
-                .set(CanState::RunningError(can::Error::BitRecessive)),
-            0x101 => self
-                .can_state
-                .set(CanState::RunningError(can::Error::BitDominant)),
-            0x110 => self.can_state.set(CanState::RunningError(can::Error::Crc)),
-            0x111 => self
-                .can_state
-                .set(CanState::RunningError(can::Error::SetBySoftware)),
-            _ => {}
-        }
 
         self.error_interrupt_counter
             .replace(self.error_interrupt_counter.get() + 1);
 
         match self.can_state.get() {
             CanState::RunningError(err) => {
+                kernel::log_info!(Can, "Error state: {:?}", err);
                 self.controller_client.map(|controller_client| {
                     controller_client.state_changed(kernel::hil::can::State::Error(err));
                 });
@@ -1106,6 +1620,11 @@ impl DeferredCallClient for Can<'_> {
                         controller_client.enabled(Err(err));
                     });
                 }
+                AsyncAction::Restarted => {
+                    self.controller_client.map(|controller_client| {
+                        controller_client.state_changed(self.can_state.get().into());
+                    });
+                }
             },
             // todo no action set
             None => todo!(),
@@ -1159,9 +1678,10 @@ impl can::Configure for Can<'_> {
                 self.bit_timing.set(bit_timing);
                 Ok(())
             }
-            CanState::Normal | CanState::Initialization | CanState::RunningError(_) => {
-                Err(kernel::ErrorCode::BUSY)
-            }
+            CanState::Normal
+            | CanState::Initialization
+            | CanState::Parked
+            | CanState::RunningError(_) => Err(kernel::ErrorCode::BUSY),
         }
     }
 
@@ -1171,9 +1691,10 @@ impl can::Configure for Can<'_> {
                 self.operating_mode.set(mode);
                 Ok(())
             }
-            CanState::Normal | CanState::Initialization | CanState::RunningError(_) => {
-                Err(kernel::ErrorCode::BUSY)
-            }
+            CanState::Normal
+            | CanState::Initialization
+            | CanState::Parked
+            | CanState::RunningError(_) => Err(kernel::ErrorCode::BUSY),
         }
     }
 
@@ -1199,9 +1720,10 @@ impl can::Configure for Can<'_> {
                 self.automatic_retransmission.replace(automatic);
                 Ok(())
             }
-            CanState::Normal | CanState::Initialization | CanState::RunningError(_) => {
-                Err(kernel::ErrorCode::BUSY)
-            }
+            CanState::Normal
+            | CanState::Initialization
+            | CanState::Parked
+            | CanState::RunningError(_) => Err(kernel::ErrorCode::BUSY),
         }
     }
 
@@ -1211,9 +1733,10 @@ impl can::Configure for Can<'_> {
                 self.automatic_wake_up.replace(wake_up);
                 Ok(())
             }
-            CanState::Normal | CanState::Initialization | CanState::RunningError(_) => {
-                Err(kernel::ErrorCode::BUSY)
-            }
+            CanState::Normal
+            | CanState::Initialization
+            | CanState::Parked
+            | CanState::RunningError(_) => Err(kernel::ErrorCode::BUSY),
         }
     }
 
@@ -1225,6 +1748,23 @@ impl can::Configure for Can<'_> {
         Ok(self.automatic_wake_up.get())
     }
 
+    fn set_automatic_bus_off_recovery(&self, automatic: bool) -> Result<(), kernel::ErrorCode> {
+        match self.can_state.get() {
+            CanState::Sleep => {
+                self.automatic_bus_off_recovery.replace(automatic);
+                Ok(())
+            }
+            CanState::Normal
+            | CanState::Initialization
+            | CanState::Parked
+            | CanState::RunningError(_) => Err(kernel::ErrorCode::BUSY),
+        }
+    }
+
+    fn get_automatic_bus_off_recovery(&self) -> Result<bool, kernel::ErrorCode> {
+        Ok(self.automatic_bus_off_recovery.get())
+    }
+
     fn receive_fifo_count(&self) -> usize {
         2
     }
@@ -1264,14 +1804,16 @@ impl can::Controller for Can<'_> {
                     }
                 }
             }
-            CanState::Normal | CanState::Initialization => Err(kernel::ErrorCode::ALREADY),
+            CanState::Normal | CanState::Initialization | CanState::Parked => {
+                Err(kernel::ErrorCode::ALREADY)
+            }
             CanState::RunningError(_) => Err(kernel::ErrorCode::FAIL),
         }
     }
 
     fn disable(&self) -> Result<(), kernel::ErrorCode> {
         match self.can_state.get() {
-            CanState::Normal | CanState::RunningError(_) => {
+            CanState::Normal | CanState::Parked | CanState::RunningError(_) => {
                 self.enter_sleep_mode();
                 if self.deferred_action.is_some() {
                     // there is another deferred action that must be completed
@@ -1290,6 +1832,32 @@ impl can::Controller for Can<'_> {
     fn get_state(&self) -> Result<can::State, kernel::ErrorCode> {
         Ok(self.can_state.get().into())
     }
+
+    fn restart(&self) -> Result<(), kernel::ErrorCode> {
+        match self.can_state.get() {
+            CanState::RunningError(can::Error::BusOff) => {
+                if self.deferred_action.is_some() {
+                    // there is another deferred action that must be completed
+                    return Err(kernel::ErrorCode::BUSY);
+                }
+                // Entering and leaving the initialization mode starts the
+                // bus-off recovery sequence (as explained in RM0090
+                // Reference Manual, Chapter 32.7.6).
+                self.registers.can_mcr.modify(CAN_MCR::INRQ::SET);
+                if !Can::wait_for(20000, || self.registers.can_msr.is_set(CAN_MSR::INAK)) {
+                    return Err(kernel::ErrorCode::FAIL);
+                }
+                self.enter_normal_mode()?;
+                self.deferred_action.set(AsyncAction::Restarted);
+                self.deferred_call.set();
+                Ok(())
+            }
+            CanState::Normal | CanState::RunningError(_) => Err(kernel::ErrorCode::ALREADY),
+            CanState::Sleep | CanState::Initialization | CanState::Parked => {
+                Err(kernel::ErrorCode::OFF)
+            }
+        }
+    }
 }
 
 impl can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
@@ -1316,17 +1884,65 @@ impl can::Transmit<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
             &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
         ),
     > {
+        self.send_with_flags(id, buffer, len, can::FrameFlags::CLASSIC)
+    }
+
+    fn send_with_flags(
+        &self,
+        id: can::Id,
+        buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        len: usize,
+        flags: can::FrameFlags,
+    ) -> Result<
+        (),
+        (
+            kernel::ErrorCode,
+            &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        ),
+    > {
+        if flags.fd {
+            return Err((kernel::ErrorCode::NOSUPPORT, buffer));
+        }
         match self.can_state.get() {
+            CanState::RunningError(can::Error::BusOff)
+                if !self.automatic_bus_off_recovery.get() =>
+            {
+                // waiting for `restart`
+                Err((kernel::ErrorCode::OFF, buffer))
+            }
             CanState::Normal | CanState::RunningError(_) => {
-                self.tx_buffer.replace(buffer);
+                if self.tx_waiting.buffer.is_some() {
+                    // a frame is already waiting for a mailbox
+                    self.failed_messages.replace(self.failed_messages.get() + 1);
+                    self.count(Counter::TxDropped);
+                    return Err((kernel::ErrorCode::BUSY, buffer));
+                }
+                self.enable_irq(CanInterruptMode::ErrorAndStatusChangeInterrupt);
                 self.enable_irq(CanInterruptMode::TransmitInterrupt);
-                self.can_state.set(CanState::Normal);
-                match self.send_8byte_message(id, len, 0) {
-                    Ok(()) => Ok(()),
-                    Err(err) => Err((err, self.tx_buffer.take().unwrap())),
+                if !self.is_bus_off() {
+                    self.can_state.set(CanState::Normal);
+                }
+                if let Some(tx_mailbox) = self.find_empty_mailbox() {
+                    self.tx_mailboxes[tx_mailbox].set(id, buffer, len, flags.remote);
+                    self.load_mailbox(tx_mailbox);
+                    Ok(())
+                } else if let Some(tx_mailbox) = self.preemptible_mailbox(id, flags.remote) {
+                    // abort the lowest priority frame, it is requeued once
+                    // the abort completes
+                    self.tx_waiting.set(id, buffer, len, flags.remote);
+                    self.tx_preempted.set(tx_mailbox);
+                    self.registers.can_tsr.write(TSR_ABRQ[tx_mailbox].val(1));
+                    Ok(())
+                } else {
+                    // no mailbox empty
+                    self.failed_messages.replace(self.failed_messages.get() + 1);
+                    self.count(Counter::TxDropped);
+                    Err((kernel::ErrorCode::BUSY, buffer))
                 }
             }
-            CanState::Sleep | CanState::Initialization => Err((kernel::ErrorCode::OFF, buffer)),
+            CanState::Sleep | CanState::Initialization | CanState::Parked => {
+                Err((kernel::ErrorCode::OFF, buffer))
+            }
         }
     }
 }
@@ -1356,31 +1972,17 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
         match self.can_state.get() {
             CanState::Normal | CanState::RunningError(_) => {
                 self.can_state.set(CanState::Normal);
-                self.config_filter(
-                    can::FilterParameters {
-                        number: 0,
-                        scale_bits: can::ScaleBits::Bits32,
-                        identifier_mode: can::IdentifierMode::Mask,
-                        fifo_number: 0,
-                    },
-                    true,
-                );
-                self.config_filter(
-                    can::FilterParameters {
-                        number: 1,
-                        scale_bits: can::ScaleBits::Bits32,
-                        identifier_mode: can::IdentifierMode::Mask,
-                        fifo_number: 1,
-                    },
-                    true,
-                );
-                self.enable_filter_config();
+                if self.filters.get() == 0 {
+                    self.set_default_filters(true);
+                }
                 self.enable_irq(CanInterruptMode::Fifo0Interrupt);
                 self.enable_irq(CanInterruptMode::Fifo1Interrupt);
                 self.rx_buffer.put(Some(buffer));
                 Ok(())
             }
-            CanState::Sleep | CanState::Initialization => Err((kernel::ErrorCode::OFF, buffer)),
+            CanState::Sleep | CanState::Initialization | CanState::Parked => {
+                Err((kernel::ErrorCode::OFF, buffer))
+            }
         }
     }
 
@@ -1388,25 +1990,9 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
         match self.can_state.get() {
             CanState::Normal | CanState::RunningError(_) => {
                 self.can_state.set(CanState::Normal);
-                self.config_filter(
-                    can::FilterParameters {
-                        number: 0,
-                        scale_bits: can::ScaleBits::Bits32,
-                        identifier_mode: can::IdentifierMode::Mask,
-                        fifo_number: 0,
-                    },
-                    false,
-                );
-                self.config_filter(
-                    can::FilterParameters {
-                        number: 1,
-                        scale_bits: can::ScaleBits::Bits32,
-                        identifier_mode: can::IdentifierMode::Mask,
-                        fifo_number: 1,
-                    },
-                    false,
-                );
-                self.enable_filter_config();
+                if self.default_filters.get() {
+                    self.set_default_filters(false);
+                }
                 self.disable_irq(CanInterruptMode::Fifo0Interrupt);
                 self.disable_irq(CanInterruptMode::Fifo1Interrupt);
                 // there is another deferred action that must be completed
@@ -1422,7 +2008,200 @@ impl can::Receive<{ can::STANDARD_CAN_PACKET_SIZE }> for Can<'_> {
                     Ok(())
                 }
             }
-            CanState::Sleep | CanState::Initialization => Err(kernel::ErrorCode::OFF),
+            CanState::Sleep | CanState::Initialization | CanState::Parked => {
+                Err(kernel::ErrorCode::OFF)
+            }
+        }
+    }
+}
+
+impl can::Filter for Can<'_> {
+    fn enable_filter(&self, filter: can::FilterParameters) -> Result<(), kernel::ErrorCode> {
+        if filter.number as usize >= self.filter_count() || filter.fifo_number >= RX_MAILBOX_COUNT {
+            return Err(kernel::ErrorCode::INVAL);
         }
+        filter_bank_values(&filter)?;
+        if self.default_filters.get() {
+            self.set_default_filters(false);
+        }
+        self.config_filter(filter, true)?;
+        self.enable_filter_config();
+        self.filters.set(self.filters.get() | 1 << filter.number);
+        Ok(())
+    }
+
+    fn disable_filter(&self, number: u32) -> Result<(), kernel::ErrorCode> {
+        if number as usize >= self.filter_count() {
+            return Err(kernel::ErrorCode::INVAL);
+        }
+        if self.filters.get() & 1 << number == 0 {
+            return Err(kernel::ErrorCode::ALREADY);
+        }
+        self.deactivate_filter(number);
+        self.filters.set(self.filters.get() & !(1 << number));
+        // the receive buffer is owned by the driver while receiving
+        if self.filters.get() == 0 && self.rx_buffer.is_some() {
+            self.set_default_filters(true);
+        }
+        Ok(())
+    }
+
+    fn filter_count(&self) -> usize {
+        self.registers.can_fmr.read(CAN_FMR::CANSB) as usize
+    }
+}
+
+impl can::Diagnostics for Can<'_> {
+    fn bus_status(&self) -> Result<can::BusStatus, kernel::ErrorCode> {
+        if !self.clock.is_enabled() {
+            return Err(kernel::ErrorCode::OFF);
+        }
+        let esr = self.registers.can_esr.extract();
+        let bus_off = esr.is_set(CAN_ESR::BOFF);
+        let state = if bus_off {
+            can::ErrorState::BusOff
+        } else if esr.is_set(CAN_ESR::EPVF) {
+            can::ErrorState::Passive
+        } else if esr.is_set(CAN_ESR::EWGF) {
+            can::ErrorState::Warning
+        } else {
+            can::ErrorState::Active
+        };
+        // the register only holds the low byte of the 9-bit transmit error
+        // counter, whose high bit is set in the bus-off state
+        let transmit_error_count = esr.read(CAN_ESR::TEC) as u16 | (bus_off as u16) << 8;
+        Ok(can::BusStatus {
+            transmit_error_count,
+            receive_error_count: esr.read(CAN_ESR::REC) as u16,
+            state,
+            last_error: last_error(esr.read(CAN_ESR::LEC)),
+        })
+    }
+}
+
+// Bus errors reported by the error interrupt are counted as receive errors,
+// failed transmissions as transmit errors.
+impl NetworkStatistics for Can<'_> {
+    fn interface_name(&self) -> &'static str {
+        "can1"
+    }
+
+    fn counters(&self) -> Counters {
+        self.counters.get()
+    }
+
+    fn reset_counters(&self) {
+        self.counters.set(Counters::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::utilities::registers::assert_register_fields;
+
+    #[test]
+    fn register_fields() {
+        assert_register_fields!(disjoint u32, [
+            CAN_MCR, CAN_MSR, CAN_TSR, CAN_RF0R, CAN_RF1R, CAN_IER, CAN_ESR, CAN_BTR,
+            CAN_TIxR, CAN_TDTxR, CAN_TDLxR, CAN_TDHxR, CAN_RIxR, CAN_RDTxR, CAN_RDLxR,
+            CAN_RDHxR, CAN_FMR, CAN_FM1R, CAN_FS1R, CAN_FFA1R, CAN_FA1R, CAN_FiRx,
+        ]);
+    }
+
+    fn filter(
+        scale_bits: can::ScaleBits,
+        identifier_mode: can::IdentifierMode,
+        id: can::Id,
+        mask: u32,
+    ) -> can::FilterParameters {
+        can::FilterParameters {
+            number: 2,
+            scale_bits,
+            identifier_mode,
+            fifo_number: 0,
+            id,
+            mask,
+        }
+    }
+
+    #[test]
+    fn arbitration_order() {
+        use can::Id;
+
+        let frames = [
+            arbitration_value(Id::Standard(0x100), false),
+            arbitration_value(Id::Standard(0x100), true),
+            arbitration_value(Id::Extended(0x100 << 18), false),
+            arbitration_value(Id::Extended(0x100 << 18), true),
+            arbitration_value(Id::Extended(0x100 << 18 | 1), false),
+            arbitration_value(Id::Standard(0x101), false),
+        ];
+        assert!(frames.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn last_error_codes() {
+        assert_eq!(last_error(0), None);
+        assert_eq!(last_error(3), Some(can::Error::Ack));
+        assert_eq!(last_error(7), Some(can::Error::SetBySoftware));
+        assert_eq!(
+            last_error(CAN_ESR::LEC::Value::CrcError as u32),
+            Some(can::Error::Crc)
+        );
+    }
+
+    #[test]
+    fn filter_bank_layouts() {
+        use can::{Id, IdentifierMode, ScaleBits};
+
+        // accept-all filter
+        let accept_all = filter(ScaleBits::Bits32, IdentifierMode::Mask, Id::Standard(0), 0);
+        assert_eq!(filter_bank_values(&accept_all), Ok((0, 0)));
+
+        let standard = filter(
+            ScaleBits::Bits32,
+            IdentifierMode::Mask,
+            Id::Standard(0x123),
+            0x7ff,
+        );
+        assert_eq!(
+            filter_bank_values(&standard),
+            Ok((0x123 << 21, 0x7ff << 21 | FILTER32_IDE))
+        );
+        let list = filter(
+            ScaleBits::Bits32,
+            IdentifierMode::List,
+            Id::Extended(0x1234567),
+            0x89abcde,
+        );
+        assert_eq!(
+            filter_bank_values(&list),
+            Ok((0x1234567 << 3 | FILTER32_IDE, 0x89abcde << 3 | FILTER32_IDE))
+        );
+        let list16 = filter(
+            ScaleBits::Bits16,
+            IdentifierMode::List,
+            Id::Standard(0x10),
+            0x20,
+        );
+        assert_eq!(
+            filter_bank_values(&list16),
+            Ok((0x20 << 21 | 0x10 << 5, 0x20 << 21 | 0x10 << 5))
+        );
+
+        // identifiers out of range, or too wide for the scale
+        let wide = filter(
+            ScaleBits::Bits32,
+            IdentifierMode::List,
+            Id::Standard(0x10),
+            0x800,
+        );
+        assert_eq!(filter_bank_values(&wide), Err(kernel::ErrorCode::INVAL));
+        let extended16 = filter(ScaleBits::Bits16, IdentifierMode::Mask, Id::Extended(1), 0);
+        assert_eq!(
+            filter_bank_values(&extended16),
+            Err(kernel::ErrorCode::INVAL)
+        );
     }
 }
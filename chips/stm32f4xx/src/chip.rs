@@ -33,6 +33,12 @@ pub struct Stm32f4xxDefaultPeripherals<'a, ChipSpecs> {
     pub clocks: &'a crate::clocks::Clocks<'a, ChipSpecs>,
     pub spi3: crate::spi::Spi<'a>,
     pub tim2: crate::tim2::Tim2<'a>,
+    pub tim3: crate::tim::Tim<'a>,
+    pub tim4: crate::tim::Tim<'a>,
+    pub tim5: crate::tim::Tim<'a>,
+    pub tim9: crate::tim::Tim<'a>,
+    pub tim10: crate::tim::Tim<'a>,
+    pub tim11: crate::tim::Tim<'a>,
     pub usart1: crate::usart::Usart<'a, dma::Dma2<'a>>,
     pub usart2: crate::usart::Usart<'a, dma::Dma1<'a>>,
     pub usart3: crate::usart::Usart<'a, dma::Dma1<'a>>,
@@ -76,6 +82,12 @@ impl<'a, ChipSpecs: ChipSpecsTrait> Stm32f4xxDefaultPeripherals<'a, ChipSpecs> {
                 dma::Dma1Peripheral::SPI3_RX,
             ),
             tim2: crate::tim2::Tim2::new(clocks),
+            tim3: crate::tim::Tim::new_tim3(clocks),
+            tim4: crate::tim::Tim::new_tim4(clocks),
+            tim5: crate::tim::Tim::new_tim5(clocks),
+            tim9: crate::tim::Tim::new_tim9(clocks),
+            tim10: crate::tim::Tim::new_tim10(clocks),
+            tim11: crate::tim::Tim::new_tim11(clocks),
             usart1: crate::usart::Usart::new_usart1(clocks),
             usart2: crate::usart::Usart::new_usart2(clocks),
             usart3: crate::usart::Usart::new_usart3(clocks),
@@ -120,6 +132,9 @@ impl<'a, ChipSpecs: ChipSpecsTrait> InterruptService
                 self.dma1_streams[dma::Dma1Peripheral::SPI3_TX.get_stream_idx()].handle_interrupt()
             }
 
+            nvic::DMA2_Stream0 => {
+                self.dma2_streams[dma::Dma2Peripheral::ADC1.get_stream_idx()].handle_interrupt()
+            }
             nvic::DMA2_Stream5 => self.dma2_streams
                 [dma::Dma2Peripheral::USART1_RX.get_stream_idx()]
             .handle_interrupt(),
@@ -138,15 +153,21 @@ impl<'a, ChipSpecs: ChipSpecsTrait> InterruptService
 
             nvic::SPI3 => self.spi3.handle_interrupt(),
 
-            nvic::EXTI0 => self.exti.handle_interrupt(),
-            nvic::EXTI1 => self.exti.handle_interrupt(),
-            nvic::EXTI2 => self.exti.handle_interrupt(),
-            nvic::EXTI3 => self.exti.handle_interrupt(),
-            nvic::EXTI4 => self.exti.handle_interrupt(),
-            nvic::EXTI9_5 => self.exti.handle_interrupt(),
-            nvic::EXTI15_10 => self.exti.handle_interrupt(),
+            nvic::EXTI0 => self.exti.handle_interrupt(nvic::EXTI0),
+            nvic::EXTI1 => self.exti.handle_interrupt(nvic::EXTI1),
+            nvic::EXTI2 => self.exti.handle_interrupt(nvic::EXTI2),
+            nvic::EXTI3 => self.exti.handle_interrupt(nvic::EXTI3),
+            nvic::EXTI4 => self.exti.handle_interrupt(nvic::EXTI4),
+            nvic::EXTI9_5 => self.exti.handle_interrupt(nvic::EXTI9_5),
+            nvic::EXTI15_10 => self.exti.handle_interrupt(nvic::EXTI15_10),
 
             nvic::TIM2 => self.tim2.handle_interrupt(),
+            nvic::TIM3 => self.tim3.handle_interrupt(),
+            nvic::TIM4 => self.tim4.handle_interrupt(),
+            nvic::TIM5 => self.tim5.handle_interrupt(),
+            nvic::TIM1_BRK_TIM9 => self.tim9.handle_interrupt(),
+            nvic::TIM1_UP_TIM10 => self.tim10.handle_interrupt(),
+            nvic::TIM1_TRG_COM_TIM11 => self.tim11.handle_interrupt(),
 
             _ => return false,
         }
@@ -175,6 +196,7 @@ impl<'a, I: InterruptService + 'a> Chip for Stm32f4xx<'a, I> {
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt {}", interrupt);
                     }
+                    cortexm4::interrupt_latency::complete(interrupt);
 
                     let n = cortexm4::nvic::Nvic::new(interrupt);
                     n.clear_pending();
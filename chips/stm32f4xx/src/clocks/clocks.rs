@@ -547,6 +547,9 @@ pub trait Stm32f4Clocks {
     /// Get current AHB clock (HCLK) frequency in Hz
     fn get_ahb_frequency(&self) -> usize;
 
+    /// Get the frequency of the PLL source clock (HSI or HSE) in Hz, if known
+    fn get_pll_source_frequency(&self) -> Option<usize>;
+
     // Extend this to expose additional clock resources
 }
 
@@ -558,6 +561,14 @@ impl<'a, ChipSpecs: ChipSpecsTrait> Stm32f4Clocks for Clocks<'a, ChipSpecs> {
     fn get_ahb_frequency(&self) -> usize {
         self.get_ahb_frequency_mhz() * 1_000_000
     }
+
+    fn get_pll_source_frequency(&self) -> Option<usize> {
+        let frequency_mhz = match self.rcc.get_pll_clocks_source() {
+            PllSource::HSI => Some(HSI_FREQUENCY_MHZ),
+            PllSource::HSE => self.hse.get_frequency_mhz(),
+        };
+        frequency_mhz.map(|frequency_mhz| frequency_mhz * 1_000_000)
+    }
 }
 
 /// Tests for clocks functionalities
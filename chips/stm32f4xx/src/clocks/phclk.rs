@@ -3,8 +3,9 @@
 // Copyright Tock Contributors 2022.
 
 use crate::clocks::Stm32f4Clocks;
-use crate::rcc::{APBPrescaler, Rcc, RtcClockSource};
+use crate::rcc::{APBPrescaler, PllSaiDivR, Rcc, RtcClockSource};
 use kernel::platform::chip::ClockInterface;
+use kernel::ErrorCode;
 
 pub struct PeripheralClock<'a> {
     pub clock: PeripheralClockType,
@@ -26,6 +27,7 @@ pub enum PeripheralClockType {
 pub enum HCLK1 {
     DMA1,
     DMA2,
+    DMA2D,
     GPIOH,
     GPIOG,
     GPIOF,
@@ -50,6 +52,12 @@ pub enum HCLK2 {
 /// Peripherals clocked by PCLK1
 pub enum PCLK1 {
     TIM2,
+    TIM3,
+    TIM4,
+    TIM5,
+    TIM12,
+    TIM13,
+    TIM14,
     USART2,
     USART3,
     SPI3,
@@ -63,6 +71,11 @@ pub enum PCLK2 {
     USART1,
     ADC1,
     SYSCFG,
+    LTDC,
+    SAI1,
+    TIM9,
+    TIM10,
+    TIM11,
 }
 
 impl<'a> PeripheralClock<'a> {
@@ -74,6 +87,120 @@ impl<'a> PeripheralClock<'a> {
         self.clocks.get_rcc().configure_rng_clock();
     }
 
+    /// Keep (or stop) the CAN1 clock running while the core is in Sleep mode.
+    pub fn configure_can1_sleep_clock(&self, enable: bool) {
+        self.clocks.get_rcc().configure_can1_sleep_clock(enable);
+    }
+
+    /// Configure the PLLSAI to generate the LCD pixel clock as close as
+    /// possible to `frequency` Hz, and return the actual frequency.
+    ///
+    /// The PLLSAI shares its input divider with the main PLL. Returns
+    /// `INVAL` if no configuration is within 5% of `frequency`, and `FAIL` if
+    /// the PLLSAI does not lock.
+    pub fn configure_ltdc_pixel_clock(&self, frequency: usize) -> Result<usize, ErrorCode> {
+        const DIVIDERS_R: [PllSaiDivR; 4] = [
+            PllSaiDivR::DivideBy2,
+            PllSaiDivR::DivideBy4,
+            PllSaiDivR::DivideBy8,
+            PllSaiDivR::DivideBy16,
+        ];
+
+        let rcc = self.clocks.get_rcc();
+        let vco_input_frequency = self
+            .clocks
+            .get_pll_source_frequency()
+            .ok_or(ErrorCode::FAIL)?
+            / rcc.get_pll_clocks_m_divider() as usize;
+
+        // (error, n, r, div_r, actual frequency)
+        let mut best: Option<(usize, usize, usize, PllSaiDivR, usize)> = None;
+        for div_r in DIVIDERS_R {
+            for r in 2..=7 {
+                let divider = r * usize::from(div_r);
+                let n = (frequency * divider + vco_input_frequency / 2) / vco_input_frequency;
+                let vco_output_frequency = vco_input_frequency * n;
+                // Reference Manual RM0090 section 6.3.24
+                if !(50..=432).contains(&n)
+                    || !(100_000_000..=432_000_000).contains(&vco_output_frequency)
+                {
+                    continue;
+                }
+                let actual = vco_output_frequency / divider;
+                let error = actual.abs_diff(frequency);
+                if best.map_or(true, |(best_error, ..)| error < best_error) {
+                    best = Some((error, n, r, div_r, actual));
+                }
+            }
+        }
+
+        let (error, n, r, div_r, actual) = best.ok_or(ErrorCode::INVAL)?;
+        if error > frequency / 20 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        rcc.disable_pllsai_clock();
+        rcc.configure_pllsai_lcd_clock(n, r, div_r);
+        rcc.enable_pllsai_clock();
+        for _ in 0..100_000 {
+            if rcc.is_locked_pllsai_clock() {
+                return Ok(actual);
+            }
+        }
+        Err(ErrorCode::FAIL)
+    }
+
+    /// Configure the PLLI2S to generate the SAI1 block A kernel clock as
+    /// close as possible to `frequency` Hz, and return the actual frequency.
+    ///
+    /// The PLLI2S shares its input divider with the main PLL. Returns
+    /// `INVAL` if no configuration is within 1% of `frequency`, and `FAIL` if
+    /// the PLLI2S does not lock.
+    pub fn configure_sai1_clock(&self, frequency: usize) -> Result<usize, ErrorCode> {
+        let rcc = self.clocks.get_rcc();
+        let vco_input_frequency = self
+            .clocks
+            .get_pll_source_frequency()
+            .ok_or(ErrorCode::FAIL)?
+            / rcc.get_pll_clocks_m_divider() as usize;
+
+        // (error, n, q, div_q, actual frequency)
+        let mut best: Option<(usize, usize, usize, usize, usize)> = None;
+        for div_q in 1..=32 {
+            for q in 2..=15 {
+                let divider = q * div_q;
+                let n = (frequency * divider + vco_input_frequency / 2) / vco_input_frequency;
+                let vco_output_frequency = vco_input_frequency * n;
+                // Reference Manual RM0090 section 6.3.23
+                if !(50..=432).contains(&n)
+                    || !(100_000_000..=432_000_000).contains(&vco_output_frequency)
+                {
+                    continue;
+                }
+                let actual = vco_output_frequency / divider;
+                let error = actual.abs_diff(frequency);
+                if best.map_or(true, |(best_error, ..)| error < best_error) {
+                    best = Some((error, n, q, div_q, actual));
+                }
+            }
+        }
+
+        let (error, n, q, div_q, actual) = best.ok_or(ErrorCode::INVAL)?;
+        if error > frequency / 100 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        rcc.disable_plli2s_clock();
+        rcc.configure_plli2s_sai1_clock(n, q, div_q);
+        rcc.enable_plli2s_clock();
+        for _ in 0..100_000 {
+            if rcc.is_locked_plli2s_clock() {
+                return Ok(actual);
+            }
+        }
+        Err(ErrorCode::FAIL)
+    }
+
     pub fn get_frequency(&self) -> u32 {
         #[inline(always)]
         fn tim_freq(rcc: &Rcc, hclk_freq: usize, prescaler: APBPrescaler) -> usize {
@@ -105,13 +232,24 @@ impl<'a> PeripheralClock<'a> {
             PeripheralClockType::APB1(ref v) => {
                 let prescaler = rcc.get_apb1_prescaler();
                 match v {
-                    PCLK1::TIM2 => tim_freq(rcc, hclk_freq, prescaler) as u32,
+                    PCLK1::TIM2
+                    | PCLK1::TIM3
+                    | PCLK1::TIM4
+                    | PCLK1::TIM5
+                    | PCLK1::TIM12
+                    | PCLK1::TIM13
+                    | PCLK1::TIM14 => tim_freq(rcc, hclk_freq, prescaler) as u32,
                     _ => (hclk_freq / usize::from(prescaler)) as u32,
                 }
             }
-            PeripheralClockType::APB2(_) => {
+            PeripheralClockType::APB2(ref v) => {
                 let prescaler = rcc.get_apb2_prescaler();
-                (hclk_freq / usize::from(prescaler)) as u32
+                match v {
+                    PCLK2::TIM9 | PCLK2::TIM10 | PCLK2::TIM11 => {
+                        tim_freq(rcc, hclk_freq, prescaler) as u32
+                    }
+                    _ => (hclk_freq / usize::from(prescaler)) as u32,
+                }
             }
             //TODO: implement clock frequency retrieval for RTC and PWR peripherals
             PeripheralClockType::RTC => todo!(),
@@ -127,6 +265,7 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
             PeripheralClockType::AHB1(ref v) => match v {
                 HCLK1::DMA1 => rcc.is_enabled_dma1_clock(),
                 HCLK1::DMA2 => rcc.is_enabled_dma2_clock(),
+                HCLK1::DMA2D => rcc.is_enabled_dma2d_clock(),
                 HCLK1::GPIOH => rcc.is_enabled_gpioh_clock(),
                 HCLK1::GPIOG => rcc.is_enabled_gpiog_clock(),
                 HCLK1::GPIOF => rcc.is_enabled_gpiof_clock(),
@@ -145,6 +284,12 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
             },
             PeripheralClockType::APB1(ref v) => match v {
                 PCLK1::TIM2 => rcc.is_enabled_tim2_clock(),
+                PCLK1::TIM3 => rcc.is_enabled_tim3_clock(),
+                PCLK1::TIM4 => rcc.is_enabled_tim4_clock(),
+                PCLK1::TIM5 => rcc.is_enabled_tim5_clock(),
+                PCLK1::TIM12 => rcc.is_enabled_tim12_clock(),
+                PCLK1::TIM13 => rcc.is_enabled_tim13_clock(),
+                PCLK1::TIM14 => rcc.is_enabled_tim14_clock(),
                 PCLK1::USART2 => rcc.is_enabled_usart2_clock(),
                 PCLK1::USART3 => rcc.is_enabled_usart3_clock(),
                 PCLK1::I2C1 => rcc.is_enabled_i2c1_clock(),
@@ -156,6 +301,11 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK2::USART1 => rcc.is_enabled_usart1_clock(),
                 PCLK2::ADC1 => rcc.is_enabled_adc1_clock(),
                 PCLK2::SYSCFG => rcc.is_enabled_syscfg_clock(),
+                PCLK2::LTDC => rcc.is_enabled_ltdc_clock(),
+                PCLK2::SAI1 => rcc.is_enabled_sai1_clock(),
+                PCLK2::TIM9 => rcc.is_enabled_tim9_clock(),
+                PCLK2::TIM10 => rcc.is_enabled_tim10_clock(),
+                PCLK2::TIM11 => rcc.is_enabled_tim11_clock(),
             },
             PeripheralClockType::RTC => rcc.is_enabled_rtc_clock(),
             PeripheralClockType::PWR => rcc.is_enabled_pwr_clock(),
@@ -172,6 +322,9 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 HCLK1::DMA2 => {
                     rcc.enable_dma2_clock();
                 }
+                HCLK1::DMA2D => {
+                    rcc.enable_dma2d_clock();
+                }
                 HCLK1::GPIOH => {
                     rcc.enable_gpioh_clock();
                 }
@@ -212,6 +365,24 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK1::TIM2 => {
                     rcc.enable_tim2_clock();
                 }
+                PCLK1::TIM3 => {
+                    rcc.enable_tim3_clock();
+                }
+                PCLK1::TIM4 => {
+                    rcc.enable_tim4_clock();
+                }
+                PCLK1::TIM5 => {
+                    rcc.enable_tim5_clock();
+                }
+                PCLK1::TIM12 => {
+                    rcc.enable_tim12_clock();
+                }
+                PCLK1::TIM13 => {
+                    rcc.enable_tim13_clock();
+                }
+                PCLK1::TIM14 => {
+                    rcc.enable_tim14_clock();
+                }
                 PCLK1::USART2 => {
                     rcc.enable_usart2_clock();
                 }
@@ -241,6 +412,21 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK2::SYSCFG => {
                     rcc.enable_syscfg_clock();
                 }
+                PCLK2::LTDC => {
+                    rcc.enable_ltdc_clock();
+                }
+                PCLK2::SAI1 => {
+                    rcc.enable_sai1_clock();
+                }
+                PCLK2::TIM9 => {
+                    rcc.enable_tim9_clock();
+                }
+                PCLK2::TIM10 => {
+                    rcc.enable_tim10_clock();
+                }
+                PCLK2::TIM11 => {
+                    rcc.enable_tim11_clock();
+                }
             },
             PeripheralClockType::RTC => rcc.enable_rtc_clock(RtcClockSource::LSI),
             PeripheralClockType::PWR => rcc.enable_pwr_clock(),
@@ -257,6 +443,9 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 HCLK1::DMA2 => {
                     rcc.disable_dma2_clock();
                 }
+                HCLK1::DMA2D => {
+                    rcc.disable_dma2d_clock();
+                }
                 HCLK1::GPIOH => {
                     rcc.disable_gpioh_clock();
                 }
@@ -297,6 +486,24 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK1::TIM2 => {
                     rcc.disable_tim2_clock();
                 }
+                PCLK1::TIM3 => {
+                    rcc.disable_tim3_clock();
+                }
+                PCLK1::TIM4 => {
+                    rcc.disable_tim4_clock();
+                }
+                PCLK1::TIM5 => {
+                    rcc.disable_tim5_clock();
+                }
+                PCLK1::TIM12 => {
+                    rcc.disable_tim12_clock();
+                }
+                PCLK1::TIM13 => {
+                    rcc.disable_tim13_clock();
+                }
+                PCLK1::TIM14 => {
+                    rcc.disable_tim14_clock();
+                }
                 PCLK1::USART2 => {
                     rcc.disable_usart2_clock();
                 }
@@ -326,6 +533,21 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK2::SYSCFG => {
                     rcc.disable_syscfg_clock();
                 }
+                PCLK2::LTDC => {
+                    rcc.disable_ltdc_clock();
+                }
+                PCLK2::SAI1 => {
+                    rcc.disable_sai1_clock();
+                }
+                PCLK2::TIM9 => {
+                    rcc.disable_tim9_clock();
+                }
+                PCLK2::TIM10 => {
+                    rcc.disable_tim10_clock();
+                }
+                PCLK2::TIM11 => {
+                    rcc.disable_tim11_clock();
+                }
             },
             PeripheralClockType::RTC => rcc.disable_rtc_clock(),
             PeripheralClockType::PWR => rcc.disable_pwr_clock(),
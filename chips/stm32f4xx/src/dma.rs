@@ -10,6 +10,7 @@ use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeabl
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 
+use crate::adc;
 use crate::clocks::{phclk, Stm32f4Clocks};
 use crate::nvic;
 use crate::spi;
@@ -1581,6 +1582,7 @@ impl<'a> StreamServer<'a> for Dma1<'a> {
 pub enum Dma2Peripheral {
     USART1_TX,
     USART1_RX,
+    ADC1,
 }
 
 impl Dma2Peripheral {
@@ -1590,6 +1592,7 @@ impl Dma2Peripheral {
         match self {
             Dma2Peripheral::USART1_TX => nvic::DMA2_Stream7,
             Dma2Peripheral::USART1_RX => nvic::DMA2_Stream5, // could also be Stream 2, chosen arbitrarily
+            Dma2Peripheral::ADC1 => nvic::DMA2_Stream0, // could also be Stream 4, chosen arbitrarily
         }
     }
 
@@ -1603,17 +1606,25 @@ impl From<Dma2Peripheral> for StreamId {
         match pid {
             Dma2Peripheral::USART1_TX => StreamId::Stream7,
             Dma2Peripheral::USART1_RX => StreamId::Stream5,
+            Dma2Peripheral::ADC1 => StreamId::Stream0,
         }
     }
 }
 
 impl StreamPeripheral for Dma2Peripheral {
     fn transfer_mode(&self) -> TransferMode {
-        TransferMode::Fifo(FifoSize::Full)
+        match self {
+            // Samples are written to memory as soon as they are converted
+            Dma2Peripheral::ADC1 => TransferMode::Direct,
+            _ => TransferMode::Fifo(FifoSize::Full),
+        }
     }
 
     fn data_width(&self) -> (Msize, Psize) {
-        (Msize(Size::Byte), Psize(Size::Byte))
+        match self {
+            Dma2Peripheral::ADC1 => (Msize(Size::HalfWord), Psize(Size::HalfWord)),
+            _ => (Msize(Size::Byte), Psize(Size::Byte)),
+        }
     }
 
     fn channel_id(&self) -> ChannelId {
@@ -1622,6 +1633,8 @@ impl StreamPeripheral for Dma2Peripheral {
             Dma2Peripheral::USART1_TX => ChannelId::Channel4,
             // USART1_RX Stream 5, Channel 4
             Dma2Peripheral::USART1_RX => ChannelId::Channel4,
+            // ADC1 Stream 0, Channel 0
+            Dma2Peripheral::ADC1 => ChannelId::Channel0,
         }
     }
 
@@ -1629,6 +1642,7 @@ impl StreamPeripheral for Dma2Peripheral {
         match self {
             Dma2Peripheral::USART1_TX => Direction::MemoryToPeripheral,
             Dma2Peripheral::USART1_RX => Direction::PeripheralToMemory,
+            Dma2Peripheral::ADC1 => Direction::PeripheralToMemory,
         }
     }
 
@@ -1636,6 +1650,7 @@ impl StreamPeripheral for Dma2Peripheral {
         match self {
             Dma2Peripheral::USART1_TX => usart::get_address_dr(usart::USART1_BASE),
             Dma2Peripheral::USART1_RX => usart::get_address_dr(usart::USART1_BASE),
+            Dma2Peripheral::ADC1 => adc::get_address_dr(),
         }
     }
 }
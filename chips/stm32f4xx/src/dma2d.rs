@@ -0,0 +1,431 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Chrom-ART Accelerator (DMA2D) driver.
+//!
+//! The DMA2D is a 2D DMA engine available on STM32F42x/F43x (and with the
+//! same register layout on STM32F7) parts. This driver uses it to fill and
+//! copy rectangles in a framebuffer, and exposes these operations through
+//! `hil::screen::ScreenAccelerated`.
+//!
+//! The framebuffer is owned by the display driver, which must tell the DMA2D
+//! where to draw with [`Dma2d::set_framebuffer`], for example after swapping
+//! buffers.
+
+use crate::clocks::{phclk, Stm32f4Clocks};
+use core::cell::Cell;
+use kernel::hil::screen::{ScreenAccelerated, ScreenAcceleratedClient, ScreenPixelFormat};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+#[repr(C)]
+pub struct Dma2dRegisters {
+    /// control register
+    cr: ReadWrite<u32, CR::Register>,
+    /// interrupt status register
+    isr: ReadWrite<u32, ISR::Register>,
+    /// interrupt flag clear register
+    ifcr: WriteOnly<u32, IFCR::Register>,
+    /// foreground memory address register
+    fgmar: ReadWrite<u32>,
+    /// foreground offset register
+    fgor: ReadWrite<u32, OR::Register>,
+    /// background memory address register
+    bgmar: ReadWrite<u32>,
+    /// background offset register
+    bgor: ReadWrite<u32, OR::Register>,
+    /// foreground PFC control register
+    fgpfccr: ReadWrite<u32, PFCCR::Register>,
+    /// foreground color register
+    fgcolr: ReadWrite<u32>,
+    /// background PFC control register
+    bgpfccr: ReadWrite<u32, PFCCR::Register>,
+    /// background color register
+    bgcolr: ReadWrite<u32>,
+    /// foreground CLUT memory address register
+    fgcmar: ReadWrite<u32>,
+    /// background CLUT memory address register
+    bgcmar: ReadWrite<u32>,
+    /// output PFC control register
+    opfccr: ReadWrite<u32, OPFCCR::Register>,
+    /// output color register
+    ocolr: ReadWrite<u32>,
+    /// output memory address register
+    omar: ReadWrite<u32>,
+    /// output offset register
+    oor: ReadWrite<u32, OR::Register>,
+    /// number of line register
+    nlr: ReadWrite<u32, NLR::Register>,
+    /// line watermark register
+    lwr: ReadWrite<u32>,
+    /// AHB master timer configuration register
+    amtcr: ReadWrite<u32>,
+}
+
+register_bitfields![u32,
+    CR [
+        /// DMA2D mode
+        MODE OFFSET(16) NUMBITS(2) [
+            MemoryToMemory = 0,
+            MemoryToMemoryPfc = 1,
+            MemoryToMemoryBlending = 2,
+            RegisterToMemory = 3
+        ],
+        /// Configuration error interrupt enable
+        CEIE OFFSET(13) NUMBITS(1) [],
+        /// CLUT transfer complete interrupt enable
+        CTCIE OFFSET(12) NUMBITS(1) [],
+        /// CLUT access error interrupt enable
+        CAEIE OFFSET(11) NUMBITS(1) [],
+        /// Transfer watermark interrupt enable
+        TWIE OFFSET(10) NUMBITS(1) [],
+        /// Transfer complete interrupt enable
+        TCIE OFFSET(9) NUMBITS(1) [],
+        /// Transfer error interrupt enable
+        TEIE OFFSET(8) NUMBITS(1) [],
+        /// Abort
+        ABORT OFFSET(2) NUMBITS(1) [],
+        /// Suspend
+        SUSP OFFSET(1) NUMBITS(1) [],
+        /// Start
+        START OFFSET(0) NUMBITS(1) []
+    ],
+    ISR [
+        /// Configuration error interrupt flag
+        CEIF OFFSET(5) NUMBITS(1) [],
+        /// CLUT transfer complete interrupt flag
+        CTCIF OFFSET(4) NUMBITS(1) [],
+        /// CLUT access error interrupt flag
+        CAEIF OFFSET(3) NUMBITS(1) [],
+        /// Transfer watermark interrupt flag
+        TWIF OFFSET(2) NUMBITS(1) [],
+        /// Transfer complete interrupt flag
+        TCIF OFFSET(1) NUMBITS(1) [],
+        /// Transfer error interrupt flag
+        TEIF OFFSET(0) NUMBITS(1) []
+    ],
+    IFCR [
+        /// Clear configuration error interrupt flag
+        CCEIF OFFSET(5) NUMBITS(1) [],
+        /// Clear CLUT transfer complete interrupt flag
+        CCTCIF OFFSET(4) NUMBITS(1) [],
+        /// Clear CLUT access error interrupt flag
+        CAECIF OFFSET(3) NUMBITS(1) [],
+        /// Clear transfer watermark interrupt flag
+        CTWIF OFFSET(2) NUMBITS(1) [],
+        /// Clear transfer complete interrupt flag
+        CTCIF OFFSET(1) NUMBITS(1) [],
+        /// Clear transfer error interrupt flag
+        CTEIF OFFSET(0) NUMBITS(1) []
+    ],
+    OR [
+        /// Line offset, in pixels
+        LO OFFSET(0) NUMBITS(14) []
+    ],
+    PFCCR [
+        /// Alpha value
+        ALPHA OFFSET(24) NUMBITS(8) [],
+        /// Alpha mode
+        AM OFFSET(16) NUMBITS(2) [],
+        /// CLUT size
+        CS OFFSET(8) NUMBITS(8) [],
+        /// Start CLUT loading
+        START OFFSET(5) NUMBITS(1) [],
+        /// CLUT color mode
+        CCM OFFSET(4) NUMBITS(1) [],
+        /// Color mode
+        CM OFFSET(0) NUMBITS(4) []
+    ],
+    OPFCCR [
+        /// Color mode
+        CM OFFSET(0) NUMBITS(3) []
+    ],
+    NLR [
+        /// Pixels per line
+        PL OFFSET(16) NUMBITS(14) [],
+        /// Number of lines
+        NL OFFSET(0) NUMBITS(16) []
+    ]
+];
+
+/// Color mode value shared by the foreground and output PFC registers.
+fn color_mode(format: ScreenPixelFormat) -> Option<u32> {
+    match format {
+        ScreenPixelFormat::ARGB_8888 => Some(0),
+        ScreenPixelFormat::RGB_888 => Some(1),
+        ScreenPixelFormat::RGB_565 => Some(2),
+        _ => None,
+    }
+}
+
+/// The memory the DMA2D draws into.
+#[derive(Copy, Clone)]
+struct Framebuffer {
+    address: usize,
+    width: usize,
+    height: usize,
+    format: ScreenPixelFormat,
+}
+
+impl Framebuffer {
+    /// Address of the first pixel of a rectangle, checking that the rectangle
+    /// fits in the framebuffer.
+    fn rectangle_address(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<usize, ErrorCode> {
+        if width == 0
+            || height == 0
+            || x.checked_add(width).map_or(true, |end| end > self.width)
+            || y.checked_add(height).map_or(true, |end| end > self.height)
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        let bytes_per_pixel = self.format.get_bits_per_pixel() / 8;
+        Ok(self.address + (y * self.width + x) * bytes_per_pixel)
+    }
+}
+
+pub struct Dma2d<'a> {
+    registers: StaticRef<Dma2dRegisters>,
+    clock: Dma2dClock<'a>,
+    client: OptionalCell<&'a dyn ScreenAcceleratedClient>,
+    framebuffer: OptionalCell<Framebuffer>,
+    busy: Cell<bool>,
+    blit_buffer: MapCell<SubSliceMut<'static, u8>>,
+}
+
+impl<'a> Dma2d<'a> {
+    pub fn new(registers: StaticRef<Dma2dRegisters>, clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self {
+            registers,
+            clock: Dma2dClock(phclk::PeripheralClock::new(
+                phclk::PeripheralClockType::AHB1(phclk::HCLK1::DMA2D),
+                clocks,
+            )),
+            client: OptionalCell::empty(),
+            framebuffer: OptionalCell::empty(),
+            busy: Cell::new(false),
+            blit_buffer: MapCell::empty(),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Set the framebuffer that fills and blits draw into.
+    ///
+    /// `width` and `height` are in pixels. Returns `NOSUPPORT` if the DMA2D
+    /// cannot output `format`, and `BUSY` while an operation is in progress.
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to `width * height` pixels of memory reachable by
+    /// the DMA2D and owned by the caller for as long as it is set, as the
+    /// DMA2D writes to it without any further check.
+    pub unsafe fn set_framebuffer(
+        &self,
+        address: *mut u8,
+        width: usize,
+        height: usize,
+        format: ScreenPixelFormat,
+    ) -> Result<(), ErrorCode> {
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        color_mode(format).ok_or(ErrorCode::NOSUPPORT)?;
+        self.framebuffer.set(Framebuffer {
+            address: address as usize,
+            width,
+            height,
+            format,
+        });
+        Ok(())
+    }
+
+    /// Stop drawing into the framebuffer.
+    pub fn clear_framebuffer(&self) {
+        self.framebuffer.clear();
+    }
+
+    /// Check that a new operation can start and return the framebuffer and
+    /// its color mode.
+    fn prepare(&self) -> Result<(Framebuffer, u32), ErrorCode> {
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let framebuffer = self.framebuffer.get().ok_or(ErrorCode::OFF)?;
+        let mode = color_mode(framebuffer.format).ok_or(ErrorCode::NOSUPPORT)?;
+        Ok((framebuffer, mode))
+    }
+
+    /// Program the output rectangle and start the transfer.
+    fn start(
+        &self,
+        mode: CR::MODE::Value,
+        framebuffer: &Framebuffer,
+        address: usize,
+        width: usize,
+        height: usize,
+        color_mode: u32,
+    ) {
+        self.registers.opfccr.write(OPFCCR::CM.val(color_mode));
+        self.registers.omar.set(address as u32);
+        self.registers
+            .oor
+            .write(OR::LO.val((framebuffer.width - width) as u32));
+        self.registers
+            .nlr
+            .write(NLR::PL.val(width as u32) + NLR::NL.val(height as u32));
+        self.registers.ifcr.write(
+            IFCR::CCEIF::SET
+                + IFCR::CCTCIF::SET
+                + IFCR::CAECIF::SET
+                + IFCR::CTWIF::SET
+                + IFCR::CTCIF::SET
+                + IFCR::CTEIF::SET,
+        );
+        self.busy.set(true);
+        self.registers.cr.write(
+            CR::MODE.val(mode as u32)
+                + CR::CEIE::SET
+                + CR::TCIE::SET
+                + CR::TEIE::SET
+                + CR::START::SET,
+        );
+    }
+
+    pub fn handle_interrupt(&self) {
+        let isr = self.registers.isr.extract();
+        self.registers.ifcr.write(
+            IFCR::CCEIF::SET
+                + IFCR::CCTCIF::SET
+                + IFCR::CAECIF::SET
+                + IFCR::CTWIF::SET
+                + IFCR::CTCIF::SET
+                + IFCR::CTEIF::SET,
+        );
+        self.registers
+            .cr
+            .modify(CR::CEIE::CLEAR + CR::TCIE::CLEAR + CR::TEIE::CLEAR);
+
+        if !self.busy.get() {
+            return;
+        }
+        self.busy.set(false);
+
+        let result = if isr.is_set(ISR::TEIF) || isr.is_set(ISR::CEIF) {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        };
+
+        match self.blit_buffer.take() {
+            Some(buffer) => self
+                .client
+                .map(|client| client.blit_complete(buffer, result)),
+            None => self.client.map(|client| client.fill_complete(result)),
+        };
+    }
+}
+
+struct Dma2dClock<'a>(phclk::PeripheralClock<'a>);
+
+impl ClockInterface for Dma2dClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
+
+impl<'a> ScreenAccelerated<'a> for Dma2d<'a> {
+    fn set_client(&self, client: &'a dyn ScreenAcceleratedClient) {
+        self.client.set(client);
+    }
+
+    fn fill(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: u32,
+    ) -> Result<(), ErrorCode> {
+        let (framebuffer, mode) = self.prepare()?;
+        let address = framebuffer.rectangle_address(x, y, width, height)?;
+
+        self.registers.ocolr.set(color);
+        self.start(
+            CR::MODE::Value::RegisterToMemory,
+            &framebuffer,
+            address,
+            width,
+            height,
+            mode,
+        );
+        Ok(())
+    }
+
+    fn blit(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        let (framebuffer, mode) = match self.prepare() {
+            Ok(prepared) => prepared,
+            Err(e) => return Err((e, buffer)),
+        };
+        let address = match framebuffer.rectangle_address(x, y, width, height) {
+            Ok(address) => address,
+            Err(e) => return Err((e, buffer)),
+        };
+        let bytes_per_pixel = framebuffer.format.get_bits_per_pixel() / 8;
+        if buffer.len() != width * height * bytes_per_pixel {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+
+        // Without pixel format conversion the foreground is read in the
+        // output color mode.
+        self.registers.fgmar.set(buffer.as_ptr() as u32);
+        self.registers.fgor.write(OR::LO.val(0));
+        self.registers.fgpfccr.write(PFCCR::CM.val(mode));
+        self.blit_buffer.replace(buffer);
+        self.start(
+            CR::MODE::Value::MemoryToMemory,
+            &framebuffer,
+            address,
+            width,
+            height,
+            mode,
+        );
+        Ok(())
+    }
+}
@@ -10,8 +10,10 @@ use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 use crate::gpio;
+use crate::nvic;
 use crate::syscfg;
 
 /// External interrupt/event controller
@@ -386,6 +388,36 @@ enum_from_primitive! {
     }
 }
 
+impl LineId {
+    /// The interrupt of the line. Lines 5 to 9, and lines 10 to 15, share
+    /// an interrupt.
+    pub fn irq(self) -> u32 {
+        match self as u8 {
+            0 => nvic::EXTI0,
+            1 => nvic::EXTI1,
+            2 => nvic::EXTI2,
+            3 => nvic::EXTI3,
+            4 => nvic::EXTI4,
+            5..=9 => nvic::EXTI9_5,
+            _ => nvic::EXTI15_10,
+        }
+    }
+}
+
+/// Bits of the lines served by the interrupt `irq`.
+fn irq_lines(irq: u32) -> u32 {
+    match irq {
+        nvic::EXTI0 => 1 << 0,
+        nvic::EXTI1 => 1 << 1,
+        nvic::EXTI2 => 1 << 2,
+        nvic::EXTI3 => 1 << 3,
+        nvic::EXTI4 => 1 << 4,
+        nvic::EXTI9_5 => 0b11111 << 5,
+        nvic::EXTI15_10 => 0b111111 << 10,
+        _ => 0,
+    }
+}
+
 // `line_gpiopin_map` is used to call `handle_interrupt()` on the pin.
 pub struct Exti<'a> {
     registers: StaticRef<ExtiRegisters>,
@@ -433,14 +465,27 @@ impl<'a> Exti<'a> {
         self.clock.disable();
     }
 
-    pub fn associate_line_gpiopin(&self, lineid: LineId, pin: &'static gpio::Pin<'static>) {
-        self.line_gpiopin_map[usize::from(lineid as u8)].set(pin);
+    /// Route the interrupts of a line to a pin. The line of a pin is given by
+    /// its pin number, so pins with the same number on different ports
+    /// cannot both use interrupts: the line stays with the first pin, and
+    /// `ErrorCode::BUSY` is returned.
+    pub fn associate_line_gpiopin(
+        &self,
+        lineid: LineId,
+        pin: &'static gpio::Pin<'static>,
+    ) -> Result<(), ErrorCode> {
+        let line = &self.line_gpiopin_map[usize::from(lineid as u8)];
+        if line.map_or(false, |other| !core::ptr::eq(other, pin)) {
+            return Err(ErrorCode::BUSY);
+        }
+        line.set(pin);
         self.syscfg.configure_interrupt(pin.get_pinid());
         pin.set_exti_lineid(lineid);
 
         // By default, all interrupts are masked. But, this will ensure that it
         // is really the case.
         self.mask_interrupt(lineid);
+        Ok(())
     }
 
     pub fn mask_interrupt(&self, lineid: LineId) {
@@ -507,6 +552,10 @@ impl<'a> Exti<'a> {
         }
     }
 
+    pub fn is_masked(&self, lineid: LineId) -> bool {
+        self.registers.imr.get() & (1 << lineid as u8) == 0
+    }
+
     pub fn is_pending(&self, lineid: LineId) -> bool {
         let val = match lineid {
             LineId::Exti0 => self.registers.pr.read(PR::PR0),
@@ -613,38 +662,29 @@ impl<'a> Exti<'a> {
         }
     }
 
-    pub fn handle_interrupt(&self) {
+    /// Handle the interrupt `irq` of the GPIO lines, demultiplexing the
+    /// lines sharing `EXTI9_5` and `EXTI15_10`.
+    pub fn handle_interrupt(&self, irq: u32) {
         let mut exti_pr: u32 = 0;
 
-        // Read the `EXTI_PR` register and toggle the appropriate bits in
-        // `exti_pr`. Once that is done, write the value of `exti_pr` back. We
-        // can have a situation where memory value of `EXTI_PR` could have
-        // changed due to an external interrupt. `EXTI_PR` is a read/clear write
-        // 1 register (`rc_w1`). So, we only clear bits whose value has been
-        // transferred to `exti_pr`.
+        // Read the `EXTI_PR` register and keep the bits of the unmasked lines
+        // served by `irq`. Once that is done, write the value of `exti_pr`
+        // back. We can have a situation where memory value of `EXTI_PR` could
+        // have changed due to an external interrupt. `EXTI_PR` is a read/clear
+        // write 1 register (`rc_w1`). So, we only clear bits whose value has
+        // been transferred to `exti_pr`, and leave the lines of the other
+        // interrupts pending for their own handler.
         unsafe {
             atomic(|| {
-                exti_pr = self.registers.pr.get();
+                exti_pr = self.registers.pr.get() & self.registers.imr.get() & irq_lines(irq);
                 self.registers.pr.set(exti_pr);
             });
         }
 
-        // ignore the "reserved" EXTI bits. Use bits [22:0]. See `EXTI_PR` for
-        // details.
-        exti_pr |= 0x007fffff;
-
-        let mut flagged_bit = 0;
-
-        // stay in loop until we have processed all the flagged event bits
-        while exti_pr != 0 {
-            if (exti_pr & 0b1) != 0 {
-                if let Some(d) = LineId::from_u8(flagged_bit) {
-                    self.line_gpiopin_map[usize::from(d as u8)].map(|pin| pin.handle_interrupt());
-                }
+        for (line, pin) in self.line_gpiopin_map.iter().enumerate() {
+            if exti_pr & (1 << line) != 0 {
+                pin.map(|pin| pin.handle_interrupt());
             }
-            // move to next bit
-            flagged_bit += 1;
-            exti_pr >>= 1;
         }
     }
 }
@@ -667,3 +707,23 @@ impl ClockInterface for ExtiClock<'_> {
         self.0.disable_clock();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_interrupts() {
+        let mut lines = 0;
+        for line in 0..16 {
+            let lineid = LineId::from_u8(line).unwrap();
+            assert_ne!(irq_lines(lineid.irq()) & (1 << line), 0);
+            lines |= irq_lines(lineid.irq());
+        }
+        // every GPIO line is served by exactly one interrupt
+        assert_eq!(lines, 0xffff);
+        assert_eq!(irq_lines(nvic::EXTI9_5) & irq_lines(nvic::EXTI15_10), 0);
+        assert_eq!(irq_lines(nvic::EXTI9_5).count_ones(), 5);
+        assert_eq!(irq_lines(nvic::EXTI15_10).count_ones(), 6);
+    }
+}
@@ -0,0 +1,348 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FMC SDRAM controller, available on STM32F42xxx/43xxx.
+//!
+//! The controller maps an external SDRAM device in the address space. Once
+//! [`FmcSdram::init`] returns, the memory can be used like internal RAM, for
+//! example as a display framebuffer.
+//!
+//! The FMC pins must be configured in their alternate function by the board
+//! before calling `init`.
+
+use crate::clocks::{phclk, Stm32f4Clocks};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+/// FMC SDRAM registers, starting at offset 0x140 of the FMC block.
+#[repr(C)]
+pub struct FmcSdramRegisters {
+    /// SDRAM control registers
+    sdcr: [ReadWrite<u32, SDCR::Register>; 2],
+    /// SDRAM timing registers
+    sdtr: [ReadWrite<u32, SDTR::Register>; 2],
+    /// SDRAM command mode register
+    sdcmr: ReadWrite<u32, SDCMR::Register>,
+    /// SDRAM refresh timer register
+    sdrtr: ReadWrite<u32, SDRTR::Register>,
+    /// SDRAM status register
+    sdsr: ReadOnly<u32, SDSR::Register>,
+}
+
+register_bitfields![u32,
+    SDCR [
+        /// Read pipe
+        RPIPE OFFSET(13) NUMBITS(2) [],
+        /// Burst read
+        RBURST OFFSET(12) NUMBITS(1) [],
+        /// SDRAM clock configuration
+        SDCLK OFFSET(10) NUMBITS(2) [
+            Disabled = 0,
+            Hclk2 = 2,
+            Hclk3 = 3
+        ],
+        /// Write protection
+        WP OFFSET(9) NUMBITS(1) [],
+        /// CAS latency
+        CAS OFFSET(7) NUMBITS(2) [],
+        /// Number of internal banks
+        NB OFFSET(6) NUMBITS(1) [
+            TwoBanks = 0,
+            FourBanks = 1
+        ],
+        /// Memory data bus width
+        MWID OFFSET(4) NUMBITS(2) [
+            Bits8 = 0,
+            Bits16 = 1,
+            Bits32 = 2
+        ],
+        /// Number of row address bits
+        NR OFFSET(2) NUMBITS(2) [],
+        /// Number of column address bits
+        NC OFFSET(0) NUMBITS(2) []
+    ],
+    SDTR [
+        /// Row to column delay
+        TRCD OFFSET(24) NUMBITS(4) [],
+        /// Row precharge delay
+        TRP OFFSET(20) NUMBITS(4) [],
+        /// Recovery delay
+        TWR OFFSET(16) NUMBITS(4) [],
+        /// Row cycle delay
+        TRC OFFSET(12) NUMBITS(4) [],
+        /// Self refresh time
+        TRAS OFFSET(8) NUMBITS(4) [],
+        /// Exit self-refresh delay
+        TXSR OFFSET(4) NUMBITS(4) [],
+        /// Load mode register to active
+        TMRD OFFSET(0) NUMBITS(4) []
+    ],
+    SDCMR [
+        /// Mode register definition
+        MRD OFFSET(9) NUMBITS(13) [],
+        /// Number of auto-refresh
+        NRFS OFFSET(5) NUMBITS(4) [],
+        /// Command target bank 1
+        CTB1 OFFSET(4) NUMBITS(1) [],
+        /// Command target bank 2
+        CTB2 OFFSET(3) NUMBITS(1) [],
+        /// Command mode
+        MODE OFFSET(0) NUMBITS(3) [
+            Normal = 0,
+            ClockConfigurationEnable = 1,
+            PrechargeAll = 2,
+            AutoRefresh = 3,
+            LoadModeRegister = 4,
+            SelfRefresh = 5,
+            PowerDown = 6
+        ]
+    ],
+    SDRTR [
+        /// RES interrupt enable
+        REIE OFFSET(14) NUMBITS(1) [],
+        /// Refresh timer count
+        COUNT OFFSET(1) NUMBITS(13) [],
+        /// Clear refresh error flag
+        CRE OFFSET(0) NUMBITS(1) []
+    ],
+    SDSR [
+        /// Busy status
+        BUSY OFFSET(5) NUMBITS(1) [],
+        /// Status mode for bank 2
+        MODES2 OFFSET(3) NUMBITS(2) [],
+        /// Status mode for bank 1
+        MODES1 OFFSET(1) NUMBITS(2) [],
+        /// Refresh error flag
+        RE OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+/// SDRAM bank of the FMC.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SdramBank {
+    /// SDNE0/SDCKE0, mapped at `0xC000_0000`
+    Bank1 = 0,
+    /// SDNE1/SDCKE1, mapped at `0xD000_0000`
+    Bank2 = 1,
+}
+
+impl SdramBank {
+    fn address(&self) -> usize {
+        match self {
+            SdramBank::Bank1 => 0xC000_0000,
+            SdramBank::Bank2 => 0xD000_0000,
+        }
+    }
+}
+
+/// Geometry and timings of an SDRAM device.
+///
+/// Timings are in SDRAM clock cycles, which run at half the AHB frequency.
+#[derive(Copy, Clone, Debug)]
+pub struct SdramConfig {
+    pub bank: SdramBank,
+    /// Number of column address bits, 8 to 11
+    pub column_bits: usize,
+    /// Number of row address bits, 11 to 13
+    pub row_bits: usize,
+    /// Data bus width in bits: 8, 16 or 32
+    pub bus_width: usize,
+    /// Whether the device has four internal banks (two otherwise)
+    pub four_internal_banks: bool,
+    /// CAS latency, 1 to 3
+    pub cas_latency: usize,
+    pub load_to_active_delay: usize,
+    pub exit_self_refresh_delay: usize,
+    pub self_refresh_time: usize,
+    pub row_cycle_delay: usize,
+    pub write_recovery_time: usize,
+    pub rp_delay: usize,
+    pub rcd_delay: usize,
+    /// Refresh period of the whole device in milliseconds (usually 64)
+    pub refresh_period_ms: usize,
+}
+
+pub struct FmcSdram<'a> {
+    registers: StaticRef<FmcSdramRegisters>,
+    clock: FmcClock<'a>,
+    clocks: &'a dyn Stm32f4Clocks,
+}
+
+impl<'a> FmcSdram<'a> {
+    pub fn new(registers: StaticRef<FmcSdramRegisters>, clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self {
+            registers,
+            clock: FmcClock(phclk::PeripheralClock::new(
+                phclk::PeripheralClockType::AHB3(phclk::HCLK3::FMC),
+                clocks,
+            )),
+            clocks,
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Busy wait for about `us` microseconds.
+    fn delay_us(&self, us: usize) {
+        // A loop iteration takes a few cycles, so this waits at least `us`.
+        for _ in 0..(self.clocks.get_ahb_frequency() / 1_000_000 * us) {
+            cortexm4::support::nop();
+        }
+    }
+
+    fn wait_not_busy(&self) -> Result<(), ErrorCode> {
+        for _ in 0..100_000 {
+            if !self.registers.sdsr.is_set(SDSR::BUSY) {
+                return Ok(());
+            }
+        }
+        Err(ErrorCode::FAIL)
+    }
+
+    fn send_command(
+        &self,
+        bank: SdramBank,
+        mode: SDCMR::MODE::Value,
+        auto_refresh: usize,
+        mode_register: u32,
+    ) -> Result<(), ErrorCode> {
+        self.wait_not_busy()?;
+        let target = match bank {
+            SdramBank::Bank1 => SDCMR::CTB1::SET,
+            SdramBank::Bank2 => SDCMR::CTB2::SET,
+        };
+        self.registers.sdcmr.write(
+            SDCMR::MODE.val(mode as u32)
+                + target
+                + SDCMR::NRFS.val(auto_refresh.saturating_sub(1) as u32)
+                + SDCMR::MRD.val(mode_register),
+        );
+        self.wait_not_busy()
+    }
+
+    /// Configure the controller, run the SDRAM power-up sequence and return
+    /// the address where the memory is mapped.
+    ///
+    /// Returns `INVAL` if the configuration is not supported and `FAIL` if
+    /// the controller does not respond.
+    pub fn init(&self, config: &SdramConfig) -> Result<usize, ErrorCode> {
+        let bus_width = match config.bus_width {
+            8 => SDCR::MWID::Bits8,
+            16 => SDCR::MWID::Bits16,
+            32 => SDCR::MWID::Bits32,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        if !(8..=11).contains(&config.column_bits)
+            || !(11..=13).contains(&config.row_bits)
+            || !(1..=3).contains(&config.cas_latency)
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        let internal_banks = if config.four_internal_banks {
+            SDCR::NB::FourBanks
+        } else {
+            SDCR::NB::TwoBanks
+        };
+        let timings = [
+            config.load_to_active_delay,
+            config.exit_self_refresh_delay,
+            config.self_refresh_time,
+            config.row_cycle_delay,
+            config.write_recovery_time,
+            config.rp_delay,
+            config.rcd_delay,
+        ];
+        if timings.iter().any(|&t| !(1..=16).contains(&t)) {
+            return Err(ErrorCode::INVAL);
+        }
+
+        self.enable_clock();
+
+        // SDCLK, RBURST and RPIPE are only taken into account in the bank 1
+        // register, and TRC and TRP in the bank 1 timing register.
+        let shared_control = SDCR::SDCLK::Hclk2 + SDCR::RBURST::SET + SDCR::RPIPE.val(1);
+        let control = SDCR::NC.val((config.column_bits - 8) as u32)
+            + SDCR::NR.val((config.row_bits - 11) as u32)
+            + bus_width
+            + internal_banks
+            + SDCR::CAS.val(config.cas_latency as u32);
+        let shared_timing = SDTR::TRC.val((config.row_cycle_delay - 1) as u32)
+            + SDTR::TRP.val((config.rp_delay - 1) as u32);
+        let timing = SDTR::TMRD.val((config.load_to_active_delay - 1) as u32)
+            + SDTR::TXSR.val((config.exit_self_refresh_delay - 1) as u32)
+            + SDTR::TRAS.val((config.self_refresh_time - 1) as u32)
+            + SDTR::TWR.val((config.write_recovery_time - 1) as u32)
+            + SDTR::TRCD.val((config.rcd_delay - 1) as u32);
+        match config.bank {
+            SdramBank::Bank1 => {
+                self.registers.sdcr[0].write(shared_control + control);
+                self.registers.sdtr[0].write(shared_timing + timing);
+            }
+            SdramBank::Bank2 => {
+                self.registers.sdcr[0].write(shared_control);
+                self.registers.sdcr[1].write(control);
+                self.registers.sdtr[0].write(shared_timing);
+                self.registers.sdtr[1].write(timing);
+            }
+        }
+
+        // Power-up sequence, Reference Manual RM0090 section 37.7.3
+        self.send_command(
+            config.bank,
+            SDCMR::MODE::Value::ClockConfigurationEnable,
+            1,
+            0,
+        )?;
+        self.delay_us(100);
+        self.send_command(config.bank, SDCMR::MODE::Value::PrechargeAll, 1, 0)?;
+        self.send_command(config.bank, SDCMR::MODE::Value::AutoRefresh, 8, 0)?;
+        // Burst length 1, sequential burst, single location write access.
+        let mode_register = (config.cas_latency as u32) << 4 | 1 << 9;
+        self.send_command(
+            config.bank,
+            SDCMR::MODE::Value::LoadModeRegister,
+            1,
+            mode_register,
+        )?;
+
+        // Refresh rate = refresh period / number of rows, minus a margin of
+        // 20 cycles, in SDRAM clock cycles.
+        let sdram_frequency_khz = self.clocks.get_ahb_frequency() / 2 / 1000;
+        let count = ((config.refresh_period_ms * sdram_frequency_khz) >> config.row_bits)
+            .saturating_sub(20)
+            .max(41);
+        self.registers.sdrtr.modify(SDRTR::COUNT.val(count as u32));
+
+        Ok(config.bank.address())
+    }
+}
+
+struct FmcClock<'a>(phclk::PeripheralClock<'a>);
+
+impl ClockInterface for FmcClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
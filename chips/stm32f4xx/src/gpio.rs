@@ -6,11 +6,13 @@ use cortexm4::support::atomic;
 use enum_primitive::cast::FromPrimitive;
 use enum_primitive::enum_from_primitive;
 use kernel::hil;
+use kernel::hil::wakeup_source::WakeupEdge;
 use kernel::platform::chip::ClockInterface;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 use crate::clocks::{phclk, Stm32f4Clocks};
 use crate::exti::{self, LineId};
@@ -765,6 +767,8 @@ pub struct Pin<'a> {
     exti: &'a exti::Exti<'a>,
     client: OptionalCell<&'a dyn hil::gpio::Client>,
     exti_lineid: OptionalCell<exti::LineId>,
+    wakeup_client: OptionalCell<&'a dyn hil::wakeup_source::WakeupClient>,
+    wakeup_edge: OptionalCell<WakeupEdge>,
 }
 
 impl<'a> Pin<'a> {
@@ -775,6 +779,8 @@ impl<'a> Pin<'a> {
             exti,
             client: OptionalCell::empty(),
             exti_lineid: OptionalCell::empty(),
+            wakeup_client: OptionalCell::empty(),
+            wakeup_edge: OptionalCell::empty(),
         }
     }
 
@@ -787,7 +793,11 @@ impl<'a> Pin<'a> {
     }
 
     pub fn handle_interrupt(&self) {
-        self.client.map(|client| client.fired());
+        if self.wakeup_edge.is_some() {
+            self.wakeup_client.map(|client| client.woken());
+        } else {
+            self.client.map(|client| client.fired());
+        }
     }
 
     pub fn get_mode(&self) -> Mode {
@@ -868,10 +878,23 @@ impl<'a> Pin<'a> {
         self.pinid
     }
 
+    // Route the interrupts of the EXTI line of the pin to the pin. A line is
+    // shared by the pins with the same number on all the ports, and stays
+    // with the first pin enabling it.
     pub unsafe fn enable_interrupt(&'static self) {
         let exti_line_id = LineId::from_u8(self.pinid.get_pin_number()).unwrap();
 
-        self.exti.associate_line_gpiopin(exti_line_id, self);
+        if self
+            .exti
+            .associate_line_gpiopin(exti_line_id, self)
+            .is_err()
+        {
+            kernel::log_warn!(
+                Kernel,
+                "EXTI line {} already used by another pin",
+                exti_line_id as u8
+            );
+        }
     }
 
     pub fn set_exti_lineid(&self, lineid: exti::LineId) {
@@ -1252,3 +1275,42 @@ impl<'a> hil::gpio::Interrupt<'a> for Pin<'a> {
             .map_or(false, |lineid| self.exti.is_pending(lineid))
     }
 }
+
+/// EXTI lines keep detecting edges in every sleep mode, so a wakeup source
+/// is a GPIO interrupt reported to a different client. The pin must have
+/// been associated with its EXTI line with `enable_interrupt`, and cannot be
+/// used for GPIO interrupts while it is armed.
+impl<'a> hil::wakeup_source::WakeupSource<'a> for Pin<'a> {
+    fn enable_wakeup(&self, edge: WakeupEdge) -> Result<(), ErrorCode> {
+        let lineid = self.exti_lineid.get().ok_or(ErrorCode::NOSUPPORT)?;
+        if self.wakeup_edge.is_none() && !self.exti.is_masked(lineid) {
+            // Used for GPIO interrupts.
+            return Err(ErrorCode::BUSY);
+        }
+        self.wakeup_edge.set(edge);
+        hil::gpio::Interrupt::enable_interrupts(
+            self,
+            match edge {
+                WakeupEdge::Rising => hil::gpio::InterruptEdge::RisingEdge,
+                WakeupEdge::Falling => hil::gpio::InterruptEdge::FallingEdge,
+                WakeupEdge::Either => hil::gpio::InterruptEdge::EitherEdge,
+            },
+        );
+        Ok(())
+    }
+
+    fn disable_wakeup(&self) {
+        if self.wakeup_edge.is_some() {
+            hil::gpio::Interrupt::disable_interrupts(self);
+            self.wakeup_edge.clear();
+        }
+    }
+
+    fn is_wakeup_enabled(&self) -> bool {
+        self.wakeup_edge.is_some()
+    }
+
+    fn set_wakeup_client(&self, client: &'a dyn hil::wakeup_source::WakeupClient) {
+        self.wakeup_client.set(client);
+    }
+}
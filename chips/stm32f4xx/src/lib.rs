@@ -20,14 +20,19 @@ pub mod can;
 pub mod dac;
 pub mod dbg;
 pub mod dma;
+pub mod dma2d;
 pub mod exti;
 pub mod flash;
+pub mod fmc_sdram;
 pub mod fsmc;
 pub mod gpio;
 pub mod i2c;
+pub mod ltdc;
 pub mod rcc;
+pub mod sai;
 pub mod spi;
 pub mod syscfg;
+pub mod tim;
 pub mod tim2;
 pub mod trng;
 pub mod usart;
@@ -0,0 +1,810 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! LCD-TFT display controller (LTDC) driver, available on STM32F42xxx/43xxx.
+//!
+//! The LTDC continuously scans a framebuffer in memory (usually external
+//! SDRAM) and drives a parallel RGB display. This driver exposes it through
+//! `hil::screen::Screen`.
+//!
+//! Tear-free updates
+//! -----------------
+//!
+//! The driver uses two framebuffers. Writes go to the back buffer while the
+//! front buffer is displayed. Once a write (or accelerated fill or blit)
+//! reaches the end of the write frame, the buffers are swapped during the
+//! next vertical blanking period, and the updated area is copied to the new
+//! back buffer so that both buffers hold the same image again. The write
+//! completes after the swap, so a screen is never shown half-drawn.
+//!
+//! Pixels are stored in memory in the order they appear in write buffers,
+//! which is the little-endian order the LTDC reads them in (for example, the
+//! low byte of an `RGB_565` pixel comes first).
+//!
+//! Acceleration
+//! ------------
+//!
+//! If a DMA2D is attached with [`Ltdc::set_dma2d`], the driver also provides
+//! `hil::screen::ScreenAccelerated`, drawing into the back buffer with the
+//! DMA2D. The DMA2D client must then be set to the LTDC.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! ltdc.init(&TIMING, ScreenPixelFormat::RGB_565, [framebuffer0, framebuffer1])?;
+//! ltdc.set_dma2d(&peripherals.dma2d);
+//! ScreenAccelerated::set_client(&peripherals.dma2d, &peripherals.ltdc);
+//! ```
+
+use crate::clocks::{phclk, Stm32f4Clocks};
+use crate::dma2d::Dma2d;
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::screen::{
+    self, ScreenAccelerated, ScreenAcceleratedClient, ScreenPixelFormat, ScreenRotation,
+};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+#[repr(C)]
+pub struct LtdcRegisters {
+    _reserved0: [u32; 2],
+    /// synchronization size configuration register
+    sscr: ReadWrite<u32, SIZE::Register>,
+    /// back porch configuration register
+    bpcr: ReadWrite<u32, SIZE::Register>,
+    /// active width configuration register
+    awcr: ReadWrite<u32, SIZE::Register>,
+    /// total width configuration register
+    twcr: ReadWrite<u32, SIZE::Register>,
+    /// global control register
+    gcr: ReadWrite<u32, GCR::Register>,
+    _reserved1: [u32; 2],
+    /// shadow reload configuration register
+    srcr: ReadWrite<u32, SRCR::Register>,
+    _reserved2: u32,
+    /// background color configuration register
+    bccr: ReadWrite<u32>,
+    _reserved3: u32,
+    /// interrupt enable register
+    ier: ReadWrite<u32, INTERRUPT::Register>,
+    /// interrupt status register
+    isr: ReadOnly<u32, INTERRUPT::Register>,
+    /// interrupt clear register
+    icr: WriteOnly<u32, INTERRUPT::Register>,
+    /// line interrupt position configuration register
+    lipcr: ReadWrite<u32>,
+    /// current position status register
+    cpsr: ReadOnly<u32>,
+    /// current display status register
+    cdsr: ReadOnly<u32>,
+    _reserved4: [u32; 14],
+    layers: [LayerRegisters; 2],
+}
+
+#[repr(C)]
+struct LayerRegisters {
+    /// layer control register
+    cr: ReadWrite<u32, LCR::Register>,
+    /// layer window horizontal position configuration register
+    whpcr: ReadWrite<u32, POSITION::Register>,
+    /// layer window vertical position configuration register
+    wvpcr: ReadWrite<u32, POSITION::Register>,
+    /// layer color keying configuration register
+    ckcr: ReadWrite<u32>,
+    /// layer pixel format configuration register
+    pfcr: ReadWrite<u32, PFCR::Register>,
+    /// layer constant alpha configuration register
+    cacr: ReadWrite<u32>,
+    /// layer default color configuration register
+    dccr: ReadWrite<u32>,
+    /// layer blending factors configuration register
+    bfcr: ReadWrite<u32, BFCR::Register>,
+    _reserved0: [u32; 2],
+    /// layer color frame buffer address register
+    cfbar: ReadWrite<u32>,
+    /// layer color frame buffer length register
+    cfblr: ReadWrite<u32, CFBLR::Register>,
+    /// layer color frame buffer line number register
+    cfblnr: ReadWrite<u32>,
+    _reserved1: [u32; 3],
+    /// layer CLUT write register
+    clutwr: WriteOnly<u32>,
+    _reserved2: [u32; 15],
+}
+
+register_bitfields![u32,
+    SIZE [
+        /// Width related value, in pixel clock cycles
+        W OFFSET(16) NUMBITS(12) [],
+        /// Height related value, in lines
+        H OFFSET(0) NUMBITS(11) []
+    ],
+    GCR [
+        /// Horizontal synchronization polarity
+        HSPOL OFFSET(31) NUMBITS(1) [],
+        /// Vertical synchronization polarity
+        VSPOL OFFSET(30) NUMBITS(1) [],
+        /// Data enable polarity
+        DEPOL OFFSET(29) NUMBITS(1) [],
+        /// Pixel clock polarity
+        PCPOL OFFSET(28) NUMBITS(1) [],
+        /// Dither enable
+        DEN OFFSET(16) NUMBITS(1) [],
+        /// LCD-TFT controller enable
+        LTDCEN OFFSET(0) NUMBITS(1) []
+    ],
+    SRCR [
+        /// Vertical blanking reload
+        VBR OFFSET(1) NUMBITS(1) [],
+        /// Immediate reload
+        IMR OFFSET(0) NUMBITS(1) []
+    ],
+    INTERRUPT [
+        /// Register reload
+        RR OFFSET(3) NUMBITS(1) [],
+        /// Transfer error
+        TERR OFFSET(2) NUMBITS(1) [],
+        /// FIFO underrun
+        FU OFFSET(1) NUMBITS(1) [],
+        /// Line
+        L OFFSET(0) NUMBITS(1) []
+    ],
+    LCR [
+        /// Color look-up table enable
+        CLUTEN OFFSET(4) NUMBITS(1) [],
+        /// Color keying enable
+        COLKEN OFFSET(1) NUMBITS(1) [],
+        /// Layer enable
+        LEN OFFSET(0) NUMBITS(1) []
+    ],
+    POSITION [
+        /// Window stop position
+        STOP OFFSET(16) NUMBITS(12) [],
+        /// Window start position
+        START OFFSET(0) NUMBITS(12) []
+    ],
+    PFCR [
+        /// Pixel format
+        PF OFFSET(0) NUMBITS(3) [
+            ARGB8888 = 0,
+            RGB888 = 1,
+            RGB565 = 2
+        ]
+    ],
+    BFCR [
+        /// Blending factor 1
+        BF1 OFFSET(8) NUMBITS(3) [
+            ConstantAlpha = 4,
+            PixelAlphaTimesConstantAlpha = 6
+        ],
+        /// Blending factor 2
+        BF2 OFFSET(0) NUMBITS(3) [
+            OneMinusConstantAlpha = 5,
+            OneMinusPixelAlphaTimesConstantAlpha = 7
+        ]
+    ],
+    CFBLR [
+        /// Color frame buffer pitch, in bytes
+        CFBP OFFSET(16) NUMBITS(13) [],
+        /// Color frame buffer line length, in bytes plus 3
+        CFBLL OFFSET(0) NUMBITS(13) []
+    ]
+];
+
+/// Timings of the display panel.
+#[derive(Copy, Clone, Debug)]
+pub struct LtdcTiming {
+    /// Active width, in pixels
+    pub width: usize,
+    /// Active height, in lines
+    pub height: usize,
+    /// Horizontal synchronization width, in pixel clock cycles
+    pub hsync: usize,
+    /// Horizontal back porch, in pixel clock cycles
+    pub hback_porch: usize,
+    /// Horizontal front porch, in pixel clock cycles
+    pub hfront_porch: usize,
+    /// Vertical synchronization height, in lines
+    pub vsync: usize,
+    /// Vertical back porch, in lines
+    pub vback_porch: usize,
+    /// Vertical front porch, in lines
+    pub vfront_porch: usize,
+    /// Pixel clock frequency, in Hz
+    pub pixel_clock: usize,
+    pub hsync_active_high: bool,
+    pub vsync_active_high: bool,
+    pub data_enable_active_high: bool,
+    /// Whether data is sampled on the falling edge of the pixel clock
+    pub pixel_clock_inverted: bool,
+}
+
+/// A rectangle of the screen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Frame {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Frame {
+    fn union(&self, other: &Frame) -> Frame {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Frame {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// The callback to deliver once the current operation is over.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Pending {
+    None,
+    CommandComplete(Result<(), ErrorCode>),
+    ScreenReady,
+    WriteComplete(Result<(), ErrorCode>),
+    FillComplete(Result<(), ErrorCode>),
+    BlitComplete(Result<(), ErrorCode>),
+}
+
+fn bytes_per_pixel(format: ScreenPixelFormat) -> usize {
+    format.get_bits_per_pixel() / 8
+}
+
+pub struct Ltdc<'a> {
+    registers: StaticRef<LtdcRegisters>,
+    clock: LtdcClock<'a>,
+    client: OptionalCell<&'a dyn screen::ScreenClient>,
+    accelerated_client: OptionalCell<&'a dyn ScreenAcceleratedClient>,
+    dma2d: OptionalCell<&'a Dma2d<'a>>,
+    timing: OptionalCell<LtdcTiming>,
+    pixel_format: Cell<ScreenPixelFormat>,
+    framebuffers: [TakeCell<'static, [u8]>; 2],
+    /// Index of the displayed framebuffer.
+    front: Cell<usize>,
+    powered: Cell<bool>,
+    write_frame: Cell<Frame>,
+    /// Number of pixels of the write frame already written.
+    write_position: Cell<usize>,
+    /// Area of the back buffer that differs from the front buffer.
+    dirty: OptionalCell<Frame>,
+    /// Area drawn by the ongoing accelerated operation.
+    accelerated_frame: OptionalCell<Frame>,
+    busy: Cell<bool>,
+    swapping: Cell<bool>,
+    pending: Cell<Pending>,
+    buffer: MapCell<SubSliceMut<'static, u8>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> Ltdc<'a> {
+    pub fn new(registers: StaticRef<LtdcRegisters>, clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self {
+            registers,
+            clock: LtdcClock(phclk::PeripheralClock::new(
+                phclk::PeripheralClockType::APB2(phclk::PCLK2::LTDC),
+                clocks,
+            )),
+            client: OptionalCell::empty(),
+            accelerated_client: OptionalCell::empty(),
+            dma2d: OptionalCell::empty(),
+            timing: OptionalCell::empty(),
+            pixel_format: Cell::new(ScreenPixelFormat::RGB_565),
+            framebuffers: [TakeCell::empty(), TakeCell::empty()],
+            front: Cell::new(0),
+            powered: Cell::new(false),
+            write_frame: Cell::new(Frame {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            }),
+            write_position: Cell::new(0),
+            dirty: OptionalCell::empty(),
+            accelerated_frame: OptionalCell::empty(),
+            busy: Cell::new(false),
+            swapping: Cell::new(false),
+            pending: Cell::new(Pending::None),
+            buffer: MapCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Configure the pixel clock, the panel timings and the layer displaying
+    /// the framebuffers.
+    ///
+    /// Each framebuffer must hold `width * height` pixels. The display stays
+    /// off until `set_power` is called.
+    ///
+    /// Returns `NOSUPPORT` if the pixel format cannot be displayed, `SIZE` if
+    /// a framebuffer is too small and `INVAL` if the pixel clock cannot be
+    /// generated.
+    pub fn init(
+        &self,
+        timing: &LtdcTiming,
+        pixel_format: ScreenPixelFormat,
+        framebuffers: [&'static mut [u8]; 2],
+    ) -> Result<(), ErrorCode> {
+        let format = match pixel_format {
+            ScreenPixelFormat::ARGB_8888 => PFCR::PF::ARGB8888,
+            ScreenPixelFormat::RGB_888 => PFCR::PF::RGB888,
+            ScreenPixelFormat::RGB_565 => PFCR::PF::RGB565,
+            _ => return Err(ErrorCode::NOSUPPORT),
+        };
+        let line_length = timing.width * bytes_per_pixel(pixel_format);
+        if framebuffers
+            .iter()
+            .any(|framebuffer| framebuffer.len() < line_length * timing.height)
+        {
+            return Err(ErrorCode::SIZE);
+        }
+
+        self.clock
+            .0
+            .configure_ltdc_pixel_clock(timing.pixel_clock)?;
+        self.enable_clock();
+
+        let hsync = timing.hsync;
+        let hback = hsync + timing.hback_porch;
+        let hactive = hback + timing.width;
+        let htotal = hactive + timing.hfront_porch;
+        let vsync = timing.vsync;
+        let vback = vsync + timing.vback_porch;
+        let vactive = vback + timing.height;
+        let vtotal = vactive + timing.vfront_porch;
+        let size = |w: usize, h: usize| SIZE::W.val(w as u32 - 1) + SIZE::H.val(h as u32 - 1);
+        self.registers.sscr.write(size(hsync, vsync));
+        self.registers.bpcr.write(size(hback, vback));
+        self.registers.awcr.write(size(hactive, vactive));
+        self.registers.twcr.write(size(htotal, vtotal));
+        self.registers.gcr.write(
+            GCR::HSPOL.val(timing.hsync_active_high as u32)
+                + GCR::VSPOL.val(timing.vsync_active_high as u32)
+                + GCR::DEPOL.val(timing.data_enable_active_high as u32)
+                + GCR::PCPOL.val(timing.pixel_clock_inverted as u32),
+        );
+        // Black background, shown when the layer is disabled.
+        self.registers.bccr.set(0);
+
+        let layer = &self.registers.layers[0];
+        layer
+            .whpcr
+            .write(POSITION::START.val(hback as u32 + 1) + POSITION::STOP.val(hactive as u32));
+        layer
+            .wvpcr
+            .write(POSITION::START.val(vback as u32 + 1) + POSITION::STOP.val(vactive as u32));
+        layer.pfcr.write(format);
+        layer.cacr.set(0xFF);
+        layer
+            .bfcr
+            .write(BFCR::BF1::ConstantAlpha + BFCR::BF2::OneMinusConstantAlpha);
+        layer.cfbar.set(framebuffers[0].as_ptr() as u32);
+        layer
+            .cfblr
+            .write(CFBLR::CFBP.val(line_length as u32) + CFBLR::CFBLL.val(line_length as u32 + 3));
+        layer.cfblnr.set(timing.height as u32);
+        layer.cr.write(LCR::LEN::SET);
+        self.registers.srcr.write(SRCR::IMR::SET);
+
+        let [framebuffer0, framebuffer1] = framebuffers;
+        framebuffer0.fill(0);
+        framebuffer1.fill(0);
+        self.framebuffers[0].replace(framebuffer0);
+        self.framebuffers[1].replace(framebuffer1);
+        self.front.set(0);
+        self.pixel_format.set(pixel_format);
+        self.timing.set(*timing);
+        self.write_frame.set(Frame {
+            x: 0,
+            y: 0,
+            width: timing.width,
+            height: timing.height,
+        });
+        Ok(())
+    }
+
+    /// Use `dma2d` to accelerate fills and blits.
+    pub fn set_dma2d(&self, dma2d: &'a Dma2d<'a>) {
+        self.dma2d.set(dma2d);
+    }
+
+    fn back(&self) -> usize {
+        1 - self.front.get()
+    }
+
+    fn check_frame(&self, frame: &Frame) -> Result<(), ErrorCode> {
+        let (width, height) = screen::Screen::get_resolution(self);
+        if frame.width == 0
+            || frame.height == 0
+            || frame.x + frame.width > width
+            || frame.y + frame.height > height
+        {
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn mark_dirty(&self, frame: Frame) {
+        self.dirty
+            .set(self.dirty.map_or(frame, |dirty| dirty.union(&frame)));
+    }
+
+    /// Deliver `pending` from a deferred call.
+    fn complete_later(&self, pending: Pending) {
+        self.pending.set(pending);
+        self.deferred_call.set();
+    }
+
+    /// Display the back buffer at the next vertical blanking period and
+    /// deliver `pending` once done.
+    fn swap(&self, pending: Pending) {
+        self.pending.set(pending);
+        let address = self.framebuffers[self.back()].map_or(0, |fb| fb.as_ptr() as u32);
+        self.registers.layers[0].cfbar.set(address);
+        if self.powered.get() {
+            self.swapping.set(true);
+            self.registers.icr.write(INTERRUPT::RR::SET);
+            self.registers.ier.modify(INTERRUPT::RR::SET);
+            self.registers.srcr.write(SRCR::VBR::SET);
+        } else {
+            // Nothing is displayed, so the buffers can be swapped right away.
+            self.registers.srcr.write(SRCR::IMR::SET);
+            self.finish_swap();
+            self.deferred_call.set();
+        }
+    }
+
+    /// Make the back buffer the front buffer, and bring the new back buffer
+    /// up to date.
+    fn finish_swap(&self) {
+        self.front.set(self.back());
+        let width = self.timing.map_or(0, |timing| timing.width);
+        let bytes_per_pixel = bytes_per_pixel(self.pixel_format.get());
+        if let Some(dirty) = self.dirty.take() {
+            self.framebuffers[self.front.get()].map(|front| {
+                self.framebuffers[self.back()].map(|back| {
+                    for row in dirty.y..dirty.y + dirty.height {
+                        let start = (row * width + dirty.x) * bytes_per_pixel;
+                        let end = start + dirty.width * bytes_per_pixel;
+                        back[start..end].copy_from_slice(&front[start..end]);
+                    }
+                });
+            });
+        }
+    }
+
+    /// Deliver the callback of the finished operation.
+    fn complete(&self) {
+        let pending = self.pending.replace(Pending::None);
+        self.busy.set(false);
+        match pending {
+            Pending::None => {}
+            Pending::CommandComplete(r) => {
+                self.client.map(|client| client.command_complete(r));
+            }
+            Pending::ScreenReady => {
+                self.client.map(|client| client.screen_is_ready());
+            }
+            Pending::WriteComplete(r) => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client.map(|client| client.write_complete(buffer, r));
+                }
+            }
+            Pending::FillComplete(r) => {
+                self.accelerated_client
+                    .map(|client| client.fill_complete(r));
+            }
+            Pending::BlitComplete(r) => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.accelerated_client
+                        .map(|client| client.blit_complete(buffer, r));
+                }
+            }
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let status = self.registers.isr.extract();
+        self.registers.icr.write(
+            INTERRUPT::RR::SET + INTERRUPT::TERR::SET + INTERRUPT::FU::SET + INTERRUPT::L::SET,
+        );
+
+        if status.is_set(INTERRUPT::RR) && self.swapping.get() {
+            self.swapping.set(false);
+            self.registers.ier.modify(INTERRUPT::RR::CLEAR);
+            self.finish_swap();
+            self.complete();
+        }
+    }
+
+    /// Copy `pixels` to the write frame of the back buffer, starting at the
+    /// current write position.
+    fn write_pixels(&self, pixels: &[u8]) {
+        let frame = self.write_frame.get();
+        let width = self.timing.map_or(0, |timing| timing.width);
+        let bytes_per_pixel = bytes_per_pixel(self.pixel_format.get());
+        let mut position = self.write_position.get();
+        let mut pixels = pixels;
+        self.framebuffers[self.back()].map(|back| {
+            while !pixels.is_empty() {
+                // Copy up to the end of the current row of the write frame.
+                let column = position % frame.width;
+                let count = (frame.width - column).min(pixels.len() / bytes_per_pixel);
+                let start = ((frame.y + position / frame.width) * width + frame.x + column)
+                    * bytes_per_pixel;
+                let length = count * bytes_per_pixel;
+                back[start..start + length].copy_from_slice(&pixels[..length]);
+                pixels = &pixels[length..];
+                position += count;
+            }
+        });
+        self.write_position.set(position);
+        self.mark_dirty(frame);
+    }
+
+    /// Check that the back buffer can be drawn into.
+    fn prepare(&self) -> Result<(), ErrorCode> {
+        if !self.powered.get() {
+            Err(ErrorCode::OFF)
+        } else if self.busy.get() {
+            Err(ErrorCode::BUSY)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Point the DMA2D at the back buffer.
+    fn prepare_dma2d(&self) -> Result<&'a Dma2d<'a>, ErrorCode> {
+        let dma2d = self.dma2d.get().ok_or(ErrorCode::NOSUPPORT)?;
+        let (width, height) = screen::Screen::get_resolution(self);
+        let address = self.framebuffers[self.back()]
+            .map(|back| back.as_mut_ptr())
+            .ok_or(ErrorCode::OFF)?;
+        // Safety: the framebuffers are owned by the LTDC for the lifetime of
+        // the kernel, and hold `width * height` pixels.
+        unsafe { dma2d.set_framebuffer(address, width, height, self.pixel_format.get())? };
+        Ok(dma2d)
+    }
+}
+
+struct LtdcClock<'a>(phclk::PeripheralClock<'a>);
+
+impl ClockInterface for LtdcClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
+
+impl DeferredCallClient for Ltdc<'_> {
+    fn handle_deferred_call(&self) {
+        self.complete();
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> screen::Screen<'a> for Ltdc<'a> {
+    fn set_client(&self, client: &'a dyn screen::ScreenClient) {
+        self.client.set(client);
+    }
+
+    fn get_resolution(&self) -> (usize, usize) {
+        self.timing
+            .map_or((0, 0), |timing| (timing.width, timing.height))
+    }
+
+    fn get_pixel_format(&self) -> ScreenPixelFormat {
+        self.pixel_format.get()
+    }
+
+    fn get_rotation(&self) -> ScreenRotation {
+        ScreenRotation::Normal
+    }
+
+    fn set_write_frame(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), ErrorCode> {
+        self.prepare()?;
+        let frame = Frame {
+            x,
+            y,
+            width,
+            height,
+        };
+        self.check_frame(&frame)?;
+        self.write_frame.set(frame);
+        self.write_position.set(0);
+        self.busy.set(true);
+        self.complete_later(Pending::CommandComplete(Ok(())));
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+        continue_write: bool,
+    ) -> Result<(), ErrorCode> {
+        self.prepare()?;
+        let frame = self.write_frame.get();
+        let bytes_per_pixel = bytes_per_pixel(self.pixel_format.get());
+        if !continue_write {
+            self.write_position.set(0);
+        }
+        let pixels = buffer.len() / bytes_per_pixel;
+        if self.write_position.get() + pixels > frame.width * frame.height {
+            return Err(ErrorCode::SIZE);
+        }
+
+        self.busy.set(true);
+        self.write_pixels(&buffer[..pixels * bytes_per_pixel]);
+        self.buffer.replace(buffer);
+        if self.write_position.get() == frame.width * frame.height {
+            self.swap(Pending::WriteComplete(Ok(())));
+        } else {
+            self.complete_later(Pending::WriteComplete(Ok(())));
+        }
+        Ok(())
+    }
+
+    fn set_brightness(&self, brightness: u16) -> Result<(), ErrorCode> {
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        // The panel backlight is not controlled by the LTDC, so brightness
+        // only shows or hides the layer.
+        let layer = &self.registers.layers[0];
+        if brightness == 0 {
+            layer.cr.modify(LCR::LEN::CLEAR);
+        } else {
+            layer.cr.modify(LCR::LEN::SET);
+        }
+        self.registers.srcr.write(SRCR::VBR::SET);
+        self.busy.set(true);
+        self.complete_later(Pending::CommandComplete(Ok(())));
+        Ok(())
+    }
+
+    fn set_power(&self, enabled: bool) -> Result<(), ErrorCode> {
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.timing.is_none() {
+            return Err(ErrorCode::INVAL);
+        }
+        if enabled {
+            self.enable_clock();
+            self.registers.gcr.modify(GCR::LTDCEN::SET);
+        } else {
+            self.registers.gcr.modify(GCR::LTDCEN::CLEAR);
+        }
+        self.powered.set(enabled);
+        self.busy.set(true);
+        self.complete_later(Pending::ScreenReady);
+        Ok(())
+    }
+
+    fn set_invert(&self, _enabled: bool) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+impl<'a> ScreenAccelerated<'a> for Ltdc<'a> {
+    fn set_client(&self, client: &'a dyn ScreenAcceleratedClient) {
+        self.accelerated_client.set(client);
+    }
+
+    fn fill(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: u32,
+    ) -> Result<(), ErrorCode> {
+        self.prepare()?;
+        let frame = Frame {
+            x,
+            y,
+            width,
+            height,
+        };
+        self.check_frame(&frame)?;
+        let dma2d = self.prepare_dma2d()?;
+        dma2d.fill(x, y, width, height, color)?;
+        self.busy.set(true);
+        self.accelerated_frame.set(frame);
+        Ok(())
+    }
+
+    fn blit(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        let frame = Frame {
+            x,
+            y,
+            width,
+            height,
+        };
+        if let Err(e) = self.prepare().and_then(|()| self.check_frame(&frame)) {
+            return Err((e, buffer));
+        }
+        let dma2d = match self.prepare_dma2d() {
+            Ok(dma2d) => dma2d,
+            Err(e) => return Err((e, buffer)),
+        };
+        dma2d.blit(buffer, x, y, width, height)?;
+        self.busy.set(true);
+        self.accelerated_frame.set(frame);
+        Ok(())
+    }
+}
+
+impl ScreenAcceleratedClient for Ltdc<'_> {
+    fn fill_complete(&self, result: Result<(), ErrorCode>) {
+        if let Some(frame) = self.accelerated_frame.take() {
+            self.mark_dirty(frame);
+        }
+        if result.is_ok() {
+            self.swap(Pending::FillComplete(result));
+        } else {
+            self.complete_later(Pending::FillComplete(result));
+        }
+    }
+
+    fn blit_complete(&self, buffer: SubSliceMut<'static, u8>, result: Result<(), ErrorCode>) {
+        if let Some(frame) = self.accelerated_frame.take() {
+            self.mark_dirty(frame);
+        }
+        self.buffer.replace(buffer);
+        if result.is_ok() {
+            self.swap(Pending::BlitComplete(result));
+        } else {
+            self.complete_later(Pending::BlitComplete(result));
+        }
+    }
+}
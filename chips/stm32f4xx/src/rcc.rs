@@ -2,8 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+use cortexm4::support::atomic;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
-use kernel::utilities::registers::{register_bitfields, ReadWrite};
+use kernel::utilities::registers::{register_bitfields, FieldValue, ReadWrite, RegisterLongName};
 use kernel::utilities::StaticRef;
 
 /// Reset and clock control
@@ -74,6 +75,10 @@ struct RccRegisters {
 
 register_bitfields![u32,
     CR [
+        /// PLLSAI clock ready flag
+        PLLSAIRDY OFFSET(29) NUMBITS(1) [],
+        /// PLLSAI enable
+        PLLSAION OFFSET(28) NUMBITS(1) [],
         /// PLLI2S clock ready flag
         PLLI2SRDY OFFSET(27) NUMBITS(1) [],
         /// PLLI2S enable
@@ -322,6 +327,8 @@ register_bitfields![u32,
         OTGHSULPIEN OFFSET(30) NUMBITS(1) [],
         /// USB OTG HS clock enable
         OTGHSEN OFFSET(29) NUMBITS(1) [],
+        /// DMA2D clock enable
+        DMA2DEN OFFSET(23) NUMBITS(1) [],
         /// DMA2 clock enable
         DMA2EN OFFSET(22) NUMBITS(1) [],
         /// DMA1 clock enable
@@ -447,7 +454,9 @@ register_bitfields![u32,
         /// SAI1 clock enable
         SAI1EN OFFSET(22) NUMBITS(1) [],
         /// SAI2 clock enable
-        SAI2EN OFFSET(23) NUMBITS(1) []
+        SAI2EN OFFSET(23) NUMBITS(1) [],
+        /// LTDC clock enable
+        LTDCEN OFFSET(26) NUMBITS(1) []
     ],
     AHB1LPENR [
         /// IO port A clock enable during sleep mode
@@ -655,13 +664,17 @@ register_bitfields![u32,
         /// PLLSAI division factor for 48 MHz clock
         PLLSAIP OFFSET(16) NUMBITS(2) [],
         /// PLLSAI division factor for SAIs clock
-        PLLSAIQ OFFSET(24) NUMBITS(4) []
+        PLLSAIQ OFFSET(24) NUMBITS(4) [],
+        /// PLLSAI division factor for LCD clock (STM32F42xxx/43xxx)
+        PLLSAIR OFFSET(28) NUMBITS(3) []
     ],
     DCKCFGR [
         /// PLLI2S division factor for SAIs clock
         PLLI2SDIVQ OFFSET(0) NUMBITS(5) [],
         /// PLLSAI division factor for SAIs clock
         PLLSAIDIVQ OFFSET(8) NUMBITS(5) [],
+        /// PLLSAI division factor for LCD clock (STM32F42xxx/43xxx)
+        PLLSAIDIVR OFFSET(16) NUMBITS(2) [],
         /// SAI1 clock source selection
         SAI1SRC OFFSET(20) NUMBITS(2) [],
         /// SAI2 clock source selection
@@ -754,6 +767,18 @@ impl Rcc {
         self.init_pll_clock();
     }
 
+    // Read-modify-write a register with interrupts disabled. The enable,
+    // reset and configuration registers are shared by the drivers of many
+    // peripherals: if an interrupt handler modified the same register between
+    // the read and the write, its update would be lost.
+    fn modify_shared<R: RegisterLongName>(
+        &self,
+        register: &ReadWrite<u32, R>,
+        field: FieldValue<u32, R>,
+    ) {
+        unsafe { atomic(|| register.modify(field)) }
+    }
+
     // Init the PLL clock. The default configuration:
     // + if DEFAULT_PLLM_VALUE == PLLM::DivideBy8:
     //   + 2MHz VCO input frequency for reduced PLL jitter: freq_VCO_input = freq_source / PLLM
@@ -789,7 +814,7 @@ impl Rcc {
     // The source must be enabled
     // NOTE: The flash latency also needs to be configured when changing the system clock frequency
     pub(crate) fn set_sys_clock_source(&self, source: SysClockSource) {
-        self.registers.cfgr.modify(CFGR::SW.val(source as u32));
+        self.modify_shared(&self.registers.cfgr, CFGR::SW.val(source as u32));
     }
 
     pub(crate) fn is_hsi_clock_system_clock(&self) -> bool {
@@ -809,11 +834,11 @@ impl Rcc {
     /* HSI clock */
     // The HSI clock must not be configured as the system clock, either directly or indirectly.
     pub(crate) fn disable_hsi_clock(&self) {
-        self.registers.cr.modify(CR::HSION::CLEAR);
+        self.modify_shared(&self.registers.cr, CR::HSION::CLEAR);
     }
 
     pub(crate) fn enable_hsi_clock(&self) {
-        self.registers.cr.modify(CR::HSION::SET);
+        self.modify_shared(&self.registers.cr, CR::HSION::SET);
     }
 
     pub(crate) fn is_enabled_hsi_clock(&self) -> bool {
@@ -827,16 +852,16 @@ impl Rcc {
 
     /* HSE clock */
     pub(crate) fn disable_hse_clock(&self) {
-        self.registers.cr.modify(CR::HSEON::CLEAR);
-        self.registers.cr.modify(CR::HSEBYP::CLEAR);
+        self.modify_shared(&self.registers.cr, CR::HSEON::CLEAR);
+        self.modify_shared(&self.registers.cr, CR::HSEBYP::CLEAR);
     }
 
     pub(crate) fn enable_hse_clock_bypass(&self) {
-        self.registers.cr.modify(CR::HSEBYP::SET);
+        self.modify_shared(&self.registers.cr, CR::HSEBYP::SET);
     }
 
     pub(crate) fn enable_hse_clock(&self) {
-        self.registers.cr.modify(CR::HSEON::SET);
+        self.modify_shared(&self.registers.cr, CR::HSEON::SET);
     }
 
     pub(crate) fn is_enabled_hse_clock(&self) -> bool {
@@ -852,11 +877,11 @@ impl Rcc {
 
     // The main PLL clock must not be configured as the system clock.
     pub(crate) fn disable_pll_clock(&self) {
-        self.registers.cr.modify(CR::PLLON::CLEAR);
+        self.modify_shared(&self.registers.cr, CR::PLLON::CLEAR);
     }
 
     pub(crate) fn enable_pll_clock(&self) {
-        self.registers.cr.modify(CR::PLLON::SET);
+        self.modify_shared(&self.registers.cr, CR::PLLON::SET);
     }
 
     pub(crate) fn is_enabled_pll_clock(&self) -> bool {
@@ -877,9 +902,7 @@ impl Rcc {
 
     // This method must be called only when all PLL clocks are disabled
     pub(crate) fn set_pll_clocks_source(&self, source: PllSource) {
-        self.registers
-            .pllcfgr
-            .modify(PLLCFGR::PLLSRC.val(source as u32));
+        self.modify_shared(&self.registers.pllcfgr, PLLCFGR::PLLSRC.val(source as u32));
     }
 
     pub(crate) fn get_pll_clocks_m_divider(&self) -> PLLM {
@@ -892,7 +915,7 @@ impl Rcc {
 
     // This method must be called only when all PLL clocks are disabled
     pub(crate) fn set_pll_clocks_m_divider(&self, m: PLLM) {
-        self.registers.pllcfgr.modify(PLLCFGR::PLLM.val(m as u32));
+        self.modify_shared(&self.registers.pllcfgr, PLLCFGR::PLLM.val(m as u32));
     }
 
     pub(crate) fn get_pll_clock_n_multiplier(&self) -> usize {
@@ -901,7 +924,7 @@ impl Rcc {
 
     // This method must be called only if the main PLL clock is disabled
     pub(crate) fn set_pll_clock_n_multiplier(&self, n: usize) {
-        self.registers.pllcfgr.modify(PLLCFGR::PLLN.val(n as u32));
+        self.modify_shared(&self.registers.pllcfgr, PLLCFGR::PLLN.val(n as u32));
     }
 
     pub(crate) fn get_pll_clock_p_divider(&self) -> PLLP {
@@ -915,7 +938,7 @@ impl Rcc {
 
     // This method must be called only if the main PLL clock is disabled
     pub(crate) fn set_pll_clock_p_divider(&self, p: PLLP) {
-        self.registers.pllcfgr.modify(PLLCFGR::PLLP.val(p as u32));
+        self.modify_shared(&self.registers.pllcfgr, PLLCFGR::PLLP.val(p as u32));
     }
 
     pub(crate) fn _get_pll_clock_q_divider(&self) -> PLLQ {
@@ -933,15 +956,13 @@ impl Rcc {
 
     // This method must be called only if the main PLL clock is disabled
     pub(crate) fn set_pll_clock_q_divider(&self, q: PLLQ) {
-        self.registers.pllcfgr.modify(PLLCFGR::PLLQ.val(q as u32));
+        self.modify_shared(&self.registers.pllcfgr, PLLCFGR::PLLQ.val(q as u32));
     }
 
     /* AHB prescaler */
 
     pub(crate) fn set_ahb_prescaler(&self, ahb_prescaler: AHBPrescaler) {
-        self.registers
-            .cfgr
-            .modify(CFGR::HPRE.val(ahb_prescaler as u32));
+        self.modify_shared(&self.registers.cfgr, CFGR::HPRE.val(ahb_prescaler as u32));
     }
 
     pub(crate) fn get_ahb_prescaler(&self) -> AHBPrescaler {
@@ -961,9 +982,7 @@ impl Rcc {
     /* APB1 prescaler */
 
     pub(crate) fn set_apb1_prescaler(&self, apb1_prescaler: APBPrescaler) {
-        self.registers
-            .cfgr
-            .modify(CFGR::PPRE1.val(apb1_prescaler as u32));
+        self.modify_shared(&self.registers.cfgr, CFGR::PPRE1.val(apb1_prescaler as u32));
     }
 
     pub(crate) fn get_apb1_prescaler(&self) -> APBPrescaler {
@@ -979,9 +998,7 @@ impl Rcc {
     /* APB2 prescaler */
 
     pub(crate) fn set_apb2_prescaler(&self, apb2_prescaler: APBPrescaler) {
-        self.registers
-            .cfgr
-            .modify(CFGR::PPRE2.val(apb2_prescaler as u32));
+        self.modify_shared(&self.registers.cfgr, CFGR::PPRE2.val(apb2_prescaler as u32));
     }
 
     pub(crate) fn get_apb2_prescaler(&self) -> APBPrescaler {
@@ -995,7 +1012,7 @@ impl Rcc {
     }
 
     pub(crate) fn set_mco1_clock_source(&self, source: MCO1Source) {
-        self.registers.cfgr.modify(CFGR::MCO1.val(source as u32));
+        self.modify_shared(&self.registers.cfgr, CFGR::MCO1.val(source as u32));
     }
 
     pub(crate) fn get_mco1_clock_source(&self) -> MCO1Source {
@@ -1010,9 +1027,7 @@ impl Rcc {
     }
 
     pub(crate) fn set_mco1_clock_divider(&self, divider: MCO1Divider) {
-        self.registers
-            .cfgr
-            .modify(CFGR::MCO1PRE.val(divider as u32));
+        self.modify_shared(&self.registers.cfgr, CFGR::MCO1PRE.val(divider as u32));
     }
 
     pub(crate) fn get_mco1_clock_divider(&self) -> MCO1Divider {
@@ -1026,8 +1041,96 @@ impl Rcc {
     }
 
     pub(crate) fn configure_rng_clock(&self) {
-        self.registers.pllcfgr.modify(PLLCFGR::PLLQ.val(2));
-        self.registers.cr.modify(CR::PLLON::SET);
+        self.modify_shared(&self.registers.pllcfgr, PLLCFGR::PLLQ.val(2));
+        self.modify_shared(&self.registers.cr, CR::PLLON::SET);
+    }
+
+    // PLLSAI clock
+
+    pub(crate) fn disable_pllsai_clock(&self) {
+        self.modify_shared(&self.registers.cr, CR::PLLSAION::CLEAR);
+    }
+
+    pub(crate) fn enable_pllsai_clock(&self) {
+        self.modify_shared(&self.registers.cr, CR::PLLSAION::SET);
+    }
+
+    // The PLLSAI clock is locked when its signal is stable
+    pub(crate) fn is_locked_pllsai_clock(&self) -> bool {
+        self.registers.cr.is_set(CR::PLLSAIRDY)
+    }
+
+    // This method must be called only when the PLLSAI clock is disabled.
+    //
+    // LCD clock = VCO input frequency * n / r / div_r
+    pub(crate) fn configure_pllsai_lcd_clock(&self, n: usize, r: usize, div_r: PllSaiDivR) {
+        self.modify_shared(
+            &self.registers.pllsaicfgr,
+            PLLSAICFGR::PLLSAIN.val(n as u32) + PLLSAICFGR::PLLSAIR.val(r as u32),
+        );
+        self.modify_shared(
+            &self.registers.dckcfgr,
+            DCKCFGR::PLLSAIDIVR.val(div_r as u32),
+        );
+    }
+
+    // PLLI2S clock
+
+    pub(crate) fn disable_plli2s_clock(&self) {
+        self.modify_shared(&self.registers.cr, CR::PLLI2SON::CLEAR);
+    }
+
+    pub(crate) fn enable_plli2s_clock(&self) {
+        self.modify_shared(&self.registers.cr, CR::PLLI2SON::SET);
+    }
+
+    // The PLLI2S clock is locked when its signal is stable
+    pub(crate) fn is_locked_plli2s_clock(&self) -> bool {
+        self.registers.cr.is_set(CR::PLLI2SRDY)
+    }
+
+    // This method must be called only when the PLLI2S clock is disabled.
+    //
+    // SAI1 clock = VCO input frequency * n / q / div_q
+    pub(crate) fn configure_plli2s_sai1_clock(&self, n: usize, q: usize, div_q: usize) {
+        self.modify_shared(
+            &self.registers.plli2scfgr,
+            PLLI2SCFGR::PLLI2SN.val(n as u32) + PLLI2SCFGR::PLLI2SQ.val(q as u32),
+        );
+        // PLLI2SDIVQ holds the division factor minus one. SAI1 block A is
+        // clocked from PLLI2S_Q / PLLI2SDIVQ.
+        self.modify_shared(
+            &self.registers.dckcfgr,
+            DCKCFGR::PLLI2SDIVQ.val(div_q as u32 - 1) + DCKCFGR::SAI1SRC.val(0b01),
+        );
+    }
+
+    // SAI1 clock
+
+    pub(crate) fn is_enabled_sai1_clock(&self) -> bool {
+        self.registers.apb2enr.is_set(APB2ENR::SAI1EN)
+    }
+
+    pub(crate) fn enable_sai1_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SAI1EN::SET)
+    }
+
+    pub(crate) fn disable_sai1_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SAI1EN::CLEAR)
+    }
+
+    // LTDC clock
+
+    pub(crate) fn is_enabled_ltdc_clock(&self) -> bool {
+        self.registers.apb2enr.is_set(APB2ENR::LTDCEN)
+    }
+
+    pub(crate) fn enable_ltdc_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::LTDCEN::SET)
+    }
+
+    pub(crate) fn disable_ltdc_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::LTDCEN::CLEAR)
     }
 
     // I2C1 clock
@@ -1037,13 +1140,13 @@ impl Rcc {
     }
 
     pub(crate) fn enable_i2c1_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::I2C1EN::SET);
-        self.registers.apb1rstr.modify(APB1RSTR::I2C1RST::SET);
-        self.registers.apb1rstr.modify(APB1RSTR::I2C1RST::CLEAR);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::I2C1EN::SET);
+        self.modify_shared(&self.registers.apb1rstr, APB1RSTR::I2C1RST::SET);
+        self.modify_shared(&self.registers.apb1rstr, APB1RSTR::I2C1RST::CLEAR);
     }
 
     pub(crate) fn disable_i2c1_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::I2C1EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::I2C1EN::CLEAR)
     }
 
     // SPI3 clock
@@ -1053,11 +1156,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_spi3_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::SPI3EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::SPI3EN::SET)
     }
 
     pub(crate) fn disable_spi3_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::SPI3EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::SPI3EN::CLEAR)
     }
 
     // TIM2 clock
@@ -1070,11 +1173,137 @@ impl Rcc {
     }
 
     pub(crate) fn enable_tim2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::TIM2EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM2EN::SET)
     }
 
     pub(crate) fn disable_tim2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::TIM2EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM2EN::CLEAR)
+    }
+
+    // TIM3 clock
+
+    pub(crate) fn is_enabled_tim3_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM3EN)
+    }
+
+    pub(crate) fn enable_tim3_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM3EN::SET)
+    }
+
+    pub(crate) fn disable_tim3_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM3EN::CLEAR)
+    }
+
+    // TIM4 clock
+
+    pub(crate) fn is_enabled_tim4_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM4EN)
+    }
+
+    pub(crate) fn enable_tim4_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM4EN::SET)
+    }
+
+    pub(crate) fn disable_tim4_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM4EN::CLEAR)
+    }
+
+    // TIM5 clock
+
+    pub(crate) fn is_enabled_tim5_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM5EN)
+    }
+
+    pub(crate) fn enable_tim5_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM5EN::SET)
+    }
+
+    pub(crate) fn disable_tim5_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM5EN::CLEAR)
+    }
+
+    // TIM9 clock
+
+    pub(crate) fn is_enabled_tim9_clock(&self) -> bool {
+        self.registers.apb2enr.is_set(APB2ENR::TIM9EN)
+    }
+
+    pub(crate) fn enable_tim9_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::TIM9EN::SET)
+    }
+
+    pub(crate) fn disable_tim9_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::TIM9EN::CLEAR)
+    }
+
+    // TIM10 clock
+
+    pub(crate) fn is_enabled_tim10_clock(&self) -> bool {
+        self.registers.apb2enr.is_set(APB2ENR::TIM10EN)
+    }
+
+    pub(crate) fn enable_tim10_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::TIM10EN::SET)
+    }
+
+    pub(crate) fn disable_tim10_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::TIM10EN::CLEAR)
+    }
+
+    // TIM11 clock
+
+    pub(crate) fn is_enabled_tim11_clock(&self) -> bool {
+        self.registers.apb2enr.is_set(APB2ENR::TIM11EN)
+    }
+
+    pub(crate) fn enable_tim11_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::TIM11EN::SET)
+    }
+
+    pub(crate) fn disable_tim11_clock(&self) {
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::TIM11EN::CLEAR)
+    }
+
+    // TIM12 clock
+
+    pub(crate) fn is_enabled_tim12_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM12EN)
+    }
+
+    pub(crate) fn enable_tim12_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM12EN::SET)
+    }
+
+    pub(crate) fn disable_tim12_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM12EN::CLEAR)
+    }
+
+    // TIM13 clock
+
+    pub(crate) fn is_enabled_tim13_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM13EN)
+    }
+
+    pub(crate) fn enable_tim13_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM13EN::SET)
+    }
+
+    pub(crate) fn disable_tim13_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM13EN::CLEAR)
+    }
+
+    // TIM14 clock
+
+    pub(crate) fn is_enabled_tim14_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM14EN)
+    }
+
+    pub(crate) fn enable_tim14_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM14EN::SET)
+    }
+
+    pub(crate) fn disable_tim14_clock(&self) {
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::TIM14EN::CLEAR)
     }
 
     // SYSCFG clock
@@ -1084,11 +1313,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_syscfg_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::SYSCFGEN::SET)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SYSCFGEN::SET)
     }
 
     pub(crate) fn disable_syscfg_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::SYSCFGEN::CLEAR)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::SYSCFGEN::CLEAR)
     }
 
     // DMA1 clock
@@ -1098,11 +1327,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_dma1_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::DMA1EN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::DMA1EN::SET)
     }
 
     pub(crate) fn disable_dma1_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::DMA1EN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::DMA1EN::CLEAR)
     }
 
     // DMA2 clock
@@ -1111,11 +1340,25 @@ impl Rcc {
     }
 
     pub(crate) fn enable_dma2_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::DMA2EN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::DMA2EN::SET)
     }
 
     pub(crate) fn disable_dma2_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::DMA2EN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::DMA2EN::CLEAR)
+    }
+
+    // DMA2D clock
+
+    pub(crate) fn is_enabled_dma2d_clock(&self) -> bool {
+        self.registers.ahb1enr.is_set(AHB1ENR::DMA2DEN)
+    }
+
+    pub(crate) fn enable_dma2d_clock(&self) {
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::DMA2DEN::SET)
+    }
+
+    pub(crate) fn disable_dma2d_clock(&self) {
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::DMA2DEN::CLEAR)
     }
 
     // GPIOH clock
@@ -1125,11 +1368,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpioh_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOHEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOHEN::SET)
     }
 
     pub(crate) fn disable_gpioh_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOHEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOHEN::CLEAR)
     }
 
     // GPIOG clock
@@ -1139,11 +1382,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpiog_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOGEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOGEN::SET)
     }
 
     pub(crate) fn disable_gpiog_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOGEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOGEN::CLEAR)
     }
 
     // GPIOF clock
@@ -1153,11 +1396,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpiof_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOFEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOFEN::SET)
     }
 
     pub(crate) fn disable_gpiof_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOFEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOFEN::CLEAR)
     }
 
     // GPIOE clock
@@ -1167,11 +1410,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpioe_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOEEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOEEN::SET)
     }
 
     pub(crate) fn disable_gpioe_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOEEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOEEN::CLEAR)
     }
 
     // GPIOD clock
@@ -1181,11 +1424,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpiod_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIODEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIODEN::SET)
     }
 
     pub(crate) fn disable_gpiod_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIODEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIODEN::CLEAR)
     }
 
     // GPIOC clock
@@ -1195,11 +1438,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpioc_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOCEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOCEN::SET)
     }
 
     pub(crate) fn disable_gpioc_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOCEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOCEN::CLEAR)
     }
 
     // GPIOB clock
@@ -1209,11 +1452,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpiob_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOBEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOBEN::SET)
     }
 
     pub(crate) fn disable_gpiob_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOBEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOBEN::CLEAR)
     }
 
     // GPIOA clock
@@ -1223,11 +1466,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_gpioa_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOAEN::SET)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOAEN::SET)
     }
 
     pub(crate) fn disable_gpioa_clock(&self) {
-        self.registers.ahb1enr.modify(AHB1ENR::GPIOAEN::CLEAR)
+        self.modify_shared(&self.registers.ahb1enr, AHB1ENR::GPIOAEN::CLEAR)
     }
 
     // FMC
@@ -1237,11 +1480,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_fmc_clock(&self) {
-        self.registers.ahb3enr.modify(AHB3ENR::FMCEN::SET)
+        self.modify_shared(&self.registers.ahb3enr, AHB3ENR::FMCEN::SET)
     }
 
     pub(crate) fn disable_fmc_clock(&self) {
-        self.registers.ahb3enr.modify(AHB3ENR::FMCEN::CLEAR)
+        self.modify_shared(&self.registers.ahb3enr, AHB3ENR::FMCEN::CLEAR)
     }
 
     // USART1 clock
@@ -1250,11 +1493,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_usart1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::USART1EN::SET)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::USART1EN::SET)
     }
 
     pub(crate) fn disable_usart1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::USART1EN::CLEAR)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::USART1EN::CLEAR)
     }
 
     // USART2 clock
@@ -1264,11 +1507,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_usart2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART2EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART2EN::SET)
     }
 
     pub(crate) fn disable_usart2_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART2EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART2EN::CLEAR)
     }
 
     // USART3 clock
@@ -1278,11 +1521,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_usart3_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART3EN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART3EN::SET)
     }
 
     pub(crate) fn disable_usart3_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::USART3EN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::USART3EN::CLEAR)
     }
 
     // ADC1 clock
@@ -1292,11 +1535,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_adc1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::ADC1EN::SET)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::ADC1EN::SET)
     }
 
     pub(crate) fn disable_adc1_clock(&self) {
-        self.registers.apb2enr.modify(APB2ENR::ADC1EN::CLEAR)
+        self.modify_shared(&self.registers.apb2enr, APB2ENR::ADC1EN::CLEAR)
     }
 
     // DAC clock
@@ -1306,11 +1549,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_dac_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::DACEN::SET)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::DACEN::SET)
     }
 
     pub(crate) fn disable_dac_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::DACEN::CLEAR)
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::DACEN::CLEAR)
     }
 
     // RNG clock
@@ -1320,11 +1563,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_rng_clock(&self) {
-        self.registers.ahb2enr.modify(AHB2ENR::RNGEN::SET);
+        self.modify_shared(&self.registers.ahb2enr, AHB2ENR::RNGEN::SET);
     }
 
     pub(crate) fn disable_rng_clock(&self) {
-        self.registers.ahb2enr.modify(AHB2ENR::RNGEN::CLEAR);
+        self.modify_shared(&self.registers.ahb2enr, AHB2ENR::RNGEN::CLEAR);
     }
 
     // OTGFS clock
@@ -1334,11 +1577,11 @@ impl Rcc {
     }
 
     pub(crate) fn enable_otgfs_clock(&self) {
-        self.registers.ahb2enr.modify(AHB2ENR::OTGFSEN::SET);
+        self.modify_shared(&self.registers.ahb2enr, AHB2ENR::OTGFSEN::SET);
     }
 
     pub(crate) fn disable_otgfs_clock(&self) {
-        self.registers.ahb2enr.modify(AHB2ENR::OTGFSEN::CLEAR);
+        self.modify_shared(&self.registers.ahb2enr, AHB2ENR::OTGFSEN::CLEAR);
     }
 
     // CAN1 clock
@@ -1348,13 +1591,21 @@ impl Rcc {
     }
 
     pub(crate) fn enable_can1_clock(&self) {
-        self.registers.apb1rstr.modify(APB1RSTR::CAN1RST::SET);
-        self.registers.apb1rstr.modify(APB1RSTR::CAN1RST::CLEAR);
-        self.registers.apb1enr.modify(APB1ENR::CAN1EN::SET);
+        self.modify_shared(&self.registers.apb1rstr, APB1RSTR::CAN1RST::SET);
+        self.modify_shared(&self.registers.apb1rstr, APB1RSTR::CAN1RST::CLEAR);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::CAN1EN::SET);
     }
 
     pub(crate) fn disable_can1_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::CAN1EN::CLEAR);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::CAN1EN::CLEAR);
+    }
+
+    pub(crate) fn configure_can1_sleep_clock(&self, enable: bool) {
+        if enable {
+            self.modify_shared(&self.registers.apb1lpenr, APB1LPENR::CAN1LPEN::SET);
+        } else {
+            self.modify_shared(&self.registers.apb1lpenr, APB1LPENR::CAN1LPEN::CLEAR);
+        }
     }
 
     // RTC clock
@@ -1367,7 +1618,7 @@ impl Rcc {
     }
 
     pub(crate) fn enable_lsi_clock(&self) {
-        self.registers.csr.modify(CSR::LSION::SET);
+        self.modify_shared(&self.registers.csr, CSR::LSION::SET);
     }
 
     pub(crate) fn is_enabled_pwr_clock(&self) -> bool {
@@ -1376,11 +1627,11 @@ impl Rcc {
 
     pub(crate) fn enable_pwr_clock(&self) {
         // Enable the power interface clock
-        self.registers.apb1enr.modify(APB1ENR::PWREN::SET);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::PWREN::SET);
     }
 
     pub(crate) fn disable_pwr_clock(&self) {
-        self.registers.apb1enr.modify(APB1ENR::PWREN::CLEAR);
+        self.modify_shared(&self.registers.apb1enr, APB1ENR::PWREN::CLEAR);
     }
 
     pub(crate) fn is_enabled_rtc_clock(&self) -> bool {
@@ -1400,15 +1651,15 @@ impl Rcc {
 
         // Select RTC clock source
         let source_num = Rcc::source_into_u32(source);
-        self.registers.bdcr.modify(BDCR::RTCSEL.val(source_num));
+        self.modify_shared(&self.registers.bdcr, BDCR::RTCSEL.val(source_num));
 
         // Enable RTC clock
-        self.registers.bdcr.modify(BDCR::RTCEN::SET);
+        self.modify_shared(&self.registers.bdcr, BDCR::RTCEN::SET);
     }
 
     pub(crate) fn disable_rtc_clock(&self) {
-        self.registers.bdcr.modify(BDCR::RTCEN.val(1));
-        self.registers.bdcr.modify(BDCR::RTCSEL.val(0));
+        self.modify_shared(&self.registers.bdcr, BDCR::RTCEN.val(1));
+        self.modify_shared(&self.registers.bdcr, BDCR::RTCSEL.val(0));
     }
 }
 
@@ -1427,6 +1678,21 @@ impl From<PLLP> for usize {
     }
 }
 
+/// Division factor between the PLLSAI R output and the LCD clock.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum PllSaiDivR {
+    DivideBy2 = 0b00,
+    DivideBy4 = 0b01,
+    DivideBy8 = 0b10,
+    DivideBy16 = 0b11,
+}
+
+impl From<PllSaiDivR> for usize {
+    fn from(item: PllSaiDivR) -> Self {
+        2 << item as usize
+    }
+}
+
 // Theoretically, the PLLM value can range from 2 to 63. However, the current implementation was
 // designed to support 1MHz frequency precision. In a future update, PLLM will become a usize.
 #[allow(dead_code)]
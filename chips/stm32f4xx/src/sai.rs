@@ -0,0 +1,479 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Serial audio interface (SAI) driver, available on STM32F42xxx/43xxx.
+//!
+//! Block A of the SAI is used as a master receiver in TDM mode, to capture
+//! multi-channel microphone arrays (for example several I2S/TDM MEMS
+//! microphones sharing the same data line). Each frame is made of up to 8
+//! slots of 32 bits, each carrying a 16-bit sample. The frame sync is a one
+//! bit wide pulse, asserted one bit before the first slot.
+//!
+//! The SAI kernel clock is generated by the PLLI2S so that the bit clock is
+//! exactly `sample rate * slots * 32`. Samples are read from the FIFO on
+//! interrupts and interleaved in the buffers as described in
+//! `hil::audio::TdmAudioSource`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! peripherals.sai1.set_sample_rate(16_000);
+//! TdmAudioSource::set_slots(&peripherals.sai1, 4)?;
+//! AudioSource::set_client(&peripherals.sai1, capture);
+//! ```
+
+use crate::clocks::{phclk, Stm32f4Clocks};
+use core::cell::Cell;
+use kernel::hil::audio::{self, AudioClient, AudioSource, TdmAudioSource};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+#[repr(C)]
+pub struct SaiBlockRegisters {
+    /// configuration register 1
+    cr1: ReadWrite<u32, CR1::Register>,
+    /// configuration register 2
+    cr2: ReadWrite<u32, CR2::Register>,
+    /// frame configuration register
+    frcr: ReadWrite<u32, FRCR::Register>,
+    /// slot register
+    slotr: ReadWrite<u32, SLOTR::Register>,
+    /// interrupt mask register
+    imr: ReadWrite<u32, IMR::Register>,
+    /// status register
+    sr: ReadOnly<u32, SR::Register>,
+    /// clear flag register
+    clrfr: WriteOnly<u32, SR::Register>,
+    /// data register
+    dr: ReadWrite<u32>,
+}
+
+#[repr(C)]
+pub struct SaiRegisters {
+    /// global configuration register
+    gcr: ReadWrite<u32>,
+    /// audio block A
+    a: SaiBlockRegisters,
+    /// audio block B
+    b: SaiBlockRegisters,
+}
+
+register_bitfields![u32,
+    CR1 [
+        /// Audio block mode
+        MODE OFFSET(0) NUMBITS(2) [
+            MasterTransmitter = 0,
+            MasterReceiver = 1,
+            SlaveTransmitter = 2,
+            SlaveReceiver = 3
+        ],
+        /// Protocol configuration
+        PRTCFG OFFSET(2) NUMBITS(2) [
+            Free = 0,
+            Spdif = 1,
+            Ac97 = 2
+        ],
+        /// Data size
+        DS OFFSET(5) NUMBITS(3) [
+            Bits8 = 2,
+            Bits10 = 3,
+            Bits16 = 4,
+            Bits20 = 5,
+            Bits24 = 6,
+            Bits32 = 7
+        ],
+        /// Least significant bit first
+        LSBFIRST OFFSET(8) NUMBITS(1) [],
+        /// Clock strobing edge
+        CKSTR OFFSET(9) NUMBITS(1) [],
+        /// Synchronization enable
+        SYNCEN OFFSET(10) NUMBITS(2) [],
+        /// Mono mode
+        MONO OFFSET(12) NUMBITS(1) [],
+        /// Output drive
+        OUTDRIV OFFSET(13) NUMBITS(1) [],
+        /// Audio block enable
+        SAIEN OFFSET(16) NUMBITS(1) [],
+        /// DMA enable
+        DMAEN OFFSET(17) NUMBITS(1) [],
+        /// No divider
+        NODIV OFFSET(19) NUMBITS(1) [],
+        /// Master clock divider
+        MCKDIV OFFSET(20) NUMBITS(4) []
+    ],
+    CR2 [
+        /// FIFO threshold
+        FTH OFFSET(0) NUMBITS(3) [
+            Empty = 0,
+            QuarterFull = 1,
+            HalfFull = 2,
+            ThreeQuarterFull = 3,
+            Full = 4
+        ],
+        /// FIFO flush
+        FFLUSH OFFSET(3) NUMBITS(1) [],
+        /// Tristate management on data line
+        TRIS OFFSET(4) NUMBITS(1) [],
+        /// Mute
+        MUTE OFFSET(5) NUMBITS(1) [],
+        /// Mute value
+        MUTEVAL OFFSET(6) NUMBITS(1) [],
+        /// Mute counter
+        MUTECNT OFFSET(7) NUMBITS(6) [],
+        /// Complement bit
+        CPL OFFSET(13) NUMBITS(1) [],
+        /// Companding mode
+        COMP OFFSET(14) NUMBITS(2) []
+    ],
+    FRCR [
+        /// Frame length, minus one
+        FRL OFFSET(0) NUMBITS(8) [],
+        /// Frame synchronization active level length, minus one
+        FSALL OFFSET(8) NUMBITS(7) [],
+        /// Frame synchronization definition
+        FSDEF OFFSET(16) NUMBITS(1) [],
+        /// Frame synchronization polarity
+        FSPOL OFFSET(17) NUMBITS(1) [],
+        /// Frame synchronization offset
+        FSOFF OFFSET(18) NUMBITS(1) []
+    ],
+    SLOTR [
+        /// First bit offset
+        FBOFF OFFSET(0) NUMBITS(5) [],
+        /// Slot size
+        SLOTSZ OFFSET(6) NUMBITS(2) [
+            DataSize = 0,
+            Bits16 = 1,
+            Bits32 = 2
+        ],
+        /// Number of slots in an audio frame, minus one
+        NBSLOT OFFSET(8) NUMBITS(4) [],
+        /// Slot enable
+        SLOTEN OFFSET(16) NUMBITS(16) []
+    ],
+    IMR [
+        /// Overrun/underrun interrupt enable
+        OVRUDRIE OFFSET(0) NUMBITS(1) [],
+        /// Mute detection interrupt enable
+        MUTEDETIE OFFSET(1) NUMBITS(1) [],
+        /// Wrong clock configuration interrupt enable
+        WCKCFGIE OFFSET(2) NUMBITS(1) [],
+        /// FIFO request interrupt enable
+        FREQIE OFFSET(3) NUMBITS(1) [],
+        /// Codec not ready interrupt enable
+        CNRDYIE OFFSET(4) NUMBITS(1) [],
+        /// Anticipated frame synchronization detection interrupt enable
+        AFSDETIE OFFSET(5) NUMBITS(1) [],
+        /// Late frame synchronization detection interrupt enable
+        LFSDETIE OFFSET(6) NUMBITS(1) []
+    ],
+    SR [
+        /// Overrun / underrun
+        OVRUDR OFFSET(0) NUMBITS(1) [],
+        /// Mute detection
+        MUTEDET OFFSET(1) NUMBITS(1) [],
+        /// Wrong clock configuration
+        WCKCFG OFFSET(2) NUMBITS(1) [],
+        /// FIFO request
+        FREQ OFFSET(3) NUMBITS(1) [],
+        /// Codec not ready
+        CNRDY OFFSET(4) NUMBITS(1) [],
+        /// Anticipated frame synchronization detection
+        AFSDET OFFSET(5) NUMBITS(1) [],
+        /// Late frame synchronization detection
+        LFSDET OFFSET(6) NUMBITS(1) [],
+        /// FIFO level
+        FLVL OFFSET(16) NUMBITS(3) [
+            Empty = 0
+        ]
+    ]
+];
+
+/// Width of a slot, in bit clock periods.
+const SLOT_BITS: usize = 32;
+
+pub struct Sai<'a> {
+    registers: StaticRef<SaiRegisters>,
+    clock: SaiClock<'a>,
+    client: OptionalCell<&'a dyn AudioClient>,
+    sample_rate: Cell<u32>,
+    slots: Cell<usize>,
+    running: Cell<bool>,
+    /// Buffer being filled.
+    buffer: TakeCell<'static, [i16]>,
+    /// Number of samples already stored in `buffer`.
+    position: Cell<usize>,
+    /// Buffer to fill once `buffer` is full.
+    next_buffer: TakeCell<'static, [i16]>,
+    /// Slot of the next sample read from the FIFO.
+    slot: Cell<usize>,
+}
+
+impl<'a> Sai<'a> {
+    pub fn new(registers: StaticRef<SaiRegisters>, clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self {
+            registers,
+            clock: SaiClock(phclk::PeripheralClock::new(
+                phclk::PeripheralClockType::APB2(phclk::PCLK2::SAI1),
+                clocks,
+            )),
+            client: OptionalCell::empty(),
+            sample_rate: Cell::new(16_000),
+            slots: Cell::new(2),
+            running: Cell::new(false),
+            buffer: TakeCell::empty(),
+            position: Cell::new(0),
+            next_buffer: TakeCell::empty(),
+            slot: Cell::new(0),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Set the frame rate used by the next capture, in Hz.
+    pub fn set_sample_rate(&self, sample_rate: u32) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.sample_rate.set(sample_rate);
+        Ok(())
+    }
+
+    fn configure(&self) -> Result<(), ErrorCode> {
+        let slots = self.slots.get();
+        let bit_clock = self.sample_rate.get() as usize * slots * SLOT_BITS;
+        self.clock.0.configure_sai1_clock(bit_clock)?;
+
+        let block = &self.registers.a;
+        block.cr1.modify(CR1::SAIEN::CLEAR);
+        while block.cr1.is_set(CR1::SAIEN) {}
+
+        // With NODIV set and MCKDIV at 0, the bit clock is the SAI kernel
+        // clock.
+        block.cr1.write(
+            CR1::MODE::MasterReceiver
+                + CR1::PRTCFG::Free
+                + CR1::DS::Bits16
+                + CR1::CKSTR::SET
+                + CR1::NODIV::SET
+                + CR1::MCKDIV.val(0),
+        );
+        block.cr2.write(CR2::FTH::QuarterFull + CR2::FFLUSH::SET);
+        block.frcr.write(
+            FRCR::FRL.val((slots * SLOT_BITS - 1) as u32)
+                + FRCR::FSALL.val(0)
+                + FRCR::FSPOL::SET
+                + FRCR::FSOFF::SET,
+        );
+        block.slotr.write(
+            SLOTR::SLOTSZ::Bits32
+                + SLOTR::NBSLOT.val(slots as u32 - 1)
+                + SLOTR::SLOTEN.val((1 << slots) - 1),
+        );
+        Ok(())
+    }
+
+    fn enable(&self) {
+        let block = &self.registers.a;
+        block.clrfr.write(
+            SR::OVRUDR::SET
+                + SR::MUTEDET::SET
+                + SR::WCKCFG::SET
+                + SR::CNRDY::SET
+                + SR::AFSDET::SET
+                + SR::LFSDET::SET,
+        );
+        self.slot.set(0);
+        block.imr.write(IMR::FREQIE::SET + IMR::OVRUDRIE::SET);
+        block.cr1.modify(CR1::SAIEN::SET);
+    }
+
+    fn disable(&self) {
+        let block = &self.registers.a;
+        block.imr.set(0);
+        block.cr1.modify(CR1::SAIEN::CLEAR);
+        while block.cr1.is_set(CR1::SAIEN) {}
+        block.cr2.modify(CR2::FFLUSH::SET);
+    }
+
+    /// Usable length of a buffer: a whole number of frames.
+    fn frames_length(&self, buffer: &[i16]) -> usize {
+        buffer.len() - buffer.len() % self.slots.get()
+    }
+
+    fn store_sample(&self, sample: i16) {
+        let slot = self.slot.get();
+        self.slot.set((slot + 1) % self.slots.get());
+
+        // Only start filling a buffer at the beginning of a frame.
+        if self.position.get() == 0 && slot != 0 {
+            return;
+        }
+
+        let full = self.buffer.map_or(false, |buffer| {
+            let position = self.position.get();
+            buffer[position] = sample;
+            self.position.set(position + 1);
+            position + 1 >= self.frames_length(buffer)
+        });
+
+        if full {
+            let length = self.position.get();
+            self.position.set(0);
+            if let Some(buffer) = self.buffer.take() {
+                if let Some(next) = self.next_buffer.take() {
+                    self.buffer.replace(next);
+                }
+                self.client
+                    .map(|client| client.samples_ready(buffer, length));
+            }
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let block = &self.registers.a;
+
+        if block.sr.is_set(SR::OVRUDR) {
+            // Samples were lost, so we cannot tell which slot the next
+            // sample belongs to. Restart the block to realign on a frame and
+            // drop the incomplete frame of the current buffer.
+            self.disable();
+            self.position
+                .set(self.position.get() - self.position.get() % self.slots.get());
+            if self.running.get() {
+                self.enable();
+            }
+            return;
+        }
+
+        while block.sr.read(SR::FLVL) != SR::FLVL::Empty.value {
+            let sample = block.dr.get() as u16 as i16;
+            if !self.running.get() {
+                break;
+            }
+            self.store_sample(sample);
+        }
+    }
+}
+
+impl<'a> AudioSource<'a> for Sai<'a> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate.get()
+    }
+
+    fn start(
+        &self,
+        buffer1: &'static mut [i16],
+        buffer2: &'static mut [i16],
+    ) -> Result<(), (ErrorCode, &'static mut [i16], &'static mut [i16])> {
+        if self.running.get() {
+            return Err((ErrorCode::BUSY, buffer1, buffer2));
+        }
+        if self.frames_length(buffer1) == 0 || self.frames_length(buffer2) == 0 {
+            return Err((ErrorCode::SIZE, buffer1, buffer2));
+        }
+        if !self.is_enabled_clock() {
+            self.enable_clock();
+        }
+        if let Err(e) = self.configure() {
+            return Err((e, buffer1, buffer2));
+        }
+
+        self.buffer.replace(buffer1);
+        self.next_buffer.replace(buffer2);
+        self.position.set(0);
+        self.running.set(true);
+        self.enable();
+        Ok(())
+    }
+
+    fn provide_buffer(
+        &self,
+        buffer: &'static mut [i16],
+    ) -> Result<(), (ErrorCode, &'static mut [i16])> {
+        if self.frames_length(buffer) == 0 {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if self.buffer.is_none() {
+            self.position.set(0);
+            self.buffer.replace(buffer);
+            Ok(())
+        } else if self.next_buffer.is_none() {
+            self.next_buffer.replace(buffer);
+            Ok(())
+        } else {
+            Err((ErrorCode::NOMEM, buffer))
+        }
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if !self.running.get() {
+            return Err(ErrorCode::OFF);
+        }
+        self.running.set(false);
+        self.disable();
+        Ok(())
+    }
+
+    fn retrieve_buffers(
+        &self,
+    ) -> Result<(Option<&'static mut [i16]>, Option<&'static mut [i16]>), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        Ok((self.buffer.take(), self.next_buffer.take()))
+    }
+
+    fn set_client(&self, client: &'a dyn AudioClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a> TdmAudioSource<'a> for Sai<'a> {
+    fn slots(&self) -> usize {
+        self.slots.get()
+    }
+
+    fn set_slots(&self, slots: usize) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if slots == 0 || slots > audio::MAX_TDM_SLOTS {
+            return Err(ErrorCode::INVAL);
+        }
+        self.slots.set(slots);
+        Ok(())
+    }
+}
+
+struct SaiClock<'a>(phclk::PeripheralClock<'a>);
+
+impl ClockInterface for SaiClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
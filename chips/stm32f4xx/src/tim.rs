@@ -0,0 +1,675 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! General-purpose timers TIM2-TIM5 and TIM9-TIM14.
+//!
+//! Each capture/compare channel of a timer is used independently, as a PWM
+//! output, an input capture or a one-pulse output, so that a single timer can
+//! serve several capsules through its [`TimChannel`]s. As the channels share
+//! the counter of the timer:
+//!
+//! - PWM outputs share the period of the timer: a channel can only start at
+//!   a different frequency when no other channel of the timer is running.
+//! - Input captures share a free-running 1 MHz counter, and cannot run
+//!   alongside PWM outputs.
+//! - A one-pulse output needs the whole timer.
+//!
+//! Operations that conflict with the channels already running return `BUSY`.
+//! The timer clock is only enabled while at least one channel is running.
+//!
+//! TIM2 and TIM5 have 32-bit counters, the other timers 16-bit counters.
+//! TIM2 is usually the kernel alarm (see [`crate::tim2`]), and must not be
+//! instantiated twice.
+//!
+//! The pins of the channels must be configured in the matching alternate
+//! function by the board.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::hil::input_capture::{self, Edge, InputCapture};
+use kernel::hil::pwm::{self, OnePulseClient};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clocks::{phclk, Stm32f4Clocks};
+
+/// General-purpose timer registers. Timers with fewer than four channels
+/// leave the registers of the missing channels reserved.
+#[repr(C)]
+struct TimRegisters {
+    /// control register 1
+    cr1: ReadWrite<u32, CR1::Register>,
+    /// control register 2
+    cr2: ReadWrite<u32>,
+    /// slave mode control register
+    smcr: ReadWrite<u32>,
+    /// DMA/Interrupt enable register
+    dier: ReadWrite<u32, DIER::Register>,
+    /// status register
+    sr: ReadWrite<u32>,
+    /// event generation register
+    egr: WriteOnly<u32, EGR::Register>,
+    /// capture/compare mode registers 1 and 2, one byte per channel
+    ccmr: [ReadWrite<u32>; 2],
+    /// capture/compare enable register, four bits per channel
+    ccer: ReadWrite<u32>,
+    /// counter
+    cnt: ReadWrite<u32>,
+    /// prescaler
+    psc: ReadWrite<u32>,
+    /// auto-reload register
+    arr: ReadWrite<u32>,
+    _reserved0: [u8; 4],
+    /// capture/compare registers 1 to 4
+    ccr: [ReadWrite<u32>; 4],
+    _reserved1: [u8; 4],
+    /// DMA control register
+    dcr: ReadWrite<u32>,
+    /// DMA address for full transfer
+    dmar: ReadWrite<u32>,
+    /// option register
+    or_: ReadWrite<u32>,
+}
+
+register_bitfields![u32,
+    CR1 [
+        /// Auto-reload preload enable
+        ARPE OFFSET(7) NUMBITS(1) [],
+        /// One-pulse mode
+        OPM OFFSET(3) NUMBITS(1) [],
+        /// Update request source
+        URS OFFSET(2) NUMBITS(1) [],
+        /// Counter enable
+        CEN OFFSET(0) NUMBITS(1) []
+    ],
+    DIER [
+        /// Capture/Compare 4 interrupt enable
+        CC4IE OFFSET(4) NUMBITS(1) [],
+        /// Capture/Compare 3 interrupt enable
+        CC3IE OFFSET(3) NUMBITS(1) [],
+        /// Capture/Compare 2 interrupt enable
+        CC2IE OFFSET(2) NUMBITS(1) [],
+        /// Capture/Compare 1 interrupt enable
+        CC1IE OFFSET(1) NUMBITS(1) [],
+        /// Update interrupt enable
+        UIE OFFSET(0) NUMBITS(1) []
+    ],
+    EGR [
+        /// Update generation
+        UG OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+// Per-channel bits, shifted by the channel index.
+
+/// SR: update interrupt flag
+const SR_UIF: u32 = 1 << 0;
+/// SR and DIER: capture/compare 1 interrupt flag and enable
+const CC1_IRQ: u32 = 1 << 1;
+/// SR: capture/compare 1 overcapture flag
+const SR_CC1OF: u32 = 1 << 9;
+
+/// CCMR: capture/compare selection, channel as output
+const CCMR_OUTPUT: u32 = 0b00;
+/// CCMR: capture/compare selection, channel as input mapped on its own pin
+const CCMR_INPUT: u32 = 0b01;
+/// CCMR: output compare preload enable
+const CCMR_OCPE: u32 = 1 << 3;
+/// CCMR: output compare PWM mode 1, active while the counter is below the
+/// compare value
+const CCMR_PWM1: u32 = 0b110 << 4;
+/// CCMR: output compare PWM mode 2, active once the counter reaches the
+/// compare value
+const CCMR_PWM2: u32 = 0b111 << 4;
+
+/// CCER: capture/compare output enable
+const CCER_CCE: u32 = 1 << 0;
+/// CCER: capture/compare polarity
+const CCER_CCP: u32 = 1 << 1;
+/// CCER: capture/compare complementary polarity
+const CCER_CCNP: u32 = 1 << 3;
+
+/// Frequency of the counter for input capture and one-pulse outputs.
+const TICK_FREQUENCY: u32 = 1_000_000;
+
+/// Value of a 100% PWM duty cycle.
+const MAX_DUTY_CYCLE: usize = u16::MAX as usize + 1;
+
+const TIM2_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_0000 as *const TimRegisters) };
+const TIM3_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_0400 as *const TimRegisters) };
+const TIM4_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_0800 as *const TimRegisters) };
+const TIM5_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_0C00 as *const TimRegisters) };
+const TIM12_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_1800 as *const TimRegisters) };
+const TIM13_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_1C00 as *const TimRegisters) };
+const TIM14_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4000_2000 as *const TimRegisters) };
+const TIM9_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4001_4000 as *const TimRegisters) };
+const TIM10_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4001_4400 as *const TimRegisters) };
+const TIM11_BASE: StaticRef<TimRegisters> =
+    unsafe { StaticRef::new(0x4001_4800 as *const TimRegisters) };
+
+/// Capture/compare channel of a timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Channel1 = 0,
+    Channel2 = 1,
+    Channel3 = 2,
+    Channel4 = 3,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Idle,
+    Pwm { frequency_hz: usize },
+    Capture,
+    OnePulse,
+}
+
+/// Prescaler and auto-reload values for a counter of period `ticks` clock
+/// cycles, or `None` if the period is too long.
+fn prescaler_for_period(ticks: u64, counter_max: u32) -> Option<(u32, u32)> {
+    let prescaler = (ticks.max(1) - 1) / (counter_max as u64 + 1);
+    if prescaler > u16::MAX as u64 {
+        return None;
+    }
+    let reload = ticks / (prescaler + 1) - 1;
+    Some((prescaler as u32, reload as u32))
+}
+
+pub struct Tim<'a> {
+    registers: StaticRef<TimRegisters>,
+    clock: TimClock<'a>,
+    /// Number of capture/compare channels.
+    channels: usize,
+    /// Whether the counter is 32 bits wide.
+    wide: bool,
+    mode: Cell<Mode>,
+    /// Channels running, one bit per channel.
+    active: Cell<u8>,
+    capture_clients: [OptionalCell<&'a dyn input_capture::Client>; 4],
+    pulse_clients: [OptionalCell<&'a dyn OnePulseClient>; 4],
+}
+
+impl<'a> Tim<'a> {
+    const fn new(
+        registers: StaticRef<TimRegisters>,
+        clock: phclk::PeripheralClockType,
+        clocks: &'a dyn Stm32f4Clocks,
+        channels: usize,
+        wide: bool,
+    ) -> Self {
+        Self {
+            registers,
+            clock: TimClock(phclk::PeripheralClock::new(clock, clocks)),
+            channels,
+            wide,
+            mode: Cell::new(Mode::Idle),
+            active: Cell::new(0),
+            capture_clients: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
+            pulse_clients: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
+        }
+    }
+
+    pub const fn new_tim2(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM2_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM2),
+            clocks,
+            4,
+            true,
+        )
+    }
+
+    pub const fn new_tim3(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM3_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM3),
+            clocks,
+            4,
+            false,
+        )
+    }
+
+    pub const fn new_tim4(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM4_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM4),
+            clocks,
+            4,
+            false,
+        )
+    }
+
+    pub const fn new_tim5(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM5_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM5),
+            clocks,
+            4,
+            true,
+        )
+    }
+
+    pub const fn new_tim9(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM9_BASE,
+            phclk::PeripheralClockType::APB2(phclk::PCLK2::TIM9),
+            clocks,
+            2,
+            false,
+        )
+    }
+
+    pub const fn new_tim10(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM10_BASE,
+            phclk::PeripheralClockType::APB2(phclk::PCLK2::TIM10),
+            clocks,
+            1,
+            false,
+        )
+    }
+
+    pub const fn new_tim11(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM11_BASE,
+            phclk::PeripheralClockType::APB2(phclk::PCLK2::TIM11),
+            clocks,
+            1,
+            false,
+        )
+    }
+
+    pub const fn new_tim12(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM12_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM12),
+            clocks,
+            2,
+            false,
+        )
+    }
+
+    pub const fn new_tim13(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM13_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM13),
+            clocks,
+            1,
+            false,
+        )
+    }
+
+    pub const fn new_tim14(clocks: &'a dyn Stm32f4Clocks) -> Self {
+        Self::new(
+            TIM14_BASE,
+            phclk::PeripheralClockType::APB1(phclk::PCLK1::TIM14),
+            clocks,
+            1,
+            false,
+        )
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Number of capture/compare channels of the timer.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn counter_max(&self) -> u32 {
+        if self.wide {
+            u32::MAX
+        } else {
+            u16::MAX as u32
+        }
+    }
+
+    fn clock_frequency(&self) -> u32 {
+        self.clock.0.get_frequency()
+    }
+
+    /// Prescaler value for a counter running at `TICK_FREQUENCY`.
+    fn tick_prescaler(&self) -> u32 {
+        (self.clock_frequency() / TICK_FREQUENCY).max(1) - 1
+    }
+
+    fn index(&self, channel: Channel) -> Result<usize, ErrorCode> {
+        let index = channel as usize;
+        if index < self.channels {
+            Ok(index)
+        } else {
+            Err(ErrorCode::INVAL)
+        }
+    }
+
+    fn set_ccmr(&self, index: usize, value: u32) {
+        let register = &self.registers.ccmr[index / 2];
+        let shift = (index % 2) * 8;
+        register.set(register.get() & !(0xFF << shift) | value << shift);
+    }
+
+    fn set_ccer(&self, index: usize, value: u32) {
+        let shift = index * 4;
+        self.registers
+            .ccer
+            .set(self.registers.ccer.get() & !(0xF << shift) | value << shift);
+    }
+
+    /// Load new prescaler and auto-reload values and start the counter.
+    fn start_counter(&self, prescaler: u32, reload: u32) {
+        self.registers.psc.set(prescaler);
+        self.registers.arr.set(reload);
+        // Load the prescaler now. With URS set, this does not raise an update
+        // interrupt.
+        self.registers.egr.write(EGR::UG::SET);
+        self.registers.cr1.modify(CR1::CEN::SET);
+    }
+
+    /// Mark the channel at `index` as running in `mode`, and configure the
+    /// counter if it was not used by other channels.
+    fn claim(&self, index: usize, mode: Mode) -> Result<(), ErrorCode> {
+        let others = self.active.get() & !(1 << index);
+        if others != 0 {
+            if self.mode.get() != mode {
+                return Err(ErrorCode::BUSY);
+            }
+        } else {
+            let (prescaler, reload) = match mode {
+                Mode::Pwm { frequency_hz } => {
+                    if frequency_hz == 0 || frequency_hz > self.clock_frequency() as usize {
+                        return Err(ErrorCode::INVAL);
+                    }
+                    let ticks = self.clock_frequency() as u64 / frequency_hz as u64;
+                    prescaler_for_period(ticks, self.counter_max()).ok_or(ErrorCode::INVAL)?
+                }
+                Mode::Capture => (self.tick_prescaler(), self.counter_max()),
+                Mode::Idle | Mode::OnePulse => return Err(ErrorCode::INVAL),
+            };
+            self.clock.enable();
+            self.registers.cr1.write(CR1::URS::SET + CR1::ARPE::SET);
+            self.start_counter(prescaler, reload);
+            self.mode.set(mode);
+        }
+        self.active.set(self.active.get() | 1 << index);
+        Ok(())
+    }
+
+    /// Stop the channel at `index`, and the timer if no other channel runs.
+    fn release(&self, index: usize) {
+        self.set_ccer(index, 0);
+        self.set_ccmr(index, 0);
+        self.registers
+            .dier
+            .set(self.registers.dier.get() & !(CC1_IRQ << index));
+        self.active.set(self.active.get() & !(1 << index));
+        if self.active.get() == 0 {
+            self.registers.cr1.set(0);
+            self.registers.dier.set(0);
+            self.mode.set(Mode::Idle);
+            self.clock.disable();
+        }
+    }
+
+    fn stop(&self, channel: Channel) -> Result<(), ErrorCode> {
+        let index = self.index(channel)?;
+        if self.active.get() & 1 << index != 0 {
+            self.release(index);
+        }
+        Ok(())
+    }
+
+    fn start_pwm(
+        &self,
+        channel: Channel,
+        frequency_hz: usize,
+        duty_cycle: usize,
+    ) -> Result<(), ErrorCode> {
+        let index = self.index(channel)?;
+        if duty_cycle > MAX_DUTY_CYCLE {
+            return Err(ErrorCode::INVAL);
+        }
+        self.claim(index, Mode::Pwm { frequency_hz })?;
+
+        let period = self.registers.arr.get() as u64 + 1;
+        let compare = period * duty_cycle as u64 / MAX_DUTY_CYCLE as u64;
+        self.registers.ccr[index].set(compare as u32);
+        self.set_ccmr(index, CCMR_OUTPUT | CCMR_PWM1 | CCMR_OCPE);
+        self.set_ccer(index, CCER_CCE);
+        Ok(())
+    }
+
+    fn start_capture(&self, channel: Channel, edge: Edge) -> Result<(), ErrorCode> {
+        let index = self.index(channel)?;
+        self.claim(index, Mode::Capture)?;
+
+        // The channel must be disabled while its direction changes.
+        self.set_ccer(index, 0);
+        self.set_ccmr(index, CCMR_INPUT);
+        let polarity = match edge {
+            Edge::Rising => 0,
+            Edge::Falling => CCER_CCP,
+            Edge::Both => CCER_CCP | CCER_CCNP,
+        };
+        self.set_ccer(index, CCER_CCE | polarity);
+        self.registers.sr.set(!((CC1_IRQ | SR_CC1OF) << index));
+        self.registers
+            .dier
+            .set(self.registers.dier.get() | CC1_IRQ << index);
+        Ok(())
+    }
+
+    fn pulse(&self, channel: Channel, delay_us: u32, width_us: u32) -> Result<(), ErrorCode> {
+        let index = self.index(channel)?;
+        if self.active.get() != 0 {
+            return Err(ErrorCode::BUSY);
+        }
+        // With a compare value of 0 the output would stay high once the
+        // counter stops.
+        let delay = cmp::max(delay_us, 1);
+        let reload = match width_us.checked_sub(1).and_then(|w| delay.checked_add(w)) {
+            Some(reload) if reload <= self.counter_max() => reload,
+            _ => return Err(ErrorCode::INVAL),
+        };
+
+        self.clock.enable();
+        self.registers.cr1.write(CR1::URS::SET + CR1::OPM::SET);
+        self.registers.ccr[index].set(delay);
+        self.set_ccmr(index, CCMR_OUTPUT | CCMR_PWM2);
+        self.set_ccer(index, CCER_CCE);
+        self.registers.sr.set(!SR_UIF);
+        self.registers.dier.write(DIER::UIE::SET);
+        self.mode.set(Mode::OnePulse);
+        self.active.set(1 << index);
+        // The counter stops by itself at the end of the pulse.
+        self.start_counter(self.tick_prescaler(), reload);
+        Ok(())
+    }
+
+    fn cancel_pulse(&self, channel: Channel) -> Result<(), ErrorCode> {
+        let index = self.index(channel)?;
+        if self.mode.get() == Mode::OnePulse && self.active.get() & 1 << index != 0 {
+            self.release(index);
+        }
+        Ok(())
+    }
+
+    pub fn handle_interrupt(&self) {
+        let status = self.registers.sr.get();
+        match self.mode.get() {
+            Mode::Capture => {
+                for index in 0..self.channels {
+                    if self.active.get() & 1 << index == 0 || status & CC1_IRQ << index == 0 {
+                        continue;
+                    }
+                    // Reading the captured value clears the interrupt flag.
+                    let value = self.registers.ccr[index].get();
+                    let overcapture = status & SR_CC1OF << index != 0;
+                    self.registers.sr.set(!(SR_CC1OF << index));
+                    self.capture_clients[index].map(|client| client.captured(value, overcapture));
+                }
+            }
+            Mode::OnePulse => {
+                if status & SR_UIF != 0 {
+                    self.registers.sr.set(!SR_UIF);
+                    let index = self.active.get().trailing_zeros() as usize;
+                    self.release(index);
+                    self.pulse_clients[index].map(|client| client.pulse_done());
+                }
+            }
+            Mode::Idle | Mode::Pwm { .. } => {
+                self.registers.sr.set(0);
+            }
+        }
+    }
+}
+
+impl pwm::Pwm for Tim<'_> {
+    type Pin = Channel;
+
+    /// Start a PWM output on a channel. Returns `BUSY` if other channels of
+    /// the timer run at a different frequency or in another mode.
+    fn start(
+        &self,
+        pin: &Channel,
+        frequency_hz: usize,
+        duty_cycle: usize,
+    ) -> Result<(), ErrorCode> {
+        self.start_pwm(*pin, frequency_hz, duty_cycle)
+    }
+
+    fn stop(&self, pin: &Channel) -> Result<(), ErrorCode> {
+        Tim::stop(self, *pin)
+    }
+
+    fn get_maximum_frequency_hz(&self) -> usize {
+        self.clock_frequency() as usize
+    }
+
+    fn get_maximum_duty_cycle(&self) -> usize {
+        MAX_DUTY_CYCLE
+    }
+}
+
+/// A capture/compare channel of a timer, used as a PWM output, an input
+/// capture or a one-pulse output.
+pub struct TimChannel<'a> {
+    tim: &'a Tim<'a>,
+    channel: Channel,
+}
+
+impl<'a> TimChannel<'a> {
+    pub const fn new(tim: &'a Tim<'a>, channel: Channel) -> Self {
+        Self { tim, channel }
+    }
+}
+
+impl pwm::PwmPin for TimChannel<'_> {
+    fn start(&self, frequency_hz: usize, duty_cycle: usize) -> Result<(), ErrorCode> {
+        self.tim.start_pwm(self.channel, frequency_hz, duty_cycle)
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        self.tim.stop(self.channel)
+    }
+
+    fn get_maximum_frequency_hz(&self) -> usize {
+        pwm::Pwm::get_maximum_frequency_hz(self.tim)
+    }
+
+    fn get_maximum_duty_cycle(&self) -> usize {
+        MAX_DUTY_CYCLE
+    }
+}
+
+impl<'a> InputCapture<'a> for TimChannel<'a> {
+    fn start(&self, edge: Edge) -> Result<(), ErrorCode> {
+        self.tim.start_capture(self.channel, edge)
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        self.tim.stop(self.channel)
+    }
+
+    fn frequency_hz(&self) -> u32 {
+        self.tim.clock_frequency() / (self.tim.tick_prescaler() + 1)
+    }
+
+    fn width(&self) -> u32 {
+        if self.tim.wide {
+            32
+        } else {
+            16
+        }
+    }
+
+    fn set_client(&self, client: &'a dyn input_capture::Client) {
+        self.tim.capture_clients[self.channel as usize].set(client);
+    }
+}
+
+impl<'a> pwm::OnePulse<'a> for TimChannel<'a> {
+    /// The delay and width are rounded to microseconds, with a delay of at
+    /// least 1 µs.
+    fn pulse(&self, delay_us: u32, width_us: u32) -> Result<(), ErrorCode> {
+        self.tim.pulse(self.channel, delay_us, width_us)
+    }
+
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        self.tim.cancel_pulse(self.channel)
+    }
+
+    fn set_client(&self, client: &'a dyn OnePulseClient) {
+        self.tim.pulse_clients[self.channel as usize].set(client);
+    }
+}
+
+struct TimClock<'a>(phclk::PeripheralClock<'a>);
+
+impl ClockInterface for TimClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
@@ -158,6 +158,13 @@ pub const USART2_BASE: StaticRef<UsartRegisters> =
 pub const USART3_BASE: StaticRef<UsartRegisters> =
     unsafe { StaticRef::new(0x40004800 as *const UsartRegisters) };
 
+/// Data bits of a word, including the ninth bit in 9-bit mode.
+const WORD_MASK: u32 = 0x1FF;
+
+/// Largest prescaler of the smart card clock, which divides the peripheral
+/// clock by twice its value.
+const MAX_SMARTCARD_PRESCALER: u32 = 31;
+
 // for use by dma1
 pub(crate) fn get_address_dr(regs: StaticRef<UsartRegisters>) -> u32 {
     core::ptr::addr_of!(regs.dr) as u32
@@ -168,6 +175,7 @@ pub(crate) fn get_address_dr(regs: StaticRef<UsartRegisters>) -> u32 {
 enum USARTStateRX {
     Idle,
     DMA_Receiving,
+    Word_Receiving,
     Aborted(Result<(), ErrorCode>, hil::uart::Error),
 }
 
@@ -176,6 +184,7 @@ enum USARTStateRX {
 enum USARTStateTX {
     Idle,
     DMA_Transmitting,
+    Word_Transmitting,
     Aborted(Result<(), ErrorCode>),
     Transfer_Completing, // DMA finished, but not all bytes sent
 }
@@ -204,6 +213,9 @@ pub struct Usart<'a, DMA: dma::StreamServer<'a>> {
     partial_rx_buffer: TakeCell<'static, [u8]>,
     partial_rx_len: Cell<usize>,
 
+    /// The USART is in smart card mode.
+    smartcard: Cell<bool>,
+
     deferred_call: DeferredCall,
 }
 
@@ -282,6 +294,8 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
             partial_rx_buffer: TakeCell::empty(),
             partial_rx_len: Cell::new(0),
 
+            smartcard: Cell::new(false),
+
             deferred_call: DeferredCall::new(),
         }
     }
@@ -309,6 +323,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
         if self.registers.sr.is_set(SR::TC) {
             self.clear_transmit_complete();
             self.disable_transmit_complete_interrupt();
+            self.release_line();
 
             // Ignore if USARTStateTX is in some other state other than
             // Transfer_Completing.
@@ -327,9 +342,30 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
                         client.transmitted_buffer(buf, len, Ok(()));
                     });
                 });
+            } else if self.usart_tx_state.get() == USARTStateTX::Word_Transmitting {
+                self.usart_tx_state.set(USARTStateTX::Idle);
+                self.tx_client.map(|client| client.transmitted_word(Ok(())));
             }
         }
 
+        if self.usart_rx_state.get() == USARTStateRX::Word_Receiving
+            && self.registers.sr.is_set(SR::RXNE)
+            && self.registers.sr.is_set(SR::PE)
+        {
+            // Only enabled in smart card mode, where the receiver requested
+            // the repetition of the word: drop it and wait for the next one.
+            let _ = self.registers.dr.get();
+        } else if self.usart_rx_state.get() == USARTStateRX::Word_Receiving
+            && self.registers.sr.is_set(SR::RXNE)
+        {
+            // Reading the data register clears RXNE and any overrun error.
+            let word = self.registers.dr.get() & WORD_MASK;
+            self.registers.cr1.modify(CR1::RXNEIE::CLEAR);
+            self.usart_rx_state.set(USARTStateRX::Idle);
+            self.rx_client
+                .map(|client| client.received_word(word, Ok(()), hil::uart::Error::None));
+        }
+
         if self.is_enabled_error_interrupt() && self.registers.sr.is_set(SR::ORE) {
             let _ = self.registers.dr.get(); // clear overrun error
             if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving {
@@ -372,6 +408,20 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
         self.registers.dr.set(byte.into());
     }
 
+    // In smart card mode the I/O line is shared, so the receiver is disabled
+    // while transmitting, not to receive the words sent.
+    fn take_line(&self) {
+        if self.smartcard.get() {
+            self.registers.cr1.modify(CR1::RE::CLEAR);
+        }
+    }
+
+    fn release_line(&self) {
+        if self.smartcard.get() {
+            self.registers.cr1.modify(CR1::RE::SET);
+        }
+    }
+
     // enable DMA TX from the peripheral side
     fn enable_tx(&self) {
         self.registers.cr3.modify(CR3::DMAT::SET);
@@ -409,6 +459,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
 
     fn abort_tx(&self, rcode: Result<(), ErrorCode>) {
         self.disable_tx();
+        self.release_line();
 
         // get buffer
         let (mut buffer, len) = self.tx_dma.map_or((None, 0), |tx_dma| {
@@ -614,16 +665,30 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Transmit<'a> for Usart<'a, DMA>
         self.usart_tx_state.set(USARTStateTX::DMA_Transmitting);
 
         // enable dma tx on peripheral side
+        self.take_line();
         self.enable_tx();
         Ok(())
     }
 
-    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
-        Err(ErrorCode::FAIL)
+    fn transmit_word(&self, word: u32) -> Result<(), ErrorCode> {
+        if self.usart_tx_state.get() != USARTStateTX::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.usart_tx_state.set(USARTStateTX::Word_Transmitting);
+        self.take_line();
+        self.clear_transmit_complete();
+        self.registers.dr.set(word & WORD_MASK);
+        self.enable_transmit_complete_interrupt();
+        Ok(())
     }
 
     fn transmit_abort(&self) -> Result<(), ErrorCode> {
-        if self.usart_tx_state.get() != USARTStateTX::Idle {
+        if self.usart_tx_state.get() == USARTStateTX::Word_Transmitting {
+            // The word is already in the shift register, the callback will
+            // follow shortly.
+            Err(ErrorCode::FAIL)
+        } else if self.usart_tx_state.get() != USARTStateTX::Idle {
             self.abort_tx(Err(ErrorCode::CANCEL));
             Err(ErrorCode::BUSY)
         } else {
@@ -637,13 +702,20 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Configure for Usart<'a, DMA> {
         if params.stop_bits != hil::uart::StopBits::One
             || params.parity != hil::uart::Parity::None
             || params.hw_flow_control
-            || params.width != hil::uart::Width::Eight
+            || (params.width != hil::uart::Width::Eight && params.width != hil::uart::Width::Nine)
         {
-            panic!("Currently we only support uart setting of 8N1, no hardware flow control");
+            panic!(
+                "Currently we only support uart setting of 8N1 or 9N1, no hardware flow control"
+            );
         }
 
         // Configure the word length - 0: 1 Start bit, 8 Data bits, n Stop bits
-        self.registers.cr1.modify(CR1::M::CLEAR);
+        //                             1: 1 Start bit, 9 Data bits, n Stop bits
+        if params.width == hil::uart::Width::Nine {
+            self.registers.cr1.modify(CR1::M::SET);
+        } else {
+            self.registers.cr1.modify(CR1::M::CLEAR);
+        }
 
         // Set the stop bit length - 00: 1 Stop bits
         self.registers.cr2.modify(CR2::STOP.val(0b00_u32));
@@ -651,6 +723,13 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Configure for Usart<'a, DMA> {
         // Set no parity
         self.registers.cr1.modify(CR1::PCE::CLEAR);
 
+        // Leave the smart card mode
+        self.registers
+            .cr3
+            .modify(CR3::SCEN::CLEAR + CR3::NACK::CLEAR);
+        self.registers.cr2.modify(CR2::CLKEN::CLEAR);
+        self.smartcard.set(false);
+
         self.set_baud_rate(params.baud_rate)?;
 
         // Enable transmit block
@@ -666,6 +745,63 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Configure for Usart<'a, DMA> {
     }
 }
 
+impl<'a, DMA: dma::StreamServer<'a>> hil::uart::SmartCard for Usart<'a, DMA> {
+    fn configure_smartcard(
+        &self,
+        params: hil::uart::SmartCardParameters,
+    ) -> Result<u32, ErrorCode> {
+        if params.clock_hz == 0 || params.etu_clocks == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // The card clock is the peripheral clock divided by twice the
+        // prescaler, the closest frequency not above the requested one.
+        let pclk_freq = self.clock.0.get_frequency();
+        let prescaler = pclk_freq.div_ceil(2 * params.clock_hz).max(1);
+        if prescaler > MAX_SMARTCARD_PRESCALER {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let clock_hz = pclk_freq / (2 * prescaler);
+
+        // A bit lasts `etu_clocks` card clock cycles. With 16 times
+        // oversampling, BRR holds the number of peripheral clock cycles per
+        // bit.
+        let brr = params.etu_clocks * 2 * prescaler;
+        if brr > 0xFFFF {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
+        self.registers.cr1.modify(CR1::UE::CLEAR);
+
+        // 8 data bits with even parity, 1.5 stop bits
+        self.registers
+            .cr1
+            .modify(CR1::M::SET + CR1::PCE::SET + CR1::PS::CLEAR + CR1::OVER8::CLEAR);
+        self.registers.brr.set(brr);
+        self.registers.cr2.modify(
+            CR2::LINEN::CLEAR
+                + CR2::STOP.val(0b11)
+                + CR2::CLKEN::SET
+                + CR2::CPOL::CLEAR
+                + CR2::CPHA::CLEAR
+                + CR2::LBCL::CLEAR,
+        );
+        self.registers
+            .gtpr
+            .write(GTPR::GT.val(params.extra_guard_time.into()) + GTPR::PSC.val(prescaler));
+        self.registers
+            .cr3
+            .modify(CR3::SCEN::SET + CR3::NACK::SET + CR3::HDSEL::CLEAR + CR3::IREN::CLEAR);
+        self.smartcard.set(true);
+
+        self.registers
+            .cr1
+            .modify(CR1::TE::SET + CR1::RE::SET + CR1::UE::SET);
+
+        Ok(clock_hz)
+    }
+}
+
 impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Receive<'a> for Usart<'a, DMA> {
     fn set_receive_client(&self, client: &'a dyn hil::uart::ReceiveClient) {
         self.rx_client.set(client);
@@ -700,10 +836,21 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Receive<'a> for Usart<'a, DMA> {
     }
 
     fn receive_word(&self) -> Result<(), ErrorCode> {
-        Err(ErrorCode::FAIL)
+        if self.usart_rx_state.get() != USARTStateRX::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.usart_rx_state.set(USARTStateRX::Word_Receiving);
+        self.registers.cr1.modify(CR1::RXNEIE::SET);
+        Ok(())
     }
 
     fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.usart_rx_state.get() == USARTStateRX::Word_Receiving {
+            self.registers.cr1.modify(CR1::RXNEIE::CLEAR);
+            self.usart_rx_state.set(USARTStateRX::Idle);
+            return Ok(());
+        }
         self.abort_rx(Err(ErrorCode::CANCEL), hil::uart::Error::Aborted);
         Err(ErrorCode::BUSY)
     }
@@ -80,6 +80,58 @@ pub(crate) struct Config {
     // credentials checking, e.g., whether elf2tab and tockloader are generating
     // properly formatted footers.
     pub(crate) debug_process_credentials: bool,
+    /// How many of its most recent system calls the kernel records for each
+    /// process, along with their return values.
+    ///
+    /// The trace can be displayed from the process console and is included in
+    /// the process state printed when a process faults. A value of 0 disables
+    /// the trace.
+    pub(crate) syscall_trace_length: usize,
+    /// For how many drivers the kernel records the time each process spends
+    /// executing their upcalls.
+    ///
+    /// This tells which subscription makes a process exhaust its timeslices.
+    /// The records can be displayed from the process console. Drivers beyond
+    /// this number are not recorded. A value of 0 disables the accounting.
+    pub(crate) upcall_time_drivers: usize,
+    /// For how many drivers the kernel counts the commands, allows and
+    /// subscribes of all processes, and how many of them failed.
+    ///
+    /// This tells which driver the processes use heavily, or whether they
+    /// call driver numbers the board does not have. The counts can be
+    /// displayed from the process console. Drivers beyond this number are not
+    /// counted. A value of 0 disables the counting.
+    pub(crate) syscall_stats_drivers: usize,
+    /// Whether the kernel should print a report of its memory usage after
+    /// loading processes.
+    ///
+    /// The report lists the static memory allocated for each type of capsule
+    /// or buffer with `static_buf!()` and `static_init!()`, the memory of each
+    /// process with the part reserved for the kernel and grants, and the RAM
+    /// left for processes. This helps fitting a board into a tight RAM budget.
+    pub(crate) debug_memory_report: bool,
+    /// Whether the kernel should bracket grant allocations with canary words.
+    ///
+    /// If enabled, the canaries are checked every time a grant is entered,
+    /// and the kernel panics with the driver number of the grant if a capsule
+    /// wrote past its grant data. This uses two extra words per grant.
+    pub(crate) debug_grant_canaries: bool,
+    /// Whether panics in syscall drivers disable the driver instead of
+    /// halting the system.
+    ///
+    /// If enabled, the kernel records which driver it is calling for each
+    /// command, subscribe and allow. A board that calls
+    /// `debug::panic_contain()` in its panic handler then resets after a
+    /// panic in a driver, restarting every process, and the driver fails all
+    /// syscalls with `FAIL` until the next power cycle.
+    pub(crate) contain_capsule_panics: bool,
+    /// How many of the most recent interrupts and deferred calls the kernel
+    /// records, in the order they were delivered.
+    ///
+    /// The record is printed on panics, and a chip that supports it can
+    /// replay a recorded order against the capsules to reproduce bugs that
+    /// depend on the order of events. A value of 0 disables the recording.
+    pub(crate) event_record_length: usize,
 }
 
 /// A unique instance of `Config` where compile-time configuration options are
@@ -92,4 +144,27 @@ pub(crate) const CONFIG: Config = Config {
     debug_load_processes: cfg!(feature = "debug_load_processes"),
     debug_panics: !cfg!(feature = "no_debug_panics"),
     debug_process_credentials: cfg!(feature = "debug_process_credentials"),
+    syscall_trace_length: if cfg!(feature = "debug_syscall_trace") {
+        16
+    } else {
+        0
+    },
+    upcall_time_drivers: if cfg!(feature = "debug_upcall_time") {
+        8
+    } else {
+        0
+    },
+    syscall_stats_drivers: if cfg!(feature = "debug_syscall_stats") {
+        32
+    } else {
+        0
+    },
+    debug_grant_canaries: cfg!(feature = "debug_grant_canaries"),
+    debug_memory_report: cfg!(feature = "debug_memory_report"),
+    contain_capsule_panics: cfg!(feature = "contain_capsule_panics"),
+    event_record_length: if cfg!(feature = "debug_event_record") {
+        64
+    } else {
+        0
+    },
 };
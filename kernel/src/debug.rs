@@ -6,7 +6,9 @@
 //!
 //! For printing, this module uses an internal buffer to write the strings into.
 //! If you are writing and the buffer fills up, you can make the size of
-//! `output_buffer` larger.
+//! `output_buffer` larger. Output that does not fit is dropped as a whole and
+//! replaced by a single `[N bytes dropped]` marker once there is room again;
+//! the total number of dropped bytes is returned by `debug_dropped_bytes()`.
 //!
 //! Before debug interfaces can be used, the board file must assign them hardware:
 //!
@@ -52,14 +54,40 @@
 //! Yes the code gets here with value 42
 //! TOCK_DEBUG(0): /tock/capsules/src/sensys.rs:24: got here
 //! ```
+//!
+//! Leveled logging
+//! ---------------
+//!
+//! Drivers report errors and bring-up details with the `log_error!`,
+//! `log_warn!`, `log_info!` and `log_trace!` macros, which take the
+//! [`LogSubsystem`] the message belongs to. A message is only printed if the
+//! level of its subsystem, which can be changed at runtime (for example with
+//! the `log` command of the process console), is at least as verbose as the
+//! message. All subsystems start at [`DEFAULT_LOG_LEVEL`].
+//!
+//! ```no_run
+//! # use kernel::{log_trace, log_warn};
+//! # fn main() {
+//! # let channel = 26;
+//! log_warn!(Radio, "Received interrupt while off");
+//! log_trace!(Radio, "Switching to channel {}", channel);
+//! # }
+//! ```
+//!
+//! ```text
+//! [warn radio] Received interrupt while off
+//! ```
 
 use core::cell::Cell;
 use core::fmt::{write, Arguments, Result, Write};
 use core::panic::PanicInfo;
+use core::ptr::{addr_of, addr_of_mut};
 use core::str;
+use core::sync::atomic::{AtomicU8, Ordering};
 
 use crate::collections::queue::Queue;
 use crate::collections::ring_buffer::RingBuffer;
+use crate::config;
 use crate::hil;
 use crate::platform::chip::Chip;
 use crate::process::Process;
@@ -121,6 +149,7 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
     flush(writer);
     panic_banner(writer, panic_info);
     panic_cpu_state(chip, writer);
+    panic_recorded_events(writer);
 
     // Some systems may enforce memory protection regions for the
     // kernel, making application memory inaccessible. However,
@@ -247,9 +276,171 @@ pub fn panic_blink_forever<L: hil::led::Led>(leds: &mut [&L]) -> ! {
     }
 }
 
+/// Play `pattern` on alert outputs in an infinite loop, as
+/// `panic_blink_forever` does with LEDs. Durations are measured by calling
+/// `nop`, `nops_per_ms` times per millisecond, so they are approximate.
+pub fn panic_alert_forever(
+    outputs: &[&dyn hil::alert::AlertOutput],
+    pattern: &hil::alert::Pattern,
+    nop: &dyn Fn(),
+    nops_per_ms: usize,
+) -> ! {
+    outputs.iter().for_each(|output| output.init());
+    loop {
+        for (step, &duration_ms) in pattern.durations_ms.iter().enumerate() {
+            if step % 2 == 0 {
+                outputs.iter().for_each(|output| output.on(pattern.tone_hz));
+            } else {
+                outputs.iter().for_each(|output| output.off());
+            }
+            for _ in 0..duration_ms as usize * nops_per_ms {
+                nop();
+            }
+        }
+        outputs.iter().for_each(|output| output.off());
+    }
+}
+
 // panic! support routines
 ///////////////////////////////////////////////////////////////////
 
+///////////////////////////////////////////////////////////////////
+// capsule panic containment
+
+/// Largest number of syscall drivers disabled after they panicked.
+pub const MAX_PANICKED_DRIVERS: usize = 4;
+
+/// Marks a valid `PanicRecord`. RAM holds random values at power on.
+const PANIC_RECORD_MAGIC: usize = 0x5041_4e43;
+
+/// Syscall drivers that panicked since the last power on.
+#[repr(C)]
+struct PanicRecord {
+    magic: usize,
+    /// Number of valid entries in `drivers`.
+    count: usize,
+    drivers: [usize; MAX_PANICKED_DRIVERS],
+}
+
+/// The record is not cleared at boot, so it survives the reset that follows
+/// a contained panic.
+#[cfg_attr(target_os = "none", link_section = ".noinit")]
+static mut PANIC_RECORD: PanicRecord = PanicRecord {
+    magic: 0,
+    count: 0,
+    drivers: [0; MAX_PANICKED_DRIVERS],
+};
+
+/// Syscall driver whose command, subscribe or allow the kernel is running.
+static mut RUNNING_DRIVER: Option<usize> = None;
+
+/// Record the syscall driver that the kernel is calling, if capsule panic
+/// containment is enabled.
+pub(crate) fn set_running_driver(driver_num: Option<usize>) {
+    if config::CONFIG.contain_capsule_panics {
+        // Safety: only called from the kernel loop, and only read by the
+        // panic handler, which does not return.
+        unsafe {
+            *addr_of_mut!(RUNNING_DRIVER) = driver_num;
+        }
+    }
+}
+
+/// Return the drivers recorded as panicked.
+fn panicked_drivers() -> impl Iterator<Item = usize> {
+    // Safety: the record is only written by the panic handler and by
+    // `clear_panicked_drivers()`, never while this iterator is used.
+    let record = unsafe { core::ptr::read_volatile(addr_of!(PANIC_RECORD)) };
+    let valid = config::CONFIG.contain_capsule_panics && record.magic == PANIC_RECORD_MAGIC;
+    record.drivers.into_iter().take(if valid {
+        record.count.min(MAX_PANICKED_DRIVERS)
+    } else {
+        0
+    })
+}
+
+/// Whether syscalls to `driver_num` fail because the driver panicked.
+pub(crate) fn driver_panicked(driver_num: usize) -> bool {
+    panicked_drivers().any(|panicked| panicked == driver_num)
+}
+
+/// Iterate over the syscall drivers disabled because they panicked.
+pub fn for_each_panicked_driver(f: impl FnMut(usize)) {
+    panicked_drivers().for_each(f);
+}
+
+/// Enable again the syscall drivers disabled because they panicked.
+pub fn clear_panicked_drivers() {
+    // Safety: the kernel is single-threaded and the record is not borrowed.
+    unsafe {
+        core::ptr::write_volatile(addr_of_mut!(PANIC_RECORD.magic), 0);
+    }
+}
+
+/// Contain a panic in a syscall driver, if capsule panic containment is
+/// enabled.
+///
+/// Containment means resetting the board, then fencing off the driver. The
+/// kernel is built with `panic = "abort"`, so a panic cannot be unwound back
+/// to the syscall that caused it, and the kernel state it left behind cannot
+/// be trusted.
+///
+/// If the panic happened while the kernel was running a command, subscribe
+/// or allow of a syscall driver, the driver is recorded in RAM that is
+/// preserved across resets, the panic is printed and the board is reset
+/// with `reset`. Every process restarts and loses its state. After the
+/// reset, every syscall to the driver fails with `FAIL` until the next power
+/// cycle, while the other drivers work as usual. So a faulty non-critical
+/// driver costs one reset instead of halting the system until it is power
+/// cycled, and processes that keep calling it get an error instead of
+/// bringing the board down again. Otherwise, this function returns and the
+/// board handles the panic as usual.
+///
+/// Panics in interrupt handlers or deferred calls are not contained, since
+/// the driver they belong to is not known. If `MAX_PANICKED_DRIVERS` drivers
+/// already panicked, the panic is not contained either.
+///
+/// This must be called at the beginning of the panic handler of the board.
+/// `reset` must keep the content of the `.noinit` section: a reset that
+/// clears RAM loses the record, and the driver panics again after the reset.
+///
+/// **NOTE:** The supplied `writer` must be synchronous.
+pub unsafe fn panic_contain<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
+    writer: &mut W,
+    panic_info: &PanicInfo,
+    nop: &dyn Fn(),
+    processes: &'static [Option<&'static dyn Process>],
+    chip: &'static Option<&'static C>,
+    process_printer: &'static Option<&'static PP>,
+    reset: fn() -> !,
+) {
+    let driver_num = match *addr_of!(RUNNING_DRIVER) {
+        Some(driver_num) if config::CONFIG.contain_capsule_panics => driver_num,
+        _ => return,
+    };
+
+    let record = &mut *addr_of_mut!(PANIC_RECORD);
+    if record.magic != PANIC_RECORD_MAGIC {
+        record.count = 0;
+    }
+    if record.count >= MAX_PANICKED_DRIVERS {
+        return;
+    }
+    record.drivers[record.count] = driver_num;
+    record.count += 1;
+    record.magic = PANIC_RECORD_MAGIC;
+
+    panic_print(writer, panic_info, nop, processes, chip, process_printer);
+    let _ = writer.write_fmt(format_args!(
+        "\r\nDisabling syscall driver {:#x} and resetting.\r\n",
+        driver_num
+    ));
+    reset()
+}
+
+// capsule panic containment
+///////////////////////////////////////////////////////////////////
+
 ///////////////////////////////////////////////////////////////////
 // debug_gpio! support
 
@@ -280,6 +471,138 @@ macro_rules! debug_gpio {
     }};
 }
 
+///////////////////////////////////////////////////////////////////
+// Peripheral register snapshots
+
+/// A block of memory-mapped peripheral registers that can be dumped for bug
+/// reports, for example with the `regs` command of the process console.
+///
+/// Boards declare the blocks of the peripherals they use:
+///
+/// ```ignore
+/// static REGISTER_BLOCKS: [kernel::debug::RegisterBlock; 1] =
+///     [unsafe { kernel::debug::RegisterBlock::new("CAN1", 0x4000_6400, 8) }];
+/// ```
+pub struct RegisterBlock {
+    name: &'static str,
+    base: usize,
+    words: usize,
+}
+
+impl RegisterBlock {
+    /// Declare the `words` 32-bit registers starting at `base`.
+    ///
+    /// # Safety
+    ///
+    /// The block must be mapped, aligned, and reading any of its registers
+    /// must have no side effect (such as clearing a status flag or popping a
+    /// FIFO). Registers of a peripheral whose clock is gated may read as zero
+    /// or fault on some chips.
+    pub const unsafe fn new(name: &'static str, base: usize, words: usize) -> RegisterBlock {
+        RegisterBlock { name, base, words }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Number of 32-bit registers in the block.
+    pub fn len(&self) -> usize {
+        self.words
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words == 0
+    }
+
+    /// Read register `index` of the block.
+    pub fn read(&self, index: usize) -> Option<u32> {
+        if index >= self.words {
+            return None;
+        }
+        let address = (self.base + index * 4) as *const u32;
+        // Safety: the board guaranteed that the block can be read when
+        // declaring it.
+        Some(unsafe { core::ptr::read_volatile(address) })
+    }
+}
+
+///////////////////////////////////////////////////////////////////
+// Interrupt and deferred call recording
+
+/// An interrupt or deferred call delivered by the kernel loop.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// An interrupt, numbered by the chip that delivered it.
+    Interrupt(usize),
+    /// A deferred call, by the index it was serviced with.
+    DeferredCall(usize),
+}
+
+/// The most recent events, oldest first from `next`.
+struct EventRecord {
+    events: [Option<Event>; config::CONFIG.event_record_length],
+    next: usize,
+}
+
+static mut EVENT_RECORD: EventRecord = EventRecord {
+    events: [None; config::CONFIG.event_record_length],
+    next: 0,
+};
+
+/// Record the delivery of `event`, if event recording is enabled.
+///
+/// Deferred calls are recorded by the kernel. Chips record the interrupts
+/// they deliver in `Chip::service_pending_interrupts()`.
+pub fn record_event(event: Event) {
+    let length = config::CONFIG.event_record_length;
+    if length != 0 {
+        // Safety: the kernel is single-threaded and no reference to the record
+        // outlives this function.
+        let record = unsafe { &mut *addr_of_mut!(EVENT_RECORD) };
+        record.events[record.next] = Some(event);
+        record.next = (record.next + 1) % length;
+    }
+}
+
+/// Call `f` with the recorded events, oldest first.
+pub fn for_each_recorded_event(mut f: impl FnMut(Event)) {
+    let length = config::CONFIG.event_record_length;
+    // Safety: the kernel is single-threaded and `f` cannot record events
+    // while the record is borrowed, since events are only delivered by the
+    // kernel loop.
+    let record = unsafe { &*addr_of!(EVENT_RECORD) };
+    for index in 0..length {
+        if let Some(event) = record.events[(record.next + index) % length] {
+            f(event);
+        }
+    }
+}
+
+/// Forget the recorded events, e.g. before starting a scenario to record.
+pub fn clear_recorded_events() {
+    // Safety: see `record_event()`.
+    let record = unsafe { &mut *addr_of_mut!(EVENT_RECORD) };
+    record.events = [None; config::CONFIG.event_record_length];
+    record.next = 0;
+}
+
+/// Print the recorded events, if event recording is enabled.
+///
+/// **NOTE:** The supplied `writer` must be synchronous.
+pub fn panic_recorded_events<W: Write>(writer: &mut W) {
+    if config::CONFIG.event_record_length != 0 {
+        let _ = writer.write_fmt(format_args!("\r\n---| Recorded events |---\r\n"));
+        for_each_recorded_event(|event| {
+            let _ = writer.write_fmt(format_args!("{:?}\r\n", event));
+        });
+    }
+}
+
 ///////////////////////////////////////////////////////////////////
 // debug_enqueue! support
 
@@ -395,8 +718,15 @@ pub struct DebugWriter {
     internal_buffer: TakeCell<'static, RingBuffer<'static, u8>>,
     // Number of debug!() calls.
     count: Cell<usize>,
+    // Bytes dropped since the last "[N bytes dropped]" marker was written.
+    dropped: Cell<usize>,
+    // Bytes dropped since boot.
+    total_dropped: Cell<usize>,
 }
 
+/// Longest `[N bytes dropped]` marker, for `N` up to `usize::MAX`.
+const DROPPED_MSG_MAX_LEN: usize = 40;
+
 /// Static variable that holds the kernel's reference to the debug tool. This is
 /// needed so the debug!() macros have a reference to the object to use.
 static mut DEBUG_WRITER: Option<&'static mut DebugWriterWrapper> = None;
@@ -433,6 +763,8 @@ impl DebugWriter {
             output_buffer: TakeCell::new(out_buffer),
             internal_buffer: TakeCell::new(internal_buffer),
             count: Cell::new(0), // how many debug! calls
+            dropped: Cell::new(0),
+            total_dropped: Cell::new(0),
         }
     }
 
@@ -487,6 +819,55 @@ impl DebugWriter {
     fn available_len(&self) -> usize {
         self.internal_buffer.map_or(0, |rb| rb.available_len())
     }
+
+    fn get_dropped(&self) -> usize {
+        self.dropped.get()
+    }
+
+    fn get_total_dropped(&self) -> usize {
+        self.total_dropped.get()
+    }
+
+    /// Record that `len` bytes of output did not fit in the internal buffer.
+    fn drop_bytes(&self, len: usize) {
+        self.dropped.set(self.dropped.get().saturating_add(len));
+        self.total_dropped
+            .set(self.total_dropped.get().saturating_add(len));
+    }
+
+    /// Enqueue the `[N bytes dropped]` marker for the output dropped since the
+    /// last marker, if any. Returns `false` if some output was dropped and the
+    /// marker does not fit yet.
+    fn enqueue_dropped_marker(&self, ring_buffer: &mut RingBuffer<'static, u8>) -> bool {
+        let dropped = self.dropped.get();
+        if dropped == 0 {
+            return true;
+        }
+        if ring_buffer.available_len() < DROPPED_MSG_MAX_LEN {
+            return false;
+        }
+
+        let mut digits = [0; 20];
+        let mut start = digits.len();
+        let mut n = dropped;
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        for &b in b"\r\n["
+            .iter()
+            .chain(&digits[start..])
+            .chain(b" bytes dropped]\r\n")
+        {
+            ring_buffer.enqueue(b);
+        }
+        self.dropped.set(0);
+        true
+    }
 }
 
 impl hil::uart::TransmitClient for DebugWriter {
@@ -499,6 +880,10 @@ impl hil::uart::TransmitClient for DebugWriter {
         // Replace this buffer since we are done with it.
         self.output_buffer.replace(buffer);
 
+        // Now that there is room again, report any output we had to drop.
+        self.internal_buffer
+            .map(|buf| self.enqueue_dropped_marker(buf));
+
         if self.internal_buffer.map_or(false, |buf| buf.has_elements()) {
             // Buffer not empty, go around again
             self.publish_bytes();
@@ -528,35 +913,46 @@ impl DebugWriterWrapper {
     }
 
     fn available_len(&self) -> usize {
-        const FULL_MSG: &[u8] = b"\n*** DEBUG BUFFER FULL ***\n";
-        self.dw
-            .map_or(0, |dw| dw.available_len().saturating_sub(FULL_MSG.len()))
+        self.dw.map_or(0, |dw| {
+            dw.available_len()
+                .saturating_sub(DROPPED_MSG_MAX_LEN)
+                .saturating_sub(if dw.get_dropped() != 0 {
+                    DROPPED_MSG_MAX_LEN
+                } else {
+                    0
+                })
+        })
+    }
+
+    fn get_dropped(&self) -> usize {
+        self.dw.map_or(0, |dw| dw.get_dropped())
+    }
+
+    fn get_total_dropped(&self) -> usize {
+        self.dw.map_or(0, |dw| dw.get_total_dropped())
     }
 }
 
 impl IoWrite for DebugWriterWrapper {
     fn write(&mut self, bytes: &[u8]) -> usize {
-        const FULL_MSG: &[u8] = b"\n*** DEBUG BUFFER FULL ***\n";
         self.dw.map_or(0, |dw| {
             dw.internal_buffer.map_or(0, |ring_buffer| {
-                let available_len_for_msg =
-                    ring_buffer.available_len().saturating_sub(FULL_MSG.len());
-
-                if available_len_for_msg >= bytes.len() {
+                // Always keep room for a `[N bytes dropped]` marker, so that
+                // output that does not fit is reported rather than silently
+                // truncated. Consecutive drops are coalesced into one marker.
+                if dw.enqueue_dropped_marker(ring_buffer)
+                    && ring_buffer
+                        .available_len()
+                        .saturating_sub(DROPPED_MSG_MAX_LEN)
+                        >= bytes.len()
+                {
                     for &b in bytes {
                         ring_buffer.enqueue(b);
                     }
                     bytes.len()
                 } else {
-                    for &b in &bytes[..available_len_for_msg] {
-                        ring_buffer.enqueue(b);
-                    }
-                    // When the buffer is close to full, print a warning and drop the current
-                    // string.
-                    for &b in FULL_MSG {
-                        ring_buffer.enqueue(b);
-                    }
-                    available_len_for_msg
+                    dw.drop_bytes(bytes.len());
+                    0
                 }
             })
         })
@@ -606,6 +1002,12 @@ pub fn debug_available_len() -> usize {
     writer.available_len()
 }
 
+/// Number of bytes of `debug!()` output dropped since boot because the
+/// internal buffer was full.
+pub fn debug_dropped_bytes() -> usize {
+    unsafe { try_get_debug_writer() }.map_or(0, |writer| writer.get_total_dropped())
+}
+
 fn write_header(writer: &mut DebugWriterWrapper, (file, line): &(&'static str, u32)) -> Result {
     writer.increment_count();
     let count = writer.get_count();
@@ -675,6 +1077,168 @@ macro_rules! debug_verbose {
     });
 }
 
+/// Verbosity of a log message or of a subsystem, from the least to the most
+/// verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Nothing is printed. Only used as a subsystem level.
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Trace = 4,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [
+        LogLevel::Off,
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Trace,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        LogLevel::ALL.into_iter().find(|level| level.name() == name)
+    }
+}
+
+/// Parts of the kernel whose log level can be set separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogSubsystem {
+    Kernel = 0,
+    /// 802.15.4 and BLE radios and MAC layers.
+    Radio = 1,
+    /// Network stack (6LoWPAN, IPv6, UDP, Thread).
+    Net = 2,
+    Can = 3,
+    Usb = 4,
+    Storage = 5,
+    Crypto = 6,
+    /// Sensors and ADCs.
+    Sensors = 7,
+}
+
+impl LogSubsystem {
+    pub const ALL: [LogSubsystem; 8] = [
+        LogSubsystem::Kernel,
+        LogSubsystem::Radio,
+        LogSubsystem::Net,
+        LogSubsystem::Can,
+        LogSubsystem::Usb,
+        LogSubsystem::Storage,
+        LogSubsystem::Crypto,
+        LogSubsystem::Sensors,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogSubsystem::Kernel => "kernel",
+            LogSubsystem::Radio => "radio",
+            LogSubsystem::Net => "net",
+            LogSubsystem::Can => "can",
+            LogSubsystem::Usb => "usb",
+            LogSubsystem::Storage => "storage",
+            LogSubsystem::Crypto => "crypto",
+            LogSubsystem::Sensors => "sensors",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LogSubsystem> {
+        LogSubsystem::ALL
+            .into_iter()
+            .find(|subsystem| subsystem.name() == name)
+    }
+}
+
+/// Level of every subsystem at boot. Errors and warnings are printed, bring-up
+/// details are not.
+pub const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Warn;
+
+static LOG_LEVELS: [AtomicU8; LogSubsystem::ALL.len()] =
+    [const { AtomicU8::new(DEFAULT_LOG_LEVEL as u8) }; LogSubsystem::ALL.len()];
+
+/// Current level of `subsystem`.
+pub fn log_level(subsystem: LogSubsystem) -> LogLevel {
+    let level = LOG_LEVELS[subsystem as usize].load(Ordering::Relaxed);
+    LogLevel::ALL
+        .get(level as usize)
+        .copied()
+        .unwrap_or(DEFAULT_LOG_LEVEL)
+}
+
+/// Set the level of `subsystem`. Messages more verbose than `level` are
+/// discarded.
+pub fn set_log_level(subsystem: LogSubsystem, level: LogLevel) {
+    LOG_LEVELS[subsystem as usize].store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether a message of `level` from `subsystem` is printed.
+pub fn log_enabled(subsystem: LogSubsystem, level: LogLevel) -> bool {
+    level != LogLevel::Off && level <= log_level(subsystem)
+}
+
+pub fn debug_log(subsystem: LogSubsystem, level: LogLevel, args: Arguments) {
+    debug_println(format_args!(
+        "[{} {}] {}",
+        level.name(),
+        subsystem.name(),
+        args
+    ));
+}
+
+/// Print a message if the level of the subsystem allows it. Use the
+/// `log_error!`, `log_warn!`, `log_info!` and `log_trace!` shorthands.
+#[macro_export]
+macro_rules! debug_log {
+    ($level:ident, $subsystem:ident, $($arg:tt)+) => ({
+        if $crate::debug::log_enabled(
+            $crate::debug::LogSubsystem::$subsystem,
+            $crate::debug::LogLevel::$level,
+        ) {
+            $crate::debug::debug_log(
+                $crate::debug::LogSubsystem::$subsystem,
+                $crate::debug::LogLevel::$level,
+                format_args!($($arg)+),
+            );
+        }
+    });
+}
+
+/// Log an error of a subsystem: `log_error!(Radio, "...")`.
+#[macro_export]
+macro_rules! log_error {
+    ($subsystem:ident, $($arg:tt)+) => ($crate::debug_log!(Error, $subsystem, $($arg)+));
+}
+
+/// Log a warning of a subsystem: `log_warn!(Radio, "...")`.
+#[macro_export]
+macro_rules! log_warn {
+    ($subsystem:ident, $($arg:tt)+) => ($crate::debug_log!(Warn, $subsystem, $($arg)+));
+}
+
+/// Log an informational message of a subsystem: `log_info!(Radio, "...")`.
+#[macro_export]
+macro_rules! log_info {
+    ($subsystem:ident, $($arg:tt)+) => ($crate::debug_log!(Info, $subsystem, $($arg)+));
+}
+
+/// Log a detailed bring-up message of a subsystem: `log_trace!(Radio, "...")`.
+#[macro_export]
+macro_rules! log_trace {
+    ($subsystem:ident, $($arg:tt)+) => ($crate::debug_log!(Trace, $subsystem, $($arg)+));
+}
+
 #[macro_export]
 /// Prints out the expression and its location, then returns it.
 ///
@@ -723,6 +1287,11 @@ pub unsafe fn flush<W: Write + IoWrite>(writer: &mut W) {
             }
         }
 
+        let dropped = debug_writer.get_dropped();
+        if dropped != 0 {
+            let _ = writer.write_fmt(format_args!("\r\n[{} bytes dropped]\r\n", dropped));
+        }
+
         match DEBUG_QUEUE.as_deref_mut() {
             None => {
                 let _ = writer.write_str(
@@ -58,6 +58,7 @@
 //! some_capsule.register();
 //! ```
 
+use crate::debug;
 use crate::utilities::cells::OptionalCell;
 use core::cell::Cell;
 use core::marker::Copy;
@@ -204,6 +205,7 @@ impl DeferredCall {
             let bit = val.trailing_zeros() as usize;
             let new_val = val & !(1 << bit);
             bitmask.set(new_val);
+            debug::record_event(debug::Event::DeferredCall(bit));
             defcalls[bit].map(|dc| {
                 dc.handle_deferred_call();
                 bit
@@ -211,6 +213,26 @@ impl DeferredCall {
         }
     }
 
+    /// Services and clears the deferred call at index `idx`, out of order,
+    /// if it is pending. Returns whether it was pending.
+    ///
+    /// This is used to replay a recorded order of events, see
+    /// `debug::record_event()`.
+    pub fn service_pending(idx: usize) -> bool {
+        // SAFETY: No accesses to BITMASK/DEFCALLS are via an &mut, and the Tock kernel is
+        // single-threaded so all accesses will occur from this thread.
+        let bitmask = unsafe { &*addr_of!(BITMASK) };
+        let defcalls = unsafe { &*addr_of!(DEFCALLS) };
+        let val = bitmask.get();
+        if idx >= defcalls.len() || val & (1 << idx) == 0 {
+            return false;
+        }
+        bitmask.set(val & !(1 << idx));
+        debug::record_event(debug::Event::DeferredCall(idx));
+        defcalls[idx].map(|dc| dc.handle_deferred_call());
+        true
+    }
+
     /// Returns true if any deferred calls are waiting to be serviced,
     /// false otherwise.
     pub fn has_tasks() -> bool {
@@ -135,6 +135,7 @@ use core::ops::{Deref, DerefMut};
 use core::ptr::{write, NonNull};
 use core::slice;
 
+use crate::config;
 use crate::kernel::Kernel;
 use crate::process::{Error, Process, ProcessCustomGrantIdentifier, ProcessId};
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
@@ -218,6 +219,15 @@ impl<const NUM: u8> AllowRwSize for AllowRwCount<NUM> {
 /// |-------------|-------------|-------------|-------------|
 /// ```
 ///
+/// If the `debug_grant_canaries` kernel feature is enabled, the grant is
+/// additionally bracketed with two canary words, one before the counters,
+/// followed by the size of the grant, and one after T. They are checked every
+/// time the grant is entered, by a capsule or by the kernel for a subscribe or
+/// an allow, to catch capsules writing past their grant data. Capsules find the
+/// tail canary from the size of T. The kernel does not know T and uses the
+/// stored size instead, after checking that it stays within the process's
+/// memory.
+///
 /// This type is created whenever a grant is entered, and is responsible for
 /// ensuring that the grant is closed when it is no longer used. On `Drop`, we
 /// leave the grant. This protects against calling `grant.enter()` without
@@ -260,6 +270,30 @@ struct GrantDataSize(usize);
 #[derive(Copy, Clone)]
 struct GrantDataAlign(usize);
 
+/// Value of the canary words bracketing grants when `debug_grant_canaries` is
+/// enabled.
+const GRANT_CANARY: usize = 0x6A4E_C0DE;
+
+/// Size of each canary word bracketing grants, zero if they are disabled.
+const fn grant_canary_size() -> usize {
+    if config::CONFIG.debug_grant_canaries {
+        size_of::<usize>()
+    } else {
+        0
+    }
+}
+
+/// Size of the head canary and of the grant size stored after it, zero if
+/// canaries are disabled. The size lets the kernel find the tail canary
+/// without knowing T.
+const fn grant_header_size() -> usize {
+    if config::CONFIG.debug_grant_canaries {
+        2 * size_of::<usize>()
+    } else {
+        0
+    }
+}
+
 impl<'a> EnteredGrantKernelManagedLayout<'a> {
     /// Reads the specified pointer as the base of the kernel owned grant region
     /// that has previously been initialized.
@@ -276,7 +310,7 @@ impl<'a> EnteredGrantKernelManagedLayout<'a> {
         process: &'a dyn Process,
         grant_num: usize,
     ) -> Self {
-        let counters_ptr = base_ptr.as_ptr() as *mut usize;
+        let counters_ptr = base_ptr.as_ptr().add(grant_header_size()) as *mut usize;
         let counters_val = counters_ptr.read();
 
         // Parse the counters field for each of the fields
@@ -316,7 +350,7 @@ impl<'a> EnteredGrantKernelManagedLayout<'a> {
         process: &'a dyn Process,
         grant_num: usize,
     ) -> Self {
-        let counters_ptr = base_ptr.as_ptr() as *mut usize;
+        let counters_ptr = base_ptr.as_ptr().add(grant_header_size()) as *mut usize;
 
         // Create the counters usize value by correctly packing the various
         // counts into 8 bit fields.
@@ -353,7 +387,8 @@ impl<'a> EnteredGrantKernelManagedLayout<'a> {
         grant_t_size: GrantDataSize,
         grant_t_align: GrantDataAlign,
     ) -> usize {
-        let kernel_managed_size = size_of::<usize>()
+        let kernel_managed_size = grant_header_size()
+            + size_of::<usize>()
             + upcalls_num.0 as usize * size_of::<SavedUpcall>()
             + allow_ro_num.0 as usize * size_of::<SavedAllowRo>()
             + allow_rw_num.0 as usize * size_of::<SavedAllowRw>();
@@ -365,7 +400,7 @@ impl<'a> EnteredGrantKernelManagedLayout<'a> {
         // ensuring a full alignment value maps to 0.
         let padding =
             (grant_t_align.0 - (kernel_managed_size & grant_t_align_mask)) & grant_t_align_mask;
-        kernel_managed_size + padding + grant_t_size.0
+        kernel_managed_size + padding + grant_t_size.0 + grant_canary_size()
     }
 
     /// Returns the alignment of the entire grant region based on the alignment
@@ -390,10 +425,135 @@ impl<'a> EnteredGrantKernelManagedLayout<'a> {
         grant_t_size: GrantDataSize,
     ) -> NonNull<u8> {
         // The location of the grant data T is the last element in the entire
-        // grant region. Caller must verify that memory is accessible and well
-        // aligned to T.
+        // grant region, before the tail canary. Caller must verify that memory
+        // is accessible and well aligned to T.
         let grant_t_size_usize: usize = grant_t_size.0;
-        NonNull::new_unchecked(base_ptr.as_ptr().add(grant_size - grant_t_size_usize))
+        NonNull::new_unchecked(
+            base_ptr
+                .as_ptr()
+                .add(grant_size - grant_canary_size() - grant_t_size_usize),
+        )
+    }
+
+    /// Write the canary words bracketing the grant, and the size of the grant
+    /// after the head canary.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the specified base pointer is well aligned
+    /// and points to a grant that is of size grant_size bytes.
+    unsafe fn initialize_canaries(base_ptr: NonNull<u8>, grant_size: usize) {
+        if config::CONFIG.debug_grant_canaries {
+            let head = base_ptr.as_ptr() as *mut usize;
+            head.write(GRANT_CANARY);
+            head.add(1).write(grant_size);
+            let tail = base_ptr.as_ptr().add(grant_size - grant_canary_size()) as *mut usize;
+            tail.write_unaligned(GRANT_CANARY);
+        }
+    }
+
+    /// Check the canary words bracketing a grant of `grant_size` bytes, and
+    /// panic if a capsule wrote over them.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the specified base pointer is well aligned
+    /// and points to a grant of `grant_size` bytes initialized with
+    /// `initialize_canaries()`.
+    unsafe fn check_canaries(
+        base_ptr: NonNull<u8>,
+        grant_size: usize,
+        driver_num: usize,
+        process: &dyn Process,
+    ) {
+        if !config::CONFIG.debug_grant_canaries {
+            return;
+        }
+        Self::check_head_canary(base_ptr, driver_num, process);
+        Self::check_tail_canary(base_ptr, grant_size, driver_num, process);
+    }
+
+    /// Check the canary words bracketing a grant entered by the kernel, which
+    /// does not know T, and panic if a capsule wrote over them.
+    ///
+    /// The tail canary is found with the grant size stored after the head
+    /// canary. As that size is itself in the grant region, it is checked
+    /// against the end of the process's memory before it is used.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the specified base pointer is well aligned
+    /// and points to a grant of `process` initialized with
+    /// `initialize_canaries()`.
+    unsafe fn check_canaries_stored_size(
+        base_ptr: NonNull<u8>,
+        driver_num: usize,
+        process: &dyn Process,
+    ) {
+        if !config::CONFIG.debug_grant_canaries {
+            return;
+        }
+        // The head canary is checked first: an overflow of the grant allocated
+        // after this one reaches the head canary before the grant size.
+        Self::check_head_canary(base_ptr, driver_num, process);
+
+        let grant_size = (base_ptr.as_ptr() as *const usize).add(1).read();
+        let min_size = grant_header_size() + size_of::<usize>() + grant_canary_size();
+        let max_size = process
+            .get_addresses()
+            .sram_end
+            .saturating_sub(base_ptr.as_ptr() as usize);
+        if grant_size < min_size || grant_size > max_size {
+            panic!(
+                "Grant size of driver {:#x} overwritten in process {} (size {:#x}).",
+                driver_num,
+                process.get_process_name(),
+                grant_size
+            );
+        }
+        Self::check_tail_canary(base_ptr, grant_size, driver_num, process);
+    }
+
+    /// Panic if the canary word before the counters was overwritten.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the specified base pointer is well aligned
+    /// and points to a grant initialized with `initialize_canaries()`.
+    unsafe fn check_head_canary(base_ptr: NonNull<u8>, driver_num: usize, process: &dyn Process) {
+        let head = (base_ptr.as_ptr() as *const usize).read();
+        if head != GRANT_CANARY {
+            panic!(
+                "Grant of driver {:#x} overwritten by the grant allocated after it in process {} (canary {:#x}).",
+                driver_num,
+                process.get_process_name(),
+                head
+            );
+        }
+    }
+
+    /// Panic if the canary word following the grant data T was overwritten.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the specified base pointer points to a
+    /// grant of `grant_size` bytes initialized with `initialize_canaries()`.
+    unsafe fn check_tail_canary(
+        base_ptr: NonNull<u8>,
+        grant_size: usize,
+        driver_num: usize,
+        process: &dyn Process,
+    ) {
+        let tail = (base_ptr.as_ptr().add(grant_size - grant_canary_size()) as *const usize)
+            .read_unaligned();
+        if tail != GRANT_CANARY {
+            panic!(
+                "Grant data of driver {:#x} overflowed in process {} (canary {:#x}).",
+                driver_num,
+                process.get_process_name(),
+                tail
+            );
+        }
     }
 
     /// Read an 8 bit value from the counter field offset by the specified
@@ -784,6 +944,13 @@ fn enter_grant_kernel_managed(
     // We know that this pointer is well aligned and initialized with meaningful
     // data when the grant region was allocated.
     let layout = unsafe {
+        // Catch capsules that wrote past their grant data since the grant was
+        // last entered.
+        EnteredGrantKernelManagedLayout::check_canaries_stored_size(
+            grant_base_ptr,
+            driver_num,
+            process,
+        );
         EnteredGrantKernelManagedLayout::read_from_base(grant_base_ptr, process, grant_num)
     };
     Ok(layout)
@@ -1078,6 +1245,7 @@ impl<'a, T: Default, Upcalls: UpcallSize, AllowROs: AllowRoSize, AllowRWs: Allow
                             process,
                             grant_num,
                         );
+                        EnteredGrantKernelManagedLayout::initialize_canaries(grant_ptr, alloc_size);
                     }
 
                     // # Safety
@@ -1395,6 +1563,22 @@ impl<'a, T: Default, Upcalls: UpcallSize, AllowROs: AllowRoSize, AllowRWs: Allow
             grant_t_align,
         );
 
+        // Catch capsules that wrote past their grant data since the grant was
+        // last entered.
+        //
+        // # Safety
+        //
+        // Grant pointer is well aligned and points to an initialized grant of
+        // `alloc_size` bytes.
+        unsafe {
+            EnteredGrantKernelManagedLayout::check_canaries(
+                grant_ptr,
+                alloc_size,
+                self.driver_num,
+                self.process,
+            );
+        }
+
         // Parse layout of entire grant allocation using the known base pointer.
         //
         // # Safety
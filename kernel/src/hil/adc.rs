@@ -3,6 +3,12 @@
 // Copyright Tock Contributors 2022.
 
 //! Interfaces for analog to digital converter peripherals.
+//!
+//! Samples are the raw ADC value left-justified in the sample width, which
+//! is 16 bits for ADCs of up to 16 bits of resolution, and 32 bits for
+//! high-resolution converters (see `sample_width_bits`). Single samples are
+//! passed in a u32 whatever the width. Buffered samples are stored in u16
+//! buffers, so only ADCs with 16-bit samples support them.
 
 use crate::ErrorCode;
 
@@ -15,7 +21,8 @@ pub trait Adc<'a> {
 
     /// Request a single ADC sample on a particular channel.
     /// Used for individual samples that have no timing requirements.
-    /// All ADC samples will be the raw ADC value left-justified in the u16.
+    /// All ADC samples will be the raw ADC value left-justified in the
+    /// sample width.
     fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode>;
 
     /// Request repeated ADC samples on a particular channel.
@@ -23,7 +30,8 @@ pub trait Adc<'a> {
     /// set to any frequency supported by the chip implementation. However
     /// callbacks may be limited based on how quickly the system can service
     /// individual samples, leading to missed samples at high frequencies.
-    /// All ADC samples will be the raw ADC value left-justified in the u16.
+    /// All ADC samples will be the raw ADC value left-justified in the
+    /// sample width.
     fn sample_continuous(&self, channel: &Self::Channel, frequency: u32) -> Result<(), ErrorCode>;
 
     /// Stop a sampling operation.
@@ -35,6 +43,11 @@ pub trait Adc<'a> {
     /// it is returning.
     fn get_resolution_bits(&self) -> usize;
 
+    /// The number of bits the samples are left-justified in, 16 or 32.
+    fn get_sample_width_bits(&self) -> usize {
+        sample_width_bits(self.get_resolution_bits())
+    }
+
     /// Function to ask the ADC what reference voltage it used when taking the
     /// samples. This allows the user of this interface to calculate an actual
     /// voltage from the ADC reading.
@@ -42,19 +55,235 @@ pub trait Adc<'a> {
     /// The returned reference voltage is in millivolts, or `None` if unknown.
     fn get_voltage_reference_mv(&self) -> Option<usize>;
 
+    /// Set the oversampling ratio of the following samples: each sample is
+    /// the average of `ratio` conversions, which increases its effective
+    /// resolution at the cost of the maximum sampling rate. A ratio of 1
+    /// disables oversampling.
+    ///
+    /// Return `INVAL` if the hardware does not support `ratio`, `BUSY` if a
+    /// sampling operation is ongoing, and `NOSUPPORT` if the ADC has no
+    /// hardware oversampling.
+    fn set_oversampling(&self, ratio: usize) -> Result<(), ErrorCode> {
+        if ratio == 1 {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    /// The number of conversions averaged in each sample.
+    fn get_oversampling(&self) -> usize {
+        1
+    }
+
+    /// Set the number of bits of the following conversions, as then returned
+    /// by `get_resolution_bits`. Lower resolutions convert faster, which
+    /// allows higher sampling frequencies. Samples stay left-justified in the
+    /// sample width of the resolution.
+    ///
+    /// Return `INVAL` if the hardware does not support `bits`, `BUSY` if a
+    /// sampling operation is ongoing, and `NOSUPPORT` if the resolution of
+    /// the ADC is fixed.
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        if bits == self.get_resolution_bits() {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    /// Request a single sample of the voltage of the `positive` channel
+    /// minus the voltage of the `negative` channel.
+    ///
+    /// The sample is signed: it is the raw ADC value in two's complement,
+    /// left-justified in the sample width, and should be read as an `i16`
+    /// (or an `i32` for 32-bit samples). It is delivered with
+    /// `Client::sample_ready`.
+    ///
+    /// Return `INVAL` if the hardware cannot pair these channels, and
+    /// `NOSUPPORT` if the ADC has no differential inputs.
+    fn sample_differential(
+        &self,
+        _positive: &Self::Channel,
+        _negative: &Self::Channel,
+    ) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Sample a channel repeatedly with the hardware analog watchdog, and
+    /// call `Client::sample_ready` once, with the first sample below `low` or
+    /// above `high`. Sampling then stops.
+    ///
+    /// Samples inside the window are not reported, so the CPU can sleep until
+    /// the voltage leaves the window. The thresholds are raw ADC values
+    /// left-justified in the sample width, like samples. `stop_sampling`
+    /// disarms the watchdog.
+    ///
+    /// Return `BUSY` if the ADC is already sampling, `INVAL` if a threshold
+    /// does not fit in the sample width, and `NOSUPPORT` if the ADC has no
+    /// analog watchdog.
+    fn sample_window(
+        &self,
+        _channel: &Self::Channel,
+        _low: u32,
+        _high: u32,
+    ) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Run the hardware offset and gain calibration of the ADC, and call
+    /// `Client::calibration_done` when it completes. The calibration drifts
+    /// with the temperature and the supply voltage, so long-running systems
+    /// should calibrate again when they change.
+    ///
+    /// Return `BUSY` if the ADC is sampling, and `NOSUPPORT` if the ADC has
+    /// no calibration sequence.
+    fn calibrate(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Set the gain of the input amplifier and the voltage reference used to
+    /// convert `channel`, for the following samples of this channel. The
+    /// full-scale voltage of the channel is then
+    /// `get_channel_voltage_reference_mv`.
+    ///
+    /// Return `INVAL` if the hardware does not support this combination for
+    /// the channel, `BUSY` if it cannot change while sampling, and
+    /// `NOSUPPORT` if the gain and reference are fixed.
+    fn set_channel_gain_and_reference(
+        &self,
+        _channel: &Self::Channel,
+        _gain: Gain,
+        _reference: Reference,
+    ) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// The input voltage of `channel` at full scale in millivolts, with the
+    /// gain and reference of the channel, or `None` if unknown. The default
+    /// implementation returns `get_voltage_reference_mv`, for ADCs without
+    /// per-channel settings.
+    fn get_channel_voltage_reference_mv(&self, _channel: &Self::Channel) -> Option<usize> {
+        self.get_voltage_reference_mv()
+    }
+
+    /// Convert a single-ended sample to millivolts, rounded to the nearest
+    /// millivolt.
+    ///
+    /// The default implementation scales the sample to the voltage
+    /// reference. Chips with per-device calibration data should override it
+    /// to apply their corrections. Returns `None` if the voltage reference is
+    /// unknown.
+    fn sample_to_mv(&self, sample: u32) -> Option<usize> {
+        self.get_voltage_reference_mv()
+            .map(|reference_mv| sample_to_mv(sample, self.get_resolution_bits(), reference_mv))
+    }
+
     fn set_client(&self, client: &'a dyn Client);
 }
 
+/// The voltage reference a channel is converted against.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reference {
+    /// A reference generated inside the chip, such as a bandgap.
+    Internal = 0,
+    /// The supply voltage of the ADC, or a fixed fraction of it.
+    Vdd = 1,
+    /// A reference voltage applied to a pin.
+    External = 2,
+}
+
+/// Gain of the input amplifier of a channel, `numerator / denominator`.
+/// Gains below 1 attenuate the input, so that voltages above the reference
+/// can be measured.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Gain {
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl Gain {
+    pub const fn new(numerator: u8, denominator: u8) -> Gain {
+        Gain {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Whether this gain equals `numerator / denominator`, whatever the
+    /// reduction of the fractions.
+    pub const fn matches(&self, numerator: u8, denominator: u8) -> bool {
+        self.denominator != 0
+            && self.numerator as u16 * denominator as u16
+                == numerator as u16 * self.denominator as u16
+    }
+
+    /// The full-scale input voltage in millivolts with a reference of
+    /// `reference_mv` millivolts, rounded down.
+    pub const fn full_scale_mv(&self, reference_mv: usize) -> usize {
+        if self.numerator == 0 {
+            0
+        } else {
+            reference_mv * self.denominator as usize / self.numerator as usize
+        }
+    }
+}
+
+/// The width of the samples of an ADC with `resolution_bits` bits: 16 bits
+/// up to a resolution of 16 bits, and 32 bits above.
+pub const fn sample_width_bits(resolution_bits: usize) -> usize {
+    if resolution_bits <= 16 {
+        16
+    } else {
+        32
+    }
+}
+
+/// Convert a single-ended sample, left-justified in the sample width, to
+/// millivolts for an ADC with `resolution_bits` bits and a reference of
+/// `reference_mv` millivolts. The result is rounded to the nearest
+/// millivolt.
+///
+/// A raw value of `n` stands for `n * reference_mv / 2^resolution_bits`
+/// millivolts, so the full scale reads slightly below the reference.
+pub fn sample_to_mv(sample: u32, resolution_bits: usize, reference_mv: usize) -> usize {
+    let bits = resolution_bits.clamp(1, 32);
+    let raw = (sample >> (sample_width_bits(bits) - bits)) as u64;
+    ((raw * reference_mv as u64 + (1 << (bits - 1))) >> bits) as usize
+}
+
+/// Truncate a sample, left-justified in `sample_width_bits` bits, to its 16
+/// most significant bits, for clients working with 16-bit samples.
+pub fn sample_to_u16(sample: u32, sample_width_bits: usize) -> u16 {
+    (sample >> (sample_width_bits.clamp(16, 32) - 16)) as u16
+}
+
 /// Trait for handling callbacks from simple ADC calls.
 pub trait Client {
     /// Called when a sample is ready.
-    fn sample_ready(&self, sample: u16);
+    fn sample_ready(&self, sample: u32);
+
+    /// Called when a calibration started with `Adc::calibrate` completes.
+    fn calibration_done(&self, _result: Result<(), ErrorCode>) {}
+
+    /// Called when a single sample that was accepted, but queued, cannot be
+    /// started. No `sample_ready` follows.
+    fn sample_failed(&self, _error: ErrorCode) {}
+}
+
+/// Trait for handling the samples of periodic sampling, see
+/// `AdcChannel::sample_periodic`.
+pub trait PeriodicClient {
+    /// Called with the sample of a period. `id` is the identifier given to
+    /// `sample_periodic`.
+    fn periodic_sample_ready(&self, id: usize, sample: u32);
 }
 
 // *** Interfaces for high-speed, buffered ADC sampling ***
 
 /// Interface for continuously sampling at a given frequency on a channel.
-/// Requires the AdcSimple interface to have been implemented as well.
+/// Requires the AdcSimple interface to have been implemented as well, with
+/// 16-bit samples.
 pub trait AdcHighSpeed<'a>: Adc<'a> {
     /// Start sampling continuously into buffers.
     /// Samples are double-buffered, going first into `buffer1` and then into
@@ -102,6 +331,15 @@ pub trait AdcHighSpeed<'a>: Adc<'a> {
         &self,
     ) -> Result<(Option<&'static mut [u16]>, Option<&'static mut [u16]>), ErrorCode>;
 
+    /// Return the frequency in Hz at which the ongoing, or last, continuous
+    /// or high-speed sampling operation actually samples. Because of the
+    /// resolution of the hardware clock dividers, it may differ from the
+    /// frequency requested by the client.
+    ///
+    /// Returns `None` if no sampling operation has been configured, or if the
+    /// implementation cannot determine the achieved frequency.
+    fn get_sampling_frequency_hz(&self) -> Option<u32>;
+
     fn set_highspeed_client(&self, client: &'a dyn HighSpeedClient);
 }
 
@@ -117,7 +355,8 @@ pub trait HighSpeedClient {
 pub trait AdcChannel<'a> {
     /// Request a single ADC sample on a particular channel.
     /// Used for individual samples that have no timing requirements.
-    /// All ADC samples will be the raw ADC value left-justified in the u16.
+    /// All ADC samples will be the raw ADC value left-justified in the
+    /// sample width.
     fn sample(&self) -> Result<(), ErrorCode>;
 
     /// Request repeated ADC samples on a particular channel.
@@ -125,7 +364,8 @@ pub trait AdcChannel<'a> {
     /// set to any frequency supported by the chip implementation. However
     /// callbacks may be limited based on how quickly the system can service
     /// individual samples, leading to missed samples at high frequencies.
-    /// All ADC samples will be the raw ADC value left-justified in the u16.
+    /// All ADC samples will be the raw ADC value left-justified in the
+    /// sample width.
     fn sample_continuous(&self) -> Result<(), ErrorCode>;
 
     /// Stop a sampling operation.
@@ -137,6 +377,12 @@ pub trait AdcChannel<'a> {
     /// it is returning.
     fn get_resolution_bits(&self) -> usize;
 
+    /// The number of bits the samples are left-justified in, see
+    /// `Adc::get_sample_width_bits`.
+    fn get_sample_width_bits(&self) -> usize {
+        sample_width_bits(self.get_resolution_bits())
+    }
+
     /// Function to ask the ADC what reference voltage it used when taking the
     /// samples. This allows the user of this interface to calculate an actual
     /// voltage from the ADC reading.
@@ -144,5 +390,155 @@ pub trait AdcChannel<'a> {
     /// The returned reference voltage is in millivolts, or `None` if unknown.
     fn get_voltage_reference_mv(&self) -> Option<usize>;
 
+    /// Whether the channel samples the difference between two inputs, in
+    /// which case the samples are signed (see `Adc::sample_differential`).
+    fn is_differential(&self) -> bool {
+        false
+    }
+
+    /// Request `length` samples at `frequency` into `buffer`, with the
+    /// `HighSpeedClient` notified once when the buffer is filled. The
+    /// sampling may be delayed until the ADC is available.
+    ///
+    /// If the operation is stopped or cannot be started after this call
+    /// returned, the buffer is given back to the client with a length of 0.
+    /// Only supported by channels with 16-bit samples.
+    ///
+    /// All ADC samples will be the raw ADC value left-justified in the u16.
+    fn sample_buffer(
+        &self,
+        _frequency: u32,
+        buffer: &'static mut [u16],
+        _length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u16])> {
+        Err((ErrorCode::NOSUPPORT, buffer))
+    }
+
+    /// Set the resolution of the conversions, see `Adc::set_resolution_bits`.
+    /// The resolution is usually shared by all the channels of the ADC.
+    fn set_resolution_bits(&self, bits: usize) -> Result<(), ErrorCode> {
+        if bits == self.get_resolution_bits() {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    /// Set the gain and voltage reference of the channel, see
+    /// `Adc::set_channel_gain_and_reference`. The voltage reference reported
+    /// by `get_voltage_reference_mv` is then the full-scale voltage of the
+    /// channel.
+    fn set_gain_and_reference(&self, _gain: Gain, _reference: Reference) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Convert a single-ended sample of this channel to millivolts, see
+    /// `Adc::sample_to_mv`.
+    fn sample_to_mv(&self, sample: u32) -> Option<usize> {
+        self.get_voltage_reference_mv()
+            .map(|reference_mv| sample_to_mv(sample, self.get_resolution_bits(), reference_mv))
+    }
+
+    /// Sample the channel every `interval_ms` milliseconds, with the
+    /// `PeriodicClient` called with `id` and the sample of each period, until
+    /// `stop_periodic` is called. `stop_sampling` does not stop it. Calling
+    /// it again changes the interval. A period is skipped if the channel is
+    /// still busy with another operation.
+    fn sample_periodic(&self, _interval_ms: u32, _id: usize) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Stop the periodic sampling started by `sample_periodic`, without
+    /// stopping the other operations of the channel.
+    fn stop_periodic(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
     fn set_client(&self, client: &'a dyn Client);
+
+    fn set_highspeed_client(&self, _client: &'a dyn HighSpeedClient) {}
+
+    fn set_periodic_client(&self, _client: &'a dyn PeriodicClient) {}
+}
+
+// *** Interface for hardware-triggered sampling ***
+
+/// Interface for sampling a group of channels each time a hardware event
+/// occurs, such as a timer compare match or the end of a PWM period.
+///
+/// Conversions start without software involvement, so they are phase-locked
+/// to the peripheral generating the event. Motor control, for example, samples
+/// the phase currents in the middle of each PWM period. Requires the Adc
+/// interface to have been implemented as well, with 16-bit samples.
+pub trait AdcTriggered<'a>: Adc<'a> {
+    /// The chip-dependent type of a hardware trigger source.
+    type Trigger;
+
+    /// Return the maximum number of channels in a group.
+    fn max_group_len(&self) -> usize;
+
+    /// Convert `channels`, in order, each time `trigger` occurs, until
+    /// `stop_sampling` is called. The client receives one sample per channel
+    /// after each trigger event.
+    ///
+    /// All ADC samples will be the raw ADC value left-justified in the u16.
+    ///
+    /// The valid `ErrorCode`s that can occur are:
+    ///
+    /// - `BUSY`: the ADC is already sampling.
+    /// - `SIZE`: `channels` is empty or longer than `max_group_len()`.
+    /// - `INVAL`: one of the channels cannot be used in a triggered group.
+    /// - `NOSUPPORT`: the trigger is not supported by the implementation.
+    fn sample_triggered(
+        &self,
+        channels: &[Self::Channel],
+        trigger: &Self::Trigger,
+    ) -> Result<(), ErrorCode>;
+
+    fn set_triggered_client(&self, client: &'a dyn TriggeredClient);
+}
+
+/// Trait for handling callbacks from hardware-triggered ADC sampling.
+pub trait TriggeredClient {
+    /// Called after each trigger event with one sample per channel of the
+    /// group, in the order the channels were given.
+    fn samples_triggered(&self, samples: &[u16]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sample_to_mv, sample_width_bits, Gain};
+
+    #[test]
+    fn sample_to_mv_rounds() {
+        // 12-bit ADC, 3300 mV reference: 1 LSB = 0.806 mV
+        assert_eq!(sample_to_mv(0, 12, 3300), 0);
+        assert_eq!(sample_to_mv(1 << 4, 12, 3300), 1);
+        assert_eq!(sample_to_mv(2048 << 4, 12, 3300), 1650);
+        assert_eq!(sample_to_mv(0xFFF0, 12, 3300), 3299);
+        // Bits below the resolution are ignored.
+        assert_eq!(sample_to_mv(0xFFFF, 12, 3300), 3299);
+        assert_eq!(sample_to_mv(0x8000, 16, 1000), 500);
+    }
+
+    #[test]
+    fn sample_to_mv_32_bit_samples() {
+        assert_eq!(sample_width_bits(12), 16);
+        assert_eq!(sample_width_bits(24), 32);
+        // 24-bit ADC, 2500 mV reference, left-justified in the u32.
+        assert_eq!(sample_to_mv(0, 24, 2500), 0);
+        assert_eq!(sample_to_mv(0x8000_0000, 24, 2500), 1250);
+        assert_eq!(sample_to_mv(0xFFFF_FF00, 24, 2500), 2500);
+        assert_eq!(sample_to_mv(0xFFFF_FFFF, 32, 1000), 1000);
+    }
+
+    #[test]
+    fn gain_full_scale() {
+        // nRF52: 0.6 V internal reference with a gain of 1/6.
+        assert_eq!(Gain::new(1, 6).full_scale_mv(600), 3600);
+        assert_eq!(Gain::new(2, 1).full_scale_mv(600), 300);
+        assert!(Gain::new(2, 12).matches(1, 6));
+        assert!(!Gain::new(1, 5).matches(1, 6));
+        assert!(!Gain::new(1, 0).matches(1, 0));
+    }
 }
@@ -0,0 +1,207 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for signaling failures to the user, with blink or beep
+//! patterns.
+//!
+//! Each class of failure has its own [`Pattern`], configured by the board in
+//! an [`AlertPatterns`] table. Patterns are played on [`AlertOutput`]s, which
+//! are switched on and off synchronously: LEDs ([`AlertLed`]), buzzers driven
+//! by a PWM ([`AlertPwm`], [`AlertPwmPin`]), or other outputs implemented by
+//! capsules. As outputs are synchronous, the panic handler can play patterns
+//! on them without interrupts, while capsules play them with an [`Alert`]
+//! implementation based on an alarm.
+
+use crate::hil::{led, pwm};
+use crate::ErrorCode;
+
+/// Classes of failures, signaled with distinct patterns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AlertClass {
+    /// The kernel panicked.
+    Panic,
+    /// A process faulted.
+    ProcessFault,
+    /// The watchdog was about to expire, or reset the chip.
+    Watchdog,
+    /// The supply voltage or the battery is low.
+    LowPower,
+    /// A peripheral or a sensor stopped responding.
+    Peripheral,
+}
+
+/// A blink or beep pattern: the output is on for the first duration, off for
+/// the second one, and so on. Patterns with an odd number of durations end
+/// with the output on for the last one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    /// Durations of the steps, in milliseconds.
+    pub durations_ms: &'static [u16],
+    /// Frequency of the tone of buzzers, in Hz.
+    pub tone_hz: u32,
+}
+
+impl Pattern {
+    pub const fn new(durations_ms: &'static [u16], tone_hz: u32) -> Pattern {
+        Pattern {
+            durations_ms,
+            tone_hz,
+        }
+    }
+
+    /// Duration of the whole pattern, in milliseconds.
+    pub fn duration_ms(&self) -> u32 {
+        self.durations_ms.iter().map(|&ms| ms as u32).sum()
+    }
+}
+
+/// The pattern of each class of failure, configured by the board.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AlertPatterns {
+    pub panic: Pattern,
+    pub process_fault: Pattern,
+    pub watchdog: Pattern,
+    pub low_power: Pattern,
+    pub peripheral: Pattern,
+}
+
+impl AlertPatterns {
+    pub const fn get(&self, class: AlertClass) -> Pattern {
+        match class {
+            AlertClass::Panic => self.panic,
+            AlertClass::ProcessFault => self.process_fault,
+            AlertClass::Watchdog => self.watchdog,
+            AlertClass::LowPower => self.low_power,
+            AlertClass::Peripheral => self.peripheral,
+        }
+    }
+}
+
+/// Patterns of boards that do not configure theirs: the panic pattern is
+/// the blink of `debug::panic_blink_forever`, and the other classes blink
+/// once to four times.
+pub const DEFAULT_PATTERNS: AlertPatterns = AlertPatterns {
+    panic: Pattern::new(&[250, 25, 250, 125], 2000),
+    process_fault: Pattern::new(&[100, 900], 1000),
+    watchdog: Pattern::new(&[100, 100, 100, 700], 1500),
+    low_power: Pattern::new(&[100, 100, 100, 100, 100, 500], 500),
+    peripheral: Pattern::new(&[100, 100, 100, 100, 100, 100, 100, 300], 800),
+};
+
+/// An output switched on and off to play patterns.
+///
+/// Switching is synchronous, so that outputs can be used by the panic
+/// handler.
+pub trait AlertOutput {
+    /// Initialize the output. Must be called before the output is used.
+    fn init(&self);
+
+    /// Switch the output on. Buzzers play a tone at `tone_hz`.
+    fn on(&self, tone_hz: u32);
+
+    /// Switch the output off.
+    fn off(&self);
+}
+
+/// Interface to play the pattern of a class of failure on outputs.
+pub trait Alert<'a> {
+    /// Play the pattern of `class` `count` times, or until `stop` is called
+    /// if `count` is 0. A new alert replaces the alert being played, which
+    /// ends without calling the client.
+    fn alert(&self, class: AlertClass, count: usize) -> Result<(), ErrorCode>;
+
+    /// Stop the alert being played.
+    ///
+    /// Return `OFF` if no alert is being played.
+    fn stop(&self) -> Result<(), ErrorCode>;
+
+    fn set_client(&self, client: &'a dyn AlertClient);
+}
+
+pub trait AlertClient {
+    /// The alert of `class` was played the requested number of times, or
+    /// stopped.
+    fn alert_done(&self, class: AlertClass);
+}
+
+/// An LED used as an alert output.
+pub struct AlertLed<'a, L: led::Led> {
+    led: &'a L,
+}
+
+impl<'a, L: led::Led> AlertLed<'a, L> {
+    pub fn new(led: &'a L) -> Self {
+        Self { led }
+    }
+}
+
+impl<L: led::Led> AlertOutput for AlertLed<'_, L> {
+    fn init(&self) {
+        self.led.init();
+    }
+
+    fn on(&self, _tone_hz: u32) {
+        self.led.on();
+    }
+
+    fn off(&self) {
+        self.led.off();
+    }
+}
+
+/// A buzzer on a pin of a PWM controller, used as an alert output. The
+/// controller is used directly, so this output is meant for the panic
+/// handler.
+pub struct AlertPwm<'a, P: pwm::Pwm> {
+    pwm: &'a P,
+    pin: &'a P::Pin,
+}
+
+impl<'a, P: pwm::Pwm> AlertPwm<'a, P> {
+    pub fn new(pwm: &'a P, pin: &'a P::Pin) -> Self {
+        Self { pwm, pin }
+    }
+}
+
+impl<P: pwm::Pwm> AlertOutput for AlertPwm<'_, P> {
+    fn init(&self) {}
+
+    fn on(&self, tone_hz: u32) {
+        let _ = self.pwm.start(
+            self.pin,
+            tone_hz as usize,
+            self.pwm.get_maximum_duty_cycle() / 2,
+        );
+    }
+
+    fn off(&self) {
+        let _ = self.pwm.stop(self.pin);
+    }
+}
+
+/// A buzzer on a PWM pin, such as a virtualized PWM pin, used as an alert
+/// output.
+pub struct AlertPwmPin<'a, P: pwm::PwmPin> {
+    pin: &'a P,
+}
+
+impl<'a, P: pwm::PwmPin> AlertPwmPin<'a, P> {
+    pub fn new(pin: &'a P) -> Self {
+        Self { pin }
+    }
+}
+
+impl<P: pwm::PwmPin> AlertOutput for AlertPwmPin<'_, P> {
+    fn init(&self) {}
+
+    fn on(&self, tone_hz: u32) {
+        let _ = self
+            .pin
+            .start(tone_hz as usize, self.pin.get_maximum_duty_cycle() / 2);
+    }
+
+    fn off(&self) {
+        let _ = self.pin.stop();
+    }
+}
@@ -0,0 +1,88 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for digital audio capture.
+//!
+//! An `AudioSource` is a peripheral (typically a PDM or I2S microphone
+//! interface) that produces a continuous stream of signed 16-bit PCM samples
+//! at a fixed sample rate. Like `hil::adc::AdcHighSpeed`, samples are
+//! double-buffered: the source fills one buffer while the client processes
+//! the other and hands it back with `provide_buffer`.
+//!
+//! A `TdmAudioSource` captures several channels at once, as time-division
+//! multiplexed (TDM) frames of up to [`MAX_TDM_SLOTS`] slots. Its buffers hold
+//! whole frames with the channels interleaved.
+
+use crate::ErrorCode;
+
+/// Continuous capture of PCM audio samples.
+pub trait AudioSource<'a> {
+    /// Sample rate of the PCM stream in Hz.
+    fn sample_rate(&self) -> u32;
+
+    /// Start capturing into buffers.
+    ///
+    /// Samples go first into `buffer1` and then into `buffer2`. The client's
+    /// `samples_ready` is called whenever a buffer is full, and the client is
+    /// expected to give a buffer back with `provide_buffer`. If an error
+    /// occurs, the buffers are returned.
+    fn start(
+        &self,
+        buffer1: &'static mut [i16],
+        buffer2: &'static mut [i16],
+    ) -> Result<(), (ErrorCode, &'static mut [i16], &'static mut [i16])>;
+
+    /// Provide a buffer to fill with the ongoing capture.
+    ///
+    /// If no buffer is available when the current one is full, samples are
+    /// dropped until a buffer is provided.
+    fn provide_buffer(
+        &self,
+        buffer: &'static mut [i16],
+    ) -> Result<(), (ErrorCode, &'static mut [i16])>;
+
+    /// Stop capturing. No further callbacks will occur.
+    fn stop(&self) -> Result<(), ErrorCode>;
+
+    /// Reclaim ownership of the buffers held by the source.
+    ///
+    /// Can only be called when the source is stopped.
+    fn retrieve_buffers(
+        &self,
+    ) -> Result<(Option<&'static mut [i16]>, Option<&'static mut [i16]>), ErrorCode>;
+
+    fn set_client(&self, client: &'a dyn AudioClient);
+}
+
+/// Trait for handling callbacks from an `AudioSource`.
+pub trait AudioClient {
+    /// Called when a buffer is full. `length` is the number of valid samples
+    /// in `buffer`.
+    fn samples_ready(&self, buffer: &'static mut [i16], length: usize);
+}
+
+/// Largest number of slots in a TDM frame.
+pub const MAX_TDM_SLOTS: usize = 8;
+
+/// Continuous capture of multi-channel PCM audio in TDM frames.
+///
+/// Each frame holds one sample for every slot (channel). Samples are stored
+/// in buffers frame after frame, with the slots interleaved: slot 0 of frame
+/// 0, slot 1 of frame 0, ..., slot 0 of frame 1, and so on. The `length`
+/// passed to `AudioClient::samples_ready` is always a whole number of frames,
+/// and `sample_rate` is the frame rate.
+pub trait TdmAudioSource<'a>: AudioSource<'a> {
+    /// Number of slots in each frame.
+    fn slots(&self) -> usize;
+
+    /// Set the number of slots in each frame, from 1 to [`MAX_TDM_SLOTS`].
+    ///
+    /// Can only be called when the source is stopped.
+    ///
+    /// The possible ErrorCodes are:
+    ///     - `BUSY`: The source is capturing.
+    ///     - `INVAL`: `slots` is 0 or larger than `MAX_TDM_SLOTS`.
+    ///     - `NOSUPPORT`: The source does not support this number of slots.
+    fn set_slots(&self, slots: usize) -> Result<(), ErrorCode>;
+}
@@ -0,0 +1,43 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for editing persistent boot settings.
+//!
+//! A boot configuration is a small set of `key=value` settings (console baud
+//! rate, default radio channel, ...) kept in nonvolatile storage and read by
+//! the board when it boots. This interface lets kernel components, such as
+//! the process console, inspect and change the settings at runtime. Changes
+//! are saved immediately but only take effect the next time the board boots.
+
+use crate::ErrorCode;
+
+pub trait BootConfig {
+    /// Number of settings. Settings cannot be listed while they are being
+    /// saved.
+    fn count(&self) -> usize;
+
+    /// Call `f` with the key and value of the setting at `index`. Returns
+    /// `false` if there is no such setting.
+    fn with_entry(&self, index: usize, f: &mut dyn FnMut(&str, &str)) -> bool;
+
+    /// Copy the value of `key` into `value` and return its length.
+    ///
+    /// Returns `FAIL` if the key is not set, `SIZE` if `value` is too short,
+    /// and `BUSY` if the settings are being saved.
+    fn get(&self, key: &str, value: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Set `key` to `value`, replacing any previous value, and save the
+    /// settings.
+    ///
+    /// Returns `INVAL` if the key or value is malformed, `SIZE` if the
+    /// settings do not fit in storage, and `BUSY` if the previous change is
+    /// still being saved.
+    fn set(&self, key: &str, value: &str) -> Result<(), ErrorCode>;
+
+    /// Remove `key` and save the settings.
+    ///
+    /// Returns `FAIL` if the key is not set, and `BUSY` if the previous
+    /// change is still being saved.
+    fn unset(&self, key: &str) -> Result<(), ErrorCode>;
+}
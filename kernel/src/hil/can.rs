@@ -26,6 +26,9 @@
 //! The `Filter` trait is used to configure filter banks for receiving
 //! messages. The action is synchronous.
 //!
+//! The `Diagnostics` trait reports the error counters of the peripheral,
+//! its fault confinement state and the last error detected on the bus.
+//!
 //! The `Transmit` trait is used to asynchronously send a message on
 //! the CAN bus. The device must be previously enabled. The
 //! `TransmitClient` trait is used to notify the capsule when the
@@ -37,6 +40,16 @@
 //! when a message was received, when the receiving process was aborted
 //! and anytime an error occurs.
 //!
+//! Peripherals supporting CAN FD implement `Transmit` and `Receive` with
+//! `FD_CAN_PACKET_SIZE` buffers, and `ConfigureFd` for the bit timing of
+//! the data phase. The format of each frame, classic or FD, and whether
+//! its data phase switches to the payload bit rate, is given by its
+//! `FrameFlags`.
+//!
+//! Remote frames (RTR), which request the data of an identifier from the
+//! node transmitting it, are also sent and reported with `FrameFlags`. They
+//! carry no payload: their length is the length of the requested data.
+//!
 
 use crate::ErrorCode;
 use core::cmp;
@@ -126,6 +139,43 @@ impl From<Error> for ErrorCode {
     }
 }
 
+/// The fault confinement state of the peripheral, given by its transmit
+/// and receive error counters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorState {
+    /// Both error counters are below the warning limit (96).
+    Active = 0,
+
+    /// An error counter reached the warning limit (96).
+    Warning = 1,
+
+    /// An error counter is greater than 127: the peripheral only signals
+    /// errors with passive error flags.
+    Passive = 2,
+
+    /// The transmit error counter is greater than 255: the peripheral is
+    /// disconnected from the bus.
+    BusOff = 3,
+}
+
+/// The health of the bus, as seen by the peripheral.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BusStatus {
+    /// The transmit error counter (TEC).
+    pub transmit_error_count: u16,
+
+    /// The receive error counter (REC).
+    pub receive_error_count: u16,
+
+    /// The fault confinement state.
+    pub state: ErrorState,
+
+    /// The last error detected on the bus, if any: one of `Error::Stuff`,
+    /// `Error::Form`, `Error::Ack`, `Error::BitRecessive`,
+    /// `Error::BitDominant`, `Error::Crc` and `Error::SetBySoftware`.
+    pub last_error: Option<Error>,
+}
+
 /// The Scale Bits structure defines the 2 possible widths
 /// of the filter bank
 #[derive(Debug, Copy, Clone)]
@@ -145,12 +195,111 @@ pub enum IdentifierMode {
 }
 
 /// The identifier can be standard (11 bits) or extended (29 bits)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Id {
     Standard(u16),
     Extended(u32),
 }
 
+/// The format of a frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FrameFlags {
+    /// The frame is a CAN FD frame, with up to `FD_CAN_PACKET_SIZE` bytes of
+    /// payload.
+    pub fd: bool,
+
+    /// The data phase of the CAN FD frame is transmitted with the payload
+    /// bit timing (bit rate switch, BRS).
+    pub bit_rate_switch: bool,
+
+    /// The frame is a classic remote frame (RTR), without payload. CAN FD
+    /// has no remote frames.
+    pub remote: bool,
+}
+
+impl FrameFlags {
+    /// A classic CAN frame.
+    pub const CLASSIC: FrameFlags = FrameFlags {
+        fd: false,
+        bit_rate_switch: false,
+        remote: false,
+    };
+
+    /// A CAN FD frame transmitted entirely at the nominal bit rate.
+    pub const FD: FrameFlags = FrameFlags {
+        fd: true,
+        bit_rate_switch: false,
+        remote: false,
+    };
+
+    /// A CAN FD frame with its data phase at the payload bit rate.
+    pub const FD_BRS: FrameFlags = FrameFlags {
+        fd: true,
+        bit_rate_switch: true,
+        remote: false,
+    };
+
+    /// A classic remote frame.
+    pub const REMOTE: FrameFlags = FrameFlags {
+        fd: false,
+        bit_rate_switch: false,
+        remote: true,
+    };
+}
+
+/// The payload lengths of CAN FD frames above 8 bytes, for the data length
+/// codes 9 to 15.
+const FD_LENGTHS: [usize; 7] = [12, 16, 20, 24, 32, 48, 64];
+
+/// Returns the payload length of a frame with the data length code `dlc`.
+/// Classic frames carry at most 8 bytes, whatever their code.
+pub const fn dlc_to_len(dlc: u8, fd: bool) -> usize {
+    let dlc = (dlc & 0xF) as usize;
+    if dlc <= STANDARD_CAN_PACKET_SIZE {
+        dlc
+    } else if fd {
+        FD_LENGTHS[dlc - 9]
+    } else {
+        STANDARD_CAN_PACKET_SIZE
+    }
+}
+
+/// Returns the data length code of a frame carrying `len` bytes, or `None`
+/// if no frame has this exact length. CAN FD payloads longer than 8 bytes
+/// must be padded to the next valid length (see `fd_padded_len`).
+pub const fn len_to_dlc(len: usize, fd: bool) -> Option<u8> {
+    if len <= STANDARD_CAN_PACKET_SIZE {
+        return Some(len as u8);
+    }
+    if !fd {
+        return None;
+    }
+    let mut i = 0;
+    while i < FD_LENGTHS.len() {
+        if FD_LENGTHS[i] == len {
+            return Some(9 + i as u8);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns the smallest CAN FD payload length holding `len` bytes, or
+/// `None` if `len` is above `FD_CAN_PACKET_SIZE`.
+pub const fn fd_padded_len(len: usize) -> Option<usize> {
+    if len <= STANDARD_CAN_PACKET_SIZE {
+        return Some(len);
+    }
+    let mut i = 0;
+    while i < FD_LENGTHS.len() {
+        if FD_LENGTHS[i] >= len {
+            return Some(FD_LENGTHS[i]);
+        }
+        i += 1;
+    }
+    None
+}
+
 /// This structure defines the parameters to configure a filter bank
 #[derive(Copy, Clone)]
 pub struct FilterParameters {
@@ -168,6 +317,17 @@ pub struct FilterParameters {
 
     /// The receive FIFO Id that the filter will be applied to
     pub fifo_number: usize,
+
+    /// In `Mask` mode, the identifier the masked identifiers of the
+    /// messages are compared with. In `List` mode, the first identifier
+    /// accepted by the filter.
+    pub id: Id,
+
+    /// In `Mask` mode, the bits of the identifiers compared with `id`,
+    /// which only accepts messages with the same format as `id`; a mask of
+    /// `0` accepts every message. In `List` mode, the second identifier
+    /// accepted by the filter, with the same format as `id`.
+    pub mask: u32,
 }
 
 /// This structure defines the parameters for the timing mode
@@ -215,6 +375,12 @@ pub enum OperationMode {
     /// Normal mode means that the transmission and reception of frames
     /// are available
     Normal,
+
+    /// Silent loopback mode combines the loopback and the monitoring modes:
+    /// each message is received on the RX channel, while the peripheral only
+    /// sends recessive bits on the bus. It tests the peripheral without
+    /// affecting the bus
+    SilentLoopback,
 }
 
 /// The `StandardBitTiming` trait is used to calculate the optimum timing parameters
@@ -486,6 +652,36 @@ pub trait Configure {
     ///                      request cannot be completed
     fn get_wake_up(&self) -> Result<bool, ErrorCode>;
 
+    /// Configures how the CAN peripheral leaves the bus-off state. With the
+    /// automatic recovery, the peripheral goes back on the bus by itself once
+    /// it observed the bus idle (128 sequences of 11 recessive bits). Without
+    /// it, the peripheral stays bus-off until `Controller::restart` is called.
+    /// This function is optional, but if used, must be called before the
+    /// `enable` function. This function is synchronous as the driver should
+    /// only store the arguments, and should not configure the hardware.
+    ///
+    /// # Arguments:
+    ///
+    /// * `automatic` - Value to configure the automatic bus-off recovery
+    ///                 setting
+    ///
+    /// # Return values:
+    ///
+    /// * `Ok()` - The setting was stored.
+    /// * `Err(ErrorCode)` - Indicates the error because of which the request
+    ///                      cannot be completed
+    fn set_automatic_bus_off_recovery(&self, automatic: bool) -> Result<(), ErrorCode>;
+
+    /// Returns the current automatic bus-off recovery setting of the
+    /// peripheral.
+    ///
+    /// # Return values:
+    ///
+    /// * `Ok(bool)` - The current automatic bus-off recovery setting
+    /// * `Err(ErrorCode)` - Indicates the error because of which the
+    ///                      request cannot be completed
+    fn get_automatic_bus_off_recovery(&self) -> Result<bool, ErrorCode>;
+
     /// Returns the number of receive FIFOs the peripheral provides
     fn receive_fifo_count(&self) -> usize;
 }
@@ -543,8 +739,10 @@ pub trait ConfigureFd: Configure {
 
 /// The `Filter` trait is used to enable and disable a filter bank.
 ///
-/// When the receiving process starts by calling the `start_receiving_process`
-/// in the `Receive` trait, there MUST be no filter enabled.
+/// When the receiving process starts by calling the `start_receive_process`
+/// in the `Receive` trait with no filter enabled, all the messages are
+/// received. Otherwise, only the messages accepted by the enabled filters
+/// are received. Filters can be enabled and disabled while receiving.
 pub trait Filter {
     /// Enables a filter for message reception.
     ///
@@ -558,6 +756,9 @@ pub trait Filter {
     /// * `Ok()` - The filter was successfully configured.
     /// * `Err(ErrorCode)` - indicates the error because of which the
     ///                      request cannot be completed
+    ///                    - `ErrorCode::INVAL` indicates that the filter
+    ///                      bank does not exist or that the identifiers
+    ///                      cannot be matched by the filter bank
     fn enable_filter(&self, filter: FilterParameters) -> Result<(), ErrorCode>;
 
     /// Disables a filter.
@@ -572,12 +773,29 @@ pub trait Filter {
     /// * `Ok()` - The filter was successfully disabled.
     /// * `Err(ErrorCode)` - indicates the error because of which the
     ///                      request cannot be completed
+    ///                    - `ErrorCode::ALREADY` indicates that the filter
+    ///                      was not enabled
     fn disable_filter(&self, number: u32) -> Result<(), ErrorCode>;
 
     /// Returns the number of filters the peripheral provides
     fn filter_count(&self) -> usize;
 }
 
+/// The `Diagnostics` trait is used to read the health of the bus.
+pub trait Diagnostics {
+    /// Returns the error counters and the fault confinement state of the
+    /// peripheral, and the last error it detected.
+    ///
+    /// # Return values:
+    ///
+    /// * `Ok(BusStatus)` - The current status of the peripheral
+    /// * `Err(ErrorCode)` - Indicates the error because of which the
+    ///                      request cannot be completed
+    ///                    - `ErrorCode::OFF` indicates that the peripheral
+    ///                      is not powered
+    fn bus_status(&self) -> Result<BusStatus, ErrorCode>;
+}
+
 /// The `Controller` trait is used to enable and disable the CAN peripheral.
 /// The enable process applies the settings that were previously provided
 /// to the driver using the `Configure` trait.
@@ -635,6 +853,24 @@ pub trait Controller {
     /// * `Err(ErrorCode)` - The driver cannot report the state of the peripheral
     ///                      if it is not functional.
     fn get_state(&self) -> Result<State, ErrorCode>;
+
+    /// This function restarts the CAN peripheral after it entered the bus-off
+    /// state, reported by the `state_changed` callback with
+    /// `State::Error(Error::BusOff)`. The peripheral goes back on the bus
+    /// once it observed the bus idle (128 sequences of 11 recessive bits),
+    /// and frames sent in the meantime wait for it.
+    ///
+    /// # Return values:
+    ///
+    /// * `Ok()` - The peripheral was restarted. The driver will call the
+    ///            `state_changed` callback with `State::Running`.
+    /// * `Err(ErrorCode)` - Indicates the error because of which the
+    ///                      request cannot be completed.
+    ///     * `ErrorCode::ALREADY` - the peripheral is not bus-off
+    ///     * `ErrorCode::OFF` - the peripheral is not enabled
+    ///     * `ErrorCode::BUSY` - another request is being completed
+    ///     * `ErrorCode::FAIL` - the peripheral did not restart
+    fn restart(&self) -> Result<(), ErrorCode>;
 }
 
 /// The `Transmit` trait is used to interact with the CAN driver through transmission
@@ -651,6 +887,10 @@ pub trait Transmit<const PACKET_SIZE: usize> {
     /// In most cases, this function should be called after the peripheral was
     /// previously configures and at least one filter has been enabled.
     ///
+    /// Drivers with several transmit mailboxes may accept new frames before
+    /// the previous ones are sent. Each buffer is returned through its own
+    /// `transmit_complete` call, not necessarily in the order of the requests.
+    ///
     /// # Arguments:
     ///
     /// * `id` - The identifier of the message (standard or extended)
@@ -670,6 +910,29 @@ pub trait Transmit<const PACKET_SIZE: usize> {
         buffer: &'static mut [u8; PACKET_SIZE],
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8; PACKET_SIZE])>;
+
+    /// Sends a buffer using the CAN bus, in the format given by `flags`.
+    ///
+    /// The length of a CAN FD frame above 8 bytes must be one of the
+    /// lengths of the CAN FD data length codes (see `len_to_dlc`). The
+    /// content of the buffer of a remote frame is not sent, and its length
+    /// is the length of the requested data.
+    ///
+    /// The default implementation sends classic data frames with `send`, and
+    /// returns `ErrorCode::NOSUPPORT` for CAN FD and remote frames.
+    fn send_with_flags(
+        &self,
+        id: Id,
+        buffer: &'static mut [u8; PACKET_SIZE],
+        len: usize,
+        flags: FrameFlags,
+    ) -> Result<(), (ErrorCode, &'static mut [u8; PACKET_SIZE])> {
+        if flags.fd || flags.remote {
+            Err((ErrorCode::NOSUPPORT, buffer))
+        } else {
+            self.send(id, buffer, len)
+        }
+    }
 }
 
 /// The `Receive` trait is used to interact with the CAN driver through receive
@@ -684,9 +947,9 @@ pub trait Receive<const PACKET_SIZE: usize> {
     /// Start receiving messaged on the CAN bus.
     ///
     /// In most cases, this function should be called after the peripheral was
-    /// previously configured. When calling this function, there MUST be
-    /// no filters enabled by the user. The implementation of this function
-    /// MUST permit receiving frames on all available receiving FIFOs.
+    /// previously configured. If no filter was enabled by the user with the
+    /// `Filter` trait, the implementation of this function MUST permit
+    /// receiving frames on all available receiving FIFOs.
     ///
     /// # Arguments:
     ///
@@ -722,6 +985,12 @@ pub trait ControllerClient {
     /// The driver calls this function when the state of the CAN peripheral is
     /// changed.
     ///
+    /// When the peripheral goes bus-off, the frames waiting to be transmitted
+    /// are dropped and the driver calls this function with
+    /// `State::Error(Error::BusOff)`. It calls this function with
+    /// `State::Running` once the peripheral is back on the bus, after an
+    /// automatic recovery or a call to `Controller::restart`.
+    ///
     /// # Arguments:
     ///
     /// * `state` - The current state of the peripheral
@@ -793,6 +1062,29 @@ pub trait ReceiveClient<const PACKET_SIZE: usize> {
         status: Result<(), Error>,
     );
 
+    /// The driver calls this function instead of `message_received` when
+    /// it reports the format of the received frame, as drivers supporting
+    /// CAN FD or remote frames do. For a remote frame, `len` is the length
+    /// of the requested data, and the buffer holds no data. The default
+    /// implementation ignores the format and calls `message_received`.
+    ///
+    /// # Arguments:
+    ///
+    /// * `flags` - The format of the received frame
+    ///
+    /// The other arguments are those of `message_received`.
+    fn message_received_with_flags(
+        &self,
+        id: Id,
+        buffer: &mut [u8; PACKET_SIZE],
+        len: usize,
+        flags: FrameFlags,
+        status: Result<(), Error>,
+    ) {
+        let _ = flags;
+        self.message_received(id, buffer, len, status);
+    }
+
     /// The driver calls this function when the reception of messages has been stopped.
     ///
     /// # Arguments:
@@ -809,8 +1101,10 @@ pub trait Can:
 {
 }
 
+/// Convenience type for capsules that configure, send
+/// and receive CAN FD frames using the CAN peripheral
 pub trait CanFd:
-    Transmit<FD_CAN_PACKET_SIZE> + Configure + ConfigureFd + Receive<FD_CAN_PACKET_SIZE>
+    Transmit<FD_CAN_PACKET_SIZE> + Configure + ConfigureFd + Controller + Receive<FD_CAN_PACKET_SIZE>
 {
 }
 
@@ -825,7 +1119,39 @@ impl<
 }
 
 /// Provide blanket implementation for CanFd trait group
-impl<T: Transmit<FD_CAN_PACKET_SIZE> + Configure + ConfigureFd + Receive<FD_CAN_PACKET_SIZE>> CanFd
-    for T
+impl<
+        T: Transmit<FD_CAN_PACKET_SIZE>
+            + Configure
+            + ConfigureFd
+            + Controller
+            + Receive<FD_CAN_PACKET_SIZE>,
+    > CanFd for T
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_length_codes() {
+        assert_eq!(dlc_to_len(8, false), 8);
+        assert_eq!(dlc_to_len(15, false), 8);
+        assert_eq!(dlc_to_len(9, true), 12);
+        assert_eq!(dlc_to_len(15, true), 64);
+        for dlc in 0..16 {
+            assert_eq!(len_to_dlc(dlc_to_len(dlc, true), true), Some(dlc));
+        }
+        assert_eq!(len_to_dlc(12, false), None);
+        assert_eq!(len_to_dlc(13, true), None);
+    }
+
+    #[test]
+    fn fd_padding() {
+        assert_eq!(fd_padded_len(5), Some(5));
+        assert_eq!(fd_padded_len(9), Some(12));
+        assert_eq!(fd_padded_len(33), Some(48));
+        assert_eq!(fd_padded_len(64), Some(64));
+        assert_eq!(fd_padded_len(65), None);
+    }
+}
@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for communication between the cores of multi-core chips.
+//!
+//! A [`Mailbox`] sends 32-bit words to another core, and notifies its
+//! [`MailboxClient`] of the words received from it, for example through the
+//! inter-processor FIFOs of the RP2040. A [`HwSemaphore`] provides locks
+//! shared by all the cores, such as the spinlocks of the RP2040 or the HSEM
+//! peripheral of the STM32H7, to protect memory or peripherals accessed by
+//! several cores.
+//!
+//! Words are the building block of higher-level protocols: they can carry a
+//! command, or the address of a message in memory shared between the cores
+//! and protected by a semaphore.
+
+use crate::ErrorCode;
+
+/// A one-way channel of words to another core, and the channel back from it.
+pub trait Mailbox<'a> {
+    /// Send `word` to the other core.
+    ///
+    /// Return `BUSY` if the mailbox is full because the other core has not
+    /// read the previous words yet.
+    fn send(&self, word: u32) -> Result<(), ErrorCode>;
+
+    /// Whether `send` would accept a word.
+    fn can_send(&self) -> bool;
+
+    /// Set the client notified of the words received from the other core.
+    fn set_client(&self, client: &'a dyn MailboxClient);
+}
+
+pub trait MailboxClient {
+    /// A word was received from the other core, in the order they were sent.
+    fn received(&self, word: u32);
+}
+
+/// A set of hardware semaphores, shared by all the cores.
+///
+/// Taking a semaphore is atomic across the cores, so at most one core holds
+/// each semaphore. Semaphores are identified by their index, from `0` to
+/// `count() - 1`.
+pub trait HwSemaphore {
+    /// The number of semaphores.
+    fn count(&self) -> usize;
+
+    /// Take the semaphore `index` without waiting.
+    ///
+    /// Return `BUSY` if a core holds the semaphore, and `INVAL` if `index`
+    /// is out of range.
+    fn try_lock(&self, index: usize) -> Result<(), ErrorCode>;
+
+    /// Release the semaphore `index`, which must be held by this core.
+    ///
+    /// Return `ALREADY` if the semaphore is not held, and `INVAL` if `index`
+    /// is out of range.
+    fn unlock(&self, index: usize) -> Result<(), ErrorCode>;
+
+    /// Whether a core holds the semaphore `index`.
+    fn is_locked(&self, index: usize) -> bool;
+}
@@ -0,0 +1,49 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for timestamping edges on an input pin.
+//!
+//! Input capture latches the value of a free-running hardware counter when
+//! an edge occurs on a pin, so the timing of the edge does not depend on the
+//! interrupt latency. It is used to measure frequencies and pulse widths, or
+//! to decode protocols such as IR remotes.
+
+use crate::ErrorCode;
+
+/// The edges that trigger a capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Capture the counter value on the edges of a single input.
+pub trait InputCapture<'a> {
+    /// Start capturing on the given edges. Every capture is reported with
+    /// `Client::captured`, until `stop` is called.
+    ///
+    /// Returns `BUSY` if the underlying counter is used in a way that is
+    /// incompatible with input capture.
+    fn start(&self, edge: Edge) -> Result<(), ErrorCode>;
+
+    /// Stop capturing.
+    fn stop(&self) -> Result<(), ErrorCode>;
+
+    /// Frequency in Hz of the counter whose values are captured.
+    fn frequency_hz(&self) -> u32;
+
+    /// Width in bits of the counter. Captured values wrap at `2^width`.
+    fn width(&self) -> u32;
+
+    fn set_client(&self, client: &'a dyn Client);
+}
+
+pub trait Client {
+    /// An edge occurred and the counter was `value` at that time.
+    ///
+    /// `overcapture` is `true` if at least one earlier capture was lost
+    /// because it was not handled before this one.
+    fn captured(&self, value: u32, overcapture: bool);
+}
@@ -0,0 +1,74 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for reading interrupt latency measurements.
+//!
+//! The latency of an interrupt is the time from the entry of its hardware
+//! handler to the end of its processing by the kernel, when the driver
+//! handling it returns. Chips that can timestamp their interrupts implement
+//! [`InterruptLatency`] so that the process console `irqs` command can
+//! report the measurements, for example to validate real-time behavior.
+
+/// Number of buckets of a latency histogram.
+pub const HISTOGRAM_BUCKETS: usize = 8;
+
+/// Upper bound, in cycles, of the first histogram bucket. Each following
+/// bucket is four times larger, and the last one has no upper bound.
+pub const FIRST_BUCKET_CYCLES: u32 = 256;
+
+/// Latency statistics of one interrupt, in CPU cycles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of measured interrupts.
+    pub count: u32,
+    /// Longest latency.
+    pub max: u32,
+    /// Sum of the latencies, to compute the mean.
+    pub total: u64,
+    /// Number of latencies in each bucket.
+    pub histogram: [u32; HISTOGRAM_BUCKETS],
+}
+
+impl LatencyStats {
+    /// Index of the histogram bucket of `cycles`.
+    pub fn bucket(cycles: u32) -> usize {
+        let mut bucket = 0;
+        let mut limit = FIRST_BUCKET_CYCLES as u64;
+        while bucket < HISTOGRAM_BUCKETS - 1 && cycles as u64 >= limit {
+            bucket += 1;
+            limit *= 4;
+        }
+        bucket
+    }
+
+    /// Add a measurement.
+    pub fn record(&mut self, cycles: u32) {
+        self.count = self.count.wrapping_add(1);
+        self.max = self.max.max(cycles);
+        self.total = self.total.wrapping_add(cycles as u64);
+        let bucket = &mut self.histogram[Self::bucket(cycles)];
+        *bucket = bucket.wrapping_add(1);
+    }
+
+    /// Mean latency, or 0 if nothing was measured.
+    pub fn mean(&self) -> u32 {
+        if self.count == 0 {
+            0
+        } else {
+            (self.total / self.count as u64) as u32
+        }
+    }
+}
+
+/// Interrupt latency measurements of a chip.
+pub trait InterruptLatency {
+    /// Number of interrupts with measurements.
+    fn count(&self) -> usize;
+
+    /// Interrupt number and statistics of the `index`-th measured interrupt.
+    fn get(&self, index: usize) -> Option<(u32, LatencyStats)>;
+
+    /// Clear all the measurements.
+    fn reset(&self);
+}
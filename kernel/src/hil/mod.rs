@@ -5,8 +5,11 @@
 //! Public traits for interfaces between Tock components.
 
 pub mod adc;
+pub mod alert;
 pub mod analog_comparator;
+pub mod audio;
 pub mod ble_advertising;
+pub mod boot_config;
 pub mod bus8080;
 pub mod buzzer;
 pub mod can;
@@ -21,11 +24,18 @@ pub mod gpio;
 pub mod gpio_async;
 pub mod hasher;
 pub mod hw_debug;
+pub mod hw_mailbox;
 pub mod i2c;
+pub mod input_capture;
+pub mod interrupt_latency;
 pub mod kv;
 pub mod led;
 pub mod log;
+pub mod motor_control;
+pub mod network_statistics;
+pub mod neural_network;
 pub mod nonvolatile_storage;
+pub mod power;
 pub mod public_key_crypto;
 pub mod pwm;
 pub mod radio;
@@ -40,6 +50,8 @@ pub mod touch;
 pub mod uart;
 pub mod usb;
 pub mod usb_hid;
+pub mod wakeup_source;
+pub mod xip;
 
 /// Shared interface for configuring components.
 pub trait Controller {
@@ -0,0 +1,56 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for motor control: synchronized multi-phase PWM output and
+//! position encoders.
+//!
+//! Together with hardware-triggered ADC sampling (see
+//! `hil::adc::AdcTriggered`), they provide what a field-oriented control loop
+//! needs: phase currents sampled at a fixed point of the PWM period, the rotor
+//! position, and outputs updated once per period.
+
+use crate::ErrorCode;
+
+/// Center-aligned PWM driving the phases of a motor through a power stage.
+///
+/// All phases share one counter, so their pulses are centered on the same
+/// instant. New duty cycles are buffered by the hardware and take effect
+/// together at the start of the next period.
+pub trait PhasePwm {
+    /// Return the number of phases driven by the PWM.
+    fn phase_count(&self) -> usize;
+
+    /// Start generating center-aligned PWM at `frequency_hz`, with all duty
+    /// cycles at 50%.
+    ///
+    /// Any trigger output used to sample the phase currents (for example a
+    /// timer update event in the middle of the period) is enabled as well.
+    fn start(&self, frequency_hz: usize) -> Result<(), ErrorCode>;
+
+    /// Set the duty cycle of each phase, as a portion of
+    /// `get_maximum_duty_cycle()`. `duty_cycles` must have one entry per
+    /// phase.
+    fn set_duty_cycles(&self, duty_cycles: &[usize]) -> Result<(), ErrorCode>;
+
+    /// Stop the outputs, leaving the power stage off.
+    fn stop(&self) -> Result<(), ErrorCode>;
+
+    /// Return the maximum PWM frequency supported by the implementation, in
+    /// Hertz.
+    fn get_maximum_frequency_hz(&self) -> usize;
+
+    /// Return the value that represents a 100% duty cycle.
+    fn get_maximum_duty_cycle(&self) -> usize;
+}
+
+/// Incremental position encoder, for example a quadrature encoder counted by
+/// a timer.
+pub trait Encoder {
+    /// Return the current position, in counts. The position wraps to 0 after
+    /// `counts_per_revolution() - 1`.
+    fn position(&self) -> u32;
+
+    /// Return the number of counts in one mechanical revolution.
+    fn counts_per_revolution(&self) -> u32;
+}
@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for reading the traffic and error counters of network
+//! interfaces.
+//!
+//! Network drivers (802.15.4 radios, CAN controllers, Ethernet MACs, ...)
+//! implement [`NetworkStatistics`] so that the same diagnostics can be
+//! reported for every interface of a board, for example by the process
+//! console `netstat` command.
+
+/// Counters of a network interface.
+///
+/// All counters wrap around on overflow.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Frames transmitted successfully.
+    pub tx_frames: u32,
+    /// Frames received successfully.
+    pub rx_frames: u32,
+    /// Frames that could not be queued for transmission.
+    pub tx_dropped: u32,
+    /// Frames received but discarded, for example because no buffer was
+    /// available.
+    pub rx_dropped: u32,
+    /// Frames whose transmission failed.
+    pub tx_errors: u32,
+    /// Frames received with errors.
+    pub rx_errors: u32,
+}
+
+/// Index of each counter, as used by the userspace driver.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counter {
+    TxFrames = 0,
+    RxFrames = 1,
+    TxDropped = 2,
+    RxDropped = 3,
+    TxErrors = 4,
+    RxErrors = 5,
+}
+
+impl Counter {
+    pub fn from_index(index: usize) -> Option<Counter> {
+        match index {
+            0 => Some(Counter::TxFrames),
+            1 => Some(Counter::RxFrames),
+            2 => Some(Counter::TxDropped),
+            3 => Some(Counter::RxDropped),
+            4 => Some(Counter::TxErrors),
+            5 => Some(Counter::RxErrors),
+            _ => None,
+        }
+    }
+}
+
+impl Counters {
+    /// Value of a single counter.
+    pub fn get(&self, counter: Counter) -> u32 {
+        match counter {
+            Counter::TxFrames => self.tx_frames,
+            Counter::RxFrames => self.rx_frames,
+            Counter::TxDropped => self.tx_dropped,
+            Counter::RxDropped => self.rx_dropped,
+            Counter::TxErrors => self.tx_errors,
+            Counter::RxErrors => self.rx_errors,
+        }
+    }
+
+    /// Increment a single counter.
+    pub fn increment(&mut self, counter: Counter) {
+        let value = match counter {
+            Counter::TxFrames => &mut self.tx_frames,
+            Counter::RxFrames => &mut self.rx_frames,
+            Counter::TxDropped => &mut self.tx_dropped,
+            Counter::RxDropped => &mut self.rx_dropped,
+            Counter::TxErrors => &mut self.tx_errors,
+            Counter::RxErrors => &mut self.rx_errors,
+        };
+        *value = value.wrapping_add(1);
+    }
+}
+
+/// Statistics of a network interface.
+pub trait NetworkStatistics {
+    /// Short name of the interface, for example `"can1"`.
+    fn interface_name(&self) -> &'static str;
+
+    /// Current value of the counters.
+    fn counters(&self) -> Counters;
+
+    /// Reset all the counters to zero.
+    fn reset_counters(&self);
+}
@@ -0,0 +1,57 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for neural network compute kernels.
+//!
+//! These kernels run quantized (int8) neural network layers on the CPU,
+//! possibly using optimized libraries such as CMSIS-NN on Cortex-M. They
+//! follow the TensorFlow Lite for Microcontrollers quantization scheme.
+//!
+//! Calls are synchronous, so callers should keep the amount of work per call
+//! small (for example by computing a few output rows at a time) to avoid
+//! blocking the kernel loop.
+//!
+//! Tensors are passed as byte slices: `int8` tensors store one value per
+//! byte, and `int32` tensors store little-endian values.
+
+use crate::ErrorCode;
+
+/// Quantization parameters of a fully connected layer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FullyConnectedParams {
+    /// Negated zero point of the input tensor.
+    pub input_offset: i32,
+    /// Zero point of the output tensor.
+    pub output_offset: i32,
+    /// Fixed point (Q31) multiplier used to requantize the accumulators.
+    pub multiplier: i32,
+    /// Shift used to requantize the accumulators, positive to the left.
+    pub shift: i32,
+    /// Minimum value of the output, after requantization.
+    pub activation_min: i32,
+    /// Maximum value of the output, after requantization.
+    pub activation_max: i32,
+}
+
+pub trait NeuralNetworkKernels {
+    /// Compute a fully connected layer with `int8` input, weights and output
+    /// and `int32` bias.
+    ///
+    /// `weights` contains one row of `input.len()` weights for each output,
+    /// and `bias` one value for each output.
+    ///
+    /// Return values:
+    /// - `Ok(())`: the output was computed.
+    /// - `SIZE`: the lengths of the tensors do not match.
+    /// - `INVAL`: the tensors do not satisfy the alignment requirements of the
+    ///   implementation.
+    fn fully_connected_s8(
+        &self,
+        params: &FullyConnectedParams,
+        input: &[u8],
+        weights: &[u8],
+        bias: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), ErrorCode>;
+}
@@ -0,0 +1,44 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interfaces for switching peripheral stacks off and on at runtime.
+//!
+//! A power domain groups the peripherals of a stack (for example the radio,
+//! or the ADC and its clock) that are switched off together to reach a lower
+//! sleep current. The drivers using these peripherals are dependents of the
+//! domain: they are asked before the domain is switched off, and told when it
+//! is switched back on.
+
+use crate::ErrorCode;
+
+/// A group of peripherals that can be switched off and on as a whole.
+pub trait PowerDomain {
+    /// Switch the domain off, gating its clocks and, where the hardware
+    /// allows it, its supply.
+    ///
+    /// Returns `ALREADY` if the domain is already off.
+    fn power_off(&self) -> Result<(), ErrorCode>;
+
+    /// Switch the domain back on. Peripherals may have lost their
+    /// configuration and must be reconfigured by their drivers.
+    ///
+    /// Returns `ALREADY` if the domain is already on.
+    fn power_on(&self) -> Result<(), ErrorCode>;
+
+    /// Whether the domain is on.
+    fn is_powered(&self) -> bool;
+}
+
+/// A driver using the peripherals of a power domain.
+pub trait PowerDependent {
+    /// The domain is about to be switched off. The driver must not start new
+    /// operations until `powered_on` is called.
+    ///
+    /// Returns `BUSY` to keep the domain on, for example because an
+    /// operation is in progress.
+    fn powering_off(&self) -> Result<(), ErrorCode>;
+
+    /// The domain was switched back on, or switching it off was canceled.
+    fn powered_on(&self);
+}
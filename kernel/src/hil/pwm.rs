@@ -68,3 +68,28 @@ pub trait PwmPin {
     /// Same as the `get_maximum_duty_cycle` function in the `Pwm` trait.
     fn get_maximum_duty_cycle(&self) -> usize;
 }
+
+/// Generation of a single pulse on a pin, after a delay.
+///
+/// The pulse is timed by hardware, so its delay and width do not depend on
+/// the interrupt latency.
+pub trait OnePulse<'a> {
+    /// Drive the pin high `delay_us` microseconds from now, for `width_us`
+    /// microseconds. `OnePulseClient::pulse_done` is called once the pin is
+    /// low again.
+    ///
+    /// Returns `BUSY` if a pulse is in progress or the underlying timer is
+    /// used by other outputs, and `INVAL` if the delay or width cannot be
+    /// generated.
+    fn pulse(&self, delay_us: u32, width_us: u32) -> Result<(), ErrorCode>;
+
+    /// Abort a pulse in progress. No callback is issued.
+    fn cancel(&self) -> Result<(), ErrorCode>;
+
+    fn set_client(&self, client: &'a dyn OnePulseClient);
+}
+
+pub trait OnePulseClient {
+    /// The pulse requested with `OnePulse::pulse` is complete.
+    fn pulse_done(&self);
+}
@@ -29,6 +29,10 @@
 //!
 //! Configuration sets cause a `command_complete` callback unless noted
 //! otherwise.
+//!
+//! Screens backed by a framebuffer in memory may additionally provide
+//! `ScreenAccelerated`, which lets a 2D engine fill and copy rectangles
+//! instead of the CPU or the display bus.
 
 use crate::utilities::leasable_buffer::SubSliceMut;
 use crate::ErrorCode;
@@ -306,6 +310,58 @@ pub trait Screen<'a> {
     fn set_invert(&self, enabled: bool) -> Result<(), ErrorCode>;
 }
 
+/// Hardware-accelerated drawing operations.
+///
+/// Pixels use the current pixel format of the screen. Coordinates are in the
+/// current screen orientation. These operations are optional: users must be
+/// ready to fall back to `Screen::write` when they return `NOSUPPORT`.
+pub trait ScreenAccelerated<'a> {
+    /// Set the object to receive the completion callbacks.
+    fn set_client(&self, client: &'a dyn ScreenAcceleratedClient);
+
+    /// Fill a rectangle with a single color.
+    ///
+    /// `color` holds the bytes of one pixel, as they would appear in a write
+    /// buffer, read in little-endian order.
+    ///
+    /// Return values:
+    /// - `Ok(())`: The fill started, `fill_complete` will be called.
+    /// - `INVAL`: The rectangle does not fit in the screen.
+    /// - `NOSUPPORT`: The current pixel format cannot be accelerated.
+    /// - `BUSY`: Another operation is in progress.
+    /// - `OFF`: The screen is powered off.
+    fn fill(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: u32,
+    ) -> Result<(), ErrorCode>;
+
+    /// Copy the pixels from `buffer` to a rectangle of the screen.
+    ///
+    /// `buffer` holds `width * height` pixels, row after row. The buffer is
+    /// returned through `blit_complete`, or with the error if the copy could
+    /// not start.
+    ///
+    /// Return values:
+    /// - `Ok(())`: The copy started, `blit_complete` will be called.
+    /// - `INVAL`: The rectangle does not fit in the screen.
+    /// - `SIZE`: The buffer does not hold exactly `width * height` pixels.
+    /// - `NOSUPPORT`: The current pixel format cannot be accelerated.
+    /// - `BUSY`: Another operation is in progress.
+    /// - `OFF`: The screen is powered off.
+    fn blit(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
+}
+
 pub trait ScreenAdvanced<'a>: Screen<'a> + ScreenSetup<'a> {}
 // Provide blanket implementations for trait group
 impl<'a, T: Screen<'a> + ScreenSetup<'a>> ScreenAdvanced<'a> for T {}
@@ -329,3 +385,12 @@ pub trait ScreenClient {
     /// screen is ready.
     fn screen_is_ready(&self);
 }
+
+pub trait ScreenAcceleratedClient {
+    /// The screen will call this function when a fill has finished.
+    fn fill_complete(&self, result: Result<(), ErrorCode>);
+
+    /// The screen will call this function when a blit has finished, passing
+    /// back the buffer.
+    fn blit_complete(&self, buffer: SubSliceMut<'static, u8>, result: Result<(), ErrorCode>);
+}
@@ -21,10 +21,61 @@ pub trait TemperatureClient {
     fn callback(&self, value: Result<i32, ErrorCode>);
 }
 
+/// Access to the most recent die temperature, used to compensate other
+/// measurements (ADC readings, RTC crystal trim, ...) for temperature drift.
+///
+/// Drifts are expressed in parts per billion (ppb) of the quantity being
+/// compensated, and temperatures in hundredths of degrees centigrade.
+pub trait TemperatureCompensation {
+    /// The most recently measured die temperature, or `None` if no
+    /// measurement is available yet.
+    fn temperature(&self) -> Option<i32>;
+
+    /// Drift of a quantity with a linear temperature coefficient of
+    /// `ppm_per_degree` ppm/°C, relative to its value at `reference`.
+    fn linear_drift_ppb(&self, reference: i32, ppm_per_degree: i32) -> Option<i32> {
+        self.temperature().map(|temperature| {
+            // ppm/°C * hundredths of °C * 10 = ppb
+            ((temperature - reference) as i64 * ppm_per_degree as i64 * 10) as i32
+        })
+    }
+
+    /// Drift of a crystal with a parabolic temperature curve of
+    /// `ppb_per_degree2` ppb/°C² around its `turnover` temperature. Tuning
+    /// fork 32.768 kHz crystals typically have a coefficient of -34 ppb/°C²
+    /// and a turnover temperature of 25 °C.
+    fn parabolic_drift_ppb(&self, turnover: i32, ppb_per_degree2: i32) -> Option<i32> {
+        self.temperature().map(|temperature| {
+            let delta = (temperature - turnover) as i64;
+            (delta * delta * ppb_per_degree2 as i64 / 10_000) as i32
+        })
+    }
+
+    /// Remove a drift of `drift_ppb` from `value`.
+    fn compensate(&self, value: i32, drift_ppb: i32) -> i32 {
+        let value = value as i64;
+        (value - value * drift_ppb as i64 / 1_000_000_000) as i32
+    }
+}
+
 /// A basic interface for a humidity sensor
 pub trait HumidityDriver<'a> {
     fn set_client(&self, client: &'a dyn HumidityClient);
     fn read_humidity(&self) -> Result<(), ErrorCode>;
+
+    /// Switch the heater of the sensor on or off, to evaporate condensation
+    /// on the sensing element. Readings are too low while the sensor is hot.
+    /// `heater_done` is called once the heater is switched.
+    fn set_heater(&self, _enabled: bool) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Recover from condensation: heat the sensor for `duration_ms`, then
+    /// discard the readings taken while it cools down. `heater_done` is
+    /// called at the end of the sequence.
+    fn recover_condensation(&self, _duration_ms: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 /// Client for receiving humidity readings.
@@ -33,6 +84,10 @@ pub trait HumidityClient {
     ///
     /// - `value`: the most recently read humidity in hundredths of percent.
     fn callback(&self, value: usize);
+
+    /// Called when the heater was switched by `set_heater`, or when a
+    /// condensation recovery sequence completed.
+    fn heater_done(&self, _result: Result<(), ErrorCode>) {}
 }
 
 /// A basic interface for a Air Quality sensor
@@ -233,3 +288,73 @@ pub trait PressureClient {
     /// Returns the value in hPa.
     fn callback(&self, pressure: Result<u32, ErrorCode>);
 }
+
+/// A basic interface for a voltage sensor, such as a voltage divider behind
+/// an ADC.
+pub trait VoltageDriver<'a> {
+    /// Start a voltage reading.
+    ///
+    /// This function might return the following errors:
+    /// - `BUSY`: Indicates that a reading is already in progress.
+    /// - `NOSUPPORT`: Indicates that the voltage cannot be measured.
+    fn read_voltage(&self) -> Result<(), ErrorCode>;
+
+    /// Set the client
+    fn set_client(&self, client: &'a dyn VoltageClient);
+}
+
+pub trait VoltageClient {
+    /// Called when a voltage reading has completed.
+    ///
+    /// Returns the value in millivolts.
+    fn callback(&self, voltage: Result<i32, ErrorCode>);
+}
+
+/// A load cell, such as a strain gauge bridge behind an ADC.
+pub trait LoadCellDriver<'a> {
+    /// Start a reading of the load cell.
+    ///
+    /// This function might return the following errors:
+    /// - `BUSY`: Indicates that a reading is already in progress.
+    /// - `OFF`: The sensor is powered down.
+    fn read_load(&self) -> Result<(), ErrorCode>;
+
+    /// Set the client
+    fn set_client(&self, client: &'a dyn LoadCellClient);
+}
+
+pub trait LoadCellClient {
+    /// Called when a load cell reading has completed.
+    ///
+    /// Returns the raw, signed, conversion result. Its scale and offset
+    /// depend on the load cell and must be calibrated.
+    fn callback(&self, load: Result<i32, ErrorCode>);
+}
+
+/// An optical pulse sensor (photoplethysmograph), which samples the light of
+/// a red and an infrared LED reflected by the skin.
+pub trait PulseOximeterDriver<'a> {
+    /// Start sampling continuously. Sampling the sensor for other users
+    /// (for example processes) keeps going independently.
+    fn start_sampling(&self) -> Result<(), ErrorCode>;
+
+    /// Stop sampling for this client.
+    fn stop_sampling(&self) -> Result<(), ErrorCode>;
+
+    /// Number of red/infrared sample pairs per second.
+    fn sample_rate(&self) -> u32;
+
+    /// Set the client
+    fn set_client(&self, client: &'a dyn PulseOximeterClient);
+}
+
+pub trait PulseOximeterClient {
+    /// Called for each red/infrared sample pair, in order.
+    ///
+    /// Returns the raw ADC counts, which grow with the amount of light
+    /// received.
+    fn sample(&self, red: u32, infrared: u32);
+
+    /// Sampling stopped because of an error.
+    fn sampling_error(&self, error: ErrorCode);
+}
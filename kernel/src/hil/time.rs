@@ -191,6 +191,17 @@ pub trait ConvertTicks<T: Ticks> {
     /// rounding down any fractions. If the value overflows u32, `u32::MAX`
     /// is returned,
     fn ticks_to_us(&self, tick: T) -> u32;
+
+    /// Returns the number of ticks in the provided number of milliseconds,
+    /// rounding up any fractions, so that an alarm set with the result
+    /// never fires early. If the value overflows Ticks it returns
+    /// `Ticks::max_value()`.
+    fn ticks_from_ms_ceil(&self, ms: u32) -> T;
+
+    /// Returns the number of ticks in the provided number of microseconds,
+    /// rounding up any fractions. If the value overflows Ticks it returns
+    /// `Ticks::max_value()`.
+    fn ticks_from_us_ceil(&self, us: u32) -> T;
 }
 
 impl<T: Time + ?Sized> ConvertTicks<<T as Time>::Ticks> for T {
@@ -222,6 +233,103 @@ impl<T: Time + ?Sized> ConvertTicks<<T as Time>::Ticks> for T {
     fn ticks_to_us(&self, tick: <T as Time>::Ticks) -> u32 {
         tick.saturating_scale(1_000_000, <T as Time>::Frequency::frequency())
     }
+
+    #[inline]
+    fn ticks_from_ms_ceil(&self, ms: u32) -> <T as Time>::Ticks {
+        let frequency = <T as Time>::Frequency::frequency() as u64;
+        <T as Time>::Ticks::from_or_max(
+            checked_scale_ceil(ms as u64, frequency, 1_000).unwrap_or(u64::MAX),
+        )
+    }
+    #[inline]
+    fn ticks_from_us_ceil(&self, us: u32) -> <T as Time>::Ticks {
+        let frequency = <T as Time>::Frequency::frequency() as u64;
+        <T as Time>::Ticks::from_or_max(
+            checked_scale_ceil(us as u64, frequency, 1_000_000).unwrap_or(u64::MAX),
+        )
+    }
+}
+
+// *** Overflow-safe conversions between durations, ticks and frequencies ***
+//
+// These functions compute with 128-bit intermediate values, so they are
+// exact for any frequency, including those which are not powers of two, and
+// report overflows instead of wrapping around. They are `const`, so they can
+// also compute constants from a clock frequency.
+
+/// Scales `value` by `numerator / denominator`, rounding down. Returns `None`
+/// if `denominator` is zero or the result does not fit in a `u64`.
+pub const fn checked_scale(value: u64, numerator: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    let scaled = value as u128 * numerator as u128 / denominator as u128;
+    if scaled > u64::MAX as u128 {
+        None
+    } else {
+        Some(scaled as u64)
+    }
+}
+
+/// Scales `value` by `numerator / denominator`, rounding up. Returns `None`
+/// if `denominator` is zero or the result does not fit in a `u64`.
+pub const fn checked_scale_ceil(value: u64, numerator: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    let product = value as u128 * numerator as u128;
+    let scaled = product.div_ceil(denominator as u128);
+    if scaled > u64::MAX as u128 {
+        None
+    } else {
+        Some(scaled as u64)
+    }
+}
+
+/// Returns the number of ticks of a `frequency_hz` clock in `ms`
+/// milliseconds, rounding down, or `None` on overflow.
+pub const fn checked_ticks_from_ms(ms: u64, frequency_hz: u32) -> Option<u64> {
+    checked_scale(ms, frequency_hz as u64, 1_000)
+}
+
+/// Returns the number of ticks of a `frequency_hz` clock in `us`
+/// microseconds, rounding down, or `None` on overflow.
+pub const fn checked_ticks_from_us(us: u64, frequency_hz: u32) -> Option<u64> {
+    checked_scale(us, frequency_hz as u64, 1_000_000)
+}
+
+/// Returns the number of milliseconds in `ticks` ticks of a `frequency_hz`
+/// clock, rounding down, or `None` if the frequency is zero.
+pub const fn checked_ms_from_ticks(ticks: u64, frequency_hz: u32) -> Option<u64> {
+    checked_scale(ticks, 1_000, frequency_hz as u64)
+}
+
+/// Returns the number of microseconds in `ticks` ticks of a `frequency_hz`
+/// clock, rounding down, or `None` if the frequency is zero or the result
+/// overflows.
+pub const fn checked_us_from_ticks(ticks: u64, frequency_hz: u32) -> Option<u64> {
+    checked_scale(ticks, 1_000_000, frequency_hz as u64)
+}
+
+/// Converts `ticks` ticks of a `from_hz` clock into ticks of a `to_hz`
+/// clock, rounding down, or `None` if `from_hz` is zero or the result
+/// overflows.
+pub const fn checked_convert_ticks(ticks: u64, from_hz: u32, to_hz: u32) -> Option<u64> {
+    checked_scale(ticks, to_hz as u64, from_hz as u64)
+}
+
+/// Returns the integer divider of a `clock_hz` clock giving the frequency
+/// closest to `target_hz`, at least 1, or `None` if `target_hz` is zero.
+pub const fn divider_for_frequency(clock_hz: u32, target_hz: u32) -> Option<u32> {
+    if target_hz == 0 {
+        return None;
+    }
+    let divider = (clock_hz as u64 + target_hz as u64 / 2) / target_hz as u64;
+    if divider == 0 {
+        Some(1)
+    } else {
+        Some(divider as u32)
+    }
 }
 
 /// Represents a static moment in time, that does not change over
@@ -330,6 +438,27 @@ pub trait Alarm<'a>: Time {
     fn minimum_dt(&self) -> Self::Ticks;
 }
 
+/// An [`Alarm`] that re-arms itself at a fixed period.
+///
+/// A periodic alarm fires at `reference + period`, `reference + 2 * period`,
+/// and so on: each expiration is computed from the previous expiration, not
+/// from the time the callback ran, so latency in handling the callback does
+/// not accumulate into drift. Unlike a one-shot alarm, the alarm is still
+/// armed when [`AlarmClient::alarm`] is called. If the callback is delayed
+/// past the next expiration, the alarm fires again as soon as possible.
+///
+/// Calling [`Alarm::set_alarm`] turns the alarm back into a one-shot alarm
+/// and [`Alarm::disarm`] stops it.
+pub trait PeriodicAlarm<'a>: Alarm<'a> {
+    /// Start calling the callback every `period` ticks, with the first call
+    /// at `reference + period`. A `period` of zero is treated as one tick.
+    fn set_periodic_alarm(&self, reference: Self::Ticks, period: Self::Ticks);
+
+    /// Return the period of the alarm, or `None` if the alarm is not a
+    /// periodic alarm.
+    fn period(&self) -> Option<Self::Ticks>;
+}
+
 /// Callback handler for when a timer fires.
 pub trait TimerClient {
     fn timer(&self);
@@ -938,6 +1067,51 @@ mod tests {
         assert_eq!(us, u32::MAX);
     }
 
+    #[test]
+    fn test_ceil_conversions() {
+        // 32768 Hz: 1 ms is 32.768 ticks.
+        let t = Test32KHz32().ticks_from_ms(1);
+        assert_eq!(t.into_u32(), 32);
+        let t = Test32KHz32().ticks_from_ms_ceil(1);
+        assert_eq!(t.into_u32(), 33);
+        let t = Test32KHz32().ticks_from_us_ceil(1);
+        assert_eq!(t.into_u32(), 1);
+        let t = Test32KHz32().ticks_from_us_ceil(0);
+        assert_eq!(t.into_u32(), 0);
+        let t = Test32KHz32().ticks_from_ms_ceil(u32::MAX);
+        assert_eq!(t.into_u32(), u32::MAX);
+    }
+
+    #[test]
+    fn test_checked_conversions() {
+        assert_eq!(checked_ticks_from_ms(1_000, 32_768), Some(32_768));
+        assert_eq!(checked_ticks_from_us(1, 32_768), Some(0));
+        assert_eq!(checked_scale_ceil(1, 32_768, 1_000_000), Some(1));
+        // 2^64 - 1 ticks of a 1 MHz clock are 2^64 - 1 us, but not at 1 kHz.
+        assert_eq!(checked_us_from_ticks(u64::MAX, 1_000_000), Some(u64::MAX));
+        assert_eq!(checked_us_from_ticks(u64::MAX, 1_000), None);
+        assert_eq!(checked_ms_from_ticks(u64::MAX, 1_000), Some(u64::MAX));
+        assert_eq!(checked_ms_from_ticks(1, 0), None);
+        assert_eq!(
+            checked_convert_ticks(32_768, 32_768, 1_000_000),
+            Some(1_000_000)
+        );
+        assert_eq!(checked_convert_ticks(3, 3, 16_000_000), Some(16_000_000));
+        assert_eq!(divider_for_frequency(16_000_000, 3), Some(5_333_333));
+        assert_eq!(divider_for_frequency(1_000, 3_000), Some(1));
+        assert_eq!(divider_for_frequency(1_000, 0), None);
+    }
+
+    struct Test32KHz32();
+    impl Time for Test32KHz32 {
+        type Frequency = Freq32KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Self::Ticks {
+            0u32.into()
+        }
+    }
+
     #[test]
     fn test_dyn_object() {
         let time: &dyn Time<Frequency = Freq1KHz, Ticks = Ticks24> = &Test1KHz24();
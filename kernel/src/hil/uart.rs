@@ -26,6 +26,10 @@ pub enum Width {
     Six = 6,
     Seven = 7,
     Eight = 8,
+    /// Nine data bits, only transferred with `transmit_word` and
+    /// `receive_word`. Multi-drop buses use the ninth bit to mark address
+    /// words.
+    Nine = 9,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -37,6 +41,20 @@ pub struct Parameters {
     pub hw_flow_control: bool,
 }
 
+/// Parameters of the ISO 7816-3 smart card mode.
+#[derive(Copy, Clone, Debug)]
+pub struct SmartCardParameters {
+    /// Frequency of the clock provided to the card, in Hz.
+    pub clock_hz: u32,
+    /// Duration of a bit (elementary time unit), in card clock cycles: the
+    /// clock rate conversion factor F divided by the baud rate adjustment
+    /// factor D, 372 until the card and the reader negotiate otherwise.
+    pub etu_clocks: u32,
+    /// Extra guard time between two characters sent to the card, in bit
+    /// periods, as requested by the card in its answer to reset.
+    pub extra_guard_time: u8,
+}
+
 /// The type of error encountered during UART transaction.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Error {
@@ -302,6 +320,26 @@ pub trait ReceiveClient {
     );
 }
 
+/// Trait for UARTs that can drive an ISO 7816-3 smart card.
+///
+/// In smart card mode the UART provides the clock of the card, and exchanges
+/// characters of 8 data bits with even parity over the single, half-duplex,
+/// I/O line of the card. A receiver detecting a parity error requests the
+/// repetition of the character. Characters are transferred with
+/// `transmit_word` and `receive_word`, and the UART does not receive the
+/// characters it transmits.
+pub trait SmartCard {
+    /// Switch the UART to smart card mode and start the clock of the card.
+    /// The UART returns to normal operation when it is configured again with
+    /// `Configure::configure`.
+    ///
+    /// Returns the actual frequency of the clock, which the UART may not be
+    /// able to generate exactly, or
+    /// - INVAL: the clock frequency or the bit duration is 0.
+    /// - NOSUPPORT: the UART cannot generate a clock close to `clock_hz`.
+    fn configure_smartcard(&self, params: SmartCardParameters) -> Result<u32, ErrorCode>;
+}
+
 /// Trait that isn't required for basic UART operation, but provides useful
 /// abstractions that capsules may want to be able to leverage.
 ///
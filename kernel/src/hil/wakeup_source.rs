@@ -0,0 +1,51 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for sources that wake the chip from deep sleep.
+//!
+//! Regular GPIO interrupts often rely on peripherals that are stopped, or
+//! that keep high-frequency clocks running, in the deepest sleep modes. A
+//! wakeup source is a pin or an analog comparator that the chip monitors with
+//! its low-power detection logic (EXTI lines on STM32, the GPIO DETECT signal
+//! and LPCOMP on nRF52), so that the chip can stay in its deepest allowed
+//! sleep mode until an event occurs.
+//!
+//! A capsule arms a source once; the chip programs the detection hardware
+//! accordingly, and keeps it programmed across sleeps until the source is
+//! disarmed.
+
+use crate::ErrorCode;
+
+/// Event that wakes the chip.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WakeupEdge {
+    /// The pin goes high, or the analog input goes above the reference.
+    Rising,
+    /// The pin goes low, or the analog input goes below the reference.
+    Falling,
+    /// Either of the above.
+    Either,
+}
+
+pub trait WakeupSource<'a> {
+    /// Wake the chip and call the client when `edge` occurs. Arming a source
+    /// again replaces its edge.
+    ///
+    /// Returns `BUSY` if the detection hardware is used for something else,
+    /// and `NOSUPPORT` if the source cannot detect `edge`.
+    fn enable_wakeup(&self, edge: WakeupEdge) -> Result<(), ErrorCode>;
+
+    /// Stop waking the chip on this source.
+    fn disable_wakeup(&self);
+
+    /// Whether the source is armed.
+    fn is_wakeup_enabled(&self) -> bool;
+
+    fn set_wakeup_client(&self, client: &'a dyn WakeupClient);
+}
+
+pub trait WakeupClient {
+    /// The armed edge occurred. The source stays armed.
+    fn woken(&self);
+}
@@ -0,0 +1,35 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for mapping an external flash into the address space, to read
+//! it and execute code from it in place (XIP).
+//!
+//! Boards with little internal flash can store processes in an external,
+//! usually QSPI, flash. Once mapped, the flash region is read like internal
+//! flash, and can be given to the process loader next to the internal
+//! application flash with `kernel::process::load_processes_from_regions`.
+
+use crate::ErrorCode;
+
+/// Read-only, memory-mapped access to an external flash.
+pub trait ExecuteInPlace {
+    /// Size in bytes of the window of the address space the flash is mapped
+    /// into.
+    fn window_size(&self) -> usize;
+
+    /// Map `length` bytes of the flash, starting at `offset` in the flash,
+    /// at the start of the window, and return the mapped region.
+    ///
+    /// The region stays mapped until the chip resets, so it can hold process
+    /// code: it cannot be unmapped or moved. Writes to the flash through
+    /// other interfaces change the content of the region.
+    ///
+    /// Returns `ALREADY` if a region is mapped, `INVAL` if `offset` is not
+    /// aligned as the chip requires or the region does not fit in the
+    /// window, and `FAIL` if the flash did not become ready.
+    fn map(&self, offset: usize, length: usize) -> Result<&'static [u8], ErrorCode>;
+
+    /// The region mapped by `map`, if any.
+    fn mapped_region(&self) -> Option<&'static [u8]>;
+}
@@ -17,11 +17,65 @@
 use core::cell::Cell;
 
 use crate::capabilities::ProcessManagementCapability;
+use crate::config;
 use crate::kernel::Kernel;
 use crate::process;
 use crate::process::ProcessId;
+use crate::syscall::Syscall;
 use crate::utilities::cells::NumericCellExt;
 
+/// Number of drivers whose syscalls `KernelInfo::driver_syscall_stats()`
+/// counts at most, `0` if the counting is disabled.
+pub const SYSCALL_STATS_DRIVERS: usize = config::CONFIG.syscall_stats_drivers;
+
+/// The syscalls all processes made to a driver since boot, or since the
+/// counts were reset.
+#[derive(Copy, Clone, Debug)]
+pub struct DriverSyscallStats {
+    /// The driver number the processes called.
+    pub driver_num: usize,
+    /// Whether the board has a driver with this number. The syscalls to a
+    /// missing driver fail with `NODEVICE`.
+    pub registered: bool,
+    /// How many commands the processes called.
+    pub commands: usize,
+    /// How many read-write, read-only and userspace-readable allows the
+    /// processes called.
+    pub allows: usize,
+    /// How many subscribes the processes called.
+    pub subscribes: usize,
+    /// How many of these syscalls returned a failure.
+    pub failures: usize,
+}
+
+impl DriverSyscallStats {
+    pub(crate) fn new(driver_num: usize, registered: bool) -> DriverSyscallStats {
+        DriverSyscallStats {
+            driver_num,
+            registered,
+            commands: 0,
+            allows: 0,
+            subscribes: 0,
+            failures: 0,
+        }
+    }
+
+    /// Count a syscall to the driver.
+    pub(crate) fn count(&mut self, syscall: Syscall, success: bool) {
+        match syscall {
+            Syscall::Command { .. } => self.commands += 1,
+            Syscall::Subscribe { .. } => self.subscribes += 1,
+            Syscall::ReadWriteAllow { .. }
+            | Syscall::ReadOnlyAllow { .. }
+            | Syscall::UserspaceReadableAllow { .. } => self.allows += 1,
+            Syscall::Yield { .. } | Syscall::Memop { .. } | Syscall::Exit { .. } => return,
+        }
+        if !success {
+            self.failures += 1;
+        }
+    }
+}
+
 /// This struct provides the inspection functions.
 pub struct KernelInfo {
     kernel: &'static Kernel,
@@ -156,4 +210,20 @@ impl KernelInfo {
         });
         count.get()
     }
+
+    /// Returns the syscall counts of one of the drivers the processes
+    /// called, in the order of their first call. Returns `None` if `index` is
+    /// past the number of drivers counted, or if the counting is disabled.
+    pub fn driver_syscall_stats(
+        &self,
+        index: usize,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<DriverSyscallStats> {
+        self.kernel.driver_syscall_stats(index)
+    }
+
+    /// Clear the syscall counts of all drivers.
+    pub fn reset_driver_syscall_stats(&self, _capability: &dyn ProcessManagementCapability) {
+        self.kernel.reset_driver_syscall_stats();
+    }
 }
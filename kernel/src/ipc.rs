@@ -6,13 +6,19 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! A buffer shared with another process can also hold a single-producer,
+//! single-consumer ring buffer (see [`ring`]), for high-throughput streams
+//! between two processes. The kernel initializes the ring and notifies the
+//! peers, but the producer and the consumer append and remove data directly
+//! in the shared memory, without system calls.
 
 use crate::capabilities::MemoryAllocationCapability;
 use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use crate::kernel::Kernel;
 use crate::process;
 use crate::process::ProcessId;
-use crate::processbuffer::ReadableProcessBuffer;
+use crate::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use crate::syscall_driver::{CommandReturn, SyscallDriver};
 use crate::ErrorCode;
 
@@ -26,6 +32,57 @@ mod ro_allow {
     pub(super) const COUNT: u8 = 1;
 }
 
+/// Layout of the ring buffers shared between two processes.
+///
+/// The ring starts with a header of three little-endian `u32` words, followed
+/// by `capacity` bytes of data:
+///
+/// - `head`: number of bytes ever produced, only written by the producer.
+/// - `tail`: number of bytes ever consumed, only written by the consumer.
+/// - `capacity`: size of the data area, a power of two, written by the kernel
+///   when the ring is set up.
+///
+/// The counters wrap around, the ring holds `head - tail` bytes, and the
+/// byte counted `n` is stored at `HEADER_LEN + n % capacity`. The producer
+/// writes the data before updating `head`, and the consumer reads it before
+/// updating `tail`, so neither side ever waits for the other.
+pub mod ring {
+    /// Offset of the `head` counter.
+    pub const HEAD: usize = 0;
+    /// Offset of the `tail` counter.
+    pub const TAIL: usize = 4;
+    /// Offset of the capacity of the ring.
+    pub const CAPACITY: usize = 8;
+    /// Length of the header, the data follows it.
+    pub const HEADER_LEN: usize = 12;
+
+    /// The capacity of a ring in a buffer of `len` bytes: the largest power
+    /// of two fitting after the header, or `None` if the buffer is too small.
+    pub const fn capacity(len: usize) -> Option<u32> {
+        if len <= HEADER_LEN {
+            return None;
+        }
+        let data_len = len - HEADER_LEN;
+        let capacity = 1 << (usize::BITS - 1 - data_len.leading_zeros());
+        if capacity > u32::MAX as usize {
+            Some(1 << 31)
+        } else {
+            Some(capacity as u32)
+        }
+    }
+
+    /// The number of bytes in a ring with these counters, or `None` if the
+    /// counters are inconsistent with `capacity`.
+    pub const fn used(head: u32, tail: u32, capacity: u32) -> Option<u32> {
+        let used = head.wrapping_sub(tail);
+        if used > capacity {
+            None
+        } else {
+            Some(used)
+        }
+    }
+}
+
 /// Enum to mark which type of upcall is scheduled for the IPC mechanism.
 #[derive(Copy, Clone, Debug)]
 pub enum IPCUpcallType {
@@ -100,6 +157,92 @@ impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
             })
         })?
     }
+
+    /// Queue an IPC upcall of type `cb_type` from `processid` for the process
+    /// with index `target_id`.
+    fn notify(
+        &self,
+        target_id: usize,
+        processid: ProcessId,
+        cb_type: IPCUpcallType,
+    ) -> Result<(), ErrorCode> {
+        let other_process = self
+            .data
+            .kernel
+            .process_until(|p| match p.processid().index() {
+                Some(i) if i == target_id => Some(p.processid()),
+                _ => None,
+            });
+
+        other_process.map_or(Err(ErrorCode::INVAL), |otherapp| {
+            self.data
+                .kernel
+                .process_map_or(Err(ErrorCode::INVAL), otherapp, |target| {
+                    // `enqueue_task` does not provide information on whether the
+                    // recipient has set a non-null callback. It only reports
+                    // general failures, such as insufficient memory in the pending
+                    // tasks queue
+                    target.enqueue_task(process::Task::IPC((processid, cb_type)))
+                })
+        })
+    }
+
+    /// Initialize a ring in the buffer `processid` shares with the process
+    /// with index `target_id`, and return its capacity.
+    fn setup_ring(&self, target_id: usize, processid: ProcessId) -> Result<u32, ErrorCode> {
+        self.data
+            .enter(processid, |_, kernel_data| {
+                let buffer = kernel_data
+                    .get_readwrite_processbuffer(target_id)
+                    .map_err(ErrorCode::from)?;
+                // The counters are accessed as aligned words by the processes.
+                if buffer.ptr() as usize % 4 != 0 {
+                    return Err(ErrorCode::INVAL);
+                }
+                let capacity = ring::capacity(buffer.len()).ok_or(ErrorCode::SIZE)?;
+                buffer
+                    .mut_enter(|slice| {
+                        slice[ring::HEAD..ring::TAIL].copy_from_slice(&0u32.to_le_bytes());
+                        slice[ring::TAIL..ring::CAPACITY].copy_from_slice(&0u32.to_le_bytes());
+                        slice[ring::CAPACITY..ring::HEADER_LEN]
+                            .copy_from_slice(&capacity.to_le_bytes());
+                    })
+                    .map_err(ErrorCode::from)?;
+                Ok(capacity)
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
+
+    /// The number of bytes in the ring `producer` shares with `consumer`.
+    fn ring_used(&self, producer: ProcessId, consumer: ProcessId) -> Result<u32, ErrorCode> {
+        let consumer_id = consumer.index().ok_or(ErrorCode::INVAL)?;
+        self.data
+            .enter(producer, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(consumer_id)
+                    .and_then(|buffer| {
+                        buffer.enter(|slice| {
+                            let mut header = [0; ring::HEADER_LEN];
+                            slice
+                                .get(0..ring::HEADER_LEN)
+                                .ok_or(ErrorCode::SIZE)?
+                                .copy_to_slice(&mut header);
+                            let word = |offset: usize| {
+                                u32::from_le_bytes([
+                                    header[offset],
+                                    header[offset + 1],
+                                    header[offset + 2],
+                                    header[offset + 3],
+                                ])
+                            };
+                            ring::used(word(ring::HEAD), word(ring::TAIL), word(ring::CAPACITY))
+                                .ok_or(ErrorCode::FAIL)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::INVAL))
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
 }
 
 impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
@@ -124,6 +267,12 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
     /// - `3`: Notify a client with descriptor `target_id`, typically in response to a previous
     ///        notify from the client. Returns an error if `target_id` refers to an invalid client
     ///        or the notify fails to enqueue.
+    /// - `4`: Set up a ring buffer in the buffer shared with the process `target_id`, which
+    ///        consumes the data, and notify it. Returns the capacity of the ring, `SIZE` if the
+    ///        buffer is too small, or `INVAL` if it is not word-aligned.
+    /// - `5`: Notify the peer of the ring shared with the process `target_id`. The producer
+    ///        only wakes the consumer if the ring holds data. Returns the number of bytes in the
+    ///        ring, or `FAIL` if its counters are inconsistent.
     fn command(
         &self,
         command_number: usize,
@@ -171,68 +320,58 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
             2 =>
             /* Service notify */
             {
-                let cb_type = IPCUpcallType::Service;
-
-                let other_process =
-                    self.data
-                        .kernel
-                        .process_until(|p| match p.processid().index() {
-                            Some(i) if i == target_id => Some(p.processid()),
-                            _ => None,
-                        });
-
-                other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
-                    self.data.kernel.process_map_or(
-                        CommandReturn::failure(ErrorCode::INVAL),
-                        otherapp,
-                        |target| {
-                            let ret = target.enqueue_task(process::Task::IPC((processid, cb_type)));
-                            match ret {
-                                Ok(()) => CommandReturn::success(),
-                                Err(e) => {
-                                    // `enqueue_task` does not provide information on whether the
-                                    // recipient has set a non-null callback. It only reports
-                                    // general failures, such as insufficient memory in the pending
-                                    // tasks queue
-                                    CommandReturn::failure(e)
-                                }
-                            }
-                        },
-                    )
-                })
+                self.notify(target_id, processid, IPCUpcallType::Service)
+                    .into()
             }
             3 =>
             /* Client notify */
             {
-                let cb_type = IPCUpcallType::Client;
-
-                let other_process =
-                    self.data
-                        .kernel
-                        .process_until(|p| match p.processid().index() {
-                            Some(i) if i == target_id => Some(p.processid()),
-                            _ => None,
-                        });
-
-                other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
-                    self.data.kernel.process_map_or(
-                        CommandReturn::failure(ErrorCode::INVAL),
-                        otherapp,
-                        |target| {
-                            let ret = target.enqueue_task(process::Task::IPC((processid, cb_type)));
-                            match ret {
-                                Ok(()) => CommandReturn::success(),
-                                Err(e) => {
-                                    // `enqueue_task` does not provide information on whether the
-                                    // recipient has set a non-null callback. It only reports
-                                    // general failures, such as insufficient memory in the pending
-                                    // tasks queue
-                                    CommandReturn::failure(e)
-                                }
-                            }
-                        },
-                    )
-                })
+                self.notify(target_id, processid, IPCUpcallType::Client)
+                    .into()
+            }
+            4 =>
+            /* Ring setup */
+            {
+                match self.setup_ring(target_id, processid) {
+                    Ok(capacity) => {
+                        // Give the consumer access to the ring, and its
+                        // address.
+                        match self.notify(target_id, processid, IPCUpcallType::Client) {
+                            Ok(()) => CommandReturn::success_u32(capacity),
+                            Err(e) => CommandReturn::failure(e),
+                        }
+                    }
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+            5 =>
+            /* Ring notify */
+            {
+                let target = self
+                    .data
+                    .kernel
+                    .process_until(|p| match p.processid().index() {
+                        Some(i) if i == target_id => Some(p.processid()),
+                        _ => None,
+                    });
+                let Some(target) = target else {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                };
+                // The caller is the producer if it shares a ring with the
+                // target, the consumer otherwise.
+                let (used, wake) = match self.ring_used(processid, target) {
+                    Ok(used) => (used, used > 0),
+                    Err(_) => match self.ring_used(target, processid) {
+                        Ok(used) => (used, true),
+                        Err(e) => return CommandReturn::failure(e),
+                    },
+                };
+                if wake {
+                    if let Err(e) = self.notify(target_id, processid, IPCUpcallType::Client) {
+                        return CommandReturn::failure(e);
+                    }
+                }
+                CommandReturn::success_u32(used)
             }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -242,3 +381,24 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
         self.data.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ring;
+
+    #[test]
+    fn ring_capacity() {
+        assert_eq!(ring::capacity(ring::HEADER_LEN), None);
+        assert_eq!(ring::capacity(ring::HEADER_LEN + 1), Some(1));
+        assert_eq!(ring::capacity(ring::HEADER_LEN + 1024), Some(1024));
+        assert_eq!(ring::capacity(ring::HEADER_LEN + 1500), Some(1024));
+    }
+
+    #[test]
+    fn ring_used_wraps() {
+        assert_eq!(ring::used(10, 4, 16), Some(6));
+        // The head counter wrapped around.
+        assert_eq!(ring::used(2, u32::MAX - 1, 16), Some(4));
+        assert_eq!(ring::used(20, 0, 16), None);
+    }
+}
@@ -18,6 +18,7 @@ use crate::debug;
 use crate::deferred_call::DeferredCall;
 use crate::errorcode::ErrorCode;
 use crate::grant::{AllowRoSize, AllowRwSize, Grant, UpcallSize};
+use crate::introspection::DriverSyscallStats;
 use crate::ipc;
 use crate::memop;
 use crate::platform::chip::Chip;
@@ -27,14 +28,15 @@ use crate::platform::platform::KernelResources;
 use crate::platform::platform::{ProcessFault, SyscallDriverLookup, SyscallFilter};
 use crate::platform::scheduler_timer::SchedulerTimer;
 use crate::platform::watchdog::WatchDog;
-use crate::process::{self, ProcessId, Task};
+use crate::platform::yield_timer::{YieldTimer, YieldTimerClient};
+use crate::process::{self, FunctionCall, FunctionCallSource, ProcessId, Task};
 use crate::scheduler::{Scheduler, SchedulingDecision};
 use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
-use crate::syscall_driver::CommandReturn;
+use crate::syscall_driver::{CommandReturn, ABI_VERSION_COMMAND};
 use crate::upcall::{Upcall, UpcallId};
-use crate::utilities::cells::NumericCellExt;
+use crate::utilities::cells::{NumericCellExt, OptionalCell};
 
 /// Threshold in microseconds to consider a process's timeslice to be exhausted.
 /// That is, Tock will skip re-scheduling a process if its remaining timeslice
@@ -46,6 +48,10 @@ pub struct Kernel {
     /// This holds a pointer to the static array of Process pointers.
     processes: &'static [Option<&'static dyn process::Process>],
 
+    /// Syscall counts of the drivers called by processes, in the order of
+    /// their first call. Empty unless the counting is enabled.
+    driver_syscall_stats: [Cell<Option<DriverSyscallStats>>; config::CONFIG.syscall_stats_drivers],
+
     /// A counter which keeps track of how many process identifiers have been
     /// created. This is used to create new unique identifiers for processes.
     process_identifier_max: Cell<usize>,
@@ -60,6 +66,10 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+
+    /// Timer waking the processes whose `yield-wait-timeout` expired. Without
+    /// it, `yield-wait-timeout` returns immediately.
+    yield_timer: OptionalCell<&'static dyn YieldTimer>,
 }
 
 /// Represents the different outcomes when trying to allocate a grant region
@@ -91,9 +101,45 @@ impl Kernel {
     pub fn new(processes: &'static [Option<&'static dyn process::Process>]) -> Kernel {
         Kernel {
             processes,
+            driver_syscall_stats: core::array::from_fn(|_| Cell::new(None)),
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            yield_timer: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the timer used to implement `yield-wait-timeout`.
+    pub fn set_yield_timer(&'static self, yield_timer: &'static dyn YieldTimer) {
+        yield_timer.set_client(self);
+        self.yield_timer.set(yield_timer);
+    }
+
+    /// Count a syscall to a driver, with `registered` whether the board has
+    /// the driver and `rval` the value returned to the process.
+    fn record_driver_syscall(&self, syscall: Syscall, registered: bool, rval: &SyscallReturn) {
+        let Some(driver_num) = syscall.driver_number() else {
+            return;
+        };
+        for slot in self.driver_syscall_stats.iter() {
+            let mut stats = match slot.get() {
+                Some(stats) if stats.driver_num == driver_num => stats,
+                Some(_) => continue,
+                None => DriverSyscallStats::new(driver_num, registered),
+            };
+            stats.count(syscall, rval.is_success());
+            slot.set(Some(stats));
+            return;
+        }
+    }
+
+    pub(crate) fn driver_syscall_stats(&self, index: usize) -> Option<DriverSyscallStats> {
+        self.driver_syscall_stats.get(index).and_then(Cell::get)
+    }
+
+    pub(crate) fn reset_driver_syscall_stats(&self) {
+        for slot in self.driver_syscall_stats.iter() {
+            slot.set(None);
         }
     }
 
@@ -502,6 +548,10 @@ impl Kernel {
         // inform the scheduler.
         let mut return_reason = process::StoppedExecutingReason::NoWorkLeft;
 
+        // How much of the time used so far has already been charged to the
+        // driver whose upcall the process is executing.
+        let mut charged_us = 0;
+
         // Since the timeslice counts both the process's execution time and the
         // time spent in the kernel on behalf of the process (setting it up and
         // handling its syscalls), we intend to keep running the process until
@@ -509,7 +559,8 @@ impl Kernel {
         // no longer wants to execute this process or if it exceeds its
         // timeslice.
         loop {
-            let stop_running = match scheduler_timer.get_remaining_us() {
+            let remaining_us = scheduler_timer.get_remaining_us();
+            let stop_running = match remaining_us {
                 Some(us) => us <= MIN_QUANTA_THRESHOLD_US,
                 None => true,
             };
@@ -599,7 +650,39 @@ impl Kernel {
                     // If the process is yielded or hasn't been started it is
                     // waiting for a upcall. If there is a task scheduled for
                     // this process go ahead and set the process to execute it.
-                    match process.dequeue_task() {
+                    let task = process.dequeue_task();
+                    if task.is_some() {
+                        // The task wakes the process before the end of its
+                        // `yield-wait-timeout`, if any.
+                        if let Some(address) = self
+                            .yield_timer
+                            .and_then(|timer| timer.cancel(process.processid()))
+                        {
+                            // # Safety
+                            //
+                            // See the handling of `yield-no-wait`.
+                            unsafe {
+                                process.set_byte(address as *mut u8, 1);
+                            }
+                        }
+                    }
+                    if config::CONFIG.upcall_time_drivers != 0 {
+                        // The process either blocks or starts executing a new
+                        // upcall, so the current burst of execution ends.
+                        if let (Some(timeslice), Some(remaining)) = (timeslice_us, remaining_us) {
+                            process.debug_time_used(timeslice - remaining - charged_us);
+                            charged_us = timeslice - remaining;
+                        }
+                        let driver_num = match task {
+                            Some(Task::FunctionCall(FunctionCall {
+                                source: FunctionCallSource::Driver(upcall_id),
+                                ..
+                            })) => Some(upcall_id.driver_num),
+                            _ => None,
+                        };
+                        process.debug_upcall_started(driver_num);
+                    }
+                    match task {
                         None => break,
                         Some(cb) => match cb {
                             Task::FunctionCall(ccb) => {
@@ -670,6 +753,12 @@ impl Kernel {
             }
         });
 
+        if config::CONFIG.upcall_time_drivers != 0 {
+            if let Some(time_executed) = time_executed_us {
+                process.debug_time_used(time_executed.saturating_sub(charged_us));
+            }
+        }
+
         // Reset the scheduler timer in case it unconditionally triggers
         // interrupts upon expiration. We do not want it to expire while the
         // chip is sleeping, for example.
@@ -705,10 +794,7 @@ impl Kernel {
         // immediately (assuming the process has not already exhausted its
         // timeslice) allowing the process to decide how to handle the error.
         match syscall {
-            Syscall::Yield {
-                which: _,
-                address: _,
-            } => {} // Yield is not filterable.
+            Syscall::Yield { .. } => {} // Yield is not filterable.
             Syscall::Exit {
                 which: _,
                 completion_code: _,
@@ -752,12 +838,16 @@ impl Kernel {
                 }
                 process.set_syscall_return_value(rval);
             }
-            Syscall::Yield { which, address } => {
+            Syscall::Yield {
+                which,
+                address,
+                timeout,
+            } => {
                 if config::CONFIG.trace_syscalls {
                     debug!("[{:?}] yield. which: {}", process.processid(), which);
                 }
-                if which > (YieldCall::Wait as usize) {
-                    // Only 0 and 1 are valid, so this is not a valid yield
+                if which > (YieldCall::WaitTimeout as usize) {
+                    // Only 0, 1 and 2 are valid, so this is not a valid yield
                     // system call, Yield does not have a return value because
                     // it can push a function call onto the stack; just return
                     // control to the process.
@@ -780,7 +870,33 @@ impl Kernel {
                         process.set_byte(address, upcall_triggered);
                     }
                 }
-                let wait = which == (YieldCall::Wait as usize);
+                if which == (YieldCall::WaitTimeout as usize) && !process.has_tasks() {
+                    // Wake the process when the timeout expires, unless an
+                    // upcall arrives first. If there is no timer, or it cannot
+                    // track the process, the timeout expires immediately.
+                    let started = self.yield_timer.map_or(Err(ErrorCode::NOSUPPORT), |timer| {
+                        timer.start(process.processid(), timeout as u32, address as usize)
+                    });
+                    if started.is_err() {
+                        // # Safety
+                        //
+                        // See the `yield-no-wait` case above.
+                        unsafe {
+                            process.set_byte(address, 0);
+                        }
+                        return;
+                    }
+                } else if which == (YieldCall::WaitTimeout as usize) {
+                    // An upcall is pending, so it executes now.
+                    //
+                    // # Safety
+                    //
+                    // See the `yield-no-wait` case above.
+                    unsafe {
+                        process.set_byte(address, 1);
+                    }
+                }
+                let wait = which != (YieldCall::NoWait as usize);
                 // If this is a yield-no-wait AND there are no pending tasks,
                 // then return immediately. Otherwise, go into the yielded state
                 // and execute tasks now or when they arrive.
@@ -794,6 +910,48 @@ impl Kernel {
             | Syscall::ReadWriteAllow { driver_number, .. }
             | Syscall::UserspaceReadableAllow { driver_number, .. }
             | Syscall::ReadOnlyAllow { driver_number, .. } => {
+                if config::CONFIG.contain_capsule_panics && debug::driver_panicked(driver_number) {
+                    // The driver panicked before the last reset. Subscribe and
+                    // allow return the upcall or buffer to the process.
+                    let failure = match syscall {
+                        Syscall::Subscribe {
+                            upcall_ptr,
+                            appdata,
+                            ..
+                        } => SyscallReturn::SubscribeFailure(ErrorCode::FAIL, upcall_ptr, appdata),
+                        Syscall::ReadWriteAllow {
+                            allow_address,
+                            allow_size,
+                            ..
+                        } => SyscallReturn::AllowReadWriteFailure(
+                            ErrorCode::FAIL,
+                            allow_address,
+                            allow_size,
+                        ),
+                        Syscall::UserspaceReadableAllow {
+                            allow_address,
+                            allow_size,
+                            ..
+                        } => SyscallReturn::UserspaceReadableAllowFailure(
+                            ErrorCode::FAIL,
+                            allow_address,
+                            allow_size,
+                        ),
+                        Syscall::ReadOnlyAllow {
+                            allow_address,
+                            allow_size,
+                            ..
+                        } => SyscallReturn::AllowReadOnlyFailure(
+                            ErrorCode::FAIL,
+                            allow_address,
+                            allow_size,
+                        ),
+                        _ => SyscallReturn::Failure(ErrorCode::FAIL),
+                    };
+                    process.set_syscall_return_value(failure);
+                    return;
+                }
+                debug::set_running_driver(Some(driver_number));
                 resources
                 .syscall_driver_lookup()
                 .with_driver(driver_number, |driver| match syscall {
@@ -932,6 +1090,7 @@ impl Kernel {
                             );
                         }
 
+                        self.record_driver_syscall(syscall, driver.is_some(), &rval);
                         process.set_syscall_return_value(rval);
                     }
                     Syscall::Command {
@@ -941,6 +1100,11 @@ impl Kernel {
                         arg1,
                     } => {
                         let cres = match driver {
+                            Some(d) if subdriver_number == ABI_VERSION_COMMAND => d
+                                .abi_version()
+                                .map_or(CommandReturn::failure(ErrorCode::NOSUPPORT), |version| {
+                                    CommandReturn::success_u32(version)
+                                }),
                             Some(d) => d.command(subdriver_number, arg0, arg1, process.processid()),
                             None => CommandReturn::failure(ErrorCode::NODEVICE),
                         };
@@ -958,6 +1122,7 @@ impl Kernel {
                                 res,
                             );
                         }
+                        self.record_driver_syscall(syscall, driver.is_some(), &res);
                         process.set_syscall_return_value(res);
                     }
                     Syscall::ReadWriteAllow {
@@ -1075,6 +1240,7 @@ impl Kernel {
                                 res
                             );
                         }
+                        self.record_driver_syscall(syscall, driver.is_some(), &res);
                         process.set_syscall_return_value(res);
                     }
                     Syscall::UserspaceReadableAllow {
@@ -1154,6 +1320,7 @@ impl Kernel {
                                 res
                             );
                         }
+                        self.record_driver_syscall(syscall, driver.is_some(), &res);
                         process.set_syscall_return_value(res);
                     }
                     Syscall::ReadOnlyAllow {
@@ -1272,6 +1439,7 @@ impl Kernel {
                             );
                         }
 
+                        self.record_driver_syscall(syscall, driver.is_some(), &res);
                         process.set_syscall_return_value(res);
                     }
                     Syscall::Yield { .. }
@@ -1281,7 +1449,8 @@ impl Kernel {
                         // the outer match statement:
                         debug_assert!(false, "Kernel system call handling invariant violated!");
                     },
-                })
+                });
+                debug::set_running_driver(None);
             }
             Syscall::Exit {
                 which,
@@ -1298,3 +1467,23 @@ impl Kernel {
         }
     }
 }
+
+impl YieldTimerClient for Kernel {
+    fn timeout_expired(&self, processid: ProcessId, data: usize) {
+        self.process_map_or((), processid, |process| {
+            if config::CONFIG.trace_syscalls {
+                debug!("[{:?}] yield timeout", processid);
+            }
+            // Tell the process that no upcall executed, and return from its
+            // `yield-wait-timeout`.
+            //
+            // # Safety
+            //
+            // See the handling of `yield-no-wait`.
+            unsafe {
+                process.set_byte(data as *mut u8, 0);
+            }
+            process.set_running_state();
+        });
+    }
+}
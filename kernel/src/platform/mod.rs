@@ -10,6 +10,7 @@ pub mod chip;
 pub mod mpu;
 pub mod scheduler_timer;
 pub mod watchdog;
+pub mod yield_timer;
 
 pub(crate) mod platform;
 
@@ -81,6 +81,9 @@ pub trait KernelResources<C: Chip> {
 /// the core for how syscall dispatching is handled, and the implementation is
 /// responsible for dispatching to drivers for each system call number.
 ///
+/// The [`dispatch_drivers!`](crate::dispatch_drivers) macro implements this
+/// dispatch, and checks at compile time that no two drivers share a number.
+///
 /// ## Example
 ///
 /// ```ignore
@@ -116,6 +119,86 @@ pub trait SyscallDriverLookup {
         F: FnOnce(Option<&dyn SyscallDriver>) -> R;
 }
 
+/// Dispatch a driver number to the driver registered for it, for
+/// implementations of [`SyscallDriverLookup::with_driver`].
+///
+/// This expands to a `match` on the driver number, and fails to compile if
+/// two drivers are registered with the same driver number, naming both of
+/// them. A plain `match` would silently dispatch the number to the first of
+/// the two drivers. The last arm is called for the numbers without a driver.
+///
+/// ```ignore
+/// impl SyscallDriverLookup for Hail {
+///     fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+///     where
+///         F: FnOnce(Option<&dyn kernel::SyscallDriver>) -> R,
+///     {
+///         kernel::dispatch_drivers!(driver_num, f, {
+///             capsules::console::DRIVER_NUM => self.console,
+///             kernel::ipc::DRIVER_NUM => &self.ipc,
+///             capsules::dac::DRIVER_NUM => self.dac,
+///             _ => f(None),
+///         })
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! dispatch_drivers {
+    ($driver_num:expr, $f:ident, { $($arms:tt)* }) => {
+        $crate::dispatch_drivers!(@arms $driver_num, $f, [] $($arms)*)
+    };
+    // The arms are collected one by one, as a `_` arm following the driver
+    // arms would be ambiguous in a single repetition.
+    (@arms $driver_num:expr, $f:ident, [$($num:path => $driver:expr,)*]
+        _ => $default:expr $(,)?) => {{
+        const _: () = {
+            $crate::assert_distinct_driver_nums!($($num),*);
+        };
+        match $driver_num {
+            $($num => $f(Some($driver)),)*
+            _ => $default,
+        }
+    }};
+    (@arms $driver_num:expr, $f:ident, [$($arms:tt)*]
+        $num:path => $driver:expr, $($rest:tt)*) => {
+        $crate::dispatch_drivers!(@arms $driver_num, $f, [$($arms)* $num => $driver,] $($rest)*)
+    };
+}
+
+/// Fail to compile if two of the driver numbers are equal, naming both.
+///
+/// [`dispatch_drivers!`](crate::dispatch_drivers) checks its driver numbers
+/// with this macro. Boards dispatching driver numbers with their own `match`
+/// can check them in a constant:
+///
+/// ```ignore
+/// const _: () = {
+///     kernel::assert_distinct_driver_nums!(
+///         capsules::console::DRIVER_NUM,
+///         capsules::rng::DRIVER_NUM,
+///     );
+/// };
+/// ```
+#[macro_export]
+macro_rules! assert_distinct_driver_nums {
+    () => {};
+    ($head:path $(, $tail:path)* $(,)?) => {
+        $(
+            assert!(
+                $head != $tail,
+                concat!(
+                    "driver number conflict: ",
+                    stringify!($head),
+                    " and ",
+                    stringify!($tail),
+                    " are the same driver number",
+                ),
+            );
+        )*
+        $crate::assert_distinct_driver_nums!($($tail),*);
+    };
+}
+
 /// Trait for implementing system call filters that the kernel uses to decide
 /// whether to handle a specific system call or not.
 pub trait SyscallFilter {
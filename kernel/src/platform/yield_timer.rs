@@ -0,0 +1,156 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Yield Timer for bounding `yield-wait-timeout`
+//!
+//! Interface for use by the kernel to wake processes that called
+//! `yield-wait-timeout` when no upcall arrived before their timeout.
+
+use core::cell::Cell;
+
+use crate::hil::time::{self, Frequency, Ticks};
+use crate::process::ProcessId;
+use crate::ErrorCode;
+
+/// Interface for the timer of `yield-wait-timeout`.
+///
+/// The kernel starts a timeout when a process calls `yield-wait-timeout`
+/// without pending upcalls, and cancels it when an upcall wakes the process
+/// first. When a timeout expires, the timer calls its client, which resumes
+/// the process.
+pub trait YieldTimer {
+    /// Set the client called when a timeout expires.
+    fn set_client(&self, client: &'static dyn YieldTimerClient);
+
+    /// Start a timeout of `ms` milliseconds for `processid`, replacing any
+    /// previous timeout of the process. `data` is returned to the client when
+    /// the timeout expires, or by `cancel()`.
+    ///
+    /// Returns `INVAL` if `ms` is too long for the timer to measure, and
+    /// `NOMEM` if the timer cannot track another process.
+    fn start(&self, processid: ProcessId, ms: u32, data: usize) -> Result<(), ErrorCode>;
+
+    /// Cancel the timeout of `processid`. Returns the `data` passed to
+    /// `start()` if a timeout was running.
+    fn cancel(&self, processid: ProcessId) -> Option<usize>;
+}
+
+/// Client of a [`YieldTimer`].
+pub trait YieldTimerClient {
+    /// The timeout of `processid` expired.
+    fn timeout_expired(&self, processid: ProcessId, data: usize);
+}
+
+/// A timeout started by [`VirtualYieldTimer`].
+#[derive(Copy, Clone)]
+struct Timeout<T: Ticks> {
+    processid: ProcessId,
+    reference: T,
+    dt: T,
+    data: usize,
+}
+
+/// Implementation of [`YieldTimer`] on top of an alarm, typically a virtual
+/// alarm, tracking up to `NUM_PROCS` processes at a time.
+pub struct VirtualYieldTimer<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm: &'static A,
+    timeouts: [Cell<Option<Timeout<A::Ticks>>>; NUM_PROCS],
+    client: Cell<Option<&'static dyn YieldTimerClient>>,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> VirtualYieldTimer<A, NUM_PROCS> {
+    pub fn new(alarm: &'static A) -> Self {
+        Self {
+            alarm,
+            timeouts: core::array::from_fn(|_| Cell::new(None)),
+            client: Cell::new(None),
+        }
+    }
+
+    /// Arm the alarm for the earliest timeout, or disarm it.
+    fn rearm(&self) {
+        let now = self.alarm.now();
+        let next = self
+            .timeouts
+            .iter()
+            .filter_map(|slot| slot.get())
+            .map(|timeout| {
+                let expiration = timeout.reference.wrapping_add(timeout.dt);
+                if now.within_range(timeout.reference, expiration) {
+                    expiration.wrapping_sub(now)
+                } else {
+                    A::Ticks::from(0)
+                }
+            })
+            .min_by_key(|remaining| remaining.into_u32());
+        match next {
+            Some(remaining) => self.alarm.set_alarm(now, remaining),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> YieldTimer
+    for VirtualYieldTimer<A, NUM_PROCS>
+{
+    fn set_client(&self, client: &'static dyn YieldTimerClient) {
+        self.client.set(Some(client));
+    }
+
+    fn start(&self, processid: ProcessId, ms: u32, data: usize) -> Result<(), ErrorCode> {
+        // Timeouts that do not fit in the ticks of the alarm would wrap around
+        // and expire early.
+        let dt = time::checked_ticks_from_ms(ms as u64, A::Frequency::frequency())
+            .filter(|ticks| A::Ticks::width() >= u64::BITS || ticks >> A::Ticks::width() == 0)
+            .map(A::Ticks::from_or_max)
+            .ok_or(ErrorCode::INVAL)?;
+        let _ = self.cancel(processid);
+        let slot = self
+            .timeouts
+            .iter()
+            .find(|slot| slot.get().is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        slot.set(Some(Timeout {
+            processid,
+            reference: self.alarm.now(),
+            dt,
+            data,
+        }));
+        self.rearm();
+        Ok(())
+    }
+
+    fn cancel(&self, processid: ProcessId) -> Option<usize> {
+        self.timeouts
+            .iter()
+            .find(|slot| {
+                slot.get()
+                    .map_or(false, |timeout| timeout.processid == processid)
+            })
+            .and_then(|slot| slot.take())
+            .map(|timeout| timeout.data)
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> time::AlarmClient
+    for VirtualYieldTimer<A, NUM_PROCS>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for slot in self.timeouts.iter() {
+            if let Some(timeout) = slot.get() {
+                let expiration = timeout.reference.wrapping_add(timeout.dt);
+                if !now.within_range(timeout.reference, expiration) {
+                    slot.set(None);
+                    if let Some(client) = self.client.get() {
+                        client.timeout_expired(timeout.processid, timeout.data);
+                    }
+                }
+            }
+        }
+        self.rearm();
+    }
+}
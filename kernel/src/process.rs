@@ -11,6 +11,7 @@ use core::ptr::NonNull;
 use core::str;
 
 use crate::capabilities;
+use crate::config;
 use crate::errorcode::ErrorCode;
 use crate::ipc;
 use crate::kernel::Kernel;
@@ -25,6 +26,8 @@ use tock_tbf::types::CommandPermissions;
 pub use crate::process_binary::ProcessBinary;
 pub use crate::process_checker::{ProcessCheckerMachine, ProcessCheckerMachineClient};
 pub use crate::process_loading::load_processes;
+pub use crate::process_loading::load_processes_from_regions;
+pub use crate::process_loading::ProcessBinaryClient;
 pub use crate::process_loading::ProcessLoadError;
 pub use crate::process_loading::SequentialProcessLoaderMachine;
 pub use crate::process_loading::{ProcessLoadingAsync, ProcessLoadingAsyncClient};
@@ -214,6 +217,13 @@ impl ProcessId {
         self.kernel
             .process_map_or(None, *self, |process| process.get_storage_permissions())
     }
+
+    /// Check whether the process may access the read-only data blob (asset)
+    /// with identifier `asset_id`, as declared in its TBF header.
+    pub fn has_asset_access(&self, asset_id: u32) -> bool {
+        self.kernel
+            .process_map_or(false, *self, |process| process.has_asset_access(asset_id))
+    }
 }
 
 /// A compressed form of an Application Identifier.
@@ -298,6 +308,11 @@ impl BinaryVersion {
     pub fn new(value: NonZeroU32) -> Self {
         Self(value)
     }
+
+    /// Returns the version number.
+    pub fn get(&self) -> u32 {
+        self.0.get()
+    }
 }
 
 /// This trait represents a generic process that the Tock scheduler can
@@ -367,6 +382,13 @@ pub trait Process {
     /// running.
     fn set_yielded_state(&self);
 
+    /// Move this process from the yielded state back to the running state,
+    /// such that its yield returns without executing an upcall.
+    ///
+    /// This transitions a process from `Yielded` -> `Running` or
+    /// `StoppedYielded` -> `StoppedRunning`, and does nothing in other states.
+    fn set_running_state(&self);
+
     /// Move this process from running or yielded state into the stopped state.
     ///
     /// This will fail (i.e. not do anything) if the process was not either
@@ -557,6 +579,10 @@ pub trait Process {
     /// Returns `None` if the process has no storage permissions.
     fn get_storage_permissions(&self) -> Option<storage_permissions::StoragePermissions>;
 
+    /// Check whether the process may access the read-only data blob (asset)
+    /// with identifier `asset_id`.
+    fn has_asset_access(&self, asset_id: u32) -> bool;
+
     // mpu
 
     /// Configure the MPU to use the process's allocated regions.
@@ -578,8 +604,19 @@ pub trait Process {
         min_region_size: usize,
     ) -> Option<mpu::Region>;
 
+    /// Allocate a new read-only MPU region for the process that covers
+    /// exactly `size` bytes starting at `start`, for example to give the
+    /// process access to data stored in flash outside of its own binary.
+    ///
+    /// Returns `None` if the MPU cannot cover exactly this range (e.g.
+    /// because of alignment constraints) or has no region left.
+    ///
+    /// It is not valid to call this function when the process is inactive (i.e.
+    /// the process will not run again).
+    fn add_read_only_mpu_region(&self, start: *const u8, size: usize) -> Option<mpu::Region>;
+
     /// Removes an MPU region from the process that has been previously added
-    /// with `add_mpu_region`.
+    /// with `add_mpu_region` or `add_read_only_mpu_region`.
     ///
     /// It is not valid to call this function when the process is inactive (i.e.
     /// the process will not run again).
@@ -763,6 +800,63 @@ pub trait Process {
     /// Return the last syscall the process called. Returns `None` if the
     /// process has not called any syscalls or the information is unknown.
     fn debug_syscall_last(&self) -> Option<Syscall>;
+
+    /// Return an entry of the syscall trace of the process, `0` being the most
+    /// recent syscall. Returns `None` if the process has not called that many
+    /// syscalls or the trace is disabled.
+    fn debug_syscall_trace(&self, index: usize) -> Option<SyscallTraceEntry>;
+
+    /// Record that the process starts executing an upcall from the driver
+    /// `driver_num`, or `None` if it blocks or executes a function that does
+    /// not come from a driver. The time the process uses until the next call
+    /// is charged to that driver.
+    fn debug_upcall_started(&self, driver_num: Option<usize>);
+
+    /// Charge `time_us` microseconds of execution to the driver whose upcall
+    /// the process is executing.
+    fn debug_time_used(&self, time_us: u32);
+
+    /// Return the time the process spent executing upcalls of one driver.
+    /// Returns `None` if `index` is past the number of drivers recorded or
+    /// the accounting is disabled.
+    fn debug_upcall_time(&self, index: usize) -> Option<UpcallTimeEntry>;
+}
+
+/// Number of syscalls `Process::debug_syscall_trace()` returns at most, `0`
+/// if the trace is disabled.
+pub const SYSCALL_TRACE_LENGTH: usize = config::CONFIG.syscall_trace_length;
+
+/// A syscall recorded in the syscall trace of a process.
+#[derive(Copy, Clone, Debug)]
+pub struct SyscallTraceEntry {
+    /// The syscall the process called.
+    pub syscall: Syscall,
+    /// The value returned to the process, or `None` if the syscall did not
+    /// return (yet), e.g. a blocking yield or an exit.
+    pub return_value: Option<SyscallReturn>,
+}
+
+/// Number of drivers whose upcall time `Process::debug_upcall_time()` returns
+/// at most, `0` if the accounting is disabled.
+pub const UPCALL_TIME_DRIVERS: usize = config::CONFIG.upcall_time_drivers;
+
+/// The time a process spent executing the upcalls of a driver.
+///
+/// The time spent from the delivery of an upcall until the process blocks
+/// again, or the next upcall is delivered, is charged to the driver that
+/// scheduled the upcall.
+#[derive(Copy, Clone, Debug)]
+pub struct UpcallTimeEntry {
+    /// The driver that scheduled the upcalls.
+    pub driver_num: usize,
+    /// How many upcalls of the driver the process executed.
+    pub upcall_count: usize,
+    /// The total execution time in microseconds, including the time spent
+    /// in the kernel handling the syscalls of the process.
+    pub time_us: u64,
+    /// How many times the process exceeded its timeslice while executing
+    /// upcalls of the driver.
+    pub timeslice_expiration_count: usize,
 }
 
 /// Opaque identifier for custom grants allocated dynamically from a process's
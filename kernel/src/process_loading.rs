@@ -27,6 +27,7 @@ use crate::process_checker::{AppIdPolicy, ProcessCheckError, ProcessCheckerMachi
 use crate::process_policies::ProcessFaultPolicy;
 use crate::process_standard::ProcessStandard;
 use crate::utilities::cells::{MapCell, OptionalCell};
+use crate::utilities::static_init;
 
 /// Errors that can occur when trying to load and create processes.
 pub enum ProcessLoadError {
@@ -162,6 +163,43 @@ pub fn load_processes<C: Chip>(
     Ok(())
 }
 
+/// Load processes, as `load_processes` does, from several regions of flash,
+/// such as the internal flash and an external flash mapped for
+/// execute-in-place with `hil::xip::ExecuteInPlace`. The regions are searched
+/// in order, and their processes share `app_memory` and `procs`.
+///
+/// Processes stored outside of the internal flash must be built for the
+/// address they are mapped at.
+#[inline(always)]
+pub fn load_processes_from_regions<C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    app_flash_regions: &[&'static [u8]],
+    app_memory: &'static mut [u8],
+    procs: &'static mut [Option<&'static dyn Process>],
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    _capability_management: &dyn ProcessManagementCapability,
+) -> Result<(), ProcessLoadError> {
+    let mut index = 0;
+    let mut remaining_memory = app_memory;
+    for app_flash in app_flash_regions {
+        remaining_memory = load_processes_from_region(
+            kernel,
+            chip,
+            app_flash,
+            remaining_memory,
+            procs,
+            &mut index,
+            fault_policy,
+        );
+    }
+
+    if config::CONFIG.debug_memory_report {
+        print_memory_report(procs, remaining_memory.len());
+    }
+    Ok(())
+}
+
 /// Helper function to load processes from flash into an array of active
 /// processes. This is the default template for loading processes, but a board
 /// is able to create its own `load_processes()` function and use that instead.
@@ -189,6 +227,36 @@ fn load_processes_from_flash<C: Chip>(
     procs: &mut &'static mut [Option<&'static dyn Process>],
     fault_policy: &'static dyn ProcessFaultPolicy,
 ) -> Result<(), ProcessLoadError> {
+    let mut index = 0;
+    let remaining_memory = load_processes_from_region(
+        kernel,
+        chip,
+        app_flash,
+        app_memory,
+        procs,
+        &mut index,
+        fault_policy,
+    );
+
+    if config::CONFIG.debug_memory_report {
+        print_memory_report(procs, remaining_memory.len());
+    }
+    Ok(())
+}
+
+/// Load the processes found in the flash region `app_flash` into `procs`,
+/// starting at `index`, with memory taken from `app_memory`. `index` is
+/// advanced past the loaded processes, and the memory left is returned.
+#[inline(always)]
+fn load_processes_from_region<C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    app_flash: &'static [u8],
+    app_memory: &'static mut [u8],
+    procs: &mut [Option<&'static dyn Process>],
+    index: &mut usize,
+    fault_policy: &'static dyn ProcessFaultPolicy,
+) -> &'static mut [u8] {
     if config::CONFIG.debug_load_processes {
         debug!(
             "Loading processes from flash={:#010X}-{:#010X} into sram={:#010X}-{:#010X}",
@@ -202,9 +270,8 @@ fn load_processes_from_flash<C: Chip>(
     let mut remaining_flash = app_flash;
     let mut remaining_memory = app_memory;
     // Try to discover up to `procs.len()` processes in flash.
-    let mut index = 0;
     let num_procs = procs.len();
-    while index < num_procs {
+    while *index < num_procs {
         let load_binary_result = discover_process_binary(remaining_flash);
 
         match load_binary_result {
@@ -217,7 +284,7 @@ fn load_processes_from_flash<C: Chip>(
                     process_binary,
                     remaining_memory,
                     ShortId::LocallyUnique,
-                    index,
+                    *index,
                     fault_policy,
                 );
                 match load_result {
@@ -228,8 +295,8 @@ fn load_processes_from_flash<C: Chip>(
                                 if config::CONFIG.debug_load_processes {
                                     debug!("Loaded process {}", p.get_process_name())
                                 }
-                                procs[index] = proc;
-                                index += 1;
+                                procs[*index] = proc;
+                                *index += 1;
                             }
                             None => {
                                 if config::CONFIG.debug_load_processes {
@@ -269,13 +336,30 @@ fn load_processes_from_flash<C: Chip>(
             }
         }
     }
-    Ok(())
+
+    remaining_memory
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 // HELPER FUNCTIONS
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Print the static memory used by the kernel, the memory of each process
+/// and the memory left for processes.
+fn print_memory_report(procs: &[Option<&'static dyn Process>], remaining_memory: usize) {
+    static_init::print_static_memory_report(16);
+    for proc in procs.iter().flatten() {
+        let addresses = proc.get_addresses();
+        debug!(
+            "Process {}: {} bytes of RAM, {} bytes for the kernel and grants",
+            proc.get_process_name(),
+            addresses.sram_end - addresses.sram_start,
+            addresses.sram_end - addresses.sram_grant_start,
+        );
+    }
+    debug!("RAM left for processes: {} bytes", remaining_memory);
+}
+
 /// Find a process binary stored at the beginning of `flash` and create a
 /// `ProcessBinary` object if the process is viable to run on this kernel.
 fn discover_process_binary(
@@ -422,6 +506,16 @@ pub trait ProcessLoadingAsyncClient {
     fn process_loading_finished(&self);
 }
 
+/// Client notified of the binary of each process that is loaded, for example
+/// to measure the processes running on the board.
+pub trait ProcessBinaryClient {
+    /// The process `process` was loaded from the TBF object `binary`.
+    ///
+    /// `binary` is the part of the TBF object covered by integrity: its
+    /// header and the application binary, without the footers.
+    fn process_binary_loaded(&self, process: &dyn Process, binary: &'static [u8]);
+}
+
 /// Asynchronous process loading.
 ///
 /// Machines which implement this trait perform asynchronous process loading and
@@ -481,6 +575,8 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static> {
     fault_policy: &'static dyn ProcessFaultPolicy,
     /// Current mode of the loading machine.
     state: OptionalCell<SequentialProcessLoaderMachineState>,
+    /// Client notified of the binary of each loaded process.
+    binary_client: OptionalCell<&'a dyn ProcessBinaryClient>,
 }
 
 impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
@@ -513,9 +609,15 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
             policy: OptionalCell::new(policy),
             fault_policy,
             state: OptionalCell::empty(),
+            binary_client: OptionalCell::empty(),
         }
     }
 
+    /// Set the client notified of the binary of each loaded process.
+    pub fn set_binary_client(&self, client: &'a dyn ProcessBinaryClient) {
+        self.binary_client.set(client);
+    }
+
     /// Find a slot in the `PROCESSES` array to store this process.
     fn find_open_process_slot(&self) -> Option<usize> {
         self.procs.map_or(None, |procs| {
@@ -711,6 +813,8 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
                             policy.to_short_id(&process_binary)
                         });
 
+                        let binary = process_binary.get_integrity_region_slice();
+
                         // Try to create a `Process` object.
                         let load_result = load_process(
                             self.kernel,
@@ -738,6 +842,9 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
                                         self.procs.map(|procs| {
                                             procs[index] = proc;
                                         });
+                                        self.binary_client.map(|client| {
+                                            client.process_binary_loaded(p, binary);
+                                        });
                                         // Notify the client the process was loaded
                                         // successfully.
                                         self.client.map(|client| {
@@ -774,6 +881,13 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
         }
         self.proc_binaries.put(proc_binaries);
 
+        if config::CONFIG.debug_memory_report {
+            let remaining_memory = self.app_memory.take();
+            self.procs
+                .map(|procs| print_memory_report(procs, remaining_memory.len()));
+            self.app_memory.set(remaining_memory);
+        }
+
         // We have iterated all discovered `ProcessBinary`s and loaded what we
         // could so now we can signal that process loading is finished.
         self.client.map(|client| {
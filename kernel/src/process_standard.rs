@@ -26,7 +26,7 @@ use crate::process::BinaryVersion;
 use crate::process::ProcessBinary;
 use crate::process::{Error, FunctionCall, FunctionCallSource, Process, State, Task};
 use crate::process::{FaultAction, ProcessCustomGrantIdentifier, ProcessId};
-use crate::process::{ProcessAddresses, ProcessSizes, ShortId};
+use crate::process::{ProcessAddresses, ProcessSizes, ShortId, SyscallTraceEntry, UpcallTimeEntry};
 use crate::process_loading::ProcessLoadError;
 use crate::process_policies::ProcessFaultPolicy;
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
@@ -69,6 +69,12 @@ struct ProcessStandardDebug {
     /// What was the most recent syscall.
     last_syscall: Option<Syscall>,
 
+    /// The most recent syscalls, used as a ring buffer.
+    syscall_trace: [Option<SyscallTraceEntry>; config::CONFIG.syscall_trace_length],
+
+    /// Index in `syscall_trace` where the next syscall will be recorded.
+    syscall_trace_next: usize,
+
     /// How many upcalls were dropped because the queue was insufficiently
     /// long.
     dropped_upcall_count: usize,
@@ -76,6 +82,30 @@ struct ProcessStandardDebug {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// The time spent executing the upcalls of each driver.
+    upcall_time: [Option<UpcallTimeEntry>; config::CONFIG.upcall_time_drivers],
+
+    /// The driver whose upcall the process is executing, if any.
+    upcall_driver: Option<usize>,
+}
+
+impl ProcessStandardDebug {
+    /// Return the record of the driver whose upcall the process is executing,
+    /// creating it if there is room left.
+    fn upcall_time_entry(&mut self) -> Option<&mut UpcallTimeEntry> {
+        let driver_num = self.upcall_driver?;
+        let index = self
+            .upcall_time
+            .iter()
+            .position(|entry| entry.map_or(true, |entry| entry.driver_num == driver_num))?;
+        Some(self.upcall_time[index].get_or_insert(UpcallTimeEntry {
+            driver_num,
+            upcall_count: 0,
+            time_us: 0,
+            timeslice_expiration_count: 0,
+        }))
+    }
 }
 
 /// Entry that is stored in the grant pointer table at the top of process
@@ -335,6 +365,14 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         }
     }
 
+    fn set_running_state(&self) {
+        match self.state.get() {
+            State::Yielded => self.state.set(State::Running),
+            State::StoppedYielded => self.state.set(State::StoppedRunning),
+            _ => {} // Do nothing
+        }
+    }
+
     fn stop(&self) {
         match self.state.get() {
             State::Running => self.state.set(State::StoppedRunning),
@@ -474,6 +512,10 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         ))
     }
 
+    fn has_asset_access(&self, asset_id: u32) -> bool {
+        self.header.has_asset_access(asset_id)
+    }
+
     fn number_writeable_flash_regions(&self) -> usize {
         self.header.number_writeable_flash_regions()
     }
@@ -514,25 +556,25 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         unallocated_memory_size: usize,
         min_region_size: usize,
     ) -> Option<mpu::Region> {
-        self.mpu_config.and_then(|config| {
-            let new_region = self.chip.mpu().allocate_region(
-                unallocated_memory_start,
-                unallocated_memory_size,
-                min_region_size,
-                mpu::Permissions::ReadWriteOnly,
-                config,
-            )?;
+        self.allocate_mpu_region(
+            unallocated_memory_start,
+            unallocated_memory_size,
+            min_region_size,
+            mpu::Permissions::ReadWriteOnly,
+        )
+    }
 
-            for region in self.mpu_regions.iter() {
-                if region.get().is_none() {
-                    region.set(Some(new_region));
-                    return Some(new_region);
+    fn add_read_only_mpu_region(&self, start: *const u8, size: usize) -> Option<mpu::Region> {
+        self.allocate_mpu_region(start, size, size, mpu::Permissions::ReadOnly)
+            .and_then(|region| {
+                if region.start_address() == start && region.size() == size {
+                    Some(region)
+                } else {
+                    // The MPU could not cover exactly the requested range.
+                    let _ = self.remove_mpu_region(region);
+                    None
                 }
-            }
-
-            // Not enough room in Process struct to store the MPU region.
-            None
-        })
+            })
     }
 
     fn remove_mpu_region(&self, region: mpu::Region) -> Result<(), ErrorCode> {
@@ -976,6 +1018,16 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     }
 
     fn set_syscall_return_value(&self, return_value: SyscallReturn) {
+        if config::CONFIG.syscall_trace_length != 0 {
+            self.debug.map(|debug| {
+                let length = config::CONFIG.syscall_trace_length;
+                let last = (debug.syscall_trace_next + length - 1) % length;
+                if let Some(entry) = debug.syscall_trace[last].as_mut() {
+                    entry.return_value = Some(return_value);
+                }
+            });
+        }
+
         match self.stored_state.map(|stored_state| unsafe {
             // Actually set the return value for a particular process.
             //
@@ -1111,14 +1163,27 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     }
 
     fn debug_timeslice_expired(&self) {
-        self.debug
-            .map(|debug| debug.timeslice_expiration_count += 1);
+        self.debug.map(|debug| {
+            debug.timeslice_expiration_count += 1;
+            if let Some(entry) = debug.upcall_time_entry() {
+                entry.timeslice_expiration_count += 1;
+            }
+        });
     }
 
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.map(|debug| {
             debug.syscall_count += 1;
             debug.last_syscall = Some(last_syscall);
+
+            if config::CONFIG.syscall_trace_length != 0 {
+                debug.syscall_trace[debug.syscall_trace_next] = Some(SyscallTraceEntry {
+                    syscall: last_syscall,
+                    return_value: None,
+                });
+                debug.syscall_trace_next =
+                    (debug.syscall_trace_next + 1) % config::CONFIG.syscall_trace_length;
+            }
         });
     }
 
@@ -1126,6 +1191,39 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         self.debug.map_or(None, |debug| debug.last_syscall)
     }
 
+    fn debug_syscall_trace(&self, index: usize) -> Option<SyscallTraceEntry> {
+        let length = config::CONFIG.syscall_trace_length;
+        if index >= length {
+            return None;
+        }
+        self.debug.map_or(None, |debug| {
+            debug.syscall_trace[(debug.syscall_trace_next + length - 1 - index) % length]
+        })
+    }
+
+    fn debug_upcall_started(&self, driver_num: Option<usize>) {
+        self.debug.map(|debug| {
+            debug.upcall_driver = driver_num;
+            if let Some(entry) = debug.upcall_time_entry() {
+                entry.upcall_count += 1;
+            }
+        });
+    }
+
+    fn debug_time_used(&self, time_us: u32) {
+        self.debug.map(|debug| {
+            if let Some(entry) = debug.upcall_time_entry() {
+                entry.time_us += time_us as u64;
+            }
+        });
+    }
+
+    fn debug_upcall_time(&self, index: usize) -> Option<UpcallTimeEntry> {
+        self.debug.map_or(None, |debug| {
+            debug.upcall_time.get(index).copied().flatten()
+        })
+    }
+
     fn get_addresses(&self) -> ProcessAddresses {
         ProcessAddresses {
             flash_start: self.flash_start() as usize,
@@ -1214,6 +1312,19 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             }
         });
 
+        // Display the most recent syscalls, oldest first.
+        if config::CONFIG.syscall_trace_length != 0 {
+            let _ = writer.write_fmt(format_args!("\r\n Syscall trace:\r\n"));
+            for index in (0..config::CONFIG.syscall_trace_length).rev() {
+                if let Some(entry) = self.debug_syscall_trace(index) {
+                    let _ = writer.write_fmt(format_args!(
+                        "  {:?} -> {:?}\r\n",
+                        entry.syscall, entry.return_value
+                    ));
+                }
+            }
+        }
+
         // Display the current state of the MPU for this process.
         self.mpu_config.map(|config| {
             let _ = writer.write_fmt(format_args!("{}", config));
@@ -1666,8 +1777,12 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             app_stack_min_pointer: None,
             syscall_count: 0,
             last_syscall: None,
+            syscall_trace: [None; config::CONFIG.syscall_trace_length],
+            syscall_trace_next: 0,
             dropped_upcall_count: 0,
             timeslice_expiration_count: 0,
+            upcall_time: [None; config::CONFIG.upcall_time_drivers],
+            upcall_driver: None,
         });
 
         // Handle any architecture-specific requirements for a new process.
@@ -1742,8 +1857,12 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         self.debug.map(|debug| {
             debug.syscall_count = 0;
             debug.last_syscall = None;
+            debug.syscall_trace = [None; config::CONFIG.syscall_trace_length];
+            debug.syscall_trace_next = 0;
             debug.dropped_upcall_count = 0;
             debug.timeslice_expiration_count = 0;
+            debug.upcall_driver = None;
+            debug.upcall_time = [None; config::CONFIG.upcall_time_drivers];
         });
 
         // Reset MPU region configuration.
@@ -1760,6 +1879,12 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         let mut mpu_config = self.mpu_config.take().ok_or(ErrorCode::FAIL)?;
         self.chip.mpu().reset_config(&mut mpu_config);
 
+        // Regions added at runtime (IPC, assets, ...) are gone with the old
+        // configuration.
+        for region in self.mpu_regions.iter() {
+            region.set(None);
+        }
+
         // Allocate MPU region for flash.
         let app_mpu_flash = self.chip.mpu().allocate_region(
             self.flash.as_ptr(),
@@ -2000,6 +2125,36 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         process_memory_end - identifier.offset
     }
 
+    /// Allocate a new MPU region with `permissions` for the process and
+    /// record it in the process's MPU regions.
+    fn allocate_mpu_region(
+        &self,
+        unallocated_memory_start: *const u8,
+        unallocated_memory_size: usize,
+        min_region_size: usize,
+        permissions: mpu::Permissions,
+    ) -> Option<mpu::Region> {
+        self.mpu_config.and_then(|config| {
+            let new_region = self.chip.mpu().allocate_region(
+                unallocated_memory_start,
+                unallocated_memory_size,
+                min_region_size,
+                permissions,
+                config,
+            )?;
+
+            for region in self.mpu_regions.iter() {
+                if region.get().is_none() {
+                    region.set(Some(new_region));
+                    return Some(new_region);
+                }
+            }
+
+            // Not enough room in Process struct to store the MPU region.
+            None
+        })
+    }
+
     /// The start address of allocated RAM for this process.
     fn mem_start(&self) -> *const u8 {
         self.memory_start
@@ -1005,18 +1005,62 @@ impl WriteableProcessSlice {
         if self.len() != src.len() {
             Err(ErrorCode::SIZE)
         } else {
-            // _If_ this turns out to not be efficiently optimized, it
-            // should be possible to use a ptr::copy_nonoverlapping here
-            // given we have exclusive mutable access to the destination
-            // slice which will never be in process memory, and the layout
-            // of &[Cell<u8>] is guaranteed to be compatible to &[u8].
-            src.iter()
-                .zip(self.slice.iter())
-                .for_each(|(src, dst)| dst.set(*src));
+            // # Safety
+            //
+            // The layout of a `[Cell<u8>]` is the same as a `[u8]`, and `Cell`
+            // permits writes through a shared reference. The lengths are
+            // equal, and `src` cannot overlap with `self` as it is borrowed
+            // immutably while process memory is only exposed through `Cell`s.
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    src.as_ptr(),
+                    self.slice.as_ptr() as *mut u8,
+                    self.len(),
+                );
+            }
             Ok(())
         }
     }
 
+    /// Copy a slice of `u16` values into a [`WriteableProcessSlice`], each
+    /// value stored as two little-endian bytes.
+    ///
+    /// The length of `self` must be twice the length of `src`. On
+    /// little-endian targets this is a single memory copy, otherwise the
+    /// values are copied one byte at a time.
+    pub fn copy_from_u16_slice_le(&self, src: &[u16]) -> Result<(), ErrorCode> {
+        if self.len() != src.len() * 2 {
+            return Err(ErrorCode::SIZE);
+        }
+
+        if cfg!(target_endian = "little") {
+            // # Safety
+            //
+            // The layout of a `[Cell<u8>]` is the same as a `[u8]`, and `Cell`
+            // permits writes through a shared reference. The lengths were
+            // checked above, and `src` cannot overlap with `self` as it is
+            // borrowed immutably while `self` is not (process memory is never
+            // exposed as a `&[u16]`). On little-endian targets the in-memory
+            // representation of `src` is the expected byte order.
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    src.as_ptr() as *const u8,
+                    self.slice.as_ptr() as *mut u8,
+                    self.len(),
+                );
+            }
+        } else {
+            src.iter()
+                .zip(self.slice.chunks_exact(2))
+                .for_each(|(value, bytes)| {
+                    let [low, high] = value.to_le_bytes();
+                    bytes[0].set(low);
+                    bytes[1].set(high);
+                });
+        }
+        Ok(())
+    }
+
     /// Return the length of the slice in bytes.
     pub fn len(&self) -> usize {
         self.slice.len()
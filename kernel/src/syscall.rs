@@ -14,7 +14,7 @@ use core::fmt::Write;
 use crate::errorcode::ErrorCode;
 use crate::process;
 
-pub use crate::syscall_driver::{CommandReturn, SyscallDriver};
+pub use crate::syscall_driver::{CommandReturn, SyscallDriver, ABI_VERSION_COMMAND};
 
 /// Helper function to split a u64 into a higher and lower u32.
 ///
@@ -55,6 +55,7 @@ pub enum SyscallClass {
 pub enum YieldCall {
     NoWait = 0,
     Wait = 1,
+    WaitTimeout = 2,
 }
 
 // Required as long as no solution to
@@ -85,7 +86,12 @@ pub enum Syscall {
     ///
     /// - `which`: the Yield identifier value
     /// - `address`: the no wait field
-    Yield { which: usize, address: *mut u8 },
+    /// - `timeout`: the timeout of `yield-wait-timeout`, in milliseconds
+    Yield {
+        which: usize,
+        address: *mut u8,
+        timeout: usize,
+    },
 
     /// Structure representing an invocation of the Subscribe system call class.
     ///
@@ -191,6 +197,7 @@ impl Syscall {
             Ok(SyscallClass::Yield) => Some(Syscall::Yield {
                 which: r0,
                 address: r1 as *mut u8,
+                timeout: r2,
             }),
             Ok(SyscallClass::Subscribe) => Some(Syscall::Subscribe {
                 driver_number: r0,
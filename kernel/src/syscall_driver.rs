@@ -47,6 +47,12 @@
 //! command can also return more information, like the number of supported
 //! devices (useful for things like the number of LEDs).
 //!
+//! Similarly, _driver minor number_ [`ABI_VERSION_COMMAND`] is handled by the
+//! core kernel and returns the version of the driver's userspace interface,
+//! as reported by [`SyscallDriver::abi_version`]. Drivers that do not report
+//! a version return `NOSUPPORT`. Userspace libraries can use this to detect
+//! changes in how arguments and upcall payloads are encoded.
+//!
 //! # The `yield` system call class
 //!
 //! While drivers do not handle `yield` system calls, it is important
@@ -83,6 +89,11 @@ use crate::process::ProcessId;
 use crate::processbuffer::UserspaceReadableProcessBuffer;
 use crate::syscall::SyscallReturn;
 
+/// Command number reserved for querying the ABI version of a driver. This
+/// command is answered by the core kernel and never reaches
+/// [`SyscallDriver::command`].
+pub const ABI_VERSION_COMMAND: usize = 0xFFFF_FFFF;
+
 /// Possible return values of a `command` driver method, as specified
 /// in TRD104.
 ///
@@ -159,7 +170,7 @@ impl From<Result<(), ErrorCode>> for CommandReturn {
     fn from(rc: Result<(), ErrorCode>) -> Self {
         match rc {
             Ok(()) => CommandReturn::success(),
-            _ => CommandReturn::failure(ErrorCode::try_from(rc).unwrap()),
+            Err(e) => CommandReturn::failure(e),
         }
     }
 }
@@ -202,6 +213,16 @@ pub trait SyscallDriver {
         CommandReturn::failure(ErrorCode::NOSUPPORT)
     }
 
+    /// Version of the userspace interface of this driver, returned to
+    /// processes issuing [`ABI_VERSION_COMMAND`].
+    ///
+    /// Drivers should increase the version whenever the meaning of a
+    /// command, allow or upcall argument changes. Returns `None` if the
+    /// driver does not report a version.
+    fn abi_version(&self) -> Option<u32> {
+        None
+    }
+
     /// System call for a process to pass a buffer (a
     /// `UserspaceReadableProcessBuffer`) to the kernel that the kernel can
     /// either read or write. The kernel calls this method only after it checks
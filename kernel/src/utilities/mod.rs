@@ -13,6 +13,7 @@ pub mod mut_imut_buffer;
 pub mod peripheral_management;
 pub mod static_init;
 pub mod storage_volume;
+pub mod upcall_packing;
 
 mod static_ref;
 
@@ -24,7 +25,7 @@ pub mod registers {
     pub use tock_registers::interfaces;
     pub use tock_registers::registers::InMemoryRegister;
     pub use tock_registers::registers::{Aliased, ReadOnly, ReadWrite, WriteOnly};
-    pub use tock_registers::{register_bitfields, register_structs};
+    pub use tock_registers::{assert_register_fields, register_bitfields, register_structs};
     pub use tock_registers::{LocalRegisterCopy, RegisterLongName};
 }
 
@@ -4,6 +4,11 @@
 
 //! Support for statically initializing objects in memory.
 
+use core::ptr::addr_of_mut;
+
+use crate::config;
+use crate::debug;
+
 /// Allocates a statically-sized global array of memory and initializes the
 /// memory for a particular data structure.
 ///
@@ -92,8 +97,133 @@ macro_rules! static_buf {
         // boolean to true otherwise.
         $crate::utilities::static_init::static_buf_check_used(&mut BUF.1);
 
+        // Account for the memory of the buffer in the boot-time memory report,
+        // if it is enabled.
+        $crate::utilities::static_init::static_buf_record::<$T>();
+
         // If we get to this point we can wrap our buffer to be eventually
         // initialized.
         &mut BUF.0
     }};
 }
+
+/// Number of different types whose static buffers are accounted separately in
+/// the memory report. Buffers of other types are only included in the total.
+const STATIC_MEMORY_ENTRIES: usize = if config::CONFIG.debug_memory_report {
+    32
+} else {
+    0
+};
+
+/// Static memory used by the buffers of one type.
+#[derive(Copy, Clone)]
+struct StaticMemoryEntry {
+    /// Name of the type, without its module path and generic parameters.
+    name: &'static str,
+    size: usize,
+    count: usize,
+}
+
+/// Static memory allocated with `static_buf!()` since boot.
+struct StaticMemory {
+    entries: [Option<StaticMemoryEntry>; STATIC_MEMORY_ENTRIES],
+    total_size: usize,
+    total_count: usize,
+}
+
+static mut STATIC_MEMORY: StaticMemory = StaticMemory {
+    entries: [None; STATIC_MEMORY_ENTRIES],
+    total_size: 0,
+    total_count: 0,
+};
+
+/// Shorten a type name to the name of the type itself, e.g.
+/// `capsules_core::console::Console<'static>` to `Console` or `[u8; 64]` to
+/// `u8`, so that buffers of the same capsule are accounted together.
+fn short_type_name(type_name: &'static str) -> &'static str {
+    let name = type_name.trim_start_matches(['[', '(', '&']);
+    let name = name.split(['<', ';', ',', ')']).next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Record the memory used by a static buffer of type `T` for the memory
+/// report. This is a no-op unless the report is enabled.
+#[inline(always)]
+pub fn static_buf_record<T>() {
+    if config::CONFIG.debug_memory_report {
+        static_buf_record_size(
+            short_type_name(core::any::type_name::<T>()),
+            core::mem::size_of::<T>(),
+        );
+    }
+}
+
+#[inline(never)]
+fn static_buf_record_size(name: &'static str, size: usize) {
+    // Safety: static buffers are only created while the board is initialized,
+    // which is single-threaded, and no reference to `STATIC_MEMORY` outlives
+    // this function.
+    let memory = unsafe { &mut *addr_of_mut!(STATIC_MEMORY) };
+    memory.total_size += size;
+    memory.total_count += 1;
+
+    let slot = memory
+        .entries
+        .iter_mut()
+        .find(|entry| entry.map_or(true, |entry| entry.name == name));
+    if let Some(slot) = slot {
+        let entry = slot.get_or_insert(StaticMemoryEntry {
+            name,
+            size: 0,
+            count: 0,
+        });
+        entry.size += size;
+        entry.count += 1;
+    }
+}
+
+/// Print the static memory allocated with `static_buf!()` so far, largest
+/// types first. Only the `max_lines` largest types are listed.
+pub(crate) fn print_static_memory_report(max_lines: usize) {
+    // Safety: see `static_buf_record_size()`.
+    let memory = unsafe { &mut *addr_of_mut!(STATIC_MEMORY) };
+    memory
+        .entries
+        .sort_unstable_by_key(|entry| core::cmp::Reverse(entry.map_or(0, |entry| entry.size)));
+
+    debug!("Static memory:    bytes  buffers  type");
+    let mut listed_size = 0;
+    let mut listed_count = 0;
+    for entry in memory.entries.iter().flatten().take(max_lines) {
+        debug!("{:>23} {:>8}  {}", entry.size, entry.count, entry.name);
+        listed_size += entry.size;
+        listed_count += entry.count;
+    }
+    if listed_count != memory.total_count {
+        debug!(
+            "{:>23} {:>8}  (other)",
+            memory.total_size - listed_size,
+            memory.total_count - listed_count
+        );
+    }
+    debug!("{:>23} {:>8}  total", memory.total_size, memory.total_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::short_type_name;
+
+    #[test]
+    fn short_names() {
+        assert_eq!(
+            short_type_name("capsules_core::console::Console<'static>"),
+            "Console"
+        );
+        assert_eq!(short_type_name("[u8; 64]"), "u8");
+        assert_eq!(
+            short_type_name("[core::option::Option<&dyn kernel::process::Process>; 4]"),
+            "Option"
+        );
+        assert_eq!(short_type_name("usize"), "usize");
+    }
+}
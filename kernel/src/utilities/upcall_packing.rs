@@ -0,0 +1,179 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Helpers for packing several fields into a single upcall argument.
+//!
+//! Upcalls only carry three `usize` arguments, so drivers often pack several
+//! values into one of them (for example the ADC reports the number of samples
+//! and the channel as `(len << 8) | channel`). Describing such an encoding
+//! with a [`PackedLayout`] keeps the bit positions in one place, and because
+//! layouts are built with `const fn`s, a layout with overlapping fields or
+//! fields that do not fit in 32 bits is rejected at compile time when
+//! declared as a `const`.
+//!
+//! Only the lower 32 bits are used so that the encoding is identical on
+//! 32-bit and 64-bit platforms.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! use kernel::utilities::upcall_packing::{PackedField, PackedLayout};
+//!
+//! // (len << 8) | channel
+//! const LEN_CHANNEL: PackedLayout<2> =
+//!     PackedLayout::new([PackedField::new(0, 8), PackedField::new(8, 24)]);
+//!
+//! let packed = LEN_CHANNEL.pack([3, 128]);
+//! assert_eq!(packed, (128 << 8) | 3);
+//! assert_eq!(LEN_CHANNEL.unpack(packed), [3, 128]);
+//! ```
+
+/// A contiguous range of bits within a packed upcall argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackedField {
+    shift: u8,
+    width: u8,
+}
+
+impl PackedField {
+    /// Create a field `width` bits wide starting at bit `shift`.
+    ///
+    /// Panics (at compile time when used in a `const`) if the field is empty
+    /// or does not fit in 32 bits.
+    pub const fn new(shift: u8, width: u8) -> Self {
+        assert!(width > 0, "packed field must not be empty");
+        assert!(
+            shift as u32 + width as u32 <= 32,
+            "packed field must fit in 32 bits"
+        );
+        PackedField { shift, width }
+    }
+
+    /// Bit offset of the field.
+    pub const fn shift(&self) -> u8 {
+        self.shift
+    }
+
+    /// Width of the field in bits.
+    pub const fn width(&self) -> u8 {
+        self.width
+    }
+
+    /// Mask of the field, already shifted into place.
+    pub const fn mask(&self) -> u32 {
+        (u32::MAX >> (32 - self.width as u32)) << self.shift
+    }
+
+    /// Largest value the field can hold.
+    pub const fn max_value(&self) -> usize {
+        (u32::MAX >> (32 - self.width as u32)) as usize
+    }
+
+    /// Whether `value` can be stored in the field without truncation.
+    pub const fn fits(&self, value: usize) -> bool {
+        value <= self.max_value()
+    }
+
+    /// Place `value` in the field. Bits that do not fit are dropped.
+    pub const fn pack(&self, value: usize) -> usize {
+        (((value as u32) << self.shift) & self.mask()) as usize
+    }
+
+    /// Extract the field from a packed argument.
+    pub const fn unpack(&self, packed: usize) -> usize {
+        ((packed as u32 & self.mask()) >> self.shift) as usize
+    }
+}
+
+/// A set of non-overlapping fields packed into one upcall argument.
+#[derive(Clone, Copy, Debug)]
+pub struct PackedLayout<const N: usize> {
+    fields: [PackedField; N],
+}
+
+impl<const N: usize> PackedLayout<N> {
+    /// Create a layout from its fields.
+    ///
+    /// Panics (at compile time when used in a `const`) if two fields overlap.
+    pub const fn new(fields: [PackedField; N]) -> Self {
+        let mut used = 0u32;
+        let mut i = 0;
+        while i < N {
+            let mask = fields[i].mask();
+            assert!(used & mask == 0, "packed fields must not overlap");
+            used |= mask;
+            i += 1;
+        }
+        PackedLayout { fields }
+    }
+
+    /// The fields of the layout, in declaration order.
+    pub const fn fields(&self) -> &[PackedField; N] {
+        &self.fields
+    }
+
+    /// Pack one value per field, in declaration order. Values that do not
+    /// fit in their field are truncated.
+    pub fn pack(&self, values: [usize; N]) -> usize {
+        self.fields
+            .iter()
+            .zip(values.iter())
+            .fold(0, |packed, (field, value)| packed | field.pack(*value))
+    }
+
+    /// Like [`PackedLayout::pack`], but fails if any value does not fit in
+    /// its field.
+    pub fn try_pack(&self, values: [usize; N]) -> Option<usize> {
+        self.fields
+            .iter()
+            .zip(values.iter())
+            .all(|(field, value)| field.fits(*value))
+            .then(|| self.pack(values))
+    }
+
+    /// Unpack every field, in declaration order.
+    pub fn unpack(&self, packed: usize) -> [usize; N] {
+        let mut values = [0; N];
+        for (value, field) in values.iter_mut().zip(self.fields.iter()) {
+            *value = field.unpack(packed);
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LEN_CHANNEL: PackedLayout<2> =
+        PackedLayout::new([PackedField::new(0, 8), PackedField::new(8, 24)]);
+
+    #[test]
+    fn pack_matches_manual_encoding() {
+        let packed = LEN_CHANNEL.pack([5, 1000]);
+        assert_eq!(packed, (1000 << 8) | 5);
+        assert_eq!(LEN_CHANNEL.unpack(packed), [5, 1000]);
+    }
+
+    #[test]
+    fn pack_truncates_and_try_pack_rejects() {
+        assert_eq!(LEN_CHANNEL.pack([0x1FF, 0]), 0xFF);
+        assert_eq!(LEN_CHANNEL.try_pack([0x1FF, 0]), None);
+        assert_eq!(LEN_CHANNEL.try_pack([0xFF, 1]), Some(0x1FF));
+    }
+
+    #[test]
+    fn full_width_field() {
+        let field = PackedField::new(0, 32);
+        assert_eq!(field.mask(), u32::MAX);
+        assert_eq!(field.unpack(field.pack(0xDEAD_BEEF)), 0xDEAD_BEEF);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overlapping_fields_rejected() {
+        let _ = PackedLayout::new([PackedField::new(0, 8), PackedField::new(4, 8)]);
+    }
+}
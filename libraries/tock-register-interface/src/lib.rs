@@ -70,6 +70,7 @@ pub mod macros;
 pub mod registers;
 
 pub mod debug;
+pub mod validate;
 
 mod local_register;
 pub use local_register::LocalRegisterCopy;
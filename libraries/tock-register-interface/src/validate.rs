@@ -0,0 +1,250 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Consistency checks of register definitions, for unit tests.
+//!
+//! [`register_structs!`](crate::register_structs) already checks the offsets
+//! of the fields of a register struct, and its size against the `@END`
+//! marker, at compile time. The fields of the registers defined with
+//! [`register_bitfields!`](crate::register_bitfields) are not checked: a
+//! field extending past the width of its register is silently truncated.
+//! [`assert_register_fields!`](crate::assert_register_fields) checks the
+//! fields of registers from a unit test of the crate defining them:
+//!
+//! ```rust
+//! use tock_registers::{assert_register_fields, register_bitfields};
+//!
+//! register_bitfields![u32,
+//!     CTRL [
+//!         ENABLE OFFSET(0) NUMBITS(1) [],
+//!         MODE OFFSET(1) NUMBITS(3) [],
+//!     ],
+//! ];
+//!
+//! // Each field fits in the register, and writing random values to a field
+//! // reads them back without changing the other bits.
+//! assert_register_fields!(u32, [CTRL]);
+//! // Also check that the fields do not overlap.
+//! assert_register_fields!(disjoint u32, [CTRL]);
+//! ```
+
+use core::fmt;
+
+use crate::debug::RegisterDebugInfo;
+use crate::UIntLike;
+
+/// What is wrong with a field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldErrorKind {
+    /// The mask of the field is empty.
+    Empty,
+    /// The mask of the field has holes, or does not start at bit 0.
+    NotContiguous,
+    /// The field extends past the width of the register.
+    OutOfRange,
+    /// The field overlaps with the named field.
+    Overlap(&'static str),
+}
+
+/// A field failing a check.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub register: &'static str,
+    pub field: &'static str,
+    pub kind: FieldErrorKind,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}: ", self.register, self.field)?;
+        match self.kind {
+            FieldErrorKind::Empty => write!(f, "empty mask"),
+            FieldErrorKind::NotContiguous => write!(f, "mask is not contiguous"),
+            FieldErrorKind::OutOfRange => write!(f, "field extends past the register"),
+            FieldErrorKind::Overlap(other) => write!(f, "field overlaps with {}", other),
+        }
+    }
+}
+
+/// Number of bits of `T`.
+fn bit_width<T: UIntLike>() -> usize {
+    let mut all = !T::zero();
+    let mut bits = 0;
+    while all != T::zero() {
+        all = all >> 1;
+        bits += 1;
+    }
+    bits
+}
+
+/// Check that every field of the register `R` has a contiguous, non-empty
+/// mask, and fits in the register.
+pub fn check_fields<T: UIntLike + 'static, R: RegisterDebugInfo<T> + 'static>(
+) -> Result<(), FieldError> {
+    let bits = bit_width::<T>();
+    for (field, name) in R::fields().iter().zip(R::field_names()) {
+        let error = |kind| FieldError {
+            register: R::name(),
+            field: name,
+            kind,
+        };
+        if field.mask == T::zero() {
+            return Err(error(FieldErrorKind::Empty));
+        }
+        // Shift out the ones of the mask: nothing may be left.
+        let mut rest = field.mask;
+        while rest & !(!T::zero() << 1) != T::zero() {
+            rest = rest >> 1;
+        }
+        if rest != T::zero() {
+            return Err(error(FieldErrorKind::NotContiguous));
+        }
+        if field.shift >= bits || (field.mask << field.shift) >> field.shift != field.mask {
+            return Err(error(FieldErrorKind::OutOfRange));
+        }
+    }
+    Ok(())
+}
+
+/// Check that no two fields of the register `R` share a bit. The fields must
+/// have passed [`check_fields`].
+pub fn check_fields_disjoint<T: UIntLike + 'static, R: RegisterDebugInfo<T> + 'static>(
+) -> Result<(), FieldError> {
+    let fields = R::fields();
+    let names = R::field_names();
+    for (i, field) in fields.iter().enumerate() {
+        for (other, other_name) in fields.iter().zip(names).skip(i + 1) {
+            if (field.mask << field.shift) & (other.mask << other.shift) != T::zero() {
+                return Err(FieldError {
+                    register: R::name(),
+                    field: names[i],
+                    kind: FieldErrorKind::Overlap(other_name),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deterministic xorshift generator of the values written by
+/// [`assert_register_fields!`](crate::assert_register_fields).
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub const fn new(seed: u64) -> XorShift {
+        // A zero state would only generate zeros.
+        XorShift(if seed == 0 {
+            0x2545_f491_4f6c_dd1d
+        } else {
+            seed
+        })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Check the fields of registers defined with
+/// [`register_bitfields!`](crate::register_bitfields), and panic on the
+/// first error.
+///
+/// Every field must fit in its register (see [`check_fields`]). Random
+/// values are then written to each field of a local copy of the register,
+/// holding a random value: the field must read back the value, truncated to
+/// its width, and the other bits of the register must be unchanged. With
+/// `disjoint`, the fields of a register must also not overlap.
+#[macro_export]
+macro_rules! assert_register_fields {
+    (disjoint $valtype:ident, [$($reg:ident),+ $(,)?]) => {{
+        $crate::assert_register_fields!($valtype, [$($reg),+]);
+        $(
+            if let Err(error) =
+                $crate::validate::check_fields_disjoint::<$valtype, $reg::Register>()
+            {
+                panic!("{}", error);
+            }
+        )+
+    }};
+
+    ($valtype:ident, [$($reg:ident),+ $(,)?]) => {{
+        use $crate::debug::RegisterDebugInfo;
+
+        let mut rng = $crate::validate::XorShift::new(0);
+        $(
+            if let Err(error) = $crate::validate::check_fields::<$valtype, $reg::Register>() {
+                panic!("{}", error);
+            }
+            let fields = <$reg::Register as RegisterDebugInfo<$valtype>>::fields();
+            let names = <$reg::Register as RegisterDebugInfo<$valtype>>::field_names();
+            for (field, name) in fields.iter().zip(names.iter()) {
+                let bits = field.mask << field.shift;
+                for _ in 0..64 {
+                    let initial = rng.next_u64() as $valtype;
+                    let value = rng.next_u64() as $valtype;
+                    let mut reg =
+                        $crate::LocalRegisterCopy::<$valtype, $reg::Register>::new(initial);
+                    reg.modify(field.val(value));
+                    assert_eq!(
+                        reg.read(*field),
+                        value & field.mask,
+                        "{}.{}: written value not read back",
+                        stringify!($reg),
+                        name,
+                    );
+                    assert_eq!(
+                        reg.get() & !bits,
+                        initial & !bits,
+                        "{}.{}: write changed other fields",
+                        stringify!($reg),
+                        name,
+                    );
+                }
+            }
+        )+
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::register_bitfields;
+
+    register_bitfields![u8,
+        GOOD [
+            LOW OFFSET(0) NUMBITS(4) [],
+            HIGH OFFSET(4) NUMBITS(4) []
+        ],
+        TOO_WIDE [
+            LOW OFFSET(0) NUMBITS(4) [],
+            HIGH OFFSET(6) NUMBITS(4) []
+        ],
+        OVERLAPPING [
+            LOW OFFSET(0) NUMBITS(5) [],
+            HIGH OFFSET(4) NUMBITS(4) []
+        ]
+    ];
+
+    #[test]
+    fn valid_fields() {
+        assert_register_fields!(disjoint u8, [GOOD]);
+    }
+
+    #[test]
+    fn fields_past_the_register() {
+        let error = check_fields::<u8, TOO_WIDE::Register>().unwrap_err();
+        assert_eq!(error.field, "HIGH");
+        assert_eq!(error.kind, FieldErrorKind::OutOfRange);
+    }
+
+    #[test]
+    fn overlapping_fields() {
+        let error = check_fields_disjoint::<u8, OVERLAPPING::Register>().unwrap_err();
+        assert_eq!(error.field, "LOW");
+        assert_eq!(error.kind, FieldErrorKind::Overlap("HIGH"));
+    }
+}
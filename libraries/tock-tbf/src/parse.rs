@@ -128,6 +128,7 @@ pub fn parse_tbf_header(
                 let mut permissions_pointer: Option<&'static [u8]> = None;
                 let mut storage_permissions_pointer: Option<&'static [u8]> = None;
                 let mut kernel_version: Option<types::TbfHeaderV2KernelVersion> = None;
+                let mut asset_access_pointer: Option<&'static [u8]> = None;
 
                 // Iterate the remainder of the header looking for TLV entries.
                 while remaining.len() > 0 {
@@ -256,6 +257,21 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderAssetAccess => {
+                            // Length must be a multiple of the size of an asset identifier.
+                            if tlv_header.length as usize % mem::size_of::<u32>() == 0 {
+                                asset_access_pointer = Some(
+                                    remaining
+                                        .get(0..tlv_header.length as usize)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
                         _ => {}
                     }
 
@@ -279,6 +295,7 @@ pub fn parse_tbf_header(
                     permissions: permissions_pointer,
                     storage_permissions: storage_permissions_pointer,
                     kernel_version: kernel_version,
+                    asset_access: asset_access_pointer,
                 };
 
                 Ok(types::TbfHeader::TbfHeaderV2(tbf_header))
@@ -130,6 +130,7 @@ pub enum TbfHeaderTypes {
     TbfHeaderStoragePermissions = 7,
     TbfHeaderKernelVersion = 8,
     TbfHeaderProgram = 9,
+    TbfHeaderAssetAccess = 10,
     TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
@@ -319,6 +320,7 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             7 => Ok(TbfHeaderTypes::TbfHeaderStoragePermissions),
             8 => Ok(TbfHeaderTypes::TbfHeaderKernelVersion),
             9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
+            10 => Ok(TbfHeaderTypes::TbfHeaderAssetAccess),
             128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
@@ -633,6 +635,7 @@ pub struct TbfHeaderV2 {
     pub(crate) permissions: Option<&'static [u8]>,
     pub(crate) storage_permissions: Option<&'static [u8]>,
     pub(crate) kernel_version: Option<TbfHeaderV2KernelVersion>,
+    pub(crate) asset_access: Option<&'static [u8]>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -965,6 +968,22 @@ impl TbfHeader {
         }
     }
 
+    /// Return whether the process may access the read-only data blob
+    /// (asset) with identifier `asset_id`.
+    ///
+    /// The Asset Access TLV is a list of `u32` asset identifiers. Processes
+    /// without the TLV cannot access any asset.
+    pub fn has_asset_access(&self, asset_id: u32) -> bool {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd.asset_access.map_or(false, |asset_ids| {
+                asset_ids
+                    .chunks_exact(4)
+                    .any(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]) == asset_id)
+            }),
+            _ => false,
+        }
+    }
+
     /// Return the offset where the binary ends in the TBF or 0 if there
     /// is no binary. If there is a Main header the end offset is the size
     /// of the TBF, while if there is a Program header it can be smaller.